@@ -0,0 +1,122 @@
+// Sources that are already compressed hard, like 1.5 Mbps h264 web rips, can't show the difference a
+// high VMAF target asks for: the encode only reproduces their artifacts at a larger size and the
+// CRF search spends its tries at the top of the scale. The bits per pixel of the video, weighted by
+// how efficient its codec is, decide whether the target of such a file is lowered. The thresholds
+// are all here, --no-adaptive-target keeps the target of every file
+
+use crate::*;
+
+// The target of a source below --low-quality-bpp, and below half of it
+const LOW_QUALITY_TARGET: f32 = 95.0;
+const VERY_LOW_QUALITY_TARGET: f32 = 93.0;
+
+// The bits a codec spends for the quality h264 gets from one
+fn codec_weight(codec: &str) -> f32 {
+    match codec {
+        "mpeg1video" | "mpeg2video" => 2.0,
+        "mpeg4" | "msmpeg4v3" | "wmv3" => 1.4,
+        "hevc" | "vp9" => 0.65,
+        "av1" => 0.55,
+        _ => 1.0,
+    }
+}
+
+// What the quality of a source is judged by
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceQuality {
+    pub codec: String,
+    pub width: u64,
+    pub height: u64,
+    pub fps: f32,
+    pub bitrate_kbps: f32,
+}
+
+impl SourceQuality {
+    // None when ffprobe has no size, rate or bitrate for the video
+    pub fn probe(file: &str) -> Option<SourceQuality> {
+        let info = probe_file(file);
+        let stream = info.video_stream()?;
+        let quality = SourceQuality {
+            codec: stream["codec_name"].as_str().unwrap_or("").to_string(),
+            width: stream["width"].as_u64()?,
+            height: stream["height"].as_u64()?,
+            fps: info.avg_fps(),
+            bitrate_kbps: info.bitrate_kbps(),
+        };
+        (quality.width > 0 && quality.height > 0 && quality.fps > 0.0 && quality.bitrate_kbps > 0.0)
+            .then_some(quality)
+    }
+
+    // The bits per pixel of a frame the video would have as h264
+    pub fn h264_bpp(&self) -> f32 {
+        let pixels_per_second = self.width as f32 * self.height as f32 * self.fps;
+        self.bitrate_kbps * 1000.0 / pixels_per_second / codec_weight(&self.codec)
+    }
+}
+
+// The VMAF target for a source of quality, lower than requested when its bits per pixel are below
+// low_quality_bpp, None when the requested target stays
+pub fn adaptive_vmaf_target(
+    quality: &SourceQuality,
+    requested: f32,
+    low_quality_bpp: f32,
+) -> Option<f32> {
+    let bpp = quality.h264_bpp();
+    let target = if bpp < low_quality_bpp / 2.0 {
+        VERY_LOW_QUALITY_TARGET
+    } else if bpp < low_quality_bpp {
+        LOW_QUALITY_TARGET
+    } else {
+        return None;
+    };
+    (target < requested).then_some(target)
+}
+
+// A file whose VMAF target was lowered, for the summary and --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct AdaptedTarget {
+    pub path: String,
+    pub codec: String,
+    pub bitrate_kbps: f32,
+    // as h264, see SourceQuality::h264_bpp
+    pub bpp: f32,
+    pub requested_vmaf: f32,
+    pub effective_vmaf: f32,
+}
+
+// The lowered target of file, None when it keeps --vmaf: with --no-adaptive-target, --target-size
+// and --renditions, which don't search for --vmaf, and for a source of enough bits per pixel
+pub(crate) fn adapt_vmaf_target(file: &str, args: &Args) -> Option<AdaptedTarget> {
+    if args.no_adaptive_target || args.target_size.is_some() || args.renditions.is_some() {
+        return None;
+    }
+    let quality = SourceQuality::probe(file)?;
+    let effective = adaptive_vmaf_target(&quality, args.vmaf, args.low_quality_bpp)?;
+    let adapted = AdaptedTarget {
+        path: file.to_string(),
+        codec: quality.codec.clone(),
+        bitrate_kbps: quality.bitrate_kbps,
+        bpp: quality.h264_bpp(),
+        requested_vmaf: args.vmaf,
+        effective_vmaf: effective,
+    };
+    warn!(
+        "{}",
+        format!(
+            "{}: {} at {:.0} kb/s is {:.3} bits per pixel as h264, below --low-quality-bpp {}, VMAF {} would only keep its artifacts at a larger size, encoding it at VMAF {} (--no-adaptive-target keeps {})",
+            file,
+            quality.codec,
+            quality.bitrate_kbps,
+            adapted.bpp,
+            args.low_quality_bpp,
+            args.vmaf,
+            effective,
+            args.vmaf
+        )
+        .yellow()
+    );
+    Some(adapted)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,122 @@
+use super::*;
+
+fn quality(codec: &str, bitrate_kbps: f32, width: u64, height: u64, fps: f32) -> SourceQuality {
+    SourceQuality {
+        codec: codec.to_string(),
+        width,
+        height,
+        fps,
+        bitrate_kbps,
+    }
+}
+
+// A probed source of quality
+fn source(name: &str, quality: &SourceQuality) -> String {
+    probed_file(
+        name,
+        serde_json::json!({
+            "streams": [{
+                "index": 0, "codec_type": "video", "codec_name": quality.codec,
+                "width": quality.width, "height": quality.height,
+                "r_frame_rate": format!("{}/1", quality.fps),
+                "avg_frame_rate": format!("{}/1", quality.fps),
+                "bit_rate": (quality.bitrate_kbps * 1000.0).to_string(),
+            }],
+            "format": {"duration": "60.0"},
+        }),
+    )
+}
+
+#[test]
+fn the_target_of_representative_sources() {
+    let cases = [
+        // a 1.5 Mbps 1080p h264 web rip and the same bitrate in hevc
+        (quality("h264", 1500.0, 1920, 1080, 25.0), Some(95.0)),
+        (quality("hevc", 1500.0, 1920, 1080, 25.0), Some(95.0)),
+        (quality("h264", 600.0, 1920, 1080, 25.0), Some(93.0)),
+        (quality("av1", 800.0, 1920, 1080, 24.0), Some(95.0)),
+        (quality("hevc", 2500.0, 3840, 2160, 24.0), Some(93.0)),
+        // a Blu-ray, a 720p rip and a DVD have enough bits
+        (quality("h264", 20000.0, 1920, 1080, 24.0), None),
+        (quality("h264", 1500.0, 1280, 720, 24.0), None),
+        (quality("mpeg2video", 4000.0, 720, 576, 25.0), None),
+    ];
+    for (quality, target) in cases {
+        assert_eq!(
+            adaptive_vmaf_target(&quality, 97.0, 0.05),
+            target,
+            "{:?}",
+            quality
+        );
+    }
+}
+
+#[test]
+fn the_bits_per_pixel_are_weighted_by_the_codec() {
+    let h264 = quality("h264", 1500.0, 1920, 1080, 25.0);
+    assert!(
+        (h264.h264_bpp() - 0.0289).abs() < 0.0001,
+        "{}",
+        h264.h264_bpp()
+    );
+    let hevc = quality("hevc", 1500.0, 1920, 1080, 25.0);
+    assert_eq!(hevc.h264_bpp(), h264.h264_bpp() / 0.65);
+    let mpeg2 = quality("mpeg2video", 1500.0, 1920, 1080, 25.0);
+    assert_eq!(mpeg2.h264_bpp(), h264.h264_bpp() / 2.0);
+}
+
+#[test]
+fn a_lower_requested_target_stays() {
+    let low = quality("h264", 1500.0, 1920, 1080, 25.0);
+    assert_eq!(adaptive_vmaf_target(&low, 95.0, 0.05), None);
+    assert_eq!(adaptive_vmaf_target(&low, 94.0, 0.05), None);
+    let very_low = quality("h264", 600.0, 1920, 1080, 25.0);
+    assert_eq!(adaptive_vmaf_target(&very_low, 94.0, 0.05), Some(93.0));
+    assert_eq!(adaptive_vmaf_target(&very_low, 90.0, 0.05), None);
+    // a lower --low-quality-bpp
+    assert_eq!(adaptive_vmaf_target(&low, 97.0, 0.02), None);
+}
+
+#[test]
+fn a_source_without_a_bitrate_is_not_judged() {
+    let web_rip = quality("h264", 1500.0, 1920, 1080, 25.0);
+    let file = source("web-rip.mkv", &web_rip);
+    assert_eq!(SourceQuality::probe(&file), Some(web_rip.clone()));
+
+    let file = probed_file(
+        "no-bitrate.mkv",
+        serde_json::json!({
+            "streams": [{
+                "index": 0, "codec_type": "video", "codec_name": "h264",
+                "width": 1920, "height": 1080, "avg_frame_rate": "25/1",
+            }],
+        }),
+    );
+    assert_eq!(SourceQuality::probe(&file), None);
+    assert!(adapt_vmaf_target(&file, &test_args(&[])).is_none());
+}
+
+#[test]
+fn adapt_vmaf_target_follows_the_options() {
+    let file = source("adapted.mkv", &quality("h264", 1500.0, 1920, 1080, 25.0));
+    let adapted = adapt_vmaf_target(&file, &test_args(&[])).unwrap();
+    assert_eq!(
+        (adapted.requested_vmaf, adapted.effective_vmaf),
+        (97.0, 95.0)
+    );
+    assert_eq!(adapted.codec, "h264");
+    assert_eq!(adapted.bitrate_kbps, 1500.0);
+
+    for options in [
+        &["--no-adaptive-target"][..],
+        &["--target-size", "700"],
+        &["--low-quality-bpp", "0.02"],
+        &["-v", "94"],
+    ] {
+        assert!(
+            adapt_vmaf_target(&file, &test_args(options)).is_none(),
+            "{:?}",
+            options
+        );
+    }
+}
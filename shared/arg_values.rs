@@ -0,0 +1,385 @@
+// The value parsers of the options of Args, and the options filled in or replaced after parsing
+
+use crate::*;
+
+pub fn possible_mode_values(s: &str) -> Result<String, String> {
+    // ["default", "chunked", "audio-only"]
+    let possible_values = vec!["default", "chunked", "audio-only"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid mode").unwrap())
+    }
+}
+
+pub(crate) fn possible_content_tune_values(s: &str) -> Result<String, String> {
+    let possible_values = vec!["auto", "film", "animation", "grain", "none"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid content tune").unwrap())
+    }
+}
+
+pub(crate) fn possible_crf_search_values(s: &str) -> Result<String, String> {
+    let possible_values = vec!["ab-av1", "native"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid crf search").unwrap())
+    }
+}
+
+pub(crate) fn possible_chunk_by_values(s: &str) -> Result<String, String> {
+    let possible_values = vec!["scenes", "chapters"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid chunk-by value").unwrap())
+    }
+}
+
+pub(crate) fn possible_sync_check_values(s: &str) -> Result<String, String> {
+    let possible_values = ["fail", "warn", "off"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid sync-check value").unwrap())
+    }
+}
+
+pub(crate) fn possible_notify_on_values(s: &str) -> Result<String, String> {
+    let possible_values = vec!["failure", "success", "all"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid notify-on value").unwrap())
+    }
+}
+
+pub(crate) fn possible_deinterlace_values(s: &str) -> Result<String, String> {
+    let possible_values = vec!["auto", "on", "off"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid deinterlace value").unwrap())
+    }
+}
+
+pub(crate) fn possible_tonemap_values(s: &str) -> Result<String, String> {
+    let possible_values = vec!["off", "sdr"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid tonemap value").unwrap())
+    }
+}
+
+pub(crate) fn possible_fallback_encoder_values(s: &str) -> Result<String, String> {
+    let possible_values = vec![
+        "libx265",
+        "av1",
+        "libsvtav1",
+        "hevc_nvenc",
+        "hevc_qsv",
+        "av1_qsv",
+        "none",
+    ];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid fallback encoder value").unwrap())
+    }
+}
+
+// --env: KEY=VALUE, the value can be empty
+pub(crate) fn env_input_validation(s: &str) -> Result<String, String> {
+    match s.split_once('=') {
+        Some((key, _))
+            if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            Ok(s.to_string())
+        }
+        _ => Err("input must be KEY=VALUE with a key of letters, digits and _".to_string()),
+    }
+}
+
+pub(crate) fn possible_progress_format_values(s: &str) -> Result<String, String> {
+    let possible_values = vec!["text", "json"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid progress format value").unwrap())
+    }
+}
+
+pub(crate) fn possible_log_level_values(s: &str) -> Result<String, String> {
+    // ["off", "error", "warn", "info", "debug", "trace"]
+    let possible_values = vec!["off", "error", "warn", "info", "debug", "trace"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid log level").unwrap())
+    }
+}
+
+pub(crate) fn possible_audio_codec_values(s: &str) -> Result<String, String> {
+    // ["opus", "aac"]
+    let possible_values = vec!["opus", "aac"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid audio codec").unwrap())
+    }
+}
+
+pub(crate) fn possible_db_export_format_values(s: &str) -> Result<String, String> {
+    // ["json", "csv"]
+    let possible_values = vec!["json", "csv"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid export format").unwrap())
+    }
+}
+
+pub(crate) fn possible_pool_values(s: &str) -> Result<String, String> {
+    // ["min", "harmonic_mean", "mean"}
+    let possible_values = vec!["min", "harmonic_mean", "mean"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("invalid pool").unwrap())
+    }
+}
+
+// validate thread input, must be integer, and not exceed the number of logical cores. Empty is
+// the adaptive default of resolve_thread_counts
+pub(crate) fn vmaf_thread_input_validation(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        return Ok(String::new());
+    }
+    let re = Regex::new(r"^[0-9]+$").unwrap();
+    if !re.is_match(s) {
+        return Err(String::from_str("input must be an integer").unwrap());
+    }
+    let s = s.parse::<i32>().unwrap();
+    if s > (num_cpus::get() as i32) {
+        return Err(String::from_str("input must not exceed the number of logical cores").unwrap());
+    }
+    Ok(s.to_string())
+}
+
+const DEFAULT_SCENE_JOBS: usize = 2;
+
+// More VMAF threads than this many times the logical cores across the scenes searched in parallel
+// are refused
+const MAX_THREAD_OVERSUBSCRIPTION: usize = 2;
+
+// --output-folder used to be a second -o that only some of the outputs went to, it is --output now
+pub fn resolve_output_folder(args: &mut Args) {
+    if let Some(folder) = args.output_folder.take() {
+        warn!(
+            "--output-folder is deprecated, use --output. Using --output {}",
+            folder
+        );
+        args.outputpath = folder;
+    }
+}
+
+// Fill in --vmaf-threads and --scene-jobs when they were left out, so the VMAF threads of all
+// scenes searched in parallel fit the logical cores, and refuse combinations that would
+// oversubscribe the machine
+pub fn resolve_thread_counts(args: &mut Args) -> Result<(), String> {
+    let cores = num_cpus::get().max(1);
+    let chunked = args.mode == "chunked";
+    let vmaf_threads = match args.vmaf_threads.trim() {
+        "" => None,
+        threads => match threads.parse::<usize>() {
+            Ok(threads) if threads > 0 => Some(threads),
+            _ => {
+                return Err(format!(
+                    "--vmaf-threads {}: must be a positive integer",
+                    threads
+                ))
+            }
+        },
+    };
+    if args.scene_jobs == Some(0) {
+        return Err("--scene-jobs must be at least 1".to_string());
+    }
+
+    if !chunked {
+        let threads = vmaf_threads.unwrap_or((cores / 2).max(1));
+        args.vmaf_threads = threads.to_string();
+        return Ok(());
+    }
+
+    // --vmaf-threads alone is the threads of each VMAF, the scenes searched in parallel fit the
+    // cores around it
+    let scene_jobs = args
+        .scene_jobs
+        .unwrap_or_else(|| DEFAULT_SCENE_JOBS.min((cores / vmaf_threads.unwrap_or(1)).max(1)));
+    let threads = vmaf_threads.unwrap_or_else(|| (cores / 2).min(cores / scene_jobs).max(1));
+    if scene_jobs * threads > cores * MAX_THREAD_OVERSUBSCRIPTION {
+        return Err(format!(
+            "--scene-jobs {} with --vmaf-threads {} runs {} VMAF threads on {} logical cores, more than {} times the cores. Lower --scene-jobs or --vmaf-threads",
+            scene_jobs,
+            threads,
+            scene_jobs * threads,
+            cores,
+            MAX_THREAD_OVERSUBSCRIPTION
+        ));
+    }
+    args.scene_jobs = Some(scene_jobs);
+    args.vmaf_threads = threads.to_string();
+    Ok(())
+}
+
+// --vmaf: 0 to 100, fractions allowed
+pub fn vmaf_input_validation(s: &str) -> Result<f32, String> {
+    match s.trim().parse::<f32>() {
+        Ok(vmaf) if (0.0..=100.0).contains(&vmaf) => Ok(vmaf),
+        _ => Err(String::from_str("input must be a number from 0 to 100").unwrap()),
+    }
+}
+
+pub(crate) fn vmaf_retry_step_input_validation(s: &str) -> Result<f32, String> {
+    match s.trim().parse::<f32>() {
+        Ok(step) if step > 0.0 && step <= 100.0 => Ok(step),
+        _ => Err(String::from_str("input must be a number above 0").unwrap()),
+    }
+}
+
+pub(crate) fn vmaf_subsample_input_validation(s: &str) -> Result<String, String> {
+    let re = Regex::new(r"^[0-9]+$").unwrap();
+    if !re.is_match(s) {
+        return Err(String::from_str("input must be an integer").unwrap());
+    }
+    let s = s.parse::<i32>().unwrap();
+    if s > 100 {
+        return Err(String::from_str("input must not exceed 100").unwrap());
+    }
+    Ok(s.to_string())
+}
+
+// --film-grain: auto or a level up to 50
+pub(crate) fn film_grain_input_validation(s: &str) -> Result<String, String> {
+    if s == "auto" {
+        return Ok(s.to_string());
+    }
+    match s.parse::<u8>() {
+        Ok(level) if level <= 50 => Ok(level.to_string()),
+        _ => Err(String::from_str("input must be auto or a level from 0 to 50").unwrap()),
+    }
+}
+
+pub(crate) fn film_grain_denoise_input_validation(s: &str) -> Result<String, String> {
+    let possible_values = vec!["0", "1"];
+    if possible_values.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("input must be 0 or 1").unwrap())
+    }
+}
+
+// --burn-subs: auto, a subtitle stream index or a language code
+pub(crate) fn burn_subs_input_validation(s: &str) -> Result<String, String> {
+    let re = Regex::new(r"^(auto|[0-9]+|[A-Za-z]{2,3})$").unwrap();
+    if re.is_match(s) {
+        Ok(s.to_string())
+    } else {
+        Err(
+            String::from_str("input must be auto, a subtitle stream index or a language code")
+                .unwrap(),
+        )
+    }
+}
+
+// --keyint-seconds: 2 to 10 seconds
+pub(crate) fn keyint_seconds_input_validation(s: &str) -> Result<f32, String> {
+    let seconds: f32 = s.parse().map_err(|_| format!("{} is not a number", s))?;
+    if !(MIN_KEYINT_SECONDS..=MAX_KEYINT_SECONDS).contains(&seconds) {
+        return Err(format!(
+            "must be from {} to {} seconds",
+            MIN_KEYINT_SECONDS, MAX_KEYINT_SECONDS
+        ));
+    }
+    Ok(seconds)
+}
+
+// --low-quality-bpp: above 0, a bit per pixel is far above any lossy source
+pub(crate) fn low_quality_bpp_input_validation(s: &str) -> Result<f32, String> {
+    let bpp: f32 = s.parse().map_err(|_| format!("{} is not a number", s))?;
+    if !(bpp > 0.0 && bpp < 1.0) {
+        return Err("must be above 0 and below 1".to_string());
+    }
+    Ok(bpp)
+}
+
+// --min-estimated-savings: a percent of the source
+pub(crate) fn min_estimated_savings_input_validation(s: &str) -> Result<f64, String> {
+    let percent: f64 = s.parse().map_err(|_| format!("{} is not a number", s))?;
+    if !(0.0..100.0).contains(&percent) {
+        return Err("must be from 0 to below 100".to_string());
+    }
+    Ok(percent)
+}
+
+// --trial-range: <start>-<end> of at least a few seconds, the end is checked against each file
+pub(crate) fn trial_range_input_validation(s: &str) -> Result<String, String> {
+    parse_trial_range(s).map(|_| s.to_string())
+}
+
+// --lock-stale-after: a duration longer than two refreshes of the locks
+pub(crate) fn lock_stale_after_validation(s: &str) -> Result<String, String> {
+    parse_lock_stale_after(s).map(|_| s.to_string())
+}
+
+// --since: "7d", "12h 30m" or a UTC date/time, see parse_since
+pub(crate) fn since_input_validation(s: &str) -> Result<String, String> {
+    parse_since(s).map(|_| s.to_string())
+}
+
+// --max-trash-fraction: 0 to 1
+pub(crate) fn trash_fraction_validation(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        _ => Err("expected a fraction from 0 to 1".to_string()),
+    }
+}
+
+// --clean-artifacts: a duration like "7d"
+pub(crate) fn artifacts_age_validation(s: &str) -> Result<String, String> {
+    humantime::parse_duration(s)
+        .map(|_| s.to_string())
+        .map_err(|_| "expected a duration (7d, 12h)".to_string())
+}
+
+// Point in time of a --since value, durations count back from now
+pub fn parse_since(s: &str) -> Result<SystemTime, String> {
+    if let Ok(duration) = humantime::parse_duration(s) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| "duration too long".to_string());
+    }
+    // a date alone means midnight
+    let timestamp = if s.len() == 10 {
+        format!("{}T00:00:00", s)
+    } else {
+        s.to_string()
+    };
+    humantime::parse_rfc3339_weak(&timestamp)
+        .map_err(|_| "expected a duration (7d, 12h) or a date (2024-05-01T18:00:00)".to_string())
+}
+
+// Files whose modification time can't be read are kept
+pub fn modified_since(file: &str, since: SystemTime) -> bool {
+    match fs::metadata(file).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified >= since,
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,186 @@
+use super::*;
+
+#[test]
+fn possible_values_accept_only_their_list() {
+    assert_eq!(possible_mode_values("chunked"), Ok("chunked".to_string()));
+    assert!(possible_mode_values("Chunked").is_err());
+    assert!(possible_content_tune_values("grain").is_ok());
+    assert!(possible_crf_search_values("native").is_ok());
+    assert!(possible_crf_search_values("ab_av1").is_err());
+    assert!(possible_chunk_by_values("chapters").is_ok());
+    assert!(possible_sync_check_values("warn").is_ok());
+    assert!(possible_notify_on_values("never").is_err());
+    assert!(possible_deinterlace_values("auto").is_ok());
+    assert!(possible_tonemap_values("hdr").is_err());
+    assert!(possible_fallback_encoder_values("none").is_ok());
+    assert!(possible_fallback_encoder_values("av1_nvenc").is_err());
+    assert!(possible_progress_format_values("json").is_ok());
+    assert!(possible_log_level_values("trace").is_ok());
+    assert!(possible_audio_codec_values("mp3").is_err());
+    assert!(possible_db_export_format_values("csv").is_ok());
+    assert!(possible_pool_values("harmonic_mean").is_ok());
+    assert!(possible_pool_values("").is_err());
+}
+
+#[test]
+fn env_input_validation_needs_a_key() {
+    assert!(env_input_validation("CUDA_VISIBLE_DEVICES=1").is_ok());
+    assert!(env_input_validation("EMPTY=").is_ok());
+    assert!(env_input_validation("=1").is_err());
+    assert!(env_input_validation("NO_VALUE").is_err());
+    assert!(env_input_validation("BAD-KEY=1").is_err());
+}
+
+#[test]
+fn vmaf_thread_input_validation_bounds() {
+    assert_eq!(vmaf_thread_input_validation(""), Ok(String::new()));
+    assert_eq!(vmaf_thread_input_validation("1"), Ok("1".to_string()));
+    assert!(vmaf_thread_input_validation("-1").is_err());
+    assert!(vmaf_thread_input_validation("two").is_err());
+    assert!(vmaf_thread_input_validation(&(num_cpus::get() + 1).to_string()).is_err());
+}
+
+#[test]
+fn vmaf_input_validation_bounds() {
+    assert_eq!(vmaf_input_validation("95.5"), Ok(95.5));
+    assert_eq!(vmaf_input_validation(" 100 "), Ok(100.0));
+    assert_eq!(vmaf_input_validation("0"), Ok(0.0));
+    assert!(vmaf_input_validation("100.1").is_err());
+    assert!(vmaf_input_validation("-1").is_err());
+    assert!(vmaf_input_validation("high").is_err());
+}
+
+#[test]
+fn vmaf_retry_step_and_subsample_bounds() {
+    assert_eq!(vmaf_retry_step_input_validation("0.5"), Ok(0.5));
+    assert!(vmaf_retry_step_input_validation("0").is_err());
+    assert_eq!(
+        vmaf_subsample_input_validation("100"),
+        Ok("100".to_string())
+    );
+    assert!(vmaf_subsample_input_validation("101").is_err());
+    assert!(vmaf_subsample_input_validation("1.5").is_err());
+}
+
+#[test]
+fn film_grain_validation() {
+    assert_eq!(film_grain_input_validation("auto"), Ok("auto".to_string()));
+    assert_eq!(film_grain_input_validation("50"), Ok("50".to_string()));
+    assert!(film_grain_input_validation("51").is_err());
+    assert!(film_grain_input_validation("-1").is_err());
+    assert!(film_grain_denoise_input_validation("1").is_ok());
+    assert!(film_grain_denoise_input_validation("2").is_err());
+}
+
+#[test]
+fn burn_subs_input_validation_forms() {
+    for value in ["auto", "3", "eng", "de"] {
+        assert!(burn_subs_input_validation(value).is_ok(), "{}", value);
+    }
+    for value in ["", "english", "e", "3a"] {
+        assert!(burn_subs_input_validation(value).is_err(), "{}", value);
+    }
+}
+
+#[test]
+fn number_validations_bounds() {
+    assert_eq!(
+        keyint_seconds_input_validation(&MIN_KEYINT_SECONDS.to_string()),
+        Ok(MIN_KEYINT_SECONDS)
+    );
+    assert!(keyint_seconds_input_validation(&(MAX_KEYINT_SECONDS + 1.0).to_string()).is_err());
+    assert!(keyint_seconds_input_validation("two").is_err());
+    assert_eq!(low_quality_bpp_input_validation("0.05"), Ok(0.05));
+    assert!(low_quality_bpp_input_validation("0").is_err());
+    assert!(low_quality_bpp_input_validation("1").is_err());
+    assert_eq!(min_estimated_savings_input_validation("0"), Ok(0.0));
+    assert!(min_estimated_savings_input_validation("100").is_err());
+    assert_eq!(trash_fraction_validation("1"), Ok(1.0));
+    assert!(trash_fraction_validation("1.5").is_err());
+}
+
+#[test]
+fn duration_validations() {
+    assert!(artifacts_age_validation("7d").is_ok());
+    assert!(artifacts_age_validation("week").is_err());
+    assert!(since_input_validation("12h 30m").is_ok());
+    assert!(since_input_validation("2024-05-01").is_ok());
+    assert!(since_input_validation("2024-05-01T18:00:00").is_ok());
+    assert!(since_input_validation("yesterday").is_err());
+}
+
+#[test]
+fn parse_since_counts_back_from_now() {
+    let since = parse_since("1h").unwrap();
+    let ago = SystemTime::now().duration_since(since).unwrap();
+    assert!(ago >= Duration::from_secs(3600) && ago < Duration::from_secs(3660));
+    assert_eq!(
+        parse_since("1970-01-02").unwrap(),
+        SystemTime::UNIX_EPOCH + Duration::from_secs(24 * 3600)
+    );
+}
+
+#[test]
+fn one_output_folder_for_the_video_and_the_audio_outputs() {
+    let output = Path::new("/out");
+    for option in ["-o", "--output", "--outputpath", "--output-folder"] {
+        let mut args = test_args(&[option, "/out"]);
+        resolve_output_folder(&mut args);
+        assert_eq!(args.outputpath, "/out", "{}", option);
+        assert_eq!(args.output_folder, None, "{}", option);
+        assert_eq!(
+            set_output_folder_filename(
+                "/media/movie.mkv",
+                "libx265",
+                &97.0,
+                "24",
+                &args.outputpath
+            ),
+            output
+                .join("movie.libx265.vmaf97.crf24.mkv")
+                .display()
+                .to_string(),
+            "{}",
+            option
+        );
+        assert_eq!(
+            set_output_folder_filename_audio("/media/movie.mkv", &args.outputpath),
+            output.join("movie.mkv").display().to_string(),
+            "{}",
+            option
+        );
+    }
+    assert_eq!(test_args(&[]).outputpath, ".");
+}
+
+#[test]
+fn output_and_output_folder_are_not_given_together() {
+    let argv = [
+        "TransRustica",
+        "-i",
+        ".",
+        "-o",
+        "/out",
+        "--output-folder",
+        "/other",
+    ];
+    assert!(Args::try_parse_from(argv).is_err());
+}
+
+#[test]
+fn vmaf_threads_alone_in_chunked_mode_are_the_threads_of_each_vmaf() {
+    let cores = num_cpus::get().max(1);
+    for threads in [1, cores] {
+        let threads_arg = threads.to_string();
+        let mut args = test_args(&["--mode", "chunked", "--vmaf-threads", &threads_arg]);
+        resolve_thread_counts(&mut args).unwrap();
+        assert_eq!(args.vmaf_threads, threads_arg);
+        // the scenes searched at a time come from the cores, not from --vmaf-threads
+        assert_eq!(
+            args.scene_jobs,
+            Some(DEFAULT_SCENE_JOBS.min(cores / threads).max(1)),
+            "--vmaf-threads {}",
+            threads
+        );
+    }
+}
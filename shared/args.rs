@@ -0,0 +1,597 @@
+// Command line options of the cli, the web server fills the same struct. The subcommands are in
+// commands.rs, the value parsers in arg_values.rs
+
+use crate::*;
+
+#[derive(Parser, Serialize, Deserialize, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+#[clap(
+    name = "TransRustica",
+    author = "BankaiNoJutsu <lbegert@gmail.com>",
+    about = "TransRustica - Transcoding, Rust, FFMPEG, VMAF, Chunking",
+    long_about = None,
+    subcommand_negates_reqs = true
+)]
+pub struct Args {
+    /// input video path folder path (\\... or /... or C:\...)
+    /// can be repeated, and accepts glob patterns ("/media/**/*.mkv")
+    #[clap(
+        short = 'i',
+        long,
+        required_unless_present_any = ["input_list", "db_export", "db_import", "db_duplicates", "db_maintain", "verify_library", "save_profile", "list_profiles", "clean_artifacts", "generate_test_media", "resume_last", "resume"]
+    )]
+    pub inputpath: Vec<String>,
+
+    /// Text file with one input path or glob pattern per line (blank lines and # comments are ignored)
+    #[clap(long)]
+    pub input_list: Option<String>,
+
+    /// Output folder (\\... or /... or C:\...) of the video and the audio-only encodes
+    #[clap(
+        short = 'o',
+        long = "output",
+        alias = "outputpath",
+        value_name = "OUTPUT",
+        default_value = "."
+    )]
+    pub outputpath: String,
+
+    /// Put each output in the folders of its file below the -i folder, -i /media/TV with -o /out encodes
+    /// Show/Season 01/E01.mkv to /out/Show/Season 01, chunked mode too instead of the working directory
+    #[clap(long)]
+    pub mirror_structure: bool,
+
+    /// VMAF target value, like 95.5
+    #[clap(short = 'v', long, default_value = "97", value_parser = vmaf_input_validation)]
+    pub vmaf: f32,
+
+    /// How much the VMAF target is lowered each time the CRF search finds no CRF that reaches it
+    #[clap(long, default_value = "0.5", value_parser = vmaf_retry_step_input_validation)]
+    pub vmaf_retry_step: f32,
+
+    /// Keep the VMAF target for heavily compressed sources instead of lowering it, see --low-quality-bpp
+    #[clap(long)]
+    pub no_adaptive_target: bool,
+
+    /// Bits per pixel, counted as h264, below which a source is heavily compressed and its VMAF target is lowered to 95, to 93 below half of it
+    #[clap(long, default_value = "0.05", value_parser = low_quality_bpp_input_validation)]
+    pub low_quality_bpp: f32,
+
+    /// Encode to this size in MB at a fixed bitrate instead of searching a CRF for the VMAF target
+    /// (default mode), two-pass with libx265 and libaom-av1, the encoder's own bitrate mode with the others
+    #[clap(long, conflicts_with = "vmaf")]
+    pub target_size: Option<f64>,
+
+    /// Encode every file into several renditions (default mode), like "1080p:vmaf=95,720p:bitrate=2500k":
+    /// a VMAF rendition searches its own CRF at its height, a bitrate rendition is encoded at that bitrate,
+    /// heights above the source are left out
+    #[clap(long, conflicts_with = "target_size", value_parser = renditions_validation)]
+    pub renditions: Option<String>,
+
+    /// Scale a larger source down into this size, like 1920x1080 or 1080p, keeping its aspect ratio, never up:
+    /// the CRF is searched against the source scaled the same way and the output name gets the new size
+    #[clap(long, conflicts_with = "renditions", value_parser = max_resolution_validation)]
+    pub max_resolution: Option<String>,
+
+    /// Encoder to use
+    /// (possible values: auto, libx265, av1, libsvtav1, hevc_nvenc, hevc_qsv, av1_qsv)
+    /// auto picks hevc_nvenc, then hevc_qsv if the hardware works, otherwise libx265
+    #[clap(short = 'e', long, default_value = "libx265")]
+    pub encoder: String,
+
+    /// Encoder a hardware encoder falls back to when it fails to start, the file is searched and encoded again with it
+    /// (possible values: libx265, av1, libsvtav1, hevc_nvenc, hevc_qsv, av1_qsv, none) defaults to libx265, libsvtav1 for av1_qsv
+    #[clap(long, value_parser = possible_fallback_encoder_values)]
+    pub fallback_encoder: Option<String>,
+
+    /// Encode on this GPU of a multi GPU host: CUDA_VISIBLE_DEVICES for hevc_nvenc, the child_device= of -init_hw_device in the qsv params, the render node of -vaapi_device in the params
+    #[clap(long)]
+    pub gpu_index: Option<u32>,
+
+    /// Set an environment variable of the ffmpeg, ffprobe and ab-av1 processes, KEY=VALUE, can be repeated
+    #[clap(long, value_parser = env_input_validation)]
+    pub env: Vec<String>,
+
+    /// Start ffmpeg, ffprobe and ab-av1 with a minimal environment (PATH, HOME, the temp folder, the driver variables) instead of the whole environment, --env adds to it
+    #[clap(long)]
+    pub clean_env: bool,
+
+    /// Deprecated, use --output
+    #[clap(long, hide = true, conflicts_with = "outputpath")]
+    pub output_folder: Option<String>,
+
+    /// show output crf search
+    #[clap(long)]
+    pub verbose: bool,
+
+    /// Which mode to use for processing
+    /// (possible values: default, chunked, audio-only) audio-only converts the audio and copies the video
+    #[clap(short = 'm', long, default_value = "default", value_parser = possible_mode_values)]
+    pub mode: String,
+
+    /// Which vmaf pool method to use
+    /// (possible values: min, harmonic_mean, mean)
+    #[clap(short = 'p', long, default_value = "mean", value_parser = possible_pool_values)]
+    pub vmaf_pool: String,
+
+    /// libvmaf threads of each VMAF calculation (default: half the logical cores, in chunked mode the logical cores divided over the --scene-jobs)
+    #[clap(short = 't', long, default_value = "", hide_default_value = true, value_parser = vmaf_thread_input_validation)]
+    pub vmaf_threads: String,
+
+    /// Number of scenes whose CRF is searched in parallel in chunked mode (default: 2, fewer when the logical cores do not fit their VMAF threads)
+    #[clap(long)]
+    pub scene_jobs: Option<usize>,
+
+    /// Number of files whose frames are counted in parallel before a batch is encoded, the counts are kept in the database
+    #[clap(long, default_value = "4")]
+    pub probe_jobs: usize,
+
+    /// Packets the VMAF ffmpeg buffers of the piped sample encode (default: 4096 up to 1080p, 8192 up to 4K, 16384 above)
+    #[clap(long)]
+    pub thread_queue_size: Option<u32>,
+
+    /// Seconds a sample encode with its VMAF may run before both are killed and the scene or sample is given up, 0 waits forever
+    #[clap(long, default_value = "3600")]
+    pub scene_timeout: u64,
+
+    /// Every n frame to subsample in the vmaf calculation
+    #[clap(short = 'S', long, default_value = "1", value_parser = vmaf_subsample_input_validation)]
+    pub vmaf_subsample: String,
+
+    /// Pixel format to use
+    #[clap(long, default_value = "yuv420p10le")]
+    pub pix_fmt: String,
+
+    /// Seconds between two keyframes of the encodes and the CRF search samples, from 2 for faster seeking to 10
+    #[clap(long, default_value = "10", value_parser = keyint_seconds_input_validation)]
+    pub keyint_seconds: f32,
+
+    /// Fewest frames between two keyframes, a scene cut can't place them closer (default: the encoder's)
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub min_keyint: Option<u32>,
+
+    /// Max CRF value
+    /// (possible values: 0-51)
+    #[clap(long, default_value = "28")]
+    pub max_crf: String,
+
+    /// Sample every Nth minute
+    #[clap(long, default_value = "3m")]
+    pub sample_every: String,
+
+    /// CRF search of the default mode, native encodes and measures samples without ab-av1
+    /// (possible values: ab-av1, native) native is used when ab-av1 is not installed
+    #[clap(long, default_value = "ab-av1", value_parser = possible_crf_search_values)]
+    pub crf_search: String,
+
+    /// Seconds below which a file is a short input, like a trailer or a sample, encoded at --short-input-crf without the CRF search, chunked mode encodes it like the default mode
+    #[clap(long, default_value = "60")]
+    pub min_duration: f64,
+
+    /// CRF of the short inputs below --min-duration
+    #[clap(long, default_value = "20")]
+    pub short_input_crf: f32,
+
+    /// Skip the short inputs below --min-duration with status skipped_short instead of encoding them
+    #[clap(long)]
+    pub skip_short: bool,
+
+    /// Encode only <start>-<end> of each file to try the settings, in timecodes or seconds like 20:00-25:00, the output is named .trial_<start>_<end> and the file gets the status trial
+    #[clap(long, value_parser = trial_range_input_validation)]
+    pub trial_range: Option<String>,
+
+    /// Params for ab-av1
+    #[clap(
+        long,
+        default_value = "x265-params=limit-sao,bframes=8,psy-rd=1,aq-mode=3"
+    )]
+    pub params_ab_av1: String,
+
+    /// Params for x265
+    #[clap(
+        long,
+        default_value = "-x265-params limit-sao:bframes=8:psy-rd=1:aq-mode=3",
+        value_parser = params_input_validation
+    )]
+    pub params_x265: String,
+
+    /// Preset for x265
+    /// (possible values: ultrafast, superfast, veryfast, faster, fast, medium, slow, slower, veryslow, placebo)
+    #[clap(long, default_value = "slow")]
+    pub preset_x265: String,
+
+    /// Preset for av1
+    #[clap(long, default_value = "8")]
+    pub preset_av1: String,
+
+    /// Preset for hevc_nvenc
+    #[clap(long, default_value = "p7")]
+    pub preset_hevc_nvenc: String,
+
+    /// Params for hevc_nvenc
+    #[clap(long, default_value = "-rc-lookahead 100 -b_ref_mode each -tune hq", value_parser = params_input_validation)]
+    pub params_hevc_nvenc: String,
+
+    /// Preset for hevc_qsv
+    #[clap(long, default_value = "veryslow")]
+    pub preset_hevc_qsv: String,
+
+    /// Preset for av1_qsv
+    #[clap(long, default_value = "1")]
+    pub preset_av1_qsv: String,
+
+    /// Params for hevc_qsv
+    #[clap(
+        long,
+        default_value = "-init_hw_device qsv=intel,child_device=0 -b_strategy 1 -look_ahead 1 -async_depth 100",
+        value_parser = params_input_validation
+    )]
+    pub params_hevc_qsv: String,
+
+    /// Params for av1_qsv
+    #[clap(
+        long,
+        default_value = "-init_hw_device qsv=intel,child_device=0 -b_strategy 1 -look_ahead 1 -async_depth 100",
+        value_parser = params_input_validation
+    )]
+    pub params_av1_qsv: String,
+
+    /// Preset for libsvtav1
+    /// (possible values: -2 - 13)
+    #[clap(long, default_value = "5")]
+    pub preset_libsvtav1: String,
+
+    /// Params for libsvtav1
+    #[clap(long, default_value = "", value_parser = params_input_validation)]
+    pub params_libsvtav1: String,
+
+    /// Preset for libaom-av1
+    #[clap(long, default_value = "4")]
+    pub preset_libaom_av1: String,
+
+    /// Params for libaom-av1
+    #[clap(long, default_value = "", value_parser = params_input_validation)]
+    pub params_libaom_av1: String,
+
+    /// Tune the encoder for the content (possible values: auto, film, animation, grain, none)
+    /// auto picks film, animation or grain by the bitrate per pixel of the source
+    #[clap(long, default_value = "none", value_parser = possible_content_tune_values)]
+    pub content_tune: String,
+
+    /// Film grain synthesis level of libsvtav1 and av1 (libaom-av1), the CRF search and the encode get the same
+    /// (possible values: 0-50, auto) auto picks the level by the bitrate per pixel of the source
+    #[clap(long, value_parser = film_grain_input_validation)]
+    pub film_grain: Option<String>,
+
+    /// Denoise the source for --film-grain (possible values: 0, 1) the encoder's default without it
+    #[clap(long, value_parser = film_grain_denoise_input_validation)]
+    pub film_grain_denoise: Option<String>,
+
+    /// Scene split minimum seconds
+    #[clap(short = 's', long, default_value = "2")]
+    pub scene_split_min: f32,
+
+    /// How chunked mode splits the file
+    /// (possible values: scenes, chapters) chapters falls back to scene detection for files with fewer than 2 chapters
+    #[clap(long, default_value = "scenes", value_parser = possible_chunk_by_values)]
+    pub chunk_by: String,
+
+    /// Source kB per frame below which chunked mode encodes a scene, like credits or black frames, with a faster
+    /// preset at --max-crf, kept when its VMAF reaches --vmaf, otherwise the scene gets the full CRF search
+    #[clap(long, default_value = "5.0")]
+    pub fast_scene_threshold: f64,
+
+    /// Search the CRF of every scene in chunked mode with the configured preset, no fast scenes
+    #[clap(long)]
+    pub uniform_scenes: bool,
+
+    /// Task ID
+    #[clap(short = 'd', long, default_value = "")]
+    pub task_id: String,
+
+    /// Write per-scene CRF/VMAF debug lines to this file (chunked mode), a relative path is put in the run's artifacts folder
+    #[clap(long)]
+    pub debug_log: Option<String>,
+
+    /// Folder of the per-run artifacts of chunked mode (chunks.txt, progress.json, the debug log), defaults to ./artifacts
+    #[clap(long)]
+    pub artifacts_dir: Option<String>,
+
+    /// Write the chunked mode artifacts to the working directory like older versions, to be removed in the next release
+    #[clap(long)]
+    pub legacy_artifacts: bool,
+
+    /// Remove the run folders in the artifacts folder older than this ("7d", "12h") and exit
+    #[clap(long, value_parser = artifacts_age_validation)]
+    pub clean_artifacts: Option<String>,
+
+    /// Log level (possible values: off, error, warn, info, debug, trace)
+    #[clap(long, default_value = "info", value_parser = possible_log_level_values)]
+    pub log_level: String,
+
+    /// Write the log to this file instead of the terminal
+    #[clap(long)]
+    pub log_file: Option<String>,
+
+    /// Progress output (possible values: text, json) json replaces the bars with newline-delimited json events on stdout (scan_progress, file_start, crf_found, transcode_progress, file_done, file_failed, run_done), the log stays on stderr
+    #[clap(long, default_value = "text", value_parser = possible_progress_format_values, conflicts_with = "interactive")]
+    pub progress_format: String,
+
+    /// Milliseconds between two updates of the progress bars and the web progress, ffmpeg output is still read as it comes
+    #[clap(long, default_value = "250")]
+    pub progress_interval: u64,
+
+    /// Path of the sqlite database
+    #[clap(long, default_value = "data.db")]
+    pub db_path: String,
+
+    /// Export the video_info table to this file and exit
+    #[clap(long)]
+    pub db_export: Option<String>,
+
+    /// Format of --db-export (possible values: json, csv)
+    #[clap(long, default_value = "json", value_parser = possible_db_export_format_values)]
+    pub db_export_format: String,
+
+    /// Import a json or csv export into the video_info table and exit, rows are matched by filepath
+    #[clap(long)]
+    pub db_import: Option<String>,
+
+    /// List the files of the database that are duplicates of each other, by content hash or for
+    /// files without one by resolution, duration and size within 1%, and exit
+    #[clap(long)]
+    pub db_duplicates: bool,
+
+    /// With --db-duplicates, set the status of all but the highest bitrate copy to duplicate so
+    /// they are not encoded
+    #[clap(long, requires = "db_duplicates")]
+    pub mark_duplicates: bool,
+
+    /// Create the missing indexes of the database, run PRAGMA optimize, VACUUM it when enough pages
+    /// are free, print its size and row counts before and after and exit
+    #[clap(long)]
+    pub db_maintain: bool,
+
+    /// Percent of free pages from which --db-maintain runs VACUUM
+    #[clap(long, default_value = "10.0")]
+    pub vacuum_threshold: f64,
+
+    /// Decode every file of the database, or those below -i, with ffmpeg -xerror and store whether
+    /// it is ok, corrupt or suspect, list the broken ones and exit. A file marked corrupt is not encoded
+    #[clap(long)]
+    pub verify_library: bool,
+
+    /// With --verify-library, only decode the first minute of each file
+    #[clap(long, requires = "verify_library")]
+    pub quick: bool,
+
+    /// Number of files --verify-library decodes in parallel
+    #[clap(long, default_value = "2")]
+    pub verify_jobs: usize,
+
+    /// Encode again even if the output folder already has a matching output
+    #[clap(long)]
+    pub force_reencode: bool,
+
+    /// Clear the locks another run left on the inputs when it has not refreshed them for --lock-stale-after, after a crash
+    #[clap(long)]
+    pub force_lock_takeover: bool,
+
+    /// How long a run lock may go without being refreshed before --force-lock-takeover clears it, a duration like 10m
+    #[clap(long, default_value = DEFAULT_LOCK_STALE_AFTER, value_parser = lock_stale_after_validation)]
+    pub lock_stale_after: String,
+
+    /// Audio codec used when an audio stream is re-encoded
+    /// (possible values: opus, aac)
+    #[clap(long, default_value = "opus", value_parser = possible_audio_codec_values)]
+    pub audio_codec: String,
+
+    /// Re-encode aac/opus/mp3 audio streams above this bitrate in kbps (default: no limit)
+    #[clap(long)]
+    pub max_audio_bitrate: Option<i64>,
+
+    /// Bitrate in kbps of re-encoded stereo audio streams (default: 128 for opus, 192 for aac)
+    #[clap(long, value_parser = clap::value_parser!(i64).range(6..=1024))]
+    pub audio_bitrate_stereo: Option<i64>,
+
+    /// Bitrate in kbps of re-encoded 5.1 audio streams (default: 256 for opus, 384 for aac)
+    #[clap(long, value_parser = clap::value_parser!(i64).range(6..=1024))]
+    pub audio_bitrate_51: Option<i64>,
+
+    /// Bitrate in kbps of re-encoded 7.1 audio streams (default: 450 for opus, 512 for aac)
+    #[clap(long, value_parser = clap::value_parser!(i64).range(6..=1024))]
+    pub audio_bitrate_71: Option<i64>,
+
+    /// Only keep the audio streams in these languages ("eng,jpn"), the default or else the first audio stream is kept when none matches
+    #[clap(long, value_delimiter = ',')]
+    pub keep_audio_langs: Vec<String>,
+
+    /// Only keep the subtitle streams in these languages ("eng")
+    #[clap(long, value_delimiter = ',')]
+    pub keep_subs_langs: Vec<String>,
+
+    /// Ask before encoding each pending file (cli only)
+    #[clap(long)]
+    pub interactive: bool,
+
+    /// Also process files that were declined in interactive mode (status skipped_manual)
+    #[clap(long)]
+    pub include_manual_skips: bool,
+
+    /// Mark a file skipped instead of encoding it when the crf-search predicts no size savings
+    #[clap(long)]
+    pub skip_if_no_savings: bool,
+
+    /// Encode three 10 second samples of each pending file at CRF 23, print the estimated size, savings and encode time of the files ranked by the bytes saved, store them and exit
+    #[clap(long)]
+    pub estimate: bool,
+
+    /// Mark a file skipped_estimate instead of encoding it when its --estimate with the same encoder and preset saves less than this percent
+    #[clap(long, value_parser = min_estimated_savings_input_validation)]
+    pub min_estimated_savings: Option<f64>,
+
+    /// Delete an encode larger than --max-output-ratio of its source and keep the original (status kept_original), chunked mode gives up once a fifth of the scenes came out larger
+    #[clap(long)]
+    pub discard_if_larger: bool,
+
+    /// Keep an encode larger than --max-output-ratio of its source, only listed in red in the summary
+    #[clap(long)]
+    pub warn_if_larger: bool,
+
+    /// Output size over source size above which --discard-if-larger and --warn-if-larger apply, like 0.95
+    #[clap(long, default_value = "1.0")]
+    pub max_output_ratio: f64,
+
+    /// Number of scenes encoded in parallel in chunked mode (default: 1 for hardware encoders, otherwise scene-jobs)
+    #[clap(long)]
+    pub encode_jobs: Option<usize>,
+
+    /// Append each encoded scene to the output as soon as the scenes before it are done and delete it, chunked mode then needs a couple of scenes of scratch space instead of the whole video twice
+    #[clap(long)]
+    pub stream_concat: bool,
+
+    /// What a chunked encode whose video drifted from its audio by more than --sync-max-drift after the concat does
+    /// (possible values: fail, warn, off) fail marks it failed_sync and keeps its scenes
+    #[clap(long, default_value = "fail", value_parser = possible_sync_check_values)]
+    pub sync_check: String,
+
+    /// Milliseconds the streams and frames of a chunked encode may drift from the source before --sync-check applies
+    #[clap(long, default_value = "50")]
+    pub sync_max_drift: f64,
+
+    /// Measure the VMAF of the finished encode against the source on evenly spaced samples
+    #[clap(long)]
+    pub verify_vmaf: bool,
+
+    /// Number of 20 second samples measured by --verify-vmaf
+    #[clap(long, default_value = "3")]
+    pub verify_samples: u32,
+
+    /// Warn when the verified VMAF is more than this below the target
+    #[clap(long, default_value = "1.0")]
+    pub verify_max_delta: f32,
+
+    /// Set the status to failed_verification when the verified VMAF is too low
+    #[clap(long)]
+    pub fail_verification: bool,
+
+    /// Save this many frames of the source and the encode as png under <outputpath>/screenshots after a successful encode, chunked mode picks the scenes with the lowest VMAF first
+    #[clap(long)]
+    pub screenshots: Option<u32>,
+
+    /// Give the output the modified time of the source after a successful encode
+    #[clap(long)]
+    pub preserve_mtime: bool,
+
+    /// Copy the files next to the source that share its name (movie.srt, movie.en.srt, movie.nfo) to the output folder after a successful encode, renamed to the output's name, newer files already there are kept
+    #[clap(long)]
+    pub copy_sidecars: bool,
+
+    /// Mux the .srt, .ass and .ssa files next to the source that share its name (movie.srt, movie.en.srt) into the output as subtitle streams, with the language of their name, an embedded subtitle of the same language is kept instead
+    #[clap(long)]
+    pub ingest_external_subs: bool,
+
+    /// With --ingest-external-subs, drop the embedded subtitles of the language of an external subtitle instead
+    #[clap(long, requires = "ingest_external_subs")]
+    pub prefer_external_subs: bool,
+
+    /// Free space in GB to keep on the output and working volumes, files that would not fit are skipped
+    #[clap(long, default_value = "5.0")]
+    pub min_free_space: f64,
+
+    /// Copy every file to this local folder before it is encoded, the next one while the current one encodes, for sources on a slow network share. Files that don't fit with --min-free-space are encoded from where they are
+    #[clap(long)]
+    pub stage_dir: Option<String>,
+
+    /// Encode Dolby Vision files as HDR10 base layer only by stripping the Dolby Vision signaling, otherwise they are skipped with status skipped_dovi
+    #[clap(long)]
+    pub strip_dovi: bool,
+
+    /// Convert HDR10 and HLG sources to SDR (possible values: off, sdr) sdr tonemaps with libplacebo when ffmpeg has it, zscale and tonemap otherwise, and encodes 8 bit bt709 without the HDR metadata
+    #[clap(long, default_value = "off", value_parser = possible_tonemap_values)]
+    pub tonemap: String,
+
+    /// Burn a subtitle track into the video and drop it from the output: auto (the forced track), a subtitle stream index (0 is the first subtitle) or a language ("eng")
+    #[clap(long, value_parser = burn_subs_input_validation)]
+    pub burn_subs: Option<String>,
+
+    /// Deinterlace the video with yadif (possible values: auto, on, off) auto deinterlaces streams whose field order is interlaced, like DVB/ATSC captures
+    #[clap(long, default_value = "auto", value_parser = possible_deinterlace_values)]
+    pub deinterlace: String,
+
+    /// Leave files matching this gitignore-style pattern out of folder scans ("**/sample/**", "extras/", "*.partial~"), can be repeated, a .transrusticaignore file in the scanned folder adds its patterns
+    #[clap(long)]
+    pub exclude: Vec<String>,
+
+    /// Share of the rows of the scanned folder a scan may move to the trash of the database, more are only listed unless --force is given
+    #[clap(long, default_value = "0.2", value_parser = trash_fraction_validation)]
+    pub max_trash_fraction: f64,
+
+    /// Remove the files of the scanned folder that are gone from the database even when they are more than --max-trash-fraction of its rows
+    #[clap(long)]
+    pub force: bool,
+
+    /// Only scan and encode files modified since then, a duration ("7d", "12h") or a UTC date ("2024-05-01", "2024-05-01T18:00:00")
+    #[clap(long, value_parser = since_input_validation)]
+    pub since: Option<String>,
+
+    /// Continue the last unfinished batch with its files in the same order, skipping the done ones, the encoder, mode and target have to be the same
+    #[clap(long, conflicts_with = "resume")]
+    pub resume_last: bool,
+
+    /// Continue the unfinished batch of this session id like --resume-last, `sessions list` shows them
+    #[clap(long)]
+    pub resume: Option<String>,
+
+    /// Keep watching the input folder after the first pass and encode video files as they appear (cli only)
+    #[clap(long)]
+    pub watch: bool,
+
+    /// Seconds the size of a new file must stay the same before --watch processes it
+    #[clap(long, default_value = "30")]
+    pub watch_settle: u64,
+
+    /// Seconds since its last modification before a file is encoded, a file modified more recently may still be written and is encoded at the end of the batch, 0 turns this off
+    #[clap(long, default_value = "30")]
+    pub settle_seconds: u64,
+
+    /// Write the files that failed, with the failed stage and the end of the tool output, to this json file
+    #[clap(long)]
+    pub failures_json: Option<String>,
+
+    /// Write a report of the run to this json file: the files that failed and the encodes larger than their source
+    #[clap(long)]
+    pub report_json: Option<String>,
+
+    /// POST a JSON message to this url for every failed file and with the summary at the end of the run
+    #[clap(long)]
+    pub notify_webhook: Option<String>,
+
+    /// Which --notify-webhook messages to send
+    /// (possible values: failure, success, all) failure sends the failed files and the summary of a run with failures, success only the summary of a run without
+    #[clap(long, default_value = "all", value_parser = possible_notify_on_values)]
+    pub notify_on: String,
+
+    /// Serve Prometheus metrics (files, bytes saved, frames, fps, failures, queue and scans) at http://0.0.0.0:<port>/metrics while the cli runs (cli only, the web server has /metrics)
+    #[clap(long)]
+    pub metrics_port: Option<u16>,
+
+    /// Load the options saved with --save-profile, options given on the command line take precedence
+    #[clap(long, value_parser = profile_name_validation)]
+    pub profile: Option<String>,
+
+    /// Save the current options (without input, output and task id) as a profile, without an input path it exits after saving
+    #[clap(long, value_parser = profile_name_validation)]
+    pub save_profile: Option<String>,
+
+    /// List the saved profiles and exit
+    #[clap(long)]
+    pub list_profiles: bool,
+
+    /// Write small synthetic test files (h264 with aac, with 5.1 flac, without audio, variable frame rate) to this folder and exit
+    #[clap(long, hide = true)]
+    pub generate_test_media: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: Option<Commands>,
+}
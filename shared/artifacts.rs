@@ -0,0 +1,225 @@
+// Files chunked mode leaves next to the scenes: the debug log, chunks.txt, progress.json and the
+// concat list. Every encode gets its own run folder under artifacts/ in the work dir or under
+// --artifacts-dir, so runs don't append to each other's files
+// --legacy-artifacts writes them to the work dir as before
+// done.txt and searched.txt stay in the work dir, a resumed run has to find them
+
+use crate::*;
+
+pub const ARTIFACTS_FOLDER: &str = "artifacts";
+pub const CHUNK_LOG_FILE: &str = "chunks.txt";
+pub const PROGRESS_SNAPSHOT_FILE: &str = "progress.json";
+pub const CONCAT_LIST_FILE: &str = "list.txt";
+
+// The debug log is rotated to <name>.1 .. <name>.3 when it grows past this
+const DEBUG_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const DEBUG_LOG_KEEP: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct RunArtifacts {
+    pub dir: PathBuf,
+    pub legacy: bool,
+}
+
+// The folder the run folders are created in
+pub fn artifacts_root(args: &Args) -> PathBuf {
+    match &args.artifacts_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(ARTIFACTS_FOLDER),
+    }
+}
+
+impl RunArtifacts {
+    // A new run folder for file, <utc time>_<file stem>, the colons are left out for Windows
+    // Two encodes of the same file in the same second get _2, _3 ...
+    pub fn create(args: &Args, file: &str) -> Result<RunArtifacts, io::Error> {
+        if args.legacy_artifacts {
+            return Ok(RunArtifacts {
+                dir: PathBuf::from("."),
+                legacy: true,
+            });
+        }
+        let root = artifacts_root(args);
+        fs::create_dir_all(&root)?;
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .replace(':', "");
+        let stem = Path::new(file)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("file");
+        let name = format!("{}_{}", timestamp, stem);
+        let mut dir = root.join(&name);
+        let mut n = 2;
+        // create_dir fails on an existing folder, so two runs never share one
+        loop {
+            match fs::create_dir(&dir) {
+                Ok(()) => break,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    dir = root.join(format!("{}_{}", name, n));
+                    n += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        debug!("Run artifacts of {} in {}", file, dir.display());
+        Ok(RunArtifacts { dir, legacy: false })
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // --debug-log: a relative path goes into the run folder, an absolute one is used as given
+    pub fn debug_log_path(&self, debug_log: &str) -> PathBuf {
+        let path = Path::new(debug_log);
+        if path.is_absolute() || self.legacy {
+            path.to_path_buf()
+        } else {
+            self.path(debug_log)
+        }
+    }
+}
+
+// Per-scene CRF/VMAF lines, shared by the scene threads
+// Appends across runs like before, but is rotated when it gets too large
+pub struct DebugLog {
+    path: PathBuf,
+    log: Mutex<ProgressLog>,
+}
+
+impl DebugLog {
+    pub fn open(path: &Path) -> Result<DebugLog, io::Error> {
+        Ok(DebugLog {
+            path: path.to_path_buf(),
+            log: Mutex::new(ProgressLog::open(&path.to_string_lossy())?),
+        })
+    }
+
+    pub fn write_line(&self, line: &str) -> Result<(), io::Error> {
+        let mut log = self.log.lock().unwrap();
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size >= DEBUG_LOG_MAX_BYTES {
+            rotate_file(&self.path, DEBUG_LOG_KEEP)?;
+            *log = ProgressLog::open(&self.path.to_string_lossy())?;
+        }
+        log.write_line(line)
+    }
+}
+
+// path.1 becomes path.2 and so on, the oldest past keep is dropped, then path becomes path.1
+fn rotate_file(path: &Path, keep: u32) -> Result<(), io::Error> {
+    let rotated = |n: u32| PathBuf::from(format!("{}.{}", path.display(), n));
+    let _ = fs::remove_file(rotated(keep));
+    for n in (1..keep).rev() {
+        if rotated(n).exists() {
+            fs::rename(rotated(n), rotated(n + 1))?;
+        }
+    }
+    fs::rename(path, rotated(1))
+}
+
+// index, scene size and encoded size in bytes and the path (fast or full) of every finished scene
+// A run folder starts it empty, --legacy-artifacts appends to the one in the work dir
+pub struct ChunkLog {
+    log: ProgressLog,
+}
+
+impl ChunkLog {
+    pub fn open(artifacts: &RunArtifacts) -> Result<ChunkLog, io::Error> {
+        let path = artifacts.path(CHUNK_LOG_FILE);
+        if !artifacts.legacy {
+            File::create(&path)?;
+        }
+        Ok(ChunkLog {
+            log: ProgressLog::open(&path.to_string_lossy())?,
+        })
+    }
+
+    pub fn write_scene(
+        &self,
+        index: i32,
+        scene_size: u64,
+        encoded_size: u64,
+        scene_path: &str,
+    ) -> Result<(), io::Error> {
+        self.log.write_line(&format!(
+            "index: {}, scene_size: {}, encoded_size: {}, path: {}",
+            index, scene_size, encoded_size, scene_path
+        ))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkProgress<'a> {
+    pub file: &'a str,
+    pub total_scenes: usize,
+    pub searched_scenes: usize,
+    pub encoded_scenes: usize,
+}
+
+// progress.json, only ever the latest state of the run
+// Written to a temporary file and renamed, a reader never sees half of it
+pub struct ProgressSnapshot {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl ProgressSnapshot {
+    pub fn new(artifacts: &RunArtifacts) -> ProgressSnapshot {
+        ProgressSnapshot {
+            path: artifacts.path(PROGRESS_SNAPSHOT_FILE),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn write(&self, progress: &ChunkProgress) -> Result<(), io::Error> {
+        let json = serde_json::to_string_pretty(progress)?;
+        let _lock = self.lock.lock().unwrap();
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, json)?;
+        fs::rename(&temp, &self.path)
+    }
+}
+
+// --clean-artifacts: remove the run folders under the artifacts folder last modified longer than
+// older_than ago, returns how many were removed
+pub fn clean_artifacts(args: &Args, older_than: &str) -> Result<usize, String> {
+    let age = humantime::parse_duration(older_than)
+        .map_err(|e| format!("invalid age {}: {}", older_than, e))?;
+    let cutoff = SystemTime::now()
+        .checked_sub(age)
+        .ok_or_else(|| "age too long".to_string())?;
+    let root = artifacts_root(args);
+    if !root.is_dir() {
+        info!("No artifacts in {}", root.display());
+        return Ok(0);
+    }
+    let entries =
+        fs::read_dir(&root).map_err(|e| format!("Failed to read {}: {}", root.display(), e))?;
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let modified = match entry.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) => continue,
+        };
+        if modified >= cutoff {
+            continue;
+        }
+        match fs::remove_dir_all(&path) {
+            Ok(()) => {
+                debug!("Removed {}", path.display());
+                removed += 1;
+            }
+            Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests;
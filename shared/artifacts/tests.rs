@@ -0,0 +1,170 @@
+use super::*;
+
+// The options of a run whose artifacts go to a folder of its own
+fn artifacts_args(name: &str) -> (Args, PathBuf) {
+    let root = temp_path(name);
+    let args = test_args(&["--artifacts-dir", &root.to_string_lossy()]);
+    (args, root)
+}
+
+#[test]
+fn each_run_gets_its_own_folder() {
+    let (args, root) = artifacts_args("artifacts-runs");
+    let first = RunArtifacts::create(&args, "/media/Movie (2020)/Movie.mkv").unwrap();
+    let second = RunArtifacts::create(&args, "/media/Movie (2020)/Movie.mkv").unwrap();
+    assert_ne!(first.dir, second.dir);
+    for run in [&first, &second] {
+        assert!(!run.legacy);
+        assert!(run.dir.is_dir());
+        assert_eq!(run.dir.parent(), Some(root.as_path()));
+        let name = run.dir.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.contains("_Movie"), "{}", name);
+        assert!(!name.contains(':'), "{}", name);
+    }
+}
+
+#[test]
+fn the_debug_log_goes_into_the_run_folder_unless_absolute() {
+    let (args, _) = artifacts_args("artifacts-debug-path");
+    let run = RunArtifacts::create(&args, "Movie.mkv").unwrap();
+    assert_eq!(run.debug_log_path("debug.txt"), run.dir.join("debug.txt"));
+    let absolute = temp_path("debug.txt");
+    assert_eq!(run.debug_log_path(&absolute.to_string_lossy()), absolute);
+}
+
+#[test]
+fn legacy_artifacts_stay_in_the_working_directory() {
+    let args = test_args(&["--legacy-artifacts"]);
+    let run = RunArtifacts::create(&args, "Movie.mkv").unwrap();
+    assert!(run.legacy);
+    assert_eq!(
+        run.path(CHUNK_LOG_FILE),
+        Path::new(".").join(CHUNK_LOG_FILE)
+    );
+    assert_eq!(run.debug_log_path("debug.txt"), PathBuf::from("debug.txt"));
+}
+
+#[test]
+fn parallel_scene_threads_write_to_the_files_of_their_run() {
+    let (args, _) = artifacts_args("artifacts-parallel");
+    let debug_log_name = "transrustica-artifacts-test-debug.txt";
+    let runs: Vec<RunArtifacts> = (0..2)
+        .map(|_| RunArtifacts::create(&args, "Movie.mkv").unwrap())
+        .collect();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(8)
+        .build()
+        .unwrap();
+    for run in &runs {
+        let debug_log = DebugLog::open(&run.debug_log_path(debug_log_name)).unwrap();
+        let chunk_log = ChunkLog::open(run).unwrap();
+        let snapshot = ProgressSnapshot::new(run);
+        pool.install(|| {
+            (0..64).into_par_iter().for_each(|index| {
+                debug_log
+                    .write_line(&format!("scene {} crf 24 vmaf 95", index))
+                    .unwrap();
+                chunk_log
+                    .write_scene(index, 1000, 400, &format!("encoded/{}.mkv", index))
+                    .unwrap();
+                snapshot
+                    .write(&ChunkProgress {
+                        file: "Movie.mkv",
+                        total_scenes: 64,
+                        searched_scenes: 64,
+                        encoded_scenes: index as usize + 1,
+                    })
+                    .unwrap();
+            });
+        });
+    }
+
+    for run in &runs {
+        let debug_lines = fs::read_to_string(run.path(debug_log_name)).unwrap();
+        assert_eq!(debug_lines.lines().count(), 64);
+        let chunk_lines = fs::read_to_string(run.path(CHUNK_LOG_FILE)).unwrap();
+        let mut indexes: Vec<i32> = chunk_lines
+            .lines()
+            .map(|line| {
+                line.strip_prefix("index: ")
+                    .and_then(|line| line.split(',').next())
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        indexes.sort();
+        assert_eq!(indexes, (0..64).collect::<Vec<_>>());
+        // always a whole snapshot, never the temporary file
+        let snapshot: Value =
+            serde_json::from_str(&fs::read_to_string(run.path(PROGRESS_SNAPSHOT_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(snapshot["total_scenes"], 64);
+        assert!(!run.path("progress.json.tmp").exists());
+    }
+    // nothing in the working directory
+    assert!(!Path::new(debug_log_name).exists());
+}
+
+#[test]
+fn the_debug_log_is_rotated_past_its_size() {
+    let dir = temp_path("artifacts-rotate");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("debug.txt");
+    for n in 1..=DEBUG_LOG_KEEP {
+        fs::write(format!("{}.{}", path.display(), n), n.to_string()).unwrap();
+    }
+    File::create(&path)
+        .unwrap()
+        .set_len(DEBUG_LOG_MAX_BYTES)
+        .unwrap();
+
+    let log = DebugLog::open(&path).unwrap();
+    log.write_line("scene 0 crf 24 vmaf 95").unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "scene 0 crf 24 vmaf 95\n"
+    );
+    let rotated = |n: u32| PathBuf::from(format!("{}.{}", path.display(), n));
+    assert_eq!(fs::metadata(rotated(1)).unwrap().len(), DEBUG_LOG_MAX_BYTES);
+    assert_eq!(fs::read_to_string(rotated(2)).unwrap(), "1");
+    assert_eq!(fs::read_to_string(rotated(3)).unwrap(), "2");
+    // the oldest is dropped
+    assert!(!rotated(DEBUG_LOG_KEEP + 1).exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn clean_artifacts_removes_the_old_run_folders() {
+    let (args, root) = artifacts_args("artifacts-clean");
+    let old = RunArtifacts::create(&args, "Old.mkv").unwrap();
+    let recent = RunArtifacts::create(&args, "Recent.mkv").unwrap();
+    fs::write(old.path(CHUNK_LOG_FILE), "index: 0").unwrap();
+    let eight_days_ago = SystemTime::now() - Duration::from_secs(8 * 24 * 3600);
+    File::open(&old.dir)
+        .unwrap()
+        .set_modified(eight_days_ago)
+        .unwrap();
+    // a file next to the run folders is not one
+    fs::write(root.join("notes.txt"), "").unwrap();
+    File::options()
+        .write(true)
+        .open(root.join("notes.txt"))
+        .unwrap()
+        .set_modified(eight_days_ago)
+        .unwrap();
+
+    assert_eq!(clean_artifacts(&args, "7d"), Ok(1));
+    assert!(!old.dir.exists());
+    assert!(recent.dir.is_dir());
+    assert!(root.join("notes.txt").exists());
+    assert!(clean_artifacts(&args, "a week").is_err());
+}
+
+#[test]
+fn clean_artifacts_without_an_artifacts_folder() {
+    let (args, root) = artifacts_args("artifacts-none");
+    assert_eq!(clean_artifacts(&args, "7d"), Ok(0));
+    assert!(!root.exists());
+}
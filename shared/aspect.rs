@@ -0,0 +1,92 @@
+// Anamorphic sources: DVDs and some web sources store 720x480 frames that are shown at 16:9 through
+// the sample aspect ratio of the video stream. The encode keeps the SAR with setsar and the display
+// aspect ratio with -aspect, and the VMAF scales both of its inputs to the same square pixels
+
+use crate::*;
+
+// The aspect ratios and size of an anamorphic video stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anamorphic {
+    pub sar: (u64, u64),
+    pub dar: (u64, u64),
+    pub width: u64,
+    pub height: u64,
+}
+
+impl Anamorphic {
+    // a : would end the option of the filter
+    pub(crate) fn setsar_filter(&self) -> String {
+        format!("setsar=sar={}/{}", self.sar.0, self.sar.1)
+    }
+
+    // the cover art after the video keeps its own
+    pub(crate) fn aspect_args(&self) -> Vec<String> {
+        vec![
+            "-aspect:v:0".to_string(),
+            format!("{}:{}", self.dar.0, self.dar.1),
+        ]
+    }
+
+    // The size of the frames shown with square pixels, the width stretched by the SAR to an even
+    // number, like 854x480 for 720x480 at 32:27
+    pub(crate) fn square_pixel_size(&self) -> (u64, u64) {
+        let width = (self.width * self.sar.0 + self.sar.1) / (2 * self.sar.1) * 2;
+        (width.max(2), self.height)
+    }
+}
+
+impl std::fmt::Display for Anamorphic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} SAR {}:{} DAR {}:{}",
+            self.width, self.height, self.sar.0, self.sar.1, self.dar.0, self.dar.1
+        )
+    }
+}
+
+// A ratio like "32:27" of ffprobe, None for the "N/A" and "0:1" it reports when it doesn't know
+fn parse_aspect_ratio(value: &str) -> Option<(u64, u64)> {
+    let (num, den) = value.trim().split_once(':')?;
+    let num: u64 = num.parse().ok()?;
+    let den: u64 = den.parse().ok()?;
+    (num > 0 && den > 0).then_some((num, den))
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// The aspect ratios of the video stream of file, None when its pixels are square or its SAR is
+// unknown. A missing DAR is the size stretched by the SAR
+pub fn anamorphic(file: &str) -> Option<Anamorphic> {
+    let info = probe_file(file);
+    let stream = info.video_stream()?;
+    let sar = parse_aspect_ratio(stream["sample_aspect_ratio"].as_str()?)?;
+    if sar.0 == sar.1 {
+        return None;
+    }
+    let width = stream["width"].as_u64().filter(|width| *width > 0)?;
+    let height = stream["height"].as_u64().filter(|height| *height > 0)?;
+    let dar = stream["display_aspect_ratio"]
+        .as_str()
+        .and_then(parse_aspect_ratio)
+        .unwrap_or_else(|| {
+            let (num, den) = (width * sar.0, height * sar.1);
+            let divisor = gcd(num, den);
+            (num / divisor, den / divisor)
+        });
+    Some(Anamorphic {
+        sar,
+        dar,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,67 @@
+use super::*;
+
+// A 720x480 DVD stream with sar and dar as ffprobe reports them, null for a missing field
+fn dvd(name: &str, sar: Value, dar: Value) -> String {
+    probed_file(
+        name,
+        serde_json::json!({
+            "streams": [{
+                "index": 0, "codec_type": "video", "codec_name": "mpeg2video",
+                "width": 720, "height": 480,
+                "sample_aspect_ratio": sar, "display_aspect_ratio": dar,
+            }],
+        }),
+    )
+}
+
+#[test]
+fn unknown_ratios_are_none() {
+    assert_eq!(parse_aspect_ratio("32:27"), Some((32, 27)));
+    assert_eq!(parse_aspect_ratio(" 16:9\n"), Some((16, 9)));
+    for value in ["N/A", "0:1", "1:0", "", "16/9", "16:", "-4:3"] {
+        assert_eq!(parse_aspect_ratio(value), None, "{:?}", value);
+    }
+}
+
+#[test]
+fn a_widescreen_dvd_is_anamorphic() {
+    let file = dvd("anamorphic-dvd.mkv", "32:27".into(), "16:9".into());
+    let anamorphic = anamorphic(&file).unwrap();
+    assert_eq!(
+        anamorphic,
+        Anamorphic {
+            sar: (32, 27),
+            dar: (16, 9),
+            width: 720,
+            height: 480,
+        }
+    );
+    assert_eq!(anamorphic.setsar_filter(), "setsar=sar=32/27");
+    assert_eq!(anamorphic.aspect_args(), ["-aspect:v:0", "16:9"]);
+    assert_eq!(anamorphic.square_pixel_size(), (854, 480));
+    assert_eq!(anamorphic.to_string(), "720x480 SAR 32:27 DAR 16:9");
+}
+
+#[test]
+fn a_missing_dar_is_the_size_stretched_by_the_sar() {
+    let file = dvd("anamorphic-no-dar.mkv", "32:27".into(), "N/A".into());
+    assert_eq!(anamorphic(&file).unwrap().dar, (16, 9));
+    let file = dvd("anamorphic-null-dar.mkv", "8:9".into(), Value::Null);
+    let anamorphic = anamorphic(&file).unwrap();
+    assert_eq!(anamorphic.dar, (4, 3));
+    assert_eq!(anamorphic.square_pixel_size(), (640, 480));
+}
+
+#[test]
+fn square_and_unknown_pixels_get_no_filter() {
+    for (name, sar, dar) in [
+        ("square.mkv", "1:1", "3:2"),
+        ("unknown-sar.mkv", "0:1", "N/A"),
+        ("na-sar.mkv", "N/A", "16:9"),
+    ] {
+        let file = dvd(name, sar.into(), dar.into());
+        assert_eq!(anamorphic(&file), None, "{}", name);
+    }
+    let file = dvd("no-sar.mkv", Value::Null, "16:9".into());
+    assert_eq!(anamorphic(&file), None);
+}
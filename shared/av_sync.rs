@@ -0,0 +1,318 @@
+// A/V sync of chunked encodes: the merged scenes are muxed with the audio the source had next to
+// them, a scene that came out a frame long or a timestamp rounded at a scene boundary shifts the
+// video against that audio from there on. Before the output gets its name the durations of its
+// video and audio streams are compared with the ones of the source and of the extracted audio, and
+// the time of a few frames from the start of the audio with the same frames of the source. A drift
+// above --sync-max-drift fails the file as failed_sync and keeps the scenes, --sync-check warn only
+// warns. Every check goes to the av_sync list of --report-json
+
+use crate::*;
+
+// Where the frames are compared, as a share of the frames of the video
+const SAMPLE_POINTS: [f64; 5] = [0.1, 0.3, 0.5, 0.7, 0.9];
+
+lazy_static! {
+    // The checks since the pipeline last took them
+    static ref SYNC_CHECKS: Mutex<Vec<SyncCheck>> = Mutex::new(Vec::new());
+}
+
+// The duration of a stream of the output against its stream in the source
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamDrift {
+    // video or audio
+    pub codec_type: String,
+    // the index of the stream in the output
+    pub index: u64,
+    pub source_s: f64,
+    pub output_s: f64,
+    // output minus source
+    pub drift_ms: f64,
+}
+
+// The time of a frame from the start of the audio, in the source and in the output
+#[derive(Debug, Clone, Serialize)]
+pub struct PtsSample {
+    pub frame: usize,
+    pub source_s: f64,
+    pub output_s: f64,
+    // output minus source
+    pub drift_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncCheck {
+    pub path: String,
+    pub output: String,
+    pub streams: Vec<StreamDrift>,
+    pub samples: Vec<PtsSample>,
+    // the largest drift of the streams and samples, either way
+    pub max_drift_ms: f64,
+    pub max_allowed_ms: f64,
+    // passed, drifted (--sync-check warn) or failed_sync
+    pub outcome: String,
+}
+
+// Seconds of a stream from its duration, or from the DURATION tag matroska writes instead
+fn stream_duration(stream: &Value) -> Option<f64> {
+    if let Some(duration) = stream["duration"]
+        .as_str()
+        .and_then(|duration| duration.parse::<f64>().ok())
+    {
+        return Some(duration);
+    }
+    stream["tags"]
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.to_uppercase().starts_with("DURATION"))
+        .and_then(|(_, value)| value.as_str())
+        .and_then(parse_timecode)
+}
+
+fn stream_start(stream: &Value) -> f64 {
+    stream["start_time"]
+        .as_str()
+        .and_then(|start| start.parse::<f64>().ok())
+        .unwrap_or(0.0)
+}
+
+// The presentation times of the packets of stream index of file, sorted
+fn packet_times(file: &str, index: u64) -> Result<Vec<f64>, String> {
+    let output = ffprobe_command()
+        .args(["-v", "error", "-select_streams"])
+        .arg(index.to_string())
+        .args(["-show_entries", "packet=pts_time", "-of", "csv=p=0"])
+        .arg(file)
+        .output()
+        .map_err(|e| format!("Failed to execute ffprobe: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed on {}: {}",
+            file,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let mut times: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().trim_end_matches(',').parse().ok())
+        .collect();
+    times.sort_by(f64::total_cmp);
+    Ok(times)
+}
+
+// Milliseconds output_s is behind source_s, to a microsecond
+fn drift_ms(source_s: f64, output_s: f64) -> f64 {
+    ((output_s - source_s) * 1_000_000.0).round() / 1000.0
+}
+
+fn drift(codec_type: &str, index: u64, source_s: f64, output_s: f64) -> StreamDrift {
+    StreamDrift {
+        codec_type: codec_type.to_string(),
+        index,
+        source_s,
+        output_s,
+        drift_ms: drift_ms(source_s, output_s),
+    }
+}
+
+// The durations of the streams of output against the source: the video with the video of the
+// source, the audio streams in their order with the ones of non_video, which holds the kept audio of
+// the source in the order of the output
+fn stream_drifts(
+    source_info: &MediaInfo,
+    audio_info: &MediaInfo,
+    output_info: &MediaInfo,
+    output: &str,
+) -> Vec<StreamDrift> {
+    let mut streams = Vec::new();
+    if let (Some(source_video), Some(output_video)) =
+        (source_info.video_stream(), output_info.video_stream())
+    {
+        let index = output_video["index"].as_u64().unwrap_or(0);
+        // the decoded duration when the metadata of the source has none
+        let source_s =
+            stream_duration(source_video).or_else(|| source_info.duration().map(f64::from));
+        match (source_s, stream_duration(output_video)) {
+            (Some(source_s), Some(output_s)) => {
+                streams.push(drift("video", index, source_s, output_s))
+            }
+            _ => debug!("{}: no duration of the video to compare", output),
+        }
+    }
+    for (audio, output_audio) in audio_info
+        .streams_of("audio")
+        .iter()
+        .zip(output_info.streams_of("audio"))
+    {
+        let index = output_audio["index"].as_u64().unwrap_or(0);
+        match (stream_duration(audio), stream_duration(output_audio)) {
+            (Some(source_s), Some(output_s)) => {
+                streams.push(drift("audio", index, source_s, output_s))
+            }
+            _ => debug!(
+                "{}: no duration of audio stream {} to compare",
+                output, index
+            ),
+        }
+    }
+    streams
+}
+
+// The time of the frames at SAMPLE_POINTS from the start of the audio, the packet times of the
+// source and of the output less their reference
+fn pts_samples(
+    source_times: &[f64],
+    output_times: &[f64],
+    source_reference: f64,
+    output_reference: f64,
+) -> Vec<PtsSample> {
+    let frames = source_times.len().min(output_times.len());
+    if frames == 0 {
+        return Vec::new();
+    }
+    SAMPLE_POINTS
+        .iter()
+        .map(|point| {
+            let frame = ((frames as f64 * point) as usize).min(frames - 1);
+            let source_s = source_times[frame] - source_reference;
+            let output_s = output_times[frame] - output_reference;
+            PtsSample {
+                frame,
+                source_s,
+                output_s,
+                drift_ms: drift_ms(source_s, output_s),
+            }
+        })
+        .collect()
+}
+
+// The largest drift of the streams and samples, either way
+fn max_drift_ms(streams: &[StreamDrift], samples: &[PtsSample]) -> f64 {
+    streams
+        .iter()
+        .map(|stream| stream.drift_ms.abs())
+        .chain(samples.iter().map(|sample| sample.drift_ms.abs()))
+        .fold(0.0, f64::max)
+}
+
+// What a check of max_drift_ms does with --sync-max-drift max_allowed_ms and --sync-check
+// sync_check: passed up to the max, above it failed_sync with fail and drifted with warn
+fn sync_outcome(max_drift_ms: f64, max_allowed_ms: f64, sync_check: &str) -> &'static str {
+    if max_drift_ms <= max_allowed_ms {
+        "passed"
+    } else if sync_check == "fail" {
+        "failed_sync"
+    } else {
+        "drifted"
+    }
+}
+
+// Compare output with source, see stream_drifts, and the time of a few frames from the start of the
+// audio
+fn check_av_sync(source: &str, non_video: &str, output: &str, max_allowed_ms: f64) -> SyncCheck {
+    let source_info = probe_file(source);
+    let audio_info = probe_file(non_video);
+    let output_info = probe_file(output);
+    let streams = stream_drifts(&source_info, &audio_info, &output_info, output);
+    let mut samples = Vec::new();
+    if let (Some(source_video), Some(output_video)) =
+        (source_info.video_stream(), output_info.video_stream())
+    {
+        // the frames are timed from the start of the first audio stream, the video without audio
+        let reference = |info: &MediaInfo, video: &Value| {
+            info.streams_of("audio")
+                .first()
+                .map(|audio| stream_start(audio))
+                .unwrap_or_else(|| stream_start(video))
+        };
+        let source_index = source_video["index"].as_u64().unwrap_or(0);
+        let index = output_video["index"].as_u64().unwrap_or(0);
+        match (
+            packet_times(source, source_index),
+            packet_times(output, index),
+        ) {
+            (Ok(source_times), Ok(output_times)) => {
+                samples = pts_samples(
+                    &source_times,
+                    &output_times,
+                    reference(&source_info, source_video),
+                    reference(&output_info, output_video),
+                );
+            }
+            (Err(e), _) | (_, Err(e)) => warn!("Failed to sample the frames of {}: {}", output, e),
+        }
+    }
+    let max_drift_ms = max_drift_ms(&streams, &samples);
+    SyncCheck {
+        path: source.to_string(),
+        output: output.to_string(),
+        streams,
+        samples,
+        max_drift_ms,
+        max_allowed_ms,
+        outcome: "passed".to_string(),
+    }
+}
+
+// Check the sync of part, the mux of the scenes of source with non_video for output, and keep the
+// check for the report. An error when it drifted with --sync-check fail, the status of source is
+// then failed_sync and the scenes and part are left for a look
+pub(crate) fn verify_av_sync(
+    source: &str,
+    non_video: &str,
+    part: &str,
+    output: &str,
+    args: &Args,
+) -> Result<(), io::Error> {
+    // a trial is compared with the whole source
+    if args.sync_check == "off" || trial_range(source).is_some() {
+        return Ok(());
+    }
+    set_concat_step("sync");
+    let mut check = check_av_sync(source, non_video, part, args.sync_max_drift);
+    check.output = output.to_string();
+    let outcome = sync_outcome(check.max_drift_ms, args.sync_max_drift, &args.sync_check);
+    let result = if outcome == "passed" {
+        info!(
+            "A/V sync of {}: {:.1} ms at most",
+            output, check.max_drift_ms
+        );
+        Ok(())
+    } else {
+        warn!(
+            "{}",
+            format!(
+                "The video of {} drifted {:.1} ms from the audio, more than --sync-max-drift {} ms",
+                output, check.max_drift_ms, args.sync_max_drift
+            )
+            .red()
+        );
+        for stream in &check.streams {
+            warn!(
+                "  {} stream {}: {:.3}s, the source {:.3}s",
+                stream.codec_type, stream.index, stream.output_s, stream.source_s
+            );
+        }
+        if outcome == "failed_sync" {
+            if let Err(e) = set_db_status(source, "failed_sync") {
+                error!("Failed to update status of {}: {}", source, e);
+            }
+            Err(io::Error::other(format!(
+                "A/V drift of {:.1} ms, the scenes and {} are kept",
+                check.max_drift_ms, part
+            )))
+        } else {
+            Ok(())
+        }
+    };
+    check.outcome = outcome.to_string();
+    SYNC_CHECKS.lock().unwrap().push(check);
+    result
+}
+
+// The sync checks since the last call
+pub(crate) fn take_sync_checks() -> Vec<SyncCheck> {
+    std::mem::take(&mut *SYNC_CHECKS.lock().unwrap())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,149 @@
+use super::*;
+
+// A probed file with a video stream of video_s seconds and an audio stream of audio_s seconds,
+// the audio duration in the DURATION tag like matroska writes it
+fn probed(name: &str, video_s: Option<&str>, audio_s: &str) -> String {
+    let mut streams = vec![serde_json::json!({
+        "index": 1, "codec_type": "audio", "codec_name": "opus",
+        "tags": {"DURATION": format!("00:00:{}", audio_s)},
+    })];
+    if let Some(video_s) = video_s {
+        streams.insert(
+            0,
+            serde_json::json!({
+                "index": 0, "codec_type": "video", "codec_name": "hevc",
+                "width": 1920, "height": 1080, "duration": video_s,
+            }),
+        );
+    }
+    probed_file(
+        name,
+        serde_json::json!({"streams": streams, "format": {"duration": "10.0"}}),
+    )
+}
+
+// The streams of an output whose video is video_s and audio is audio_s seconds against a source of
+// 10 seconds
+fn output_streams(name: &str, video_s: &str, audio_s: &str) -> Vec<StreamDrift> {
+    let source = probed(&format!("{}-source.mkv", name), Some("10.000"), "10.000");
+    let non_video = probed(&format!("{}-audio.mka", name), None, "10.000");
+    let output = probed(&format!("{}-output.mkv", name), Some(video_s), audio_s);
+    stream_drifts(
+        &probe_file(&source),
+        &probe_file(&non_video),
+        &probe_file(&output),
+        &output,
+    )
+}
+
+fn drifts(name: &str, video_s: &str, audio_s: &str) -> Vec<f64> {
+    output_streams(name, video_s, audio_s)
+        .iter()
+        .map(|stream| stream.drift_ms)
+        .collect()
+}
+
+#[test]
+fn the_durations_of_the_output_are_compared_with_the_source() {
+    assert_eq!(drifts("in-sync", "10.000", "10.000"), [0.0, 0.0]);
+    // a scene a frame long at 24 fps, and audio cut short
+    assert_eq!(drifts("long", "10.041667", "09.980"), [41.667, -20.0]);
+    let streams = output_streams("types", "10.0", "10.0");
+    let types: Vec<(&str, u64)> = streams
+        .iter()
+        .map(|stream| (stream.codec_type.as_str(), stream.index))
+        .collect();
+    assert_eq!(types, [("video", 0), ("audio", 1)]);
+}
+
+#[test]
+fn the_frames_are_timed_from_the_start_of_the_audio() {
+    let source: Vec<f64> = (0..100).map(|frame| frame as f64 / 25.0).collect();
+    // the output starts 0.5 s later and so does its audio
+    let output: Vec<f64> = source.iter().map(|time| time + 0.5).collect();
+    let samples = pts_samples(&source, &output, 0.0, 0.5);
+    let frames: Vec<usize> = samples.iter().map(|sample| sample.frame).collect();
+    assert_eq!(frames, [10, 30, 50, 70, 90]);
+    assert!(samples.iter().all(|sample| sample.drift_ms == 0.0));
+
+    // a frame lost at the start shifts every sample by one frame
+    let samples = pts_samples(&source, &output[1..], 0.0, 0.5);
+    assert!(samples.iter().all(|sample| sample.drift_ms == 40.0));
+    assert!(pts_samples(&source, &[], 0.0, 0.0).is_empty());
+}
+
+#[test]
+fn the_largest_drift_counts_either_way() {
+    let streams = [
+        drift("video", 0, 10.0, 10.02),
+        drift("audio", 1, 10.0, 9.94),
+    ];
+    let samples = pts_samples(&[0.0, 1.0], &[0.0, 1.03], 0.0, 0.0);
+    assert_eq!(max_drift_ms(&streams, &samples), 60.0);
+    assert_eq!(max_drift_ms(&streams[..1], &samples), 30.0);
+    assert_eq!(max_drift_ms(&[], &[]), 0.0);
+}
+
+#[test]
+fn the_outcome_at_below_and_above_the_max_drift() {
+    // the default --sync-max-drift
+    let max = 50.0;
+    for sync_check in ["fail", "warn"] {
+        assert_eq!(sync_outcome(0.0, max, sync_check), "passed");
+        assert_eq!(sync_outcome(49.999, max, sync_check), "passed");
+        assert_eq!(sync_outcome(50.0, max, sync_check), "passed");
+    }
+    assert_eq!(sync_outcome(50.001, max, "fail"), "failed_sync");
+    assert_eq!(sync_outcome(50.001, max, "warn"), "drifted");
+    assert_eq!(sync_outcome(41.667, 40.0, "fail"), "failed_sync");
+}
+
+// Verify the sync of an output whose video is video_s seconds, returns the result, the check and
+// the status of the source
+fn verify(name: &str, video_s: &str, options: &[&str]) -> (bool, String, String) {
+    shared_db();
+    let source = probed(&format!("{}-source.mkv", name), Some("10.000"), "10.000");
+    let non_video = probed(&format!("{}-audio.mka", name), None, "10.000");
+    let part = probed(
+        &format!("{}-output.mkv.part", name),
+        Some(video_s),
+        "10.000",
+    );
+    db().insert_video_info(&video_info(&source)).unwrap();
+    let result = verify_av_sync(
+        &source,
+        &non_video,
+        &part,
+        "output.mkv",
+        &test_args(options),
+    );
+    let check = take_sync_checks()
+        .into_iter()
+        .find(|check| check.path == source)
+        .unwrap();
+    let rows = db().get_all().unwrap();
+    let status = rows.iter().find(|row| row.2 == source).unwrap().14.clone();
+    (result.is_ok(), check.outcome, status)
+}
+
+#[test]
+fn a_drift_above_the_max_fails_the_file_as_failed_sync() {
+    // no packet times without ffprobe, and the stubs of the other tests must not answer
+    let _tools = lock_tools();
+    let (ok, outcome, status) = verify("sync-50ms", "10.050", &[]);
+    assert!(ok);
+    assert_eq!(outcome, "passed");
+    assert_ne!(status, "failed_sync");
+
+    let (ok, outcome, status) = verify("sync-51ms", "10.051", &[]);
+    assert!(!ok);
+    assert_eq!(
+        (outcome.as_str(), status.as_str()),
+        ("failed_sync", "failed_sync")
+    );
+
+    let (ok, outcome, status) = verify("sync-warn", "10.051", &["--sync-check", "warn"]);
+    assert!(ok);
+    assert_eq!(outcome, "drifted");
+    assert_ne!(status, "failed_sync");
+}
@@ -0,0 +1,403 @@
+// What this host can run: the encoders, filters and bitstream filters of ffmpeg, the CPU, the
+// memory and the hardware encoders that work, queried once per process
+
+use crate::*;
+
+// Encoders and filters of the system ffmpeg, detected once by ffmpeg_capabilities()
+#[derive(Debug, Clone, Default)]
+pub struct FfmpegCapabilities {
+    pub version: String,
+    pub encoders: Vec<String>,
+    pub filters: Vec<String>,
+    pub bitstream_filters: Vec<String>,
+}
+
+static FFMPEG_CAPABILITIES: OnceLock<FfmpegCapabilities> = OnceLock::new();
+
+fn run_ffmpeg_query(arg: &str) -> String {
+    match ffmpeg_command().arg("-hide_banner").arg(arg).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(e) => {
+            error!("Failed to execute ffmpeg {}: {}", arg, e);
+            String::new()
+        }
+    }
+}
+
+// ffmpeg version 6.1.1-full_build-www.gyan.dev Copyright (c) 2000-2023 the FFmpeg developers
+fn parse_ffmpeg_version(output: &str) -> Option<String> {
+    let line = output.lines().next()?;
+    line.strip_prefix("ffmpeg version ")?
+        .split_whitespace()
+        .next()
+        .map(|version| version.to_string())
+}
+
+//  V....D libx265              libx265 H.265 / HEVC (codec hevc)
+// the encoder list starts after the " ------" line below the legend
+fn parse_ffmpeg_encoders(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| line.trim() != "------")
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+//  ... libvmaf           VV->V      Calculate the VMAF between two video streams.
+fn parse_ffmpeg_filters(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() > 2 && fields[2].contains("->") {
+                Some(fields[1].to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Bitstream filters:
+// aac_adtstoasc
+fn parse_ffmpeg_bitstream_filters(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Bitstream filters:"))
+        .skip(1)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+// Detected on the first call, later calls (web requests, progress) reuse the result
+pub fn ffmpeg_capabilities() -> &'static FfmpegCapabilities {
+    FFMPEG_CAPABILITIES.get_or_init(|| {
+        let capabilities = FfmpegCapabilities {
+            version: parse_ffmpeg_version(&run_ffmpeg_query("-version"))
+                .unwrap_or_else(|| "unknown".to_string()),
+            encoders: parse_ffmpeg_encoders(&run_ffmpeg_query("-encoders")),
+            filters: parse_ffmpeg_filters(&run_ffmpeg_query("-filters")),
+            bitstream_filters: parse_ffmpeg_bitstream_filters(&run_ffmpeg_query("-bsfs")),
+        };
+        info!("ffmpeg version {}", capabilities.version);
+        capabilities
+    })
+}
+
+// Check that ffmpeg has what the selected encoder and mode need, call after args.encoder is resolved
+// Missing required encoders/filters are returned as one error, missing optional ones are only logged
+pub fn check_ffmpeg_capabilities(args: &Args) -> Result<(), String> {
+    let capabilities = ffmpeg_capabilities();
+    let audio_encoder = match args.audio_codec.as_str() {
+        "aac" => "aac",
+        _ => "libopus",
+    };
+
+    // audio-only copies the video, it needs neither the video encoder nor libvmaf
+    let audio_only = args.mode == "audio-only";
+    let mut missing: Vec<String> = Vec::new();
+    if !audio_only && !capabilities.encoders.iter().any(|e| e == &args.encoder) {
+        missing.push(format!("encoder {}", args.encoder));
+    }
+    if !audio_only && !capabilities.filters.iter().any(|f| f == "libvmaf") {
+        missing.push("filter libvmaf".to_string());
+    }
+    if args.mode == "chunked" {
+        for filter in ["select", "showinfo"] {
+            if !capabilities.filters.iter().any(|f| f == filter) {
+                missing.push(format!("filter {}", filter));
+            }
+        }
+        if !capabilities.filters.iter().any(|f| f == "scdet") {
+            warn!("ffmpeg {} has no scdet filter", capabilities.version);
+        }
+    }
+    if args.strip_dovi
+        && !capabilities
+            .bitstream_filters
+            .iter()
+            .any(|f| f == "dovi_rpu")
+    {
+        missing.push("bitstream filter dovi_rpu (ffmpeg 7.1 or newer)".to_string());
+    }
+    if args.burn_subs.is_some() {
+        if !capabilities.filters.iter().any(|f| f == "overlay") {
+            missing.push("filter overlay".to_string());
+        }
+        // only needed for text subtitles, PGS/DVD subtitles use overlay
+        if !capabilities.filters.iter().any(|f| f == "subtitles") {
+            warn!(
+                "ffmpeg {} has no subtitles filter (libass), text subtitles can't be burned in",
+                capabilities.version
+            );
+        }
+    }
+    // --tonemap sdr takes libplacebo, or zscale and tonemap
+    if args.tonemap == "sdr" && !audio_only && !has_libplacebo() && !has_zscale_tonemap() {
+        missing.push("filter libplacebo, or zscale and tonemap".to_string());
+    }
+    // only needed for HDR sources
+    if args.screenshots.is_some() && !has_zscale_tonemap() {
+        warn!(
+            "ffmpeg {} has no zscale or tonemap filter, HDR screenshots won't be tonemapped",
+            capabilities.version
+        );
+    }
+    // only needed when an audio stream is re-encoded, which is all audio-only does
+    if audio_only && !capabilities.encoders.iter().any(|e| e == audio_encoder) {
+        missing.push(format!("encoder {}", audio_encoder));
+    } else if !capabilities.encoders.iter().any(|e| e == audio_encoder) {
+        warn!(
+            "ffmpeg {} has no {} encoder, audio can't be re-encoded",
+            capabilities.version, audio_encoder
+        );
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "ffmpeg {} is missing required capabilities: {}",
+            capabilities.version,
+            missing.join(", ")
+        ))
+    }
+}
+
+// Hardware found by probe_hardware(), the slow part of system_info()
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct HardwareProbe {
+    pub cpu_model: String,
+    pub gpus: Vec<String>,
+    pub hw_device_types: Vec<String>,
+    // hardware encoders that finished a 1 frame test encode
+    pub hardware_encoders: Vec<String>,
+    pub ab_av1_version: String,
+}
+
+// Host capabilities reported by GET /system, memory is in MB
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemInfo {
+    #[serde(flatten)]
+    pub hardware: HardwareProbe,
+    pub logical_cores: usize,
+    pub total_memory_mb: u64,
+    pub free_memory_mb: u64,
+    pub ffmpeg_version: String,
+}
+
+static HARDWARE_PROBE: OnceLock<HardwareProbe> = OnceLock::new();
+
+const HARDWARE_ENCODERS: [&str; 3] = ["hevc_nvenc", "hevc_qsv", "av1_qsv"];
+
+// What ffmpeg prints when nvenc or qsv can't open the GPU: a busy or missing device, or a driver that
+// is too old or crashed
+const HARDWARE_INIT_ERRORS: [&str; 12] = [
+    "Cannot init CUDA",
+    "CUDA_ERROR_",
+    "Cannot load libcuda",
+    "Cannot load nvcuda.dll",
+    "No NVENC capable devices found",
+    "No capable devices found",
+    "OpenEncodeSessionEx failed",
+    "Driver does not support the required nvenc API version",
+    "The minimum required Nvidia driver for nvenc",
+    "Error creating a MFX session",
+    "Error initializing an internal MFX session",
+    "Error initializing the MFX video session",
+];
+
+pub fn is_hardware_encoder(encoder: &str) -> bool {
+    HARDWARE_ENCODERS.contains(&encoder)
+}
+
+// The tool output of a failed encode shows that the hardware encoder could not start
+pub fn is_hardware_init_failure(stderr: &str) -> bool {
+    HARDWARE_INIT_ERRORS
+        .iter()
+        .any(|error| stderr.contains(error))
+}
+
+pub(crate) fn command_stdout(program: &str, args: &[&str]) -> Option<String> {
+    let output = tool_command(program).args(args).output().ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        None
+    }
+}
+
+fn get_cpu_model() -> String {
+    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+        if let Some(line) = cpuinfo.lines().find(|line| line.starts_with("model name")) {
+            if let Some((_, model)) = line.split_once(':') {
+                return model.trim().to_string();
+            }
+        }
+    }
+    // Name
+    // AMD Ryzen 9 5950X 16-Core Processor
+    if let Some(output) = command_stdout("wmic", &["cpu", "get", "name"]) {
+        if let Some(model) = output
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && *line != "Name")
+        {
+            return model.to_string();
+        }
+    }
+    env::var("PROCESSOR_IDENTIFIER").unwrap_or_else(|_| "unknown".to_string())
+}
+
+// (total, free) in MB of the memory counts in kB of text, "MemTotal: 32768 kB" lines of
+// /proc/meminfo or "TotalVisibleMemorySize=33554432" lines of wmic
+fn parse_memory_mb(text: &str, total_key: &str, free_key: &str) -> Option<(u64, u64)> {
+    let kb_value = |key: &str| -> Option<u64> {
+        text.lines()
+            .find(|line| line.trim_start().starts_with(key))?
+            .split(|c: char| c == ':' || c == '=')
+            .nth(1)?
+            .split_whitespace()
+            .next()?
+            .parse::<u64>()
+            .ok()
+    };
+    Some((kb_value(total_key)? / 1024, kb_value(free_key)? / 1024))
+}
+
+// (total, free) physical memory in MB, (0, 0) when unknown
+fn get_memory_mb() -> (u64, u64) {
+    if let Ok(meminfo) = fs::read_to_string("/proc/meminfo") {
+        if let Some(memory) = parse_memory_mb(&meminfo, "MemTotal", "MemAvailable") {
+            return memory;
+        }
+    }
+    let wmic = command_stdout(
+        "wmic",
+        &[
+            "OS",
+            "get",
+            "FreePhysicalMemory,TotalVisibleMemorySize",
+            "/Value",
+        ],
+    );
+    wmic.and_then(|output| parse_memory_mb(&output, "TotalVisibleMemorySize", "FreePhysicalMemory"))
+        .unwrap_or((0, 0))
+}
+
+// Encode one black frame to nowhere, fails when the driver or device is missing
+fn test_hardware_encoder(encoder: &str) -> bool {
+    ffmpeg_command()
+        .args([
+            "-hide_banner",
+            "-v",
+            "error",
+            "-f",
+            "lavfi",
+            "-i",
+            "color=black:s=256x256",
+            "-frames:v",
+            "1",
+            "-pix_fmt",
+            "nv12",
+            "-c:v",
+            encoder,
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+// NVIDIA GeForce RTX 3080, a line per GPU of nvidia-smi --query-gpu=name --format=csv,noheader
+fn parse_gpu_names(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+// Supported hardware device types:
+// cuda
+// qsv
+fn parse_hw_device_types(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Supported hardware device types"))
+        .skip(1)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+// Detected on the first call, later calls reuse the result
+pub fn probe_hardware() -> &'static HardwareProbe {
+    HARDWARE_PROBE.get_or_init(|| {
+        let gpus = command_stdout("nvidia-smi", &["--query-gpu=name", "--format=csv,noheader"])
+            .map(|output| parse_gpu_names(&output))
+            .unwrap_or_default();
+        let hw_device_types =
+            command_stdout(&ffmpeg_bin(), &["-hide_banner", "-init_hw_device", "list"])
+                .map(|output| parse_hw_device_types(&output))
+                .unwrap_or_default();
+        let hardware_encoders = HARDWARE_ENCODERS
+            .iter()
+            .filter(|encoder| {
+                ffmpeg_capabilities().encoders.iter().any(|e| e == *encoder)
+                    && test_hardware_encoder(encoder)
+            })
+            .map(|encoder| encoder.to_string())
+            .collect();
+        let ab_av1_version = ab_av1_version()
+            .map(str::to_string)
+            .or_else(|| {
+                command_stdout("ab-av1", &["--version"]).map(|output| output.trim().to_string())
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let probe = HardwareProbe {
+            cpu_model: get_cpu_model(),
+            gpus,
+            hw_device_types,
+            hardware_encoders,
+            ab_av1_version,
+        };
+        info!("Hardware encoders: {:?}", probe.hardware_encoders);
+        probe
+    })
+}
+
+pub fn system_info() -> SystemInfo {
+    let (total_memory_mb, free_memory_mb) = get_memory_mb();
+    SystemInfo {
+        hardware: probe_hardware().clone(),
+        logical_cores: num_cpus::get(),
+        total_memory_mb,
+        free_memory_mb,
+        ffmpeg_version: ffmpeg_capabilities().version.clone(),
+    }
+}
+
+// Encoder used for --encoder auto: hevc_nvenc, then hevc_qsv, otherwise libx265
+pub fn auto_encoder() -> String {
+    pick_auto_encoder(&probe_hardware().hardware_encoders)
+}
+
+// The encoder of --encoder auto among the hardware encoders that work
+fn pick_auto_encoder(hardware_encoders: &[String]) -> String {
+    for encoder in ["hevc_nvenc", "hevc_qsv"] {
+        if hardware_encoders.iter().any(|e| e == encoder) {
+            return encoder.to_string();
+        }
+    }
+    "libx265".to_string()
+}
+
+#[cfg(test)]
+mod tests;
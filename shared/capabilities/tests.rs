@@ -0,0 +1,155 @@
+use super::*;
+
+const VERSION: &str = "\
+ffmpeg version 6.1.1-full_build-www.gyan.dev Copyright (c) 2000-2023 the FFmpeg developers
+built with gcc 12.2.0 (Rev10, Built by MSYS2 project)
+";
+
+const ENCODERS: &str = "\
+Encoders:
+ V..... = Video
+ A..... = Audio
+ ------
+ V....D libx265              libx265 H.265 / HEVC (codec hevc)
+ V....D hevc_nvenc           NVIDIA NVENC hevc encoder (codec hevc)
+ V..... hevc_qsv             HEVC (Intel Quick Sync Video acceleration) (codec hevc)
+ A....D libopus              libopus Opus (codec opus)
+";
+
+const FILTERS: &str = "\
+Filters:
+  T.. = Timeline support
+  | = Source or sink filter
+ ... libvmaf           VV->V      Calculate the VMAF between two video streams.
+ TSC yadif             V->V       Deinterlace the input image.
+ ... color             |->V       Provide an uniformly colored input.
+";
+
+const BITSTREAM_FILTERS: &str = "\
+Bitstream filters:
+aac_adtstoasc
+hevc_metadata
+
+";
+
+#[test]
+fn parse_the_ffmpeg_queries() {
+    assert_eq!(
+        parse_ffmpeg_version(VERSION).as_deref(),
+        Some("6.1.1-full_build-www.gyan.dev")
+    );
+    assert_eq!(parse_ffmpeg_version("not ffmpeg"), None);
+    assert_eq!(
+        parse_ffmpeg_encoders(ENCODERS),
+        ["libx265", "hevc_nvenc", "hevc_qsv", "libopus"]
+    );
+    assert_eq!(parse_ffmpeg_filters(FILTERS), ["libvmaf", "yadif", "color"]);
+    assert_eq!(
+        parse_ffmpeg_bitstream_filters(BITSTREAM_FILTERS),
+        ["aac_adtstoasc", "hevc_metadata"]
+    );
+}
+
+#[test]
+fn parse_the_gpus_and_the_device_types() {
+    assert_eq!(
+        parse_gpu_names("NVIDIA GeForce RTX 3080\r\nNVIDIA RTX A2000\n\n"),
+        ["NVIDIA GeForce RTX 3080", "NVIDIA RTX A2000"]
+    );
+    assert_eq!(parse_gpu_names(""), Vec::<String>::new());
+    assert_eq!(
+        parse_hw_device_types("Supported hardware device types:\ncuda\ndxva2\nqsv\nd3d11va\n\n"),
+        ["cuda", "dxva2", "qsv", "d3d11va"]
+    );
+    assert_eq!(
+        parse_hw_device_types("Supported hardware device types:\n"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn parse_the_memory_of_linux_and_windows() {
+    let meminfo =
+        "MemTotal:       32768000 kB\nMemFree:         1024000 kB\nMemAvailable:   16384000 kB\n";
+    assert_eq!(
+        parse_memory_mb(meminfo, "MemTotal", "MemAvailable"),
+        Some((32000, 16000))
+    );
+    let wmic = "\r\n\r\nFreePhysicalMemory=8192000\r\nTotalVisibleMemorySize=33554432\r\n\r\n";
+    assert_eq!(
+        parse_memory_mb(wmic, "TotalVisibleMemorySize", "FreePhysicalMemory"),
+        Some((32768, 8000))
+    );
+    assert_eq!(
+        parse_memory_mb("MemTotal: 1024 kB\n", "MemTotal", "MemAvailable"),
+        None
+    );
+}
+
+#[test]
+fn auto_prefers_nvenc_then_qsv_then_libx265() {
+    let encoders =
+        |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+    let cases = [
+        (
+            encoders(&["hevc_qsv", "hevc_nvenc", "av1_qsv"]),
+            "hevc_nvenc",
+        ),
+        (encoders(&["av1_qsv", "hevc_qsv"]), "hevc_qsv"),
+        // av1_qsv is not picked, the other options are hevc
+        (encoders(&["av1_qsv"]), "libx265"),
+        (encoders(&[]), "libx265"),
+    ];
+    for (hardware_encoders, expected) in cases {
+        assert_eq!(
+            pick_auto_encoder(&hardware_encoders),
+            expected,
+            "{:?}",
+            hardware_encoders
+        );
+    }
+}
+
+#[test]
+fn hardware_init_failures() {
+    assert!(is_hardware_init_failure(
+        "[hevc_nvenc @ 0x1] OpenEncodeSessionEx failed: no encode device (1): (no details)"
+    ));
+    assert!(is_hardware_init_failure(
+        "[hevc_qsv @ 0x1] Error creating a MFX session: -9."
+    ));
+    assert!(!is_hardware_init_failure("Conversion failed!"));
+    assert!(is_hardware_encoder("av1_qsv"));
+    assert!(!is_hardware_encoder("libsvtav1"));
+}
+
+#[test]
+fn system_info_is_one_flat_json_object() {
+    let info = SystemInfo {
+        hardware: HardwareProbe {
+            cpu_model: "AMD Ryzen 9 5950X 16-Core Processor".to_string(),
+            gpus: vec!["NVIDIA GeForce RTX 3080".to_string()],
+            hw_device_types: vec!["cuda".to_string()],
+            hardware_encoders: vec!["hevc_nvenc".to_string()],
+            ab_av1_version: "ab-av1 0.9.4".to_string(),
+        },
+        logical_cores: 32,
+        total_memory_mb: 32768,
+        free_memory_mb: 8000,
+        ffmpeg_version: "6.1.1".to_string(),
+    };
+    assert_eq!(
+        serde_json::to_value(&info).unwrap(),
+        serde_json::json!({
+            "cpu_model": "AMD Ryzen 9 5950X 16-Core Processor",
+            "gpus": ["NVIDIA GeForce RTX 3080"],
+            "hw_device_types": ["cuda"],
+            "hardware_encoders": ["hevc_nvenc"],
+            "ab_av1_version": "ab-av1 0.9.4",
+            "logical_cores": 32,
+            "total_memory_mb": 32768,
+            "free_memory_mb": 8000,
+            "ffmpeg_version": "6.1.1",
+        })
+    );
+}
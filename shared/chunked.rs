@@ -0,0 +1,580 @@
+// Chunked mode: a CRF search per scene in a threadpool feeds the scene encodes through a bounded
+// channel, then the scenes are concatenated and muxed with the audio and subtitles. The stages are
+// in chunked_search.rs and chunked_encode.rs
+
+use crate::*;
+
+// A scene whose CRF search is finished, waiting for the encode stage
+pub(crate) struct SceneEncodeJob {
+    pub(crate) index: i32,
+    pub(crate) start_frame: u64,
+    // None for the last scene, it runs to the end of the file
+    pub(crate) frame_count: Option<u64>,
+    // bytes
+    pub(crate) scene_size: u64,
+    pub(crate) crf: f32,
+    pub(crate) vmaf_score: f32,
+    // encoded with the fast_scene_args at the fast CRF
+    pub(crate) fast: bool,
+}
+
+pub fn run_ffmpeg_extract_scene_changes_pipe_vmaf_target_threaded(
+    file: &str,
+    scene_changes: &[f32],
+    scene_sizes: &Vec<(i32, u64)>,
+    args: &Args,
+    fps: &f32,
+    split_source: &str,
+    size_checks: &Mutex<Vec<SizeCheck>>,
+) -> Result<Vec<(i32, f32, f32)>, io::Error> {
+    // resolve_thread_counts has set scene_jobs for chunked mode
+    let thread_count = args.scene_jobs.unwrap_or(1);
+    let threadpool = ThreadPool::new(thread_count);
+    let scene_changes_len = scene_changes.len();
+    let last_scene_change = scene_changes.last().cloned().unwrap_or(0.0);
+    // a trial ends on its range, the last scene of a whole file runs to its end
+    let trial = trial_range(file);
+    let open_end = trial.is_none();
+    let fps = *fps;
+    let scene_changes = Arc::new(Mutex::new(scene_changes.to_vec()));
+    let scene_sizes_clone = Arc::new(Mutex::new(scene_sizes.clone()));
+    let mut i = 0;
+    let file_size = get_file_size(&file).unwrap_or(0);
+    // the cost of the scene encodes of this run, a resumed run leaves out the scenes it found
+    let started = Instant::now();
+    let scene_costs = Arc::new(Mutex::new(EncodeCost::default()));
+
+    let output_filename = chunked_output_name(file, args, trial);
+    info!("Output file name: {}", output_filename);
+    //exit(1);
+
+    let mut scenes: Vec<(i32, f32, f32)> = Vec::new();
+    {
+        let scene_changes_locked = scene_changes.lock().unwrap();
+        for window in scene_changes_locked.windows(2) {
+            scenes.push((i, window[0], window[1]));
+            i += 1;
+        }
+    }
+
+    // Sort scenes by duration, from longest to shortest
+    // The sorting should be based on the duration between the the first and the second value in the tuple
+    scenes.sort_by(|a, b| (b.2 - b.1).partial_cmp(&(a.2 - a.1)).unwrap());
+
+    // Sort scenes by duration, from shortest to longest
+    scenes.reverse();
+
+    // --stream-concat can only append a scene once the ones before it are done, so the scenes go
+    // in index order and only the few running at once finish out of order
+    if args.stream_concat {
+        scenes.sort_by_key(|(index, _, _)| *index);
+    }
+
+    // TODO: add an argument to control this
+
+    info!(
+        "{} scenes to process (due to minimum duration of {} seconds per scene)",
+        scenes.len(),
+        args.scene_split_min
+    );
+
+    let vmaf_scores = Arc::new(Mutex::new(Vec::<(i32, f32, f32)>::new()));
+    // the CRF and VMAF each scene ends up encoded with and whether it took the fast path,
+    // vmaf_scores has every search iteration
+    let chosen_scores = Arc::new(Mutex::new(HashMap::<i32, (f32, f32, bool)>::new()));
+    let m = Arc::new(Mutex::new(MultiProgress::new()));
+
+    // Get the number of frames in the file, or in the range of a trial
+    let total_frames = match trial {
+        Some(range) => range.frames(fps),
+        None => frame_count(&file) as u64,
+    };
+
+    // Create a progress bar
+    let frames_bar = Arc::new(Mutex::new(progress_bar(total_frames as u64)));
+    let frames_bar_style = "[frames][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]";
+    frames_bar.lock().unwrap().set_style(
+        ProgressStyle::default_bar()
+            .template(frames_bar_style)
+            .unwrap(),
+    );
+
+    // Create a progress bar
+    let info_vmaf_bar = Arc::new(Mutex::new(progress_bar(scene_changes_len as u64)));
+    let info_vmaf_bar_style = "[info][{spinner}][{msg}]";
+    info_vmaf_bar.lock().unwrap().set_style(
+        ProgressStyle::default_bar()
+            .template(info_vmaf_bar_style)
+            .unwrap(),
+    );
+
+    // Create a progress bar for the search and encode stages
+    let pipeline_bar = Arc::new(Mutex::new(progress_bar(scene_changes_len as u64)));
+    let pipeline_bar_style = "[pipe][{msg}]";
+    pipeline_bar.lock().unwrap().set_style(
+        ProgressStyle::default_bar()
+            .template(pipeline_bar_style)
+            .unwrap(),
+    );
+
+    // Add optimal_vmaf_bar to multi-progress bar as a child
+    m.lock().unwrap().add(frames_bar.lock().unwrap().clone());
+    m.lock().unwrap().add(info_vmaf_bar.lock().unwrap().clone());
+    m.lock().unwrap().add(pipeline_bar.lock().unwrap().clone());
+
+    // Enable steady tick on the progress bars
+    /*     frames_bar
+        .lock()
+        .unwrap()
+        .enable_steady_tick(Duration::from_millis(1000));
+    */
+
+    //TODO: fix this
+    //function to extract all audio, video and subs
+    let streams = select_streams(file, args, None);
+    if !streams.dropped.is_empty() {
+        info!("{}: dropping {}", file, streams.dropped.join(", "));
+    }
+    // the work dir files of this encode, scene_changes_len - 1 scenes
+    let chunk_files = ChunkFiles::new(file, scene_changes_len.saturating_sub(1));
+    // the last search iteration of a scene keeps its encode, the encode stage remuxes it when the
+    // scene is encoded at that CRF
+    let reuse_search_streams = search_streams_reusable(file, args);
+    let remuxed_count = Arc::new(AtomicUsize::new(0));
+    extract_non_video_content(file, &chunk_files.non_video(), &streams)?;
+
+    // vector of index, start_frame, end_frame, total_frames
+    let scenes_clone = scenes.clone();
+    let mut scenes_frames = Vec::<(i32, f32, f32, f32)>::new();
+    let mut scenes_frames_index = 0;
+    let mut scenes_frames_nosum = Vec::<(i32, f32, f32, f32)>::new();
+
+    for (_scene_index, scene_change, next_scene_change) in scenes_clone {
+        let start_frame = scene_change * fps;
+        let end_frame = next_scene_change * fps;
+        let scene_frames = end_frame - start_frame;
+        let scene_frames_rounded = scene_frames.round();
+
+        // add scene_index to scenes_details
+        scenes_frames.push((
+            scenes_frames_index as i32,
+            start_frame,
+            end_frame,
+            scene_frames_rounded,
+        ));
+
+        // add scene_index to scenes_frames_nosum
+        scenes_frames_nosum.push((
+            scenes_frames_index as i32,
+            start_frame,
+            end_frame,
+            scene_frames,
+        ));
+
+        scenes_frames_index += 1;
+    }
+
+    // make scenes_frames that if entries are 0, 2, 5, 3, 4 then the result should be 0, 2, 7, 10, 14
+    let scenes_frames = scenes_frames
+        .iter()
+        .scan(0.0, |sum, (index, start_frame, end_frame, scene_frames)| {
+            let new_sum = *sum + scene_frames;
+            Some((*index, *start_frame, *end_frame, new_sum))
+        })
+        .collect::<Vec<(i32, f32, f32, f32)>>();
+
+    // make scenes_frames that if entries are 0, 2, 5, 3, 4 then the result should be 0, 2, 7, 10, 14
+    let mut scenes_frames_nosum = scenes_frames_nosum
+        .iter()
+        .scan(0.0, |sum, (index, start_frame, end_frame, scene_frames)| {
+            let new_sum = *sum + scene_frames;
+            Some((*index, *start_frame, *end_frame, new_sum))
+        })
+        .collect::<Vec<(i32, f32, f32, f32)>>();
+
+    // print all scenes_frames
+    //println!("{:?}", scenes_frames);
+    // print the sum of all scene_frames
+    //println!("{}", scenes_frames.iter().map(|(_, _, _, scene_frames)| scene_frames).sum::<f32>());
+    // print the number of scenes
+    //println!("{}", scenes_frames.len());
+    //exit(1);
+
+    let i = Arc::new(AtomicUsize::new(1)); // Initialize atomic integer at 1
+
+    // --stream-concat appends the scenes to a growing file in scene order as they finish
+    let stream = if args.stream_concat {
+        let scene_changes = scene_changes.lock().unwrap().clone();
+        Some(Arc::new(Mutex::new(StreamConcat::open(
+            &chunk_files,
+            &scene_changes,
+            fps,
+            total_frames,
+        )?)))
+    } else {
+        None
+    };
+
+    // create a done.txt file if it doesn't exist
+    if !Path::new("done.txt").exists() {
+        fs::File::create("done.txt")?;
+    }
+    // the done scenes of a --stream-concat run are the ones in its growing file and the ones
+    // whose chunk waits for its turn
+    let mut done = read_done_indices("done.txt")?;
+    if let Some(stream) = &stream {
+        done = stream.lock().unwrap().done_indices(&done, &chunk_files)?;
+    }
+    if !done.is_empty() {
+        // for each index in done.txt, which represents the done scenes, remove the corresponding index from the scenes vector
+        let total_scenes = scenes.len();
+        for line in done {
+            scenes = scenes
+                .into_iter()
+                .filter(|(index, _, _)| *index != line)
+                .collect::<Vec<(i32, f32, f32)>>();
+            scenes_frames_nosum = scenes_frames_nosum
+                .into_iter()
+                .filter(|(index, _, _, _)| *index != line)
+                .collect::<Vec<(i32, f32, f32, f32)>>();
+        }
+
+        // print the number of scenes left
+        info!("{} scenes left", scenes.len());
+
+        // print the number of scenes skipped
+        info!("{} scenes skipped", total_scenes - scenes.len());
+        //exit(1);
+
+        // TEMP List all in scenes_frames_nosum
+        //println!("{:?}", scenes_frames_nosum);
+
+        // TEMP List all in scenes_frames
+        //println!("{:?}", scenes_frames);
+        //exit(1);
+
+        // set frames_bar position to the total of frames of all the scenes in done.txt
+        frames_bar.lock().unwrap().set_position(
+            total_frames
+                - scenes_frames_nosum
+                    .iter()
+                    .map(|(_, _, _, scenes_frames_nosum)| *scenes_frames_nosum as u64)
+                    .sum::<u64>(),
+        );
+
+        // set i to total_scenes - scenes.len()
+        //println!("{}", total_scenes - scenes.len());
+        //exit(1);
+        i.store(
+            total_scenes - scenes.len(),
+            std::sync::atomic::Ordering::SeqCst,
+        );
+        info_vmaf_bar.lock().unwrap().set_message(format!(
+            "{}/{}",
+            total_scenes - scenes.len(),
+            total_scenes
+        ));
+    }
+
+    info_vmaf_bar
+        .lock()
+        .unwrap()
+        .enable_steady_tick(Duration::from_millis(100));
+
+    let total_scenes = scenes_frames.len();
+    let done_count = total_scenes - scenes.len();
+
+    // Scenes whose CRF search finished in an earlier run but were never encoded go straight to the encode stage
+    let searched_scenes = if Path::new("searched.txt").exists() {
+        read_searched_scenes("searched.txt")?
+    } else {
+        Vec::new()
+    };
+    // searched.txt also has the scenes encoded by an earlier run
+    for (index, crf, vmaf_score, fast) in &searched_scenes {
+        chosen_scores
+            .lock()
+            .unwrap()
+            .insert(*index, (*crf, *vmaf_score, *fast));
+    }
+    let mut resumed_jobs: Vec<SceneEncodeJob> = Vec::new();
+    let mut scenes_to_search: Vec<(i32, f32, f32)> = Vec::new();
+    for (index, scene_change, next_scene_change) in scenes {
+        if let Some((_, crf, vmaf_score, fast)) = searched_scenes
+            .iter()
+            .find(|(searched_index, _, _, _)| *searched_index == index)
+        {
+            let scene_size = scene_sizes
+                .iter()
+                .find(|(idx, _)| *idx == index)
+                .map(|(_, size)| *size)
+                .unwrap_or(0);
+            vmaf_scores.lock().unwrap().push((index, *crf, *vmaf_score));
+            let (start_frame, frame_count) = scene_frame_range(
+                scene_change,
+                next_scene_change,
+                fps,
+                open_end && next_scene_change == last_scene_change,
+            );
+            resumed_jobs.push(SceneEncodeJob {
+                index,
+                start_frame,
+                frame_count,
+                scene_size,
+                crf: *crf,
+                vmaf_score: *vmaf_score,
+                fast: *fast,
+            });
+        } else {
+            scenes_to_search.push((index, scene_change, next_scene_change));
+        }
+    }
+    if !resumed_jobs.is_empty() {
+        info!(
+            "{} scenes already searched, queued directly for encoding",
+            resumed_jobs.len()
+        );
+    }
+
+    let searched_count = Arc::new(AtomicUsize::new(done_count + resumed_jobs.len()));
+    let encoded_count = Arc::new(AtomicUsize::new(done_count));
+    update_pipeline_bar(
+        &pipeline_bar,
+        searched_count.load(Ordering::SeqCst),
+        encoded_count.load(Ordering::SeqCst),
+        total_scenes,
+    );
+    let frames_done = frames_bar.lock().unwrap().position();
+    update_chunked_progress(|chunked| {
+        chunked.phase = "crf_search".to_string();
+        chunked.total_scenes = total_scenes;
+        chunked.searched_scenes = searched_count.load(Ordering::SeqCst);
+        chunked.encoded_scenes = encoded_count.load(Ordering::SeqCst);
+        chunked.frames_done = frames_done;
+        chunked.total_frames = total_frames;
+    });
+
+    // Shared writers, so the workers never append to the same file concurrently
+    let done_log = Arc::new(ProgressLog::open("done.txt")?);
+    let searched_log = Arc::new(ProgressLog::open("searched.txt")?);
+    // The rest of the run's files go to its own artifacts folder
+    let artifacts = RunArtifacts::create(args, file)?;
+    let chunks_log = Arc::new(ChunkLog::open(&artifacts)?);
+    let progress_snapshot = Arc::new(ProgressSnapshot::new(&artifacts));
+    let debug_log = match &args.debug_log {
+        Some(path) => Some(Arc::new(DebugLog::open(&artifacts.debug_log_path(path))?)),
+        None => None,
+    };
+    if let Some(debug_log) = &debug_log {
+        let _ = debug_log.write_line(&format!(
+            "File: {}, Scenes: {}, Split by: {}, Film grain: {}",
+            file,
+            scene_changes_len.saturating_sub(1),
+            split_source,
+            args.film_grain.as_deref().unwrap_or("none")
+        ));
+    }
+
+    // The CRF search stage hands finished scenes to the encode stage through a bounded channel,
+    // so searching never runs more than a couple of scenes ahead of the encoders
+    let encode_jobs = encode_job_count(args, thread_count);
+    let (job_sender, job_receiver) = mpsc::sync_channel::<SceneEncodeJob>(encode_jobs * 2);
+    let job_receiver = Arc::new(Mutex::new(job_receiver));
+    // the estimated output size once --discard-if-larger gave up on the encode, the queued scenes
+    // are dropped and the searches not started yet return right away
+    let gave_up = Arc::new(Mutex::new(None::<u64>));
+    info!(
+        "Pipeline: {} CRF search workers, {} encode workers",
+        thread_count, encode_jobs
+    );
+
+    // the scenes below --fast-scene-threshold, the fast path still has to reach the VMAF target
+    if !args.uniform_scenes {
+        let scene_changes_vec = scene_changes.lock().unwrap().clone();
+        let fast_candidates = scene_sizes
+            .iter()
+            .filter(|(index, size)| {
+                let index = *index as usize;
+                match (scene_changes_vec.get(index), scene_changes_vec.get(index + 1)) {
+                    (Some(start), Some(end)) => {
+                        is_fast_scene(args, scene_kb_per_frame(*size, *start, *end, fps))
+                    }
+                    _ => false,
+                }
+            })
+            .count();
+        if fast_candidates > 0 {
+            info!(
+                "{} of {} scenes below {} kB per frame, trying the fast path (CRF {})",
+                fast_candidates,
+                scene_sizes.len(),
+                args.fast_scene_threshold,
+                fast_scene_crf(args)
+            );
+        }
+    }
+
+    let mut encode_handles = Vec::new();
+    for _ in 0..encode_jobs {
+        let worker = EncodeWorker {
+            file: file.to_string(),
+            args: args.clone(),
+            fps,
+            file_size,
+            job_receiver: Arc::clone(&job_receiver),
+            frames_bar: Arc::clone(&frames_bar),
+            info_vmaf_bar: Arc::clone(&info_vmaf_bar),
+            pipeline_bar: Arc::clone(&pipeline_bar),
+            scene_frames: scenes_frames.clone(),
+            i: Arc::clone(&i),
+            scene_sizes: Arc::clone(&scene_sizes_clone),
+            done_log: Arc::clone(&done_log),
+            chunks_log: Arc::clone(&chunks_log),
+            progress_snapshot: Arc::clone(&progress_snapshot),
+            debug_log: debug_log.clone(),
+            searched_count: Arc::clone(&searched_count),
+            encoded_count: Arc::clone(&encoded_count),
+            chunk_files: chunk_files.clone(),
+            gave_up: Arc::clone(&gave_up),
+            stream: stream.clone(),
+            scene_costs: Arc::clone(&scene_costs),
+            remuxed_count: Arc::clone(&remuxed_count),
+        };
+        encode_handles.push(thread::spawn(move || worker.run()));
+    }
+
+    for (index, scene_change, next_scene_change) in scenes_to_search {
+        let search = SceneSearch {
+            file: file.to_string(),
+            args: args.clone(),
+            scene_fps: fps,
+            open_end,
+            last_scene_change,
+            total_scenes,
+            reuse_search_streams,
+            vmaf_scores: Arc::clone(&vmaf_scores),
+            chosen_scores: Arc::clone(&chosen_scores),
+            pipeline_bar: Arc::clone(&pipeline_bar),
+            searched_log: Arc::clone(&searched_log),
+            progress_snapshot: Arc::clone(&progress_snapshot),
+            debug_log: debug_log.clone(),
+            searched_count: Arc::clone(&searched_count),
+            encoded_count: Arc::clone(&encoded_count),
+            job_sender: job_sender.clone(),
+            gave_up: Arc::clone(&gave_up),
+            chunk_files: chunk_files.clone(),
+        };
+        threadpool.execute(move || search.run(index, scene_change, next_scene_change));
+    }
+
+    // Scenes resumed from searched.txt skip the search stage
+    for job in resumed_jobs {
+        if job_sender.send(job).is_err() {
+            warn!("Encode stage stopped before all resumed scenes were queued");
+            break;
+        }
+    }
+
+    threadpool.join();
+
+    // Close the channel, the encode workers exit once every queued scene is encoded
+    drop(job_sender);
+    for handle in encode_handles {
+        let _ = handle.join();
+    }
+
+    // Close the log handles so done.txt can be removed below
+    drop(done_log);
+    drop(searched_log);
+    drop(chunks_log);
+    drop(debug_log);
+    drop(progress_snapshot);
+
+    info_vmaf_bar.lock().unwrap().finish();
+    pipeline_bar.lock().unwrap().finish();
+
+    // --discard-if-larger gave up, the scenes are removed and the original is kept
+    let estimated_output_size = *gave_up.lock().unwrap();
+    if let Some(estimated_output_size) = estimated_output_size {
+        chunk_files.remove_all();
+        let _ = fs::remove_file("done.txt");
+        let _ = fs::remove_file("searched.txt");
+        let check = keep_original(file, "", file_size, estimated_output_size);
+        size_checks.lock().unwrap().push(check);
+        return Ok(Vec::new());
+    }
+
+    // Merge the scene files of this run into one, by order of scene index, once every scene has
+    // its file and no other file of this run is left
+    let concatenante_result = match &stream {
+        Some(stream) => stream
+            .lock()
+            .unwrap()
+            .finish(&output_filename, file, &chunk_files, args),
+        None => chunk_files
+            .verified_scenes(scene_changes_len.saturating_sub(1))
+            .and_then(|scene_paths| {
+                concatenate_videos(
+                    &output_filename,
+                    file,
+                    &artifacts.path(CONCAT_LIST_FILE),
+                    &chunk_files,
+                    &scene_paths,
+                    args,
+                )
+            }),
+    };
+
+    // delete the done.txt and searched.txt files if the concatenation was successful
+    if let Err(e) = concatenante_result {
+        error!("Failed to concatenate videos: {}", e);
+        return Err(e);
+    }
+    fs::remove_file("done.txt").unwrap();
+    let _ = fs::remove_file("searched.txt");
+    chunk_files.remove_search_streams();
+    let remuxed = remuxed_count.load(Ordering::SeqCst);
+    if remuxed > 0 {
+        info!(
+            "{} of {} scenes remuxed from their CRF search instead of encoded again",
+            remuxed,
+            scene_changes_len.saturating_sub(1)
+        );
+    }
+
+    // one (scene index, crf, vmaf) per scene, by scene index, the fast scenes included
+    let chosen_scores = chosen_scores.lock().unwrap().clone();
+    let fast_scenes = chosen_scores.values().filter(|(_, _, fast)| *fast).count();
+    let mut final_scores: Vec<(i32, f32, f32)> = chosen_scores
+        .iter()
+        .map(|(index, (crf, vmaf_score, _))| (*index, *crf, *vmaf_score))
+        .collect();
+    final_scores.sort_by_key(|(index, _, _)| *index);
+
+    // a trial stays out of the statistics
+    if trial.is_some() {
+        let scores: Vec<f32> = final_scores.iter().map(|(_, _, vmaf)| *vmaf).collect();
+        let pooled = pool_vmaf_scores(&scores, &args.vmaf_pool).unwrap_or(0.0);
+        record_trial_output(file, &output_filename, "", pooled);
+        emit_file_done(file, &output_filename);
+        return Ok(final_scores);
+    }
+
+    // the scenes are encoded side by side, with the searches, the wall time is the whole encode
+    let mut cost = scene_costs.lock().unwrap().clone();
+    cost.wall_seconds = started.elapsed().as_secs_f64();
+    let scene_changes = scene_changes.lock().unwrap().clone();
+    record_chunked_output(
+        file,
+        &output_filename,
+        args,
+        &final_scores,
+        fast_scenes,
+        file_size,
+        split_source,
+        &cost,
+        &scene_changes,
+        size_checks,
+    );
+    Ok(final_scores)
+}
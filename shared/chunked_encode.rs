@@ -0,0 +1,253 @@
+// The encode stage of the chunked mode: the workers take the scenes whose CRF search is finished
+// from the channel, encode them (or remux the encode of the search) and keep done.txt, chunks.txt,
+// the bars and the estimated output size up to date
+
+use crate::*;
+
+// What an encode worker shares with the run, the counters and logs are shared by all workers
+pub(crate) struct EncodeWorker {
+    pub(crate) file: String,
+    pub(crate) args: Args,
+    pub(crate) fps: f32,
+    pub(crate) file_size: u64,
+    pub(crate) job_receiver: Arc<Mutex<mpsc::Receiver<SceneEncodeJob>>>,
+    pub(crate) frames_bar: Arc<Mutex<ProgressBar>>,
+    pub(crate) info_vmaf_bar: Arc<Mutex<ProgressBar>>,
+    pub(crate) pipeline_bar: Arc<Mutex<ProgressBar>>,
+    // index, start_frame, end_frame, frames summed up to the scene
+    pub(crate) scene_frames: Vec<(i32, f32, f32, f32)>,
+    // the scenes done, for the info bar
+    pub(crate) i: Arc<AtomicUsize>,
+    pub(crate) scene_sizes: Arc<Mutex<Vec<(i32, u64)>>>,
+    pub(crate) done_log: Arc<ProgressLog>,
+    pub(crate) chunks_log: Arc<ChunkLog>,
+    pub(crate) progress_snapshot: Arc<ProgressSnapshot>,
+    pub(crate) debug_log: Option<Arc<DebugLog>>,
+    pub(crate) searched_count: Arc<AtomicUsize>,
+    pub(crate) encoded_count: Arc<AtomicUsize>,
+    pub(crate) chunk_files: ChunkFiles,
+    pub(crate) gave_up: Arc<Mutex<Option<u64>>>,
+    pub(crate) stream: Option<Arc<Mutex<StreamConcat>>>,
+    pub(crate) scene_costs: Arc<Mutex<EncodeCost>>,
+    pub(crate) remuxed_count: Arc<AtomicUsize>,
+}
+
+impl EncodeWorker {
+    // Encode the jobs until the search stage closes the channel
+    pub(crate) fn run(self) {
+        let EncodeWorker {
+            file,
+            args,
+            fps,
+            file_size,
+            job_receiver,
+            frames_bar,
+            info_vmaf_bar,
+            pipeline_bar,
+            scene_frames,
+            i,
+            scene_sizes,
+            done_log,
+            chunks_log,
+            progress_snapshot,
+            debug_log,
+            searched_count,
+            encoded_count,
+            chunk_files,
+            gave_up,
+            stream,
+            scene_costs,
+            remuxed_count,
+        } = self;
+        // a worker thread of the task, a pause of the task stops its encodes too
+        set_current_task(&args.task_id);
+        let fast_args = fast_scene_args(&args);
+        let scene_frames_len = scene_frames.len();
+        loop {
+            // Release the receiver lock before encoding, so the other workers can pick up jobs
+            let job = job_receiver.lock().unwrap().recv();
+            let job = match job {
+                Ok(job) => job,
+                Err(_) => break, // The search stage is done and the channel is drained
+            };
+            if gave_up.lock().unwrap().is_some() {
+                continue;
+            }
+            let index = job.index;
+            let crf = job.crf;
+            let vmaf_score = job.vmaf_score;
+            let scene_size = job.scene_size;
+            let scene_args = if job.fast { &fast_args } else { &args };
+            update_chunked_progress(|chunked| {
+                chunked.phase = "encode".to_string();
+                chunked.encoding.push(EncodingScene {
+                    index,
+                    crf,
+                    fast: job.fast,
+                });
+            });
+
+            // Remux the encode of the search at the chosen CRF, otherwise encode the scene
+            let remuxed = remux_search_stream(
+                &file,
+                index,
+                &chunk_files,
+                crf,
+                job.frame_count,
+                &chunk_files.scene(index),
+                &frames_bar,
+                &debug_log,
+            );
+            let encode_result = match remuxed {
+                Some(remuxed) => {
+                    remuxed_count.fetch_add(1, Ordering::SeqCst);
+                    Ok(remuxed)
+                }
+                None => process_video_scene_encoded(
+                    &file,
+                    &index,
+                    &chunk_files.scene(index),
+                    scene_args,
+                    &crf,
+                    fps,
+                    job.start_frame,
+                    job.frame_count,
+                    &frames_bar.clone(),
+                    &scene_frames,
+                    &debug_log,
+                ),
+            };
+
+            // a scene killed by ctrl-C is not done, the resume encodes it again
+            wait_if_interrupted();
+            update_chunked_progress(|chunked| {
+                chunked.encoding.retain(|scene| scene.index != index);
+            });
+            let encoded_size = match encode_result {
+                Ok((_, value, cost)) => {
+                    scene_costs.lock().unwrap().add(&cost);
+                    value
+                }
+                Err(e) => {
+                    error!("Failed to encode scene: {}", e);
+                    0
+                }
+            };
+
+            // append the scene sizes to chunks.txt
+            let scene_path = if job.fast {
+                FAST_SCENE_PATH
+            } else {
+                FULL_SCENE_PATH
+            };
+            if let Err(e) = chunks_log.write_scene(index, scene_size, encoded_size, scene_path) {
+                error!("Failed to write {}: {}", CHUNK_LOG_FILE, e);
+            }
+
+            // the encoded scenes in the work dir, of this run and of the one it resumes, against
+            // the source size of the same scenes, with --stream-concat the growing file as well
+            let mut encoded_scenes_size: u64 = 0;
+            let mut encoded_indices: Vec<i32> = Vec::new();
+            for (index, path) in chunk_files.existing_scenes() {
+                encoded_scenes_size += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                encoded_indices.push(index);
+            }
+            if let Some(stream) = &stream {
+                let (appended, appended_size) = stream.lock().unwrap().appended();
+                encoded_scenes_size += appended_size;
+                encoded_indices.extend(appended);
+            }
+            let source_scenes_size: u64 = {
+                let scene_sizes = scene_sizes.lock().unwrap();
+                encoded_indices
+                    .iter()
+                    .filter_map(|index| scene_sizes.iter().find(|(idx, _)| idx == index))
+                    .map(|(_, size)| *size)
+                    .sum()
+            };
+
+            // the non video file holds the audio and subtitles, they are muxed in unchanged
+            let temp_size = fs::metadata(chunk_files.non_video())
+                .map(|m| m.len())
+                .unwrap_or(0);
+            let total_reduction = reduction_percent(
+                temp_size + source_scenes_size,
+                temp_size + encoded_scenes_size,
+            );
+
+            // Calculate estimated output size, based on current total reduction
+            let estimated_output_size = temp_size
+                + (file_size.saturating_sub(temp_size) as f64 * (100.0 - total_reduction) / 100.0)
+                    as u64;
+
+            let encoded_scenes = encoded_indices.len();
+            if chunked_encode_losing(
+                &args,
+                encoded_scenes,
+                scene_frames_len,
+                source_scenes_size,
+                encoded_scenes_size,
+            ) {
+                let mut gave_up = gave_up.lock().unwrap();
+                if gave_up.is_none() {
+                    warn!(
+                        "{}: {} of {} scenes encoded to {} from {}, giving up",
+                        file,
+                        encoded_scenes,
+                        scene_frames_len,
+                        format_size(encoded_scenes_size),
+                        format_size(source_scenes_size)
+                    );
+                    *gave_up = Some(estimated_output_size);
+                }
+                continue;
+            }
+
+            // append the scene index to done.txt
+            if let Err(e) = done_log.write_line(&index.to_string()) {
+                error!("Failed to write done.txt: {}", e);
+            }
+            if let Some(stream) = &stream {
+                if let Err(e) = stream.lock().unwrap().scene_done(index, &chunk_files) {
+                    error!("{}", e);
+                }
+            }
+            if let Some((_, _, _, frames)) = scene_frames.iter().find(|scene| scene.0 == index) {
+                inc_metric(
+                    &FRAMES_PROCESSED,
+                    &task_labels(&args.task_id, &args.encoder),
+                    *frames as f64,
+                );
+            }
+
+            // Update the progress bars
+            let encoded = encoded_count.fetch_add(1, Ordering::SeqCst) + 1;
+            let searched = searched_count.load(Ordering::SeqCst);
+            update_pipeline_bar(&pipeline_bar, searched, encoded, scene_frames_len);
+            write_chunk_progress(
+                &progress_snapshot,
+                &file,
+                scene_frames_len,
+                searched,
+                encoded,
+            );
+            let current_i = i.fetch_add(1, std::sync::atomic::Ordering::SeqCst); // Increment atomic integer
+            update_info_vmaf_bar(
+                info_vmaf_bar.clone(),
+                total_reduction,
+                estimated_output_size,
+                current_i,
+                scene_frames_len,
+                index,
+                vmaf_score,
+                crf,
+            );
+            update_chunked_progress(|chunked| {
+                chunked.searched_scenes = searched;
+                chunked.encoded_scenes = encoded;
+                chunked.estimated_size = estimated_output_size;
+                chunked.reduction = total_reduction;
+            });
+        }
+    }
+}
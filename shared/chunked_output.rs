@@ -0,0 +1,95 @@
+// The output of the chunked mode: its name, and the statistics, encode stats and screenshots of the
+// concatenated file
+
+use crate::*;
+
+// Name of the chunked output of file, the scenes are concatenated to it
+pub(crate) fn chunked_output_name(file: &str, args: &Args, trial: Option<TrialRange>) -> String {
+    // the dots of names like Show.S01E01.mkv stay in the stem
+    let (file_name_, file_extension_) = output_name_parts(file);
+    // Make the output_file name like file_name_ + . + encoder + . + subsample + . + vmaf_target + . + extension
+    let mut output_filename = format!(
+        "{}.{}.vmaf{}.{}.subsample{}.{}",
+        file_name_, args.encoder, args.vmaf, args.vmaf_pool, args.vmaf_subsample, file_extension_
+    );
+    // the chunked output goes to the working directory, --mirror-structure puts it in its folder
+    if args.mirror_structure {
+        output_filename = output_folder_path(&args.outputpath, &output_filename);
+    }
+    if let Some(downscale) = downscale(file, args) {
+        output_filename = downscaled_output_name(file, &output_filename, &downscale);
+    }
+    if let Some(range) = trial {
+        output_filename = range.output_name(&output_filename);
+    }
+    output_filename
+}
+
+// Record the concatenated output of file: the metrics, the VMAF statistics of final_scores (scene
+// index, crf, vmaf), the encode stats with the cost of the scene encodes, the size check and the
+// screenshots of the scenes with the lowest VMAF
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn record_chunked_output(
+    file: &str,
+    output_filename: &str,
+    args: &Args,
+    final_scores: &[(i32, f32, f32)],
+    fast_scenes: usize,
+    file_size: u64,
+    split_source: &str,
+    cost: &EncodeCost,
+    scene_changes: &[f32],
+    size_checks: &Mutex<Vec<SizeCheck>>,
+) {
+    let output_size = get_file_size(output_filename).unwrap_or(0);
+    let labels = task_labels(&args.task_id, &args.encoder);
+    inc_metric(&FILES_TRANSCODED, &labels, 1.0);
+    inc_metric(
+        &BYTES_SAVED,
+        &labels,
+        file_size.saturating_sub(output_size) as f64,
+    );
+    let vmaf_stats = chunked_vmaf_stats(
+        final_scores,
+        &args.vmaf_pool,
+        bytes_to_mb(file_size),
+        bytes_to_mb(output_size),
+        split_source,
+        fast_scenes,
+    );
+    if let Some(stats) = &vmaf_stats {
+        log_chunked_vmaf_stats(file, stats);
+        if let Err(e) = db().update_chunked_stats(file, stats) {
+            warn!("Failed to store the VMAF statistics of {}: {}", file, e);
+        }
+    }
+    record_encode_stats(
+        file,
+        output_filename,
+        &args.encoder,
+        encoder_preset_params(args).0,
+        vmaf_stats.map_or(0.0, |stats| stats.pooled as f64),
+        Some(cost),
+    );
+    emit_file_done(file, output_filename);
+
+    // the screenshots look at the scenes with the lowest VMAF first
+    let mut worst_scores = final_scores.to_vec();
+    worst_scores.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+    let worst_scenes: Vec<(f32, f32)> = worst_scores
+        .iter()
+        .filter_map(|(index, _, _)| {
+            let index = *index as usize;
+            Some((*scene_changes.get(index)?, *scene_changes.get(index + 1)?))
+        })
+        .collect();
+    // the estimate during the encode missed it, the concatenated file is checked as well
+    if let Some(check) = check_output_size(file, output_filename, args) {
+        let kept_original = check.decision == "kept_original";
+        size_checks.lock().unwrap().push(check);
+        if kept_original {
+            return;
+        }
+    }
+    save_screenshots(file, output_filename, args, &worst_scenes);
+}
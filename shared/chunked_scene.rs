@@ -0,0 +1,579 @@
+// One scene of chunked mode: the CRF search by VMAF, the encode and the progress bars they update
+
+use crate::*;
+
+// First frame and frame count of the scene between scene_change and next_scene_change
+// Neighbouring scenes round their shared boundary the same way, so no frame is encoded twice or skipped
+pub(crate) fn scene_frame_range(
+    scene_change: f32,
+    next_scene_change: f32,
+    fps: f32,
+    last_scene: bool,
+) -> (u64, Option<u64>) {
+    let start_frame = (scene_change * fps).round() as u64;
+    if last_scene {
+        return (start_frame, None);
+    }
+    let end_frame = (next_scene_change * fps).round() as u64;
+    (start_frame, Some(end_frame.saturating_sub(start_frame)))
+}
+
+// progress.json of the run, a failed write only costs the snapshot
+pub(crate) fn write_chunk_progress(
+    snapshot: &ProgressSnapshot,
+    file: &str,
+    total_scenes: usize,
+    searched_scenes: usize,
+    encoded_scenes: usize,
+) {
+    let progress = ChunkProgress {
+        file,
+        total_scenes,
+        searched_scenes,
+        encoded_scenes,
+    };
+    if let Err(e) = snapshot.write(&progress) {
+        warn!("Failed to write {}: {}", PROGRESS_SNAPSHOT_FILE, e);
+    }
+}
+
+// Count encoded frames on the frames bar and on /progress
+fn add_scene_frames(frames_bar: &Arc<Mutex<ProgressBar>>, frames: u64) {
+    let frames_done = {
+        let bar = frames_bar.lock().unwrap();
+        bar.inc(frames);
+        bar.position()
+    };
+    update_chunked_progress(|chunked| chunked.frames_done = frames_done);
+}
+
+pub(crate) fn update_pipeline_bar(
+    pipeline_bar: &Arc<Mutex<ProgressBar>>,
+    searched: usize,
+    encoded: usize,
+    total: usize,
+) {
+    pipeline_bar.lock().unwrap().set_message(format!(
+        "search: {}/{}][encode: {}/{}",
+        searched, total, encoded, total
+    ));
+}
+
+// Number of scenes encoded concurrently in chunked mode
+// Hardware encoders only have a limited number of sessions, so they default to one
+pub(crate) fn encode_job_count(args: &Args, search_jobs: usize) -> usize {
+    match args.encode_jobs {
+        Some(jobs) if jobs > 0 => jobs,
+        _ => match args.encoder.as_str() {
+            "hevc_nvenc" | "hevc_qsv" | "av1_qsv" => 1,
+            _ => search_jobs,
+        },
+    }
+}
+
+pub(crate) fn update_info_vmaf_bar(
+    info_vmaf_bar: Arc<Mutex<ProgressBar>>,
+    total_reduction: f64,
+    estimated_output_size: u64,
+    i: usize,
+    scene_changes_len: usize,
+    scene_index: i32,
+    vmaf_score: f32,
+    crf: f32,
+) {
+    // Reverse the percentage calculation
+    let total_reduction = total_reduction * -1.0;
+    let reduction_message = if total_reduction >= 0.0 {
+        // Assuming total_reduction is positive or zero, format with green color
+        format!("+{:.2}%", total_reduction).red().to_string()
+    } else {
+        // If total_reduction is negative, format with red color
+        // replace "-" with "+"
+        format!("{:.2}%", total_reduction).green().to_string()
+    };
+
+    let bar = info_vmaf_bar.lock().unwrap();
+    let bar_message = format!(
+        "{}/{}][{}({})][Scene: {} VMAF: {} CRF: {}",
+        i,
+        scene_changes_len,
+        format_size(estimated_output_size),
+        reduction_message, // Use the prepared message here
+        scene_index,
+        vmaf_score,
+        crf
+    );
+
+    bar.set_message(bar_message);
+}
+
+/// Adjusts the CRF value for a scene to achieve a target VMAF score with minimal iterations.
+///
+/// Arguments:
+/// * `scene_index`: Index of the scene being processed.
+/// * `file`: Path to the video file.
+/// * `ss_arg`: Start time for the scene.
+/// * `to_arg`: End time for the scene.
+/// * `fps`: Frames per second of the video.
+/// * `vmaf_target`: Target VMAF score to achieve.
+/// * `args`: Program arguments.
+/// * `vmaf_scores_clone`: Shared vector to store VMAF scores for each scene.
+///
+/// Returns:
+/// A tuple containing the adjusted CRF value and the achieved VMAF score for the scene.
+pub(crate) fn process_scene_adjust_crf_binary(
+    scene_index: i32,
+    scene_size: u64,
+    file: &str,
+    ss_arg: &str,
+    to_arg: &str,
+    fps: &str,
+    vmaf_target: f32,
+    args: &Args,
+    vmaf_scores_clone: Arc<Mutex<Vec<(i32, f32, f32)>>>,
+    vmaf_pool: &str,
+    vmaf_threads: &str,
+    vmaf_subsample: &str,
+    debug_log: &Option<Arc<DebugLog>>,
+    chunk_files: Option<&ChunkFiles>,
+) -> Result<(f32, f32), String> {
+    let mut crf = 23.0; // Starting CRF value, aiming for a 'middle ground'
+    let mut min_crf = 10.0;
+    let mut max_crf = 45.0;
+    let mut best_vmaf = 0.0;
+    let mut best_crf = crf;
+    let max_iterations = 3;
+    let mut iteration = 1;
+    // only the stream of the last iteration is kept, the CRF chosen is mostly the last one measured
+    let mut last_stream: Option<(f32, String)> = None;
+
+    /*     // Check if vmaf_scores_clone already contains the scene index with the same CRF, if so, return
+    if let Some((_scene_index, _crf, _vmaf_score)) = vmaf_scores_clone
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(_scene_index, _crf, _vmaf_score)| *_scene_index == scene_index)
+    {
+        // Write in debug.txt the CRF and VMAF score for the scene and that it was skipped because it was already processed
+        let mut debug_file = fs::OpenOptions::new()
+            .append(true)
+            .open("debug.txt")
+            .unwrap();
+        writeln!(
+            debug_file,
+            "Scene: {}, CRF: {}, VMAF: {}, Iterations: {}, Scene Size: {}kB",
+            scene_index, _crf, _vmaf_score, iteration, scene_size
+        )
+        .unwrap();
+        return Ok((*_crf, *_vmaf_score));
+    } */
+
+    while iteration <= max_iterations {
+        if let Some((_, stream)) = last_stream.take() {
+            let _ = fs::remove_file(stream);
+        }
+        let keep_stream =
+            chunk_files.map(|chunk_files| chunk_files.search_stream(scene_index, crf));
+        let vmaf = process_video_pipe_and_vmaf(
+            &file.to_string(),
+            args,
+            &crf,
+            &fps.to_string(),
+            &ss_arg.to_string(),
+            &to_arg.to_string(),
+            &vmaf_pool.to_string(),
+            &vmaf_threads.to_string(),
+            &vmaf_subsample.to_string(),
+            None,
+            keep_stream.as_deref(),
+        )
+        .map_err(|e| format!("VMAF of crf {} failed: {}", crf, e))?;
+        last_stream = keep_stream.map(|stream| (crf, stream));
+
+        let vmaf_score = parse_vmaf_score(&vmaf).unwrap_or(0.0);
+
+        /*         let mut vmaf_size = helper::parse_size_output(&vmaf).unwrap();
+        let pattern = Regex::new(r"\b\d+kB\b").unwrap();
+        if let Some(matched) = pattern.find(&vmaf_size) {
+            //println!("Matched size: {}", &vmaf_size[matched.start()..matched.end()]);
+            // get only the numbers from the matched size string
+            let numbers = &vmaf_size[matched.start()..matched.end()].replace("kB", "");
+            vmaf_size = numbers.trim().parse::<f32>().unwrap().to_string();
+            //println!("VMAF size in kB: {}", vmaf_size_final);
+            //println!("Original size in kB: {}", scene_size);
+        } else {
+            //println!("No match found");
+        } */
+
+        // Append index, crf and vmaf_score to the debug log if one was requested
+        if let Some(debug_log) = debug_log {
+            let _ = debug_log.write_line(&format!(
+                "Scene: {}, CRF: {}, VMAF: {}, Iterations: {}, Scene Size: {}",
+                scene_index,
+                crf,
+                vmaf_score,
+                iteration,
+                format_size(scene_size)
+            ));
+        }
+
+        {
+            let mut scores = vmaf_scores_clone.lock().unwrap();
+            scores.push((scene_index, crf, vmaf_score));
+        }
+
+        if (vmaf_target - vmaf_score).abs() <= 0.5 {
+            best_crf = crf;
+            best_vmaf = vmaf_score;
+            break; // Close enough to target VMAF, exit early
+        }
+        adjust_crf(
+            vmaf_target,
+            vmaf_score,
+            &mut crf,
+            &mut min_crf,
+            &mut max_crf,
+        );
+
+        // Check if we have narrowed the range completely
+        if min_crf > max_crf {
+            break; // Exit if the search range is invalid
+        }
+
+        /*         if vmaf_score > vmaf_target {
+            // High quality, decrease CRF (increase compression)
+            crf = std::cmp::max(min_crf, crf - step_size);
+        } else {
+            // Low quality, increase CRF (decrease compression)
+            crf = std::cmp::min(max_crf, crf + step_size);
+        } */
+
+        // Update best estimates if closer to the target VMAF score
+        if (vmaf_target - vmaf_score).abs() < (vmaf_target - best_vmaf).abs() {
+            best_crf = crf;
+            best_vmaf = vmaf_score;
+        }
+
+        iteration += 1;
+    }
+
+    // the encode stage encodes the scene again at a CRF without a stream
+    if let Some((stream_crf, stream)) = last_stream {
+        if best_vmaf == 0.0 || stream_crf != best_crf {
+            let _ = fs::remove_file(stream);
+        }
+    }
+
+    if best_vmaf != 0.0 {
+        Ok((best_crf, best_vmaf))
+    } else {
+        Err("Failed to adjust CRF to target VMAF score within max iterations".to_string())
+    }
+}
+
+// Move crf towards vmaf_target after measuring vmaf_score at crf: small steps near the target,
+// otherwise halve the min_crf..max_crf range
+pub(crate) fn adjust_crf(
+    vmaf_target: f32,
+    vmaf_score: f32,
+    crf: &mut f32,
+    min_crf: &mut f32,
+    max_crf: &mut f32,
+) {
+    let distance = (vmaf_target - vmaf_score).abs();
+    // if the vmaf is within 3.0 of the target, adjust crf only by 3.0, within 2.0 by 2.0,
+    // within 1.0 by 1.0 and within 0.8 by 0.5
+    let step = if distance <= 3.0 && distance > 2.0 {
+        Some(3.0)
+    } else if distance <= 2.0 && distance > 1.0 {
+        Some(2.0)
+    } else if distance <= 1.0 && distance > 0.8 {
+        Some(1.0)
+    } else if distance <= 0.8 && distance > 0.5 {
+        Some(0.5)
+    } else {
+        None
+    };
+
+    match step {
+        // Increase CRF for lower quality, decrease it for higher quality
+        Some(step) if vmaf_score > vmaf_target => *crf += step,
+        Some(step) => *crf -= step,
+        None => {
+            if vmaf_score > vmaf_target {
+                *min_crf = *crf - 1.0; // Can afford lower quality
+            } else {
+                *max_crf = *crf + 1.0; // Need higher quality
+            }
+            *crf = *min_crf + (*max_crf - *min_crf) / 2.0; // Update CRF to mid-point of new range
+        }
+    }
+}
+
+// Function to encode a scene with a given CRF to output_file, the ChunkFiles::scene of the scene
+// The scene is cut by frame: a fast input seek to a few seconds before it, then an exact output seek
+// to half a frame before start_frame and frame_count frames, so input seeking can't land on a keyframe
+pub(crate) fn process_video_scene_encoded(
+    file: &String,
+    scene_index: &i32,
+    output_file: &str,
+    args: &Args,
+    crf: &f32,
+    fps: f32,
+    start_frame: u64,
+    frame_count: Option<u64>,
+    frames_bar: &Arc<Mutex<ProgressBar>>,
+    scene_frames: &Vec<(i32, f32, f32, f32)>,
+    debug_log: &Option<Arc<DebugLog>>,
+) -> Result<(Output, u64, EncodeCost), io::Error> {
+    let (preset, params) = encoder_preset_params(args);
+
+    let start_time = start_frame as f64 / fps as f64;
+    let input_seek = (start_time - 10.0).max(0.0);
+    let output_seek = (start_time - input_seek - 0.5 / fps as f64).max(0.0);
+
+    let mut spec = EncodeSpec::new(
+        &args.encoder,
+        EncodeQuality::Crf(*crf),
+        preset,
+        params,
+        &args.pix_fmt,
+    );
+    spec.keyint = encode_keyint(fps, args);
+    // the input is seeked to 10 seconds before the scene, the output to its first frame
+    spec.maps = vec![
+        "-map".to_string(),
+        probe_file(file).video_map(),
+        "-map_metadata".to_string(),
+        "-1".to_string(),
+        "-ss".to_string(),
+        format!("{:.6}", output_seek),
+        // the concatenated scenes each start on a keyframe
+        "-force_key_frames".to_string(),
+        "0".to_string(),
+    ];
+    if let Some(frame_count) = frame_count {
+        spec.maps
+            .extend(["-frames:v".to_string(), frame_count.to_string()]);
+    }
+    if args.strip_dovi && is_dovi(&get_dynamic_hdr(file)) {
+        spec.params = format!("{} {}", spec.params, STRIP_DOVI_ARGS.join(" "));
+    }
+    if should_deinterlace(file, &args.deinterlace) {
+        spec.filters.push("yadif".to_string());
+    }
+    let anamorphic = anamorphic(file);
+    spec.filters
+        .extend(anamorphic.map(|anamorphic| anamorphic.setsar_filter()));
+    spec.filters.extend(tonemap_filter(file, &args.tonemap));
+    spec.filters
+        .extend(downscale(file, args).map(|downscale| downscale.filter));
+    spec.filters.push("showinfo".to_string());
+
+    let mut command = ffmpeg_command();
+    command
+        .args(
+            FfmpegArgs::new(file, spec, output_file)
+                .input_args(["-hide_banner", "-y", "-ss", &format!("{:.6}", input_seek)])
+                .output_args(["-an", "-sn", "-dn"])
+                .output_args(
+                    anamorphic
+                        .map(|anamorphic| anamorphic.aspect_args())
+                        .unwrap_or_default(),
+                )
+                .build(),
+        )
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    debug!("{:?}", command);
+
+    let mut spawned_command = command.spawn()?;
+    let _tracked = track_child(&spawned_command, &[output_file]);
+    let sampler = CostSampler::start(&spawned_command, &args.encoder);
+    let stderr = spawned_command
+        .stderr
+        .take()
+        .expect("Failed to capture stderr");
+
+    // Spawn a thread to read the stderr output
+    let frames_bar_clone = frames_bar.clone();
+
+    let scene_index_i32 = *scene_index;
+    //get the number of frames for this scene
+    let scene_frames = scene_frames
+        .iter()
+        .filter(|frame| frame.0 == scene_index_i32)
+        .collect::<Vec<_>>();
+
+    let scene_frames_count = scene_frames.iter().map(|frame| frame.3).sum::<f32>();
+    // TEMP Print the scene_frames
+    //println!("{:?}", scene_frames.iter().map(|frame| frame.3).sum::<f32>());
+
+    let stderr_reader = thread::spawn(move || {
+        let reader = BufReader::new(stderr);
+        add_throttled_frames(
+            reader.lines().map_while(Result::ok),
+            scene_frames_count as usize,
+            ProgressThrottle::new(),
+            |frames| add_scene_frames(&frames_bar_clone, frames),
+        );
+    });
+
+    // the stderr of the scene ends when its ffmpeg does
+    let _ = stderr_reader.join();
+    let cost = sampler.finish();
+    let output = spawned_command.wait_with_output()?;
+
+    // set progress bar to the 3rd value of scene_frames of the current scene index
+    //let frames_bar = frames_bar.lock().unwrap();
+    //let scene_index_usize = scene_index.parse::<usize>().unwrap(); // Convert scene_index to usize
+    //frames_bar.set_position(scene_frames[scene_index_usize].2 as u64);
+
+    // TODO Get the number of frames in the chunk
+    //let mut frames_bar = frames_bar.lock().unwrap();
+    //frames_bar.set_length(*scene_frames);
+
+    // the size of the encoded scene, 0 when the encode failed
+    let return_size = fs::metadata(output_file).map(|m| m.len()).unwrap_or(0);
+    // Append the encoded scene size to the debug log if one was requested
+    if let Some(debug_log) = debug_log {
+        let _ = debug_log.write_line(&format!(
+            "Scene: {}, Scene Size: {}",
+            scene_index,
+            format_size(return_size)
+        ));
+    }
+
+    return Ok((output, return_size, cost));
+}
+
+// Whether the streams of the CRF search are encoded like the scenes, so the one of the chosen CRF can
+// be remuxed. A VFR source is searched at its average rate and the DoVi strip is left out of the search
+pub(crate) fn search_streams_reusable(file: &str, args: &Args) -> bool {
+    let strips_dovi = args.strip_dovi && is_dovi(&get_dynamic_hdr(file));
+    !probe_file(file).is_vfr() && !strips_dovi
+}
+
+// Remux the stream the CRF search encoded the scene with at crf into output_file, None when there is
+// none or it doesn't have the frames of the scene, the scene is then encoded
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn remux_search_stream(
+    file: &str,
+    scene_index: i32,
+    chunk_files: &ChunkFiles,
+    crf: f32,
+    frame_count: Option<u64>,
+    output_file: &str,
+    frames_bar: &Arc<Mutex<ProgressBar>>,
+    debug_log: &Option<Arc<DebugLog>>,
+) -> Option<(Output, u64, EncodeCost)> {
+    let stream = chunk_files.search_stream(scene_index, crf);
+    if !Path::new(&stream).exists() {
+        return None;
+    }
+    let started = Instant::now();
+    let frames = get_framecount_ffmpeg(&stream).unwrap_or(0.0) as u64;
+    if frames == 0 || frame_count.is_some_and(|frame_count| frame_count != frames) {
+        debug!(
+            "Scene {}: the search encoded {} frames instead of {}, encoding it",
+            scene_index,
+            frames,
+            frame_count.unwrap_or(0)
+        );
+        let _ = fs::remove_file(&stream);
+        return None;
+    }
+
+    let aspect_args = anamorphic(file)
+        .map(|anamorphic| anamorphic.aspect_args())
+        .unwrap_or_default();
+    let result = tracked_output(
+        ffmpeg_command()
+            .args(["-hide_banner", "-y", "-i", &stream])
+            .args(["-map", "0:v", "-map_metadata", "-1", "-c", "copy"])
+            .args(aspect_args)
+            .arg(output_file),
+        &[output_file],
+    );
+    let _ = fs::remove_file(&stream);
+    let output = match result {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            warn!(
+                "Scene {}: remuxing the search encode failed, encoding it: {}",
+                scene_index,
+                String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+            );
+            let _ = fs::remove_file(output_file);
+            return None;
+        }
+        Err(e) => {
+            warn!(
+                "Scene {}: remuxing the search encode failed, encoding it: {}",
+                scene_index, e
+            );
+            let _ = fs::remove_file(output_file);
+            return None;
+        }
+    };
+
+    // the search doesn't count frames, the remuxed ones are counted here once
+    add_scene_frames(frames_bar, frames);
+    let return_size = fs::metadata(output_file).map(|m| m.len()).unwrap_or(0);
+    if let Some(debug_log) = debug_log {
+        let _ = debug_log.write_line(&format!(
+            "Scene: {}, Scene Size: {}, remuxed from the search",
+            scene_index,
+            format_size(return_size)
+        ));
+    }
+    let mut cost = EncodeCost::default();
+    cost.wall_seconds = started.elapsed().as_secs_f64();
+    Some((output, return_size, cost))
+}
+
+// Count the frames of the showinfo lines of a scene, up to max_frames, and add them with add at
+// most every --progress-interval of throttle, the rest once the lines end
+fn add_throttled_frames(
+    lines: impl Iterator<Item = String>,
+    max_frames: usize,
+    mut throttle: ProgressThrottle,
+    mut add: impl FnMut(u64),
+) {
+    let mut counted = 0;
+    let mut pending_frames = 0;
+    for line in lines {
+        // try to set the progress bar to the value parsed like: n:  59 pts:   2461
+        if let Some(progress) = parse_frame_progress(&line) {
+            if counted < max_frames {
+                pending_frames += progress;
+                counted += 1;
+            }
+        }
+        if pending_frames > 0 && throttle.ready() {
+            add(pending_frames);
+            pending_frames = 0;
+        }
+    }
+    if pending_frames > 0 {
+        add(pending_frames);
+    }
+}
+
+// Implement parse_frame_progress to parse the ffmpeg stderr output
+fn parse_frame_progress(line: &str) -> Option<u64> {
+    // This function needs to parse lines from ffmpeg's stderr to find frame processing updates.
+    // Adjust the parsing logic based on the actual output format of ffmpeg.
+    if line.contains("n:") {
+        return Some(1);
+    } else {
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests;
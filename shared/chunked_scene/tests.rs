@@ -0,0 +1,50 @@
+use super::*;
+
+// The showinfo output of a scene of frames frames, a line of stream info every 100 frames
+fn showinfo_lines(frames: u64) -> impl Iterator<Item = String> {
+    (0..frames).flat_map(|n| {
+        let frame = format!(
+            "[Parsed_showinfo_0 @ 0x55d0c3a1c2c0] n:{:5} pts:{:7} pts_time:{:.3}",
+            n,
+            n * 1001,
+            n as f64 / 23.976
+        );
+        let info = (n % 100 == 0).then(|| "Stream #0:0: Video: hevc, yuv420p10le".to_string());
+        std::iter::once(frame).chain(info)
+    })
+}
+
+// The batches of frames add_throttled_frames adds with throttle
+fn added_batches(
+    lines: impl Iterator<Item = String>,
+    max_frames: usize,
+    interval: Duration,
+) -> Vec<u64> {
+    let mut batches = Vec::new();
+    add_throttled_frames(
+        lines,
+        max_frames,
+        ProgressThrottle::with_interval(interval),
+        |frames| batches.push(frames),
+    );
+    batches
+}
+
+#[test]
+fn the_frames_of_a_fast_scene_are_added_in_batches() {
+    // read faster than the interval: the first frame, then the rest at the end
+    let batches = added_batches(showinfo_lines(10_000), 10_000, Duration::from_secs(3600));
+    assert_eq!(batches, [1, 9_999]);
+
+    // without a throttle every frame is one increment of the bar
+    let batches = added_batches(showinfo_lines(10_000), 10_000, Duration::ZERO);
+    assert_eq!(batches.len(), 10_000);
+    assert_eq!(batches.iter().sum::<u64>(), 10_000);
+}
+
+#[test]
+fn the_frames_of_a_scene_stop_at_its_frame_count() {
+    let batches = added_batches(showinfo_lines(10_000), 4_000, Duration::from_secs(3600));
+    assert_eq!(batches.iter().sum::<u64>(), 4_000);
+    assert!(added_batches(showinfo_lines(0), 4_000, Duration::ZERO).is_empty());
+}
@@ -0,0 +1,164 @@
+// The CRF search stage of the chunked mode: a search per scene in the threadpool, the scenes below
+// --fast-scene-threshold try the fast CRF first, the result goes to searched.txt and to the encode
+// stage
+
+use crate::*;
+
+// The (crf, vmaf, fast) each scene index ends up encoded with
+pub(crate) type ChosenScores = Arc<Mutex<HashMap<i32, (f32, f32, bool)>>>;
+
+// What the search of a scene shares with the run
+pub(crate) struct SceneSearch {
+    pub(crate) file: String,
+    pub(crate) args: Args,
+    pub(crate) scene_fps: f32,
+    // the last scene of a whole file runs to its end
+    pub(crate) open_end: bool,
+    pub(crate) last_scene_change: f32,
+    pub(crate) total_scenes: usize,
+    pub(crate) reuse_search_streams: bool,
+    pub(crate) vmaf_scores: Arc<Mutex<Vec<(i32, f32, f32)>>>,
+    pub(crate) chosen_scores: ChosenScores,
+    pub(crate) pipeline_bar: Arc<Mutex<ProgressBar>>,
+    pub(crate) searched_log: Arc<ProgressLog>,
+    pub(crate) progress_snapshot: Arc<ProgressSnapshot>,
+    pub(crate) debug_log: Option<Arc<DebugLog>>,
+    pub(crate) searched_count: Arc<AtomicUsize>,
+    pub(crate) encoded_count: Arc<AtomicUsize>,
+    pub(crate) job_sender: mpsc::SyncSender<SceneEncodeJob>,
+    pub(crate) gave_up: Arc<Mutex<Option<u64>>>,
+    pub(crate) chunk_files: ChunkFiles,
+}
+
+impl SceneSearch {
+    // Search the CRF of the scene from scene_change to next_scene_change and queue its encode
+    pub(crate) fn run(self, index: i32, scene_change: f32, next_scene_change: f32) {
+        let SceneSearch {
+            file,
+            args,
+            scene_fps,
+            open_end,
+            last_scene_change,
+            total_scenes,
+            reuse_search_streams,
+            vmaf_scores,
+            chosen_scores,
+            pipeline_bar,
+            searched_log,
+            progress_snapshot,
+            debug_log,
+            searched_count,
+            encoded_count,
+            job_sender,
+            gave_up,
+            chunk_files,
+        } = self;
+        // a pool thread of the task, a pause of the task stops its searches too
+        set_current_task(&args.task_id);
+        if gave_up.lock().unwrap().is_some() {
+            return;
+        }
+        let fps = get_fps(&file);
+        let search_streams = reuse_search_streams.then_some(&chunk_files);
+        let ss_arg = format_timecode(&scene_change);
+        let to_arg = format_timecode(&next_scene_change);
+
+        let scene_size = get_scene_size(&file, &ss_arg, &to_arg).unwrap();
+
+        // A cheap scene is kept at the fast CRF when one measurement reaches the target
+        let kb_per_frame =
+            scene_kb_per_frame(scene_size, scene_change, next_scene_change, scene_fps);
+        let fast_result = if is_fast_scene(&args, kb_per_frame) {
+            let fast_result = process_scene_fast(
+                index,
+                &file,
+                &ss_arg,
+                &to_arg,
+                &fps,
+                &fast_scene_args(&args),
+                &vmaf_scores,
+                &debug_log,
+                search_streams,
+            );
+            if fast_result.is_none() {
+                debug!(
+                    "Scene {}: {:.2} kB per frame, fast path below VMAF {}, searching",
+                    index, kb_per_frame, args.vmaf
+                );
+            }
+            fast_result
+        } else {
+            None
+        };
+        let fast = fast_result.is_some();
+
+        // Find the best CRF for the scene
+        let search_result = match fast_result {
+            Some(result) => Ok(result),
+            None => process_scene_adjust_crf_binary(
+                index,
+                scene_size,
+                &file,
+                &ss_arg,
+                &to_arg,
+                &fps,
+                args.vmaf,
+                &args,
+                vmaf_scores.clone(),
+                &args.vmaf_pool,
+                &args.vmaf_threads,
+                &args.vmaf_subsample,
+                &debug_log,
+                search_streams,
+            ),
+        };
+        match search_result {
+            Ok((crf, vmaf_score)) => {
+                // a search cut short by ctrl-C must not reach searched.txt
+                wait_if_interrupted();
+                chosen_scores
+                    .lock()
+                    .unwrap()
+                    .insert(index, (crf, vmaf_score, fast));
+                let scene_path = if fast {
+                    FAST_SCENE_PATH
+                } else {
+                    FULL_SCENE_PATH
+                };
+                // remember the search result, so a restart only has to encode this scene
+                if let Err(e) = searched_log
+                    .write_line(&format!("{} {} {} {}", index, crf, vmaf_score, scene_path))
+                {
+                    error!("Failed to write searched.txt: {}", e);
+                }
+
+                let searched = searched_count.fetch_add(1, Ordering::SeqCst) + 1;
+                let encoded = encoded_count.load(Ordering::SeqCst);
+                update_pipeline_bar(&pipeline_bar, searched, encoded, total_scenes);
+                update_chunked_progress(|chunked| chunked.searched_scenes = searched);
+                write_chunk_progress(&progress_snapshot, &file, total_scenes, searched, encoded);
+
+                // Blocks while the encode stage is busy and the channel is full
+                let (start_frame, frame_count) = scene_frame_range(
+                    scene_change,
+                    next_scene_change,
+                    scene_fps,
+                    open_end && next_scene_change == last_scene_change,
+                );
+                let job = SceneEncodeJob {
+                    index,
+                    start_frame,
+                    frame_count,
+                    scene_size,
+                    crf,
+                    vmaf_score,
+                    fast,
+                };
+                if job_sender.send(job).is_err() {
+                    warn!("Encode stage stopped, scene {} was not encoded", index);
+                }
+            }
+            Err(e) => warn!("Scene {}: {}", index, e),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+// The chunked encode before the search and encode stages were split, one scene after another
+
+use crate::*;
+
+use std::ffi::OsString;
+
+pub fn run_ffmpeg_extract_scene_changes_pipe_vmaf_target(
+    file: &str,
+    scene_changes: &Vec<f32>,
+    args: &Args,
+) -> Result<Vec<f32>, io::Error> {
+    // Create a progress bar
+    let progress_bar = progress_bar(scene_changes.len() as u64);
+    let progress_bar_style =
+        "[extract][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]";
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template(progress_bar_style)
+            .unwrap(),
+    );
+
+    // Run ffmpeg to extract each scene change into a separate file
+    let scene_changes_list: Vec<f32> = Vec::new();
+    let mut scene_count = 0;
+    let vmaf_target = args.vmaf;
+    let mut crf = 25;
+    let mut i = 0;
+
+    for scene_change in scene_changes.clone() {
+        // If scene_change is the last item in the scene_changes vector, break
+        if i == scene_changes.len() - 1 {
+            break;
+        }
+
+        // Get the fps of the input file
+        let fps = vmaf_fps(&file);
+
+        // Write the command like this, where fps=23.98 is the fps of the input file
+        // Should use get_fps function to get the fps of the input file
+
+        let output_file = "./temp_output.nut";
+
+        let mut first_command = ffmpeg_command()
+            .args(scene_encode_args(
+                file,
+                args,
+                25.0,
+                (&scene_change, &scene_changes[i + 1]),
+                output_file, // Write output to a temporary file
+            ))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to start first command");
+
+        // Wait for the first command to finish
+        let _ = first_command
+            .wait()
+            .expect("Failed to wait on the first command");
+
+        // Second FFmpeg command
+        let second_command = ffmpeg_command()
+            .arg("-ss")
+            .arg(format_timecode(&scene_change))
+            .arg("-to")
+            .arg(format_timecode(&scene_changes[i + 1]))
+            .arg("-i")
+            .arg(file) // Read input from the temporary file
+            .arg("-f")
+            .arg("nut")
+            .arg("-thread_queue_size")
+            .arg("4096")
+            .arg("-i")
+            .arg(output_file)
+            .arg("-lavfi")
+            .arg(
+                format!(
+                    "[{}]setpts=PTS-STARTPTS,fps={}[reference];[1:v]setpts=PTS-STARTPTS,fps={}[distorted];[reference][distorted]libvmaf",
+                        probe_file(file).video_map(),
+                    fps,
+                    fps
+                )
+            )
+            .arg("-f")
+            .arg("null")
+            .arg("-")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("Failed to start second command");
+
+        let output = second_command.wait_with_output().unwrap();
+        let output_str_stderr = String::from_utf8_lossy(&output.stderr);
+        let split_output_stderr: Vec<&str> = output_str_stderr.split("\n").collect();
+
+        let mut vmaf_score: f32 = 0.0;
+        for line in split_output_stderr.clone() {
+            if line.contains("VMAF score:") {
+                vmaf_score = line.split("VMAF score:").collect::<Vec<&str>>()[1]
+                    .trim()
+                    .parse()
+                    .unwrap();
+                // print the score and crf, which is after "VMAF score:", but make sure to remove the trailing whitespace
+                // if score is less than vmax_target, then print in red, else print in green
+                // also print the scene index
+                if vmaf_score < vmaf_target {
+                    info!(
+                        "Scene index: {}, VMAF score: {}, crf: {}",
+                        scene_count,
+                        line.split("VMAF score: ").collect::<Vec<&str>>()[1]
+                            .trim()
+                            .red(),
+                        crf.to_string().red()
+                    );
+                } else {
+                    info!(
+                        "Scene index: {}, VMAF score: {}, crf: {}",
+                        scene_count,
+                        line.split("VMAF score: ").collect::<Vec<&str>>()[1]
+                            .trim()
+                            .green(),
+                        crf.to_string().green()
+                    );
+                }
+                break;
+            }
+        }
+
+        while vmaf_score < vmaf_target || vmaf_score > vmaf_target + 1.0 {
+            if vmaf_score < vmaf_target {
+                crf -= 1;
+            } else if vmaf_score > vmaf_target + 1.0 {
+                crf += 1;
+            }
+
+            let output_file = "./temp_output.nut";
+
+            let mut first_command = ffmpeg_command()
+                .args(scene_encode_args(
+                    file,
+                    args,
+                    crf as f32,
+                    (&scene_change, &scene_changes[i + 1]),
+                    output_file, // Write output to a temporary file
+                ))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .expect("Failed to start first command");
+
+            // Wait for the first command to finish
+            let _ = first_command
+                .wait()
+                .expect("Failed to wait on the first command");
+
+            // Second FFmpeg command
+            let second_command = ffmpeg_command()
+                .arg("-ss")
+                .arg(format_timecode(&scene_change))
+                .arg("-to")
+                .arg(format_timecode(&scene_changes[i + 1]))
+                .arg("-i")
+                .arg(file) // Read input from the temporary file
+                .arg("-f")
+                .arg("nut")
+                .arg("-thread_queue_size")
+                .arg("4096")
+                .arg("-i")
+                .arg(output_file)
+                .arg("-lavfi")
+                .arg(
+                    format!(
+                        "[{}]setpts=PTS-STARTPTS,fps={}[reference];[1:v]setpts=PTS-STARTPTS,fps={}[distorted];[reference][distorted]libvmaf",
+                        probe_file(file).video_map(),
+                        fps,
+                        fps
+                    )
+                )
+                .arg("-f")
+                .arg("null")
+                .arg("-")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .expect("Failed to start second command");
+
+            let output = second_command.wait_with_output().unwrap();
+            let output_str_stderr = String::from_utf8_lossy(&output.stderr);
+            let split_output_stderr: Vec<&str> = output_str_stderr.split("\n").collect();
+
+            vmaf_score = 0.0;
+            for line in split_output_stderr.clone() {
+                if line.contains("VMAF score:") {
+                    vmaf_score = line.split("VMAF score:").collect::<Vec<&str>>()[1]
+                        .trim()
+                        .parse()
+                        .unwrap();
+                    // print the score and crf, which is after "VMAF score:", but make sure to remove the trailing whitespace
+                    // if score is less than vmax_target, then print in red, else print in green
+                    // also print the scene index
+                    if vmaf_score < vmaf_target {
+                        info!(
+                            "Scene index: {}, VMAF score: {}, crf: {}",
+                            scene_count,
+                            line.split("VMAF score: ").collect::<Vec<&str>>()[1]
+                                .trim()
+                                .red(),
+                            crf.to_string().red()
+                        );
+                    } else {
+                        info!(
+                            "Scene index: {}, VMAF score: {}, crf: {}",
+                            scene_count,
+                            line.split("VMAF score: ").collect::<Vec<&str>>()[1]
+                                .trim()
+                                .green(),
+                            crf.to_string().green()
+                        );
+                    }
+                    break;
+                }
+            }
+        }
+
+        fs::remove_file(output_file).expect("Failed to remove temporary file");
+
+        scene_count += 1;
+        i = i + 1;
+        progress_bar.set_position(scene_count as u64);
+    }
+
+    info!("Extracted {} scenes", scene_count);
+
+    Ok(scene_changes_list)
+}
+
+// The encode of the scene from ss to to, hevc_nvenc at crf is the one it was written for
+fn scene_encode_args(
+    file: &str,
+    args: &Args,
+    crf: f32,
+    (ss, to): (&f32, &f32),
+    output_file: &str,
+) -> Vec<OsString> {
+    let (preset, params) = encoder_preset_params(args);
+    let mut spec = EncodeSpec::new(
+        &args.encoder,
+        EncodeQuality::Crf(crf),
+        preset,
+        params,
+        &args.pix_fmt,
+    );
+    spec.keyint = encode_keyint(get_fps(file).parse().unwrap_or(0.0), args);
+    spec.seek = Some((format_timecode(ss), format_timecode(to)));
+    // the concatenated scenes each start on a keyframe
+    spec.maps = vec![
+        "-map".to_string(),
+        probe_file(file).video_map(),
+        "-force_key_frames".to_string(),
+        "0".to_string(),
+    ];
+    FfmpegArgs::new(file, spec, output_file)
+        .input_args(["-y"])
+        .output_args(["-f", "nut"])
+        .build()
+}
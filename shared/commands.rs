@@ -0,0 +1,63 @@
+// The subcommands of Args and of its db and sessions subcommands
+
+use crate::*;
+
+use clap::Subcommand;
+
+// The subcommands, without one the cli processes the -i files
+#[derive(Subcommand, Serialize, Deserialize, Debug, Clone)]
+pub enum Commands {
+    /// Print the streams of a file, the status a scan gives it and how the options before inspect would encode it
+    Inspect {
+        /// The file to inspect
+        file: String,
+
+        /// Print the report as json
+        #[clap(long)]
+        json: bool,
+    },
+    /// Reports of the database
+    Db {
+        #[clap(subcommand)]
+        command: DbCommands,
+    },
+    /// The journals of the batches --resume-last continues
+    Sessions {
+        #[clap(subcommand)]
+        command: SessionCommands,
+    },
+}
+
+// The subcommands of sessions
+#[derive(Subcommand, Serialize, Deserialize, Debug, Clone)]
+pub enum SessionCommands {
+    /// List the sessions with their state and how many of their files are done, the newest first
+    List {
+        /// Print the list as json
+        #[clap(long)]
+        json: bool,
+    },
+    /// Remove the done sessions, and the unfinished ones not updated for --older-than
+    Clean {
+        /// Also remove the unfinished sessions not updated for this long ("30d", "12h")
+        #[clap(long, value_parser = artifacts_age_validation)]
+        older_than: Option<String>,
+    },
+}
+
+// The subcommands of db
+#[derive(Subcommand, Serialize, Deserialize, Debug, Clone)]
+pub enum DbCommands {
+    /// Compare the encoders and presets by what their encodes saved per CPU hour and per wall hour
+    CompareEncoders {
+        /// Print the report as json
+        #[clap(long)]
+        json: bool,
+    },
+    /// Put the rows the folder scans moved to the trash back into the library, the ones trashed in the last 30 days are kept
+    RestoreTrash {
+        /// Only the rows trashed since then, a duration ("2h", "1d") or a UTC date ("2024-05-01")
+        #[clap(long, value_parser = since_input_validation)]
+        since: Option<String>,
+    },
+}
@@ -0,0 +1,479 @@
+// CRF search with ab-av1 crf-search and the size ab-av1 predicts, the native sample search is in
+// native_crf_search.rs
+
+use crate::*;
+
+// Output size ab-av1 predicts for the crf it found, size in MB and percent of the source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PredictedSize {
+    pub size_mb: f32,
+    pub percent: f32,
+}
+
+impl PredictedSize {
+    // no savings when the encode is predicted to be as large as the source
+    pub fn no_savings(&self) -> bool {
+        self.percent >= 100.0
+    }
+}
+
+impl std::fmt::Display for PredictedSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.size_mb >= 1024.0 {
+            write!(
+                f,
+                "predicted {:.1} GiB, {:.0}% of source",
+                self.size_mb / 1024.0,
+                self.percent
+            )
+        } else {
+            write!(
+                f,
+                "predicted {:.1} MiB, {:.0}% of source",
+                self.size_mb, self.percent
+            )
+        }
+    }
+}
+
+// crf 21 VMAF 97.15 predicted video stream size 6.60 GiB (72%) taking 21 minutes, older versions
+// print predicted full encode size
+pub fn parse_predicted_size(output: &str) -> Option<PredictedSize> {
+    let re = Regex::new(
+        r"predicted (?:video stream|full encode) size ([\d.]+) ?(\w+) \((\d+(?:\.\d+)?)%\)",
+    )
+    .unwrap();
+    let captures = re.captures(output)?;
+    let size = captures[1].parse::<f32>().ok()?;
+    let size_mb = match &captures[2] {
+        "B" => size / 1024.0 / 1024.0,
+        "KiB" | "kB" => size / 1024.0,
+        "MiB" | "MB" => size,
+        "GiB" | "GB" => size * 1024.0,
+        "TiB" | "TB" => size * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    let percent = captures[3].parse::<f32>().ok()?;
+    Some(PredictedSize { size_mb, percent })
+}
+
+// The CRF ab-av1 crf-search found, from its stdout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AbAv1Result {
+    // fractional with --crf-increment below 1, like 22.5
+    pub crf: f32,
+    // the VMAF, or the XPSNR of --xpsnr, of the samples at crf
+    pub score: Option<f32>,
+    pub predicted: Option<PredictedSize>,
+}
+
+// The result of ab-av1 crf-search in its stdout, None when it has no CRF. It prints
+// crf 21 VMAF 97.15 predicted video stream size 6.60 GiB (72%) taking 21 minutes
+// - crf 19 VMAF 97.24 (76%) (cache)
+//
+// Encode with: ab-av1 encode -e hevc_nvenc -i "file.mkv" --crf 21 --preset p7
+// with colors on a terminal, a fractional CRF like 22.5 with a --crf-increment below 1 and XPSNR
+// instead of VMAF with --xpsnr. The other CRFs it tried start with a -, the --crf of the encode
+// command is taken when the result line is missing
+pub fn parse_ab_av1_result(stdout: &str) -> Option<AbAv1Result> {
+    let stdout = Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]")
+        .unwrap()
+        .replace_all(stdout, "");
+    let result_line =
+        Regex::new(r"^crf\s+(\d+(?:\.\d+)?)\s+(?:VMAF|XPSNR)\s+(\d+(?:\.\d+)?)").unwrap();
+    if let Some((line, caps)) = stdout
+        .lines()
+        .map(str::trim)
+        .find_map(|line| result_line.captures(line).map(|caps| (line, caps)))
+    {
+        return Some(AbAv1Result {
+            crf: caps[1].parse().ok()?,
+            score: caps[2].parse().ok(),
+            predicted: parse_predicted_size(line),
+        });
+    }
+    let caps = Regex::new(r"Encode with:.*--crf\s+(\d+(?:\.\d+)?)")
+        .unwrap()
+        .captures(&stdout)?;
+    Some(AbAv1Result {
+        crf: caps[1].parse().ok()?,
+        score: None,
+        predicted: None,
+    })
+}
+
+// How an ab-av1 crf-search ended, the end of its stderr has the details
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AbAv1Outcome {
+    Found(AbAv1Result),
+    // no CRF up to --max-crf reaches the VMAF target, the search is retried with a lower one
+    NoSuitableCrf,
+    // ab-av1 failed otherwise, or printed no result that can be read, the file fails
+    Failed,
+}
+
+// ab-av1's progress bar with its escape codes removed, like "sample 2/5 crf 28 43%"
+// The percent is ab-av1's estimate of the whole search, "(76%)" is the size of a result line
+// Returns whether progress was updated
+pub(crate) fn parse_ab_av1_progress(line: &str, progress: &mut CrfSearchProgress) -> bool {
+    let line = Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]")
+        .unwrap()
+        .replace_all(line, "");
+    let mut updated = false;
+    if let Some(caps) = Regex::new(r"crf\s+(\d+(?:\.\d+)?)")
+        .unwrap()
+        .captures(&line)
+    {
+        let crf = caps[1].parse::<f32>().ok();
+        if crf != progress.crf {
+            // a new CRF starts over at its first sample
+            progress.crf = crf;
+            progress.sample = 0;
+            updated = true;
+        }
+    }
+    if let Some(caps) = Regex::new(r"sample\s+(\d+)/(\d+)").unwrap().captures(&line) {
+        if let (Ok(sample), Ok(samples)) = (caps[1].parse::<u32>(), caps[2].parse::<u32>()) {
+            progress.sample = sample;
+            progress.samples = samples;
+            updated = true;
+        }
+        match Regex::new(r"(?:^|\s)(\d{1,3}(?:\.\d+)?)%")
+            .unwrap()
+            .captures(&line)
+            .and_then(|caps| caps[1].parse::<f32>().ok())
+        {
+            Some(percentage) => progress.percentage = percentage.min(100.0),
+            // without a percent, the samples of the current CRF done
+            None if progress.samples > 0 => {
+                progress.percentage =
+                    progress.sample.saturating_sub(1) as f32 / progress.samples as f32 * 100.0
+            }
+            None => {}
+        }
+    }
+    updated
+}
+
+// Show the CRF search on the vmaf bar and on /progress
+fn show_crf_search_progress(vmaf_bar: &ProgressBar, progress: &CrfSearchProgress) {
+    vmaf_bar.set_position(progress.percentage.round() as u64);
+    vmaf_bar.set_message(progress.to_string());
+    *WEB_CRF_SEARCH_STATIC.lock().unwrap() = Some(progress.clone());
+}
+
+// Read ab-av1's stderr as it comes, its progress bar is redrawn with \r and its log lines end with
+// \n. The log lines are shown with --verbose. Returns the end of the stderr
+fn read_ab_av1_stderr(
+    mut stderr: impl Read,
+    verbose: bool,
+    vmaf_bar: &ProgressBar,
+    mut progress: CrfSearchProgress,
+) -> String {
+    let mut stderr_tail = String::new();
+    let mut line = String::new();
+    let mut buffer = [0; 1024];
+    loop {
+        match stderr.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let chunk = String::from_utf8_lossy(&buffer[..n]);
+                push_stderr_tail(&mut stderr_tail, &chunk);
+                for c in chunk.chars() {
+                    if c != '\r' && c != '\n' {
+                        line.push(c);
+                        continue;
+                    }
+                    if parse_ab_av1_progress(&line, &mut progress) {
+                        show_crf_search_progress(vmaf_bar, &progress);
+                    }
+                    if verbose && c == '\n' && !line.trim().is_empty() {
+                        info!("{}", line);
+                    }
+                    line.clear();
+                }
+            }
+        }
+    }
+    stderr_tail
+}
+
+// Returns how the search ended and the end of ab-av1's stderr, with the stdout of a result that
+// can't be read. input is what ab-av1 reads, the staged copy of file or the clip of a trial
+fn execute_crf_search(
+    file: &str,
+    input: &str,
+    encoder: &str,
+    vmaf: f32,
+    max_crf: &str,
+    sample_every: &str,
+    pix_fmt: &str,
+    preset_x265: &str,
+    vmaf_threads: &str,
+    verbose: bool,
+    extra_args: &[String],
+    vmaf_bar: &ProgressBar,
+    progress: CrfSearchProgress,
+) -> Result<(AbAv1Outcome, String), io::Error> {
+    // prefix vmaf_threads it with 'n_threads='
+    let vmaf_threads = format!("n_threads={}", vmaf_threads);
+    debug!(
+        "{} crf-search -i {} --min-vmaf {} --max-crf {} --sample-every {} -e {} --pix-format {} --preset {} --vmaf {} {}",
+        ab_av1_bin(), file, vmaf, max_crf, sample_every, encoder, pix_fmt, preset_x265, vmaf_threads, extra_args.join(" ")
+    );
+    let mut command = ab_av1_command();
+    command
+        .arg("crf-search")
+        .arg("-i")
+        .arg(input)
+        .arg("--min-vmaf")
+        .arg(vmaf.to_string())
+        .arg("--max-crf")
+        .arg(max_crf)
+        .arg("--sample-every")
+        .arg(sample_every)
+        .arg("-e")
+        .arg(encoder)
+        .arg("--pix-format")
+        .arg(pix_fmt)
+        .arg("--preset")
+        .arg(preset_x265)
+        .arg("--vmaf")
+        .arg(&vmaf_threads)
+        .args(extra_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    shield_from_ctrlc(&mut command);
+    let mut output = command.spawn()?;
+    let _tracked = track_child(&output, &[]);
+
+    // the progress is read on its own thread while the stdout is collected
+    let stderr = output.stderr.take().unwrap();
+    let bar = vmaf_bar.clone();
+    let reader = thread::spawn(move || read_ab_av1_stderr(stderr, verbose, &bar, progress));
+
+    let output_result = output.wait_with_output()?;
+    let mut stderr_tail = reader.join().unwrap_or_default();
+    wait_if_interrupted();
+
+    let stdout = String::from_utf8_lossy(&output_result.stdout);
+    let outcome = ab_av1_outcome(output_result.status.success(), &stdout, &stderr_tail);
+    if !output_result.status.success() {
+        push_stderr_tail(
+            &mut stderr_tail,
+            &format!("ab-av1 {}\n", output_result.status),
+        );
+    } else if outcome == AbAv1Outcome::Failed {
+        push_stderr_tail(
+            &mut stderr_tail,
+            &format!(
+                "{} printed no CRF that can be read:\n{}\n",
+                ab_av1_version().unwrap_or("ab-av1"),
+                if stdout.trim().is_empty() {
+                    "(no output)"
+                } else {
+                    stdout.trim()
+                }
+            ),
+        );
+    }
+    Ok((outcome, stderr_tail))
+}
+
+// How a crf-search that exited with success or not ended, from its stdout and the end of its stderr
+fn ab_av1_outcome(success: bool, stdout: &str, stderr_tail: &str) -> AbAv1Outcome {
+    if success {
+        return match parse_ab_av1_result(stdout) {
+            Some(result) => AbAv1Outcome::Found(result),
+            None => AbAv1Outcome::Failed,
+        };
+    }
+    // Error: Failed to find a suitable crf
+    if stderr_tail
+        .to_lowercase()
+        .contains("failed to find a suitable crf")
+    {
+        AbAv1Outcome::NoSuitableCrf
+    } else {
+        AbAv1Outcome::Failed
+    }
+}
+
+// Show the CRF search of the file on /progress until ffmpeg starts
+pub(crate) fn set_crf_search_progress(
+    task_id: &str,
+    current_file_count: &u64,
+    total_files: &i32,
+    vmaf: f32,
+    requested_vmaf: f32,
+) {
+    *WEB_TASK_ID_STATIC.lock().unwrap() = task_id.to_string();
+    *WEB_FPS_STATIC.lock().unwrap() = 0;
+    *WEB_CURRENT_FRAME_STATIC.lock().unwrap() = 0;
+    *WEB_TOTAL_FRAME_STATIC.lock().unwrap() = 0.0;
+    *WEB_EXPECTED_SIZE_STATIC.lock().unwrap() = 0;
+    *WEB_CURRENT_FILE_STATIC.lock().unwrap() = current_file_count.clone() as u64;
+    *WEB_TOTAL_FILES_STATIC.lock().unwrap() = total_files.clone() as u64;
+    *WEB_CURRENT_FILE_NAME_STATIC.lock().unwrap() =
+        format!("Searching for best CRF for VMAF {}...", vmaf);
+    *WEB_CRF_SEARCH_STATIC.lock().unwrap() = Some(CrfSearchProgress::new(vmaf, requested_vmaf));
+}
+
+// The search ended, /progress moves on to the encode
+pub(crate) fn clear_crf_search_progress() {
+    *WEB_CRF_SEARCH_STATIC.lock().unwrap() = None;
+}
+
+// The VMAF target of the next try after no CRF reached vmaf, rounded to hundredths so that
+// steps like 0.1 do not drift
+pub(crate) fn lower_vmaf_target(vmaf: f32, vmaf_retry_step: f32) -> f32 {
+    ((vmaf - vmaf_retry_step).max(0.0) * 100.0).round() / 100.0
+}
+
+// Lowers the VMAF target by --vmaf-retry-step until ab-av1 finds a CRF, the error carries the end
+// of ab-av1's stderr
+pub fn run_ab_av1_crf_search(
+    file: &str,
+    encoder: &str,
+    preset_x265: &str,
+    pix_fmt: &str,
+    mut vmaf: f32,
+    vmaf_retry_step: f32,
+    max_crf: &str,
+    sample_every: &str,
+    vmaf_threads: &str,
+    verbose: bool,
+    extra_args: &[String],
+    task_id: &str,
+    current_file_count: &u64,
+    total_files: &i32,
+    vmaf_bar: &ProgressBar,
+) -> Result<(String, f32, Option<PredictedSize>), String> {
+    let requested_vmaf = vmaf;
+    // ab-av1 searches a whole file, a trial gives it a clip of its range
+    let trial_clip = match trial_range(file) {
+        Some(range) => Some(cut_trial_clip(file, &range)?),
+        None => None,
+    };
+    let input = trial_clip.clone().unwrap_or_else(|| staged_input(file));
+    vmaf_bar.set_length(100);
+    let result = loop {
+        // a retry shows its lowered target
+        set_crf_search_progress(
+            task_id,
+            current_file_count,
+            total_files,
+            vmaf,
+            requested_vmaf,
+        );
+        let progress = CrfSearchProgress::new(vmaf, requested_vmaf);
+        show_crf_search_progress(vmaf_bar, &progress);
+        // print searching for best crf for vmaf <value> in yellow
+        info!(
+            "{}",
+            format!("Searching for best CRF for VMAF {}...", vmaf).yellow()
+        );
+        let searched = execute_crf_search(
+            file,
+            &input,
+            encoder,
+            vmaf,
+            max_crf,
+            sample_every,
+            pix_fmt,
+            preset_x265,
+            vmaf_threads,
+            verbose,
+            extra_args,
+            vmaf_bar,
+            progress,
+        )
+        .map_err(|e| format!("Failed to run ab-av1: {}", e));
+        let (outcome, stderr_tail) = match searched {
+            Ok(searched) => searched,
+            Err(e) => break Err(e),
+        };
+        record_task_log(
+            task_id,
+            &format!("ab-av1 crf-search -i {} --min-vmaf {}", file, vmaf),
+            &stderr_tail,
+        );
+
+        let result = match outcome {
+            AbAv1Outcome::Found(result) => result,
+            AbAv1Outcome::NoSuitableCrf if vmaf > 0.0 => {
+                vmaf = lower_vmaf_target(vmaf, vmaf_retry_step);
+
+                // show the new vmaf value
+                warn!("{}", format!("Retrying with VMAF of {}...", vmaf).yellow());
+                continue;
+            }
+            AbAv1Outcome::NoSuitableCrf => {
+                error!(
+                    "{}",
+                    format!("Failed to find a suitable CRF for {}", file).red()
+                );
+                break Err(stderr_tail.trim().to_string());
+            }
+            // a retry at a lower VMAF would fail the same way
+            AbAv1Outcome::Failed => {
+                error!("{}", format!("ab-av1 failed for {}", file).red());
+                break Err(stderr_tail.trim().to_string());
+            }
+        };
+        // show the new vmaf value at the CRF
+        match result.score {
+            Some(score) => info!(
+                "{}",
+                format!(
+                    "Found CRF {} for VMAF {} (samples VMAF {:.2})!",
+                    result.crf, vmaf, score
+                )
+                .green()
+            ),
+            None => info!(
+                "{}",
+                format!("Found CRF {} for VMAF {}!", result.crf, vmaf).green()
+            ),
+        }
+        let predicted = result.predicted;
+        if let Some(predicted) = predicted {
+            info!("{}: {}", file, predicted);
+            // start the web expected size from the prediction until ffmpeg reports a size
+            *WEB_EXPECTED_SIZE_STATIC.lock().unwrap() = mb_to_bytes(predicted.size_mb as f64);
+            // the prediction of a trial is the size of its clip
+            if trial_clip.is_none() {
+                if let Err(e) = db().update_predicted_size(file, &predicted) {
+                    warn!("Failed to store predicted size of {}: {}", file, e);
+                }
+            }
+        }
+        break Ok((result.crf.to_string(), vmaf, predicted));
+    };
+    if let Some(clip) = &trial_clip {
+        let _ = fs::remove_file(clip);
+    }
+    clear_crf_search_progress();
+    vmaf_bar.set_position(0);
+    vmaf_bar.set_message("");
+    result
+}
+
+static AB_AV1_VERSION: OnceLock<Option<String>> = OnceLock::new();
+
+// What ab-av1 --version printed, like ab-av1 0.9.4, asked once. None when ab-av1 can't be run
+pub fn ab_av1_version() -> Option<&'static str> {
+    AB_AV1_VERSION
+        .get_or_init(|| {
+            command_stdout(&ab_av1_bin(), &["--version"]).map(|output| output.trim().to_string())
+        })
+        .as_deref()
+}
+
+// ab-av1 is run as ab-av1.exe (or AB_AV1_BIN), found in the current folder or the path
+pub fn ab_av1_available() -> bool {
+    ab_av1_version().is_some()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,129 @@
+use super::*;
+
+// What ab-av1 0.7 prints: the predicted size of the full encode
+const V07_FOUND_STDOUT: &str = "\
+crf 28 VMAF 95.26 predicted full encode size 1.10 GiB (38%) taking 12 minutes
+";
+const V07_NO_CRF_STDERR: &str = "\
+- crf 10 VMAF 94.10 (91%)
+- crf 5 VMAF 94.62 (118%)
+Error: Failed to find a suitable crf
+";
+const V07_FAILED_STDERR: &str = "\
+Error: ffmpeg encode exit code 1
+Unknown encoder 'hevc_qsv'
+";
+
+// What ab-av1 0.9 prints: the tried CRFs, the predicted size of the video stream, a fractional CRF
+// with --crf-increment below 1 and colors on a terminal
+const V09_FOUND_STDOUT: &str = "\
+- crf 19 VMAF 97.24 (76%) (cache)
+\x1b[1m\x1b[32mcrf 21.5\x1b[0m VMAF 97.15 predicted video stream size 6.60 GiB (72%) taking 21 minutes
+
+Encode with: ab-av1 encode -e hevc_nvenc -i \"file.mkv\" --crf 21.5 --preset p7
+";
+const V09_NO_CRF_STDERR: &str = "\
+\x1b[2m[00:01:10]\x1b[0m sample 5/5 crf 5 VMAF 93.80 (121%)
+Error: Failed to find a suitable crf
+";
+const V09_FAILED_STDERR: &str = "\
+Error: ffprobe: Invalid data found when processing input
+";
+
+#[test]
+fn found_with_ab_av1_0_7() {
+    assert_eq!(
+        ab_av1_outcome(true, V07_FOUND_STDOUT, ""),
+        AbAv1Outcome::Found(AbAv1Result {
+            crf: 28.0,
+            score: Some(95.26),
+            predicted: Some(PredictedSize {
+                size_mb: 1.10 * 1024.0,
+                percent: 38.0,
+            }),
+        })
+    );
+}
+
+#[test]
+fn found_with_ab_av1_0_9() {
+    assert_eq!(
+        ab_av1_outcome(true, V09_FOUND_STDOUT, ""),
+        AbAv1Outcome::Found(AbAv1Result {
+            crf: 21.5,
+            score: Some(97.15),
+            predicted: Some(PredictedSize {
+                size_mb: 6.60 * 1024.0,
+                percent: 72.0,
+            }),
+        })
+    );
+}
+
+#[test]
+fn found_from_the_encode_command_without_a_result_line() {
+    let stdout = "Encode with: ab-av1 encode -e libx265 -i \"file.mkv\" --crf 24 --preset slow\n";
+    assert_eq!(
+        ab_av1_outcome(true, stdout, ""),
+        AbAv1Outcome::Found(AbAv1Result {
+            crf: 24.0,
+            score: None,
+            predicted: None,
+        })
+    );
+}
+
+#[test]
+fn no_suitable_crf_with_both_versions() {
+    for stderr in [V07_NO_CRF_STDERR, V09_NO_CRF_STDERR] {
+        assert_eq!(
+            ab_av1_outcome(false, "", stderr),
+            AbAv1Outcome::NoSuitableCrf
+        );
+    }
+}
+
+#[test]
+fn failed_with_both_versions() {
+    for stderr in [V07_FAILED_STDERR, V09_FAILED_STDERR] {
+        assert_eq!(ab_av1_outcome(false, "", stderr), AbAv1Outcome::Failed);
+    }
+    // a result in the stdout of a search that failed is not taken
+    assert_eq!(
+        ab_av1_outcome(false, V09_FOUND_STDOUT, V09_FAILED_STDERR),
+        AbAv1Outcome::Failed
+    );
+}
+
+#[test]
+fn a_success_without_a_crf_failed() {
+    for stdout in ["", "\n", "- crf 19 VMAF 97.24 (76%) (cache)\n"] {
+        assert_eq!(ab_av1_outcome(true, stdout, ""), AbAv1Outcome::Failed);
+    }
+}
+
+#[test]
+fn the_tried_crfs_are_not_the_result() {
+    let result = parse_ab_av1_result(V09_FOUND_STDOUT).unwrap();
+    assert_eq!(result.crf, 21.5);
+    assert_eq!(
+        parse_ab_av1_result("- crf 19 VMAF 97.24 (76%) (cache)\n"),
+        None
+    );
+}
+
+#[test]
+fn xpsnr_is_the_score_of_xpsnr_searches() {
+    let result =
+        parse_ab_av1_result("crf 30 XPSNR 42.5 predicted video stream size 800 MiB (40%)\n")
+            .unwrap();
+    assert_eq!(result.crf, 30.0);
+    assert_eq!(result.score, Some(42.5));
+    assert_eq!(
+        result.predicted,
+        Some(PredictedSize {
+            size_mb: 800.0,
+            percent: 40.0,
+        })
+    );
+}
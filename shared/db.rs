@@ -0,0 +1,373 @@
+// The sqlite database behind db(): opening it with its schema and the settings. The tables have
+// their own files: video_info.rs, db_updates.rs, db_queue.rs, db_search.rs and trash.rs
+
+use crate::*;
+
+use rusqlite::OptionalExtension;
+use std::time::Instant;
+
+// Result of import_db
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportCounts {
+    pub inserted: u64,
+    pub updated: u64,
+    pub invalid: u64,
+}
+
+static DB: OnceLock<Db> = OnceLock::new();
+
+// The sqlite database, opened once and shared by every thread through db()
+// WAL mode lets other processes read while we write, busy_timeout waits for a lock instead of failing with "database is locked"
+pub struct Db {
+    pub(crate) conn: Mutex<Connection>,
+    path: String,
+}
+
+impl Db {
+    pub fn open(path: &str) -> Result<Db> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(Duration::from_secs(30))?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS video_info (
+                    id INTEGER PRIMARY KEY,
+                    filename TEXT NOT NULL,
+                    filepath TEXT NOT NULL,
+                    width INTEGER NOT NULL,
+                    height INTEGER NOT NULL,
+                    duration REAL NOT NULL,
+                    pixel_format TEXT NOT NULL,
+                    display_aspect_ratio TEXT NOT NULL,
+                    sample_aspect_ratio TEXT NOT NULL,
+                    format TEXT NOT NULL,
+                    size BIGINT NOT NULL,
+                    folder_size BIGINT NOT NULL,
+                    bitrate BIGINT NOT NULL,
+                    codec TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    audio_codec TEXT NOT NULL,
+                    audio_bitrate BIGINT NOT NULL,
+                    hash TEXT NOT NULL
+                  )",
+            params![],
+        )?;
+        // columns added later, older databases get them when opened
+        add_missing_columns(
+            &conn,
+            "video_info",
+            &[
+                ("predicted_size", "REAL NOT NULL DEFAULT 0"),
+                ("predicted_percent", "REAL NOT NULL DEFAULT 0"),
+                ("verified_vmaf", "REAL NOT NULL DEFAULT 0"),
+                ("dynamic_hdr", "TEXT NOT NULL DEFAULT ''"),
+                ("chunked_vmaf", "REAL NOT NULL DEFAULT 0"),
+                ("chunked_stats", "TEXT NOT NULL DEFAULT ''"),
+                ("screenshots", "TEXT NOT NULL DEFAULT ''"),
+                ("vfr", "INTEGER NOT NULL DEFAULT 0"),
+                ("content_tune", "TEXT NOT NULL DEFAULT ''"),
+                ("fallback_encoder", "TEXT NOT NULL DEFAULT ''"),
+                // the --film-grain level of the encode, 0 without grain synthesis
+                ("film_grain", "INTEGER NOT NULL DEFAULT 0"),
+                // the counted frames and the size and modification time (ms) of the file then
+                ("frame_count", "REAL NOT NULL DEFAULT 0"),
+                ("frame_count_size", "INTEGER NOT NULL DEFAULT 0"),
+                ("frame_count_mtime", "INTEGER NOT NULL DEFAULT 0"),
+                // the --verify-library check: ok, corrupt or suspect, when, the first error line
+                // of ffmpeg and the size and modification time (ms) of the file then
+                ("health", "TEXT NOT NULL DEFAULT ''"),
+                ("health_checked_at", "TEXT NOT NULL DEFAULT ''"),
+                ("health_error", "TEXT NOT NULL DEFAULT ''"),
+                ("health_size", "INTEGER NOT NULL DEFAULT 0"),
+                ("health_mtime", "INTEGER NOT NULL DEFAULT 0"),
+                // the --vmaf of the last encode and the target it used, lower for a heavily
+                // compressed source, see adapt_vmaf_target
+                ("requested_vmaf", "REAL NOT NULL DEFAULT 0"),
+                ("effective_vmaf", "REAL NOT NULL DEFAULT 0"),
+                // the tags of the container, see MediaTags
+                ("title", "TEXT NOT NULL DEFAULT ''"),
+                ("show", "TEXT NOT NULL DEFAULT ''"),
+                ("season", "TEXT NOT NULL DEFAULT ''"),
+                ("episode", "TEXT NOT NULL DEFAULT ''"),
+                ("date", "TEXT NOT NULL DEFAULT ''"),
+                // the size of the video of the last --max-resolution encode
+                ("output_width", "INTEGER NOT NULL DEFAULT 0"),
+                ("output_height", "INTEGER NOT NULL DEFAULT 0"),
+            ],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS db_queue (
+                    id INTEGER PRIMARY KEY,
+                    input_path TEXT NOT NULL,
+                    output_path TEXT NOT NULL,
+                    encoder TEXT NOT NULL,
+                    preset TEXT NOT NULL,
+                    vmaf_target TEXT NOT NULL,
+                    vmaf_threads TEXT NOT NULL
+                  )",
+            params![],
+        )?;
+        add_missing_columns(
+            &conn,
+            "db_queue",
+            &[
+                ("priority", "INTEGER NOT NULL DEFAULT 0"),
+                ("sort_order", "INTEGER NOT NULL DEFAULT 0"),
+                ("not_before", "TEXT NOT NULL DEFAULT ''"),
+                ("only_between", "TEXT NOT NULL DEFAULT ''"),
+                ("state", "TEXT NOT NULL DEFAULT 'queued'"),
+                ("task_id", "TEXT NOT NULL DEFAULT ''"),
+            ],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tasks (
+                    id TEXT PRIMARY KEY,
+                    created_at TEXT NOT NULL,
+                    state TEXT NOT NULL,
+                    input_path TEXT NOT NULL,
+                    output_path TEXT NOT NULL,
+                    encoder TEXT NOT NULL,
+                    vmaf_target INTEGER NOT NULL
+                  )",
+            params![],
+        )?;
+        add_missing_columns(
+            &conn,
+            "tasks",
+            &[
+                ("started_at", "TEXT NOT NULL DEFAULT ''"),
+                ("finished_at", "TEXT NOT NULL DEFAULT ''"),
+                ("output_file", "TEXT NOT NULL DEFAULT ''"),
+                ("output_size", "INTEGER NOT NULL DEFAULT 0"),
+                ("output_mtime", "INTEGER NOT NULL DEFAULT 0"),
+            ],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS renditions (
+                    id INTEGER PRIMARY KEY,
+                    filepath TEXT NOT NULL,
+                    rendition TEXT NOT NULL,
+                    height INTEGER NOT NULL,
+                    output_path TEXT NOT NULL,
+                    crf TEXT NOT NULL,
+                    vmaf INTEGER NOT NULL,
+                    bitrate INTEGER NOT NULL,
+                    size BIGINT NOT NULL,
+                    task_id TEXT NOT NULL
+                  )",
+            params![],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS stats (
+                    id INTEGER PRIMARY KEY,
+                    filepath TEXT NOT NULL,
+                    output_path TEXT NOT NULL,
+                    encoder TEXT NOT NULL,
+                    input_size BIGINT NOT NULL,
+                    output_size BIGINT NOT NULL,
+                    duration REAL NOT NULL,
+                    vmaf REAL NOT NULL,
+                    finished_at TEXT NOT NULL
+                  )",
+            params![],
+        )?;
+        // the cost of the encode, see encode_cost.rs, 0 and NULL for the ones recorded before
+        add_missing_columns(
+            &conn,
+            "stats",
+            &[
+                ("preset", "TEXT NOT NULL DEFAULT ''"),
+                ("wall_seconds", "REAL NOT NULL DEFAULT 0"),
+                ("cpu_seconds", "REAL"),
+                ("average_fps", "REAL NOT NULL DEFAULT 0"),
+                ("gpu_utilization", "REAL"),
+            ],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                  )",
+            params![],
+        )?;
+        // the inputs of the running pipelines, see run_lock.rs
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS locks (
+                    id INTEGER PRIMARY KEY,
+                    path TEXT NOT NULL,
+                    run_id TEXT NOT NULL,
+                    task_id TEXT NOT NULL,
+                    pid INTEGER NOT NULL,
+                    locked_at INTEGER NOT NULL,
+                    heartbeat_at INTEGER NOT NULL
+                  )",
+            params![],
+        )?;
+        // the samples of --estimate by file, encoder and preset, see estimate.rs
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS estimates (
+                    id INTEGER PRIMARY KEY,
+                    filepath TEXT NOT NULL,
+                    encoder TEXT NOT NULL,
+                    preset TEXT NOT NULL,
+                    params TEXT NOT NULL,
+                    crf REAL NOT NULL,
+                    vmaf REAL,
+                    input_size BIGINT NOT NULL,
+                    estimated_size BIGINT NOT NULL,
+                    savings_percent REAL NOT NULL,
+                    encode_fps REAL NOT NULL,
+                    estimated_seconds REAL NOT NULL,
+                    estimated_at TEXT NOT NULL,
+                    file_size INTEGER NOT NULL,
+                    file_mtime INTEGER NOT NULL
+                  )",
+            params![],
+        )?;
+        // the journals of the batches and their files, see session.rs
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                    id TEXT PRIMARY KEY,
+                    task_id TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL,
+                    state TEXT NOT NULL,
+                    input_path TEXT NOT NULL,
+                    elapsed_s INTEGER NOT NULL,
+                    args TEXT NOT NULL
+                  )",
+            params![],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS session_files (
+                    session_id TEXT NOT NULL,
+                    position INTEGER NOT NULL,
+                    path TEXT NOT NULL,
+                    frames INTEGER NOT NULL,
+                    state TEXT NOT NULL,
+                    PRIMARY KEY (session_id, position)
+                  )",
+            params![],
+        )?;
+        // the rows the scans removed from video_info with its columns, see trash.rs
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS video_info_trash (
+                    trash_id INTEGER PRIMARY KEY,
+                    deleted_at TEXT NOT NULL,
+                    reason TEXT NOT NULL
+                  )",
+            params![],
+        )?;
+        maintain_trash(&conn)?;
+        create_indexes(&conn)?;
+        Ok(Db {
+            conn: Mutex::new(conn),
+            path: path.to_string(),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // A setting of the web server, like the webhook, None when it was never set
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT value FROM settings WHERE key=?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    // Store value for key, None removes it
+    pub fn set_setting(&self, key: &str, value: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        match value {
+            Some(value) => conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+                params![key, value],
+            )?,
+            None => conn.execute("DELETE FROM settings WHERE key=?1", params![key])?,
+        };
+        Ok(())
+    }
+}
+
+// Add the columns of a table that are missing, in a database created by an older version
+fn add_missing_columns(conn: &Connection, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+    for (column, definition) in columns {
+        let exists = conn
+            .prepare(&format!(
+                "SELECT 1 FROM pragma_table_info('{}') WHERE name=?1",
+                table
+            ))?
+            .exists(params![column])?;
+        if !exists {
+            conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                params![],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// The indexes of the lookups by path, status, codec and hash, created with the database or when
+// an older one is opened
+pub(crate) const INDEXES: [(&str, &str); 8] = [
+    ("idx_video_info_filepath", "video_info(filepath)"),
+    // the prefix search of Db::search
+    (
+        "idx_video_info_filepath_nocase",
+        "video_info(filepath COLLATE NOCASE)",
+    ),
+    ("idx_video_info_filename", "video_info(filename)"),
+    ("idx_video_info_status", "video_info(status)"),
+    ("idx_video_info_codec", "video_info(codec)"),
+    ("idx_video_info_hash", "video_info(hash)"),
+    // the title= filter of the web server and the titles of the reports
+    (
+        "idx_video_info_title_nocase",
+        "video_info(title COLLATE NOCASE)",
+    ),
+    (
+        "idx_video_info_show_nocase",
+        "video_info(show COLLATE NOCASE)",
+    ),
+];
+
+// Create the missing INDEXES, returns the names of the ones created
+pub(crate) fn create_indexes(conn: &Connection) -> Result<Vec<&'static str>> {
+    let mut created = Vec::new();
+    for (name, on) in INDEXES {
+        let exists = conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type='index' AND name=?1")?
+            .exists(params![name])?;
+        if !exists {
+            let started = Instant::now();
+            conn.execute(&format!("CREATE INDEX {} ON {}", name, on), params![])?;
+            info!("Created index {} in {:?}", name, started.elapsed());
+            created.push(name);
+        }
+    }
+    Ok(created)
+}
+
+// Open the database at path for db(), call once at startup before the first db access
+pub fn init_db(path: &str) -> Result<()> {
+    let opened = Db::open(path)?;
+    if DB.set(opened).is_err() {
+        warn!("Database already opened, ignoring --db-path {}", path);
+    }
+    Ok(())
+}
+
+// The shared database, data.db in the working directory when init_db was not called
+pub fn db() -> &'static Db {
+    DB.get_or_init(|| Db::open("data.db").expect("failed to open data.db"))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,117 @@
+use super::*;
+
+const THREADS: usize = 8;
+const ROWS_PER_THREAD: usize = 50;
+
+fn pool() -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(THREADS)
+        .build()
+        .unwrap()
+}
+
+// The filepath of every row
+fn filepaths(db: &Db) -> std::collections::HashSet<String> {
+    db.get_all().unwrap().into_iter().map(|row| row.2).collect()
+}
+
+#[test]
+fn the_database_is_in_wal_mode() {
+    let db = temp_db("wal");
+    let conn = db.conn.lock().unwrap();
+    let mode: String = conn
+        .query_row("PRAGMA journal_mode", params![], |row| row.get(0))
+        .unwrap();
+    assert_eq!(mode, "wal");
+}
+
+#[test]
+fn rayon_threads_insert_through_the_shared_connection() {
+    let db = temp_db("shared-inserts");
+    let results: Vec<Result<()>> = pool().install(|| {
+        (0..THREADS * ROWS_PER_THREAD)
+            .into_par_iter()
+            .map(|index| {
+                let filepath = format!("/media/{}.mkv", index);
+                db.insert_video_info(&video_info(&filepath))?;
+                db.video_info_exists(&filepath).map(|_| ())
+            })
+            .collect()
+    });
+    for result in results {
+        result.unwrap();
+    }
+    assert_eq!(filepaths(&db).len(), THREADS * ROWS_PER_THREAD);
+}
+
+#[test]
+fn connections_of_their_own_insert_without_database_is_locked() {
+    // a connection per thread, like the cli and the web server writing the same file
+    let path = temp_path("own-connections.db");
+    let path = path.to_str().unwrap().to_string();
+    Db::open(&path).unwrap();
+    let results: Vec<Result<()>> = pool().install(|| {
+        (0..THREADS)
+            .into_par_iter()
+            .map(|thread| {
+                let db = Db::open(&path)?;
+                for index in 0..ROWS_PER_THREAD {
+                    let filepath = format!("/media/{}-{}.mkv", thread, index);
+                    db.insert_video_info(&video_info(&filepath))?;
+                    db.get_all()?;
+                }
+                Ok(())
+            })
+            .collect()
+    });
+    for result in results {
+        // a "database is locked" error fails here
+        result.unwrap();
+    }
+    let expected: std::collections::HashSet<String> = (0..THREADS)
+        .flat_map(|thread| {
+            (0..ROWS_PER_THREAD).map(move |index| format!("/media/{}-{}.mkv", thread, index))
+        })
+        .collect();
+    assert_eq!(filepaths(&Db::open(&path).unwrap()), expected);
+}
+
+#[test]
+fn episodes_of_the_same_name_are_told_apart_by_their_path() {
+    let db = temp_db("same-name");
+    db.insert_video_info(&video_info("/media/TV/Show/Season 01/E01.mkv"))
+        .unwrap();
+    assert!(db
+        .video_info_exists("/media/TV/Show/Season 01/E01.mkv")
+        .unwrap());
+    assert!(!db
+        .video_info_exists("/media/TV/Show/Season 02/E01.mkv")
+        .unwrap());
+
+    db.insert_video_info(&video_info("/media/TV/Show/Season 02/E01.mkv"))
+        .unwrap();
+    db.trash_by_filepath("/media/TV/Show/Season 01/E01.mkv", "not_found")
+        .unwrap();
+    assert_eq!(
+        filepaths(&db),
+        ["/media/TV/Show/Season 02/E01.mkv".to_string()].into()
+    );
+}
+
+#[test]
+fn the_requested_and_the_effective_vmaf_target_are_stored() {
+    let db = temp_db("vmaf-targets");
+    db.insert_video_info(&video_info("/media/web-rip.mkv"))
+        .unwrap();
+    db.update_vmaf_targets("/media/web-rip.mkv", 97.0, 95.0)
+        .unwrap();
+    let conn = db.conn.lock().unwrap();
+    let targets: (f32, f32) = conn
+        .query_row(
+            "SELECT requested_vmaf, effective_vmaf FROM video_info WHERE filepath=?1",
+            params!["/media/web-rip.mkv"],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(targets, (97.0, 95.0));
+}
@@ -0,0 +1,140 @@
+// The db_queue table, the files waiting for the web to encode them in order
+
+use crate::*;
+
+// One row of the db_queue table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub id: i64,
+    pub input_path: String,
+    pub output_path: String,
+    pub encoder: String,
+    pub preset: String,
+    pub vmaf_target: String,
+    pub vmaf_threads: String,
+    // higher runs first, sort_order orders the entries of the same priority
+    pub priority: i32,
+    pub sort_order: i64,
+    // RFC 3339 UTC, empty when the entry can start right away
+    pub not_before: String,
+    // daily local window like 23:00-07:00, empty for any time
+    pub only_between: String,
+    // queued or running
+    pub state: String,
+    // the task of a running entry
+    pub task_id: String,
+}
+
+impl QueueEntry {
+    fn from_row(row: &rusqlite::Row) -> Result<QueueEntry> {
+        Ok(QueueEntry {
+            id: row.get(0)?,
+            input_path: row.get(1)?,
+            output_path: row.get(2)?,
+            encoder: row.get(3)?,
+            preset: row.get(4)?,
+            vmaf_target: row.get(5)?,
+            vmaf_threads: row.get(6)?,
+            priority: row.get(7)?,
+            sort_order: row.get(8)?,
+            not_before: row.get(9)?,
+            only_between: row.get(10)?,
+            state: row.get(11)?,
+            task_id: row.get(12)?,
+        })
+    }
+}
+
+const QUEUE_COLUMNS: &str = "id, input_path, output_path, encoder, preset, vmaf_target, vmaf_threads, priority, sort_order, not_before, only_between, state, task_id";
+
+impl Db {
+    // Add an entry at the end of the queue, returns its id
+    #[allow(clippy::too_many_arguments)]
+    pub fn queue_add(
+        &self,
+        input_path: &str,
+        output_path: &str,
+        encoder: &str,
+        preset: &str,
+        vmaf_target: &str,
+        vmaf_threads: &str,
+        priority: i32,
+        not_before: &str,
+        only_between: &str,
+    ) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO db_queue (input_path, output_path, encoder, preset, vmaf_target, vmaf_threads, priority, sort_order, not_before, only_between)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM db_queue), ?8, ?9)",
+            params![input_path, output_path, encoder, preset, vmaf_target, vmaf_threads, priority, not_before, only_between]
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn queue_remove(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM db_queue WHERE id=?1", params![id])?;
+        Ok(())
+    }
+
+    // Highest priority first, then by sort_order
+    pub fn queue_all(&self) -> Result<Vec<QueueEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM db_queue ORDER BY priority DESC, sort_order, id",
+            QUEUE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![], QueueEntry::from_row)?;
+        rows.collect()
+    }
+
+    // Returns false when there is no entry id
+    pub fn queue_set_priority(&self, id: i64, priority: i32) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE db_queue SET priority=?1 WHERE id=?2",
+            params![priority, id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    // The given ids come first in their order, the other entries keep their order after them
+    pub fn queue_reorder(&self, ids: &[i64]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let others: Vec<i64> = {
+            let mut stmt =
+                transaction.prepare("SELECT id FROM db_queue ORDER BY sort_order, id")?;
+            let rows = stmt.query_map(params![], |row| row.get(0))?;
+            rows.collect::<Result<Vec<i64>>>()?
+                .into_iter()
+                .filter(|id| !ids.contains(id))
+                .collect()
+        };
+        for (sort_order, id) in ids.iter().chain(others.iter()).enumerate() {
+            transaction.execute(
+                "UPDATE db_queue SET sort_order=?1 WHERE id=?2",
+                params![sort_order as i64 + 1, id],
+            )?;
+        }
+        transaction.commit()
+    }
+
+    pub fn queue_set_running(&self, id: i64, task_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE db_queue SET state='running', task_id=?1 WHERE id=?2",
+            params![task_id, id],
+        )?;
+        Ok(())
+    }
+
+    // Entries left running by a server that stopped are queued again
+    pub fn queue_requeue_running(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE db_queue SET state='queued', task_id='' WHERE state='running'",
+            params![],
+        )
+    }
+}
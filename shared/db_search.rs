@@ -0,0 +1,88 @@
+// Searching the library by words of the file name or by an absolute path
+
+use crate::*;
+
+// C:\, \\server\share and / paths, not the parts of a path the search also takes
+fn is_absolute_search(search: &str) -> bool {
+    let bytes = search.as_bytes();
+    search.starts_with('/')
+        || search.starts_with("\\\\")
+        || (bytes.len() > 1 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+// The words of a search, each has to be in the path, the title or the show of a row
+pub(crate) fn search_words(search: &str) -> Vec<String> {
+    search.split_whitespace().map(str::to_string).collect()
+}
+
+// The WHERE of a search for words parameters, bound from ?first on
+pub(crate) fn search_condition(words: usize, first: usize) -> String {
+    if words == 0 {
+        return "1".to_string();
+    }
+    (first..first + words)
+        .map(|i| {
+            format!(
+                "(filepath LIKE '%' || ?{0} || '%' OR title LIKE '%' || ?{0} || '%' OR show LIKE '%' || ?{0} || '%')",
+                i
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+// 0 for the file name search, with or without its extension, 1 when every word is in the path,
+// 2 when the tags matched some
+pub(crate) fn search_rank(filename: &str, filepath: &str, search: &str) -> u8 {
+    let search = search.trim().to_lowercase();
+    let stem = Path::new(filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if filename.to_lowercase() == search || stem == search {
+        return 0;
+    }
+    let filepath = filepath.to_lowercase();
+    if search
+        .split_whitespace()
+        .all(|word| filepath.contains(word))
+    {
+        1
+    } else {
+        2
+    }
+}
+
+impl Db {
+    // Rows whose filepath contains search. An absolute path is looked up as a prefix first, which
+    // the NOCASE filepath index answers without reading the table, the scan for every word of
+    // search in the path, the title or the show is the fallback, the exact file name first and the
+    // rows only the tags matched last. Both ignore the case of ASCII letters like LIKE
+    pub fn search(&self, search: &str) -> Result<Vec<VideoInfoRow>> {
+        let conn = self.conn.lock().unwrap();
+        if is_absolute_search(search) {
+            let mut stmt = conn.prepare(
+                "SELECT * FROM video_info WHERE filepath >= ?1 COLLATE NOCASE AND filepath < ?2 COLLATE NOCASE",
+            )?;
+            // every path that starts with search sorts below search followed by the last code point
+            let end = format!("{}\u{10FFFF}", search);
+            let rows: Vec<VideoInfoRow> = stmt
+                .query_map(params![search, end], Db::video_info_from_row)?
+                .collect::<Result<_>>()?;
+            if !rows.is_empty() {
+                return Ok(rows);
+            }
+        }
+        let words = search_words(search);
+        let mut stmt = conn.prepare(&format!(
+            "SELECT * FROM video_info WHERE {} ORDER BY id",
+            search_condition(words.len(), 1)
+        ))?;
+        let mut rows: Vec<VideoInfoRow> = stmt
+            .query_map(rusqlite::params_from_iter(&words), Db::video_info_from_row)?
+            .collect::<Result<_>>()?;
+        // stable, the rows of the same rank stay in id order
+        rows.sort_by_key(|row| search_rank(&row.1, &row.2, search));
+        Ok(rows)
+    }
+}
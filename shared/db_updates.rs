@@ -0,0 +1,201 @@
+// The single columns of a video_info row the scans and the encodes update, and the renditions
+
+use crate::*;
+
+use rusqlite::OptionalExtension;
+
+impl Db {
+    pub fn status(&self, filepath: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT status FROM video_info WHERE filepath=?1")?;
+        let mut rows = stmt.query(params![filepath])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn update_status(&self, filepath: &str, status: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET status=?1 WHERE filepath=?2",
+            params![status, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_hash(&self, filepath: &str, hash: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET hash=?1 WHERE filepath=?2",
+            params![hash, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_predicted_size(&self, filepath: &str, predicted: &PredictedSize) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET predicted_size=?1, predicted_percent=?2 WHERE filepath=?3",
+            params![predicted.size_mb, predicted.percent, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_dynamic_hdr(&self, filepath: &str, dynamic_hdr: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET dynamic_hdr=?1 WHERE filepath=?2",
+            params![dynamic_hdr, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_vfr(&self, filepath: &str, vfr: bool) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET vfr=?1 WHERE filepath=?2",
+            params![vfr, filepath],
+        )?;
+        Ok(())
+    }
+
+    // The stored frame count of filepath when it was counted at this size and modification time
+    pub fn cached_frame_count(&self, filepath: &str, size: i64, mtime: i64) -> Result<Option<f64>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT frame_count FROM video_info WHERE filepath=?1 AND frame_count > 0 AND frame_count_size=?2 AND frame_count_mtime=?3",
+            params![filepath, size, mtime],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    pub fn update_frame_count(
+        &self,
+        filepath: &str,
+        frame_count: f64,
+        size: i64,
+        mtime: i64,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET frame_count=?1, frame_count_size=?2, frame_count_mtime=?3 WHERE filepath=?4",
+            params![frame_count, size, mtime, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_content_tune(&self, filepath: &str, content_tune: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET content_tune=?1 WHERE filepath=?2",
+            params![content_tune, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_vmaf_targets(
+        &self,
+        filepath: &str,
+        requested: f32,
+        effective: f32,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET requested_vmaf=?1, effective_vmaf=?2 WHERE filepath=?3",
+            params![requested, effective, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_film_grain(&self, filepath: &str, film_grain: u8) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET film_grain=?1 WHERE filepath=?2",
+            params![film_grain, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_fallback_encoder(&self, filepath: &str, fallback_encoder: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET fallback_encoder=?1 WHERE filepath=?2",
+            params![fallback_encoder, filepath],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_chunked_stats(&self, filepath: &str, stats: &ChunkedVmafStats) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET chunked_vmaf=?1, chunked_stats=?2 WHERE filepath=?3",
+            params![
+                stats.pooled,
+                serde_json::to_string(stats).unwrap_or_default(),
+                filepath
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_screenshots(&self, filepath: &str, screenshots: &[String]) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET screenshots=?1 WHERE filepath=?2",
+            params![
+                serde_json::to_string(screenshots).unwrap_or_default(),
+                filepath
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_verified_vmaf(&self, filepath: &str, vmaf: f32) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET verified_vmaf=?1 WHERE filepath=?2",
+            params![vmaf, filepath],
+        )?;
+        Ok(())
+    }
+
+    // One row per encoded rendition of filepath, an output encoded again replaces its row
+    pub fn insert_rendition(
+        &self,
+        filepath: &str,
+        rendition: &Rendition,
+        output_path: &str,
+        crf: &str,
+        vmaf: f32,
+        size: u64,
+        task_id: &str,
+    ) -> Result<()> {
+        let bitrate = match rendition.quality {
+            RenditionQuality::Bitrate(kbps) => kbps,
+            RenditionQuality::Vmaf(_) => 0,
+        };
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM renditions WHERE output_path=?1",
+            params![output_path],
+        )?;
+        conn.execute(
+            "INSERT INTO renditions (filepath, rendition, height, output_path, crf, vmaf, bitrate, size, task_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                filepath,
+                rendition.label(),
+                rendition.height,
+                output_path,
+                crf,
+                vmaf,
+                bitrate,
+                size as i64,
+                task_id
+            ],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+// --max-resolution: a source larger than the box, like 1920x1080 or 720p, is scaled down into it
+// with its aspect ratio, never up, with lanczos, or with zscale for an HDR source that stays HDR so
+// its transfer survives. The samples of the CRF search are scored against the source scaled the
+// same way, a CRF found for the frames of the source would be wrong for the smaller output
+
+use crate::*;
+
+// The box a video has to fit in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaxResolution {
+    pub width: u64,
+    pub height: u64,
+}
+
+impl MaxResolution {
+    // The size of a width x height video scaled down into the box, even for the encoders, None
+    // when it fits already
+    pub fn fit(&self, width: u64, height: u64) -> Option<(u64, u64)> {
+        if width == 0 || height == 0 || (width <= self.width && height <= self.height) {
+            return None;
+        }
+        let scale = (self.width as f64 / width as f64).min(self.height as f64 / height as f64);
+        let even = |size: f64| ((size / 2.0).round() as u64 * 2).max(2);
+        Some((
+            even(width as f64 * scale).min(self.width),
+            even(height as f64 * scale).min(self.height),
+        ))
+    }
+}
+
+// 1920x1080, or a height like 1080p with the 16:9 width
+pub fn parse_max_resolution(s: &str) -> Result<MaxResolution, String> {
+    let s = s.trim().to_lowercase();
+    let (width, height) = match s.split_once('x') {
+        Some((width, height)) => (width.trim().parse::<u64>(), height.trim().parse::<u64>()),
+        None => match s.strip_suffix('p').map(|height| height.parse::<u64>()) {
+            // 480p is 854x480
+            Some(Ok(height)) => (
+                Ok((height.saturating_mul(16) / 9).div_ceil(2) * 2),
+                Ok(height),
+            ),
+            _ => return Err(format!("{} is not a resolution like 1920x1080 or 1080p", s)),
+        },
+    };
+    match (width, height) {
+        (Ok(width), Ok(height)) if width == 0 || height == 0 => {
+            Err(format!("{}: the width and height must be above 0", s))
+        }
+        (Ok(width), Ok(height)) if width % 2 != 0 || height % 2 != 0 => {
+            Err(format!("{}: the width and height must be even", s))
+        }
+        (Ok(width), Ok(height)) => Ok(MaxResolution { width, height }),
+        _ => Err(format!("{} is not a resolution like 1920x1080 or 1080p", s)),
+    }
+}
+
+pub(crate) fn max_resolution_validation(s: &str) -> Result<String, String> {
+    parse_max_resolution(s).map(|_| s.to_string())
+}
+
+// The scale filter of a file larger than --max-resolution and the size it scales to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Downscale {
+    pub filter: String,
+    pub width: u64,
+    pub height: u64,
+}
+
+impl Downscale {
+    // 1920x1080, in the output name
+    pub fn label(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+// The downscale of the video of file, None without --max-resolution or when it fits. The frames of
+// an HDR source --tonemap sdr converts are SDR by the time they are scaled
+pub fn downscale(file: &str, args: &Args) -> Option<Downscale> {
+    let max = parse_max_resolution(args.max_resolution.as_deref()?).ok()?;
+    let (source_width, source_height) = video_size(file)?;
+    let (width, height) = max.fit(source_width, source_height)?;
+    let hdr = probe_file(file).is_hdr() && tonemap_filter(file, &args.tonemap).is_none();
+    let zscale = ffmpeg_capabilities()
+        .filters
+        .iter()
+        .any(|filter| filter == "zscale");
+    let filter = if hdr && zscale {
+        format!("zscale=w={}:h={}:f=lanczos", width, height)
+    } else {
+        format!("scale={}:{}:flags=lanczos", width, height)
+    };
+    Some(Downscale {
+        filter,
+        width,
+        height,
+    })
+}
+
+// output with the size of downscale after the name of file, like Movie.1920x1080.libx265.vmaf97.crf21.mkv
+pub(crate) fn downscaled_output_name(file: &str, output: &str, downscale: &Downscale) -> String {
+    let (stem, _) = output_name_parts(file);
+    let path = Path::new(output);
+    let name = file_name_of(output);
+    match name.strip_prefix(&format!("{}.", stem)) {
+        Some(rest) => {
+            let name = format!("{}.{}.{}", stem, downscale.label(), rest);
+            match path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                Some(parent) => output_folder_path(&parent.display().to_string(), &name),
+                None => name,
+            }
+        }
+        None => output.to_string(),
+    }
+}
+
+impl Db {
+    // The size of the video of the last --max-resolution encode of filepath, the source's when it fit
+    pub fn update_output_resolution(&self, filepath: &str, width: u64, height: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET output_width=?1, output_height=?2 WHERE filepath=?3",
+            params![width as i64, height as i64, filepath],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,74 @@
+use super::*;
+
+fn max(width: u64, height: u64) -> MaxResolution {
+    MaxResolution { width, height }
+}
+
+#[test]
+fn the_shorthands_and_a_size_are_accepted() {
+    assert_eq!(parse_max_resolution("1080p"), Ok(max(1920, 1080)));
+    assert_eq!(parse_max_resolution("720p"), Ok(max(1280, 720)));
+    assert_eq!(parse_max_resolution("480p"), Ok(max(854, 480)));
+    assert_eq!(parse_max_resolution("1920x1080"), Ok(max(1920, 1080)));
+    assert_eq!(parse_max_resolution(" 1280X720 "), Ok(max(1280, 720)));
+}
+
+#[test]
+fn odd_empty_and_nonsense_sizes_are_rejected() {
+    assert_eq!(
+        parse_max_resolution("1921x1080"),
+        Err("1921x1080: the width and height must be even".to_string())
+    );
+    assert_eq!(
+        parse_max_resolution("0x0"),
+        Err("0x0: the width and height must be above 0".to_string())
+    );
+    for s in ["0p", "1080", "fullhd", "1920x", "x1080", "-1920x1080"] {
+        assert!(parse_max_resolution(s).is_err(), "{}", s);
+    }
+    assert!(max_resolution_validation("1921x1080").is_err());
+    assert_eq!(max_resolution_validation("720p"), Ok("720p".to_string()));
+}
+
+#[test]
+fn a_larger_source_is_scaled_down_with_its_aspect_ratio() {
+    let box_1080p = max(1920, 1080);
+    assert_eq!(box_1080p.fit(3840, 2160), Some((1920, 1080)));
+    // a 16:10 and a scope source
+    assert_eq!(box_1080p.fit(2560, 1600), Some((1728, 1080)));
+    assert_eq!(box_1080p.fit(3840, 1600), Some((1920, 800)));
+    // an odd size after the scale is rounded to an even one
+    assert_eq!(max(1280, 720).fit(1920, 1040), Some((1280, 694)));
+}
+
+#[test]
+fn a_source_that_fits_is_never_scaled_up() {
+    let box_1080p = max(1920, 1080);
+    for (width, height) in [(1920, 1080), (1280, 720), (1440, 1080), (720, 480)] {
+        assert_eq!(box_1080p.fit(width, height), None, "{}x{}", width, height);
+    }
+    // no size probed
+    assert_eq!(box_1080p.fit(0, 0), None);
+}
+
+#[test]
+fn the_output_name_has_the_new_size() {
+    let downscale = Downscale {
+        filter: "scale=1920:1080:flags=lanczos".to_string(),
+        width: 1920,
+        height: 1080,
+    };
+    assert_eq!(
+        downscaled_output_name(
+            "/media/Movie.mkv",
+            "Movie.libx265.vmaf97.crf21.mkv",
+            &downscale
+        ),
+        "Movie.1920x1080.libx265.vmaf97.crf21.mkv"
+    );
+    // an output not named after the file is left as it is
+    assert_eq!(
+        downscaled_output_name("/media/Movie.mkv", "other.mkv", &downscale),
+        "other.mkv"
+    );
+}
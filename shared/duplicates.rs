@@ -0,0 +1,213 @@
+// Duplicate files in video_info: the same content hash, or for files without one the same
+// resolution with duration and size within 1%. The copy with the highest bitrate is kept
+
+use crate::*;
+
+use sha2::{Digest, Sha256};
+use std::io::{Seek, SeekFrom};
+
+const HASH_BLOCK: u64 = 1024 * 1024;
+
+// How close duration and size have to be for files matched without a hash
+const SIMILAR_TOLERANCE: f64 = 0.01;
+
+// sha256 of the size and the first and last MiB of the file, the hash column of video_info.
+// Cheap on large files and, unlike the extradata hash, different for files encoded with the same
+// settings
+pub fn content_hash(file: &str) -> io::Result<String> {
+    let mut f = File::open(file)?;
+    let size = f.metadata()?.len();
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+
+    let mut block = Vec::with_capacity(HASH_BLOCK as usize);
+    (&mut f).take(HASH_BLOCK).read_to_end(&mut block)?;
+    hasher.update(&block);
+    // the last MiB, or what is left after the first one of a small file
+    let tail = size.saturating_sub(HASH_BLOCK).min(HASH_BLOCK);
+    if tail > 0 {
+        block.clear();
+        f.seek(SeekFrom::End(-(tail as i64)))?;
+        f.take(tail).read_to_end(&mut block)?;
+        hasher.update(&block);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// A hash written by content_hash, not a failed probe ("NaN") or an extradata hash of an older db
+// ("SHA256:...")
+fn is_content_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateFile {
+    pub filepath: String,
+    pub size: i64,
+    pub bitrate: i64,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateSet {
+    // "hash" or "similar" for the files matched by duration, resolution and size
+    pub matched_by: String,
+    // the content hash, or WIDTHxHEIGHT of the similar files
+    pub key: String,
+    // the highest bitrate copy first, it is the one kept
+    pub files: Vec<DuplicateFile>,
+    // the bytes of all copies but the kept one
+    pub wasted_bytes: i64,
+}
+
+impl DuplicateSet {
+    fn new(matched_by: &str, key: String, mut files: Vec<DuplicateFile>) -> DuplicateSet {
+        files.sort_by(|a, b| {
+            b.bitrate
+                .cmp(&a.bitrate)
+                .then(b.size.cmp(&a.size))
+                .then(a.filepath.cmp(&b.filepath))
+        });
+        let wasted_bytes = files.iter().skip(1).map(|file| file.size).sum();
+        DuplicateSet {
+            matched_by: matched_by.to_string(),
+            key,
+            files,
+            wasted_bytes,
+        }
+    }
+
+    pub fn kept(&self) -> &DuplicateFile {
+        &self.files[0]
+    }
+}
+
+fn within_tolerance(a: f64, b: f64) -> bool {
+    (a - b).abs() <= a.abs().max(b.abs()) * SIMILAR_TOLERANCE
+}
+
+fn is_similar(a: &VideoInfoRow, b: &VideoInfoRow) -> bool {
+    a.3 == b.3
+        && a.4 == b.4
+        && within_tolerance(a.5, b.5)
+        && within_tolerance(a.10 as f64, b.10 as f64)
+}
+
+fn duplicate_file(row: &VideoInfoRow) -> DuplicateFile {
+    DuplicateFile {
+        filepath: row.2.clone(),
+        size: row.10,
+        bitrate: row.12,
+        status: row.14.clone(),
+    }
+}
+
+// The duplicate sets of the library, the most wasted bytes first. Rows with an older extradata
+// hash get their content hash first, rows whose file can not be read are matched as similar
+pub fn find_duplicates() -> Result<Vec<DuplicateSet>> {
+    let mut by_hash: HashMap<String, Vec<VideoInfoRow>> = HashMap::new();
+    let mut unhashed = Vec::new();
+    for mut row in db().get_all()? {
+        if !is_content_hash(&row.17) {
+            match content_hash(&row.2) {
+                Ok(hash) => {
+                    db().update_hash(&row.2, &hash)?;
+                    row.17 = hash;
+                }
+                Err(e) => debug!("Could not hash {}: {}", row.2, e),
+            }
+        }
+        if is_content_hash(&row.17) {
+            by_hash.entry(row.17.clone()).or_default().push(row);
+        } else {
+            unhashed.push(row);
+        }
+    }
+
+    let mut sets: Vec<DuplicateSet> = by_hash
+        .into_iter()
+        .filter(|(_, rows)| rows.len() > 1)
+        .map(|(hash, rows)| {
+            DuplicateSet::new("hash", hash, rows.iter().map(duplicate_file).collect())
+        })
+        .collect();
+
+    // group the rest against the first file of each group
+    let mut groups: Vec<Vec<VideoInfoRow>> = Vec::new();
+    unhashed.sort_by(|a, b| a.10.cmp(&b.10));
+    for row in unhashed {
+        match groups.iter_mut().find(|group| is_similar(&group[0], &row)) {
+            Some(group) => group.push(row),
+            None => groups.push(vec![row]),
+        }
+    }
+    sets.extend(
+        groups
+            .into_iter()
+            .filter(|rows| rows.len() > 1)
+            .map(|rows| {
+                let key = format!("{}x{}", rows[0].3, rows[0].4);
+                DuplicateSet::new("similar", key, rows.iter().map(duplicate_file).collect())
+            }),
+    );
+
+    sets.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then(a.kept().filepath.cmp(&b.kept().filepath))
+    });
+    Ok(sets)
+}
+
+// Set the status of every copy but the kept one to duplicate, so they are not encoded. Returns the
+// number of rows changed
+pub fn mark_duplicates(sets: &[DuplicateSet]) -> Result<usize> {
+    let mut marked = 0;
+    for set in sets {
+        for file in set.files.iter().skip(1) {
+            if file.status != "duplicate" {
+                db().update_status(&file.filepath, "duplicate")?;
+                marked += 1;
+            }
+        }
+    }
+    Ok(marked)
+}
+
+// Log the sets for --db-duplicates
+pub fn log_duplicates(sets: &[DuplicateSet]) {
+    for set in sets {
+        info!(
+            "{} copies ({} {}), {} wasted:",
+            set.files.len(),
+            set.matched_by,
+            set.key,
+            format_size(set.wasted_bytes as u64)
+        );
+        for (index, file) in set.files.iter().enumerate() {
+            info!(
+                "  {} {} ({}, {} kb/s){}",
+                if index == 0 { "keep" } else { "dup " },
+                file.filepath,
+                format_size(file.size as u64),
+                file.bitrate / 1000,
+                if file.status == "duplicate" {
+                    " [duplicate]"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+    let wasted: i64 = sets.iter().map(|set| set.wasted_bytes).sum();
+    info!(
+        "{} duplicate sets, {} wasted",
+        sets.len(),
+        format_size(wasted as u64)
+    );
+}
@@ -0,0 +1,320 @@
+// What an encode costs: the wall time, the CPU time of its ffmpeg processes and, for the nvenc
+// encoders, the GPU utilization nvidia-smi reports. A side thread samples the running ffmpeg every
+// 5 seconds, the costs go to the stats row of the encode for `db compare-encoders` and
+// GET /stats/encoders
+
+use crate::*;
+
+const COST_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    // Clock ticks per second of the CPU times in /proc/<pid>/stat
+    #[cfg(target_os = "linux")]
+    static ref CLOCK_TICKS: f64 = Command::new("getconf")
+        .arg("CLK_TCK")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+        .filter(|ticks| *ticks > 0.0)
+        .unwrap_or(100.0);
+}
+
+// The cost of one encode, or of the scene encodes of a chunked one
+#[derive(Debug, Clone, Default)]
+pub struct EncodeCost {
+    pub wall_seconds: f64,
+    // None when the CPU time of a process could not be read
+    pub cpu_seconds: Option<f64>,
+    // the average of the GPU samples in percent, None without nvidia-smi or an nvenc encoder
+    pub gpu_utilization: Option<f64>,
+    gpu_samples: u32,
+}
+
+impl EncodeCost {
+    // Add the cost of another process of the same encode, like the first pass of a two-pass one
+    pub(crate) fn add(&mut self, other: &EncodeCost) {
+        self.wall_seconds += other.wall_seconds;
+        self.cpu_seconds = match (self.cpu_seconds, other.cpu_seconds) {
+            (Some(cpu), Some(other_cpu)) => Some(cpu + other_cpu),
+            (cpu, other_cpu) => cpu.or(other_cpu),
+        };
+        let samples = self.gpu_samples + other.gpu_samples;
+        if samples > 0 {
+            let sum = self.gpu_utilization.unwrap_or(0.0) * self.gpu_samples as f64
+                + other.gpu_utilization.unwrap_or(0.0) * other.gpu_samples as f64;
+            self.gpu_utilization = Some(sum / samples as f64);
+        }
+        self.gpu_samples = samples;
+    }
+}
+
+// The user and system CPU seconds of pid and its waited for children. An exited process that was
+// not waited for yet still has them
+#[cfg(target_os = "linux")]
+fn process_cpu_seconds(pid: u32) -> Option<f64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the name in parentheses can hold spaces, the fields after it start with the state
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    // utime, stime, cutime and cstime are the fields 14 to 17
+    let ticks = fields
+        .get(11..15)?
+        .iter()
+        .map(|field| field.parse::<f64>().ok())
+        .sum::<Option<f64>>()?;
+    Some(ticks / *CLOCK_TICKS)
+}
+
+// Whether pid has exited and waits to be waited for
+#[cfg(target_os = "linux")]
+fn process_exited(pid: u32) -> bool {
+    // a process that is gone was waited for already
+    let stat = match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => stat,
+        Err(_) => return true,
+    };
+    match stat.rsplit_once(')') {
+        Some((_, fields)) => fields.trim_start().starts_with('Z'),
+        None => true,
+    }
+}
+
+// The whole CPU time of pid once it has exited, ffmpeg closes its output a moment before
+#[cfg(target_os = "linux")]
+fn exited_cpu_seconds(pid: u32) -> Option<f64> {
+    for _ in 0..50 {
+        if process_exited(pid) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    process_cpu_seconds(pid)
+}
+
+// Elsewhere an exited process has no times left, the last sample is used
+#[cfg(not(target_os = "linux"))]
+fn exited_cpu_seconds(_pid: u32) -> Option<f64> {
+    None
+}
+
+// Windows has no /proc, the times come from Get-Process while the process runs. The ticks are 100
+// nanoseconds and print the same in every locale
+#[cfg(windows)]
+fn process_cpu_seconds(pid: u32) -> Option<f64> {
+    let output = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            &format!("(Get-Process -Id {}).TotalProcessorTime.Ticks", pid),
+        ])
+        .output()
+        .ok()?;
+    let ticks: f64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    Some(ticks / 10_000_000.0)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn process_cpu_seconds(_pid: u32) -> Option<f64> {
+    None
+}
+
+// The utilization in percent of the busiest GPU, None when nvidia-smi is missing or fails
+fn gpu_utilization() -> Option<f64> {
+    let output = Command::new("nvidia-smi")
+        .args([
+            "--query-gpu=utilization.gpu",
+            "--format=csv,noheader,nounits",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse::<f64>().ok())
+        .reduce(f64::max)
+}
+
+// The samples of a running ffmpeg, finish before the wait for the child
+pub(crate) struct CostSampler {
+    pid: u32,
+    started: Instant,
+    stop: mpsc::Sender<()>,
+    samples: thread::JoinHandle<(Option<f64>, Option<f64>, u32)>,
+}
+
+impl CostSampler {
+    // Start sampling child, the GPU as well for the nvenc encoders
+    pub(crate) fn start(child: &Child, encoder: &str) -> CostSampler {
+        let pid = child.id();
+        let mut sample_gpu = encoder.contains("nvenc");
+        let (stop, stopped) = mpsc::channel::<()>();
+        let samples = thread::spawn(move || {
+            let mut cpu_seconds = None;
+            let mut gpu_sum = 0.0;
+            let mut gpu_samples = 0;
+            while let Err(mpsc::RecvTimeoutError::Timeout) =
+                stopped.recv_timeout(COST_SAMPLE_INTERVAL)
+            {
+                cpu_seconds = process_cpu_seconds(pid).or(cpu_seconds);
+                if sample_gpu {
+                    match gpu_utilization() {
+                        Some(utilization) => {
+                            gpu_sum += utilization;
+                            gpu_samples += 1;
+                        }
+                        // no nvidia-smi, no GPU utilization for this encode
+                        None => {
+                            debug!("nvidia-smi gave no GPU utilization, not sampling the GPU");
+                            sample_gpu = false;
+                        }
+                    }
+                }
+            }
+            let gpu_utilization = (gpu_samples > 0).then(|| gpu_sum / gpu_samples as f64);
+            (cpu_seconds, gpu_utilization, gpu_samples)
+        });
+        CostSampler {
+            pid,
+            started: Instant::now(),
+            stop,
+            samples,
+        }
+    }
+
+    // Stop sampling once the output of the child is read, the CPU time is read once more if the
+    // platform still has it for an exited process
+    pub(crate) fn finish(self) -> EncodeCost {
+        let wall_seconds = self.started.elapsed().as_secs_f64();
+        let _ = self.stop.send(());
+        let (sampled_cpu, gpu_utilization, gpu_samples) =
+            self.samples.join().unwrap_or((None, None, 0));
+        EncodeCost {
+            wall_seconds,
+            cpu_seconds: exited_cpu_seconds(self.pid).or(sampled_cpu),
+            gpu_utilization,
+            gpu_samples,
+        }
+    }
+}
+
+// The encodes of an encoder and preset with a measured cost, and what they saved per hour
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderCost {
+    pub encoder: String,
+    pub preset: String,
+    pub files: i64,
+    pub saved_bytes: i64,
+    pub wall_hours: f64,
+    // of the encodes with a CPU time
+    pub cpu_hours: Option<f64>,
+    pub saved_bytes_per_wall_hour: Option<f64>,
+    pub saved_bytes_per_cpu_hour: Option<f64>,
+    pub average_fps: Option<f64>,
+    pub average_vmaf: Option<f64>,
+    pub average_gpu_utilization: Option<f64>,
+}
+
+impl Db {
+    // The costs of the encodes by encoder and preset, the ones without a wall time were recorded
+    // before the costs were and are left out
+    pub fn encoder_costs(&self) -> Result<Vec<EncoderCost>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT encoder, preset, COUNT(*),
+                COALESCE(SUM(CASE WHEN output_size > 0 THEN input_size - output_size END), 0),
+                SUM(wall_seconds),
+                SUM(cpu_seconds),
+                COALESCE(SUM(CASE WHEN output_size > 0 AND cpu_seconds IS NOT NULL
+                    THEN input_size - output_size END), 0),
+                AVG(CASE WHEN average_fps > 0 THEN average_fps END),
+                AVG(CASE WHEN vmaf > 0 THEN vmaf END),
+                AVG(gpu_utilization)
+             FROM stats WHERE wall_seconds > 0 GROUP BY 1, 2 ORDER BY 1, 2",
+        )?;
+        let rows = stmt.query_map(params![], |row| {
+            let saved_bytes: i64 = row.get(3)?;
+            let wall_seconds: f64 = row.get(4)?;
+            let cpu_seconds: Option<f64> = row.get(5)?;
+            let cpu_saved_bytes: i64 = row.get(6)?;
+            let per_hour = |saved: i64, seconds: f64| {
+                (seconds > 0.0).then(|| saved as f64 / (seconds / 3600.0))
+            };
+            Ok(EncoderCost {
+                encoder: row.get(0)?,
+                preset: row.get(1)?,
+                files: row.get(2)?,
+                saved_bytes,
+                wall_hours: wall_seconds / 3600.0,
+                cpu_hours: cpu_seconds.map(|seconds| seconds / 3600.0),
+                saved_bytes_per_wall_hour: per_hour(saved_bytes, wall_seconds),
+                saved_bytes_per_cpu_hour: cpu_seconds
+                    .and_then(|seconds| per_hour(cpu_saved_bytes, seconds)),
+                average_fps: row.get(7)?,
+                average_vmaf: row.get(8)?,
+                average_gpu_utilization: row.get(9)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+pub fn encoder_costs_json(costs: &[EncoderCost]) -> String {
+    serde_json::to_string_pretty(costs).unwrap_or_default()
+}
+
+// A saving per hour like 12.40 GB/h, negative when the encodes came out larger
+fn format_per_hour(bytes: Option<f64>) -> String {
+    match bytes {
+        Some(bytes) if bytes < 0.0 => format!("-{}/h", format_size(-bytes as u64)),
+        Some(bytes) => format!("{}/h", format_size(bytes as u64)),
+        None => "-".to_string(),
+    }
+}
+
+// The report of `db compare-encoders`, the most saved per CPU hour first
+pub fn print_encoder_costs(costs: &[EncoderCost]) {
+    if costs.is_empty() {
+        println!("No encodes with a recorded cost yet");
+        return;
+    }
+    let mut costs = costs.to_vec();
+    costs.sort_by(|a, b| {
+        b.saved_bytes_per_cpu_hour
+            .unwrap_or(f64::MIN)
+            .total_cmp(&a.saved_bytes_per_cpu_hour.unwrap_or(f64::MIN))
+    });
+    for cost in &costs {
+        let preset = if cost.preset.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", cost.preset)
+        };
+        println!("{}{}", cost.encoder.bold(), preset);
+        println!(
+            "  {} files, {} saved, {:.2} wall hours, {} CPU hours",
+            cost.files,
+            format_size(cost.saved_bytes.max(0) as u64),
+            cost.wall_hours,
+            cost.cpu_hours
+                .map_or("-".to_string(), |hours| format!("{:.2}", hours))
+        );
+        println!(
+            "  saved per CPU hour: {}, per wall hour: {}",
+            format_per_hour(cost.saved_bytes_per_cpu_hour),
+            format_per_hour(cost.saved_bytes_per_wall_hour)
+        );
+        let optional = |value: Option<f64>, unit: &str| {
+            value.map_or("-".to_string(), |value| format!("{:.1}{}", value, unit))
+        };
+        println!(
+            "  {} fps, VMAF {}, GPU {}",
+            optional(cost.average_fps, ""),
+            optional(cost.average_vmaf, ""),
+            optional(cost.average_gpu_utilization, "%")
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// The fps and output bitrate of the running encodes over time for the charts of the web UI: every
+// progress update of an encode adds a sample to its task, GET /tasks/<id>/samples?since=<t_ms>
+// returns the new ones. The samples of a finished encode go to --report-json, downsampled
+
+use crate::*;
+
+// The samples kept per task, 15 minutes at the default --progress-interval
+const MAX_TASK_SAMPLES: usize = 3600;
+// The tasks with samples in memory, the oldest one is evicted when another one starts
+const MAX_SAMPLED_TASKS: usize = 20;
+// The samples of an encode in --report-json
+const REPORT_SAMPLES: usize = 200;
+
+// A progress update of an encode. t_ms counts from the first sample of the task in
+// /tasks/<id>/samples and from the start of the encode in --report-json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncodeSample {
+    pub t_ms: u64,
+    pub frame: u64,
+    pub fps: u64,
+    pub out_bitrate_kbps: f64,
+    pub speed: f64,
+}
+
+struct TaskSamples {
+    task_id: String,
+    started: Instant,
+    samples: VecDeque<EncodeSample>,
+}
+
+// The samples of an encode in --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct EncodeSamples {
+    pub path: String,
+    pub output: String,
+    pub samples: Vec<EncodeSample>,
+}
+
+lazy_static! {
+    // The samples of the last MAX_SAMPLED_TASKS tasks, the oldest first
+    static ref WEB_TASK_SAMPLES_STATIC: Mutex<VecDeque<TaskSamples>> = Mutex::new(VecDeque::new());
+    // The samples of the last encode of each file, until the pipeline takes them
+    static ref FINISHED_ENCODE_SAMPLES: Mutex<HashMap<String, Vec<EncodeSample>>> =
+        Mutex::new(HashMap::new());
+}
+
+fn push_task_sample(task_id: &str, sample: EncodeSample) {
+    add_task_sample(&mut WEB_TASK_SAMPLES_STATIC.lock().unwrap(), task_id, sample);
+}
+
+// Add sample to the samples of task_id in tasks, t_ms is set from the first sample of the task
+fn add_task_sample(tasks: &mut VecDeque<TaskSamples>, task_id: &str, mut sample: EncodeSample) {
+    if !tasks.iter().any(|task| task.task_id == task_id) {
+        tasks.push_back(TaskSamples {
+            task_id: task_id.to_string(),
+            started: Instant::now(),
+            samples: VecDeque::new(),
+        });
+        while tasks.len() > MAX_SAMPLED_TASKS {
+            tasks.pop_front();
+        }
+    }
+    let task = tasks
+        .iter_mut()
+        .find(|task| task.task_id == task_id)
+        .unwrap();
+    sample.t_ms = task.started.elapsed().as_millis() as u64;
+    task.samples.push_back(sample);
+    while task.samples.len() > MAX_TASK_SAMPLES {
+        task.samples.pop_front();
+    }
+}
+
+// The samples of task_id after since, None when the task has none in memory
+pub fn task_samples(task_id: &str, since: Option<u64>) -> Option<Vec<EncodeSample>> {
+    samples_since(&WEB_TASK_SAMPLES_STATIC.lock().unwrap(), task_id, since)
+}
+
+fn samples_since(
+    tasks: &VecDeque<TaskSamples>,
+    task_id: &str,
+    since: Option<u64>,
+) -> Option<Vec<EncodeSample>> {
+    let task = tasks.iter().find(|task| task.task_id == task_id)?;
+    Some(
+        task.samples
+            .iter()
+            .filter(|sample| since.is_none_or(|since| sample.t_ms > since))
+            .cloned()
+            .collect(),
+    )
+}
+
+// At most max of samples, evenly spread from the first to the last
+pub(crate) fn downsample(samples: &[EncodeSample], max: usize) -> Vec<EncodeSample> {
+    if samples.len() <= max {
+        return samples.to_vec();
+    }
+    if max < 2 {
+        return samples.iter().take(max).cloned().collect();
+    }
+    (0..max)
+        .map(|i| samples[i * (samples.len() - 1) / (max - 1)].clone())
+        .collect()
+}
+
+// The samples of the last encode of file, downsampled for --report-json
+pub(crate) fn take_encode_samples(file: &str) -> Option<Vec<EncodeSample>> {
+    let samples = FINISHED_ENCODE_SAMPLES.lock().unwrap().remove(file)?;
+    Some(downsample(&samples, REPORT_SAMPLES))
+}
+
+// The samples of one encode, pushed to its task as they are taken
+pub(crate) struct EncodeSampler {
+    task_id: String,
+    started: Instant,
+    samples: Vec<EncodeSample>,
+}
+
+impl EncodeSampler {
+    pub(crate) fn new(task_id: &str) -> EncodeSampler {
+        EncodeSampler {
+            task_id: task_id.to_string(),
+            started: Instant::now(),
+            samples: Vec::new(),
+        }
+    }
+
+    pub(crate) fn sample(&mut self, frame: u64, fps: u64, out_bitrate_kbps: f64, speed: f64) {
+        let sample = EncodeSample {
+            t_ms: self.started.elapsed().as_millis() as u64,
+            frame,
+            fps,
+            out_bitrate_kbps,
+            speed,
+        };
+        // the cli has no task
+        if !self.task_id.is_empty() {
+            push_task_sample(&self.task_id, sample.clone());
+        }
+        self.samples.push(sample);
+    }
+
+    // The encode of file is done, its samples wait for take_encode_samples
+    pub(crate) fn finish(self, file: &str) {
+        FINISHED_ENCODE_SAMPLES
+            .lock()
+            .unwrap()
+            .insert(file.to_string(), self.samples);
+    }
+}
+
+#[cfg(test)]
+mod tests;
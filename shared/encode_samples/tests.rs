@@ -0,0 +1,108 @@
+use super::*;
+
+// A sample at t_ms of frame, 24 fps
+fn sample(t_ms: u64, frame: u64) -> EncodeSample {
+    EncodeSample {
+        t_ms,
+        frame,
+        fps: 24,
+        out_bitrate_kbps: 4000.0,
+        speed: 1.0,
+    }
+}
+
+// A task whose samples are one a second, from 0 to samples - 1 seconds
+fn task(task_id: &str, samples: u64) -> TaskSamples {
+    TaskSamples {
+        task_id: task_id.to_string(),
+        started: Instant::now(),
+        samples: (0..samples).map(|i| sample(i * 1000, i * 24)).collect(),
+    }
+}
+
+fn frames(samples: &[EncodeSample]) -> Vec<u64> {
+    samples.iter().map(|sample| sample.frame).collect()
+}
+
+#[test]
+fn since_returns_only_the_new_samples() {
+    let tasks = VecDeque::from([task("task-1", 5), task("task-2", 2)]);
+    assert_eq!(
+        frames(&samples_since(&tasks, "task-1", None).unwrap()),
+        [0, 24, 48, 72, 96]
+    );
+    // the samples after the last one the web UI has
+    assert_eq!(
+        frames(&samples_since(&tasks, "task-1", Some(2000)).unwrap()),
+        [72, 96]
+    );
+    assert_eq!(
+        frames(&samples_since(&tasks, "task-1", Some(2500)).unwrap()),
+        [72, 96]
+    );
+    assert!(samples_since(&tasks, "task-1", Some(4000))
+        .unwrap()
+        .is_empty());
+    assert_eq!(samples_since(&tasks, "task-3", None), None);
+}
+
+#[test]
+fn a_task_keeps_its_last_samples_and_the_oldest_task_is_evicted() {
+    let mut tasks = VecDeque::new();
+    for frame in 0..MAX_TASK_SAMPLES as u64 + 10 {
+        add_task_sample(&mut tasks, "task-0", sample(0, frame));
+    }
+    let samples = samples_since(&tasks, "task-0", None).unwrap();
+    assert_eq!(samples.len(), MAX_TASK_SAMPLES);
+    assert_eq!(samples[0].frame, 10);
+
+    for i in 1..MAX_SAMPLED_TASKS {
+        add_task_sample(&mut tasks, &format!("task-{}", i), sample(0, 0));
+    }
+    assert_eq!(tasks.len(), MAX_SAMPLED_TASKS);
+    // one more task, its samples are freed
+    add_task_sample(&mut tasks, "task-new", sample(0, 0));
+    assert_eq!(tasks.len(), MAX_SAMPLED_TASKS);
+    assert_eq!(samples_since(&tasks, "task-0", None), None);
+    assert!(samples_since(&tasks, "task-1", None).is_some());
+}
+
+#[test]
+fn downsampling_keeps_the_first_and_the_last_sample() {
+    let samples: Vec<EncodeSample> = (0..1000).map(|i| sample(i * 250, i)).collect();
+    let downsampled = downsample(&samples, REPORT_SAMPLES);
+    assert_eq!(downsampled.len(), REPORT_SAMPLES);
+    assert_eq!(downsampled[0], samples[0]);
+    assert_eq!(downsampled[REPORT_SAMPLES - 1], samples[999]);
+    // evenly spread, in order
+    let steps: Vec<u64> = downsampled
+        .windows(2)
+        .map(|pair| pair[1].frame - pair[0].frame)
+        .collect();
+    assert!(
+        steps.iter().all(|step| (4..=6).contains(step)),
+        "{:?}",
+        steps
+    );
+
+    // fewer samples are kept as they are
+    assert_eq!(downsample(&samples[..150], REPORT_SAMPLES), samples[..150]);
+    assert_eq!(frames(&downsample(&samples, 1)), [0]);
+    assert!(downsample(&samples, 0).is_empty());
+}
+
+#[test]
+fn a_finished_encode_goes_to_the_report_downsampled() {
+    // the cli has no task, its samples only go to the report
+    let mut sampler = EncodeSampler::new("");
+    for frame in 0..500 {
+        sampler.sample(frame, 24, 4000.0, 1.0);
+    }
+    sampler.finish("/media/sampled.mkv");
+
+    let samples = take_encode_samples("/media/sampled.mkv").unwrap();
+    assert_eq!(samples.len(), REPORT_SAMPLES);
+    assert_eq!(samples[REPORT_SAMPLES - 1].frame, 499);
+    // taken once
+    assert_eq!(take_encode_samples("/media/sampled.mkv"), None);
+}
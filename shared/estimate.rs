@@ -0,0 +1,353 @@
+// --estimate: three short samples of each pending file are encoded at the CRF the native search starts
+// from, their size is extrapolated to the whole file and the time to their fps. The estimates are
+// stored by encoder and preset: --min-estimated-savings skips the files that save too little, the
+// native CRF search starts from the VMAF the samples measured and the summary of the run compares
+// them with the encodes
+
+use crate::*;
+
+use rusqlite::OptionalExtension;
+
+// The samples of a file and their length, a file shorter than all of them is encoded whole
+const ESTIMATE_SAMPLES: usize = 3;
+const ESTIMATE_SAMPLE_SECONDS: f32 = 10.0;
+
+// The savings an encode of a file is expected to have
+#[derive(Debug, Clone, Serialize)]
+pub struct SavingsEstimate {
+    pub path: String,
+    pub encoder: String,
+    pub preset: String,
+    // the params of the encoder, the CRF search only starts from a VMAF measured with the same
+    pub params: String,
+    pub crf: f32,
+    // the pooled VMAF of the samples, None when it couldn't be measured
+    pub vmaf: Option<f32>,
+    pub input_size: u64,
+    pub estimated_size: u64,
+    pub savings_percent: f64,
+    // of the encodes of the samples, with the VMAF running next to them
+    pub encode_fps: f64,
+    pub estimated_seconds: f64,
+    pub estimated_at: String,
+}
+
+// An encode of the run with an estimate, for the summary and --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct EstimateCheck {
+    pub path: String,
+    pub estimated_size: u64,
+    pub actual_size: u64,
+    // how far the estimate was off, positive when the encode came out larger
+    pub size_error_percent: f64,
+    pub estimated_seconds: f64,
+    pub actual_seconds: f64,
+}
+
+// Start and end of the samples of a file of duration seconds, centered in equal parts of it
+fn estimate_samples(duration: f32) -> Vec<(f32, f32)> {
+    if duration <= ESTIMATE_SAMPLE_SECONDS * ESTIMATE_SAMPLES as f32 {
+        return vec![(0.0, duration)];
+    }
+    (0..ESTIMATE_SAMPLES)
+        .map(|sample| {
+            let center = duration * (sample as f32 + 0.5) / ESTIMATE_SAMPLES as f32;
+            let ss = center - ESTIMATE_SAMPLE_SECONDS / 2.0;
+            (ss, ss + ESTIMATE_SAMPLE_SECONDS)
+        })
+        .collect()
+}
+
+// The bytes of the streams other than the video, copied or re-encoded at about their size, from
+// the bitrates ffprobe or the mkv statistics tags have
+fn other_streams_bytes(file: &str, duration: f32) -> u64 {
+    let kbps: i64 = probe_file(file)
+        .streams
+        .iter()
+        .filter(|stream| stream["codec_type"].as_str() == Some("audio"))
+        .map(stream_bitrate_kbps)
+        .sum();
+    (kbps.max(0) as f64 * 1000.0 / 8.0 * duration as f64) as u64
+}
+
+// Encode the samples of file with the encoder of args and extrapolate them
+pub fn estimate_file(file: &str, args: &Args) -> Result<SavingsEstimate, String> {
+    let duration =
+        get_duration(file).ok_or_else(|| format!("Failed to get the duration of {}", file))?;
+    let input_size = fs::metadata(file)
+        .map_err(|e| format!("Failed to read {}: {}", file, e))?
+        .len();
+    let fps = get_fps(file);
+    let crf = NATIVE_START_CRF.min(args.max_crf.parse().unwrap_or(NATIVE_START_CRF));
+    let run_id = chunk_run_id(file);
+    let mut sample_bytes = 0;
+    let mut sample_seconds = 0.0;
+    let mut encode_seconds = 0.0;
+    let mut scores = Vec::new();
+    for (index, (ss, to)) in estimate_samples(duration).into_iter().enumerate() {
+        let stream = format!("estimate_{}_{}.nut", run_id, index);
+        let started = Instant::now();
+        let output = process_video_pipe_and_vmaf(
+            &file.to_string(),
+            args,
+            &crf,
+            &fps,
+            &format!("{:.3}", ss),
+            &format!("{:.3}", to),
+            &args.vmaf_pool,
+            &args.vmaf_threads,
+            &args.vmaf_subsample,
+            None,
+            Some(&stream),
+        );
+        let size = fs::metadata(&stream).map(|metadata| metadata.len());
+        let _ = fs::remove_file(&stream);
+        let output = output.map_err(|e| format!("Failed to execute ffmpeg: {}", e))?;
+        let size = match size {
+            Ok(size) if size > 0 && output.status.success() => size,
+            _ => {
+                return Err(format!(
+                    "Failed to encode the sample of {} from {:.3} to {:.3}",
+                    file, ss, to
+                ))
+            }
+        };
+        encode_seconds += started.elapsed().as_secs_f64();
+        sample_bytes += size;
+        sample_seconds += (to - ss) as f64;
+        scores.extend(parse_vmaf_score(&output));
+    }
+
+    let video_bytes = sample_bytes as f64 / sample_seconds * duration as f64;
+    let estimated_size = video_bytes as u64 + other_streams_bytes(file, duration);
+    let frames_per_second = fps.parse::<f64>().unwrap_or(0.0);
+    let encode_fps = if encode_seconds > 0.0 {
+        sample_seconds * frames_per_second / encode_seconds
+    } else {
+        0.0
+    };
+    let estimated_seconds = if encode_fps > 0.0 {
+        duration as f64 * frames_per_second / encode_fps
+    } else {
+        0.0
+    };
+    let (preset, params) = encoder_preset_params(args);
+    Ok(SavingsEstimate {
+        path: file.to_string(),
+        encoder: args.encoder.clone(),
+        preset: preset.to_string(),
+        params: params.to_string(),
+        crf,
+        vmaf: pool_vmaf_scores(&scores, &args.vmaf_pool),
+        input_size,
+        estimated_size,
+        savings_percent: (1.0 - estimated_size as f64 / input_size.max(1) as f64) * 100.0,
+        encode_fps,
+        estimated_seconds,
+        estimated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+    })
+}
+
+impl Db {
+    // Replace the estimate of the file, encoder and preset of estimate
+    pub fn store_estimate(&self, estimate: &SavingsEstimate) -> Result<()> {
+        let (size, mtime) = file_stamp_ms(&estimate.path);
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM estimates WHERE filepath=?1 AND encoder=?2 AND preset=?3",
+            params![estimate.path, estimate.encoder, estimate.preset],
+        )?;
+        conn.execute(
+            "INSERT INTO estimates (filepath, encoder, preset, params, crf, vmaf, input_size, estimated_size, savings_percent, encode_fps, estimated_seconds, estimated_at, file_size, file_mtime) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                estimate.path,
+                estimate.encoder,
+                estimate.preset,
+                estimate.params,
+                estimate.crf,
+                estimate.vmaf,
+                estimate.input_size as i64,
+                estimate.estimated_size as i64,
+                estimate.savings_percent,
+                estimate.encode_fps,
+                estimate.estimated_seconds,
+                estimate.estimated_at,
+                size,
+                mtime
+            ],
+        )?;
+        Ok(())
+    }
+
+    // The estimate of filepath with encoder and preset, None when there is none or the file changed
+    // since
+    pub fn estimate(
+        &self,
+        filepath: &str,
+        encoder: &str,
+        preset: &str,
+    ) -> Result<Option<SavingsEstimate>> {
+        let (size, mtime) = file_stamp_ms(filepath);
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT params, crf, vmaf, input_size, estimated_size, savings_percent, encode_fps, estimated_seconds, estimated_at FROM estimates WHERE filepath=?1 AND encoder=?2 AND preset=?3 AND file_size=?4 AND file_mtime=?5",
+        )?;
+        let mut rows = stmt.query_map(params![filepath, encoder, preset, size, mtime], |row| {
+            Ok(SavingsEstimate {
+                path: filepath.to_string(),
+                encoder: encoder.to_string(),
+                preset: preset.to_string(),
+                params: row.get(0)?,
+                crf: row.get(1)?,
+                vmaf: row.get(2)?,
+                input_size: row.get::<_, i64>(3)?.max(0) as u64,
+                estimated_size: row.get::<_, i64>(4)?.max(0) as u64,
+                savings_percent: row.get(5)?,
+                encode_fps: row.get(6)?,
+                estimated_seconds: row.get(7)?,
+                estimated_at: row.get(8)?,
+            })
+        })?;
+        rows.next().transpose()
+    }
+
+    // The output size and wall time of the last encode of filepath finished at or after since
+    // (RFC 3339), None when there is none
+    fn encode_since(&self, filepath: &str, since: &str) -> Result<Option<(i64, f64)>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT output_size, wall_seconds FROM stats WHERE filepath=?1 AND finished_at>=?2 ORDER BY id DESC LIMIT 1",
+            params![filepath, since],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+    }
+}
+
+// The stored estimate of file for the encoder and preset of args
+pub(crate) fn stored_estimate(file: &str, args: &Args) -> Option<SavingsEstimate> {
+    let (preset, _) = encoder_preset_params(args);
+    match db().estimate(file, &args.encoder, preset) {
+        Ok(estimate) => estimate,
+        Err(e) => {
+            warn!("Failed to read the estimate of {}: {}", file, e);
+            None
+        }
+    }
+}
+
+// The CRF and VMAF the samples of --estimate measured, for the native CRF search to start from.
+// Only with the same params, a changed encode would measure another VMAF
+pub(crate) fn estimate_warm_start(file: &str, args: &Args) -> Option<(f32, f32)> {
+    let estimate = stored_estimate(file, args)?;
+    let (_, params) = encoder_preset_params(args);
+    if estimate.params != params {
+        return None;
+    }
+    Some((estimate.crf, estimate.vmaf?))
+}
+
+// The estimate of file against its encode finished since started (RFC 3339), None without either
+pub(crate) fn check_estimate(file: &str, args: &Args, started: &str) -> Option<EstimateCheck> {
+    let estimate = stored_estimate(file, args)?;
+    let (actual_size, actual_seconds) = db().encode_since(file, started).ok().flatten()?;
+    if actual_size <= 0 {
+        return None;
+    }
+    Some(EstimateCheck {
+        path: file.to_string(),
+        estimated_size: estimate.estimated_size,
+        actual_size: actual_size as u64,
+        size_error_percent: (actual_size as f64 / estimate.estimated_size.max(1) as f64 - 1.0)
+            * 100.0,
+        estimated_seconds: estimate.estimated_seconds,
+        actual_seconds,
+    })
+}
+
+// Estimate files one after the other and store the estimates, a file that fails is left out
+pub fn estimate_files(files: &[String], args: &Args) -> Vec<SavingsEstimate> {
+    let bar = progress_bar(files.len() as u64);
+    let style = "[estm][{elapsed_precise}][{wide_bar:.cyan/blue}] {pos:>7}/{len:7} [{msg}]";
+    bar.set_style(ProgressStyle::default_bar().template(style).unwrap());
+    let mut estimates = Vec::new();
+    for file in files {
+        if shutdown_requested() {
+            break;
+        }
+        bar.set_message(file_name_of(file));
+        match estimate_file(file, args) {
+            Ok(estimate) => {
+                if let Err(e) = db().store_estimate(&estimate) {
+                    warn!("Failed to store the estimate of {}: {}", file, e);
+                }
+                estimates.push(estimate);
+            }
+            Err(e) => warn!("{}", format!("No estimate for {}: {}", file, e).yellow()),
+        }
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+    estimates
+}
+
+pub fn estimates_json(estimates: &[SavingsEstimate]) -> String {
+    serde_json::to_string_pretty(estimates).unwrap_or_default()
+}
+
+// The estimates, the most saved bytes first
+pub fn print_estimates(estimates: &[SavingsEstimate]) {
+    if estimates.is_empty() {
+        println!("No files to estimate");
+        return;
+    }
+    let mut estimates = estimates.to_vec();
+    estimates.sort_by_key(|estimate| {
+        std::cmp::Reverse(estimate.input_size as i64 - estimate.estimated_size as i64)
+    });
+    let columns = terminal_columns();
+    // what the numbers leave of the terminal
+    let path_width = columns.saturating_sub(50).max(20);
+    println!(
+        "{:<path_width$} {:>10} {:>10} {:>8} {:>10}",
+        "file", "size", "estimated", "saved", "time"
+    );
+    for estimate in &estimates {
+        let name = titled_path(
+            stored_label(&estimate.path).as_deref(),
+            &estimate.path,
+            path_width,
+        );
+        let line = format!(
+            "{:<path_width$} {:>10} {:>10} {:>7.0}% {:>10}",
+            truncate_middle(&name, path_width),
+            format_size(estimate.input_size),
+            format_size(estimate.estimated_size),
+            estimate.savings_percent,
+            humantime::format_duration(Duration::from_secs(estimate.estimated_seconds as u64))
+                .to_string()
+        );
+        if estimate.savings_percent <= 0.0 {
+            println!("{}", line.yellow());
+        } else {
+            println!("{}", line);
+        }
+    }
+    let input: u64 = estimates.iter().map(|estimate| estimate.input_size).sum();
+    let estimated: u64 = estimates
+        .iter()
+        .map(|estimate| estimate.estimated_size)
+        .sum();
+    let seconds: f64 = estimates
+        .iter()
+        .map(|estimate| estimate.estimated_seconds)
+        .sum();
+    println!(
+        "{} files: {} -> {} ({:.0}% saved) in about {}",
+        estimates.len(),
+        format_size(input),
+        format_size(estimated),
+        (1.0 - estimated as f64 / input.max(1) as f64) * 100.0,
+        humantime::format_duration(Duration::from_secs(seconds as u64))
+    );
+}
@@ -0,0 +1,147 @@
+// --progress-format json: the progress as newline-delimited json events on stdout for the tools
+// that wrap the cli, one event per line flushed right away. The bars are hidden and the log stays
+// on stderr, so stdout only has events. The event names and fields are stable, new fields may be
+// added
+
+use crate::*;
+
+use indicatif::ProgressDrawTarget;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    // files probed into the database so far, of the files found
+    ScanProgress {
+        done: u64,
+        total: u64,
+    },
+    FileStart {
+        path: String,
+        // 1-based place in the run
+        index: u64,
+        total: u64,
+    },
+    // the result of the CRF search, vmaf is the target it was searched for
+    CrfFound {
+        path: String,
+        crf: f32,
+        vmaf: f32,
+        // the predicted output size in percent of the input
+        predicted_percent: Option<f32>,
+    },
+    // frame and frames count both passes of a two-pass encode
+    TranscodeProgress {
+        path: String,
+        frame: u64,
+        frames: u64,
+        fps: u64,
+        eta_s: u64,
+    },
+    FileDone {
+        path: String,
+        output: String,
+        reduction_pct: f64,
+    },
+    FileFailed {
+        path: String,
+        stage: String,
+    },
+    RunDone {
+        files: u64,
+        failed: u64,
+        elapsed_s: u64,
+    },
+}
+
+static JSON_EVENTS: AtomicBool = AtomicBool::new(false);
+
+// Switch to json events for the rest of the process and hide the bars drawn through LOG_PROGRESS
+pub fn enable_json_events() {
+    JSON_EVENTS.store(true, Ordering::SeqCst);
+    LOG_PROGRESS.set_draw_target(ProgressDrawTarget::hidden());
+}
+
+pub fn json_events() -> bool {
+    JSON_EVENTS.load(Ordering::SeqCst)
+}
+
+// Write event as one line to stdout, nothing without --progress-format json
+pub fn emit_event(event: &ProgressEvent) {
+    if !json_events() {
+        return;
+    }
+    let line = match serde_json::to_string(event) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!("Failed to serialize {:?}: {}", event, e);
+            return;
+        }
+    };
+    // the lock keeps the lines of parallel scans whole
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+}
+
+// The file_done event of a finished encode of file to output
+pub(crate) fn emit_file_done(file: &str, output: &str) {
+    emit_event(&ProgressEvent::FileDone {
+        path: file.to_string(),
+        output: output.to_string(),
+        reduction_pct: reduction_percent(
+            get_file_size(file).unwrap_or(0),
+            get_file_size(output).unwrap_or(0),
+        ),
+    });
+}
+
+// The transcode_progress event, the eta at the current fps
+pub(crate) fn emit_transcode_progress(file: &str, frame: u64, frames: u64, fps: u64) {
+    emit_event(&transcode_progress(file, frame, frames, fps));
+}
+
+pub(crate) fn transcode_progress(file: &str, frame: u64, frames: u64, fps: u64) -> ProgressEvent {
+    ProgressEvent::TranscodeProgress {
+        path: file.to_string(),
+        frame,
+        frames,
+        fps,
+        eta_s: frames.saturating_sub(frame).checked_div(fps).unwrap_or(0),
+    }
+}
+
+// A bar on stderr, hidden with --progress-format json
+pub fn progress_bar(len: u64) -> ProgressBar {
+    if json_events() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(len)
+    }
+}
+
+pub fn progress_spinner() -> ProgressBar {
+    if json_events() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    }
+}
+
+// The ProgressSink of --progress-format json: no bars, a file_start event per file
+pub struct JsonProgress;
+
+impl ProgressSink for JsonProgress {
+    fn bars(&mut self, _total_frames: u64) -> PipelineBars {
+        PipelineBars::hidden()
+    }
+
+    fn file_started(&mut self, job: &FileJob) {
+        emit_event(&ProgressEvent::FileStart {
+            path: job.file.clone(),
+            index: job.current_file_count,
+            total: job.total_files.max(0) as u64,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,167 @@
+use super::*;
+
+use serde_json::json;
+
+// One event of each type with the json it must serialize to, field for field
+fn every_event() -> Vec<(ProgressEvent, Value)> {
+    vec![
+        (
+            ProgressEvent::ScanProgress { done: 3, total: 12 },
+            json!({"event": "scan_progress", "done": 3, "total": 12}),
+        ),
+        (
+            ProgressEvent::FileStart {
+                path: "/media/movie.mkv".to_string(),
+                index: 1,
+                total: 12,
+            },
+            json!({"event": "file_start", "path": "/media/movie.mkv", "index": 1, "total": 12}),
+        ),
+        (
+            ProgressEvent::CrfFound {
+                path: "/media/movie.mkv".to_string(),
+                crf: 21.5,
+                vmaf: 96.0,
+                predicted_percent: Some(42.5),
+            },
+            json!({
+                "event": "crf_found",
+                "path": "/media/movie.mkv",
+                "crf": 21.5,
+                "vmaf": 96.0,
+                "predicted_percent": 42.5,
+            }),
+        ),
+        (
+            transcode_progress("/media/movie.mkv", 1234, 172_800, 88),
+            json!({
+                "event": "transcode_progress",
+                "path": "/media/movie.mkv",
+                "frame": 1234,
+                "frames": 172_800,
+                "fps": 88,
+                "eta_s": 1949,
+            }),
+        ),
+        (
+            ProgressEvent::FileDone {
+                path: "/media/movie.mkv".to_string(),
+                output: "/temp/movie.mkv".to_string(),
+                reduction_pct: 57.25,
+            },
+            json!({
+                "event": "file_done",
+                "path": "/media/movie.mkv",
+                "output": "/temp/movie.mkv",
+                "reduction_pct": 57.25,
+            }),
+        ),
+        (
+            ProgressEvent::FileFailed {
+                path: "/media/movie.mkv".to_string(),
+                stage: "crf_search".to_string(),
+            },
+            json!({"event": "file_failed", "path": "/media/movie.mkv", "stage": "crf_search"}),
+        ),
+        (
+            ProgressEvent::RunDone {
+                files: 12,
+                failed: 0,
+                elapsed_s: 21_540,
+            },
+            json!({"event": "run_done", "files": 12, "failed": 0, "elapsed_s": 21_540}),
+        ),
+    ]
+}
+
+#[test]
+fn every_event_serializes_with_its_stable_fields() {
+    for (event, expected) in every_event() {
+        assert_eq!(serde_json::to_value(&event).unwrap(), expected);
+    }
+}
+
+#[test]
+fn an_event_is_one_line() {
+    let event = ProgressEvent::FileStart {
+        path: "/media/two\nlines.mkv".to_string(),
+        index: 1,
+        total: 1,
+    };
+    let line = serde_json::to_string(&event).unwrap();
+    assert!(!line.contains('\n'));
+    assert_eq!(
+        serde_json::from_str::<Value>(&line).unwrap()["path"],
+        "/media/two\nlines.mkv"
+    );
+}
+
+#[test]
+fn a_crf_search_without_a_prediction_has_a_null_percent() {
+    let event = ProgressEvent::CrfFound {
+        path: "/media/movie.mkv".to_string(),
+        crf: 24.0,
+        vmaf: 95.0,
+        predicted_percent: None,
+    };
+    assert_eq!(
+        serde_json::to_value(&event).unwrap()["predicted_percent"],
+        Value::Null
+    );
+}
+
+#[test]
+fn the_eta_is_the_frames_left_at_the_current_fps() {
+    let eta = |frame, frames, fps| {
+        serde_json::to_value(transcode_progress("movie.mkv", frame, frames, fps)).unwrap()["eta_s"]
+            .as_u64()
+            .unwrap()
+    };
+    assert_eq!(eta(1000, 10_000, 90), 100);
+    // before the first fps and past the expected frames
+    assert_eq!(eta(0, 10_000, 0), 0);
+    assert_eq!(eta(12_000, 10_000, 90), 0);
+}
+
+#[test]
+fn the_readme_examples_have_the_fields_of_the_events() {
+    let readme = include_str!("../../README.md");
+    let keys = |value: &Value| {
+        let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    };
+    let events = every_event();
+    let mut examples = 0;
+    for line in readme
+        .lines()
+        .filter(|line| line.starts_with("{\"event\":"))
+    {
+        let example: Value = serde_json::from_str(line).unwrap();
+        // the webhook payloads of the README are not progress events
+        let Some((_, expected)) = events
+            .iter()
+            .find(|(_, expected)| expected["event"] == example["event"])
+        else {
+            continue;
+        };
+        assert_eq!(keys(&example), keys(expected), "{}", line);
+        examples += 1;
+    }
+    assert!(examples >= 5, "{}", examples);
+}
+
+#[test]
+fn the_json_sink_hides_the_bars() {
+    let bars = JsonProgress.bars(1000);
+    for bar in [
+        &bars.file_bar,
+        &bars.total_bar,
+        &bars.transcode_bar,
+        &bars.info_bar,
+        &bars.codec_bar,
+        &bars.vmaf_bar,
+    ] {
+        assert!(bar.is_hidden());
+    }
+}
@@ -0,0 +1,131 @@
+// Folder scan exclusions: --exclude patterns and a .transrusticaignore file at the scan root, both
+// gitignore style, leave files out of walk_count and walk_files and remove them from the database
+
+use crate::*;
+
+use glob::{MatchOptions, Pattern};
+
+pub const IGNORE_FILE: &str = ".transrusticaignore";
+
+#[derive(Debug)]
+struct ExcludePattern {
+    pattern: Pattern,
+    // !pattern includes a file again
+    negated: bool,
+    // pattern/ only matches folders
+    folder_only: bool,
+}
+
+#[derive(Debug)]
+pub struct ScanExcludes {
+    root: PathBuf,
+    patterns: Vec<ExcludePattern>,
+}
+
+fn match_options() -> MatchOptions {
+    MatchOptions {
+        case_sensitive: !cfg!(windows),
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    }
+}
+
+// One gitignore line as a pattern on the path relative to the scan root
+// Without a / inside it matches a file or folder name at any depth, with one it is relative to the root
+fn parse_exclude(line: &str) -> Option<ExcludePattern> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let line = line.replace('\\', "/");
+    let folder_only = line.ends_with('/');
+    let line = line.trim_end_matches('/');
+    if line.is_empty() {
+        return None;
+    }
+    let glob = if line.contains('/') {
+        line.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", line)
+    };
+    match Pattern::new(&glob) {
+        Ok(pattern) => Some(ExcludePattern {
+            pattern,
+            negated,
+            folder_only,
+        }),
+        Err(e) => {
+            warn!("Invalid exclude pattern {}: {}", line, e);
+            None
+        }
+    }
+}
+
+impl ScanExcludes {
+    // The patterns of the ignore file in root, then the --exclude ones, a later pattern wins
+    pub fn load(root: &str, exclude: &[String]) -> ScanExcludes {
+        let root = PathBuf::from(root);
+        let mut lines: Vec<String> = Vec::new();
+        let ignore_file = root.join(IGNORE_FILE);
+        if ignore_file.is_file() {
+            match fs::read_to_string(&ignore_file) {
+                Ok(content) => lines.extend(content.lines().map(|line| line.to_string())),
+                Err(e) => warn!("Failed to read {}: {}", ignore_file.display(), e),
+            }
+        }
+        lines.extend(exclude.iter().cloned());
+
+        let patterns: Vec<ExcludePattern> = lines
+            .iter()
+            .filter_map(|line| parse_exclude(line))
+            .collect();
+        if !patterns.is_empty() {
+            debug!("{} exclude patterns for {}", patterns.len(), root.display());
+        }
+        ScanExcludes { root, patterns }
+    }
+
+    // Whether the last pattern matching relative says excluded
+    fn matches(&self, relative: &str, is_folder: bool) -> bool {
+        let mut excluded = false;
+        for exclude in &self.patterns {
+            if exclude.folder_only && !is_folder {
+                continue;
+            }
+            if exclude.pattern.matches_with(relative, match_options()) {
+                excluded = !exclude.negated;
+            }
+        }
+        excluded
+    }
+
+    // A path under the root is excluded when it or one of its folders matches, paths outside the
+    // root never are
+    pub fn is_excluded(&self, path: &Path, is_folder: bool) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+        let components: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        // a file in an excluded folder can't be included again, like with git
+        (1..=components.len()).any(|depth| {
+            self.matches(
+                &components[..depth].join("/"),
+                depth < components.len() || is_folder,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
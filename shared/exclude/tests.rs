@@ -0,0 +1,143 @@
+use super::*;
+
+fn excludes(patterns: &[&str]) -> ScanExcludes {
+    let exclude: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+    // a root without an ignore file
+    ScanExcludes::load("/library/does-not-exist", &exclude)
+}
+
+fn excluded(excludes: &ScanExcludes, relative: &str) -> bool {
+    excludes.is_excluded(&Path::new("/library/does-not-exist").join(relative), false)
+}
+
+#[test]
+fn nested_folder_patterns() {
+    let excludes = excludes(&["**/sample/**"]);
+    for path in [
+        "sample/a.mkv",
+        "Movie (2020)/sample/a.mkv",
+        "Shows/Show/Season 1/sample/extra/a.mkv",
+    ] {
+        assert!(excluded(&excludes, path), "{}", path);
+    }
+    for path in ["Movie (2020)/Movie.mkv", "samples/a.mkv", "sample.mkv"] {
+        assert!(!excluded(&excludes, path), "{}", path);
+    }
+}
+
+#[test]
+fn a_name_without_a_slash_matches_at_any_depth() {
+    let excludes = excludes(&["extras", "*.partial~"]);
+    for path in [
+        "extras/a.mkv",
+        "Movie (2020)/extras/featurette.mkv",
+        "Movie.mkv.partial~",
+        "Shows/Episode.mkv.partial~",
+    ] {
+        assert!(excluded(&excludes, path), "{}", path);
+    }
+    assert!(!excluded(&excludes, "Movie (2020)/Movie.mkv"));
+}
+
+#[test]
+fn a_pattern_with_a_slash_is_relative_to_the_root() {
+    let excludes = excludes(&["/Movies/extras", "Shows/*/trailers/"]);
+    assert!(excluded(&excludes, "Movies/extras/a.mkv"));
+    assert!(!excluded(&excludes, "Other/Movies/extras/a.mkv"));
+    assert!(excluded(&excludes, "Shows/Show/trailers/a.mkv"));
+    // * doesn't cross a folder
+    assert!(!excluded(&excludes, "Shows/Show/Season 1/trailers/a.mkv"));
+}
+
+#[test]
+fn a_folder_pattern_only_matches_folders() {
+    let excludes = excludes(&["extras/"]);
+    assert!(excluded(&excludes, "Movie/extras/a.mkv"));
+    assert!(excludes.is_excluded(Path::new("/library/does-not-exist/Movie/extras"), true));
+    // a file named like the folder
+    assert!(!excluded(&excludes, "Movie/extras"));
+}
+
+#[test]
+fn the_last_matching_pattern_wins() {
+    let excludes = excludes(&["*.mp4", "!keep.mp4"]);
+    assert!(excluded(&excludes, "Movie/trailer.mp4"));
+    assert!(!excluded(&excludes, "Movie/keep.mp4"));
+    // a file in an excluded folder can't be included again
+    let excludes = self::excludes(&["extras/", "!extras/keep.mkv"]);
+    assert!(excluded(&excludes, "extras/keep.mkv"));
+}
+
+#[test]
+fn comments_blank_lines_and_paths_outside_of_the_root() {
+    let excludes = excludes(&["# samples", "", "   "]);
+    assert!(excludes.patterns.is_empty());
+    assert!(!excluded(&excludes, "# samples"));
+    let excludes = self::excludes(&["*.mkv"]);
+    assert!(!excludes.is_excluded(Path::new("/elsewhere/Movie.mkv"), false));
+}
+
+#[test]
+fn the_case_of_the_patterns() {
+    let excludes = excludes(&["**/Sample/**"]);
+    assert!(excluded(&excludes, "Movie/Sample/a.mkv"));
+    // only case-insensitive on windows
+    assert_eq!(excluded(&excludes, "Movie/SAMPLE/a.mkv"), cfg!(windows));
+}
+
+// A library with a sample folder, extras and a partial file next to the movies
+fn library() -> PathBuf {
+    let root = temp_path("exclude-library");
+    for path in [
+        "Movie (2020)/Movie.mkv",
+        "Movie (2020)/sample/Movie-sample.mkv",
+        "Movie (2020)/extras/Featurette.mp4",
+        "Show/Season 1/Episode 1.mkv",
+        "Show/Season 1/Episode 2.mkv.partial~",
+        "Show/Season 1/notes.txt",
+    ] {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"video").unwrap();
+    }
+    root
+}
+
+fn relative_files(root: &Path, exclude: &[String]) -> Vec<String> {
+    let mut files: Vec<String> = walk_files(&root.to_string_lossy().to_string(), exclude)
+        .iter()
+        .map(|path| {
+            Path::new(path)
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/")
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn the_ignore_file_and_exclude_leave_files_out_of_the_scan() {
+    let root = library();
+    let dir = root.to_string_lossy().to_string();
+    assert_eq!(
+        relative_files(&root, &[]),
+        [
+            "Movie (2020)/Movie.mkv",
+            "Movie (2020)/extras/Featurette.mp4",
+            "Movie (2020)/sample/Movie-sample.mkv",
+            "Show/Season 1/Episode 1.mkv",
+        ]
+    );
+    assert_eq!(walk_count(&dir, &[]), 4);
+
+    fs::write(root.join(IGNORE_FILE), "# not encoded\n**/sample/**\n").unwrap();
+    let exclude = vec!["extras/".to_string()];
+    assert_eq!(
+        relative_files(&root, &exclude),
+        ["Movie (2020)/Movie.mkv", "Show/Season 1/Episode 1.mkv"]
+    );
+    assert_eq!(walk_count(&dir, &exclude), 2);
+}
@@ -0,0 +1,178 @@
+// --ingest-external-subs: the .srt, .ass and .ssa files next to a source that share its name are
+// muxed into the output as subtitle streams, each an -i of its own after the source, with the
+// language of their name (movie.en.srt)
+
+use crate::*;
+
+const EXTERNAL_SUBTITLE_EXTENSIONS: [&str; 3] = ["srt", "ass", "ssa"];
+
+// The two letter codes of the names for the three letter codes of the stream tags
+const LANGUAGE_CODES: [(&str, &str); 24] = [
+    ("ar", "ara"),
+    ("cs", "cze"),
+    ("da", "dan"),
+    ("de", "ger"),
+    ("el", "gre"),
+    ("en", "eng"),
+    ("es", "spa"),
+    ("fi", "fin"),
+    ("fr", "fre"),
+    ("he", "heb"),
+    ("hi", "hin"),
+    ("hu", "hun"),
+    ("it", "ita"),
+    ("ja", "jpn"),
+    ("ko", "kor"),
+    ("nl", "dut"),
+    ("no", "nor"),
+    ("pl", "pol"),
+    ("pt", "por"),
+    ("ru", "rus"),
+    ("sv", "swe"),
+    ("tr", "tur"),
+    ("uk", "ukr"),
+    ("zh", "chi"),
+];
+
+// An external subtitle file muxed into the output, its -i comes after the source
+#[derive(Debug, Clone)]
+pub struct ExternalSubtitle {
+    pub path: String,
+    // the three letter code of the name, empty when the name has none
+    pub language: String,
+    // copy into matroska, mov_text into mp4 and mov
+    pub codec: String,
+}
+
+// A file of the run with external subtitles merged into its output, for --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedSubtitles {
+    pub path: String,
+    pub output: String,
+    pub subtitles: Vec<String>,
+}
+
+// The three letter code of a language code, "en" and "eng" are "eng"
+fn normalize_language(language: &str) -> String {
+    let language = language.to_lowercase();
+    LANGUAGE_CODES
+        .iter()
+        .find(|(short, _)| *short == language)
+        .map(|(_, long)| long.to_string())
+        .unwrap_or(language)
+}
+
+// The language and extension of the name after the stem, ".en.srt" is ("eng", "srt") and ".srt"
+// ("", "srt"), None for other files
+fn subtitle_suffix(suffix: &str) -> Option<(String, String)> {
+    let parts: Vec<&str> = suffix.trim_start_matches('.').split('.').collect();
+    let extension = parts.last()?.to_lowercase();
+    if !EXTERNAL_SUBTITLE_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    // movie.en.forced.srt, the language is the first part that looks like one
+    let language = parts[..parts.len() - 1]
+        .iter()
+        .find(|part| (2..=3).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|part| normalize_language(part))
+        .unwrap_or_default();
+    Some((language, extension))
+}
+
+// The -c:s of an external subtitle in an output with extension, None for the containers that
+// hold no text subtitles
+fn external_subtitle_codec(extension: &str) -> Option<&'static str> {
+    match extension.to_lowercase().as_str() {
+        "mkv" => Some("copy"),
+        "mp4" | "m4v" | "mov" => Some("mov_text"),
+        _ => None,
+    }
+}
+
+// The subtitle files next to file, the output of file keeps its extension
+pub fn external_subtitles(file: &str) -> Vec<ExternalSubtitle> {
+    let (_, extension) = output_name_parts(file);
+    let subtitles: Vec<(PathBuf, String)> = sidecar_files(file)
+        .into_iter()
+        .filter_map(|(path, suffix)| subtitle_suffix(&suffix).map(|(language, _)| (path, language)))
+        .collect();
+    if subtitles.is_empty() {
+        return Vec::new();
+    }
+    let codec = match external_subtitle_codec(&extension) {
+        Some(codec) => codec,
+        None => {
+            warn!(
+                "{}: a .{} output holds no external subtitles, leaving out {}",
+                file,
+                extension,
+                subtitles.len()
+            );
+            return Vec::new();
+        }
+    };
+    subtitles
+        .into_iter()
+        .map(|(path, language)| ExternalSubtitle {
+            path: path.to_string_lossy().to_string(),
+            language,
+            codec: codec.to_string(),
+        })
+        .collect()
+}
+
+// Add the external subtitles of file in --keep-subs-langs to streams. An embedded subtitle of the
+// same language wins, with --prefer-external-subs the embedded ones of that language are dropped
+pub(crate) fn add_external_subtitles(file: &str, args: &Args, streams: &mut StreamSelection) {
+    for external in external_subtitles(file) {
+        if !language_kept(&external.language, &args.keep_subs_langs) {
+            continue;
+        }
+        let same_language = |stream: &SubtitleStream| {
+            !external.language.is_empty()
+                && normalize_language(&stream.language) == external.language
+        };
+        if streams.subtitles.iter().any(same_language) {
+            if !args.prefer_external_subs {
+                info!(
+                    "{}: keeping the embedded {} subtitles instead of {}",
+                    file, external.language, external.path
+                );
+                continue;
+            }
+            let (embedded, kept): (Vec<SubtitleStream>, Vec<SubtitleStream>) = streams
+                .subtitles
+                .drain(..)
+                .partition(|stream| same_language(stream));
+            streams.subtitles = kept;
+            for stream in embedded {
+                streams
+                    .dropped
+                    .push(format!("s:{}({})", stream.index, external.language));
+            }
+        }
+        info!(
+            "{}: adding subtitles {} ({})",
+            file,
+            external.path,
+            stream_language(&external.language)
+        );
+        streams.external_subtitles.push(external);
+    }
+}
+
+// Warn when output has other subtitle streams than the kept and the external ones
+pub(crate) fn check_subtitle_streams(output: &str, streams: &StreamSelection) {
+    let expected = streams.subtitles.len() + streams.external_subtitles.len();
+    let found = probe_file(output).streams_of("subtitle").len();
+    if found != expected {
+        warn!(
+            "{}: {} subtitle streams, expected {} ({} embedded, {} external)",
+            output,
+            found,
+            expected,
+            streams.subtitles.len(),
+            streams.external_subtitles.len()
+        );
+    }
+}
@@ -0,0 +1,97 @@
+// --fast-scene-threshold: scenes of chunked mode that are cheap to encode, like credits, black frames
+// and title cards, get a faster preset and one VMAF measurement at --max-crf instead of the CRF search
+
+use crate::*;
+
+// How a scene was encoded, chunks.txt and searched.txt keep it per scene
+pub(crate) const FAST_SCENE_PATH: &str = "fast";
+pub(crate) const FULL_SCENE_PATH: &str = "full";
+
+// Source kB per frame of the scene between scene_change and next_scene_change, its complexity estimate
+pub(crate) fn scene_kb_per_frame(
+    scene_size: u64,
+    scene_change: f32,
+    next_scene_change: f32,
+    fps: f32,
+) -> f64 {
+    let frames = ((next_scene_change - scene_change) * fps).round() as f64;
+    if frames < 1.0 {
+        return 0.0;
+    }
+    scene_size as f64 / 1000.0 / frames
+}
+
+// Whether a scene of kb_per_frame tries the fast path, never with --uniform-scenes
+pub(crate) fn is_fast_scene(args: &Args, kb_per_frame: f64) -> bool {
+    !args.uniform_scenes && kb_per_frame < args.fast_scene_threshold
+}
+
+// The args of the fast scenes, a faster preset of the encoders that have one
+pub(crate) fn fast_scene_args(args: &Args) -> Args {
+    let mut fast_args = args.clone();
+    fast_args.preset_x265 = "medium".to_string();
+    fast_args.preset_libsvtav1 = "12".to_string();
+    fast_args.preset_hevc_nvenc = "p4".to_string();
+    fast_args
+}
+
+// The CRF of the fast scenes, --max-crf
+pub(crate) fn fast_scene_crf(args: &Args) -> f32 {
+    args.max_crf.trim().parse().unwrap_or(28.0)
+}
+
+// Measure the VMAF of the scene at the fast CRF with the fast preset, None when it misses the target
+// and the scene needs the full search
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn process_scene_fast(
+    scene_index: i32,
+    file: &str,
+    ss_arg: &str,
+    to_arg: &str,
+    fps: &str,
+    fast_args: &Args,
+    vmaf_scores: &Mutex<Vec<(i32, f32, f32)>>,
+    debug_log: &Option<Arc<DebugLog>>,
+    chunk_files: Option<&ChunkFiles>,
+) -> Option<(f32, f32)> {
+    let crf = fast_scene_crf(fast_args);
+    let keep_stream = chunk_files.map(|chunk_files| chunk_files.search_stream(scene_index, crf));
+    let vmaf = process_video_pipe_and_vmaf(
+        &file.to_string(),
+        fast_args,
+        &crf,
+        fps,
+        &ss_arg.to_string(),
+        &to_arg.to_string(),
+        &fast_args.vmaf_pool,
+        &fast_args.vmaf_threads,
+        &fast_args.vmaf_subsample,
+        None,
+        keep_stream.as_deref(),
+    );
+    let vmaf_score = match vmaf {
+        Ok(vmaf) => parse_vmaf_score(&vmaf).unwrap_or(0.0),
+        Err(e) => {
+            warn!("Scene {}: fast VMAF check failed: {}", scene_index, e);
+            return None;
+        }
+    };
+    if let Some(debug_log) = debug_log {
+        let _ = debug_log.write_line(&format!(
+            "Scene: {}, fast path CRF: {}, VMAF: {}",
+            scene_index, crf, vmaf_score
+        ));
+    }
+    vmaf_scores
+        .lock()
+        .unwrap()
+        .push((scene_index, crf, vmaf_score));
+    if vmaf_score < fast_args.vmaf {
+        // the full search encodes with the slower preset
+        if let Some(keep_stream) = keep_stream {
+            let _ = fs::remove_file(keep_stream);
+        }
+        return None;
+    }
+    Some((crf, vmaf_score))
+}
@@ -0,0 +1,364 @@
+// FfmpegArgs: the arguments of every ffmpeg video encode, the final encode and its --target-size
+// passes, the chunks and the samples the CRF searches measure. The rate control of each encoder is
+// only in quality_args, so a CRF found on the samples is encoded the same way
+
+use crate::*;
+
+use std::ffi::OsString;
+
+// How the encoder is told the quality
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodeQuality {
+    // the searched CRF, the -cq of hevc_nvenc and the -global_quality of the qsv encoders
+    Crf(f32),
+    // kbps of --target-size or a bitrate rendition, pass is (1 or 2, stats prefix) for the
+    // two-pass encoders
+    Bitrate {
+        kbps: u32,
+        pass: Option<(u8, String)>,
+    },
+    // -c:v copy of the audio remux
+    Copy,
+}
+
+// The video encode of an ffmpeg run
+#[derive(Debug, Clone)]
+pub struct EncodeSpec {
+    // the ffmpeg encoder, av1 is libaom-av1
+    pub encoder: String,
+    pub quality: EncodeQuality,
+    pub preset: String,
+    // a params string like --params-x265, quoted parameters stay together
+    pub params: String,
+    pub pix_fmt: String,
+    // the keyframe interval, the encoder's default without it
+    pub keyint: Option<Keyint>,
+    // -ss and -to of the input, to encode a part of it
+    pub seek: Option<(String, String)>,
+    // -map and the other options of the output streams
+    pub maps: Vec<String>,
+    // the -vf chain of the video, none when empty
+    pub filters: Vec<String>,
+}
+
+impl EncodeSpec {
+    pub fn new(
+        encoder: &str,
+        quality: EncodeQuality,
+        preset: &str,
+        params: &str,
+        pix_fmt: &str,
+    ) -> EncodeSpec {
+        EncodeSpec {
+            encoder: encoder.to_string(),
+            quality,
+            preset: preset.to_string(),
+            params: params.to_string(),
+            pix_fmt: pix_fmt.to_string(),
+            keyint: None,
+            seek: None,
+            maps: Vec::new(),
+            filters: Vec::new(),
+        }
+    }
+
+    // The video copied as it is, for the audio remux
+    pub fn copy() -> EncodeSpec {
+        EncodeSpec::new("copy", EncodeQuality::Copy, "", "", "")
+    }
+}
+
+// The arguments of one ffmpeg run that encodes input to output with an EncodeSpec
+#[derive(Debug, Clone)]
+pub struct FfmpegArgs {
+    // before the input, like -y, -hide_banner and -r
+    input_args: Vec<OsString>,
+    input: OsString,
+    // -i of the inputs after input, like the external subtitles
+    extra_inputs: Vec<OsString>,
+    spec: EncodeSpec,
+    // after the video encoder, like -an, -progress and -f
+    output_args: Vec<OsString>,
+    output: OsString,
+}
+
+impl FfmpegArgs {
+    pub fn new(input: &str, spec: EncodeSpec, output: &str) -> FfmpegArgs {
+        FfmpegArgs {
+            input_args: Vec::new(),
+            // --stage-dir, a staged source is read from its copy
+            input: staged_input(input).into(),
+            extra_inputs: Vec::new(),
+            spec,
+            output_args: Vec::new(),
+            output: output.into(),
+        }
+    }
+
+    pub fn input_args<I, S>(mut self, args: I) -> FfmpegArgs
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.input_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn extra_inputs<I, S>(mut self, args: I) -> FfmpegArgs
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.extra_inputs.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn output_args<I, S>(mut self, args: I) -> FfmpegArgs
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.output_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn build(&self) -> Vec<OsString> {
+        let spec = &self.spec;
+        let mut args: Vec<OsString> = self.input_args.clone();
+        if let Some((ss, to)) = &spec.seek {
+            args.extend(["-ss".into(), ss.into(), "-to".into(), to.into()]);
+        }
+        args.extend(["-i".into(), self.input.clone()]);
+        args.extend(self.extra_inputs.iter().cloned());
+        args.extend(spec.maps.iter().map(OsString::from));
+        args.extend(["-c:v".into(), spec.encoder.as_str().into()]);
+        if !spec.preset.is_empty() {
+            args.extend(["-preset".into(), spec.preset.as_str().into()]);
+        }
+        let mut params = params_args(&spec.params);
+        // libx265 takes its pass in -x265-params, libaom-av1 in quality_args
+        if let EncodeQuality::Bitrate {
+            pass: Some((pass, pass_log)),
+            ..
+        } = &spec.quality
+        {
+            if spec.encoder == "libx265" {
+                params = x265_pass_params(params, *pass, pass_log);
+            }
+        }
+        // some x265 builds ignore -g, the interval goes into -x265-params as well
+        if let (Some(keyint), "libx265") = (spec.keyint, spec.encoder.as_str()) {
+            params = add_codec_params(params, "-x265-params", &keyint.x265_params());
+        }
+        args.extend(params.into_iter().map(OsString::from));
+        args.extend(
+            quality_args(&spec.encoder, &spec.quality)
+                .into_iter()
+                .map(OsString::from),
+        );
+        if let Some(keyint) = spec.keyint {
+            args.extend(
+                keyint
+                    .ffmpeg_args(&spec.encoder)
+                    .into_iter()
+                    .map(OsString::from),
+            );
+        }
+        if !spec.filters.is_empty() {
+            args.extend(["-vf".into(), spec.filters.join(",").into()]);
+        }
+        if !spec.pix_fmt.is_empty() {
+            args.extend(["-pix_fmt".into(), spec.pix_fmt.as_str().into()]);
+        }
+        args.extend(self.output_args.iter().cloned());
+        args.push(self.output.clone());
+        args
+    }
+}
+
+// The rate control of encoder, the one place that knows how each encoder takes a quality
+pub(crate) fn quality_args(encoder: &str, quality: &EncodeQuality) -> Vec<String> {
+    match quality {
+        EncodeQuality::Crf(crf) => {
+            let crf = crf.to_string();
+            let args: Vec<&str> = match encoder {
+                // the quantizer is pinned to the CQ, so the samples and the encode get the same
+                "hevc_nvenc" => vec!["-rc:v", "vbr", "-cq:v", &crf, "-qmin", &crf, "-qmax", &crf],
+                "hevc_qsv" | "av1_qsv" => vec!["-global_quality", &crf],
+                // libx265, libsvtav1 and libaom-av1
+                _ => vec!["-crf", &crf],
+            };
+            args.into_iter().map(String::from).collect()
+        }
+        EncodeQuality::Bitrate { kbps, pass } => bitrate_args(
+            encoder,
+            *kbps,
+            pass.as_ref()
+                .map(|(pass, pass_log)| (*pass, pass_log.as_str())),
+        ),
+        EncodeQuality::Copy => Vec::new(),
+    }
+}
+
+// Rate control arguments for a target bitrate in kbps, pass is (1 or 2, stats prefix) for the
+// two-pass encoders
+fn bitrate_args(encoder: &str, kbps: u32, pass: Option<(u8, &str)>) -> Vec<String> {
+    let bitrate = format!("{}k", kbps);
+    // headroom for the VBR of the hardware encoders, 1.5x the average with a 2 second buffer
+    let maxrate = format!("{}k", kbps * 3 / 2);
+    let bufsize = format!("{}k", kbps * 2);
+    let mut args: Vec<&str> = match encoder {
+        "hevc_nvenc" => vec![
+            "-rc",
+            "vbr",
+            "-b:v",
+            &bitrate,
+            "-maxrate",
+            &maxrate,
+            "-bufsize",
+            &bufsize,
+            "-multipass",
+            "fullres",
+        ],
+        "hevc_qsv" | "av1_qsv" => {
+            vec!["-b:v", &bitrate, "-maxrate", &maxrate, "-bufsize", &bufsize]
+        }
+        _ => vec!["-b:v", &bitrate],
+    };
+    // libx265 gets its pass through x265_pass_params
+    let pass_number;
+    if let Some((pass, pass_log)) = pass {
+        if matches!(encoder, "av1" | "libaom-av1") {
+            pass_number = pass.to_string();
+            args.extend(["-pass", &pass_number, "-passlogfile", pass_log]);
+        }
+    }
+    args.into_iter().map(String::from).collect()
+}
+
+// libx265 takes the pass and its stats file in -x265-params, added to the -x265-params of params
+fn x265_pass_params(params: Vec<String>, pass: u8, pass_log: &str) -> Vec<String> {
+    let pass_params = format!("pass={}:stats={}.log", pass, pass_log);
+    add_codec_params(params, "-x265-params", &pass_params)
+}
+
+// The preset and params of args.encoder, the chunks and the samples encode with these
+pub(crate) fn encoder_preset_params(args: &Args) -> (&str, &str) {
+    let (preset, params) = match args.encoder.as_str() {
+        "libx265" => (&args.preset_x265, &args.params_x265),
+        "hevc_nvenc" => (&args.preset_hevc_nvenc, &args.params_hevc_nvenc),
+        "hevc_qsv" => (&args.preset_hevc_qsv, &args.params_hevc_qsv),
+        "av1" | "libaom-av1" => (&args.preset_libaom_av1, &args.params_libaom_av1),
+        "av1_qsv" => (&args.preset_av1_qsv, &args.params_av1_qsv),
+        "libsvtav1" => (&args.preset_libsvtav1, &args.params_libsvtav1),
+        _ => (&args.preset_x265, &args.params_x265),
+    };
+    (preset, params)
+}
+
+pub(crate) const MIN_KEYINT_SECONDS: f32 = 2.0;
+pub(crate) const MAX_KEYINT_SECONDS: f32 = 10.0;
+
+// The keyframe interval of the chunks, the samples, the final encode and the ab-av1 search, in
+// frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyint {
+    pub gop: u32,
+    // --min-keyint, the encoder's default without it
+    pub min: Option<u32>,
+}
+
+impl Keyint {
+    // -g, and -keyint_min where the ffmpeg wrapper of the encoder passes it on
+    pub(crate) fn ffmpeg_args(&self, encoder: &str) -> Vec<String> {
+        let mut args = vec!["-g".to_string(), self.gop.to_string()];
+        match self.min {
+            // svt-av1 has no minimum interval
+            Some(min) if encoder != "libsvtav1" => {
+                args.extend(["-keyint_min".to_string(), min.to_string()])
+            }
+            _ => {}
+        }
+        args
+    }
+
+    pub(crate) fn x265_params(&self) -> String {
+        match self.min {
+            Some(min) => format!("keyint={}:min-keyint={}", self.gop, min),
+            None => format!("keyint={}", self.gop),
+        }
+    }
+
+    // The same interval for the samples of ab-av1 crf-search, or its search predicts another
+    // bitrate than the encode gets
+    pub(crate) fn ab_av1_args(&self, encoder: &str) -> Vec<String> {
+        let mut args = vec!["--keyint".to_string(), self.gop.to_string()];
+        match (encoder, self.min) {
+            ("libx265", _) => args.extend([
+                "--enc".to_string(),
+                format!("x265-params={}", self.x265_params()),
+            ]),
+            ("libsvtav1", _) | (_, None) => {}
+            (_, Some(min)) => args.extend(["--enc".to_string(), format!("keyint_min={}", min)]),
+        }
+        args
+    }
+}
+
+// --keyint-seconds at fps rounded to whole frames, --min-keyint at most that. None when the fps is
+// unknown, the encoder then keeps its default
+pub(crate) fn encode_keyint(fps: f32, args: &Args) -> Option<Keyint> {
+    if fps <= 0.0 {
+        return None;
+    }
+    let gop = (fps * args.keyint_seconds).round().max(1.0) as u32;
+    Some(Keyint {
+        gop,
+        min: args.min_keyint.map(|min| min.min(gop)),
+    })
+}
+
+// The -map of the kept audio and subtitle streams, their metadata and the audio plans, after the
+// video of the final encode and the audio remux, with the audio codecs for the progress line
+pub(crate) fn stream_output_args(
+    streams: &StreamSelection,
+    audio_plans: &[AudioPlan],
+    original_audio_codec: &str,
+) -> (Vec<String>, String) {
+    // map the kept audio and subtitle streams with -map 0:a:i copy, the burned in subtitle is
+    // not in streams, and the cover art after the video
+    let mut args = streams.map_args();
+    args.extend(streams.cover_art_args(1));
+
+    let metadata = run_ffmpeg_map_metadata(streams);
+    if !metadata.is_empty() {
+        args.extend(["-map_metadata".to_string(), "-1".to_string()]);
+        args.extend(metadata.split_whitespace().map(String::from));
+    } else {
+        args.extend(["-map_metadata".to_string(), "0".to_string()]);
+    }
+
+    let mut final_audio_codec = String::new();
+    if !audio_plans.is_empty() {
+        for plan in audio_plans {
+            if !plan.args.is_empty() {
+                args.extend(plan.args.split_whitespace().map(String::from));
+                final_audio_codec
+                    .push_str(&format!("{}->{},", plan.original_codec, plan.target_codec));
+            } else {
+                args.extend([format!("-c:a:{}", plan.output_index), "copy".to_string()]);
+                final_audio_codec.push_str(&format!("{},", plan.original_codec));
+            }
+        }
+        final_audio_codec.pop(); // Remove the trailing comma
+    } else {
+        final_audio_codec = format!("{}->{}", original_audio_codec, "opus");
+    }
+    if !streams.dropped.is_empty() {
+        final_audio_codec.push_str(&format!(" -{}", streams.dropped.join(",-")));
+    }
+    (args, final_audio_codec)
+}
+
+#[cfg(test)]
+mod tests;
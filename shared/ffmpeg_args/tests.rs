@@ -0,0 +1,426 @@
+use super::*;
+
+// The arguments of spec from in.mkv to out.mkv, as text
+fn built(spec: EncodeSpec) -> Vec<String> {
+    strings(FfmpegArgs::new("in.mkv", spec, "out.mkv").build())
+}
+
+fn strings(args: Vec<OsString>) -> Vec<String> {
+    args.into_iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect()
+}
+
+// -i in.mkv -c:v encoder, then middle, then out.mkv
+fn expected(encoder: &str, middle: &[&str]) -> Vec<String> {
+    ["-i", "in.mkv", "-c:v", encoder]
+        .iter()
+        .chain(middle)
+        .chain(&["out.mkv"])
+        .map(|arg| arg.to_string())
+        .collect()
+}
+
+#[test]
+fn crf_of_each_encoder() {
+    let cases: [(&str, &str, &[&str]); 6] = [
+        (
+            "hevc_nvenc",
+            "p7",
+            &["-rc:v", "vbr", "-cq:v", "24", "-qmin", "24", "-qmax", "24"],
+        ),
+        ("hevc_qsv", "veryslow", &["-global_quality", "24"]),
+        ("av1_qsv", "veryslow", &["-global_quality", "24"]),
+        ("libx265", "slow", &["-crf", "24"]),
+        ("libsvtav1", "6", &["-crf", "24"]),
+        ("libaom-av1", "4", &["-crf", "24"]),
+    ];
+    for (encoder, preset, quality) in cases {
+        let spec = EncodeSpec::new(encoder, EncodeQuality::Crf(24.0), preset, "", "p010le");
+        let middle: Vec<&str> = ["-preset", preset]
+            .iter()
+            .chain(quality)
+            .chain(&["-pix_fmt", "p010le"])
+            .copied()
+            .collect();
+        assert_eq!(built(spec), expected(encoder, &middle), "{}", encoder);
+    }
+}
+
+#[test]
+fn a_fractional_crf_is_passed_as_it_is() {
+    let spec = EncodeSpec::new("hevc_nvenc", EncodeQuality::Crf(22.5), "", "", "");
+    assert_eq!(
+        built(spec),
+        expected(
+            "hevc_nvenc",
+            &["-rc:v", "vbr", "-cq:v", "22.5", "-qmin", "22.5", "-qmax", "22.5"]
+        )
+    );
+    let spec = EncodeSpec::new("hevc_qsv", EncodeQuality::Crf(22.5), "", "", "");
+    assert_eq!(
+        built(spec),
+        expected("hevc_qsv", &["-global_quality", "22.5"])
+    );
+}
+
+#[test]
+fn params_come_before_the_quality() {
+    let spec = EncodeSpec::new(
+        "libx265",
+        EncodeQuality::Crf(21.0),
+        "slow",
+        r#"-x265-params "limit-sao:bframes=8" -tune grain"#,
+        "yuv420p10le",
+    );
+    assert_eq!(
+        built(spec),
+        expected(
+            "libx265",
+            &[
+                "-preset",
+                "slow",
+                "-x265-params",
+                "limit-sao:bframes=8",
+                "-tune",
+                "grain",
+                "-crf",
+                "21",
+                "-pix_fmt",
+                "yuv420p10le",
+            ]
+        )
+    );
+}
+
+#[test]
+fn bitrate_of_each_encoder() {
+    let bitrate = || EncodeQuality::Bitrate {
+        kbps: 4000,
+        pass: None,
+    };
+    let hardware_vbr = ["-b:v", "4000k", "-maxrate", "6000k", "-bufsize", "8000k"];
+    let nvenc: Vec<&str> = ["-rc", "vbr"]
+        .iter()
+        .chain(&hardware_vbr)
+        .chain(&["-multipass", "fullres"])
+        .copied()
+        .collect();
+    let cases: [(&str, &[&str]); 6] = [
+        ("hevc_nvenc", &nvenc),
+        ("hevc_qsv", &hardware_vbr),
+        ("av1_qsv", &hardware_vbr),
+        ("libx265", &["-b:v", "4000k"]),
+        ("libsvtav1", &["-b:v", "4000k"]),
+        ("libaom-av1", &["-b:v", "4000k"]),
+    ];
+    for (encoder, quality) in cases {
+        let spec = EncodeSpec::new(encoder, bitrate(), "", "", "");
+        assert_eq!(built(spec), expected(encoder, quality), "{}", encoder);
+    }
+}
+
+#[test]
+fn two_pass_of_x265_goes_into_its_params() {
+    let quality = |pass| EncodeQuality::Bitrate {
+        kbps: 4000,
+        pass: Some((pass, "/tmp/stats".to_string())),
+    };
+    let spec = EncodeSpec::new("libx265", quality(1), "", "", "");
+    assert_eq!(
+        built(spec),
+        expected(
+            "libx265",
+            &[
+                "-x265-params",
+                "pass=1:stats=/tmp/stats.log",
+                "-b:v",
+                "4000k"
+            ]
+        )
+    );
+    // added to the -x265-params of the params
+    let spec = EncodeSpec::new("libx265", quality(2), "", "-x265-params aq-mode=3", "");
+    assert_eq!(
+        built(spec),
+        expected(
+            "libx265",
+            &[
+                "-x265-params",
+                "aq-mode=3:pass=2:stats=/tmp/stats.log",
+                "-b:v",
+                "4000k",
+            ]
+        )
+    );
+}
+
+#[test]
+fn two_pass_of_libaom_av1_is_in_its_options() {
+    let spec = EncodeSpec::new(
+        "libaom-av1",
+        EncodeQuality::Bitrate {
+            kbps: 4000,
+            pass: Some((2, "/tmp/stats".to_string())),
+        },
+        "",
+        "",
+        "",
+    );
+    assert_eq!(
+        built(spec),
+        expected(
+            "libaom-av1",
+            &["-b:v", "4000k", "-pass", "2", "-passlogfile", "/tmp/stats"]
+        )
+    );
+    // the hardware encoders have no pass
+    let spec = EncodeSpec::new(
+        "hevc_qsv",
+        EncodeQuality::Bitrate {
+            kbps: 4000,
+            pass: Some((1, "/tmp/stats".to_string())),
+        },
+        "",
+        "",
+        "",
+    );
+    assert_eq!(
+        built(spec),
+        expected(
+            "hevc_qsv",
+            &["-b:v", "4000k", "-maxrate", "6000k", "-bufsize", "8000k"]
+        )
+    );
+}
+
+#[test]
+fn copy_has_no_rate_control() {
+    assert_eq!(built(EncodeSpec::copy()), expected("copy", &[]));
+}
+
+#[test]
+fn the_options_keep_their_places() {
+    let mut spec = EncodeSpec::new(
+        "hevc_qsv",
+        EncodeQuality::Crf(25.0),
+        "slow",
+        "-look_ahead 1",
+        "p010le",
+    );
+    spec.seek = Some(("00:01:00.000".to_string(), "00:02:00.000".to_string()));
+    spec.maps = vec!["-map".to_string(), "0:v:0".to_string()];
+    spec.filters = vec!["yadif".to_string(), "showinfo".to_string()];
+    let args = FfmpegArgs::new("in.mkv", spec, "out.mkv")
+        .input_args(["-hide_banner", "-y"])
+        .extra_inputs(["-i", "subs.srt"])
+        .output_args(["-an", "-f", "matroska"])
+        .build();
+    assert_eq!(
+        strings(args),
+        [
+            "-hide_banner",
+            "-y",
+            "-ss",
+            "00:01:00.000",
+            "-to",
+            "00:02:00.000",
+            "-i",
+            "in.mkv",
+            "-i",
+            "subs.srt",
+            "-map",
+            "0:v:0",
+            "-c:v",
+            "hevc_qsv",
+            "-preset",
+            "slow",
+            "-look_ahead",
+            "1",
+            "-global_quality",
+            "25",
+            "-vf",
+            "yadif,showinfo",
+            "-pix_fmt",
+            "p010le",
+            "-an",
+            "-f",
+            "matroska",
+            "out.mkv",
+        ]
+    );
+}
+
+#[test]
+fn encoder_preset_params_of_each_encoder() {
+    let args = test_args(&[
+        "--preset-x265",
+        "slow",
+        "--params-x265=-tune grain",
+        "--preset-hevc-qsv",
+        "veryslow",
+        "--params-hevc-qsv=-look_ahead 1",
+    ]);
+    assert_eq!(encoder_preset_params(&args), ("slow", "-tune grain"));
+    let args = Args {
+        encoder: "hevc_qsv".to_string(),
+        ..args
+    };
+    assert_eq!(encoder_preset_params(&args), ("veryslow", "-look_ahead 1"));
+}
+
+#[test]
+fn encode_keyint_rounds_the_interval_to_whole_frames() {
+    let args = test_args(&[]);
+    assert_eq!(
+        encode_keyint(23.976, &args),
+        Some(Keyint {
+            gop: 240,
+            min: None,
+        })
+    );
+    let args = test_args(&["--keyint-seconds", "2", "--min-keyint", "24"]);
+    assert_eq!(
+        encode_keyint(59.94, &args),
+        Some(Keyint {
+            gop: 120,
+            min: Some(24),
+        })
+    );
+    // the minimum is at most the interval
+    let args = test_args(&["--keyint-seconds", "2", "--min-keyint", "500"]);
+    assert_eq!(encode_keyint(25.0, &args).unwrap().min, Some(50));
+    // an unknown fps keeps the encoder's default
+    assert_eq!(encode_keyint(0.0, &args), None);
+}
+
+#[test]
+fn keyint_of_each_encoder() {
+    let keyint = Keyint {
+        gop: 240,
+        min: Some(24),
+    };
+    let gop_and_min = ["-g", "240", "-keyint_min", "24"];
+    let cases: [(&str, &[&str], &[&str]); 6] = [
+        (
+            "hevc_nvenc",
+            &["-rc:v", "vbr", "-cq:v", "24", "-qmin", "24", "-qmax", "24"],
+            &gop_and_min,
+        ),
+        ("hevc_qsv", &["-global_quality", "24"], &gop_and_min),
+        ("av1_qsv", &["-global_quality", "24"], &gop_and_min),
+        ("libaom-av1", &["-crf", "24"], &gop_and_min),
+        // svt-av1 has no minimum interval
+        ("libsvtav1", &["-crf", "24"], &["-g", "240"]),
+        // x265 gets the interval in its params as well
+        (
+            "libx265",
+            &["-x265-params", "keyint=240:min-keyint=24", "-crf", "24"],
+            &gop_and_min,
+        ),
+    ];
+    for (encoder, quality, keyint_args) in cases {
+        let mut spec = EncodeSpec::new(encoder, EncodeQuality::Crf(24.0), "", "", "");
+        spec.keyint = Some(keyint);
+        let middle: Vec<&str> = quality.iter().chain(keyint_args).copied().collect();
+        assert_eq!(built(spec), expected(encoder, &middle), "{}", encoder);
+    }
+}
+
+#[test]
+fn keyint_without_a_minimum() {
+    let keyint = Keyint {
+        gop: 100,
+        min: None,
+    };
+    for encoder in ["hevc_nvenc", "hevc_qsv", "libsvtav1", "libx265"] {
+        assert_eq!(keyint.ffmpeg_args(encoder), ["-g", "100"], "{}", encoder);
+    }
+    assert_eq!(keyint.x265_params(), "keyint=100");
+}
+
+#[test]
+fn the_keyint_of_x265_joins_the_x265_params_of_params() {
+    let mut spec = EncodeSpec::new(
+        "libx265",
+        EncodeQuality::Crf(20.0),
+        "",
+        r#"-x265-params "limit-sao:bframes=8""#,
+        "",
+    );
+    spec.keyint = Some(Keyint {
+        gop: 240,
+        min: None,
+    });
+    assert_eq!(
+        built(spec),
+        expected(
+            "libx265",
+            &[
+                "-x265-params",
+                "limit-sao:bframes=8:keyint=240",
+                "-crf",
+                "20",
+                "-g",
+                "240",
+            ]
+        )
+    );
+}
+
+#[test]
+fn the_ab_av1_search_gets_the_same_keyint() {
+    let keyint = Keyint {
+        gop: 240,
+        min: Some(24),
+    };
+    let cases: [(&str, &[&str]); 5] = [
+        (
+            "libx265",
+            &[
+                "--keyint",
+                "240",
+                "--enc",
+                "x265-params=keyint=240:min-keyint=24",
+            ],
+        ),
+        ("hevc_nvenc", &["--keyint", "240", "--enc", "keyint_min=24"]),
+        ("hevc_qsv", &["--keyint", "240", "--enc", "keyint_min=24"]),
+        ("av1_qsv", &["--keyint", "240", "--enc", "keyint_min=24"]),
+        ("libsvtav1", &["--keyint", "240"]),
+    ];
+    for (encoder, expected) in cases {
+        assert_eq!(keyint.ab_av1_args(encoder), expected, "{}", encoder);
+    }
+    let keyint = Keyint {
+        gop: 240,
+        min: None,
+    };
+    assert_eq!(keyint.ab_av1_args("hevc_nvenc"), ["--keyint", "240"]);
+    assert_eq!(
+        keyint.ab_av1_args("libx265"),
+        ["--keyint", "240", "--enc", "x265-params=keyint=240"]
+    );
+}
+
+#[test]
+fn the_cover_art_is_mapped_after_the_video() {
+    let streams = StreamSelection {
+        cover_art: vec![0],
+        ..Default::default()
+    };
+    let (args, _) = stream_output_args(&streams, &[], "aac");
+    assert_eq!(
+        args,
+        [
+            "-map",
+            "0:0",
+            "-c:v:1",
+            "copy",
+            "-disposition:v:1",
+            "attached_pic",
+            "-map_metadata",
+            "0",
+        ]
+    );
+}
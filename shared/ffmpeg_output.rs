@@ -0,0 +1,103 @@
+// Reading the progress lines ffmpeg writes to stderr, and keeping the end of its output for the
+// task logs
+
+use crate::*;
+
+lazy_static! {
+    static ref FRAME: Regex = Regex::new(r"frame=\s*(\d+)").unwrap();
+    static ref FPS: Regex = Regex::new(r"fps=\s*(\d+)").unwrap();
+    static ref SPEED: Regex = Regex::new(r"speed=\s*([\d.]+)x").unwrap();
+    static ref TOTAL_SIZE: Regex = Regex::new(r"total_size=(\d+)").unwrap();
+    static ref BITRATE: Regex = Regex::new(r"bitrate=\s*([\d.]+)kbits/s").unwrap();
+    static ref OUT_TIME_US: Regex = Regex::new(r"out_time_us=(\d+)").unwrap();
+}
+
+// The last frame number in the ffmpeg output, a read can hold several progress blocks
+pub(crate) fn parse_frame_from_output(output: &str) -> Option<u64> {
+    FRAME
+        .captures_iter(output)
+        .last()
+        .and_then(|caps| caps[1].parse().ok())
+}
+
+// The output size in bytes of the last -progress block in output, total_size=N/A until the muxer
+// wrote something
+pub(crate) fn parse_total_size(output: &str) -> Option<u64> {
+    TOTAL_SIZE
+        .captures_iter(output)
+        .last()
+        .and_then(|caps| caps[1].parse().ok())
+}
+
+pub(crate) fn parse_fps_from_output(output: &str) -> Option<u64> {
+    FPS.captures_iter(output)
+        .last()
+        .and_then(|caps| caps[1].parse().ok())
+}
+
+// The encode speed of the last progress block, like 1.23 of speed=1.23x
+pub(crate) fn parse_speed_from_output(output: &str) -> Option<String> {
+    SPEED
+        .captures_iter(output)
+        .last()
+        .map(|caps| caps[1].to_string())
+}
+
+// The output bitrate in kbit/s of the last progress block, bitrate=N/A until the first packet
+pub(crate) fn parse_bitrate_from_output(output: &str) -> Option<f64> {
+    BITRATE
+        .captures_iter(output)
+        .last()
+        .and_then(|caps| caps[1].parse().ok())
+}
+
+// The position of the encode in microseconds of the last -progress block
+pub(crate) fn parse_out_time_us(output: &str) -> Option<u64> {
+    OUT_TIME_US
+        .captures_iter(output)
+        .last()
+        .and_then(|caps| caps[1].parse().ok())
+}
+
+// A key=value line of ffmpeg's -progress output, the stats line has spaces in it
+fn is_progress_line(line: &str) -> bool {
+    let line = line.trim();
+    match line.split_once('=') {
+        Some((key, value)) => {
+            !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !value.contains(' ')
+        }
+        None => false,
+    }
+}
+
+// Append a chunk of stderr to tail, keeping only the last STDERR_TAIL_LINES lines
+// ffmpeg ends its progress lines with \r, so they are counted as lines too, the -progress lines
+// are left out, an unfinished last line is kept until the rest of it arrives
+pub(crate) fn push_stderr_tail(tail: &mut String, chunk: &str) {
+    tail.push_str(&chunk.replace('\r', "\n"));
+    let ends_with_newline = tail.ends_with('\n');
+    let all_lines: Vec<&str> = tail
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let count = all_lines.len();
+    let lines: Vec<&str> = all_lines
+        .iter()
+        .enumerate()
+        .filter(|(i, line)| (!ends_with_newline && i + 1 == count) || !is_progress_line(line))
+        .map(|(_, line)| *line)
+        .collect();
+    if lines.len() > STDERR_TAIL_LINES || lines.len() < count {
+        let start = lines.len().saturating_sub(STDERR_TAIL_LINES);
+        let mut kept = lines[start..].join("\n");
+        if ends_with_newline {
+            kept.push('\n');
+        }
+        *tail = kept;
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,84 @@
+use super::*;
+
+// Two stats lines of the same read, ffmpeg pads the values to a width
+const STATS: &str = "frame=   48 fps= 24 q=28.0 size=     256kB time=00:00:02.00 bitrate=1048.6kbits/s speed=0.98x\rframe=  120 fps= 30 q=28.0 size=     768kB time=00:00:05.00 bitrate=1258.3kbits/s speed=1.23x\r";
+
+// Two blocks of -progress pipe:2
+const PROGRESS: &str = "frame=48\nfps=24.00\ntotal_size=N/A\nout_time_us=2000000\nprogress=continue\nframe=120\nfps=30.00\ntotal_size=786432\nout_time_us=5000000\nprogress=continue\n";
+
+#[test]
+fn parse_frame_from_output_takes_the_last_block() {
+    assert_eq!(parse_frame_from_output(STATS), Some(120));
+    assert_eq!(parse_frame_from_output(PROGRESS), Some(120));
+}
+
+#[test]
+fn parse_frame_from_output_without_a_frame() {
+    assert_eq!(parse_frame_from_output(""), None);
+    assert_eq!(
+        parse_frame_from_output("Press [q] to stop, [?] for help"),
+        None
+    );
+}
+
+#[test]
+fn parse_total_size_skips_na() {
+    assert_eq!(parse_total_size(PROGRESS), Some(786432));
+    assert_eq!(parse_total_size("total_size=N/A\n"), None);
+}
+
+#[test]
+fn parse_stats_values() {
+    assert_eq!(parse_fps_from_output(STATS), Some(30));
+    assert_eq!(parse_speed_from_output(STATS), Some("1.23".to_string()));
+    assert_eq!(parse_bitrate_from_output(STATS), Some(1258.3));
+    assert_eq!(parse_bitrate_from_output("bitrate=N/A speed=N/A"), None);
+    assert_eq!(parse_out_time_us(PROGRESS), Some(5000000));
+}
+
+#[test]
+fn push_stderr_tail_leaves_out_the_progress_lines() {
+    let mut tail = String::new();
+    push_stderr_tail(&mut tail, "Input #0, matroska\n");
+    push_stderr_tail(&mut tail, PROGRESS);
+    push_stderr_tail(&mut tail, "Conversion failed!\n");
+    assert_eq!(tail, "Input #0, matroska\nConversion failed!\n");
+}
+
+#[test]
+fn push_stderr_tail_keeps_an_unfinished_line() {
+    let mut tail = String::new();
+    push_stderr_tail(&mut tail, "Error while decoding stream #0:0: Invalid");
+    push_stderr_tail(&mut tail, " data found\n");
+    assert_eq!(
+        tail,
+        "Error while decoding stream #0:0: Invalid data found\n"
+    );
+}
+
+#[test]
+fn push_stderr_tail_keeps_the_last_lines() {
+    let mut tail = String::new();
+    for line in 0..STDERR_TAIL_LINES + 10 {
+        push_stderr_tail(&mut tail, &format!("line {}\n", line));
+    }
+    let lines: Vec<&str> = tail.lines().collect();
+    assert_eq!(lines.len(), STDERR_TAIL_LINES);
+    assert_eq!(lines[0], "line 10");
+    assert_eq!(
+        lines.last().copied(),
+        Some(format!("line {}", STDERR_TAIL_LINES + 9).as_str())
+    );
+}
+
+#[test]
+fn parse_total_size_in_bytes_next_to_the_stats() {
+    // the stats line of the end of the encode has Lsize in kB, the bytes come from -progress
+    let output = "frame= 2000 fps= 48 q=-0.0 Lsize= 5242880kB time=00:01:23.33 bitrate=N/A speed=2x\ntotal_size=5368709120\nprogress=end\n";
+    assert_eq!(parse_total_size(output), Some(5_368_709_120));
+    assert_eq!(
+        parse_total_size("total_size=1000\ntotal_size=N/A\ntotal_size=2000\n"),
+        Some(2000)
+    );
+    assert_eq!(parse_total_size("Lsize= 5242880kB\n"), None);
+}
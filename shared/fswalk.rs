@@ -0,0 +1,560 @@
+// Files on disk: input paths and folder walks, output names, existing outputs and free space
+
+use crate::*;
+
+// Nearest existing directory of path, which does not have to exist yet
+fn existing_ancestor(path: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(absolute_path(path));
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .map(|ancestor| ancestor.to_path_buf())
+}
+
+// Available bytes on the volume holding path
+pub fn available_space(path: &str) -> Option<u64> {
+    fs2::available_space(existing_ancestor(path)?).ok()
+}
+
+// Check that needed_mb fits on the volume of path with min_free_space GB to spare
+pub fn check_free_space(path: &str, needed_mb: f64, min_free_space: f64) -> Result<(), String> {
+    let available_mb = match available_space(path) {
+        Some(bytes) => bytes_to_mb(bytes),
+        None => {
+            warn!("Could not read the free space of {}", path);
+            return Ok(());
+        }
+    };
+    let margin_mb = min_free_space * 1024.0;
+    if needed_mb + margin_mb > available_mb {
+        return Err(format!(
+            "{:.2} MB needed on the volume of {} but only {:.2} MB are free (keeping {:.2} GB)",
+            needed_mb, path, available_mb, min_free_space
+        ));
+    }
+    Ok(())
+}
+
+// Space check done before a file is processed: the predicted output (or the source size) on the
+// output volume, in chunked mode the scene files (about the source size) and the merged output
+// both go to the working directory
+pub fn check_file_free_space(
+    file: &str,
+    output_path: &str,
+    predicted_mb: Option<f64>,
+    chunked: bool,
+    min_free_space: f64,
+) -> Result<(), String> {
+    let source_mb = fs::metadata(file)
+        .map(|metadata| bytes_to_mb(metadata.len()))
+        .unwrap_or(0.0);
+    let output_mb = predicted_mb.unwrap_or(source_mb);
+    if chunked {
+        return check_free_space(".", source_mb + output_mb, min_free_space);
+    }
+    check_free_space(output_path, output_mb, min_free_space)
+}
+
+// Leave a file for a later run when it does not fit on the disk
+pub fn skip_no_space(file: &str, reason: &str) {
+    warn!("{}", format!("Skipping {}: {}", file, reason).yellow());
+    if let Err(e) = set_db_status(file, "no_space") {
+        error!("Failed to update status of {}: {}", file, e);
+    }
+}
+
+// Stem and extension of file for the output names, names like .hidden keep the whole name as the
+// stem and a file without an extension is muxed to mkv
+pub(crate) fn output_name_parts(file: &str) -> (String, String) {
+    let path = Path::new(file);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "output".to_string());
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_string())
+        .unwrap_or_else(|| "mkv".to_string());
+    (stem, extension)
+}
+
+// output_filename in output_folder
+pub(crate) fn output_folder_path(output_folder: &str, output_filename: &str) -> String {
+    Path::new(output_folder)
+        .join(output_filename)
+        .display()
+        .to_string()
+}
+
+// Name of the file at path, the path itself when it has none ("..", "/")
+pub fn file_name_of(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+// path as text, ffmpeg, ffprobe and the database get the paths as strings
+// A name that is not valid UTF-8 can't be passed on without naming another file, it is skipped
+pub(crate) fn utf8_path(path: &Path) -> Option<String> {
+    let utf8 = path.to_str().map(String::from);
+    if utf8.is_none() {
+        warn!(
+            "Skipping {}: the name is not valid UTF-8",
+            path.to_string_lossy()
+        );
+    }
+    utf8
+}
+
+pub fn set_output_folder_filename(
+    file: &str,
+    encoder: &str,
+    final_vmaf: &f32,
+    target_crf: &str,
+    output_folder: &str,
+) -> String {
+    // trim target_crf
+    let trim_target_crf = target_crf.trim();
+
+    let (stem, extension) = output_name_parts(file);
+
+    // add the codec and the vmaf score to the output filename
+    let output_filename = format!(
+        "{}.{}.vmaf{}.crf{}.{}",
+        stem, encoder, final_vmaf, trim_target_crf, extension
+    );
+
+    // return the output folder and filename
+    output_folder_path(output_folder, &output_filename)
+}
+
+// Output filename of a --target-size encode, like "Movie.libx265.size4000MB.mkv"
+pub fn set_output_folder_filename_size(
+    file: &str,
+    encoder: &str,
+    target_size_mb: f64,
+    output_folder: &str,
+) -> String {
+    let (stem, extension) = output_name_parts(file);
+
+    // add the codec and the target size to the output filename
+    let output_filename = format!(
+        "{}.{}.size{}MB.{}",
+        stem, encoder, target_size_mb, extension
+    );
+
+    output_folder_path(output_folder, &output_filename)
+}
+
+// Split an output filename made by set_output_folder_filename, like "Movie.libx265.vmaf97.crf21.mkv"
+// or "Movie.libx265.vmaf95.5.crf21.mkv"
+// Returns (stem, encoder, vmaf, crf, extension)
+pub fn parse_output_filename(filename: &str) -> Option<(String, String, f32, String, String)> {
+    let re = Regex::new(
+        r"^(?P<stem>.+)\.(?P<encoder>[^.]+)\.vmaf(?P<vmaf>\d+(?:\.\d+)?)\.crf(?P<crf>\d+(?:\.\d+)?)\.(?P<extension>[^.]+)$",
+    )
+    .unwrap();
+    let caps = re.captures(filename)?;
+    Some((
+        caps["stem"].to_string(),
+        caps["encoder"].to_string(),
+        caps["vmaf"].parse::<f32>().ok()?,
+        caps["crf"].to_string(),
+        caps["extension"].to_string(),
+    ))
+}
+
+// Look in the output folder for an encode of this file from a previous run
+// It has to be made with the same encoder, reach at least the vmaf target, have the same duration and decode without errors
+// Returns (output path, vmaf, crf)
+pub fn find_existing_output(
+    file: &str,
+    encoder: &str,
+    vmaf_target: f32,
+    output_folder: &str,
+) -> Option<(String, f32, String)> {
+    let stem = Path::new(file).file_stem()?.to_str()?.to_string();
+    let entries = fs::read_dir(output_folder).ok()?;
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let filename = match path.file_name().and_then(|name| name.to_str()) {
+            Some(filename) => filename.to_string(),
+            None => continue,
+        };
+        // the .part of an encode of this file a crashed run did not finish, never an output
+        if let Some(part_of) = part_output(&filename) {
+            if parse_output_filename(part_of).map_or(false, |parsed| parsed.0 == stem) {
+                remove_stale_part(&path.display().to_string());
+            }
+            continue;
+        }
+        let (output_stem, output_encoder, vmaf, crf, _) = match parse_output_filename(&filename) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        if output_stem != stem || output_encoder != encoder || vmaf < vmaf_target {
+            continue;
+        }
+
+        let output = path.display().to_string();
+
+        // the duration has to match the input, within a second
+        let input_duration = get_duration(file).unwrap_or(0.0);
+        let output_duration = get_duration(&output).unwrap_or(0.0);
+        if output_duration == 0.0 || (input_duration - output_duration).abs() > 1.0 {
+            warn!(
+                "{} has a different duration than {} ({}s / {}s), encoding again",
+                output, file, output_duration, input_duration
+            );
+            continue;
+        }
+
+        // decode the first seconds, to catch truncated or broken files
+        let decode = ffmpeg_command()
+            .arg("-v")
+            .arg("error")
+            .arg("-t")
+            .arg("10")
+            .arg("-i")
+            .arg(&output)
+            .arg("-f")
+            .arg("null")
+            .arg("-")
+            .output();
+        match decode {
+            Ok(decode) if decode.status.success() && decode.stderr.is_empty() => {
+                return Some((output, vmaf, crf));
+            }
+            _ => warn!("{} failed the decode check, encoding again", output),
+        }
+    }
+
+    None
+}
+
+pub fn set_output_folder_filename_audio(file: &str, output_folder: &str) -> String {
+    let (stem, extension) = output_name_parts(file);
+
+    // add the codec and the vmaf score to the output filename
+    let output_filename = format!("{}.{}", stem, extension);
+
+    // return the output folder and filename
+    output_folder_path(output_folder, &output_filename)
+}
+
+// --mirror-structure: the folders the relative paths start from, the -i folders, the folder of an
+// -i file and the part of a glob pattern before its first wildcard
+pub fn scan_roots(inputs: &[String]) -> Vec<String> {
+    inputs
+        .iter()
+        .map(|input| {
+            let path = Path::new(input);
+            let root = if is_glob_pattern(input) && !path.exists() {
+                path.components()
+                    .take_while(|c| !is_glob_pattern(&c.as_os_str().to_string_lossy()))
+                    .collect::<PathBuf>()
+            } else if path.is_file() {
+                path.parent().map(Path::to_path_buf).unwrap_or_default()
+            } else {
+                path.to_path_buf()
+            };
+            absolute_path(root)
+        })
+        .collect()
+}
+
+// The components of an absolute path to compare it with a scan root, without the \\?\ of
+// long_path, and on windows without case: C:\TV and c:\tv are the same folder, as are
+// \\?\UNC\nas\media and \\nas\media
+fn root_components(path: &str) -> Vec<(String, String)> {
+    let path = if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", share)
+    } else {
+        path.strip_prefix(r"\\?\").unwrap_or(path).to_string()
+    };
+    Path::new(&path)
+        .components()
+        .map(|c| {
+            let name = c.as_os_str().to_string_lossy().to_string();
+            let key = if cfg!(windows) {
+                name.to_lowercase()
+            } else {
+                name.clone()
+            };
+            (key, name)
+        })
+        .collect()
+}
+
+// The path of file below the deepest scan root holding it, None when no root holds it
+pub fn relative_to_root(file: &str, roots: &[String]) -> Option<PathBuf> {
+    let file = root_components(&absolute_path(file));
+    roots
+        .iter()
+        .map(|root| root_components(root))
+        .filter(|root| root.len() < file.len())
+        .filter(|root| root.iter().zip(&file).all(|(a, b)| a.0 == b.0))
+        .max_by_key(|root| root.len())
+        .map(|root| file[root.len()..].iter().map(|c| c.1.as_str()).collect())
+}
+
+// path as the summary and the report show it with --mirror-structure: below its scan root, an output
+// below output_root, and as it is outside of both
+pub fn mirrored_display_path(path: &str, roots: &[String], output_root: &str) -> String {
+    let output_root = [absolute_path(output_root)];
+    relative_to_root(path, roots)
+        .or_else(|| relative_to_root(path, &output_root))
+        .map(|relative| relative.display().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+// A folder name that every filesystem takes: the characters windows forbids become _, trailing dots
+// and spaces are cut and the reserved device names get a _
+fn sanitize_path_component(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' | '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+    let stem = name.split('.').next().unwrap_or("").to_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.len() == 4
+            && stem.as_bytes()[3].is_ascii_digit());
+    match name {
+        "" => "_".to_string(),
+        name if reserved => format!("{}_", name),
+        name => name.to_string(),
+    }
+}
+
+// The output folder of file with --mirror-structure, output_root and the folders of file below
+// its scan root: /media/TV/Show/Season 01/E01.mkv from -i /media/TV goes to
+// <output_root>/Show/Season 01. A file outside the roots goes to output_root
+pub fn mirrored_output_folder(file: &str, roots: &[String], output_root: &str) -> String {
+    let mut folder = PathBuf::from(output_root);
+    if let Some(relative) = relative_to_root(file, roots) {
+        if let Some(parent) = relative.parent() {
+            for component in parent.components() {
+                folder.push(sanitize_path_component(
+                    &component.as_os_str().to_string_lossy(),
+                ));
+            }
+        }
+    }
+    folder.display().to_string()
+}
+
+pub fn absolute_path(path: impl AsRef<Path>) -> String {
+    let path = path.as_ref();
+
+    let absolute_path = (if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()
+            .expect("could not get current path")
+            .join(path)
+    })
+    .clean();
+
+    long_path(absolute_path.to_string_lossy().to_string())
+}
+
+// Windows paths past MAX_PATH (260) need the \\?\ prefix, deep TV show folders reach it
+#[cfg(windows)]
+fn long_path(path: String) -> String {
+    const MAX_PATH: usize = 260;
+    if path.len() < MAX_PATH || path.starts_with(r"\\?\") {
+        path
+    } else if let Some(share) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", path)
+    }
+}
+
+#[cfg(not(windows))]
+fn long_path(path: String) -> String {
+    path
+}
+
+pub fn walk_count(dir: &String, exclude: &[String]) -> usize {
+    let excludes = ScanExcludes::load(dir, exclude);
+    let scan_bar = progress_spinner();
+    let scan_style =
+        "[scan][{elapsed_precise}][{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} [scanned files] eta: {eta:<7}";
+    scan_bar.set_style(
+        ProgressStyle::default_spinner()
+            .template(scan_style)
+            .unwrap(),
+    );
+
+    let dir_files: Vec<_> = WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| !excludes.is_excluded(e.path(), e.file_type().is_dir()))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .collect();
+
+    let dir_files_count = dir_files.len();
+    scan_bar.set_length(dir_files_count as u64);
+
+    let count = AtomicUsize::new(0);
+
+    dir_files.into_par_iter().for_each(|e| {
+        let mime = find_mimetype(e.path());
+        if mime == "VIDEO" {
+            scan_bar.inc(1);
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    let count = count.load(Ordering::Relaxed);
+
+    info!("Found {} valid video files in folder!", count);
+    count
+}
+
+pub fn walk_files(dir: &String, exclude: &[String]) -> Vec<String> {
+    let excludes = ScanExcludes::load(dir, exclude);
+    let mut arr = vec![];
+    let mut index = 0;
+
+    // excluded folders are not entered at all
+    for e in WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| !excludes.is_excluded(e.path(), e.file_type().is_dir()))
+        .filter_map(|e| e.ok())
+    {
+        if e.file_type().is_file() {
+            let mime = find_mimetype(e.path());
+            if mime.to_string() == "VIDEO" {
+                if let Some(path) = utf8_path(e.path()) {
+                    arr.insert(index, path);
+                    index = index + 1;
+                }
+            }
+        }
+    }
+    return arr;
+}
+
+pub fn is_video_file(path: &Path) -> bool {
+    find_mimetype(path) == "VIDEO"
+}
+
+fn find_mimetype(filename: &Path) -> &'static str {
+    let mut mime_types = HashMap::new();
+    mime_types.insert("mkv", "VIDEO");
+    mime_types.insert("avi", "VIDEO");
+    mime_types.insert("mp4", "VIDEO");
+    mime_types.insert("divx", "VIDEO");
+    mime_types.insert("flv", "VIDEO");
+    mime_types.insert("m4v", "VIDEO");
+    mime_types.insert("mov", "VIDEO");
+    mime_types.insert("ogv", "VIDEO");
+    mime_types.insert("ts", "VIDEO");
+    mime_types.insert("webm", "VIDEO");
+    mime_types.insert("wmv", "VIDEO");
+
+    let extension = filename
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+
+    mime_types.get(extension).unwrap_or(&"OTHER")
+}
+
+fn is_glob_pattern(s: &str) -> bool {
+    s.contains('*') || s.contains('?') || s.contains('[')
+}
+
+// Resolve --input-list, repeated -i and glob patterns into one deduplicated list of video files
+// Returns None when a single file or folder was given, so the caller keeps walking the folder
+// Entries that don't exist or fail input_validation are reported and skipped
+pub fn resolve_input_list(args: &Args) -> Option<Vec<String>> {
+    let mut entries: Vec<String> = args.inputpath.clone();
+
+    if let Some(input_list) = &args.input_list {
+        match fs::read_to_string(input_list) {
+            Ok(content) => {
+                for line in content.lines() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    entries.push(line.to_string());
+                }
+            }
+            Err(e) => error!("Failed to read input list {}: {}", input_list, e),
+        }
+    } else if entries.len() == 1 && !is_glob_pattern(&entries[0]) {
+        return None;
+    }
+
+    Some(resolve_input_paths(entries, &args.exclude))
+}
+
+// Expand glob patterns and folders of a list of input paths, and deduplicate the resulting video files
+pub fn resolve_input_paths(entries: Vec<String>, exclude: &[String]) -> Vec<String> {
+    // expand glob patterns, paths without wildcards are kept as they are
+    let mut candidates: Vec<String> = Vec::new();
+    for entry in entries {
+        if is_glob_pattern(&entry) && !Path::new(&entry).exists() {
+            match glob::glob(&entry) {
+                Ok(paths) => {
+                    let mut matched = 0;
+                    for path in paths.filter_map(|p| p.ok()) {
+                        if let Some(path) = utf8_path(&path) {
+                            candidates.push(path);
+                            matched += 1;
+                        }
+                    }
+                    if matched == 0 {
+                        warn!("No files match {}, skipping", entry);
+                    }
+                }
+                Err(e) => warn!("Invalid glob pattern {}: {}", entry, e),
+            }
+        } else {
+            candidates.push(entry);
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut files: Vec<String> = Vec::new();
+    for candidate in candidates {
+        let candidate = match input_validation(&candidate) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("Skipping {}: {}", candidate, e);
+                continue;
+            }
+        };
+
+        // folders in the list are expanded to the video files they contain
+        let paths = if Path::new(&candidate).is_dir() {
+            walk_files(&candidate, exclude)
+        } else {
+            vec![candidate]
+        };
+
+        for path in paths {
+            let path = absolute_path(&path);
+            if seen.insert(path.clone()) {
+                files.push(path);
+            }
+        }
+    }
+
+    info!("Found {} valid video files in input list!", files.len());
+    files
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,444 @@
+use super::*;
+
+#[test]
+fn find_mimetype_of_the_video_extensions() {
+    for name in [
+        "a.mkv", "a.avi", "a.mp4", "a.divx", "a.flv", "a.m4v", "a.mov", "a.ogv", "a.ts", "a.webm",
+        "a.wmv",
+    ] {
+        assert_eq!(find_mimetype(Path::new(name)), "VIDEO", "{}", name);
+    }
+}
+
+#[test]
+fn find_mimetype_of_other_files() {
+    for name in ["a.srt", "a.nfo", "a.jpg", "a", "folder/.mkv", "a.MKV.txt"] {
+        assert_eq!(find_mimetype(Path::new(name)), "OTHER", "{}", name);
+    }
+}
+
+#[test]
+fn is_video_file_keeps_the_dots_of_the_stem() {
+    assert!(is_video_file(Path::new("/media/Show.S01E01.1080p.mkv")));
+    assert!(!is_video_file(Path::new("/media/Show.S01E01.mkv.part")));
+}
+
+#[test]
+fn parse_output_filename_reads_every_part() {
+    let parsed = |stem: &str, encoder: &str, vmaf: f32, crf: &str, extension: &str| {
+        Some((
+            stem.to_string(),
+            encoder.to_string(),
+            vmaf,
+            crf.to_string(),
+            extension.to_string(),
+        ))
+    };
+    let cases = [
+        (
+            "Movie.libx265.vmaf97.crf21.mkv",
+            parsed("Movie", "libx265", 97.0, "21", "mkv"),
+        ),
+        // a fractional vmaf and crf
+        (
+            "Movie.hevc_qsv.vmaf95.5.crf22.5.mp4",
+            parsed("Movie", "hevc_qsv", 95.5, "22.5", "mp4"),
+        ),
+        // the dots of the stem stay in it
+        (
+            "Show.S01E01.1080p.av1_nvenc.vmaf96.crf30.mkv",
+            parsed("Show.S01E01.1080p", "av1_nvenc", 96.0, "30", "mkv"),
+        ),
+        (
+            "A stem with spaces.libsvtav1.vmaf93.crf0.webm",
+            parsed("A stem with spaces", "libsvtav1", 93.0, "0", "webm"),
+        ),
+    ];
+    for (filename, expected) in cases {
+        assert_eq!(parse_output_filename(filename), expected, "{}", filename);
+    }
+}
+
+#[test]
+fn parse_output_filename_refuses_malformed_names() {
+    for filename in [
+        "",
+        "Movie.mkv",
+        // a part missing
+        "libx265.vmaf97.crf21.mkv",
+        "Movie.vmaf97.crf21.mkv",
+        "Movie.libx265.crf21.mkv",
+        "Movie.libx265.vmaf97.mkv",
+        "Movie.libx265.vmaf97.crf21",
+        // the parts out of order
+        "Movie.libx265.crf21.vmaf97.mkv",
+        // not numbers
+        "Movie.libx265.vmafhigh.crf21.mkv",
+        "Movie.libx265.vmaf97.crf.mkv",
+        "Movie.libx265.vmaf97.crf-1.mkv",
+        "Movie.libx265.vmaf97..crf21.mkv",
+        "Movie.libx265.vmaf.5.crf21.mkv",
+        // a --target-size output and a .part
+        "Movie.libx265.size4000MB.mkv",
+        "Movie.libx265.vmaf97.crf21.mkv.part",
+    ] {
+        assert_eq!(parse_output_filename(filename), None, "{}", filename);
+    }
+}
+
+#[test]
+fn parse_output_filename_reads_back_set_output_folder_filename() {
+    let output = set_output_folder_filename(
+        "/media/Show.S01E01.mkv",
+        "hevc_nvenc",
+        &95.5,
+        " 24 ",
+        "/out",
+    );
+    let filename = file_name_of(&output);
+    assert_eq!(
+        parse_output_filename(&filename),
+        Some((
+            "Show.S01E01".to_string(),
+            "hevc_nvenc".to_string(),
+            95.5,
+            "24".to_string(),
+            "mkv".to_string(),
+        ))
+    );
+}
+
+#[test]
+fn a_target_size_output_is_named_after_the_size() {
+    let output =
+        set_output_folder_filename_size("/media/Show.S01E01.mkv", "libx265", 4000.0, "/out");
+    assert_eq!(file_name_of(&output), "Show.S01E01.libx265.size4000MB.mkv");
+    let output = set_output_folder_filename_size("/media/Movie.mp4", "hevc_nvenc", 1500.5, "/out");
+    assert_eq!(file_name_of(&output), "Movie.hevc_nvenc.size1500.5MB.mp4");
+    // not the name of a crf/vmaf encode
+    assert_eq!(parse_output_filename(&file_name_of(&output)), None);
+}
+
+// The video names of the exotic names fixture, and the names of it that are not videos
+const EXOTIC_VIDEOS: [&str; 5] = [
+    "日本語 [x264] 'quote'.mkv",
+    "über.mkv",
+    "emoji 🎬.mkv",
+    "trailing space .mkv",
+    ".hidden.mkv",
+];
+const EXOTIC_OTHERS: [&str; 2] = ["noext", ".hidden"];
+
+// A folder with the exotic names, the videos in a season folder of a show with a unicode name
+fn exotic_names_fixture(name: &str) -> (String, Vec<String>) {
+    let dir = temp_path(name);
+    let season = dir.join("Shōgun (2024)").join("Season 01");
+    fs::create_dir_all(&season).unwrap();
+    let mut videos = vec![];
+    for name in EXOTIC_VIDEOS {
+        let path = season.join(name);
+        fs::write(&path, b"video").unwrap();
+        videos.push(path.to_string_lossy().to_string());
+    }
+    for name in EXOTIC_OTHERS {
+        fs::write(season.join(name), b"other").unwrap();
+    }
+    videos.sort();
+    (dir.to_string_lossy().to_string(), videos)
+}
+
+#[test]
+fn a_scan_keeps_every_exotic_name() {
+    let (dir, videos) = exotic_names_fixture("exotic-scan");
+
+    let mut found = walk_files(&dir, &[]);
+    found.sort();
+    assert_eq!(found, videos);
+    assert_eq!(walk_count(&dir, &[]), videos.len());
+
+    let mut resolved = resolve_input_paths(vec![dir.clone()], &[]);
+    resolved.sort();
+    assert_eq!(resolved, videos);
+
+    // a name with glob characters given as it is names its own file
+    let bracketed = videos
+        .iter()
+        .find(|video| video.contains("[x264]"))
+        .unwrap()
+        .clone();
+    assert_eq!(
+        resolve_input_paths(vec![bracketed.clone()], &[]),
+        vec![bracketed]
+    );
+}
+
+#[test]
+fn the_outputs_of_exotic_names_read_back() {
+    let (_, videos) = exotic_names_fixture("exotic-outputs");
+    for video in &videos {
+        let output = set_output_folder_filename(video, "libx265", &95.0, "24", "/out");
+        let (stem, extension) = output_name_parts(video);
+        assert_eq!(
+            parse_output_filename(&file_name_of(&output)),
+            Some((
+                stem,
+                "libx265".to_string(),
+                95.0,
+                "24".to_string(),
+                extension
+            )),
+            "{}",
+            video
+        );
+    }
+}
+
+#[test]
+fn output_name_parts_of_names_without_a_stem_or_an_extension() {
+    let cases = [
+        ("/media/noext", ("noext", "mkv")),
+        ("/media/.hidden", (".hidden", "mkv")),
+        ("/media/.hidden.mkv", (".hidden", "mkv")),
+        ("/media/trailing space .mkv", ("trailing space ", "mkv")),
+        (
+            "/media/日本語 [x264] 'quote'.mp4",
+            ("日本語 [x264] 'quote'", "mp4"),
+        ),
+        ("/", ("output", "mkv")),
+        ("", ("output", "mkv")),
+    ];
+    for (file, (stem, extension)) in cases {
+        assert_eq!(
+            output_name_parts(file),
+            (stem.to_string(), extension.to_string()),
+            "{}",
+            file
+        );
+    }
+    assert_eq!(
+        file_name_of(&set_output_folder_filename(
+            "/media/noext",
+            "libx265",
+            &95.0,
+            "24",
+            "/out"
+        )),
+        "noext.libx265.vmaf95.crf24.mkv"
+    );
+    assert_eq!(
+        file_name_of(&set_output_folder_filename_audio("/media/.hidden", "/out")),
+        file_name_of(&set_output_folder_filename_audio(
+            "/media/.hidden.mkv",
+            "/out"
+        ))
+    );
+}
+
+#[test]
+fn absolute_path_of_unicode_names() {
+    let relative = absolute_path("Shōgun/../über.mkv");
+    assert!(Path::new(&relative).is_absolute());
+    assert_eq!(relative, absolute_path("über.mkv"));
+    assert_eq!(file_name_of(&relative), "über.mkv");
+    let absolute = temp_path("exotic-absolute").join("emoji 🎬.mkv");
+    assert_eq!(
+        absolute_path(&absolute),
+        absolute.to_string_lossy().to_string()
+    );
+}
+
+#[cfg(not(windows))]
+#[test]
+fn long_paths_are_kept_outside_of_windows() {
+    let deep = format!("/media/{}episode.mkv", "Season 01/".repeat(40));
+    assert_eq!(absolute_path(&deep), deep);
+}
+
+#[cfg(windows)]
+#[test]
+fn long_paths_get_the_windows_prefix() {
+    let deep = format!(r"C:\media\{}episode.mkv", r"Season 01\".repeat(40));
+    assert_eq!(long_path(deep.clone()), format!(r"\\?\{}", deep));
+    assert_eq!(
+        long_path(format!(r"\\?\{}", deep)),
+        format!(r"\\?\{}", deep)
+    );
+    let share = format!(r"\\nas\media\{}episode.mkv", r"Season 01\".repeat(40));
+    assert_eq!(
+        long_path(share.clone()),
+        format!(r"\\?\UNC\{}", &share[2..])
+    );
+    assert_eq!(long_path(r"C:\media\a.mkv".to_string()), r"C:\media\a.mkv");
+}
+
+#[cfg(unix)]
+#[test]
+fn a_name_that_is_not_utf8_is_skipped_without_a_panic() {
+    use std::os::unix::ffi::OsStrExt;
+    let (dir, videos) = exotic_names_fixture("exotic-not-utf8");
+    let invalid = Path::new(&dir).join(std::ffi::OsStr::from_bytes(b"bad \xff name.mkv"));
+    // some file systems refuse such names
+    if fs::write(&invalid, b"video").is_err() {
+        return;
+    }
+
+    assert_eq!(utf8_path(&invalid), None);
+    let mut found = walk_files(&dir, &[]);
+    found.sort();
+    assert_eq!(found, videos);
+    assert!(absolute_path(&invalid).ends_with("bad \u{FFFD} name.mkv"));
+}
+
+// A TV library of two shows: seasons that share their episode names, a show folder with characters
+// windows forbids and an episode at the root
+fn tv_library(name: &str) -> PathBuf {
+    let tv = temp_path(name).join("TV");
+    for episode in [
+        "Show/Season 01/E01.mkv",
+        "Show/Season 01/E02.mkv",
+        "Show/Season 02/E01.mkv",
+        "What If: Really?/Season 1./E01.mkv",
+        "Pilot.mkv",
+    ] {
+        let path = tv.join(episode);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"video").unwrap();
+    }
+    tv
+}
+
+#[test]
+fn mirrored_outputs_keep_the_folders_without_collisions() {
+    let tv = tv_library("mirror-tree");
+    let tv_arg = tv.to_string_lossy().to_string();
+    let roots = scan_roots(std::slice::from_ref(&tv_arg));
+    assert_eq!(roots, vec![absolute_path(&tv)]);
+
+    let mut outputs: Vec<String> = walk_files(&tv_arg, &[])
+        .iter()
+        .map(|file| {
+            let folder = mirrored_output_folder(file, &roots, "/out");
+            set_output_folder_filename(file, "libx265", &95.0, "24", &folder)
+        })
+        .collect();
+    outputs.sort();
+    let expected: Vec<String> = [
+        "/out/Pilot.libx265.vmaf95.crf24.mkv",
+        "/out/Show/Season 01/E01.libx265.vmaf95.crf24.mkv",
+        "/out/Show/Season 01/E02.libx265.vmaf95.crf24.mkv",
+        "/out/Show/Season 02/E01.libx265.vmaf95.crf24.mkv",
+        "/out/What If_ Really_/Season 1/E01.libx265.vmaf95.crf24.mkv",
+    ]
+    .iter()
+    .map(|output| Path::new(output).display().to_string())
+    .collect();
+    assert_eq!(outputs, expected);
+}
+
+#[test]
+fn scan_roots_of_folders_files_and_globs() {
+    let tv = tv_library("mirror-roots");
+    let episode = tv.join("Show").join("Season 01").join("E01.mkv");
+    let glob = tv.join("Show").join("Season *").join("*.mkv");
+    let roots = scan_roots(&[
+        tv.to_string_lossy().to_string(),
+        episode.to_string_lossy().to_string(),
+        glob.to_string_lossy().to_string(),
+    ]);
+    assert_eq!(
+        roots,
+        vec![
+            absolute_path(&tv),
+            absolute_path(tv.join("Show").join("Season 01")),
+            absolute_path(tv.join("Show")),
+        ]
+    );
+}
+
+#[test]
+fn relative_to_root_takes_the_deepest_root() {
+    let roots = vec!["/media/TV".to_string(), "/media/TV/Show".to_string()];
+    assert_eq!(
+        relative_to_root("/media/TV/Show/Season 01/E01.mkv", &roots),
+        Some(PathBuf::from("Season 01/E01.mkv"))
+    );
+    assert_eq!(
+        relative_to_root("/media/TV/Other/E01.mkv", &roots),
+        Some(PathBuf::from("Other/E01.mkv"))
+    );
+    // a folder that only starts with the name of a root is not in it
+    assert_eq!(relative_to_root("/media/TV2/E01.mkv", &roots), None);
+    assert_eq!(relative_to_root("/media/TV", &roots), None);
+
+    // outside of the roots the file goes to the output folder
+    assert_eq!(
+        mirrored_output_folder("/media/Movies/Movie.mkv", &roots, "/out"),
+        "/out"
+    );
+}
+
+#[test]
+fn long_path_prefixes_are_not_part_of_the_root() {
+    assert_eq!(
+        root_components(r"\\?\UNC\nas\media\TV"),
+        root_components(r"\\nas\media\TV")
+    );
+    assert_eq!(
+        root_components(r"\\?\C:\media\TV"),
+        root_components(r"C:\media\TV")
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_roots_of_drive_letters_and_shares() {
+    let roots = vec![r"C:\Media\TV".to_string(), r"\\nas\media\TV".to_string()];
+    assert_eq!(
+        relative_to_root(r"c:\media\tv\Show\E01.mkv", &roots),
+        Some(PathBuf::from(r"Show\E01.mkv"))
+    );
+    assert_eq!(
+        relative_to_root(r"\\?\UNC\nas\media\TV\Show\E01.mkv", &roots),
+        Some(PathBuf::from(r"Show\E01.mkv"))
+    );
+    assert_eq!(relative_to_root(r"D:\Media\TV\Show\E01.mkv", &roots), None);
+}
+
+#[test]
+fn path_components_are_sanitized_for_windows() {
+    let cases = [
+        ("Season 01", "Season 01"),
+        ("What If: Really?", "What If_ Really_"),
+        (r#"a<b>c"d|e*f"#, "a_b_c_d_e_f"),
+        ("tab\there", "tab_here"),
+        ("Season 1. ", "Season 1"),
+        ("...", "_"),
+        ("CON", "CON_"),
+        ("nul.txt", "nul.txt_"),
+        ("COM1", "COM1_"),
+        ("LPT9", "LPT9_"),
+        ("COM", "COM"),
+        ("CONSOLE", "CONSOLE"),
+        ("日本語", "日本語"),
+    ];
+    for (name, sanitized) in cases {
+        assert_eq!(sanitize_path_component(name), sanitized, "{}", name);
+    }
+}
+
+#[test]
+fn the_summary_shows_the_paths_below_the_roots() {
+    let roots = vec!["/media/TV".to_string()];
+    assert_eq!(
+        mirrored_display_path("/media/TV/Show/Season 01/E01.mkv", &roots, "/out"),
+        Path::new("Show/Season 01/E01.mkv").display().to_string()
+    );
+    assert_eq!(
+        mirrored_display_path("/out/Show/Season 01/E01.mkv", &roots, "/out"),
+        Path::new("Show/Season 01/E01.mkv").display().to_string()
+    );
+    assert_eq!(
+        mirrored_display_path("/media/Movies/Movie.mkv", &roots, "/out"),
+        "/media/Movies/Movie.mkv"
+    );
+}
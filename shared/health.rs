@@ -0,0 +1,240 @@
+// --verify-library: decode the files of the database with -xerror to find the ones bit rot or an
+// interrupted download broke, before an encode fails halfway through them. The result is stored in
+// video_info with the size and modification time of the file, the encode of a file still marked
+// corrupt is refused
+
+use crate::*;
+
+use rusqlite::OptionalExtension;
+
+// --quick decodes the first minute of each file
+const QUICK_SECONDS: u32 = 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthEntry {
+    pub filepath: String,
+    // ok, corrupt (ffmpeg stopped on an error) or suspect (ffmpeg printed errors but finished)
+    pub health: String,
+    pub checked_at: String,
+    // the first error line of ffmpeg, empty for ok
+    pub error: String,
+}
+
+// GET /db/health and the report of --verify-library
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LibraryHealth {
+    pub ok: u64,
+    pub corrupt: u64,
+    pub suspect: u64,
+    // never checked, or changed since
+    pub unchecked: u64,
+    // the corrupt files first, then the suspect ones
+    pub files: Vec<HealthEntry>,
+}
+
+impl Db {
+    pub fn update_health(&self, entry: &HealthEntry, size: i64, mtime: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET health=?1, health_checked_at=?2, health_error=?3, health_size=?4, health_mtime=?5 WHERE filepath=?6",
+            params![
+                entry.health,
+                entry.checked_at,
+                entry.error,
+                size,
+                mtime,
+                entry.filepath
+            ],
+        )?;
+        Ok(())
+    }
+
+    // The health of filepath, None when it was never checked or changed since
+    pub fn file_health(
+        &self,
+        filepath: &str,
+        size: i64,
+        mtime: i64,
+    ) -> Result<Option<HealthEntry>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT health, health_checked_at, health_error FROM video_info WHERE filepath=?1 AND health != '' AND health_size=?2 AND health_mtime=?3",
+            params![filepath, size, mtime],
+            |row| {
+                Ok(HealthEntry {
+                    filepath: filepath.to_string(),
+                    health: row.get(0)?,
+                    checked_at: row.get(1)?,
+                    error: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    // The counts of the library, a file that changed since its check counts as unchecked
+    pub fn library_health(&self) -> Result<LibraryHealth> {
+        let rows: Vec<(String, String, String, String, i64, i64)> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(
+                "SELECT filepath, health, health_checked_at, health_error, health_size, health_mtime FROM video_info ORDER BY filepath",
+            )?;
+            let rows = stmt.query_map(params![], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })?;
+            rows.collect::<Result<_>>()?
+        };
+        let mut health = LibraryHealth::default();
+        let mut suspect = Vec::new();
+        for (filepath, state, checked_at, error, size, mtime) in rows {
+            if state.is_empty() || file_stamp_ms(&filepath) != (size, mtime) {
+                health.unchecked += 1;
+                continue;
+            }
+            let entry = HealthEntry {
+                filepath,
+                health: state,
+                checked_at,
+                error,
+            };
+            match entry.health.as_str() {
+                "corrupt" => {
+                    health.corrupt += 1;
+                    health.files.push(entry);
+                }
+                "suspect" => {
+                    health.suspect += 1;
+                    suspect.push(entry);
+                }
+                _ => health.ok += 1,
+            }
+        }
+        health.files.extend(suspect);
+        Ok(health)
+    }
+}
+
+// Decode file with -xerror, the first minute of it with quick
+pub fn check_file_health(file: &str, quick: bool) -> io::Result<HealthEntry> {
+    let mut cmd = ffmpeg_command();
+    cmd.args(["-v", "error", "-xerror", "-nostdin"]);
+    if quick {
+        cmd.args(["-t", &QUICK_SECONDS.to_string()]);
+    }
+    cmd.args(["-i", file, "-f", "null", "-"]);
+    let output = tracked_output(&mut cmd, &[])?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let error = stderr
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+        .to_string();
+    let health = match (output.status.success(), error.is_empty()) {
+        (true, true) => "ok",
+        (true, false) => "suspect",
+        (false, _) => "corrupt",
+    };
+    Ok(HealthEntry {
+        filepath: file.to_string(),
+        health: health.to_string(),
+        checked_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        error: match (health, error.is_empty()) {
+            ("corrupt", true) => format!("ffmpeg {}", output.status),
+            _ => error,
+        },
+    })
+}
+
+// Check the files of the database, only those below the -i paths when given, verify_jobs files at
+// a time. Files missing on disk are left to the next scan
+pub fn verify_library(args: &Args) -> Result<LibraryHealth> {
+    let mut files: Vec<String> = if args.inputpath.is_empty() {
+        db().get_all()?.into_iter().map(|row| row.2).collect()
+    } else {
+        let mut files = Vec::new();
+        for input in &args.inputpath {
+            files.extend(db().search(input)?.into_iter().map(|row| row.2));
+        }
+        files
+    };
+    files.sort();
+    files.dedup();
+    files.retain(|file| Path::new(file).is_file());
+    info!(
+        "Checking {} files{}, {} at a time",
+        files.len(),
+        if args.quick { " (first minute)" } else { "" },
+        args.verify_jobs.max(1)
+    );
+
+    let bar = progress_bar(files.len() as u64);
+    let style =
+        "[health][{elapsed_precise}] [{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} [checked files] eta: {eta:<7}";
+    bar.set_style(ProgressStyle::default_bar().template(style).unwrap());
+    let check = || {
+        files.par_iter().for_each(|file| {
+            let (size, mtime) = file_stamp_ms(file);
+            match check_file_health(file, args.quick) {
+                Ok(entry) => {
+                    if entry.health != "ok" {
+                        debug!("{}: {} ({})", file, entry.health, entry.error);
+                    }
+                    if let Err(e) = db().update_health(&entry, size, mtime) {
+                        error!("Failed to store the health of {}: {}", file, e);
+                    }
+                }
+                Err(e) => error!("Failed to check {}: {}", file, e),
+            }
+            bar.inc(1);
+        })
+    };
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(args.verify_jobs.max(1))
+        .build()
+    {
+        Ok(pool) => pool.install(check),
+        Err(e) => {
+            warn!("Failed to start {} verify jobs: {}", args.verify_jobs, e);
+            check()
+        }
+    }
+    bar.finish();
+
+    let mut health = db().library_health()?;
+    // the report is about the checked files
+    health
+        .files
+        .retain(|entry| files.binary_search(&entry.filepath).is_ok());
+    Ok(health)
+}
+
+// Log the report of --verify-library and write it to --report-json
+pub fn report_library_health(health: &LibraryHealth, report_json: Option<&str>) {
+    for entry in &health.files {
+        let line = format!("  {} {}: {}", entry.health, entry.filepath, entry.error);
+        match entry.health.as_str() {
+            "corrupt" => error!("{}", line.red()),
+            _ => warn!("{}", line),
+        }
+    }
+    info!(
+        "Library health: {} ok, {} corrupt, {} suspect, {} unchecked",
+        health.ok, health.corrupt, health.suspect, health.unchecked
+    );
+    if let Some(path) = report_json {
+        let result = serde_json::to_string_pretty(health)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to write {}: {}", path, e);
+        }
+    }
+}
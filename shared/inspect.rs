@@ -0,0 +1,411 @@
+// inspect <file>: the probe of one file, the status the scan gives it and why, where its frame count
+// came from and how the options before inspect would encode it, as a colored report or json
+
+use crate::*;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectStream {
+    pub index: i64,
+    pub codec_type: String,
+    pub codec: String,
+    // 1920x1080 23.976 fps yuv420p10le of a video stream, 6 ch 5.1(side) of an audio stream
+    pub detail: String,
+    pub bitrate_kbps: i64,
+    pub language: String,
+    pub disposition: Vec<String>,
+}
+
+// The settings the current arguments encode the file with
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectEncode {
+    pub mode: String,
+    pub encoder: String,
+    pub preset: String,
+    pub params: String,
+    pub pix_fmt: String,
+    // VMAF 97, 700 MB of --target-size or the --renditions
+    pub target: String,
+    pub crf_search: String,
+    pub filters: Vec<String>,
+    pub content_tune: Option<String>,
+    pub film_grain: Option<u8>,
+    // one line per kept audio stream, copied or re-encoded
+    pub audio: Vec<String>,
+    // streams left out by --keep-audio-langs and --keep-subs-langs
+    pub dropped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InspectReport {
+    pub file: String,
+    // the title, show, season, episode and date tags of the container
+    pub tags: MediaTags,
+    pub container: String,
+    pub duration: Option<f32>,
+    pub size: u64,
+    pub bitrate_kbps: i64,
+    pub fps: f32,
+    pub vfr: bool,
+    pub interlaced: bool,
+    pub hdr: bool,
+    pub dynamic_hdr: String,
+    pub streams: Vec<InspectStream>,
+    pub frames: f32,
+    // which of the frame count fallbacks had it
+    pub frame_count_source: String,
+    pub status: String,
+    pub status_reason: String,
+    // the status in the database, None when the file was never scanned
+    pub db_status: Option<String>,
+    pub encode: Option<InspectEncode>,
+    // why there are no encode settings, like an encoder ffmpeg lacks
+    pub encode_error: Option<String>,
+}
+
+pub(crate) fn stream_bitrate_kbps(stream: &Value) -> i64 {
+    stream["bit_rate"]
+        .as_str()
+        .or(stream["tags"]["BPS"].as_str())
+        .or(stream["tags"]["BPS-eng"].as_str())
+        .and_then(|bitrate| parse_int(bitrate).ok())
+        .unwrap_or(0)
+        / 1000
+}
+
+fn inspect_stream(stream: &Value) -> InspectStream {
+    let codec_type = stream["codec_type"].as_str().unwrap_or("").to_string();
+    let detail = match codec_type.as_str() {
+        "video" => format!(
+            "{}x{} {:.3} fps {}",
+            stream["width"].as_i64().unwrap_or(0),
+            stream["height"].as_i64().unwrap_or(0),
+            parse_rate(stream["r_frame_rate"].as_str().unwrap_or("")).unwrap_or(0.0),
+            stream["pix_fmt"].as_str().unwrap_or("")
+        ),
+        "audio" => format!(
+            "{} ch {}",
+            stream["channels"].as_i64().unwrap_or(0),
+            stream["channel_layout"].as_str().unwrap_or("")
+        ),
+        _ => stream["tags"]["title"].as_str().unwrap_or("").to_string(),
+    };
+    let disposition = match stream["disposition"].as_object() {
+        Some(disposition) => disposition
+            .iter()
+            .filter(|(_, value)| value.as_i64() == Some(1))
+            .map(|(key, _)| key.clone())
+            .collect(),
+        None => Vec::new(),
+    };
+    InspectStream {
+        index: stream["index"].as_i64().unwrap_or(0),
+        codec_type,
+        codec: stream["codec_name"].as_str().unwrap_or("NaN").to_string(),
+        detail: detail.trim().to_string(),
+        bitrate_kbps: stream_bitrate_kbps(stream),
+        language: stream["tags"]["language"]
+            .as_str()
+            .unwrap_or("")
+            .to_string(),
+        disposition,
+    }
+}
+
+// The frame count in the order frame_count tries them, with the one that had it
+fn frame_count_with_source(file: &str, info: &MediaInfo) -> (f32, String) {
+    let (len, mtime) = file_stamp_ms(file);
+    if let Ok(Some(frames)) = db().cached_frame_count(file, len, mtime) {
+        return (frames as f32, "database, counted before".to_string());
+    }
+    for tag in ["NUMBER_OF_FRAMES-eng", "NUMBER_OF_FRAMES"] {
+        if let Some(frames) = info.tagged_frames(tag) {
+            return (frames, format!("{} tag", tag));
+        }
+    }
+    if let Some(frames) = info.frame_count() {
+        return (frames, "nb_frames".to_string());
+    }
+    if let Ok(frames) = get_framecount_ffmpeg(file) {
+        return (frames, "counted by ffmpeg, stream copy".to_string());
+    }
+    match get_framecount(file) {
+        Ok(frames) => (frames, "counted by ffprobe, decoding".to_string()),
+        Err(_) => (0.0, "none".to_string()),
+    }
+}
+
+// The status of scan_status and the thresholds that decided it
+fn status_reason(bitrate: i64, audio_codec: &str) -> String {
+    let kept = KEPT_AUDIO_CODECS.join(", ");
+    let video = if bitrate > PENDING_VIDEO_BITRATE {
+        format!(
+            "bitrate {} kb/s over {} kb/s",
+            bitrate / 1000,
+            PENDING_VIDEO_BITRATE / 1000
+        )
+    } else {
+        format!(
+            "bitrate {} kb/s up to {} kb/s",
+            bitrate / 1000,
+            PENDING_VIDEO_BITRATE / 1000
+        )
+    };
+    let audio = if KEPT_AUDIO_CODECS.contains(&audio_codec) {
+        format!("audio {} is one of {}", audio_codec, kept)
+    } else {
+        format!("audio {} is not one of {}", audio_codec, kept)
+    };
+    format!("{}, {}", video, audio)
+}
+
+// How the arguments encode file, the way Pipeline::new and process_file resolve them
+fn inspect_encode(file: &str, args: &Args) -> Result<InspectEncode, String> {
+    let mut args = args.clone();
+    if args.encoder == "auto" {
+        args.encoder = auto_encoder();
+    }
+    let settings = resolve_encoder(&args, &args.encoder)?;
+    args.encoder = settings.codec.clone();
+    args.params_x265 = settings.params.clone();
+
+    let mut filters = Vec::new();
+    let mut content_tune = None;
+    let mut film_grain = None;
+    if args.mode != "audio-only" {
+        if let Some(tune) = resolve_content_tune(file, &args.content_tune) {
+            apply_content_tune(&mut args, tune);
+            content_tune = Some(tune.as_str().to_string());
+        }
+        if let (Some(level), true) = (&args.film_grain, supports_film_grain(&args.encoder)) {
+            let level = resolve_film_grain(file, level);
+            apply_film_grain(&mut args, level);
+            film_grain = Some(level);
+        }
+        if should_deinterlace(file, &args.deinterlace) {
+            filters.push("yadif".to_string());
+        }
+        if let Some(anamorphic) = anamorphic(file) {
+            filters.push(anamorphic.setsar_filter());
+        }
+        if let Some(tonemap) = tonemap_filter(file, &args.tonemap) {
+            filters.push(tonemap);
+            args.pix_fmt = TONEMAP_PIX_FMT.to_string();
+        }
+        if let Some(downscale) = downscale(file, &args) {
+            filters.push(downscale.filter);
+        }
+    }
+    let (preset, params) = encoder_preset_params(&args);
+
+    let target = match (&args.target_size, &args.renditions) {
+        (Some(size), _) => format!("{} MB", size),
+        (None, Some(renditions)) => format!("renditions {}", renditions),
+        (None, None) => format!("VMAF {}", args.vmaf),
+    };
+
+    let streams = select_streams(file, &args, None);
+    let audio = plan_audio_streams(&streams.audio, &AudioOptions::from_args(&args))
+        .iter()
+        .map(|plan| {
+            if plan.args.is_empty() {
+                format!("a:{} {} copy", plan.index, plan.original_codec)
+            } else {
+                format!(
+                    "a:{} {} -> {} ({})",
+                    plan.index,
+                    plan.original_codec,
+                    plan.target_codec,
+                    plan.args.trim()
+                )
+            }
+        })
+        .collect();
+
+    Ok(InspectEncode {
+        mode: args.mode.clone(),
+        encoder: args.encoder.clone(),
+        preset: preset.to_string(),
+        params: params.to_string(),
+        pix_fmt: args.pix_fmt.clone(),
+        target,
+        crf_search: args.crf_search.clone(),
+        filters,
+        content_tune,
+        film_grain,
+        audio,
+        dropped: streams.dropped,
+    })
+}
+
+pub fn inspect_file(file: &str, args: &Args) -> Result<InspectReport, String> {
+    if !Path::new(file).is_file() {
+        return Err(format!("{} is not a file", file));
+    }
+    let info = probe_file(file);
+    if info.streams.is_empty() {
+        return Err(format!("ffprobe found no streams in {}", file));
+    }
+
+    let bitrate = info.format["bit_rate"]
+        .as_str()
+        .and_then(|bitrate| parse_int(bitrate).ok())
+        .unwrap_or(0);
+    // the scan looks at the first audio stream, NaN without one
+    let audio_codec = info
+        .streams_of("audio")
+        .first()
+        .and_then(|stream| stream["codec_name"].as_str())
+        .unwrap_or("NaN")
+        .to_string();
+    let (frames, frame_count_source) = frame_count_with_source(file, &info);
+    let db_status = get_all_from_db_search(file)
+        .unwrap_or_default()
+        .iter()
+        .find(|item| item.2 == file)
+        .map(|item| item.14.clone());
+    let (encode, encode_error) = match inspect_encode(file, args) {
+        Ok(encode) => (Some(encode), None),
+        Err(e) => (None, Some(e)),
+    };
+
+    Ok(InspectReport {
+        file: file.to_string(),
+        tags: MediaTags::from_format(&info.format),
+        container: info.format["format_name"]
+            .as_str()
+            .unwrap_or("")
+            .to_string(),
+        duration: info.duration(),
+        size: fs::metadata(file).map(|m| m.len()).unwrap_or(0),
+        bitrate_kbps: bitrate / 1000,
+        fps: info.fps(),
+        vfr: info.is_vfr(),
+        interlaced: info.is_interlaced(),
+        hdr: info.is_hdr(),
+        dynamic_hdr: get_dynamic_hdr(file),
+        streams: info.streams.iter().map(inspect_stream).collect(),
+        frames,
+        frame_count_source,
+        status: scan_status(bitrate, &audio_codec).to_string(),
+        status_reason: status_reason(bitrate, &audio_codec),
+        db_status,
+        encode,
+        encode_error,
+    })
+}
+
+pub fn inspect_json(report: &InspectReport) -> String {
+    serde_json::to_string_pretty(report).unwrap_or_default()
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+pub fn print_inspect_report(report: &InspectReport) {
+    // the title names the file when the container has one
+    match report.tags.label() {
+        Some(label) => {
+            println!("{}", truncate_middle(&label, terminal_columns()).bold());
+            println!("  file: {}", report.file);
+        }
+        None => println!("{}", report.file.bold()),
+    }
+    if !report.tags.date.is_empty() {
+        println!("  date: {}", report.tags.date);
+    }
+    println!(
+        "  container: {}, duration: {}, size: {}, bitrate: {} kb/s",
+        report.container,
+        report
+            .duration
+            .map_or("unknown".to_string(), |duration| format!(
+                "{:.3} s",
+                duration
+            )),
+        format_size(report.size),
+        report.bitrate_kbps
+    );
+    let hdr = if report.dynamic_hdr.is_empty() {
+        yes_no(report.hdr).to_string()
+    } else {
+        format!("{} ({})", yes_no(report.hdr), report.dynamic_hdr)
+    };
+    println!(
+        "  fps: {:.3}, vfr: {}, interlaced: {}, hdr: {}",
+        report.fps,
+        yes_no(report.vfr),
+        yes_no(report.interlaced),
+        hdr
+    );
+    println!(
+        "  frames: {} ({})",
+        report.frames, report.frame_count_source
+    );
+
+    println!("{}", "Streams".bold());
+    println!(
+        "  {:<4} {:<10} {:<12} {:<32} {:>10} {:<5} disposition",
+        "#", "type", "codec", "details", "kb/s", "lang"
+    );
+    for stream in &report.streams {
+        let line = format!(
+            "  {:<4} {:<10} {:<12} {:<32} {:>10} {:<5} {}",
+            stream.index,
+            stream.codec_type,
+            stream.codec,
+            stream.detail,
+            stream.bitrate_kbps,
+            stream.language,
+            stream.disposition.join(",")
+        );
+        match stream.codec_type.as_str() {
+            "video" => println!("{}", line.cyan()),
+            "audio" => println!("{}", line.green()),
+            _ => println!("{}", line),
+        }
+    }
+
+    println!("{}", "Status".bold());
+    let status = match report.status.as_str() {
+        "skipped" => report.status.yellow(),
+        _ => report.status.green(),
+    };
+    println!("  {}: {}", status, report.status_reason);
+    if let Some(db_status) = &report.db_status {
+        println!("  in the database as {}", db_status);
+    }
+
+    println!("{}", "Encode".bold());
+    match (&report.encode, &report.encode_error) {
+        (Some(encode), _) => {
+            println!(
+                "  mode {}, {} to {}, CRF search {}",
+                encode.mode, encode.encoder, encode.target, encode.crf_search
+            );
+            println!("  preset: {}, pix_fmt: {}", encode.preset, encode.pix_fmt);
+            println!("  params: {}", encode.params);
+            if !encode.filters.is_empty() {
+                println!("  filters: {}", encode.filters.join(","));
+            }
+            if let Some(tune) = &encode.content_tune {
+                println!("  content tune: {}", tune);
+            }
+            if let Some(level) = encode.film_grain {
+                println!("  film grain: {}", level);
+            }
+            for audio in &encode.audio {
+                println!("  {}", audio);
+            }
+            if !encode.dropped.is_empty() {
+                println!("  dropped: {}", encode.dropped.join(", "));
+            }
+        }
+        (None, Some(e)) => println!("  {}", e.red()),
+        (None, None) => {}
+    }
+}
@@ -0,0 +1,173 @@
+// ctrl-C: the running ffmpeg and ab-av1 processes are tracked, killed and their unfinished outputs
+// removed, --watch finishes the current file first
+
+use crate::*;
+
+// Set by watch mode, where ctrl-C finishes the current file instead of aborting it
+pub static SHIELD_CHILDREN: AtomicBool = AtomicBool::new(false);
+
+// Start cmd in its own process group when SHIELD_CHILDREN is set, so a ctrl-C in the terminal
+// reaches only us and not the running encode
+pub(crate) fn shield_from_ctrlc(cmd: &mut Command) {
+    if !SHIELD_CHILDREN.load(Ordering::SeqCst) {
+        return;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+        cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+    }
+}
+
+// Set by the first ctrl-C: the file and scene loops don't start new work once it is set
+pub static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+// Set while the ctrl-C handler kills the children and removes their output before exiting
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static CTRLC_PRESSES: AtomicUsize = AtomicUsize::new(0);
+
+// Exit code of a run stopped with ctrl-C
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+lazy_static! {
+    // The running ffmpeg and ab-av1 processes by pid, with their task and the files they are writing
+    static ref RUNNING_CHILDREN: Mutex<HashMap<u32, (String, Vec<PathBuf>)>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+// While the ctrl-C handler cleans up, a killed encode is not a failure: the caller waits for the
+// handler to exit the process instead of reporting it
+pub fn wait_if_interrupted() {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        loop {
+            thread::park();
+        }
+    }
+}
+
+// Keeps a spawned child in RUNNING_CHILDREN until it is dropped after the wait
+pub struct TrackedChild {
+    pid: u32,
+}
+
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        RUNNING_CHILDREN.lock().unwrap().remove(&self.pid);
+    }
+}
+
+// Register child so ctrl-C can kill it, outputs are removed when it is killed
+// The child belongs to the task of this thread, started while that task is paused it is stopped
+// right away
+pub fn track_child(child: &Child, outputs: &[&str]) -> TrackedChild {
+    let pid = child.id();
+    let task_id = current_task();
+    let paused = is_task_paused(&task_id);
+    RUNNING_CHILDREN
+        .lock()
+        .unwrap()
+        .insert(pid, (task_id, outputs.iter().map(PathBuf::from).collect()));
+    if paused {
+        signal_process_tree(pid, "STOP");
+    }
+    TrackedChild { pid }
+}
+
+// The running children of task_id
+pub(crate) fn running_child_pids(task_id: &str) -> Vec<u32> {
+    RUNNING_CHILDREN
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, (task, _))| task == task_id)
+        .map(|(pid, _)| *pid)
+        .collect()
+}
+
+// cmd.output() for a child that ctrl-C can kill
+pub fn tracked_output(cmd: &mut Command, outputs: &[&str]) -> io::Result<Output> {
+    let child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let _tracked = track_child(&child, outputs);
+    child.wait_with_output()
+}
+
+fn kill_pid(pid: u32) {
+    #[cfg(unix)]
+    let result = Command::new("kill")
+        .arg("-KILL")
+        .arg(pid.to_string())
+        .output();
+    #[cfg(windows)]
+    let result = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .output();
+    if let Err(e) = result {
+        warn!("Failed to kill process {}: {}", pid, e);
+    }
+}
+
+// Kill the running children and remove the files they were writing
+// done.txt is written after a scene is complete, so a chunked encode resumes from the finished scenes
+pub fn stop_children() {
+    // stopped processes of a paused encode are continued, their own children don't stay stopped
+    resume_all_encodes();
+    let children: Vec<(u32, (String, Vec<PathBuf>))> =
+        RUNNING_CHILDREN.lock().unwrap().drain().collect();
+    for (pid, (_, outputs)) in children {
+        kill_pid(pid);
+        for output in outputs {
+            // windows keeps the file locked for a moment after the kill
+            for attempt in 0..10 {
+                match fs::remove_file(&output) {
+                    Ok(_) => {
+                        info!("Removed {}", output.display());
+                        break;
+                    }
+                    Err(e) if e.kind() == ErrorKind::NotFound => break,
+                    Err(e) if attempt == 9 => {
+                        warn!("Failed to remove {}: {}", output.display(), e)
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(200)),
+                }
+            }
+        }
+    }
+}
+
+// The cli ctrl-C handler: the first ctrl-C kills the running ffmpeg/ab-av1 processes, removes
+// their unfinished output, cancels the task and exits with EXIT_INTERRUPTED, the next one exits
+// right away. In watch mode (SHIELD_CHILDREN) the first ctrl-C only finishes the current file.
+pub fn install_ctrlc_handler() -> Result<(), String> {
+    ctrlc::set_handler(|| {
+        let presses = CTRLC_PRESSES.fetch_add(1, Ordering::SeqCst) + 1;
+        SHUTDOWN.store(true, Ordering::SeqCst);
+        let graceful = SHIELD_CHILDREN.load(Ordering::SeqCst);
+        if graceful && presses == 1 {
+            warn!("Stopping after the current file, press ctrl-C again to abort");
+            resume_all_encodes();
+        } else if !INTERRUPTED.swap(true, Ordering::SeqCst) {
+            warn!("Interrupted, stopping the running processes, press ctrl-C again to exit right away");
+            // clean up on another thread, so this handler sees the next ctrl-C
+            thread::spawn(|| {
+                stop_children();
+                remove_staging_folders();
+                release_run_locks();
+                cancel_running_tasks();
+                std::process::exit(EXIT_INTERRUPTED);
+            });
+        } else {
+            warn!("Aborting");
+            std::process::exit(EXIT_INTERRUPTED);
+        }
+    })
+    .map_err(|e| format!("Failed to set the ctrl-C handler: {}", e))
+}
@@ -0,0 +1,509 @@
+// The library in the database: the folder scan that fills video_info, export and import, the queue
+
+use crate::*;
+
+// Write the whole video_info table to out as json or csv, row by row
+pub fn export_db(format: &str, out: impl Write) -> Result<u64, io::Error> {
+    export_video_info(db(), format, out)
+}
+
+// export_db of the video_info table of db
+pub(crate) fn export_video_info(db: &Db, format: &str, out: impl Write) -> Result<u64, io::Error> {
+    if format == "csv" {
+        let mut writer = csv::Writer::from_writer(out);
+        let count = db.for_each_video_info(|info| {
+            writer
+                .serialize(&info)
+                .map_err(|e| io::Error::new(ErrorKind::Other, e))
+        })?;
+        writer.flush()?;
+        return Ok(count);
+    }
+
+    let mut out = io::BufWriter::new(out);
+    write!(out, "[")?;
+    let mut first = true;
+    let count = db.for_each_video_info(|info| {
+        if !first {
+            write!(out, ",")?;
+        }
+        first = false;
+        write!(out, "\n  ")?;
+        serde_json::to_writer(&mut out, &info)?;
+        Ok(())
+    })?;
+    write!(out, "\n]\n")?;
+    out.flush()?;
+    Ok(count)
+}
+
+// Insert or update (matched by filepath) every valid row of a json or csv export, csv is detected by the .csv extension
+pub fn import_db(path: &str) -> Result<ImportCounts, io::Error> {
+    import_video_info(db(), path)
+}
+
+// import_db into the video_info table of db
+pub(crate) fn import_video_info(db: &Db, path: &str) -> Result<ImportCounts, io::Error> {
+    let mut counts = ImportCounts::default();
+    let mut import = |row: Result<VideoInfo, String>, line: usize| {
+        let info = match row {
+            Ok(info) => info,
+            Err(e) => {
+                warn!("{} row {}: {}", path, line, e);
+                counts.invalid += 1;
+                return Ok(());
+            }
+        };
+        if let Some(reason) = info.invalid_reason() {
+            warn!("{} row {}: {}", path, line, reason);
+            counts.invalid += 1;
+            return Ok(());
+        }
+        match db.upsert_video_info(&info) {
+            Ok(true) => counts.inserted += 1,
+            Ok(false) => counts.updated += 1,
+            Err(e) => return Err(io::Error::new(ErrorKind::Other, e)),
+        }
+        Ok(())
+    };
+
+    let file = File::open(path)?;
+    if path.to_lowercase().ends_with(".csv") {
+        let mut reader = csv::Reader::from_reader(file);
+        for (i, record) in reader.deserialize::<VideoInfo>().enumerate() {
+            import(record.map_err(|e| e.to_string()), i + 1)?;
+        }
+    } else {
+        let rows: Vec<Value> = serde_json::from_reader(BufReader::new(file))?;
+        for (i, row) in rows.into_iter().enumerate() {
+            import(
+                serde_json::from_value::<VideoInfo>(row).map_err(|e| e.to_string()),
+                i + 1,
+            )?;
+        }
+    }
+    Ok(counts)
+}
+
+// Get all the items from the db
+pub fn get_all_from_db() -> Result<Vec<VideoInfoRow>> {
+    db().get_all()
+}
+
+// Get all the items from the db that match the given input
+pub fn get_all_from_db_search(search: &str) -> Result<Vec<VideoInfoRow>> {
+    db().search(search)
+}
+
+// Container bitrate in bits/s from which the scan marks a file pending_video, below it is skipped
+pub const PENDING_VIDEO_BITRATE: i64 = 6_000_000;
+// Audio codecs the scan leaves alone, any other first audio stream makes the file pending_audio
+pub const KEPT_AUDIO_CODECS: [&str; 3] = ["aac", "opus", "mp3"];
+
+// The status the scan gives a file by its bitrate and the codec of its first audio stream
+// A bitrate over 6MB/s is pending_video, a codec other than aac, opus or mp3 pending_audio, both
+// pending_all, otherwise the file is skipped
+pub fn scan_status(bitrate: i64, audio_codec: &str) -> &'static str {
+    let audio = !KEPT_AUDIO_CODECS.contains(&audio_codec);
+    if bitrate > PENDING_VIDEO_BITRATE && audio {
+        "pending_all"
+    } else if audio {
+        "pending_audio"
+    } else if bitrate < PENDING_VIDEO_BITRATE {
+        "skipped"
+    } else {
+        "pending_video"
+    }
+}
+
+pub fn add_to_db(
+    files: Vec<String>,
+    bar: ProgressBar,
+) -> Result<(Vec<AtomicI32>, Arc<Mutex<Vec<std::string::String>>>)> {
+    add_to_db_with_progress(files, bar, &|_, _| {})
+}
+
+// Same as add_to_db, progress is called with (probed files, files to probe) after each file
+// Counters: count, db_count, db_count_added, db_count_skipped, db_count_errors, the rows of the
+// files that are gone are moved to the trash by remove_from_db_folder
+pub fn add_to_db_with_progress(
+    files: Vec<String>,
+    bar: ProgressBar,
+    progress: &(dyn Fn(u64, u64) + Sync),
+) -> Result<(Vec<AtomicI32>, Arc<Mutex<Vec<std::string::String>>>)> {
+    let count: AtomicI32 = AtomicI32::new(0);
+    let db_count;
+    let db_count_added: AtomicI32 = AtomicI32::new(0);
+    let db_count_skipped: AtomicI32 = AtomicI32::new(0);
+    let db_count_errors: AtomicI32 = AtomicI32::new(0);
+    let probed: AtomicUsize = AtomicUsize::new(0);
+    let files_to_process: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+    let filenames_skip = files.clone();
+    let filenames_audio = files.clone();
+    let mut filenames = files;
+
+    // get all items in db
+    let db_items = db().get_all()?;
+
+    // get all items from filenames that are not in db
+    let mut filenames_to_process: Vec<String> = Vec::new();
+    for filename in filenames {
+        let mut found = false;
+        for item in &db_items {
+            if item.2 == filename {
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            filenames_to_process.push(filename);
+        }
+    }
+
+    // get all the items from filenames that are in db
+    let mut filenames_to_skip: Vec<String> = Vec::new();
+    for filename in filenames_skip {
+        let mut found = false;
+        for item in &db_items {
+            if item.2 == filename {
+                found = true;
+                break;
+            }
+        }
+        if found {
+            filenames_to_skip.push(filename);
+        }
+    }
+    db_count = AtomicI32::new(filenames_to_skip.len() as i32);
+
+    // print count for all items in filenames_to_process and return filenames with all items in db removed
+    info!("Found {} files not in database", filenames_to_process.len());
+    filenames = filenames_to_process.clone();
+
+    // get all the items from filenames that are in db that have audio_codec == "NaN" or audio_bitrate == 0
+    let mut filenames_to_update: Vec<String> = Vec::new();
+    for filename in filenames_audio {
+        let mut found = false;
+        for item in &db_items {
+            if item.2 == filename && (item.15 == "NaN" || item.16 == 0) {
+                found = true;
+                break;
+            }
+        }
+        if found {
+            filenames_to_update.push(filename);
+        }
+    }
+
+    // print count for all items in filenames_to_update
+    info!("Found {} files to update in db", filenames_to_update.len());
+    if filenames_to_update.len() > 0 {
+        filenames = filenames_to_update.clone();
+        bar.set_length(filenames_to_update.len() as u64);
+    }
+
+    // if >0 files to process, set bar length to filenames_to_process.len()
+    if filenames_to_process.len() > 0 {
+        bar.set_length(filenames_to_process.len() as u64);
+    }
+
+    /*     // list files to update
+    if filenames_to_update.len() > 0 {
+        for filename in filenames_to_update {
+            println!("{}", filename);
+        }
+    } */
+
+    filenames.par_iter().for_each(|filename| {
+        let file_exists: bool = db().video_info_exists(filename).unwrap();
+
+        // TEMP print filename
+        //println!("{}", filename);
+
+        if !file_exists {
+            let video_output = ffprobe_command()
+                .args([
+                    "-i",
+                    filename,
+                    "-v",
+                    "error",
+                    "-select_streams",
+                    "v",
+                    "-show_entries",
+                    "stream",
+                    "-show_format",
+                    "-show_streams",
+                    "-of",
+                    "json",
+                ])
+                .output()
+                .expect("failed to execute process");
+            let audio_output = ffprobe_command()
+                .args([
+                    "-i",
+                    filename,
+                    "-v",
+                    "error",
+                    "-select_streams",
+                    "a",
+                    "-show_entries",
+                    "stream",
+                    "-show_format",
+                    "-show_streams",
+                    "-of",
+                    "json",
+                ])
+                .output()
+                .expect("failed to execute process");
+
+            let audio_json_value = probe_json(&audio_output, filename);
+            let audio_json_str = audio_json_value.to_string();
+            let json_value = probe_json(&video_output, filename);
+            let json_str = json_value.to_string();
+            if !video_output.status.success() || !json_value["format"]["filename"].is_string() {
+                // unreadable file, count it and go on with the others
+                warn!("Failed to probe {}", filename);
+                db_count_errors.fetch_add(1, Ordering::SeqCst);
+            } else if &json_str.len() >= &1 && &audio_json_str.len() >= &1 {
+                let audio_values: Value = audio_json_value;
+                let audio_bitrate = audio_values["format"]["bit_rate"]
+                    .as_str()
+                    .and_then(|bitrate| parse_int(bitrate).ok())
+                    .unwrap_or(0);
+                let audio_codec = audio_values["streams"][0]["codec_name"]
+                    .as_str()
+                    .unwrap_or("NaN");
+                let values: Value = json_value;
+                // the stream the encode picks, not the null or data video streams of TS captures
+                let video = values["streams"]
+                    .as_array()
+                    .and_then(|streams| select_video_stream(streams))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                let _width = video["width"].as_i64().unwrap_or(0);
+                let _height = video["height"].as_i64().unwrap_or(0);
+                let filepath = values["format"]["filename"].as_str().unwrap();
+                let filename = file_name_of(filepath);
+                let size = values["format"]["size"]
+                    .as_str()
+                    .and_then(|size| parse_int(size).ok())
+                    .unwrap_or(0);
+                let bitrate = values["format"]["bit_rate"]
+                    .as_str()
+                    .and_then(|bitrate| parse_int(bitrate).ok())
+                    .unwrap_or(0);
+                // TS captures often have no duration, the video stream is read to the end then
+                let duration = values["format"]["duration"]
+                    .as_str()
+                    .and_then(|duration| parse_decimal::<f64>(duration).ok())
+                    .filter(|duration| *duration > 0.0)
+                    .or_else(|| get_duration(filepath).map(f64::from))
+                    .unwrap_or(0.0);
+                let format = values["format"]["format_name"].as_str().unwrap_or("NaN");
+                let width = video["width"].as_i64().unwrap_or(0);
+                let height = video["height"].as_i64().unwrap_or(0);
+                let codec = video["codec_name"].as_str().unwrap_or("NaN");
+                let pix_fmt = video["pix_fmt"].as_str().unwrap_or("NaN");
+                let checksum = content_hash(filepath).unwrap_or_else(|e| {
+                    warn!("Could not hash {}: {}", filepath, e);
+                    "NaN".to_string()
+                });
+                let dar = video["display_aspect_ratio"].as_str().unwrap_or("NaN");
+                let sar = video["sample_aspect_ratio"].as_str().unwrap_or("NaN");
+
+                // for each file in this folder and it's subfodlers, sum the size of the files
+                let mut folder_size = 0;
+                for entry in WalkDir::new(Path::new(filepath).parent().unwrap()) {
+                    let entry = entry.unwrap();
+                    let metadata = fs::metadata(entry.path());
+                    folder_size += metadata.unwrap().len() as i64;
+                }
+
+                let status = scan_status(bitrate, audio_codec);
+                let tags = MediaTags::from_format(&values["format"]);
+
+                db().insert_video_info(&VideoInfo {
+                    filename: filename.to_string(),
+                    filepath: filepath.to_string(),
+                    width,
+                    height,
+                    duration,
+                    pixel_format: pix_fmt.to_string(),
+                    display_aspect_ratio: dar.to_string(),
+                    sample_aspect_ratio: sar.to_string(),
+                    format: format.to_string(),
+                    size,
+                    folder_size,
+                    bitrate,
+                    codec: codec.to_string(),
+                    status: status.to_string(),
+                    audio_codec: audio_codec.to_string(),
+                    audio_bitrate,
+                    hash: checksum,
+                    predicted_size: 0.0,
+                    predicted_percent: 0.0,
+                    verified_vmaf: 0.0,
+                    dynamic_hdr: dynamic_hdr_from_stream(&video),
+                    chunked_vmaf: 0.0,
+                    chunked_stats: String::new(),
+                    screenshots: String::new(),
+                    vfr: stream_is_vfr(&video),
+                    content_tune: String::new(),
+                    fallback_encoder: String::new(),
+                    title: tags.title,
+                    show: tags.show,
+                    season: tags.season,
+                    episode: tags.episode,
+                    date: tags.date,
+                    output_width: 0,
+                    output_height: 0,
+                    requested_vmaf: 0.0,
+                    effective_vmaf: 0.0,
+                    health: String::new(),
+                    health_checked_at: String::new(),
+                    health_error: String::new(),
+                    health_size: 0,
+                    health_mtime: 0,
+                    film_grain: 0,
+                    frame_count: 0.0,
+                    frame_count_size: 0,
+                    frame_count_mtime: 0,
+                })
+                .unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+                db_count_added.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        bar.inc(1);
+        progress(
+            (probed.fetch_add(1, Ordering::SeqCst) + 1) as u64,
+            filenames.len() as u64,
+        );
+    });
+
+    // return all the counters
+    Ok((
+        vec![
+            count,
+            db_count,
+            db_count_added,
+            db_count_skipped,
+            db_count_errors,
+        ],
+        files_to_process,
+    ))
+}
+
+// Set the status of a single file in the db
+pub fn set_db_status(filepath: &str, status: &str) -> Result<()> {
+    db().update_status(filepath, status)
+}
+
+// Function to add the given files to the db in a table called db_queue, returns the id of the entry
+// not_before and only_between are the schedule of the entry, see parse_not_before and parse_only_between
+#[allow(clippy::too_many_arguments)]
+pub fn add_to_db_queue(
+    input_path: &str,
+    output_path: &str,
+    encoder: &str,
+    preset: &str,
+    vmaf_target: &str,
+    vmaf_threads: &str,
+    priority: i32,
+    not_before: &str,
+    only_between: &str,
+) -> Result<i64, String> {
+    // the worker hands it to run_from_web, which needs a number
+    if let Err(e) = vmaf_input_validation(vmaf_target) {
+        return Err(format!("{}: the VMAF target {}", vmaf_target, e));
+    }
+    let not_before = parse_not_before(not_before)?;
+    let only_between = parse_only_between(only_between)?;
+    // the worker would wait for the other run or encode the same files twice
+    if let Some(conflict) = input_conflict(input_path).map_err(|e| e.to_string())? {
+        return Err(conflict);
+    }
+    db().queue_add(
+        input_path,
+        output_path,
+        encoder,
+        preset,
+        vmaf_target.trim(),
+        vmaf_threads,
+        priority,
+        &not_before,
+        &only_between,
+    )
+    .map_err(|e| e.to_string())
+}
+
+// function to remove item from db_queue
+pub fn remove_from_db_queue(id: String) -> Result<()> {
+    db().queue_remove(&id)
+}
+
+// Function to get all the items from the db_queue table
+pub fn get_all_from_db_queue() -> Result<Vec<QueueEntry>> {
+    db().queue_all()
+}
+
+// function to move items to the trash that don't exists anymore, but only for the specified folder and it's subfolders
+// Returns the number of rows moved to the trash
+pub fn remove_from_db_folder(folder: &str, args: &Args) -> Result<usize> {
+    trash_folder_removals(db(), folder, args, RECHECK_DELAY)
+}
+
+// The rows of db in folder whose file is still gone after recheck or is excluded now go to the
+// trash, see trash_removals
+pub(crate) fn trash_folder_removals(
+    db: &Db,
+    folder: &str,
+    args: &Args,
+    recheck: Duration,
+) -> Result<usize> {
+    let db_items = db.search(folder)?;
+    let excludes = ScanExcludes::load(folder, &args.exclude);
+
+    let mut filenames_not_found: Vec<String> = Vec::new();
+    let mut filenames_excluded: Vec<String> = Vec::new();
+    let remove_bar = progress_spinner();
+    let remove_style =
+        "[remo][{elapsed_precise}][{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} eta: {eta:<7}";
+    remove_bar.set_style(
+        ProgressStyle::default_spinner()
+            .template(remove_style)
+            .unwrap(),
+    );
+
+    remove_bar.set_length(db_items.len() as u64);
+
+    for item in &db_items {
+        let file_exists = metadata(&item.2).is_ok();
+        if !file_exists {
+            filenames_not_found.push(item.2.clone());
+        } else if excludes.is_excluded(Path::new(&item.2), false) {
+            info!(
+                "Removing {} from the database, it matches an exclude pattern",
+                item.2
+            );
+            filenames_excluded.push(item.2.clone());
+        }
+        remove_bar.inc(1);
+    }
+    remove_bar.finish();
+
+    // a file that is only gone for a moment, like on a share that is reconnecting, stays
+    let mut removals: Vec<(String, &str)> = missing_files(&filenames_not_found, recheck)
+        .into_iter()
+        .map(|filename| (filename, "not_found"))
+        .collect();
+    removals.extend(
+        filenames_excluded
+            .into_iter()
+            .map(|filename| (filename, "excluded")),
+    );
+    trash_removals(db, folder, &removals, db_items.len(), args)
+}
+
+#[cfg(test)]
+mod tests;
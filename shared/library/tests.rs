@@ -0,0 +1,171 @@
+use super::*;
+
+// Rows with the characters csv and json have to escape and the columns added after the first ones
+fn library_rows() -> Vec<VideoInfo> {
+    let mut quoted = video_info("/media/Show, \"The\"/It's 01.mkv");
+    quoted.predicted_size = 1234.5;
+    quoted.verified_vmaf = 95.125;
+    quoted.vfr = true;
+    quoted.title = "Line one\nline two".to_string();
+    quoted.screenshots = "[\"/shots/a.png\"]".to_string();
+    let mut unicode = video_info("/media/Ünïcödé/映画.mp4");
+    unicode.status = "transcoded".to_string();
+    unicode.duration = 5400.25;
+    unicode.show = "Show".to_string();
+    unicode.season = "1".to_string();
+    vec![video_info("/media/plain.mkv"), quoted, unicode]
+}
+
+fn library_db(name: &str) -> Db {
+    let db = temp_db(name);
+    for info in library_rows() {
+        db.insert_video_info(&info).unwrap();
+    }
+    db
+}
+
+// Give every column of the rows that still holds its default a value, a column the export leaves
+// out then can't come back as the same default
+fn seed_every_column(db: &Db) {
+    let conn = db.conn.lock().unwrap();
+    let columns: Vec<(String, String)> = conn
+        .prepare("PRAGMA table_info(video_info)")
+        .unwrap()
+        .query_map(params![], |row| Ok((row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    for (column, kind) in columns {
+        if column == "id" {
+            continue;
+        }
+        let value = match kind.as_str() {
+            "REAL" => "1.5",
+            kind if kind.contains("INT") => "1",
+            _ => "'seeded'",
+        };
+        conn.execute(
+            &format!(
+                "UPDATE video_info SET {0}={1} WHERE {0}=0 OR {0}=''",
+                column, value
+            ),
+            params![],
+        )
+        .unwrap();
+    }
+}
+
+// Every column of every row of the table, as sqlite has them
+fn raw_rows(db: &Db) -> Vec<Vec<rusqlite::types::Value>> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT * FROM video_info ORDER BY id")
+        .unwrap();
+    let columns = stmt.column_count();
+    stmt.query_map(params![], |row| {
+        (0..columns).map(|i| row.get(i)).collect::<Result<Vec<_>>>()
+    })
+    .unwrap()
+    .collect::<Result<_>>()
+    .unwrap()
+}
+
+fn exported(db: &Db, format: &str) -> String {
+    let mut out = Vec::new();
+    export_video_info(db, format, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn rows_of(db: &Db) -> Vec<VideoInfo> {
+    let mut rows = Vec::new();
+    db.for_each_video_info(|info| {
+        rows.push(info);
+        Ok(())
+    })
+    .unwrap();
+    rows
+}
+
+#[test]
+fn export_wipe_import_export_round_trips() {
+    for (format, file) in [("json", "export.json"), ("csv", "export.csv")] {
+        let db = library_db("export");
+        seed_every_column(&db);
+        let export = exported(&db, format);
+        let path = temp_path(file);
+        fs::write(&path, &export).unwrap();
+
+        // the wiped library is a new database
+        let wiped = temp_db("import");
+        assert_eq!(
+            import_video_info(&wiped, path.to_str().unwrap()).unwrap(),
+            ImportCounts {
+                inserted: 3,
+                updated: 0,
+                invalid: 0,
+            },
+            "{}",
+            format
+        );
+        assert_eq!(exported(&wiped, format), export, "{}", format);
+        assert_eq!(rows_of(&wiped), rows_of(&db), "{}", format);
+        // the columns VideoInfo leaves out would be lost
+        assert_eq!(raw_rows(&wiped), raw_rows(&db), "{}", format);
+    }
+}
+
+#[test]
+fn import_updates_the_rows_of_the_same_filepath() {
+    let db = library_db("import-update");
+    let mut rows = library_rows();
+    rows[0].status = "transcoded".to_string();
+    rows.push(video_info("/media/new.mkv"));
+    let path = temp_path("update.json");
+    fs::write(&path, serde_json::to_string(&rows).unwrap()).unwrap();
+
+    assert_eq!(
+        import_video_info(&db, path.to_str().unwrap()).unwrap(),
+        ImportCounts {
+            inserted: 1,
+            updated: 3,
+            invalid: 0,
+        }
+    );
+    assert_eq!(rows_of(&db), rows);
+}
+
+#[test]
+fn import_skips_invalid_rows() {
+    let db = temp_db("import-invalid");
+    let mut no_filepath = video_info("/media/a.mkv");
+    no_filepath.filepath.clear();
+    let mut negative = video_info("/media/b.mkv");
+    negative.width = -1;
+    let mut rows: Vec<Value> = [video_info("/media/ok.mkv"), no_filepath, negative]
+        .iter()
+        .map(|info| serde_json::to_value(info).unwrap())
+        .collect();
+    // a row without the required columns
+    rows.push(serde_json::json!({"filepath": "/media/c.mkv"}));
+    let path = temp_path("invalid.json");
+    fs::write(&path, serde_json::to_string(&rows).unwrap()).unwrap();
+
+    assert_eq!(
+        import_video_info(&db, path.to_str().unwrap()).unwrap(),
+        ImportCounts {
+            inserted: 1,
+            updated: 0,
+            invalid: 3,
+        }
+    );
+    assert_eq!(rows_of(&db), vec![video_info("/media/ok.mkv")]);
+}
+
+#[test]
+fn import_of_a_file_that_is_not_an_export_fails() {
+    let db = temp_db("import-broken");
+    let path = temp_path("broken.json");
+    fs::write(&path, "{ not json").unwrap();
+    assert!(import_video_info(&db, path.to_str().unwrap()).is_err());
+    assert!(import_video_info(&db, temp_path("missing.csv").to_str().unwrap()).is_err());
+}
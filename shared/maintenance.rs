@@ -0,0 +1,155 @@
+// --db-maintain and the nightly maintenance of the web server: the missing indexes, PRAGMA optimize,
+// VACUUM once enough pages are free, and the size and row counts before and after with the time
+// each step took
+
+use crate::*;
+
+use std::time::Instant;
+
+// The size of data.db with its write-ahead log
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DbSize {
+    pub bytes: u64,
+    pub page_count: i64,
+    pub free_pages: i64,
+}
+
+impl DbSize {
+    pub fn free_percent(&self) -> f64 {
+        if self.page_count > 0 {
+            self.free_pages as f64 * 100.0 / self.page_count as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaintenanceReport {
+    pub before: DbSize,
+    pub after: DbSize,
+    pub created_indexes: Vec<String>,
+    pub vacuumed: bool,
+    // rows by table, after
+    pub tables: Vec<(String, i64)>,
+    // milliseconds by step
+    pub steps: Vec<(String, u128)>,
+}
+
+fn file_size(path: &str) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn db_size(conn: &Connection, path: &str) -> Result<DbSize> {
+    Ok(DbSize {
+        bytes: file_size(path) + file_size(&format!("{}-wal", path)),
+        page_count: conn.query_row("PRAGMA page_count", params![], |row| row.get(0))?,
+        free_pages: conn.query_row("PRAGMA freelist_count", params![], |row| row.get(0))?,
+    })
+}
+
+fn table_rows(conn: &Connection) -> Result<Vec<(String, i64)>> {
+    let tables: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?;
+        let rows = stmt.query_map(params![], |row| row.get(0))?;
+        rows.collect::<Result<_>>()?
+    };
+    tables
+        .into_iter()
+        .map(|table| {
+            let rows = conn.query_row(
+                &format!("SELECT COUNT(*) FROM \"{}\"", table),
+                params![],
+                |row| row.get(0),
+            )?;
+            Ok((table, rows))
+        })
+        .collect()
+}
+
+impl Db {
+    // Maintain the database, VACUUM when at least vacuum_threshold percent of its pages are free.
+    // Other threads wait for the connection meanwhile
+    pub fn maintain(&self, vacuum_threshold: f64) -> Result<MaintenanceReport> {
+        let conn = self.conn.lock().unwrap();
+        let before = db_size(&conn, self.path())?;
+        let mut steps = Vec::new();
+        let mut step = |name: &str, started: Instant| {
+            steps.push((name.to_string(), started.elapsed().as_millis()))
+        };
+
+        let started = Instant::now();
+        let created = create_indexes(&conn)?;
+        step("indexes", started);
+
+        // 0x10002 analyzes every table that needs it, not only those this connection queried
+        let started = Instant::now();
+        conn.execute_batch("PRAGMA analysis_limit=1000; PRAGMA optimize=0x10002;")?;
+        step("optimize", started);
+
+        let vacuum = before.free_percent() >= vacuum_threshold;
+        if vacuum {
+            let started = Instant::now();
+            conn.execute_batch("VACUUM;")?;
+            step("vacuum", started);
+        }
+        // the pages VACUUM wrote are in the log until the checkpoint
+        let started = Instant::now();
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", params![], |_| Ok(()))?;
+        step("checkpoint", started);
+
+        let started = Instant::now();
+        let tables = table_rows(&conn)?;
+        step("count", started);
+
+        Ok(MaintenanceReport {
+            before,
+            after: db_size(&conn, self.path())?,
+            created_indexes: created.into_iter().map(String::from).collect(),
+            vacuumed: vacuum,
+            tables,
+            steps,
+        })
+    }
+}
+
+pub fn log_maintenance(report: &MaintenanceReport, vacuum_threshold: f64) {
+    info!(
+        "Database before: {}, {} of {} pages free ({:.1}%)",
+        format_size(report.before.bytes),
+        report.before.free_pages,
+        report.before.page_count,
+        report.before.free_percent()
+    );
+    if !report.created_indexes.is_empty() {
+        info!("Created indexes: {}", report.created_indexes.join(", "));
+    }
+    if !report.vacuumed {
+        info!(
+            "Not vacuumed, less than {}% of the pages are free",
+            vacuum_threshold
+        );
+    }
+    for (table, rows) in &report.tables {
+        info!("  {}: {} rows", table, rows);
+    }
+    let steps: Vec<String> = report
+        .steps
+        .iter()
+        .map(|(step, ms)| format!("{} {} ms", step, ms))
+        .collect();
+    info!("Took {}", steps.join(", "));
+    info!(
+        "{}",
+        format!(
+            "Database after: {} ({} saved), {} of {} pages free",
+            format_size(report.after.bytes),
+            format_size(report.before.bytes.saturating_sub(report.after.bytes)),
+            report.after.free_pages,
+            report.after.page_count
+        )
+        .green()
+    );
+}
@@ -0,0 +1,321 @@
+// Probe cache: one ffprobe -show_streams -show_format -show_chapters call per file and run, the
+// fps, bitrate, stream and frame count helpers read from it instead of spawning their own ffprobe
+// An entry is probed again when the size or modification time of the file changed, outputs are
+// probed while they are written
+
+use crate::*;
+
+lazy_static! {
+    static ref MEDIA_INFO_CACHE: Mutex<HashMap<PathBuf, Arc<MediaInfo>>> =
+        Mutex::new(HashMap::new());
+}
+
+// ffprobe calls made by probe_file since the start of the process
+static PROBE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Default)]
+pub struct MediaInfo {
+    pub path: PathBuf,
+    // streams of every type in file order, codec_type tells them apart
+    pub streams: Vec<Value>,
+    pub format: Value,
+    pub chapters: Vec<Value>,
+    // size and modification time when probed
+    len: u64,
+    modified: Option<SystemTime>,
+    // frames counted by decoding, only when the metadata has no frame count
+    counted_frames: OnceLock<f32>,
+    // seconds of the video stream by decoding, only when the metadata has no duration
+    decoded_duration: OnceLock<Option<f32>>,
+}
+
+// The video stream to encode: the one with the default disposition, or else the highest
+// resolution, the first of equals. Cover art and the null video streams of DVB/ATSC captures
+// (0x0) are never picked, when nothing else is left it is the first video stream
+pub fn select_video_stream(streams: &[Value]) -> Option<&Value> {
+    let videos: Vec<&Value> = streams
+        .iter()
+        .filter(|stream| stream["codec_type"] == "video")
+        .collect();
+    let area = |stream: &&Value| {
+        stream["width"].as_u64().unwrap_or(0) * stream["height"].as_u64().unwrap_or(0)
+    };
+    let candidates: Vec<&Value> = videos
+        .iter()
+        .copied()
+        .filter(|stream| stream["disposition"]["attached_pic"] != 1 && area(stream) > 0)
+        .collect();
+    let defaults: Vec<&Value> = candidates
+        .iter()
+        .copied()
+        .filter(|stream| stream["disposition"]["default"] == 1)
+        .collect();
+    let pool = if defaults.is_empty() {
+        candidates
+    } else {
+        defaults
+    };
+    // max_by_key keeps the last of equals, the reversed list makes that the first
+    pool.into_iter()
+        .rev()
+        .max_by_key(area)
+        .or_else(|| videos.first().copied())
+}
+
+impl MediaInfo {
+    pub fn streams_of(&self, codec_type: &str) -> Vec<&Value> {
+        self.streams
+            .iter()
+            .filter(|stream| stream["codec_type"] == codec_type)
+            .collect()
+    }
+
+    // The video stream to encode, see select_video_stream
+    pub fn video_stream(&self) -> Option<&Value> {
+        select_video_stream(&self.streams)
+    }
+
+    // The -map argument of the video stream, like 0:3
+    pub fn video_map(&self) -> String {
+        match self
+            .video_stream()
+            .and_then(|stream| stream["index"].as_u64())
+        {
+            Some(index) => format!("0:{}", index),
+            None => "0:v:0".to_string(),
+        }
+    }
+
+    // The -select_streams argument of ffprobe for the video stream, its index
+    pub fn video_select(&self) -> String {
+        match self
+            .video_stream()
+            .and_then(|stream| stream["index"].as_u64())
+        {
+            Some(index) => index.to_string(),
+            None => "v:0".to_string(),
+        }
+    }
+
+    // PQ (HDR10, Dolby Vision base layers) and HLG are HDR, by the transfer of the video stream
+    pub fn is_hdr(&self) -> bool {
+        self.video_stream()
+            .and_then(|stream| stream["color_transfer"].as_str())
+            .map_or(false, |transfer| {
+                matches!(transfer, "smpte2084" | "arib-std-b67")
+            })
+    }
+
+    // tt, bb, tb and bt are interlaced, progressive and unknown are not
+    pub fn is_interlaced(&self) -> bool {
+        self.video_stream()
+            .and_then(|stream| stream["field_order"].as_str())
+            .map_or(false, |field_order| {
+                matches!(field_order, "tt" | "bb" | "tb" | "bt")
+            })
+    }
+
+    // The duration of the format, or of the video stream. TS captures often have neither or 0
+    pub fn duration(&self) -> Option<f32> {
+        let positive = |duration: &Value| {
+            parse_decimal(duration.as_str()?)
+                .ok()
+                .filter(|duration| *duration > 0.0)
+        };
+        positive(&self.format["duration"]).or_else(|| positive(&self.video_stream()?["duration"]))
+    }
+
+    pub fn size(&self) -> Option<f32> {
+        parse_count(self.format["size"].as_str()?).ok()
+    }
+
+    // r_frame_rate of the video stream, 0 when unknown
+    pub fn fps(&self) -> f32 {
+        self.video_stream()
+            .map_or(0.0, |stream| frame_rate(stream, "r_frame_rate"))
+    }
+
+    // avg_frame_rate of the video stream, frames over duration, 0 when unknown
+    pub fn avg_fps(&self) -> f32 {
+        self.video_stream()
+            .map_or(0.0, |stream| frame_rate(stream, "avg_frame_rate"))
+    }
+
+    pub fn is_vfr(&self) -> bool {
+        self.video_stream().map_or(false, stream_is_vfr)
+    }
+
+    // Bitrate in kbps of the video stream, or of the whole file when the stream has none
+    pub fn bitrate_kbps(&self) -> f32 {
+        // a bit_rate of "N/A" gives way to the one of the format too
+        let bitrate = |json: &Value| parse_count(json["bit_rate"].as_str()?).ok();
+        self.video_stream()
+            .and_then(bitrate)
+            .or_else(|| bitrate(&self.format))
+            .unwrap_or(0.0)
+            / 1000.0
+    }
+
+    // A NUMBER_OF_FRAMES tag of the video stream (mkv), or its nb_frames (mp4)
+    pub fn tagged_frames(&self, tag: &str) -> Option<f32> {
+        parse_count(self.video_stream()?["tags"][tag].as_str()?).ok()
+    }
+
+    pub fn frame_count(&self) -> Option<f32> {
+        self.tagged_frames("NUMBER_OF_FRAMES-eng")
+            .or_else(|| self.tagged_frames("NUMBER_OF_FRAMES"))
+            .or_else(|| parse_count(self.video_stream()?["nb_frames"].as_str()?).ok())
+    }
+}
+
+// A frame rate field of a stream like "24000/1001", 0 when unknown
+pub(crate) fn frame_rate(stream: &Value, field: &str) -> f32 {
+    parse_rate(stream[field].as_str().unwrap_or("")).unwrap_or(0.0)
+}
+
+// Variable frame rate: the average rate differs from the base rate by more than 0.5%, phone clips
+// and some web downloads, constant rate files only differ by rounding
+pub fn stream_is_vfr(stream: &Value) -> bool {
+    let fps = frame_rate(stream, "r_frame_rate");
+    let avg_fps = frame_rate(stream, "avg_frame_rate");
+    fps > 0.0 && avg_fps > 0.0 && (fps - avg_fps).abs() > fps * 0.005
+}
+
+pub(crate) fn file_stamp(path: &Path) -> (u64, Option<SystemTime>) {
+    match fs::metadata(path) {
+        Ok(metadata) => (metadata.len(), metadata.modified().ok()),
+        Err(_) => (0, None),
+    }
+}
+
+fn stamp_ms(modified: Option<SystemTime>) -> i64 {
+    modified
+        .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_millis() as i64)
+}
+
+// The size and modification time (ms) a result about file is stored with in video_info, the
+// result no longer applies once they changed
+pub(crate) fn file_stamp_ms(file: &str) -> (i64, i64) {
+    let (len, modified) = file_stamp(Path::new(file));
+    (len as i64, stamp_ms(modified))
+}
+
+fn run_ffprobe(path: &Path) -> MediaInfo {
+    let count = PROBE_COUNT.fetch_add(1, Ordering::SeqCst) + 1;
+    debug!("ffprobe {} ({} probes)", path.display(), count);
+    let (len, modified) = file_stamp(path);
+    let output = ffprobe_command()
+        .arg("-v")
+        .arg("error")
+        .arg("-show_streams")
+        .arg("-show_format")
+        .arg("-show_chapters")
+        .arg("-of")
+        .arg("json")
+        .arg("-i")
+        .arg(path)
+        .output();
+    let json: Value = match output {
+        Ok(output) => probe_json(&output, &path.to_string_lossy()),
+        Err(e) => {
+            warn!("Failed to run ffprobe on {}: {}", path.display(), e);
+            Value::Null
+        }
+    };
+    media_info(path, &json, (len, modified))
+}
+
+// The probe of path from the json of ffprobe, with the size and modification time it was probed at
+pub(crate) fn media_info(
+    path: &Path,
+    json: &Value,
+    (len, modified): (u64, Option<SystemTime>),
+) -> MediaInfo {
+    MediaInfo {
+        path: path.to_path_buf(),
+        streams: json["streams"].as_array().cloned().unwrap_or_default(),
+        format: json["format"].clone(),
+        chapters: json["chapters"].as_array().cloned().unwrap_or_default(),
+        len,
+        modified,
+        counted_frames: OnceLock::new(),
+        decoded_duration: OnceLock::new(),
+    }
+}
+
+// The probe of file, from the cache unless the file changed since it was probed
+pub fn probe_file(file: impl AsRef<Path>) -> Arc<MediaInfo> {
+    let path = file.as_ref();
+    let stamp = file_stamp(path);
+    if let Some(info) = MEDIA_INFO_CACHE.lock().unwrap().get(path) {
+        if (info.len, info.modified) == stamp {
+            return info.clone();
+        }
+    }
+    // probe without the lock, the scene threads probe different files in parallel
+    cache_media_info(run_ffprobe(path))
+}
+
+// Keep info in the cache until its file changes
+pub(crate) fn cache_media_info(info: MediaInfo) -> Arc<MediaInfo> {
+    let info = Arc::new(info);
+    MEDIA_INFO_CACHE
+        .lock()
+        .unwrap()
+        .insert(info.path.clone(), info.clone());
+    info
+}
+
+// Forget all probes, Pipeline::run starts every run with an empty cache
+pub fn clear_media_info_cache() {
+    MEDIA_INFO_CACHE.lock().unwrap().clear();
+}
+
+pub fn probe_count() -> usize {
+    PROBE_COUNT.load(Ordering::SeqCst)
+}
+
+// Frame count from the database when the file did not change since it was counted, otherwise from
+// the metadata, decoding the file once as the last resort
+pub fn frame_count(file: &str) -> f32 {
+    cached_frame_count(file).0
+}
+
+// frame_count and whether it came from the database. A count of a file in video_info is stored
+// with its size and modification time, a changed file is counted again
+pub fn cached_frame_count(file: &str) -> (f32, bool) {
+    let (len, modified) = file_stamp(Path::new(file));
+    let mtime = stamp_ms(modified);
+    if let Ok(Some(frames)) = db().cached_frame_count(file, len as i64, mtime) {
+        return (frames as f32, true);
+    }
+
+    let info = probe_file(file);
+    let frames = match info.frame_count() {
+        Some(frames) => frames,
+        None => *info.counted_frames.get_or_init(|| {
+            get_framecount_ffmpeg(file).unwrap_or_else(|_| get_framecount(file).unwrap_or(0.0))
+        }),
+    };
+    if frames > 0.0 && modified.is_some() {
+        if let Err(e) = db().update_frame_count(file, frames as f64, len as i64, mtime) {
+            warn!("Failed to store the frame count of {}: {}", file, e);
+        }
+    }
+    (frames, false)
+}
+
+// Duration from the metadata, decoding the video stream once as the last resort
+pub fn media_duration(file: &str) -> Option<f32> {
+    let info = probe_file(file);
+    if let Some(duration) = info.duration() {
+        return Some(duration);
+    }
+    *info
+        .decoded_duration
+        .get_or_init(|| get_duration_ffmpeg(file))
+}
+
+#[cfg(test)]
+mod tests;
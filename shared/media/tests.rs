@@ -0,0 +1,193 @@
+use super::*;
+
+// What ffprobe shows of a DVB capture: a null video stream, a data stream, an interlaced SD
+// program, the HD program and its audio, and no format duration
+fn ts_capture() -> Value {
+    serde_json::json!({
+        "streams": [
+            {"index": 0, "codec_type": "video", "codec_name": "mpeg2video", "width": 0, "height": 0},
+            {"index": 1, "codec_type": "data", "codec_name": "bin_data"},
+            {
+                "index": 2, "codec_type": "video", "codec_name": "mpeg2video",
+                "width": 720, "height": 576, "field_order": "tt", "duration": "1790.5"
+            },
+            {
+                "index": 3, "codec_type": "video", "codec_name": "h264",
+                "width": 1920, "height": 1080, "field_order": "progressive", "duration": "1800.25"
+            },
+            {"index": 4, "codec_type": "audio", "codec_name": "ac3"},
+        ],
+        "format": {"format_name": "mpegts", "duration": "N/A"},
+    })
+}
+
+fn streams_of(json: &Value) -> Vec<Value> {
+    json["streams"].as_array().cloned().unwrap()
+}
+
+fn selected_index(streams: &[Value]) -> Option<u64> {
+    select_video_stream(streams).and_then(|stream| stream["index"].as_u64())
+}
+
+fn capture_info(json: &Value) -> MediaInfo {
+    media_info(Path::new("capture.ts"), json, (0, None))
+}
+
+#[test]
+fn the_highest_resolution_is_selected_without_a_default() {
+    assert_eq!(selected_index(&streams_of(&ts_capture())), Some(3));
+}
+
+#[test]
+fn the_default_disposition_is_selected_first() {
+    let mut streams = streams_of(&ts_capture());
+    streams[2]["disposition"] = serde_json::json!({"default": 1});
+    assert_eq!(selected_index(&streams), Some(2));
+    // the default of a null stream is not taken
+    let mut streams = streams_of(&ts_capture());
+    streams[0]["disposition"] = serde_json::json!({"default": 1});
+    assert_eq!(selected_index(&streams), Some(3));
+}
+
+#[test]
+fn cover_art_and_null_streams_are_not_selected() {
+    let mut streams = streams_of(&ts_capture());
+    streams[3]["disposition"] = serde_json::json!({"attached_pic": 1});
+    assert_eq!(selected_index(&streams), Some(2));
+
+    // the first of equal resolutions
+    let mut streams = streams_of(&ts_capture());
+    streams[2]["width"] = 1920.into();
+    streams[2]["height"] = 1080.into();
+    assert_eq!(selected_index(&streams), Some(2));
+
+    // with nothing else left, the first video stream
+    let streams = streams_of(&ts_capture());
+    assert_eq!(selected_index(&streams[..2]), Some(0));
+    assert_eq!(selected_index(&streams[1..2]), None);
+}
+
+#[test]
+fn the_maps_use_the_index_of_the_selected_stream() {
+    let info = capture_info(&ts_capture());
+    assert_eq!(info.video_map(), "0:3");
+    assert_eq!(info.video_select(), "3");
+
+    let info = capture_info(&serde_json::json!({
+        "streams": [{"index": 0, "codec_type": "audio"}]
+    }));
+    assert_eq!(info.video_map(), "0:v:0");
+    assert_eq!(info.video_select(), "v:0");
+}
+
+#[test]
+fn interlacing_is_the_field_order_of_the_selected_stream() {
+    // the SD program is interlaced, the HD one that is encoded is not
+    assert!(!capture_info(&ts_capture()).is_interlaced());
+
+    for (field_order, interlaced) in [
+        ("tt", true),
+        ("bb", true),
+        ("tb", true),
+        ("bt", true),
+        ("progressive", false),
+        ("unknown", false),
+    ] {
+        let mut json = ts_capture();
+        json["streams"][3]["field_order"] = field_order.into();
+        assert_eq!(
+            capture_info(&json).is_interlaced(),
+            interlaced,
+            "{}",
+            field_order
+        );
+    }
+}
+
+#[test]
+fn should_deinterlace_follows_the_option_and_the_field_order() {
+    let mut json = ts_capture();
+    let progressive = probed_file("progressive.ts", json.clone());
+    json["streams"][3]["field_order"] = "tt".into();
+    let interlaced = probed_file("interlaced.ts", json);
+
+    assert!(!should_deinterlace(&progressive, "auto"));
+    assert!(should_deinterlace(&interlaced, "auto"));
+    assert!(should_deinterlace(&progressive, "on"));
+    assert!(!should_deinterlace(&interlaced, "off"));
+}
+
+#[test]
+fn a_missing_format_duration_falls_back_to_the_stream() {
+    assert_eq!(capture_info(&ts_capture()).duration(), Some(1800.25));
+    let file = probed_file("duration.ts", ts_capture());
+    assert_eq!(media_duration(&file), Some(1800.25));
+
+    for format_duration in ["0.000000", "N/A", ""] {
+        let mut json = ts_capture();
+        json["format"]["duration"] = format_duration.into();
+        assert_eq!(
+            capture_info(&json).duration(),
+            Some(1800.25),
+            "{}",
+            format_duration
+        );
+    }
+
+    let mut json = ts_capture();
+    json["format"]["duration"] = "1805.5".into();
+    assert_eq!(capture_info(&json).duration(), Some(1805.5));
+
+    // neither, media_duration reads the video stream to the end
+    json["format"]["duration"] = "0".into();
+    json["streams"][3]["duration"] = "0".into();
+    assert_eq!(capture_info(&json).duration(), None);
+}
+
+// A multi-program TS muxed by ffmpeg: an SD program and an HD one of 2 seconds
+fn muxed_capture(name: &str) -> String {
+    let capture = temp_path(name).to_string_lossy().to_string();
+    let status = ffmpeg_command()
+        .args(["-v", "error", "-y"])
+        .args([
+            "-f",
+            "lavfi",
+            "-i",
+            "testsrc=size=720x576:rate=25:duration=2",
+        ])
+        .args([
+            "-f",
+            "lavfi",
+            "-i",
+            "testsrc=size=1280x720:rate=25:duration=2",
+        ])
+        .args(["-map", "0:v", "-map", "1:v", "-c:v", "mpeg2video"])
+        .args(["-program", "title=SD:st=0", "-program", "title=HD:st=1"])
+        .args(["-f", "mpegts", &capture])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    capture
+}
+
+#[test]
+fn the_hd_program_of_a_muxed_capture_is_selected() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let capture = muxed_capture("multi-program.ts");
+    let info = probe_file(&capture);
+    assert_eq!(info.streams_of("video").len(), 2);
+    let stream = info.video_stream().unwrap();
+    assert_eq!(stream["width"], 1280);
+    assert_eq!(
+        info.video_map(),
+        format!("0:{}", stream["index"].as_u64().unwrap())
+    );
+
+    // the time of the last progress line of reading the selected stream
+    let duration = get_duration_ffmpeg(&capture).unwrap();
+    assert!((duration - 2.0).abs() < 0.1, "{}", duration);
+}
@@ -0,0 +1,198 @@
+// The title, show, season, episode and date tags of a container: the scan stores them in
+// video_info, the search of the library matches them next to the path and inspect and the summary
+// of a run name a file by its title instead of its often cryptic file name
+
+use crate::*;
+
+// The tags of a file, empty when the container has none
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MediaTags {
+    pub title: String,
+    pub show: String,
+    pub season: String,
+    pub episode: String,
+    pub date: String,
+}
+
+// The value of the first of keys in tags, matroska writes them upper case and mp4 lower case
+fn tag_value(tags: &Value, keys: &[&str]) -> String {
+    let tags = match tags.as_object() {
+        Some(tags) => tags,
+        None => return String::new(),
+    };
+    keys.iter()
+        .find_map(|key| {
+            tags.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .and_then(|(_, value)| value.as_str())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        })
+        .unwrap_or("")
+        .to_string()
+}
+
+impl MediaTags {
+    // The tags of the format of an ffprobe -show_format json
+    pub fn from_format(format: &Value) -> MediaTags {
+        let tags = &format["tags"];
+        MediaTags {
+            title: tag_value(tags, &["title"]),
+            show: tag_value(tags, &["show", "artist", "album_artist"]),
+            season: tag_value(tags, &["season_number", "season"]),
+            episode: tag_value(tags, &["episode_sort", "episode", "episode_id"]),
+            date: tag_value(tags, &["date", "year", "date_released"]),
+        }
+    }
+
+    // Show S01E02 - Title, Show - Title or Title, None without a title
+    pub fn label(&self) -> Option<String> {
+        if self.title.is_empty() {
+            return None;
+        }
+        if self.show.is_empty() || self.show.eq_ignore_ascii_case(&self.title) {
+            return Some(self.title.clone());
+        }
+        match (self.season.parse::<u32>(), self.episode.parse::<u32>()) {
+            (Ok(season), Ok(episode)) => Some(format!(
+                "{} S{:02}E{:02} - {}",
+                self.show, season, episode, self.title
+            )),
+            _ => Some(format!("{} - {}", self.show, self.title)),
+        }
+    }
+}
+
+// The width of the terminal from $COLUMNS, 100 when it is not set
+pub fn terminal_columns() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse::<usize>().ok())
+        .filter(|columns| *columns > 0)
+        .unwrap_or(100)
+}
+
+// text in at most width characters, the middle replaced by ... when it is longer, the end of a
+// name tells the episodes of a show apart
+pub fn truncate_middle(text: &str, width: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= width || width < 8 {
+        return text.to_string();
+    }
+    let kept = width - 3;
+    let head = kept - kept / 3;
+    let tail = kept / 3;
+    format!(
+        "{}...{}",
+        chars[..head].iter().collect::<String>(),
+        chars[chars.len() - tail..].iter().collect::<String>()
+    )
+}
+
+// How the reports name path: Label (file name) when its tags have a title, in at most width
+// characters, the path as it is otherwise
+pub fn titled_path(label: Option<&str>, path: &str, width: usize) -> String {
+    match label {
+        Some(label) => {
+            let name = truncate_middle(&file_name_of(path), width.saturating_sub(3) / 2);
+            let label = truncate_middle(label, width.saturating_sub(name.chars().count() + 3));
+            format!("{} ({})", label, name)
+        }
+        None => path.to_string(),
+    }
+}
+
+// A row of the library search of the web server
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryMatch {
+    pub id: i64,
+    // filename, path or tags, what search matched, in this order
+    pub matched: String,
+    #[serde(flatten)]
+    pub info: VideoInfo,
+}
+
+impl Db {
+    // The tags stored by the scan of filepath, None when it was never scanned
+    pub fn media_tags(&self, filepath: &str) -> Result<Option<MediaTags>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT title, show, season, episode, date FROM video_info WHERE filepath=?1",
+        )?;
+        let mut rows = stmt.query_map(params![filepath], |row| {
+            Ok(MediaTags {
+                title: row.get(0)?,
+                show: row.get(1)?,
+                season: row.get(2)?,
+                episode: row.get(3)?,
+                date: row.get(4)?,
+            })
+        })?;
+        rows.next().transpose()
+    }
+
+    pub fn update_media_tags(&self, filepath: &str, tags: &MediaTags) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE video_info SET title=?1, show=?2, season=?3, episode=?4, date=?5 WHERE filepath=?6",
+            params![tags.title, tags.show, tags.season, tags.episode, tags.date, filepath],
+        )?;
+        Ok(())
+    }
+
+    // The rows with every word of search in their path, title or show and, when title is not
+    // empty, a title starting with it, ranked like Db::search
+    pub fn search_library(&self, search: &str, title: &str) -> Result<Vec<LibraryMatch>> {
+        let conn = self.conn.lock().unwrap();
+        let words = search_words(search);
+        let mut values = words.clone();
+        let mut condition = search_condition(words.len(), 1);
+        if !title.is_empty() {
+            // a prefix range the NOCASE title index answers, like the path search
+            let first = words.len() + 1;
+            condition = format!(
+                "{} AND title >= ?{} COLLATE NOCASE AND title < ?{} COLLATE NOCASE",
+                condition,
+                first,
+                first + 1
+            );
+            values.push(title.to_string());
+            values.push(format!("{}\u{10FFFF}", title));
+        }
+        let mut stmt = conn.prepare(&format!(
+            "SELECT * FROM video_info WHERE {} ORDER BY id",
+            condition
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(&values), |row| {
+            Ok((
+                row.get::<_, i64>("id")?,
+                Db::video_info_from_named_row(row)?,
+            ))
+        })?;
+        let mut matches = Vec::new();
+        for row in rows {
+            let (id, info) = row?;
+            // only the title filter matched a search without words
+            let rank = if search.trim().is_empty() && !title.is_empty() {
+                2
+            } else {
+                search_rank(&info.filename, &info.filepath, search)
+            };
+            matches.push((rank, id, info));
+        }
+        matches.sort_by_key(|(rank, id, _)| (*rank, *id));
+        Ok(matches
+            .into_iter()
+            .map(|(rank, id, info)| LibraryMatch {
+                id,
+                matched: ["filename", "path", "tags"][rank as usize].to_string(),
+                info,
+            })
+            .collect())
+    }
+}
+
+// The label of the tags the scan stored for path, None without a title
+pub(crate) fn stored_label(path: &str) -> Option<String> {
+    db().media_tags(path).ok().flatten()?.label()
+}
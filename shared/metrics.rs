@@ -0,0 +1,228 @@
+// Counters and gauges of GET /metrics in the Prometheus text format, the web server serves them with
+// its routes and the cli on --metrics-port
+// The pipeline updates them as it goes, the queue depth and the scans are read when scraped
+
+use crate::*;
+
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+
+#[derive(Debug, PartialEq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+// A metric name, help and type, the samples are kept by their labels
+#[derive(Debug)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+}
+
+pub const FILES_TRANSCODED: Metric = Metric {
+    name: "transrustica_files_transcoded_total",
+    help: "Files encoded or remuxed successfully",
+    kind: MetricKind::Counter,
+};
+pub const BYTES_SAVED: Metric = Metric {
+    name: "transrustica_bytes_saved_total",
+    help: "Input size minus output size of the finished files",
+    kind: MetricKind::Counter,
+};
+pub const FRAMES_PROCESSED: Metric = Metric {
+    name: "transrustica_frames_processed_total",
+    help: "Frames written by the encodes",
+    kind: MetricKind::Counter,
+};
+pub const ENCODE_FAILURES: Metric = Metric {
+    name: "transrustica_encode_failures_total",
+    help: "Files that failed",
+    kind: MetricKind::Counter,
+};
+pub const TASK_FPS: Metric = Metric {
+    name: "transrustica_task_fps",
+    help: "Frames per second of the running encode, 0 between files",
+    kind: MetricKind::Gauge,
+};
+pub const QUEUE_DEPTH: Metric = Metric {
+    name: "transrustica_queue_depth",
+    help: "Entries in the db_queue table",
+    kind: MetricKind::Gauge,
+};
+pub const SCAN_PROBED_FILES: Metric = Metric {
+    name: "transrustica_scan_probed_files",
+    help: "Files probed by the scan so far",
+    kind: MetricKind::Gauge,
+};
+pub const SCAN_TOTAL_FILES: Metric = Metric {
+    name: "transrustica_scan_total_files",
+    help: "Files the scan found",
+    kind: MetricKind::Gauge,
+};
+
+// In the order of the exposition, every metric is listed even without samples
+const METRICS: [&Metric; 8] = [
+    &FILES_TRANSCODED,
+    &BYTES_SAVED,
+    &FRAMES_PROCESSED,
+    &ENCODE_FAILURES,
+    &TASK_FPS,
+    &QUEUE_DEPTH,
+    &SCAN_PROBED_FILES,
+    &SCAN_TOTAL_FILES,
+];
+
+lazy_static! {
+    // samples by metric name, then by their rendered labels
+    static ref METRIC_VALUES: Mutex<HashMap<&'static str, BTreeMap<String, f64>>> =
+        Mutex::new(HashMap::new());
+}
+
+// {task_id="...",encoder="..."}, the labels of the transcode metrics
+pub fn task_labels<'a>(task_id: &'a str, encoder: &'a str) -> [(&'static str, &'a str); 2] {
+    [("task_id", task_id), ("encoder", encoder)]
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+// Add by to a counter
+pub fn inc_metric(metric: &Metric, labels: &[(&str, &str)], by: f64) {
+    let mut values = METRIC_VALUES.lock().unwrap();
+    *values
+        .entry(metric.name)
+        .or_default()
+        .entry(render_labels(labels))
+        .or_insert(0.0) += by;
+}
+
+pub fn set_metric(metric: &Metric, labels: &[(&str, &str)], value: f64) {
+    METRIC_VALUES
+        .lock()
+        .unwrap()
+        .entry(metric.name)
+        .or_default()
+        .insert(render_labels(labels), value);
+}
+
+// Read the gauges that live elsewhere: the queue in db and the scans
+fn refresh_metrics(db: &Db) {
+    match db.queue_all() {
+        Ok(queue) => set_metric(&QUEUE_DEPTH, &[], queue.len() as f64),
+        Err(e) => warn!("Failed to read the queue for the metrics: {}", e),
+    }
+
+    // the cli scan, unset values are u64::MAX
+    let count = *WEB_SCAN_COUNT_STATIC.lock().unwrap();
+    let total = *WEB_SCAN_TOTAL_STATIC.lock().unwrap();
+    if count != u64::MAX && total != u64::MAX {
+        set_metric(&SCAN_PROBED_FILES, &[("scan_id", "")], count as f64);
+        set_metric(&SCAN_TOTAL_FILES, &[("scan_id", "")], total as f64);
+    }
+    for (scan_id, scan) in WEB_SCANS_STATIC.lock().unwrap().iter() {
+        set_metric(
+            &SCAN_PROBED_FILES,
+            &[("scan_id", scan_id)],
+            scan.count as f64,
+        );
+        set_metric(
+            &SCAN_TOTAL_FILES,
+            &[("scan_id", scan_id)],
+            scan.total as f64,
+        );
+    }
+}
+
+// All metrics in the text exposition format
+pub fn render_metrics() -> String {
+    render_db_metrics(db())
+}
+
+// render_metrics with the queue of db
+pub(crate) fn render_db_metrics(db: &Db) -> String {
+    refresh_metrics(db);
+    let values = METRIC_VALUES.lock().unwrap();
+    let mut text = String::new();
+    for metric in METRICS {
+        let kind = match metric.kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+        text.push_str(&format!("# HELP {} {}\n", metric.name, metric.help));
+        text.push_str(&format!("# TYPE {} {}\n", metric.name, kind));
+        if let Some(samples) = values.get(metric.name) {
+            for (labels, value) in samples {
+                text.push_str(&format!("{}{} {}\n", metric.name, labels, value));
+            }
+        }
+    }
+    text
+}
+
+// --metrics-port: answer every GET /metrics on port from a background thread
+pub fn serve_metrics(port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .map_err(|e| format!("Failed to listen on port {} for the metrics: {}", port, e))?;
+    info!("Serving metrics at http://0.0.0.0:{}/metrics", port);
+    serve_db_metrics(listener, db());
+    Ok(())
+}
+
+// Answer the connections of listener with the metrics of db from a background thread
+pub(crate) fn serve_db_metrics(listener: TcpListener, db: &'static Db) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Metrics connection failed: {}", e);
+                    continue;
+                }
+            };
+            // a client that sends nothing doesn't block the scrapes after it
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            // the request line, then the headers up to the blank line are read and ignored
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            if reader.read_line(&mut request_line).is_err() {
+                continue;
+            }
+            let mut header = String::new();
+            while reader.read_line(&mut header).map_or(false, |n| n > 2) {
+                header.clear();
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap_or("");
+            let response = if request_line.starts_with("GET ") && path == "/metrics" {
+                let body = render_db_metrics(db);
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,137 @@
+use super::*;
+
+use std::net::TcpStream;
+
+// GET path of the metrics served by listener on addr, the status line and the body
+fn scrape(addr: std::net::SocketAddr, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+// The value of the sample of name with labels in text
+fn sample(text: &str, name: &str, labels: &str) -> Option<f64> {
+    let prefix = format!("{}{} ", name, labels);
+    text.lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map(|value| value.parse().unwrap())
+}
+
+#[test]
+fn every_metric_is_listed_with_its_help_and_type() {
+    let text = render_db_metrics(&temp_db("metrics-listed"));
+    let names: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("# TYPE "))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "transrustica_files_transcoded_total counter",
+            "transrustica_bytes_saved_total counter",
+            "transrustica_frames_processed_total counter",
+            "transrustica_encode_failures_total counter",
+            "transrustica_task_fps gauge",
+            "transrustica_queue_depth gauge",
+            "transrustica_scan_probed_files gauge",
+            "transrustica_scan_total_files gauge",
+        ]
+    );
+    for metric in METRICS {
+        assert!(
+            text.contains(&format!("# HELP {} {}\n", metric.name, metric.help)),
+            "{}",
+            metric.name
+        );
+    }
+}
+
+#[test]
+fn the_labels_are_escaped() {
+    assert_eq!(render_labels(&[]), "");
+    assert_eq!(
+        render_labels(&task_labels("task-1", "libx265")),
+        r#"{task_id="task-1",encoder="libx265"}"#
+    );
+    assert_eq!(
+        render_labels(&[("scan_id", "a\"b\\c\nd")]),
+        r#"{scan_id="a\"b\\c\nd"}"#
+    );
+}
+
+#[test]
+fn scrape_during_a_transcode() {
+    // the database of the server lives as long as its thread
+    let db: &'static Db = Box::leak(Box::new(temp_db("metrics-scrape")));
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    serve_db_metrics(listener, db);
+
+    let labels = task_labels("metrics-task", "libx265");
+    let task_labels = render_labels(&labels);
+
+    // the transcode is running: frames come in and the fps is set
+    inc_metric(&FRAMES_PROCESSED, &labels, 120.0);
+    inc_metric(&FRAMES_PROCESSED, &labels, 120.0);
+    set_metric(&TASK_FPS, &labels, 48.0);
+    db.queue_add("/media/a.mkv", "", "libx265", "", "95", "", 0, "", "")
+        .unwrap();
+    db.queue_add("/media/b.mkv", "", "libx265", "", "95", "", 0, "", "")
+        .unwrap();
+    WEB_SCANS_STATIC.lock().unwrap().insert(
+        "metrics-scan".to_string(),
+        ProgressScan {
+            count: 3,
+            total: 10,
+            ..Default::default()
+        },
+    );
+
+    let (status, text) = scrape(addr, "/metrics");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let value = |name: &str, labels: &str| sample(&text, name, labels);
+    assert_eq!(
+        value("transrustica_frames_processed_total", &task_labels),
+        Some(240.0)
+    );
+    assert_eq!(value("transrustica_task_fps", &task_labels), Some(48.0));
+    assert_eq!(value("transrustica_queue_depth", ""), Some(2.0));
+    let scan = r#"{scan_id="metrics-scan"}"#;
+    assert_eq!(value("transrustica_scan_probed_files", scan), Some(3.0));
+    assert_eq!(value("transrustica_scan_total_files", scan), Some(10.0));
+    assert_eq!(
+        value("transrustica_files_transcoded_total", &task_labels),
+        None
+    );
+
+    // the file is done 600 bytes smaller, as log_size_reduction counts it, the next one fails
+    inc_metric(&FILES_TRANSCODED, &labels, 1.0);
+    inc_metric(&BYTES_SAVED, &labels, 600.0);
+    set_metric(&TASK_FPS, &labels, 0.0);
+    inc_metric(&ENCODE_FAILURES, &labels, 1.0);
+    db.queue_remove("1").unwrap();
+
+    let (_, text) = scrape(addr, "/metrics");
+    let value = |name: &str, labels: &str| sample(&text, name, labels);
+    assert_eq!(
+        value("transrustica_files_transcoded_total", &task_labels),
+        Some(1.0)
+    );
+    assert_eq!(
+        value("transrustica_bytes_saved_total", &task_labels),
+        Some(600.0)
+    );
+    assert_eq!(
+        value("transrustica_encode_failures_total", &task_labels),
+        Some(1.0)
+    );
+    assert_eq!(value("transrustica_task_fps", &task_labels), Some(0.0));
+    assert_eq!(value("transrustica_queue_depth", ""), Some(1.0));
+
+    assert_eq!(scrape(addr, "/other").0, "HTTP/1.1 404 Not Found");
+}
@@ -0,0 +1,240 @@
+// The native CRF search: the encodes and VMAF of samples of the source with ffmpeg, searching for
+// the CRF that reaches the target when ab-av1 is not installed
+
+use crate::*;
+
+// Length of the samples of the native CRF search, the same as ab-av1's
+const NATIVE_SAMPLE_SECONDS: f32 = 20.0;
+
+// CRFs the native CRF search measures at most for one VMAF target
+const NATIVE_CRF_SEARCH_ITERATIONS: usize = 6;
+
+// The CRF the native CRF search measures first, --estimate encodes its samples at it
+pub(crate) const NATIVE_START_CRF: f32 = 23.0;
+
+// Start and end of the native CRF search samples, one sample centered in every sample_every seconds
+// Files shorter than a sample are measured as a whole
+fn native_crf_samples(duration: f32, sample_every: f32) -> Vec<(f32, f32)> {
+    if duration <= NATIVE_SAMPLE_SECONDS {
+        return vec![(0.0, duration)];
+    }
+    let samples = (duration / sample_every).round().max(1.0) as usize;
+    (0..samples)
+        .map(|sample| {
+            let center = duration * (sample as f32 + 0.5) / samples as f32;
+            let ss = (center - NATIVE_SAMPLE_SECONDS / 2.0).max(0.0);
+            (ss, (ss + NATIVE_SAMPLE_SECONDS).min(duration))
+        })
+        .collect()
+}
+
+// Encode the samples at crf and pool their VMAF with --vmaf-pool, None when no sample could be measured
+fn native_samples_vmaf(
+    file: &str,
+    args: &Args,
+    crf: f32,
+    fps: &str,
+    samples: &[(f32, f32)],
+    sample_bar: &ProgressBar,
+    scale: Option<&str>,
+) -> Option<f32> {
+    sample_bar.set_position(0);
+    sample_bar.set_message(format!("CRF {}", crf));
+    let mut scores: Vec<f32> = Vec::new();
+    for (ss, to) in samples {
+        let ss = format!("{:.3}", ss);
+        let to = format!("{:.3}", to);
+        match process_video_pipe_and_vmaf(
+            &file.to_string(),
+            args,
+            &crf,
+            fps,
+            &ss,
+            &to,
+            &args.vmaf_pool,
+            &args.vmaf_threads,
+            &args.vmaf_subsample,
+            scale,
+            None,
+        ) {
+            Ok(output) => match parse_vmaf_score(&output) {
+                Some(score) => scores.push(score),
+                None => warn!(
+                    "No VMAF score for {} from {} to {} at CRF {}",
+                    file, ss, to, crf
+                ),
+            },
+            Err(e) => error!("Failed to execute ffmpeg: {}", e),
+        }
+        sample_bar.inc(1);
+    }
+    pool_vmaf_scores(&scores, &args.vmaf_pool)
+}
+
+// Highest CRF whose pooled sample VMAF reaches vmaf_target, stepping the CRF like the chunked mode
+// The (crf, vmaf) measurements are kept in measured, so a retry with a lower target reuses them
+fn native_crf_search_target(
+    file: &str,
+    args: &Args,
+    fps: &str,
+    samples: &[(f32, f32)],
+    vmaf_target: f32,
+    max_crf: f32,
+    measured: &mut Vec<(f32, f32)>,
+    sample_bar: &ProgressBar,
+    scale: Option<&str>,
+) -> Result<Option<(f32, f32)>, String> {
+    let mut crf = NATIVE_START_CRF.min(max_crf);
+    let mut min_crf = 10.0_f32.min(max_crf);
+    let mut max_crf_range = max_crf;
+
+    for _ in 0..NATIVE_CRF_SEARCH_ITERATIONS {
+        let vmaf_score = match measured
+            .iter()
+            .find(|(measured_crf, _)| *measured_crf == crf)
+        {
+            Some((_, vmaf_score)) => *vmaf_score,
+            None => {
+                let vmaf_score =
+                    native_samples_vmaf(file, args, crf, fps, samples, sample_bar, scale)
+                        .ok_or_else(|| {
+                            format!("Failed to measure the VMAF of {} at CRF {}", file, crf)
+                        })?;
+                debug!("{}: CRF {} VMAF {:.2}", file, crf, vmaf_score);
+                measured.push((crf, vmaf_score));
+                vmaf_score
+            }
+        };
+        // reaching the target by less than 0.5 is close enough
+        if vmaf_score >= vmaf_target && vmaf_score - vmaf_target <= 0.5 {
+            break;
+        }
+
+        let previous_crf = crf;
+        adjust_crf(
+            vmaf_target,
+            vmaf_score,
+            &mut crf,
+            &mut min_crf,
+            &mut max_crf_range,
+        );
+        // whole CRFs like ab-av1, within --max-crf
+        crf = crf.round().clamp(0.0, max_crf);
+        if crf == previous_crf || min_crf > max_crf_range {
+            break;
+        }
+    }
+
+    Ok(measured
+        .iter()
+        .filter(|(_, vmaf_score)| *vmaf_score >= vmaf_target)
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+        .cloned())
+}
+
+// CRF search without ab-av1 for --crf-search native: encodes the --sample-every samples and pools
+// their VMAF, lowering the VMAF target until a CRF reaches it
+// scale is the filter of a --renditions encode, the samples are measured at that size
+// Returns the same as run_ab_av1_crf_search, without a predicted size
+pub fn run_native_crf_search(
+    file: &str,
+    args: &Args,
+    mut vmaf: f32,
+    max_crf: &str,
+    sample_every: &str,
+    task_id: &str,
+    current_file_count: &u64,
+    total_files: &i32,
+    scale: Option<&str>,
+) -> Result<(String, f32, Option<PredictedSize>), String> {
+    let duration =
+        get_duration(file).ok_or_else(|| format!("Failed to get the duration of {}", file))?;
+    let sample_every = humantime::parse_duration(sample_every)
+        .map_err(|e| format!("Invalid --sample-every {}: {}", sample_every, e))?
+        .as_secs_f32();
+    let max_crf = max_crf
+        .parse::<f32>()
+        .map_err(|e| format!("Invalid --max-crf {}: {}", max_crf, e))?;
+    // a trial samples its range
+    let samples = match trial_range(file) {
+        Some(range) => {
+            native_crf_samples(range.duration(), sample_every.max(NATIVE_SAMPLE_SECONDS))
+                .into_iter()
+                .map(|(ss, to)| (range.start + ss, range.start + to))
+                .collect()
+        }
+        None => native_crf_samples(duration, sample_every.max(NATIVE_SAMPLE_SECONDS)),
+    };
+    let fps = get_fps(file);
+
+    let requested_vmaf = vmaf;
+    set_crf_search_progress(task_id, current_file_count, total_files, vmaf, vmaf);
+    let sample_bar = LOG_PROGRESS.add(ProgressBar::new(samples.len() as u64));
+    let sample_style =
+        "[crf-search][{elapsed_precise}][{wide_bar:.cyan/blue}] {pos:>3}/{len:3} [{msg}]";
+    sample_bar.set_style(ProgressStyle::default_bar().template(sample_style).unwrap());
+
+    let mut measured: Vec<(f32, f32)> = Vec::new();
+    // the samples of --estimate measured the first CRF already, not those of a trial or a rendition
+    if trial_range(file).is_none() && scale.is_none() {
+        if let Some((crf, vmaf_score)) = estimate_warm_start(file, args) {
+            info!(
+                "{}: starting from the --estimate samples, CRF {} VMAF {:.2}",
+                file, crf, vmaf_score
+            );
+            measured.push((crf, vmaf_score));
+        }
+    }
+    let result = loop {
+        info!(
+            "{}",
+            format!(
+                "Searching for best CRF for VMAF {} on {} samples...",
+                vmaf,
+                samples.len()
+            )
+            .yellow()
+        );
+        let found = native_crf_search_target(
+            file,
+            args,
+            &fps,
+            &samples,
+            vmaf,
+            max_crf,
+            &mut measured,
+            &sample_bar,
+            scale,
+        );
+        match found {
+            Ok(Some((crf, vmaf_score))) => {
+                info!(
+                    "{}",
+                    format!(
+                        "Found CRF {} for VMAF {} (samples VMAF {:.2})!",
+                        crf, vmaf, vmaf_score
+                    )
+                    .green()
+                );
+                break Ok((crf.to_string(), vmaf, None));
+            }
+            Ok(None) if vmaf > 0.0 => {
+                vmaf = lower_vmaf_target(vmaf, args.vmaf_retry_step);
+                warn!("{}", format!("Retrying with VMAF of {}...", vmaf).yellow());
+                *WEB_CRF_SEARCH_STATIC.lock().unwrap() =
+                    Some(CrfSearchProgress::new(vmaf, requested_vmaf));
+            }
+            Ok(None) => {
+                error!(
+                    "{}",
+                    format!("Failed to find a suitable CRF for {}", file).red()
+                );
+                break Err(format!("No CRF up to {} reached the VMAF target", max_crf));
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    sample_bar.finish_and_clear();
+    clear_crf_search_progress();
+    result
+}
@@ -0,0 +1,127 @@
+// The -i paths and the params_* options, split into the arguments ffmpeg gets
+
+use crate::*;
+
+// cmd.exe passes -i "A:\folder\" as A:\folder" followed by the rest of the command line, the
+// backslash escapes the closing quote, so a path is cut at its first double quote
+pub fn strip_input_quote(s: &str) -> (&str, Option<&str>) {
+    match s.find('"') {
+        Some(quote) => (&s[..quote], Some(&s[quote + 1..])),
+        None => (s, None),
+    }
+}
+
+pub fn input_validation(s: &str) -> Result<String, String> {
+    let (s, cut) = strip_input_quote(s);
+    if let Some(cut) = cut.filter(|cut| !cut.trim().is_empty()) {
+        warn!(
+            "Input path {} ended with \\\", ignored what followed it: {}",
+            s, cut
+        );
+    }
+    let p = Path::new(s);
+
+    if p.is_dir() {
+        return Ok(String::from_str(s).unwrap());
+    }
+
+    if !p.exists() {
+        return Err(String::from_str("input path not found").unwrap());
+    }
+
+    match p.extension().and_then(|ext| ext.to_str()).unwrap_or("") {
+        "mp4" | "mkv" | "avi" => Ok(s.to_string()),
+        _ => Err(String::from_str("valid input formats: mp4/mkv/avi").unwrap()),
+    }
+}
+
+// Split a params string into ffmpeg arguments like a shell does: whitespace separates arguments,
+// single quotes keep everything literal, double quotes keep whitespace and a backslash escapes a
+// quote, backslash or whitespace (other backslashes stay, for windows paths)
+// -x265-params "limit-sao:bframes=8" -tune grain -> [-x265-params, limit-sao:bframes=8, -tune, grain]
+pub fn split_params(params: &str) -> Result<Vec<String>, String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = params.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unterminated ' in {}", params)),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.peek(), Some('"') | Some('\\')) => {
+                            word.push(chars.next().unwrap())
+                        }
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unterminated \" in {}", params)),
+                    }
+                }
+            }
+            '\\' if matches!(chars.peek(), Some(next) if next.is_whitespace() || *next == '"' || *next == '\'' || *next == '\\') =>
+            {
+                in_word = true;
+                word.push(chars.next().unwrap());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+// A params string that split_params turns into words again, words with whitespace, quotes or
+// backslashes are double quoted
+pub fn join_params(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| {
+            if word.is_empty() || word.contains(|c: char| c.is_whitespace() || "'\"\\".contains(c))
+            {
+                format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                word.clone()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// The params_* options have to split into arguments, see split_params
+pub(crate) fn params_input_validation(s: &str) -> Result<String, String> {
+    split_params(s).map(|_| s.to_string())
+}
+
+// Arguments of a params string that passed params_input_validation
+pub fn params_args(params: &str) -> Vec<String> {
+    split_params(params).unwrap_or_else(|e| {
+        warn!("Ignoring params: {}", e);
+        Vec::new()
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,118 @@
+use super::*;
+
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+#[test]
+fn split_params_keeps_quoted_arguments_whole() {
+    let cases = [
+        (
+            r#"-x265-params "limit-sao:bframes=8" -tune grain"#,
+            words(&["-x265-params", "limit-sao:bframes=8", "-tune", "grain"]),
+        ),
+        (
+            r#"-metadata title="A Movie" -tune grain"#,
+            words(&["-metadata", "title=A Movie", "-tune", "grain"]),
+        ),
+        (
+            "-vf 'scale=1280:-2, format=yuv420p'",
+            words(&["-vf", "scale=1280:-2, format=yuv420p"]),
+        ),
+        // single quotes keep backslashes and double quotes
+        (r#"-x '\"a\"'"#, words(&["-x", r#"\"a\""#])),
+        // escaped quotes, backslashes and whitespace
+        (
+            r#"-metadata "title=\"quoted\" \\ end""#,
+            words(&["-metadata", r#"title="quoted" \ end"#]),
+        ),
+        (r"a\ b c", words(&["a b", "c"])),
+        // the backslashes of windows paths stay
+        (
+            r"-attach C:\fonts\a.ttf",
+            words(&["-attach", r"C:\fonts\a.ttf"]),
+        ),
+        // an empty quoted argument is an argument
+        (r#"-metadata "" -an"#, words(&["-metadata", "", "-an"])),
+        ("  -preset\tslow \n ", words(&["-preset", "slow"])),
+        ("", words(&[])),
+    ];
+    for (params, expected) in cases {
+        assert_eq!(split_params(params), Ok(expected), "{}", params);
+    }
+}
+
+#[test]
+fn split_params_refuses_unterminated_quotes() {
+    for params in [r#"-x265-params "limit-sao"#, "-vf 'scale", r#"-x "a\""#] {
+        assert!(split_params(params).is_err(), "{}", params);
+        assert!(params_input_validation(params).is_err(), "{}", params);
+        assert_eq!(params_args(params), Vec::<String>::new(), "{}", params);
+    }
+}
+
+#[test]
+fn join_params_splits_back_into_the_same_words() {
+    let cases = [
+        words(&["-x265-params", "limit-sao:bframes=8", "-tune", "grain"]),
+        words(&["-metadata", "title=A Movie"]),
+        words(&["-x", r#"say "hi""#, r"C:\fonts\", "it's", ""]),
+    ];
+    for words in cases {
+        let joined = join_params(&words);
+        assert_eq!(split_params(&joined), Ok(words), "{}", joined);
+    }
+    assert_eq!(
+        join_params(&words(&["-tune", "grain", "title=A Movie"])),
+        r#"-tune grain "title=A Movie""#
+    );
+}
+
+#[test]
+fn strip_input_quote_cuts_the_path_at_its_first_double_quote() {
+    let cases = [
+        (r"D:\Movies", (r"D:\Movies", None)),
+        // cmd.exe turns -i "D:\Movies\" into D:\Movies"
+        (r#"D:\Movies""#, (r"D:\Movies", Some(""))),
+        (
+            r#"D:\Movies" -o D:\out"#,
+            (r"D:\Movies", Some(r" -o D:\out")),
+        ),
+        (r#"a"b"c"#, ("a", Some(r#"b"c"#))),
+        ("/media/it's.mkv", ("/media/it's.mkv", None)),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(strip_input_quote(input), expected, "{}", input);
+    }
+}
+
+#[test]
+fn input_validation_of_a_folder_with_a_trailing_quote() {
+    let folder = temp_path("input");
+    fs::create_dir_all(&folder).unwrap();
+    let folder = folder.to_str().unwrap().to_string();
+
+    assert_eq!(
+        input_validation(&format!("{}\"", folder)),
+        Ok(folder.clone())
+    );
+    assert_eq!(
+        input_validation(&format!("{}\" -o elsewhere", folder)),
+        Ok(folder)
+    );
+}
+
+#[test]
+fn input_validation_of_files() {
+    let folder = temp_path("input-files");
+    fs::create_dir_all(&folder).unwrap();
+    let movie = folder.join("movie.mkv");
+    let subtitles = folder.join("movie.srt");
+    fs::write(&movie, b"video").unwrap();
+    fs::write(&subtitles, b"text").unwrap();
+
+    let movie = movie.to_str().unwrap().to_string();
+    assert_eq!(input_validation(&movie), Ok(movie.clone()));
+    assert!(input_validation(subtitles.to_str().unwrap()).is_err());
+    assert!(input_validation(folder.join("missing.mkv").to_str().unwrap()).is_err());
+}
@@ -0,0 +1,100 @@
+// Outputs are written as <output>.part next to the output and renamed once ffmpeg succeeded and the
+// checks passed, so a media server watching the output folder never indexes a half written file
+// and a crash leaves no file that looks finished. The rename stays on one filesystem
+
+use crate::*;
+
+const PART_SUFFIX: &str = ".part";
+
+pub(crate) struct PartFile {
+    output: String,
+    part: String,
+    // the file encoded to output
+    source: String,
+}
+
+impl PartFile {
+    // The .part of output, one left by a crashed run is removed
+    pub(crate) fn new(output: &str, source: &str) -> PartFile {
+        let part = part_path(output);
+        remove_stale_part(&part);
+        PartFile {
+            output: output.to_string(),
+            part,
+            source: source.to_string(),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.part
+    }
+
+    // -f of the output, ffmpeg can't pick the muxer from the .part extension
+    pub(crate) fn format_args(&self) -> Vec<String> {
+        let extension = Path::new(&self.output)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let muxer = match extension.as_str() {
+            "mp4" | "m4v" => "mp4",
+            "mov" => "mov",
+            "avi" | "divx" => "avi",
+            "webm" => "webm",
+            "ts" => "mpegts",
+            "flv" => "flv",
+            "ogv" => "ogg",
+            "wmv" => "asf",
+            _ => "matroska",
+        };
+        vec!["-f".to_string(), muxer.to_string()]
+    }
+
+    // Rename the .part to the output, it has to hold something and its source must not have
+    // changed while it was encoded
+    pub(crate) fn commit(&self) -> Result<(), String> {
+        if let Err(e) = check_source_unchanged(&self.source) {
+            self.discard();
+            return Err(e);
+        }
+        match fs::metadata(&self.part) {
+            Ok(metadata) if metadata.len() > 0 => {}
+            Ok(_) => {
+                self.discard();
+                return Err(format!("{} is empty", self.part));
+            }
+            Err(e) => return Err(format!("{}: {}", self.part, e)),
+        }
+        fs::rename(&self.part, &self.output)
+            .map_err(|e| format!("Failed to rename {} to {}: {}", self.part, self.output, e))
+    }
+
+    // Remove the .part of a failed or stopped encode
+    pub(crate) fn discard(&self) {
+        if Path::new(&self.part).exists() {
+            if let Err(e) = fs::remove_file(&self.part) {
+                warn!("Failed to remove {}: {}", self.part, e);
+            }
+        }
+    }
+}
+
+pub(crate) fn part_path(output: &str) -> String {
+    format!("{}{}", output, PART_SUFFIX)
+}
+
+// The output a .part is written for, None for other files
+pub(crate) fn part_output(path: &str) -> Option<&str> {
+    path.strip_suffix(PART_SUFFIX)
+}
+
+pub(crate) fn remove_stale_part(part: &str) {
+    if Path::new(part).exists() {
+        warn!("Removing {}, left by a run that did not finish it", part);
+        if let Err(e) = fs::remove_file(part) {
+            warn!("Failed to remove {}: {}", part, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
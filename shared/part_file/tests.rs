@@ -0,0 +1,164 @@
+use super::*;
+
+// An output in a new folder of the temp dir
+fn output_in(name: &str, filename: &str) -> String {
+    let dir = temp_path(name);
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(filename).to_string_lossy().to_string()
+}
+
+#[test]
+fn a_stale_part_is_removed_and_the_output_left_alone() {
+    let output = output_in("part-stale", "movie.libx265.vmaf95.crf24.mkv");
+    fs::write(part_path(&output), b"half written").unwrap();
+    fs::write(&output, b"finished").unwrap();
+
+    let part = PartFile::new(&output, "movie.mkv");
+    assert_eq!(part.path(), format!("{}.part", output));
+    assert!(!Path::new(part.path()).exists());
+    assert_eq!(fs::read(&output).unwrap(), b"finished");
+}
+
+#[test]
+fn the_muxer_is_the_one_of_the_output_extension() {
+    for (filename, muxer) in [
+        ("movie.mkv", "matroska"),
+        ("movie.MP4", "mp4"),
+        ("movie.m4v", "mp4"),
+        ("movie.mov", "mov"),
+        ("movie.divx", "avi"),
+        ("movie.webm", "webm"),
+        ("capture.ts", "mpegts"),
+        ("movie.ogv", "ogg"),
+        ("movie.wmv", "asf"),
+        ("movie", "matroska"),
+    ] {
+        let part = PartFile::new(&format!("/out/{}", filename), "movie.mkv");
+        assert_eq!(part.format_args(), ["-f", muxer], "{}", filename);
+    }
+}
+
+#[test]
+fn commit_renames_the_part_to_the_output() {
+    let output = output_in("part-commit", "movie.mkv");
+    let part = PartFile::new(&output, "movie.mkv");
+    fs::write(part.path(), b"encoded").unwrap();
+    part.commit().unwrap();
+    assert_eq!(fs::read(&output).unwrap(), b"encoded");
+    assert!(!Path::new(part.path()).exists());
+}
+
+#[test]
+fn an_empty_or_missing_part_is_not_committed() {
+    let output = output_in("part-empty", "movie.mkv");
+    let part = PartFile::new(&output, "movie.mkv");
+    fs::write(part.path(), b"").unwrap();
+    assert_eq!(part.commit(), Err(format!("{} is empty", part.path())));
+    assert!(!Path::new(part.path()).exists());
+    assert!(!Path::new(&output).exists());
+
+    let e = part.commit().unwrap_err();
+    assert!(e.starts_with(part.path()), "{}", e);
+    assert!(!Path::new(&output).exists());
+}
+
+#[test]
+fn discard_removes_the_part() {
+    let output = output_in("part-discard", "movie.mkv");
+    let part = PartFile::new(&output, "movie.mkv");
+    fs::write(part.path(), b"half written").unwrap();
+    part.discard();
+    assert!(!Path::new(part.path()).exists());
+    // nothing to remove
+    part.discard();
+}
+
+#[test]
+fn a_part_is_never_taken_as_an_existing_output() {
+    let output_folder = temp_path("part-existing");
+    fs::create_dir_all(&output_folder).unwrap();
+    let part = output_folder.join("movie.libx265.vmaf95.crf24.mkv.part");
+    let other = output_folder.join("other.libx265.vmaf95.crf24.mkv.part");
+    fs::write(&part, b"half written").unwrap();
+    fs::write(&other, b"half written").unwrap();
+
+    let found = find_existing_output(
+        "/media/movie.mkv",
+        "libx265",
+        95.0,
+        &output_folder.to_string_lossy(),
+    );
+    assert_eq!(found, None);
+    // the .part of the file is stale, the one of another file may still be written
+    assert!(!part.exists());
+    assert!(other.exists());
+}
+
+// The stub ffmpeg of an encode: writes to its output, the last argument, then fails or succeeds
+#[cfg(unix)]
+fn stub_ffmpeg(exit: u8) -> String {
+    format!(
+        r#"for last; do :; done
+echo "half written" > "$last"
+echo "frame=   12 fps=24.0 total_size=13" >&2
+[ {exit} = 0 ] || echo "Conversion failed!" >&2
+exit {exit}
+"#
+    )
+}
+
+// Run the audio remux of a source of 48 frames to output with the stub ffmpeg exiting with exit
+#[cfg(unix)]
+fn remux(name: &str, output: &str, exit: u8) -> Result<(), String> {
+    shared_db();
+    let source = probed_file(
+        name,
+        serde_json::json!({
+            "streams": [{
+                "index": 0, "codec_type": "video", "codec_name": "h264",
+                "width": 1920, "height": 1080, "tags": {"NUMBER_OF_FRAMES": "48"},
+            }],
+            "format": {"duration": "2.0"},
+        }),
+    );
+    let bar = ProgressBar::hidden();
+    with_stub("FFMPEG_BIN", &stub_ffmpeg(exit), || {
+        run_ffmpeg_transcode_audio(
+            &source,
+            output,
+            &bar,
+            &bar,
+            &bar,
+            &bar,
+            &bar,
+            &1,
+            &0,
+            &vec![(source.clone(), 48)],
+            "aac",
+            "copy",
+            &[],
+            &StreamSelection::default(),
+            &vec![],
+            "part-test",
+        )
+    })
+}
+
+#[cfg(unix)]
+#[test]
+fn a_failed_encode_leaves_neither_the_output_nor_its_part() {
+    let output = output_in("part-failed", "movie.mkv");
+    let e = remux("part-failed.mkv", &output, 1).unwrap_err();
+    assert!(e.contains("Conversion failed!"), "{}", e);
+    assert!(!Path::new(&output).exists());
+    assert!(!Path::new(&part_path(&output)).exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn a_successful_encode_is_renamed_to_the_output() {
+    let output = output_in("part-success", "movie.mkv");
+    remux("part-success.mkv", &output, 0).unwrap();
+    assert_eq!(fs::read_to_string(&output).unwrap(), "half written\n");
+    assert!(!Path::new(&part_path(&output)).exists());
+}
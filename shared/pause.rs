@@ -0,0 +1,158 @@
+// Pause and resume of the running encode of a task, from POST /pause/<task_id> and
+// /resume/<task_id> or p in the cli. On unix the ffmpeg and ab-av1 processes of the task are
+// stopped with SIGSTOP and continued with SIGCONT, their output readers just wait for the next line.
+// Elsewhere the current file is finished and the next one waits.
+
+use crate::*;
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+lazy_static! {
+    // The ids of the paused tasks
+    static ref PAUSED_TASKS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+thread_local! {
+    // The task this thread works for, the children it starts belong to that task
+    static CURRENT_TASK: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+// Set by the task thread when the task starts running and by the threads of its chunked encode
+pub(crate) fn set_current_task(task_id: &str) {
+    CURRENT_TASK.with(|current| *current.borrow_mut() = task_id.to_string());
+}
+
+pub(crate) fn current_task() -> String {
+    CURRENT_TASK.with(|current| current.borrow().clone())
+}
+
+// What a pause stops right away: process on unix, file elsewhere
+pub fn pause_granularity() -> &'static str {
+    if cfg!(unix) {
+        "process"
+    } else {
+        "file"
+    }
+}
+
+pub fn is_task_paused(task_id: &str) -> bool {
+    PAUSED_TASKS.lock().unwrap().contains(task_id)
+}
+
+// Whether the task of this thread is paused
+pub fn is_paused() -> bool {
+    is_task_paused(&current_task())
+}
+
+// pid and the processes it started, ab-av1 runs its own ffmpeg
+#[cfg(unix)]
+fn process_tree(pid: u32) -> Vec<u32> {
+    let mut pids = vec![pid];
+    let mut i = 0;
+    while i < pids.len() {
+        if let Ok(output) = Command::new("pgrep")
+            .arg("-P")
+            .arg(pids[i].to_string())
+            .output()
+        {
+            pids.extend(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .filter_map(|line| line.trim().parse::<u32>().ok()),
+            );
+        }
+        i += 1;
+    }
+    pids
+}
+
+// Send signal (STOP or CONT) to pid and its children
+#[cfg(unix)]
+pub(crate) fn signal_process_tree(pid: u32, signal: &str) {
+    // the children are stopped first, so a stopped parent doesn't start new ones
+    for pid in process_tree(pid).into_iter().rev() {
+        let result = Command::new("kill")
+            .arg(format!("-{}", signal))
+            .arg(pid.to_string())
+            .output();
+        if let Err(e) = result {
+            warn!("Failed to send SIG{} to process {}: {}", signal, pid, e);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn signal_process_tree(_pid: u32, _signal: &str) {}
+
+// Returns false when the encode of task_id was already paused
+pub fn pause_encode(task_id: &str) -> bool {
+    if !PAUSED_TASKS.lock().unwrap().insert(task_id.to_string()) {
+        return false;
+    }
+    for pid in running_child_pids(task_id) {
+        signal_process_tree(pid, "STOP");
+    }
+    if cfg!(unix) {
+        info!("Paused task {}", task_id);
+    } else {
+        info!("Pausing task {} after the current file", task_id);
+    }
+    true
+}
+
+// Returns false when the encode of task_id was not paused
+pub fn resume_encode(task_id: &str) -> bool {
+    if !PAUSED_TASKS.lock().unwrap().remove(task_id) {
+        return false;
+    }
+    for pid in running_child_pids(task_id) {
+        signal_process_tree(pid, "CONT");
+    }
+    info!("Resumed task {}", task_id);
+    true
+}
+
+// Resume every paused task, before ctrl-C stops or finishes them
+pub fn resume_all_encodes() {
+    let paused: Vec<String> = PAUSED_TASKS.lock().unwrap().iter().cloned().collect();
+    for task_id in paused {
+        resume_encode(&task_id);
+    }
+}
+
+// A task that is no longer running is not paused, when it runs again it starts unpaused
+pub(crate) fn clear_pause(task_id: &str) {
+    PAUSED_TASKS.lock().unwrap().remove(task_id);
+}
+
+// Hold before the next file while paused, ctrl-C ends the wait
+pub fn wait_while_paused() {
+    while is_paused() && !shutdown_requested() {
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+// The cli: p and Enter pauses the encode of task_id, and resumes it again
+pub fn listen_for_pause_key(task_id: &str) {
+    if !io::stdin().is_terminal() {
+        return;
+    }
+    info!("Type p and Enter to pause or resume the encode");
+    let task_id = task_id.to_string();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) if line.trim() == "p" => {
+                    if !pause_encode(&task_id) {
+                        resume_encode(&task_id);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,49 @@
+use super::*;
+
+// The state letter of /proc/<pid>/stat, T is stopped
+#[cfg(target_os = "linux")]
+fn process_state(pid: u32) -> char {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap();
+    let after_name = &stat[stat.rfind(')').unwrap() + 2..];
+    after_name.chars().next().unwrap()
+}
+
+// A sleep started for task_id on this thread
+#[cfg(target_os = "linux")]
+fn task_child(task_id: &str) -> (Child, TrackedChild) {
+    set_current_task(task_id);
+    let child = Command::new("sleep").arg("30").spawn().unwrap();
+    let tracked = track_child(&child, &[]);
+    (child, tracked)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn a_pause_stops_only_the_children_of_its_task() {
+    let (mut paused, tracked_paused) = task_child("pause-test-a");
+    let (mut running, tracked_running) = task_child("pause-test-b");
+
+    assert!(pause_encode("pause-test-a"));
+    assert!(!pause_encode("pause-test-a"));
+    assert!(is_task_paused("pause-test-a"));
+    assert!(!is_task_paused("pause-test-b"));
+    // this thread works for task b
+    assert!(!is_paused());
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(process_state(paused.id()), 'T');
+    assert_ne!(process_state(running.id()), 'T');
+
+    assert!(resume_encode("pause-test-a"));
+    assert!(!resume_encode("pause-test-a"));
+    thread::sleep(Duration::from_millis(200));
+    assert_ne!(process_state(paused.id()), 'T');
+
+    for (child, tracked) in [
+        (&mut paused, tracked_paused),
+        (&mut running, tracked_running),
+    ] {
+        child.kill().unwrap();
+        child.wait().unwrap();
+        drop(tracked);
+    }
+}
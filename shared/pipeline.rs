@@ -0,0 +1,485 @@
+// The processing shared by the cli and the web server: resolve the encoder settings, scan the
+// input into the database and encode the pending files one by one. The scan is in pipeline_scan.rs,
+// the encoder and its fallback in pipeline_encoder.rs, --watch in pipeline_watch.rs, the summary in
+// pipeline_report.rs and the encode of a file in pipeline_file.rs
+
+use crate::*;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn};
+use std::time::{Duration, Instant, SystemTime};
+
+// A file that could not be encoded, listed at the end of the run and written to --failures-json
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedFile {
+    pub path: String,
+    // crf_search, target_size, transcode, audio_transcode, scene_detection, chunked_encode, mode,
+    // output_folder or health
+    pub stage: String,
+    pub stderr_tail: String,
+}
+
+impl FailedFile {
+    pub(crate) fn new(path: &str, stage: &str, stderr_tail: impl ToString) -> FailedFile {
+        FailedFile {
+            path: path.to_string(),
+            stage: stage.to_string(),
+            stderr_tail: stderr_tail.to_string(),
+        }
+    }
+}
+
+// The bars the encode functions draw on
+pub struct PipelineBars {
+    pub file_bar: ProgressBar,
+    pub total_bar: ProgressBar,
+    pub transcode_bar: ProgressBar,
+    pub info_bar: ProgressBar,
+    pub codec_bar: ProgressBar,
+    pub vmaf_bar: ProgressBar,
+}
+
+impl PipelineBars {
+    pub fn new(total_frames: u64) -> PipelineBars {
+        // setup progress bar and show count for each file being processed
+        let total_style = ProgressStyle::default_bar().template(
+            "[frames][{elapsed_precise}] [{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
+        );
+        let transcode_style = ProgressStyle::default_bar().template(
+            "[ffmpeg][{elapsed_precise}] [{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
+        );
+        let info_style = ProgressStyle::default_bar().template("[info][{msg}]");
+        let codec_style = ProgressStyle::default_bar().template("[codec][{msg}]");
+        let file_bar_style = ProgressStyle::default_bar().template("[file]{msg}");
+        // the ab-av1 CRF search, 0 to 100
+        let vmaf_bar_style = ProgressStyle::default_bar()
+            .template("[vmaf][{elapsed_precise}] [{wide_bar:.yellow/white}] {percent:3} [{msg}]");
+        let m = LOG_PROGRESS.clone();
+        let file_bar = m.add(ProgressBar::new(0));
+        file_bar.set_style(file_bar_style.unwrap());
+        let total_bar = m.add(ProgressBar::new(total_frames));
+        total_bar.set_style(total_style.unwrap());
+        let transcode_bar = m.add(ProgressBar::new(0));
+        transcode_bar.set_style(transcode_style.unwrap());
+        let info_bar = m.add(ProgressBar::new(0));
+        info_bar.set_style(info_style.unwrap());
+        let codec_bar = m.add(ProgressBar::new(0));
+        codec_bar.set_style(codec_style.unwrap());
+        let vmaf_bar = m.add(ProgressBar::new(0));
+        vmaf_bar.set_style(vmaf_bar_style.unwrap());
+        PipelineBars {
+            file_bar,
+            total_bar,
+            transcode_bar,
+            info_bar,
+            codec_bar,
+            vmaf_bar,
+        }
+    }
+
+    // Chunked mode on a single file draws its own bars
+    pub fn hidden() -> PipelineBars {
+        PipelineBars {
+            file_bar: ProgressBar::hidden(),
+            total_bar: ProgressBar::hidden(),
+            transcode_bar: ProgressBar::hidden(),
+            info_bar: ProgressBar::hidden(),
+            codec_bar: ProgressBar::hidden(),
+            vmaf_bar: ProgressBar::hidden(),
+        }
+    }
+}
+
+// A file of the run with its place in it, as shown in the progress
+pub struct FileJob {
+    pub file: String,
+    pub current_file_count: u64,
+    pub total_files: i32,
+}
+
+// Where a Pipeline reports its progress, the cli only draws the terminal bars while the web
+// server also publishes the current file for /progress
+pub trait ProgressSink {
+    // Called once the frames of all files are counted
+    fn bars(&mut self, total_frames: u64) -> PipelineBars {
+        PipelineBars::new(total_frames)
+    }
+
+    // Called before each file is processed
+    fn file_started(&mut self, _job: &FileJob) {}
+}
+
+pub struct Pipeline {
+    pub(crate) args: Args,
+    // the encoder as passed on the command line (av1, not libaom-av1)
+    pub(crate) encoder: String,
+    pub(crate) preset: String,
+    pub(crate) started: Instant,
+    // RFC 3339, the encodes of the run are the stats finished since
+    pub(crate) started_at: String,
+    // an input folder or list, a single input file otherwise
+    pub(crate) scanned: bool,
+    // the input folder, run() points args.inputpath at the file being processed
+    pub(crate) input_folder: Option<String>,
+    // the absolute -i folders of --mirror-structure, see scan_roots
+    pub(crate) scan_roots: Vec<String>,
+    pub(crate) scan_bar: Option<ProgressBar>,
+    pub(crate) files: Vec<String>,
+    pub(crate) count: i32,
+    pub(crate) current_file_count: u64,
+    pub(crate) db_count: u64,
+    pub(crate) db_count_added: u64,
+    pub(crate) frame_counts: Vec<(String, u64)>,
+    pub(crate) failures: Vec<FailedFile>,
+    // the encoded --renditions, for the summary at the end
+    pub(crate) renditions: Mutex<Vec<RenditionOutput>>,
+    // the encodes larger than --max-output-ratio of their source, for the summary at the end
+    pub(crate) size_checks: Mutex<Vec<SizeCheck>>,
+    // the files --tonemap sdr converted and their filter chain, for --report-json
+    pub(crate) tonemapped: Mutex<Vec<TonemappedFile>>,
+    // the heavily compressed files encoded at a lower VMAF target, for the summary at the end
+    pub(crate) adapted_targets: Mutex<Vec<AdaptedTarget>>,
+    // the outputs --ingest-external-subs merged subtitle files into, for --report-json
+    pub(crate) merged_subtitles: Mutex<Vec<MergedSubtitles>>,
+    // the files --stage-dir copied before their encode, for the summary at the end
+    pub(crate) staged: Vec<StagedFile>,
+    // the files put back to the end of the batch by --settle-seconds or a changed source
+    pub(crate) source_checks: Vec<SourceCheck>,
+    // the fps and bitrate curves of the encodes, for --report-json
+    pub(crate) encode_samples: Mutex<Vec<EncodeSamples>>,
+    // the encodes of --trial-range, for the summary at the end
+    pub(crate) trials: Mutex<Vec<TrialOutput>>,
+    // the A/V sync checks of the chunked encodes, for --report-json and the summary at the end
+    pub(crate) sync_checks: Mutex<Vec<SyncCheck>>,
+    // the encodes of files with an --estimate, for the summary at the end
+    pub(crate) estimate_checks: Vec<EstimateCheck>,
+    // --fallback-encoder of a hardware encoder, None once the run switched to it
+    pub(crate) fallback: Option<EncoderSettings>,
+    // files whose hardware encoder failed to start
+    pub(crate) hardware_failures: u32,
+    // the rest of the run uses the fallback encoder
+    pub(crate) on_fallback: bool,
+    // the files encoded with the fallback encoder and its ffmpeg name, for the summary at the end
+    pub(crate) fallback_files: Vec<(String, String)>,
+    // the files of the run, for the --notify-webhook summary
+    pub(crate) processed: u64,
+    pub(crate) webhook: Option<Webhook>,
+    // the locks on the inputs, released when the pipeline is dropped
+    pub(crate) run_lock: Option<RunLock>,
+    // the journal of a batch, new or resumed, see session.rs
+    pub(crate) session: Option<BatchSession>,
+}
+
+// The options that don't work with the mode or the resolved encoder of args
+pub(crate) fn check_encode_options(args: &Args) -> Result<(), String> {
+    // the chunks are encoded with a CRF each, there is no bitrate to split between them
+    if args.target_size.is_some() && args.mode != "default" {
+        return Err("--target-size only works with --mode default".to_string());
+    }
+    if args.renditions.is_some() && args.mode != "default" {
+        return Err("--renditions only works with --mode default".to_string());
+    }
+    // the grain synthesis is an option of the av1 software encoders
+    if args.film_grain.is_some() && !supports_film_grain(&args.encoder) {
+        return Err(format!(
+            "--film-grain only works with libsvtav1 and av1 (libaom-av1), not {}",
+            args.encoder
+        ));
+    }
+    if args.film_grain_denoise.is_some() && args.film_grain.is_none() {
+        return Err("--film-grain-denoise needs --film-grain".to_string());
+    }
+    Ok(())
+}
+
+impl Pipeline {
+    // Resolve the encoder, its preset and params, and check that ffmpeg supports them
+    pub fn new(mut args: Args) -> Result<Pipeline, String> {
+        let started = Instant::now();
+        // the probes of --encoder auto run on the GPU and with the environment of the run too
+        set_tool_env(&args);
+        apply_gpu_index(&mut args);
+
+        // --encoder auto picks the best hardware encoder that works on this host
+        if args.encoder == "auto" {
+            args.encoder = auto_encoder();
+            info!("Encoder auto: using {}", args.encoder);
+        }
+        resolve_output_folder(&mut args);
+        resolve_thread_counts(&mut args)?;
+        set_progress_interval(args.progress_interval);
+        let settings = resolve_encoder(&args, &args.encoder)?;
+        // the fallback takes the params of the command line, before params_x265 is replaced below
+        let fallback = resolve_fallback_encoder(&args, &settings);
+        args.encoder = settings.codec.clone();
+        args.params_x265 = settings.params.clone();
+
+        // fail before scanning when ffmpeg lacks the encoder or filters this run needs
+        check_ffmpeg_capabilities(&args)?;
+        check_encode_options(&args)?;
+        if args.burn_subs.is_some() && args.mode == "chunked" {
+            warn!("--burn-subs only applies to the default mode, the chunked encode keeps all subtitles");
+        }
+        if args.burn_subs.is_some() && args.mode == "audio-only" {
+            warn!("--burn-subs needs the video encode, --mode audio-only copies the video");
+        }
+
+        // without ab-av1 the default mode searches the CRF itself
+        if args.crf_search == "ab-av1" && args.mode == "default" && !ab_av1_available() {
+            warn!(
+                "{} not found, using the native CRF search (--crf-search native)",
+                ab_av1_bin()
+            );
+            args.crf_search = "native".to_string();
+        }
+
+        let webhook = args
+            .notify_webhook
+            .as_deref()
+            .filter(|url| !url.is_empty())
+            .map(|url| Webhook::start(url, &args.notify_on, &args.task_id));
+
+        Ok(Pipeline {
+            args,
+            encoder: settings.encoder,
+            preset: settings.preset,
+            fallback,
+            hardware_failures: 0,
+            on_fallback: false,
+            fallback_files: Vec::new(),
+            started,
+            started_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            scanned: false,
+            input_folder: None,
+            scan_roots: Vec::new(),
+            scan_bar: None,
+            files: Vec::new(),
+            count: 0,
+            current_file_count: 0,
+            db_count: 0,
+            db_count_added: 0,
+            frame_counts: Vec::new(),
+            failures: Vec::new(),
+            renditions: Mutex::new(Vec::new()),
+            size_checks: Mutex::new(Vec::new()),
+            tonemapped: Mutex::new(Vec::new()),
+            adapted_targets: Mutex::new(Vec::new()),
+            merged_subtitles: Mutex::new(Vec::new()),
+            staged: Vec::new(),
+            source_checks: Vec::new(),
+            encode_samples: Mutex::new(Vec::new()),
+            trials: Mutex::new(Vec::new()),
+            sync_checks: Mutex::new(Vec::new()),
+            estimate_checks: Vec::new(),
+            processed: 0,
+            webhook,
+            run_lock: None,
+            session: None,
+        })
+    }
+
+    // Let the caller pick from the scanned files, a single input file is always processed
+    pub fn select_files(&mut self, select: impl FnOnce(Vec<String>) -> Vec<String>) {
+        if self.scanned {
+            self.files = select(std::mem::take(&mut self.files));
+            self.count = self.files.len() as i32;
+        }
+    }
+
+    // Encode the files collected by prepare()
+    pub fn run(&mut self, sink: &mut dyn ProgressSink) -> Result<(), String> {
+        set_task_state(&self.args.task_id, "running");
+        // probes of an earlier run in the same process are not reused
+        clear_media_info_cache();
+        if self.scanned {
+            if let Some(files_bar) = self.scan_bar.take() {
+                files_bar.finish_and_clear();
+            }
+            // a resumed batch was not scanned
+            if self.session.is_none() {
+                info!(
+                    "Added {} files to the database ({} already present)",
+                    self.db_count_added, self.db_count
+                );
+            }
+
+            // print how many files are to be processed
+            info!("Processing {} files...", self.count);
+
+            let counts: Vec<(String, u64)> = match &self.session {
+                // a resumed batch keeps the frame counts of its journal
+                Some(session) => self
+                    .files
+                    .iter()
+                    .map(|file| (file.clone(), session.frames(file)))
+                    .collect(),
+                None => {
+                    // count the frames of every file once, --probe-jobs files at a time
+                    let frame_count_progress_bar = progress_bar(self.files.len() as u64);
+                    let frame_count_progress_bar_style = ProgressStyle::default_bar().template(
+                        "[frmcnt][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
+                    );
+                    frame_count_progress_bar.set_style(frame_count_progress_bar_style.unwrap());
+                    let counts =
+                        count_frames(&self.files, self.args.probe_jobs, &frame_count_progress_bar)
+                            .into_iter()
+                            .map(|(file, frames)| {
+                                let frames = trial_frame_count(&file, frames, &self.args);
+                                (file, frames)
+                            })
+                            .collect();
+                    frame_count_progress_bar.finish();
+                    counts
+                }
+            };
+            // the journal of a new batch, the run goes on without one when it can't be stored
+            if self.session.is_none() && !counts.is_empty() {
+                match BatchSession::start(&self.args, &counts) {
+                    Ok(session) => self.session = Some(session),
+                    Err(e) => warn!("Failed to store the session of the batch: {}", e),
+                }
+            }
+            let total_frames: u64 = match &self.session {
+                Some(session) => session.total_frames(),
+                None => counts.iter().map(|(_, frames)| frames).sum(),
+            };
+
+            // Print the total number of frames to be processed, within a total number of files
+            info!(
+                "Total number of frames to be processed: {} in {} files",
+                total_frames, self.count
+            );
+
+            // the files to process with their frame count, after an entry of 0
+            self.frame_counts = vec![("".to_string(), 0)];
+            self.frame_counts.extend(counts);
+
+            let bars = sink.bars(total_frames);
+            if let Some(session) = &self.session {
+                bars.total_bar.set_position(session.done_frames());
+            }
+
+            // --stage-dir copies the next file while the current one encodes
+            let mut stager = self.stager()?;
+            let files = self.files.clone();
+            let total_files = files.len() as i32;
+            // the files still being written, or changed during their encode, go last
+            let mut deferred: Vec<(FileJob, Deferral)> = Vec::new();
+            for (index, file) in files.iter().cloned().enumerate() {
+                // a paused encode holds here until it is resumed, ctrl-C stops before the next file
+                wait_while_paused();
+                if shutdown_requested() {
+                    break;
+                }
+                self.current_file_count = self.current_file_count + 1;
+
+                // print the current file being processed
+                info!(
+                    "Processing file {} of {}...",
+                    self.current_file_count, self.count
+                );
+
+                self.args.inputpath = vec![absolute_path(file.clone())];
+
+                if let Some(stager) = stager.as_mut() {
+                    let next = files.get(index + 1).map(String::as_str);
+                    self.staged.extend(stager.stage(&file, next));
+                }
+                let job = FileJob {
+                    file,
+                    current_file_count: self.current_file_count,
+                    total_files,
+                };
+                let failures = self.failures.len();
+                let deferral = self.process_or_defer(&job, &bars, sink, false);
+                self.update_session(&job.file, failures, deferral.is_some());
+                if let Some(deferral) = deferral {
+                    deferred.push((job, deferral));
+                }
+                if let Some(stager) = stager.as_mut() {
+                    stager.release();
+                }
+            }
+            // the copy of a file the run stopped before is removed with the staging folder
+            drop(stager);
+            // the deferred files are encoded from where they are
+            for (job, deferral) in deferred {
+                let failures = self.failures.len();
+                self.retry_deferred(&job, deferral, &bars, sink);
+                // a retry the stop skipped stays deferred
+                let skipped = self
+                    .source_checks
+                    .last()
+                    .is_some_and(|check| check.outcome == "not retried");
+                self.update_session(&job.file, failures, skipped);
+            }
+            if let Some(session) = &self.session {
+                session.finish(self.elapsed());
+            }
+            self.log_done(self.count);
+        } else if let Some(file) = self.files.first().cloned() {
+            // the frames bar only follows the final encode of the default and audio-only modes
+            let bars = if self.args.mode != "chunked" {
+                sink.bars(trial_frame_count(
+                    &file,
+                    frame_count(&file) as u64,
+                    &self.args,
+                ))
+            } else {
+                PipelineBars::hidden()
+            };
+
+            let mut stager = self.stager()?;
+            if let Some(stager) = stager.as_mut() {
+                self.staged.extend(stager.stage(&file, None));
+            }
+            let job = FileJob {
+                file,
+                current_file_count: 1,
+                total_files: 1,
+            };
+            let deferral = self.process_or_defer(&job, &bars, sink, false);
+            drop(stager);
+            // the end of a batch of one file is right away
+            if let Some(deferral) = deferral {
+                self.retry_deferred(&job, deferral, &bars, sink);
+            }
+            self.log_done(1);
+        }
+        Ok(())
+    }
+
+    // Store the state of file in the journal of the batch: failed when the failures grew past
+    // failures, deferred or done
+    fn update_session(&mut self, file: &str, failures: usize, deferred: bool) {
+        let state = if self.failures.len() > failures {
+            "failed"
+        } else if deferred {
+            "deferred"
+        } else {
+            "done"
+        };
+        let elapsed = self.elapsed();
+        if let Some(session) = self.session.as_mut() {
+            session.update(file, state, elapsed);
+        }
+    }
+
+    // The run time of the batch, with the earlier runs of a resumed session
+    pub(crate) fn elapsed(&self) -> Duration {
+        let before = self
+            .session
+            .as_ref()
+            .map_or(Duration::ZERO, |session| session.elapsed_before);
+        before + self.started.elapsed()
+    }
+
+    // The staging folder of --stage-dir
+    fn stager(&self) -> Result<Option<Stager>, String> {
+        self.args
+            .stage_dir
+            .as_deref()
+            .map(|stage_dir| Stager::new(stage_dir, self.args.min_free_space))
+            .transpose()
+    }
+}
@@ -0,0 +1,466 @@
+// The encode of a file in the default mode: the CRF search of each rendition and the encode, or the
+// remux of the audio when only the audio needs it
+
+use crate::*;
+
+use log::{error, info};
+
+impl Pipeline {
+    // --mode default: the CRF search and the encode of the file, once per rendition, or the remux
+    // of its audio when only the audio needs it
+    pub(crate) fn process_file_default(
+        &self,
+        settings: &FileSettings,
+        job: &FileJob,
+        bars: &PipelineBars,
+        trial: Option<TrialRange>,
+        short_input: Option<f32>,
+    ) -> Result<(), FailedFile> {
+        let file = job.file.clone();
+        let args = &settings.args;
+        let ab_av1_tune_args = &settings.ab_av1_tune_args;
+        let tonemap = &settings.tonemap;
+        let downscale = &settings.downscale;
+        let dynamic_hdr = &settings.dynamic_hdr;
+        let _vmaf = &args.vmaf;
+        let _params_x265 = &settings.params_x265;
+        let _pix_fmt = &args.pix_fmt;
+        let _max_crf = &args.max_crf;
+        let _sample_every = &args.sample_every;
+        let _vmaf_threads = &args.vmaf_threads;
+        let _verbose = &args.verbose;
+
+        info!("Running default {}...", args.crf_search);
+
+        // the video with a burned in subtitle needs the encode, see below
+        let burn_subtitle = args
+            .burn_subs
+            .as_ref()
+            .and_then(|burn_subs| burn_subtitle_for(&file, burn_subs));
+
+        // keep the audio and subtitle streams of --keep-audio-langs and --keep-subs-langs
+        let mut streams = select_streams(&file, args, burn_subtitle.as_ref());
+        if args.ingest_external_subs {
+            add_external_subtitles(&file, args, &mut streams);
+        }
+        if !streams.dropped.is_empty() {
+            info!("{}: dropping {}", file, streams.dropped.join(", "));
+        }
+
+        // copy aac/opus/mp3 audio, re-encode other codecs and streams above --max-audio-bitrate
+        let audio_streams = &streams.audio;
+        let audio_plans = plan_audio_streams(audio_streams, &AudioOptions::from_args(args));
+        // dropping streams needs a remux too
+        let audio_reencode =
+            audio_plans.iter().any(|plan| !plan.args.is_empty()) || !streams.dropped.is_empty();
+        let original_audio_codec: String = audio_plans
+            .last()
+            .map(|plan| plan.original_codec.clone())
+            .unwrap_or_default();
+        let video_details = get_video_details(&file).unwrap();
+        let mut vec_video_args: Vec<(usize, String, String, String)> = Vec::new();
+
+        let mut j = 0;
+        for (video_codec, video_width, video_height) in video_details {
+            let video_arg = format!("{}", video_codec.clone());
+            vec_video_args.push((j, video_arg.clone(), video_width, video_height));
+            j = j + 1;
+        }
+
+        let mut status = "pending_video";
+        let bitrate = get_bitrate(&file);
+        // Convert bitrate to f64
+        let bitrate = bitrate.parse::<f64>().unwrap();
+        let _transcode_info = "".to_string();
+
+        if bitrate < 3000.0 {
+            status = "skipped";
+        }
+        if bitrate > 3000.0 {
+            status = "pending_video";
+        }
+        if audio_reencode {
+            status = "pending_audio";
+        }
+        if bitrate > 3000.0 && audio_reencode {
+            status = "pending_all";
+        }
+
+        // burning in a subtitle, the renditions, tonemapping, a downscale and a trial need the
+        // video encode
+        if (burn_subtitle.is_some()
+            || args.renditions.is_some()
+            || tonemap.is_some()
+            || downscale.is_some()
+            || trial.is_some())
+            && status == "pending_audio"
+        {
+            status = "pending_all";
+        }
+
+        // Match status, if status is pending_video, transcode_info = "video", if status is pending_audio, transcode_info = "audio", if status is pending_all, transcode_info = "all"
+        let transcode_info = match status {
+            "pending_video" => "video",
+            "pending_audio" => "audio",
+            "pending_all" => "all",
+            _ => "",
+        };
+
+        // reuse the output of a previous run, unless --force-reencode is passed
+        // outputs of --target-size, --renditions, --max-resolution and trials have other names,
+        // they are always encoded again
+        let existing_output = if args.force_reencode
+            || args.target_size.is_some()
+            || args.renditions.is_some()
+            || downscale.is_some()
+            || trial.is_some()
+        {
+            None
+        } else {
+            find_existing_output(&file, &self.encoder, *_vmaf, &args.outputpath)
+        };
+
+        if status == "pending_audio".to_string() {
+            if let Err(e) =
+                check_file_free_space(&file, &args.outputpath, None, false, args.min_free_space)
+            {
+                skip_no_space(&file, &e);
+            } else {
+                // set_output_folder function
+                let final_output = set_output_folder_filename_audio(&file, &args.outputpath);
+
+                run_ffmpeg_transcode_audio(
+                    &file,
+                    &final_output,
+                    &bars.file_bar,
+                    &bars.transcode_bar,
+                    &bars.total_bar,
+                    &bars.info_bar,
+                    &bars.codec_bar,
+                    &job.total_files,
+                    &job.current_file_count,
+                    &self.frame_counts,
+                    &original_audio_codec,
+                    transcode_info,
+                    &audio_plans,
+                    &streams,
+                    &vec_video_args,
+                    &args.task_id,
+                )
+                .map_err(|tail| FailedFile::new(&file, "audio_transcode", tail))?;
+                self.record_merged_subtitles(&file, &final_output, &streams);
+                finish_output(&file, &final_output, args);
+            }
+        } else if let Some((existing, vmaf, crf)) = existing_output {
+            info!(
+                "{} already encoded (vmaf {}, crf {}): {}",
+                file, vmaf, crf, existing
+            );
+        } else if is_dovi(dynamic_hdr) && !args.strip_dovi {
+            skip_dovi(&file, dynamic_hdr);
+        } else {
+            // --renditions encodes the file once per rendition, otherwise once at its own size
+            let renditions: Vec<Option<Rendition>> = match &args.renditions {
+                Some(spec) => parse_renditions(spec)
+                    .map_err(|e| FailedFile::new(&file, "renditions", e))?
+                    .into_iter()
+                    .map(Some)
+                    .collect(),
+                None => vec![None],
+            };
+            let source_height = probe_file(&file)
+                .video_stream()
+                .and_then(|stream| stream["height"].as_u64())
+                .unwrap_or(0);
+            let deinterlace = should_deinterlace(&file, &args.deinterlace);
+            if deinterlace {
+                info!("{}: deinterlacing with yadif", file);
+            }
+            if let Some(anamorphic) = anamorphic(&file) {
+                info!(
+                    "{}: anamorphic {}, keeping its aspect ratio",
+                    file, anamorphic
+                );
+            }
+
+            for rendition in &renditions {
+                let rendition = rendition.as_ref();
+                if let Some(rendition) = rendition {
+                    // a ladder is not scaled up, the renditions above the source are left out
+                    if rendition.height as u64 > source_height {
+                        info!(
+                            "{}: skipping the {} rendition of the {}p source",
+                            file, rendition, source_height
+                        );
+                        continue;
+                    }
+                    info!("{}: rendition {}", file, rendition);
+                }
+                // scaling changes the VMAF, a VMAF rendition searches its own CRF
+                let vmaf_target = match rendition.map(|rendition| &rendition.quality) {
+                    Some(RenditionQuality::Vmaf(vmaf)) => *vmaf,
+                    _ => *_vmaf,
+                };
+                let scale = rendition.map(|rendition| rendition.scale_filter());
+                let mut ab_av1_args = ab_av1_tune_args.clone();
+                // the samples get the keyframe interval of the encode
+                if let Some(keyint) = encode_keyint(get_fps_f32(&file), args) {
+                    ab_av1_args.extend(keyint.ab_av1_args(&self.encoder));
+                }
+                // ab-av1 also runs the reference of its VMAF through --vfilter
+                let vfilter: Vec<&str> = deinterlace
+                    .then_some("yadif")
+                    .into_iter()
+                    .chain(tonemap.as_deref())
+                    .chain(scale.as_deref())
+                    .chain(
+                        downscale
+                            .as_ref()
+                            .map(|downscale| downscale.filter.as_str()),
+                    )
+                    .collect();
+                if !vfilter.is_empty() {
+                    ab_av1_args.extend(["--vfilter".to_string(), vfilter.join(",")]);
+                }
+                // an SDR sample scored against the HDR frames would be meaningless, and a
+                // downscaled one is scored at its own size
+                if tonemap.is_some() || downscale.is_some() {
+                    ab_av1_args.extend(["--reference-vfilter".to_string(), vfilter.join(",")]);
+                }
+
+                // --target-size encodes at the bitrate that fits the size, a bitrate rendition
+                // at its bitrate, no CRF is searched
+                let target_bitrate = match (rendition.map(|r| &r.quality), args.target_size) {
+                    (Some(RenditionQuality::Bitrate(kbps)), _) => Some(*kbps),
+                    (_, Some(target_size)) => Some(
+                        target_size_bitrate(&file, target_size, audio_streams, &audio_plans)
+                            .map_err(|e| FailedFile::new(&file, "target_size", e))?,
+                    ),
+                    _ => None,
+                };
+
+                // run ab-av1.exe or the native search to find the best crf for the file
+                let crf_search_result = if let Some(target_bitrate) = target_bitrate {
+                    match args.target_size {
+                        Some(target_size) => info!(
+                            "{}: {} kbps video for {} MB",
+                            file, target_bitrate, target_size
+                        ),
+                        None => info!("{}: {} kbps video", file, target_bitrate),
+                    }
+                    Ok((String::new(), vmaf_target, None))
+                } else if let Some(duration) = short_input {
+                    info!(
+                        "{}: {:.1}s, shorter than --min-duration, encoding at CRF {} without the search",
+                        file, duration, args.short_input_crf
+                    );
+                    Ok((args.short_input_crf.to_string(), vmaf_target, None))
+                } else if args.crf_search == "native" {
+                    run_native_crf_search(
+                        &file,
+                        args,
+                        vmaf_target,
+                        _max_crf,
+                        _sample_every,
+                        &args.task_id,
+                        &job.current_file_count,
+                        &job.total_files,
+                        scale.as_deref(),
+                    )
+                } else {
+                    run_ab_av1_crf_search(
+                        &file,
+                        &self.encoder,
+                        &self.preset,
+                        _pix_fmt,
+                        vmaf_target,
+                        args.vmaf_retry_step,
+                        _max_crf,
+                        _sample_every,
+                        _vmaf_threads,
+                        *_verbose,
+                        &ab_av1_args,
+                        &args.task_id,
+                        &job.current_file_count,
+                        &job.total_files,
+                        &bars.vmaf_bar,
+                    )
+                };
+
+                let output_final =
+                    crf_search_result.map_err(|tail| FailedFile::new(&file, "crf_search", tail))?;
+                // a bitrate encode has no CRF
+                if let Ok(crf) = output_final.0.parse::<f32>() {
+                    emit_event(&ProgressEvent::CrfFound {
+                        path: file.clone(),
+                        crf,
+                        vmaf: output_final.1,
+                        predicted_percent: output_final.2.map(|predicted| predicted.percent),
+                    });
+                }
+
+                if let Some(predicted) = output_final.2 {
+                    bars.info_bar.set_message(predicted.to_string());
+                }
+
+                // the status is the file's, a rendition without savings is still encoded
+                if args.skip_if_no_savings
+                    && rendition.is_none()
+                    && output_final
+                        .2
+                        .map_or(false, |predicted| predicted.no_savings())
+                {
+                    info!("{}: no size savings predicted, skipping", file);
+                    if let Err(e) = set_db_status(&file, "skipped") {
+                        error!("Failed to update status of {}: {}", file, e);
+                    }
+                } else if let Err(e) = check_file_free_space(
+                    &file,
+                    &args.outputpath,
+                    output_final
+                        .2
+                        .map(|predicted| predicted.size_mb as f64)
+                        .or(args.target_size),
+                    false,
+                    args.min_free_space,
+                ) {
+                    skip_no_space(&file, &e);
+                } else {
+                    // set_output_folder function
+                    let final_output = match (rendition, args.target_size) {
+                        (Some(rendition), _) => set_output_folder_filename_rendition(
+                            &file,
+                            &self.encoder,
+                            rendition,
+                            &output_final.1,
+                            &output_final.0,
+                            &args.outputpath,
+                        ),
+                        (None, Some(target_size)) => set_output_folder_filename_size(
+                            &file,
+                            &self.encoder,
+                            target_size,
+                            &args.outputpath,
+                        ),
+                        (None, None) => set_output_folder_filename(
+                            &file,
+                            &self.encoder,
+                            &output_final.1,
+                            &output_final.0,
+                            &args.outputpath,
+                        ),
+                    };
+                    let final_output = match &downscale {
+                        Some(downscale) => downscaled_output_name(&file, &final_output, downscale),
+                        None => final_output,
+                    };
+                    // a trial never takes the name of the real encode
+                    let final_output = match trial {
+                        Some(range) => range.output_name(&final_output),
+                        None => final_output,
+                    };
+
+                    if let Some(filter) = &tonemap {
+                        self.record_tonemap(&file, filter);
+                    }
+                    // run ffmpeg.exe to encode the file
+                    let completed = run_ffmpeg_transcode(
+                        &file,
+                        &self.encoder,
+                        _params_x265,
+                        &self.preset,
+                        _pix_fmt,
+                        &final_output,
+                        // use the result from run_ab_av1_crf_search function
+                        &output_final.0.to_string(),
+                        &bars.file_bar,
+                        &bars.transcode_bar,
+                        &bars.total_bar,
+                        &bars.info_bar,
+                        &bars.codec_bar,
+                        &job.total_files,
+                        &job.current_file_count,
+                        &self.frame_counts,
+                        &output_final.1,
+                        &original_audio_codec,
+                        transcode_info,
+                        &audio_plans,
+                        &vec_video_args,
+                        &args.task_id,
+                        args.min_free_space,
+                        burn_subtitle.as_ref(),
+                        &streams,
+                        target_bitrate,
+                        rendition,
+                        deinterlace,
+                        tonemap.as_deref(),
+                        downscale
+                            .as_ref()
+                            .map(|downscale| downscale.filter.as_str()),
+                        encode_keyint(get_fps_f32(&file), args),
+                    )
+                    .map_err(|tail| FailedFile::new(&file, "transcode", tail))?;
+                    if completed {
+                        self.record_encode_samples(&file, &final_output);
+                    }
+                    // a trial is only kept for the summary
+                    if trial.is_some() {
+                        self.trials.lock().unwrap().extend(take_trial_outputs());
+                        continue;
+                    }
+
+                    if completed && args.verify_vmaf {
+                        verify_encode_vmaf(&file, &final_output, args, output_final.1);
+                    }
+                    // --discard-if-larger and --warn-if-larger, a rendition is smaller anyway
+                    let mut kept_original = false;
+                    if completed && rendition.is_none() {
+                        if let Some(check) = check_output_size(&file, &final_output, args) {
+                            kept_original = check.decision == "kept_original";
+                            self.size_checks.lock().unwrap().push(check);
+                        }
+                    }
+                    if completed && !kept_original {
+                        self.record_merged_subtitles(&file, &final_output, &streams);
+                        save_screenshots(&file, &final_output, args, &[]);
+                        finish_output(&file, &final_output, args);
+                        if let Some(rendition) = rendition {
+                            self.record_rendition(
+                                &file,
+                                rendition,
+                                &final_output,
+                                &output_final.0,
+                                output_final.1,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The subtitle stream of file that --burn-subs selects, logged either way
+fn burn_subtitle_for(file: &str, burn_subs: &str) -> Option<SubtitleStream> {
+    let subtitle = select_burn_subtitle(&get_subtitle_streams(file), burn_subs);
+    match &subtitle {
+        Some(subtitle) => info!(
+            "{}: burning in subtitle stream {} ({}, {}{})",
+            file,
+            subtitle.index,
+            subtitle.codec,
+            if subtitle.language.is_empty() {
+                "no language"
+            } else {
+                &subtitle.language
+            },
+            if subtitle.forced { ", forced" } else { "" }
+        ),
+        None => info!(
+            "{}: no subtitle matches --burn-subs {}, encoding without burning in",
+            file, burn_subs
+        ),
+    }
+    subtitle
+}
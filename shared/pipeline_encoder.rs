@@ -0,0 +1,151 @@
+// The encoder of a Pipeline: the ffmpeg encoder, preset and params of the --encoder, and the software
+// encoder the run switches to when a hardware encoder keeps failing to start
+
+use crate::*;
+
+use colored::Colorize;
+use log::warn;
+
+// Files whose hardware encoder failed to start before the rest of the run uses the fallback
+pub(crate) const HARDWARE_FAILURES_BEFORE_SWITCH: u32 = 2;
+
+// The encoder of a run: as passed on the command line (av1), the ffmpeg encoder (libaom-av1), its
+// preset and params
+#[derive(Debug, Clone)]
+pub(crate) struct EncoderSettings {
+    pub(crate) encoder: String,
+    pub(crate) codec: String,
+    pub(crate) preset: String,
+    pub(crate) params: String,
+}
+
+// The settings of encoder from the presets and params of args
+pub(crate) fn resolve_encoder(args: &Args, encoder: &str) -> Result<EncoderSettings, String> {
+    let (codec, preset, params) = match encoder {
+        "libx265" => ("libx265", &args.preset_x265, &args.params_x265),
+        // libaom-av1 keeps --params-x265
+        "av1" => ("libaom-av1", &args.preset_libaom_av1, &args.params_x265),
+        "libsvtav1" => ("libsvtav1", &args.preset_libsvtav1, &args.params_libsvtav1),
+        "hevc_nvenc" => (
+            "hevc_nvenc",
+            &args.preset_hevc_nvenc,
+            &args.params_hevc_nvenc,
+        ),
+        "hevc_qsv" => ("hevc_qsv", &args.preset_hevc_qsv, &args.params_hevc_qsv),
+        "av1_qsv" => ("av1_qsv", &args.preset_av1_qsv, &args.params_av1_qsv),
+        _ => return Err(format!("{} is not a valid encoder!", encoder)),
+    };
+    Ok(EncoderSettings {
+        encoder: encoder.to_string(),
+        codec: codec.to_string(),
+        preset: preset.clone(),
+        params: params.clone(),
+    })
+}
+
+// The software encoder a hardware encoder falls back to when it fails to start: --fallback-encoder,
+// by default libx265, or libsvtav1 for av1_qsv. None for software encoders, with
+// --fallback-encoder none or when ffmpeg lacks the fallback
+pub(crate) fn resolve_fallback_encoder(
+    args: &Args,
+    primary: &EncoderSettings,
+) -> Option<EncoderSettings> {
+    if !is_hardware_encoder(&primary.codec) {
+        return None;
+    }
+    let name = match args.fallback_encoder.as_deref() {
+        Some("none") => return None,
+        Some(name) => name,
+        None if primary.codec == "av1_qsv" => "libsvtav1",
+        None => "libx265",
+    };
+    // validated by clap
+    let fallback = resolve_encoder(args, name).ok()?;
+    let mut fallback_args = args.clone();
+    fallback_args.encoder = fallback.codec.clone();
+    if let Err(e) = check_ffmpeg_capabilities(&fallback_args) {
+        warn!("No fallback for {}: {}", primary.codec, e);
+        return None;
+    }
+    Some(fallback)
+}
+
+impl Pipeline {
+    // process_file, again with the fallback encoder when the hardware encoder failed to start. After
+    // HARDWARE_FAILURES_BEFORE_SWITCH such files the rest of the run starts with the fallback
+    pub(crate) fn process_file_with_fallback(
+        &mut self,
+        job: &FileJob,
+        bars: &PipelineBars,
+    ) -> Result<(), FailedFile> {
+        let total_position = bars.total_bar.position();
+        let failed = match self.process_file(job, bars) {
+            Ok(()) => {
+                if self.on_fallback {
+                    self.record_fallback(&job.file);
+                }
+                return Ok(());
+            }
+            Err(failed) => failed,
+        };
+        let fallback = match &self.fallback {
+            Some(fallback) if is_hardware_init_failure(&failed.stderr_tail) => fallback.clone(),
+            _ => return Err(failed),
+        };
+
+        self.hardware_failures += 1;
+        let hint = if self.args.fallback_encoder.is_none() {
+            " (--fallback-encoder none turns this off)"
+        } else {
+            ""
+        };
+        warn!(
+            "{}",
+            format!(
+                "{} failed to start for {} ({}), retrying with {}{}",
+                self.args.encoder, job.file, failed.stage, fallback.codec, hint
+            )
+            .yellow()
+        );
+        let hardware = self.encoder_settings();
+        self.use_encoder(&fallback);
+        // the retry counts the frames of the file from the start again
+        bars.total_bar.set_position(total_position);
+        bars.transcode_bar.set_position(0);
+        let result = self.process_file(job, bars);
+        if result.is_ok() {
+            self.record_fallback(&job.file);
+        }
+
+        if self.hardware_failures >= HARDWARE_FAILURES_BEFORE_SWITCH {
+            warn!(
+                "{} failed to start {} times, encoding the remaining files with {}",
+                hardware.codec, self.hardware_failures, fallback.codec
+            );
+            self.fallback = None;
+            self.on_fallback = true;
+        } else {
+            self.use_encoder(&hardware);
+        }
+        result
+    }
+
+    fn encoder_settings(&self) -> EncoderSettings {
+        EncoderSettings {
+            encoder: self.encoder.clone(),
+            codec: self.args.encoder.clone(),
+            preset: self.preset.clone(),
+            params: self.args.params_x265.clone(),
+        }
+    }
+
+    fn use_encoder(&mut self, settings: &EncoderSettings) {
+        self.encoder = settings.encoder.clone();
+        self.args.encoder = settings.codec.clone();
+        self.preset = settings.preset.clone();
+        self.args.params_x265 = settings.params.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,310 @@
+use super::*;
+
+use std::ffi::OsString;
+
+// The fixture: a movie with a 7.1 TrueHD and a stereo AAC track, an english and a forced german
+// subtitle and a srt next to it
+fn fixture_streams() -> StreamSelection {
+    let audio = |index: usize, codec: &str, channels: &str, bitrate: i64| AudioStream {
+        index,
+        codec: codec.to_string(),
+        channels: channels.to_string(),
+        bitrate,
+        language: "eng".to_string(),
+        default: index == 0,
+    };
+    let subtitle = |index: usize, language: &str, forced: bool| SubtitleStream {
+        index,
+        codec: "subrip".to_string(),
+        language: language.to_string(),
+        forced,
+    };
+    StreamSelection {
+        audio: vec![
+            audio(0, "truehd", "8", 4_000_000),
+            audio(1, "aac", "2", 160_000),
+        ],
+        subtitles: vec![subtitle(0, "eng", false), subtitle(1, "ger", true)],
+        dropped: vec!["a:2(fre)".to_string()],
+        external_subtitles: vec![ExternalSubtitle {
+            path: "/media/Movie (2020)/Movie.fre.srt".to_string(),
+            language: "fre".to_string(),
+            codec: "copy".to_string(),
+        }],
+        cover_art: Vec::new(),
+    }
+}
+
+// The final encode of the fixture at crf 24 with the settings the pipeline resolves for encoder
+fn final_encode(options: &[&str], encoder: &str) -> (Vec<String>, String) {
+    let args = test_args(options);
+    let settings = resolve_encoder(&args, encoder).unwrap();
+    let file = "/media/Movie (2020)/Movie.mkv";
+    let mut spec = EncodeSpec::new(
+        &settings.encoder,
+        EncodeQuality::Crf(24.0),
+        &settings.preset,
+        &settings.params,
+        &args.pix_fmt,
+    );
+    spec.keyint = Some(Keyint {
+        gop: 240,
+        min: None,
+    });
+    spec.maps = vec!["-map".to_string(), "0:v:0".to_string()];
+    let streams = fixture_streams();
+    let plans = plan_audio_streams(&streams.audio, &AudioOptions::from_args(&args));
+    let output = set_output_folder_filename(file, &settings.codec, &95.0, "24", "/out");
+    let part = PartFile::new(&output, file);
+    let (built, audio_codecs) =
+        final_encode_args(file, &spec, &streams, &plans, "truehd", Vec::new(), &part);
+    let built = built
+        .into_iter()
+        .map(|arg: OsString| arg.to_string_lossy().to_string())
+        .collect();
+    (built, audio_codecs)
+}
+
+// The video of the final encode of each encoder, from -c:v to -pix_fmt
+fn video_args(built: &[String]) -> Vec<String> {
+    let start = built.iter().position(|arg| arg == "-c:v").unwrap();
+    let end = built.iter().position(|arg| arg == "-pix_fmt").unwrap();
+    built[start..end].to_vec()
+}
+
+// Everything from -pix_fmt to the output
+fn after_video(built: &[String]) -> &[String] {
+    let start = built.iter().position(|arg| arg == "-pix_fmt").unwrap();
+    &built[start..built.len() - 1]
+}
+
+#[test]
+fn final_encode_snapshot_of_the_fixture() {
+    let (built, audio_codecs) = final_encode(&[], "libx265");
+    assert_eq!(
+        built,
+        [
+            "-y",
+            "-i",
+            "/media/Movie (2020)/Movie.mkv",
+            "-i",
+            "/media/Movie (2020)/Movie.fre.srt",
+            "-map",
+            "0:v:0",
+            "-c:v",
+            "libx265",
+            "-preset",
+            "slow",
+            "-x265-params",
+            "limit-sao:bframes=8:psy-rd=1:aq-mode=3:keyint=240",
+            "-crf",
+            "24",
+            "-g",
+            "240",
+            "-pix_fmt",
+            "yuv420p10le",
+            "-map",
+            "0:a:0",
+            "-c:a:0",
+            "copy",
+            "-map",
+            "0:a:1",
+            "-c:a:1",
+            "copy",
+            "-map",
+            "0:s:0",
+            "-c:s:0",
+            "copy",
+            "-map",
+            "0:s:1",
+            "-c:s:1",
+            "copy",
+            "-map",
+            "1:s:0",
+            "-c:s:2",
+            "copy",
+            "-metadata:s:s:2",
+            "language=fre",
+            "-map_metadata",
+            "-1",
+            "-map_metadata:s:a:0",
+            "0:s:a:0",
+            "-map_metadata:s:a:1",
+            "0:s:a:1",
+            "-map_metadata:s:s:0",
+            "0:s:s:0",
+            "-map_metadata:s:s:1",
+            "0:s:s:1",
+            "-c:a:0",
+            "libopus",
+            "-filter:a:0",
+            "aformat=channel_layouts=7.1",
+            "-b:a:0",
+            "450k",
+            "-c:a:1",
+            "copy",
+            "-progress",
+            "pipe:2",
+            "-f",
+            "matroska",
+            "/out/Movie.libx265.vmaf95.crf24.mkv.part",
+        ]
+    );
+    assert_eq!(audio_codecs, "truehd->opus,aac -a:2(fre)");
+}
+
+#[test]
+fn final_encode_snapshot_of_each_encoder() {
+    let qsv_params = [
+        "-init_hw_device",
+        "qsv=intel,child_device=0",
+        "-b_strategy",
+        "1",
+        "-look_ahead",
+        "1",
+        "-async_depth",
+        "100",
+    ];
+    let cases: [(&str, Vec<&str>); 4] = [
+        (
+            "libsvtav1",
+            vec![
+                "-c:v",
+                "libsvtav1",
+                "-preset",
+                "5",
+                "-crf",
+                "24",
+                "-g",
+                "240",
+            ],
+        ),
+        (
+            "hevc_nvenc",
+            vec![
+                "-c:v",
+                "hevc_nvenc",
+                "-preset",
+                "p7",
+                "-rc-lookahead",
+                "100",
+                "-b_ref_mode",
+                "each",
+                "-tune",
+                "hq",
+                "-rc:v",
+                "vbr",
+                "-cq:v",
+                "24",
+                "-qmin",
+                "24",
+                "-qmax",
+                "24",
+                "-g",
+                "240",
+            ],
+        ),
+        (
+            "hevc_qsv",
+            ["-c:v", "hevc_qsv", "-preset", "veryslow"]
+                .iter()
+                .chain(&qsv_params)
+                .chain(&["-global_quality", "24", "-g", "240"])
+                .copied()
+                .collect(),
+        ),
+        (
+            "av1_qsv",
+            ["-c:v", "av1_qsv", "-preset", "1"]
+                .iter()
+                .chain(&qsv_params)
+                .chain(&["-global_quality", "24", "-g", "240"])
+                .copied()
+                .collect(),
+        ),
+    ];
+    let (x265, _) = final_encode(&[], "libx265");
+    for (encoder, expected) in cases {
+        let (built, _) = final_encode(&[], encoder);
+        assert_eq!(video_args(&built), expected, "{}", encoder);
+        // the streams after the video are the same for every encoder, only the output is named after it
+        assert_eq!(after_video(&built), after_video(&x265), "{}", encoder);
+        assert_eq!(
+            built.last().unwrap(),
+            &format!("/out/Movie.{}.vmaf95.crf24.mkv.part", encoder)
+        );
+    }
+}
+
+#[test]
+fn the_options_of_the_command_line_reach_the_final_encode() {
+    let (built, audio_codecs) = final_encode(
+        &[
+            "--preset-hevc-nvenc",
+            "p5",
+            "--params-hevc-nvenc=-tune uhq",
+            "--pix-fmt",
+            "p010le",
+            "--audio-codec",
+            "aac",
+        ],
+        "hevc_nvenc",
+    );
+    assert_eq!(
+        video_args(&built),
+        [
+            "-c:v",
+            "hevc_nvenc",
+            "-preset",
+            "p5",
+            "-tune",
+            "uhq",
+            "-rc:v",
+            "vbr",
+            "-cq:v",
+            "24",
+            "-qmin",
+            "24",
+            "-qmax",
+            "24",
+            "-g",
+            "240",
+        ]
+    );
+    let pix_fmt = built.iter().position(|arg| arg == "-pix_fmt").unwrap();
+    assert_eq!(built[pix_fmt + 1], "p010le");
+    assert_eq!(
+        built[built.len() - 13..built.len() - 5],
+        [
+            "-c:a:0",
+            "aac",
+            "-filter:a:0",
+            "aformat=channel_layouts=7.1",
+            "-b:a:0",
+            "512k",
+            "-c:a:1",
+            "copy",
+        ]
+    );
+    assert_eq!(audio_codecs, "truehd->aac,aac -a:2(fre)");
+}
+
+#[test]
+fn resolve_encoder_of_each_encoder() {
+    let args = test_args(&[]);
+    for (encoder, codec) in [
+        ("libx265", "libx265"),
+        ("av1", "libaom-av1"),
+        ("libsvtav1", "libsvtav1"),
+        ("hevc_nvenc", "hevc_nvenc"),
+        ("hevc_qsv", "hevc_qsv"),
+        ("av1_qsv", "av1_qsv"),
+    ] {
+        let settings = resolve_encoder(&args, encoder).unwrap();
+        assert_eq!(
+            (settings.encoder.as_str(), settings.codec.as_str()),
+            (encoder, codec)
+        );
+    }
+    assert!(resolve_encoder(&args, "h264").is_err());
+}
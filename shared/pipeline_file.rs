@@ -0,0 +1,462 @@
+// One file of a Pipeline run: the CRF search, the encode of the default, chunked or audio-only
+// mode and the database updates of the file
+
+use crate::*;
+
+use colored::Colorize;
+use indicatif::ProgressStyle;
+use log::{debug, error, info, warn};
+
+impl Pipeline {
+    // Encode one file with the default, chunked or audio-only mode
+    pub fn process_file(&self, job: &FileJob, bars: &PipelineBars) -> Result<(), FailedFile> {
+        let file = job.file.clone();
+
+        // --verify-library found it broken, the encode would fail halfway through it
+        let (size, mtime) = file_stamp_ms(&file);
+        match db().file_health(&file, size, mtime) {
+            Ok(Some(entry)) if entry.health == "corrupt" => {
+                error!(
+                    "{} was found corrupt on {}, not encoding it: {}",
+                    file, entry.checked_at, entry.error
+                );
+                return Err(FailedFile::new(&file, "health", entry.error));
+            }
+            Ok(Some(entry)) if entry.health == "suspect" => warn!(
+                "{} printed decode errors on {}: {}",
+                file, entry.checked_at, entry.error
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to read the health of {}: {}", file, e),
+        }
+
+        // --min-estimated-savings: the samples of --estimate saved too little, a file without an
+        // estimate is encoded
+        if let Some(min_savings) = self.args.min_estimated_savings {
+            let estimate = match self.args.mode.as_str() {
+                "audio-only" => None,
+                _ => stored_estimate(&file, &self.args),
+            };
+            if let Some(estimate) = estimate.filter(|e| e.savings_percent < min_savings) {
+                info!(
+                    "Skipping {}: estimated to save {:.0}%, below --min-estimated-savings {}%",
+                    file, estimate.savings_percent, min_savings
+                );
+                if let Err(e) = set_db_status(&file, "skipped_estimate") {
+                    error!("Failed to update status of {}: {}", file, e);
+                }
+                return Ok(());
+            }
+        }
+
+        // the outputs are only renamed in place while the source is as it is now
+        let _source = watch_source(&file);
+
+        // --trial-range: the encodes of the file only take the range until _trial is dropped,
+        // audio-only copies the whole video
+        let _trial = match self.args.mode.as_str() {
+            "audio-only" => None,
+            _ => start_trial(&file, &self.args)
+                .map_err(|e| FailedFile::new(&file, "trial_range", e))?,
+        };
+        let trial = _trial.as_ref().map(|trial| trial.range);
+        if let Some(range) = trial {
+            info!("{}: trial of {}", file, range);
+        }
+
+        let settings = self.file_settings(&file)?;
+        let args = &settings.args;
+        let dynamic_hdr = &settings.dynamic_hdr;
+
+        let _mode = &args.mode;
+
+        // a file below --min-duration is encoded at --short-input-crf, or skipped with --skip-short
+        let short_input = match _mode.as_str() {
+            "audio-only" => None,
+            _ => short_input_duration(&file, args),
+        };
+        if let Some(duration) = short_input {
+            if args.skip_short {
+                skip_short_input(&file, duration, args.min_duration);
+                return Ok(());
+            }
+        }
+
+        // chunked mode needs 2 scenes, a short file and a file of one scene go the default way
+        let (_mode, chunked_split) = match (_mode.as_str(), short_input) {
+            ("chunked", _) if is_dovi(dynamic_hdr) && !args.strip_dovi => ("chunked", None),
+            ("chunked", Some(duration)) => {
+                info!(
+                    "{}: {:.1}s, shorter than --min-duration, encoding it in the default mode",
+                    file, duration
+                );
+                ("default", None)
+            }
+            ("chunked", None) => {
+                info!("Running chunked...");
+                start_chunked_progress(
+                    &args.task_id,
+                    job.current_file_count,
+                    job.total_files,
+                    &file,
+                );
+                let (scene_changes, split_source) =
+                    split_chunked(&file, args).inspect_err(|_| clear_chunked_progress())?;
+                // a trial encodes the scenes in its range, cut to it
+                let scene_changes = match trial {
+                    Some(range) => range.clip_scene_changes(&scene_changes),
+                    None => scene_changes,
+                };
+                let scenes = usable_scenes(&scene_changes, get_fps_f32(&file));
+                if scenes < 2 {
+                    info!(
+                        "{}: {} usable {}, encoding it in the default mode",
+                        file,
+                        scenes,
+                        if scenes == 1 { "scene" } else { "scenes" }
+                    );
+                    ("default", None)
+                } else {
+                    ("chunked", Some((scene_changes, split_source)))
+                }
+            }
+            (mode, _) => (mode, None),
+        };
+        // a file that is not split after all goes to /progress like in the default mode
+        if chunked_split.is_none() {
+            clear_chunked_progress();
+        }
+
+        if _mode == "default" {
+            self.process_file_default(&settings, job, bars, trial, short_input)?;
+        } else if _mode == "chunked" && is_dovi(dynamic_hdr) && !args.strip_dovi {
+            skip_dovi(&file, dynamic_hdr);
+        } else if let Some((scene_changes_clone, split_source)) = chunked_split {
+            self.process_file_chunked(
+                &file,
+                args,
+                settings.tonemap.as_deref(),
+                scene_changes_clone,
+                split_source,
+            )?;
+        } else if _mode == "audio-only" {
+            self.process_file_audio_only(args, job, bars)?;
+        } else {
+            error!("{} is not a valid mode!", _mode);
+            return Err(FailedFile::new(&file, "mode", ""));
+        }
+        Ok(())
+    }
+
+    // The chunked encode of file, split at scene_changes
+    fn process_file_chunked(
+        &self,
+        file: &str,
+        args: &Args,
+        tonemap: Option<&str>,
+        scene_changes_clone: Vec<f32>,
+        split_source: &'static str,
+    ) -> Result<(), FailedFile> {
+        if let Err(e) =
+            check_file_free_space(file, &args.outputpath, None, true, args.min_free_space)
+        {
+            clear_chunked_progress();
+            skip_no_space(file, &e);
+        } else {
+            if let Some(filter) = &tonemap {
+                self.record_tonemap(file, filter);
+            }
+            let scene_changes_clone2 = scene_changes_clone.clone();
+
+            // for each scene in scene_changes run get_scene_size
+            // Create a vector of (scene_index, scene_size)
+            // Possibly add this to the vector of scene_changes
+            let scene_changes_clone = scene_changes_clone;
+            let mut scene_sizes: Vec<(i32, u64)> = Vec::new();
+            let mut scenes: Vec<(f32, f32)> = Vec::new();
+            {
+                let scene_changes_locked = scene_changes_clone;
+                for window in scene_changes_locked.windows(2) {
+                    scenes.push((window[0], window[1]));
+                }
+            }
+
+            // Create a progress bar
+            let progress_bar = progress_bar(scenes.len() as u64);
+            let progress_bar_style =
+                "[scs][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]";
+            progress_bar.set_style(
+                ProgressStyle::default_bar()
+                    .template(progress_bar_style)
+                    .unwrap(),
+            );
+
+            let mut scene_index = 0;
+            for (scene_start, scene_end) in &scenes {
+                let ss = format_timecode(scene_start);
+                let to = format_timecode(scene_end);
+                let scene_size = get_scene_size(file, &ss, &to);
+
+                let scene_size = scene_size.map_err(|e| {
+                    clear_chunked_progress();
+                    FailedFile::new(file, "scene_detection", e)
+                })?;
+                scene_sizes.push((scene_index, scene_size));
+                scene_index += 1;
+                progress_bar.inc(1);
+            }
+
+            let scene_changes_vec = scene_changes_clone2;
+
+            let _scene_changes = run_ffmpeg_extract_scene_changes_pipe_vmaf_target_threaded(
+                file,
+                &scene_changes_vec,
+                &scene_sizes,
+                args,
+                &get_fps_f32(file),
+                split_source,
+                &self.size_checks,
+            );
+            clear_chunked_progress();
+            self.sync_checks.lock().unwrap().extend(take_sync_checks());
+
+            // TODO make conversion inside function, directly after calculation
+            // It should probably have it's own function and progress bar
+            let scene_changes = match _scene_changes {
+                Ok(scene_changes) => scene_changes,
+                Err(e) => {
+                    error!("Chunked encode of {} failed: {}", file, e);
+                    return Err(FailedFile::new(file, "chunked_encode", e));
+                }
+            };
+
+            self.trials.lock().unwrap().extend(take_trial_outputs());
+            // the statistics are logged by the chunked encode, these are the scenes behind them
+            for (scene_index, crf, vmaf_score) in &scene_changes {
+                debug!("scene {}: CRF {} VMAF {}", scene_index, crf, vmaf_score);
+            }
+        }
+        Ok(())
+    }
+
+    // Store an encoded rendition in the database and keep it for the summary of the run
+    pub(crate) fn record_rendition(
+        &self,
+        file: &str,
+        rendition: &Rendition,
+        output: &str,
+        crf: &str,
+        vmaf: f32,
+    ) {
+        let size = fs::metadata(output).map(|m| m.len()).unwrap_or(0);
+        if let Err(e) = db().insert_rendition(
+            file,
+            rendition,
+            output,
+            crf.trim(),
+            vmaf,
+            size,
+            &self.args.task_id,
+        ) {
+            warn!(
+                "Failed to store the {} rendition of {}: {}",
+                rendition, file, e
+            );
+        }
+        self.renditions.lock().unwrap().push(RenditionOutput {
+            source: file.to_string(),
+            rendition: rendition.to_string(),
+            output: output.to_string(),
+            size,
+        });
+    }
+
+    // Keep the filter a file was tonemapped with for --report-json, once for all its renditions
+    pub(crate) fn record_tonemap(&self, file: &str, filter: &str) {
+        let mut tonemapped = self.tonemapped.lock().unwrap();
+        if !tonemapped.iter().any(|entry| entry.path == file) {
+            tonemapped.push(TonemappedFile {
+                path: file.to_string(),
+                filter: filter.to_string(),
+            });
+        }
+    }
+
+    // Keep a lowered VMAF target for the summary, once for a file that is retried
+    pub(crate) fn record_adapted_target(&self, adapted: AdaptedTarget) {
+        let mut adapted_targets = self.adapted_targets.lock().unwrap();
+        if !adapted_targets
+            .iter()
+            .any(|entry| entry.path == adapted.path)
+        {
+            adapted_targets.push(adapted);
+        }
+    }
+
+    // Check the subtitle streams of an output with external subtitles and keep them for
+    // --report-json
+    pub(crate) fn record_merged_subtitles(
+        &self,
+        file: &str,
+        output: &str,
+        streams: &StreamSelection,
+    ) {
+        if streams.external_subtitles.is_empty() {
+            return;
+        }
+        check_subtitle_streams(output, streams);
+        self.merged_subtitles.lock().unwrap().push(MergedSubtitles {
+            path: file.to_string(),
+            output: output.to_string(),
+            subtitles: streams
+                .external_subtitles
+                .iter()
+                .map(|external| external.path.clone())
+                .collect(),
+        });
+    }
+
+    // Keep the fps and bitrate curve of an encode for --report-json
+    pub(crate) fn record_encode_samples(&self, file: &str, output: &str) {
+        if let Some(samples) = take_encode_samples(file) {
+            self.encode_samples.lock().unwrap().push(EncodeSamples {
+                path: file.to_string(),
+                output: output.to_string(),
+                samples,
+            });
+        }
+    }
+
+    // --mode audio-only: re-encode the audio streams the default mode would and copy the video,
+    // files with only aac/opus/mp3 audio are skipped instead of remuxed
+    fn process_file_audio_only(
+        &self,
+        args: &Args,
+        job: &FileJob,
+        bars: &PipelineBars,
+    ) -> Result<(), FailedFile> {
+        let file = job.file.clone();
+        info!("Running audio-only...");
+
+        let streams = select_streams(&file, args, None);
+        let audio_plans = plan_audio_streams(&streams.audio, &AudioOptions::from_args(args));
+        // dropping streams of --keep-audio-langs and --keep-subs-langs needs the remux too
+        let audio_reencode =
+            audio_plans.iter().any(|plan| !plan.args.is_empty()) || !streams.dropped.is_empty();
+        let codecs: Vec<String> = audio_plans
+            .iter()
+            .map(|plan| {
+                if plan.args.is_empty() {
+                    format!("{} (copy)", plan.original_codec)
+                } else {
+                    format!("{}->{}", plan.original_codec, plan.target_codec)
+                }
+            })
+            .collect();
+
+        if !audio_reencode {
+            info!(
+                "{}: audio is already {}, skipping",
+                file,
+                if codecs.is_empty() {
+                    "absent".to_string()
+                } else {
+                    codecs.join(", ")
+                }
+            );
+            if let Err(e) = set_db_status(&file, "skipped") {
+                error!("Failed to update status of {}: {}", file, e);
+            }
+            return Ok(());
+        }
+
+        info!("{}: audio {}", file, codecs.join(", "));
+        if !streams.dropped.is_empty() {
+            info!("{}: dropping {}", file, streams.dropped.join(", "));
+        }
+        if let Err(e) =
+            check_file_free_space(&file, &args.outputpath, None, false, args.min_free_space)
+        {
+            skip_no_space(&file, &e);
+            return Ok(());
+        }
+
+        let original_audio_codec: String = audio_plans
+            .last()
+            .map(|plan| plan.original_codec.clone())
+            .unwrap_or_default();
+        let vec_video_args: Vec<(usize, String, String, String)> = get_video_details(&file)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(j, (codec, width, height))| (j, codec, width, height))
+            .collect();
+        let final_output = set_output_folder_filename_audio(&file, &args.outputpath);
+
+        run_ffmpeg_transcode_audio(
+            &file,
+            &final_output,
+            &bars.file_bar,
+            &bars.transcode_bar,
+            &bars.total_bar,
+            &bars.info_bar,
+            &bars.codec_bar,
+            &job.total_files,
+            &job.current_file_count,
+            &self.frame_counts,
+            &original_audio_codec,
+            "audio",
+            &audio_plans,
+            &streams,
+            &vec_video_args,
+            &args.task_id,
+        )
+        .map_err(|tail| FailedFile::new(&file, "audio_transcode", tail))?;
+        finish_output(&file, &final_output, args);
+        Ok(())
+    }
+}
+
+// The split points of file for chunked mode by --chunk-by, and what they were split by
+fn split_chunked(file: &str, args: &Args) -> Result<(Vec<f32>, &'static str), FailedFile> {
+    let chapter_changes = if args.chunk_by == "chapters" {
+        let chapter_changes = get_chapter_changes(file, args.scene_split_min);
+        if chapter_changes.is_none() {
+            warn!(
+                "{} has fewer than 2 chapters, falling back to scene detection",
+                file
+            );
+        }
+        chapter_changes
+    } else {
+        None
+    };
+    let (scene_changes, split_source) = match chapter_changes {
+        Some(chapter_changes) => (Ok(chapter_changes), "chapters"),
+        None => (run_ffmpeg_scene_change(file, args), "scenes"),
+    };
+    let scene_changes = scene_changes.map_err(|e| FailedFile::new(file, "scene_detection", e))?;
+    info!(
+        "Split {} into {} chunks by {}",
+        file,
+        scene_changes.len().saturating_sub(1),
+        split_source
+    );
+    Ok((scene_changes, split_source))
+}
+
+// The encoded video loses the Dolby Vision RPUs while the container keeps signaling Dolby Vision,
+// which plays with wrong colors on DV displays, so these files are only encoded with --strip-dovi
+pub(crate) fn skip_dovi(file: &str, dynamic_hdr: &str) {
+    warn!(
+        "{}",
+        format!(
+            "Skipping {}: Dolby Vision ({}) can't be kept by the encode, pass --strip-dovi to encode the HDR10 base layer",
+            file, dynamic_hdr
+        )
+        .yellow()
+    );
+    if let Err(e) = set_db_status(file, "skipped_dovi") {
+        error!("Failed to update status of {}: {}", file, e);
+    }
+}
@@ -0,0 +1,469 @@
+// The end of a Pipeline run: the failures with --failures-json, --report-json and the summary of
+// what the run encoded, saved and put back
+
+use crate::*;
+
+use colored::Colorize;
+use log::{debug, error, info, warn};
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+// --report-json
+#[derive(Serialize)]
+struct RunReport<'a> {
+    failures: &'a [FailedFile],
+    // the encodes larger than --max-output-ratio of their source, deleted or kept
+    larger_outputs: &'a [SizeCheck],
+    // the HDR files encoded as SDR with their filter chain
+    tonemapped: &'a [TonemappedFile],
+    // the heavily compressed files encoded at a lower VMAF target than --vmaf
+    adapted_targets: &'a [AdaptedTarget],
+    // the external subtitle files merged into the outputs
+    external_subtitles: &'a [MergedSubtitles],
+    // the files copied to --stage-dir and how fast
+    staged: &'a [StagedFile],
+    // the fps and bitrate over the course of each encode
+    encode_samples: &'a [EncodeSamples],
+    // the encodes of --trial-range, left out of the statistics
+    trials: &'a [TrialOutput],
+    // the files still being written or changed during their encode, retried at the end
+    source_checks: &'a [SourceCheck],
+    // the --estimate of the encoded files against their encode
+    estimate_checks: &'a [EstimateCheck],
+    // the drift of the video and audio of every chunked encode from its source
+    av_sync: &'a [SyncCheck],
+}
+
+impl Pipeline {
+    // The --estimate of file against its encode, a file skipped or without an estimate has none
+    pub(crate) fn record_estimate_check(&mut self, file: &str) {
+        if let Some(check) = check_estimate(file, &self.args, &self.started_at) {
+            self.estimate_checks.push(check);
+        }
+    }
+
+    // Mark file as encoded with the fallback encoder in the database and the summary
+    pub(crate) fn record_fallback(&mut self, file: &str) {
+        if let Err(e) = db().update_fallback_encoder(file, &self.args.encoder) {
+            warn!("Failed to store the fallback encoder of {}: {}", file, e);
+        }
+        self.fallback_files
+            .push((file.to_string(), self.args.encoder.clone()));
+    }
+
+    // Show the tool output of a failed file right away and report it as the task's error on /progress
+    pub(crate) fn record_failure(&mut self, failed: FailedFile) {
+        wait_if_interrupted();
+        error!(
+            "{}",
+            format!("{} failed ({}):", failed.path, failed.stage).red()
+        );
+        for line in failed.stderr_tail.lines() {
+            error!("{}", line.red());
+        }
+        inc_metric(
+            &ENCODE_FAILURES,
+            &task_labels(&self.args.task_id, &self.args.encoder),
+            1.0,
+        );
+        emit_event(&ProgressEvent::FileFailed {
+            path: failed.path.clone(),
+            stage: failed.stage.clone(),
+        });
+        let last_line = failed.stderr_tail.lines().last().unwrap_or("");
+        set_task_error(
+            &self.args.task_id,
+            &format!("{} failed ({}): {}", failed.path, failed.stage, last_line),
+        );
+        if let Some(webhook) = &self.webhook {
+            webhook.file_failed(&failed);
+        }
+        self.failures.push(failed);
+    }
+
+    // The files that failed so far
+    pub fn failures(&self) -> &[FailedFile] {
+        &self.failures
+    }
+
+    // List the failed files at the end of the run, write them to --failures-json and --report-json,
+    // post the summary to --notify-webhook and close the task as done or failed
+    pub fn report_failures(&mut self) {
+        wait_if_interrupted();
+        let state = if self.failures.is_empty() {
+            "done"
+        } else {
+            "failed"
+        };
+        set_task_state(&self.args.task_id, state);
+
+        if !self.failures.is_empty() {
+            error!("{}", format!("{} files failed:", self.failures.len()).red());
+            for failed in &self.failures {
+                let last_line = failed.stderr_tail.lines().last().unwrap_or("");
+                error!(
+                    "  {} ({}): {}",
+                    self.display_titled(&failed.path),
+                    failed.stage,
+                    last_line
+                );
+            }
+        }
+
+        if let Some(path) = &self.args.failures_json {
+            let result = serde_json::to_string_pretty(&self.failures)
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                error!("Failed to write {}: {}", path, e);
+            }
+        }
+        if let Some(path) = &self.args.report_json {
+            // the paths below the -i folders with --mirror-structure
+            let failures: Vec<FailedFile> = self
+                .failures
+                .iter()
+                .map(|failed| FailedFile {
+                    path: self.display_path(&failed.path),
+                    ..failed.clone()
+                })
+                .collect();
+            let report = RunReport {
+                failures: &failures,
+                larger_outputs: &self.display_size_checks(),
+                tonemapped: &self.display_tonemapped(),
+                adapted_targets: &self.display_adapted_targets(),
+                external_subtitles: &self.display_merged_subtitles(),
+                staged: &self.display_staged(),
+                encode_samples: &self.display_encode_samples(),
+                trials: &self.display_trials(),
+                source_checks: &self.display_source_checks(),
+                estimate_checks: &self.display_estimate_checks(),
+                av_sync: &self.display_sync_checks(),
+            };
+            let result = serde_json::to_string_pretty(&report)
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                error!("Failed to write {}: {}", path, e);
+            }
+        }
+        if let Some(mut webhook) = self.webhook.take() {
+            let elapsed = self.elapsed();
+            // the savings of a session count from the start of its batch
+            let since = match &self.session {
+                Some(session) => session.created_at.clone(),
+                None => {
+                    humantime::format_rfc3339_seconds(SystemTime::now() - self.started.elapsed())
+                        .to_string()
+                }
+            };
+            let savings = db().stats_since(&since).unwrap_or_else(|e| {
+                warn!("Failed to read the stats of the run: {}", e);
+                StatsTotals::default()
+            });
+            webhook.run_finished(RunSummary {
+                task_id: self.args.task_id.clone(),
+                files: self.processed,
+                failed: self.failures.len() as u64,
+                savings,
+                elapsed_s: elapsed.as_secs(),
+                failures: self.failures.clone(),
+                larger_outputs: self.size_checks.lock().unwrap().clone(),
+            });
+        }
+    }
+
+    // path below its -i folder with --mirror-structure, the output below the output folder
+    fn display_path(&self, path: &str) -> String {
+        if !self.args.mirror_structure || path.is_empty() {
+            return path.to_string();
+        }
+        mirrored_display_path(path, &self.scan_roots, &self.args.outputpath)
+    }
+
+    // The title the scan stored for path with its file name, display_path without one. Half of the
+    // terminal is left for the rest of the line
+    fn display_titled(&self, path: &str) -> String {
+        match stored_label(path) {
+            Some(label) => titled_path(Some(&label), path, terminal_columns() / 2),
+            None => self.display_path(path),
+        }
+    }
+
+    fn display_size_checks(&self) -> Vec<SizeCheck> {
+        self.size_checks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|check| SizeCheck {
+                path: self.display_path(&check.path),
+                output: self.display_path(&check.output),
+                ..check.clone()
+            })
+            .collect()
+    }
+
+    fn display_tonemapped(&self) -> Vec<TonemappedFile> {
+        self.tonemapped
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| TonemappedFile {
+                path: self.display_path(&entry.path),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    fn display_adapted_targets(&self) -> Vec<AdaptedTarget> {
+        self.adapted_targets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| AdaptedTarget {
+                path: self.display_path(&entry.path),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    fn display_merged_subtitles(&self) -> Vec<MergedSubtitles> {
+        self.merged_subtitles
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| MergedSubtitles {
+                path: self.display_path(&entry.path),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    fn display_staged(&self) -> Vec<StagedFile> {
+        self.staged
+            .iter()
+            .map(|entry| StagedFile {
+                path: self.display_path(&entry.path),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    fn display_encode_samples(&self) -> Vec<EncodeSamples> {
+        self.encode_samples
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| EncodeSamples {
+                path: self.display_path(&entry.path),
+                output: self.display_path(&entry.output),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    fn display_source_checks(&self) -> Vec<SourceCheck> {
+        self.source_checks
+            .iter()
+            .map(|entry| SourceCheck {
+                path: self.display_path(&entry.path),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    fn display_estimate_checks(&self) -> Vec<EstimateCheck> {
+        self.estimate_checks
+            .iter()
+            .map(|check| EstimateCheck {
+                path: self.display_path(&check.path),
+                ..check.clone()
+            })
+            .collect()
+    }
+
+    fn display_trials(&self) -> Vec<TrialOutput> {
+        self.trials
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| TrialOutput {
+                path: self.display_path(&entry.path),
+                output: self.display_path(&entry.output),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    fn display_sync_checks(&self) -> Vec<SyncCheck> {
+        self.sync_checks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|check| SyncCheck {
+                path: self.display_path(&check.path),
+                output: self.display_path(&check.output),
+                ..check.clone()
+            })
+            .collect()
+    }
+
+    pub(crate) fn log_done(&mut self, count: i32) {
+        self.processed += count.max(0) as u64;
+        let elapsed = self.elapsed();
+        let seconds = elapsed.as_secs() % 60;
+        let minutes = (elapsed.as_secs() / 60) % 60;
+        let hours = elapsed.as_secs() / 60 / 60;
+        info!(
+            "done {} files in {}h:{}m:{}s",
+            count, hours, minutes, seconds
+        );
+        report_renditions(&self.renditions.lock().unwrap());
+        log_size_checks(&self.display_size_checks());
+        if !self.fallback_files.is_empty() {
+            warn!(
+                "{} files were encoded with the fallback encoder:",
+                self.fallback_files.len()
+            );
+            for (file, encoder) in &self.fallback_files {
+                warn!("  {} ({})", self.display_titled(file), encoder);
+            }
+        }
+        if !self.staged.is_empty() {
+            let bytes: u64 = self.staged.iter().map(|entry| entry.size).sum();
+            let seconds: f64 = self.staged.iter().map(|entry| entry.seconds).sum();
+            info!(
+                "{} files were staged, {} at {:.1} MB/s:",
+                self.staged.len(),
+                format_size(bytes),
+                if seconds > 0.0 {
+                    bytes_to_mb(bytes) / seconds
+                } else {
+                    0.0
+                }
+            );
+            for entry in self.display_staged() {
+                info!("  {} ({:.1} MB/s)", entry.path, entry.mb_per_second);
+            }
+        }
+        let adapted_targets = self.display_adapted_targets();
+        if !adapted_targets.is_empty() {
+            warn!(
+                "{}",
+                format!(
+                    "{} heavily compressed files were encoded at a lower VMAF target:",
+                    adapted_targets.len()
+                )
+                .yellow()
+            );
+            for adapted in &adapted_targets {
+                warn!(
+                    "  {} ({} {:.0} kb/s, {:.3} bpp): VMAF {} instead of {}",
+                    adapted.path,
+                    adapted.codec,
+                    adapted.bitrate_kbps,
+                    adapted.bpp,
+                    adapted.effective_vmaf,
+                    adapted.requested_vmaf
+                );
+            }
+        }
+        let trials = self.display_trials();
+        if !trials.is_empty() {
+            warn!(
+                "{}",
+                format!(
+                    "{} trial encodes, not counted in the statistics:",
+                    trials.len()
+                )
+                .yellow()
+            );
+            for trial in &trials {
+                let crf = if trial.crf.is_empty() {
+                    String::new()
+                } else {
+                    format!("CRF {}, ", trial.crf)
+                };
+                warn!(
+                    "  TRIAL {} {}-{}: {} ({}VMAF {:.2}, {})",
+                    trial.path,
+                    format_timecode(&trial.start),
+                    format_timecode(&trial.end),
+                    trial.output,
+                    crf,
+                    trial.vmaf,
+                    format_size(trial.output_size)
+                );
+            }
+        }
+        let source_checks = self.display_source_checks();
+        if !source_checks.is_empty() {
+            warn!(
+                "{}",
+                format!(
+                    "{} files were put back to the end of the batch:",
+                    source_checks.len()
+                )
+                .yellow()
+            );
+            for check in &source_checks {
+                warn!(
+                    "  {} {} ({}): {}",
+                    check.check, check.path, check.detail, check.outcome
+                );
+            }
+        }
+        let drifted: Vec<SyncCheck> = self
+            .display_sync_checks()
+            .into_iter()
+            .filter(|check| check.outcome != "passed")
+            .collect();
+        if !drifted.is_empty() {
+            warn!(
+                "{}",
+                format!(
+                    "{} chunked encodes drifted from their audio by more than --sync-max-drift:",
+                    drifted.len()
+                )
+                .red()
+            );
+            for check in &drifted {
+                warn!(
+                    "  {} ({:.1} ms): {}",
+                    check.path, check.max_drift_ms, check.outcome
+                );
+            }
+        }
+        let estimate_checks = self.display_estimate_checks();
+        if !estimate_checks.is_empty() {
+            let mean_error = estimate_checks
+                .iter()
+                .map(|check| check.size_error_percent.abs())
+                .sum::<f64>()
+                / estimate_checks.len() as f64;
+            info!(
+                "{} encodes had an --estimate, the sizes were off by {:.0}% on average:",
+                estimate_checks.len(),
+                mean_error
+            );
+            for check in &estimate_checks {
+                info!(
+                    "  {}: estimated {}, encoded {} ({:+.0}%), estimated {}, took {}",
+                    check.path,
+                    format_size(check.estimated_size),
+                    format_size(check.actual_size),
+                    check.size_error_percent,
+                    humantime::format_duration(Duration::from_secs(check.estimated_seconds as u64)),
+                    humantime::format_duration(Duration::from_secs(check.actual_seconds as u64))
+                );
+            }
+        }
+        debug!("{} ffprobe calls", probe_count());
+        emit_event(&ProgressEvent::RunDone {
+            files: count.max(0) as u64,
+            failed: self.failures.len() as u64,
+            elapsed_s: elapsed.as_secs(),
+        });
+    }
+}
@@ -0,0 +1,237 @@
+// The preparation of a Pipeline run: the scan of the input into the database, the files to encode
+// with their frame counts, a resumed batch and --estimate
+
+use crate::*;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
+use std::fs::metadata;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+// The frame counts of files in their order, probe_jobs files at a time. The counts stored by an
+// earlier run are reused for the files that did not change
+pub(crate) fn count_frames(
+    files: &[String],
+    probe_jobs: usize,
+    bar: &ProgressBar,
+) -> Vec<(String, u64)> {
+    let cached = AtomicUsize::new(0);
+    let count = || -> Vec<(String, u64)> {
+        files
+            .par_iter()
+            .map(|file| {
+                let (frames, from_cache) = cached_frame_count(file);
+                if from_cache {
+                    debug!("{}: frame count from cache", file);
+                    cached.fetch_add(1, Ordering::SeqCst);
+                }
+                bar.inc(1);
+                (file.clone(), frames as u64)
+            })
+            .collect()
+    };
+    let counts = match rayon::ThreadPoolBuilder::new()
+        .num_threads(probe_jobs.max(1))
+        .build()
+    {
+        Ok(pool) => pool.install(count),
+        Err(e) => {
+            warn!("Failed to start {} probe jobs: {}", probe_jobs, e);
+            count()
+        }
+    };
+    let cached = cached.load(Ordering::SeqCst);
+    if cached > 0 {
+        info!(
+            "Frame count from cache for {} of {} files",
+            cached,
+            files.len()
+        );
+    }
+    counts
+}
+
+impl Pipeline {
+    // Scan the input into the database and collect the files to process
+    pub fn prepare(&mut self) -> Result<(), String> {
+        // --resume-last and --resume take the files of their session instead of a scan
+        if let Some(session) = resume_session(&mut self.args)? {
+            self.prepare_resumed(session)?;
+            return Ok(());
+        }
+        let args = &mut self.args;
+        // validated by clap
+        let since = args
+            .since
+            .as_deref()
+            .map(|since| parse_since(since).unwrap());
+
+        // An explicit list of files (--input-list, repeated -i or glob patterns) bypasses the folder walk
+        let input_list = resolve_input_list(args);
+        if input_list.is_none() {
+            match input_validation(&args.inputpath[0]) {
+                Ok(path) => args.inputpath = vec![path],
+                Err(e) => {
+                    return Err(format!("{}: {}", args.inputpath[0], e));
+                }
+            }
+        }
+        if args.mirror_structure {
+            self.scan_roots = scan_roots(&args.inputpath);
+        }
+        // another run on the same files, like a cli batch on a folder the web queue also has, would
+        // write the same outputs
+        let lock_inputs = input_list.as_deref().unwrap_or(&args.inputpath[..1]);
+        self.run_lock = Some(RunLock::acquire(lock_inputs, args)?);
+
+        // Check if input is a directory, if yes, check how many video files are in it, and process the ones that are smaller than the given resolution
+        if input_list.is_some() || metadata(Path::new(&args.inputpath[0])).unwrap().is_dir() {
+            let walk_count: u64 = match &input_list {
+                Some(files) => files.len() as u64,
+                None => walk_count(&args.inputpath[0], &args.exclude) as u64,
+            };
+            let files_bar = progress_bar(walk_count);
+            let files_style =
+                "[file][{elapsed_precise}] [{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} [analyzed files] eta: {eta:<7}";
+            files_bar.set_style(ProgressStyle::default_bar().template(files_style).unwrap());
+
+            let mut vector_files = match &input_list {
+                Some(files) => files.clone(),
+                None => walk_files(&args.inputpath[0], &args.exclude),
+            };
+
+            // only probe what changed since --since
+            if let Some(since) = since {
+                let found = vector_files.len();
+                vector_files.retain(|file| modified_since(file, since));
+                info!(
+                    "{} of {} files modified since {}",
+                    vector_files.len(),
+                    found,
+                    args.since.as_deref().unwrap_or_default()
+                );
+                files_bar.set_length(vector_files.len() as u64);
+            }
+
+            let result =
+                add_to_db_with_progress(vector_files.clone(), files_bar.clone(), &|done, total| {
+                    emit_event(&ProgressEvent::ScanProgress { done, total })
+                })
+                .unwrap();
+
+            // remove items from db that don't exists anymore, for the given folder and it's subfolders
+            if input_list.is_none() {
+                remove_from_db_folder(&args.inputpath[0], args).unwrap();
+            }
+
+            // get the counters from the add_to_db function
+            let counters = result.0;
+
+            let to_process = result.1;
+            // get the vector of files to process
+            let mut vector_files_to_process = to_process.lock().unwrap().clone();
+
+            // count, db_count, db_count_added, db_count_skipped
+            let mut count = format!("{:?}", counters[0]).parse::<i32>().unwrap();
+            let db_count = format!("{:?}", counters[1]).parse::<u64>().unwrap();
+            let db_count_added = format!("{:?}", counters[2]).parse::<u64>().unwrap();
+
+            if vector_files_to_process.is_empty() {
+                // an input list is matched file by file, a folder by its path
+                let patterns = match &input_list {
+                    Some(files) => files.clone(),
+                    None => vec![format!("%{}%", args.inputpath[0])],
+                };
+                let mut retry_statuses = Vec::new();
+                if args.include_manual_skips {
+                    retry_statuses.push("skipped_manual");
+                }
+                if args.strip_dovi {
+                    retry_statuses.push("skipped_dovi");
+                }
+                if !args.skip_short {
+                    retry_statuses.push("skipped_short");
+                }
+                // checked against --min-estimated-savings again
+                retry_statuses.push("skipped_estimate");
+                let pending = db().pending_filepaths(&patterns, &retry_statuses).unwrap();
+                vector_files_to_process.extend(pending);
+            }
+            if let Some(since) = since {
+                vector_files_to_process.retain(|file| modified_since(file, since));
+            }
+
+            if count == 0 && !vector_files_to_process.is_empty() {
+                count = vector_files_to_process.len() as i32;
+                self.current_file_count =
+                    db_count.saturating_sub(vector_files_to_process.len() as u64);
+            }
+
+            self.scanned = true;
+            if input_list.is_none() {
+                self.input_folder = Some(args.inputpath[0].clone());
+            }
+            self.scan_bar = Some(files_bar);
+            self.files = vector_files_to_process;
+            self.count = count;
+            self.db_count = db_count;
+            self.db_count_added = db_count_added;
+        } else if Path::new(&args.inputpath[0]).is_file() {
+            self.files = vec![args.inputpath[0].clone()];
+            self.count = 1;
+        }
+        Ok(())
+    }
+
+    // The files of a resumed session that are not done in their order, the ones that are gone are
+    // left out
+    fn prepare_resumed(&mut self, session: BatchSession) -> Result<(), String> {
+        if self.args.mirror_structure {
+            self.scan_roots = scan_roots(&self.args.inputpath);
+        }
+        let mut files = Vec::new();
+        for file in session.files.iter().filter(|file| file.state != "done") {
+            if Path::new(&file.path).is_file() {
+                files.push(file.path.clone());
+            } else {
+                warn!(
+                    "{} of session {} is gone, leaving it out",
+                    file.path, session.id
+                );
+            }
+        }
+        self.run_lock = Some(RunLock::acquire(&files, &self.args)?);
+        if self.args.input_list.is_none()
+            && self.args.inputpath.len() == 1
+            && Path::new(&self.args.inputpath[0]).is_dir()
+        {
+            self.input_folder = Some(self.args.inputpath[0].clone());
+        }
+        self.scanned = true;
+        // the batch is counted as a whole, the done files as processed
+        self.count = session.files.len() as i32;
+        self.current_file_count = (session.files.len() - files.len()) as u64;
+        self.files = files;
+        self.session = Some(session);
+        Ok(())
+    }
+
+    // --estimate: estimate and store the savings of the files collected by prepare() instead of
+    // encoding them
+    pub fn estimate(&mut self) -> Result<Vec<SavingsEstimate>, String> {
+        if self.args.mode == "audio-only" {
+            return Err(
+                "--estimate samples the video encode, --mode audio-only copies it".to_string(),
+            );
+        }
+        set_task_state(&self.args.task_id, "running");
+        if let Some(files_bar) = self.scan_bar.take() {
+            files_bar.finish_and_clear();
+        }
+        info!("Estimating {} files...", self.files.len());
+        let estimates = estimate_files(&self.files, &self.args);
+        set_task_state(&self.args.task_id, "done");
+        Ok(estimates)
+    }
+}
@@ -0,0 +1,218 @@
+// The settings of one file of a Pipeline run: the options of the run with what the file changes in
+// them, its content tune and film grain, the folder of --mirror-structure, the filters of --tonemap
+// and --max-resolution and the VMAF target adapted to the source
+
+use crate::*;
+
+use log::{info, warn};
+use std::fs;
+
+// The options of the run with what a file changes in them
+pub(crate) struct FileSettings {
+    pub(crate) args: Args,
+    // the encoder arguments of --content-tune and --film-grain for the samples of ab-av1
+    pub(crate) ab_av1_tune_args: Vec<String>,
+    // the filter of --tonemap sdr
+    pub(crate) tonemap: Option<String>,
+    // the filter and size of --max-resolution
+    pub(crate) downscale: Option<Downscale>,
+    pub(crate) dynamic_hdr: String,
+    // --params-x265 with the arguments of --strip-dovi
+    pub(crate) params_x265: String,
+}
+
+impl Pipeline {
+    // The options of the run with what file changes in them: --content-tune, --film-grain,
+    // --mirror-structure, --tonemap, --max-resolution, the adapted VMAF target and --strip-dovi
+    pub(crate) fn file_settings(&self, file: &str) -> Result<FileSettings, FailedFile> {
+        let file = file.to_string();
+        // --content-tune and --film-grain add their encoder arguments to the params of the search
+        // and the encode alike
+        let mut tuned_args;
+        let mut ab_av1_tune_args: Vec<String> = Vec::new();
+        // audio-only copies the video
+        let tune = match self.args.mode.as_str() {
+            "audio-only" => None,
+            _ => resolve_content_tune(&file, &self.args.content_tune),
+        };
+        // --film-grain, the hardware encoder's fallback may have none
+        let film_grain = match self.args.mode.as_str() {
+            "audio-only" => None,
+            _ if !supports_film_grain(&self.args.encoder) => None,
+            _ => self
+                .args
+                .film_grain
+                .as_deref()
+                .map(|film_grain| resolve_film_grain(&file, film_grain)),
+        };
+        let args = if tune.is_some() || film_grain.is_some() {
+            tuned_args = self.args.clone();
+            if let Some(tune) = tune {
+                let detected = if self.args.content_tune == "auto" {
+                    " (auto)"
+                } else {
+                    ""
+                };
+                if tune_args(&self.args.encoder, tune).is_empty() {
+                    info!(
+                        "{}: content tune {}{}, {} is encoded with its defaults",
+                        file,
+                        tune.as_str(),
+                        detected,
+                        self.args.encoder
+                    );
+                } else {
+                    info!("{}: content tune {}{}", file, tune.as_str(), detected);
+                }
+                if let Err(e) = db().update_content_tune(&file, tune.as_str()) {
+                    warn!("Failed to store the content tune of {}: {}", file, e);
+                }
+                ab_av1_tune_args = apply_content_tune(&mut tuned_args, tune);
+            }
+            if let Some(level) = film_grain {
+                let detected = if self.args.film_grain.as_deref() == Some("auto") {
+                    " (auto)"
+                } else {
+                    ""
+                };
+                info!("{}: film grain {}{}", file, level, detected);
+                if let Err(e) = db().update_film_grain(&file, level) {
+                    warn!("Failed to store the film grain of {}: {}", file, e);
+                }
+                ab_av1_tune_args.extend(apply_film_grain(&mut tuned_args, level));
+            }
+            &tuned_args
+        } else {
+            &self.args
+        };
+
+        // --mirror-structure: the output goes to the folders of the file below its -i folder
+        let mirrored_args;
+        let args = if args.mirror_structure {
+            let folder = mirrored_output_folder(&file, &self.scan_roots, &args.outputpath);
+            fs::create_dir_all(&folder).map_err(|e| {
+                FailedFile::new(
+                    &file,
+                    "output_folder",
+                    format!("Failed to create {}: {}", folder, e),
+                )
+            })?;
+            let mut file_args = args.clone();
+            file_args.outputpath = folder;
+            mirrored_args = file_args;
+            &mirrored_args
+        } else {
+            args
+        };
+
+        // --tonemap sdr: the encode of an HDR source is 8 bit, the filter goes into the encode and
+        // the VMAF references
+        let tonemap = match args.mode.as_str() {
+            "audio-only" => None,
+            _ => tonemap_filter(&file, &args.tonemap),
+        };
+        let tonemapped_args;
+        let args = if let Some(filter) = &tonemap {
+            info!("{}: HDR, tonemapping to SDR with {}", file, filter);
+            let mut file_args = args.clone();
+            file_args.pix_fmt = TONEMAP_PIX_FMT.to_string();
+            tonemapped_args = file_args;
+            &tonemapped_args
+        } else {
+            args
+        };
+
+        // --max-resolution: the filter goes into the encode, the samples and their VMAF references
+        let downscale = match args.mode.as_str() {
+            "audio-only" => None,
+            _ => downscale(&file, args),
+        };
+        if args.max_resolution.is_some() && args.mode != "audio-only" {
+            let size = downscale
+                .as_ref()
+                .map(|downscale| (downscale.width, downscale.height))
+                .or_else(|| video_size(&file));
+            if let Some(downscale) = &downscale {
+                info!(
+                    "{}: scaling down to {} with {}",
+                    file,
+                    downscale.label(),
+                    downscale.filter
+                );
+            }
+            if let Some((width, height)) = size {
+                if let Err(e) = db().update_output_resolution(&file, width, height) {
+                    warn!("Failed to store the output resolution of {}: {}", file, e);
+                }
+            }
+        }
+
+        // a heavily compressed source is encoded at a lower VMAF target, the requested and the
+        // effective target go to its row
+        let adapted_args;
+        let args = match args.mode.as_str() {
+            "audio-only" => args,
+            _ => {
+                let adapted = adapt_vmaf_target(&file, args);
+                let effective = adapted
+                    .as_ref()
+                    .map_or(args.vmaf, |adapted| adapted.effective_vmaf);
+                if let Err(e) = db().update_vmaf_targets(&file, args.vmaf, effective) {
+                    warn!("Failed to store the VMAF targets of {}: {}", file, e);
+                }
+                match adapted {
+                    Some(adapted) => {
+                        self.record_adapted_target(adapted);
+                        let mut file_args = args.clone();
+                        file_args.vmaf = effective;
+                        adapted_args = file_args;
+                        &adapted_args
+                    }
+                    None => args,
+                }
+            }
+        };
+
+        // the video encode drops the Dolby Vision RPUs, see skip_dovi
+        let dynamic_hdr = get_dynamic_hdr(&file);
+        if let Err(e) = db().update_dynamic_hdr(&file, &dynamic_hdr) {
+            warn!("Failed to store the dynamic HDR format of {}: {}", file, e);
+        }
+        // the VMAF of a variable frame rate file is measured at its average rate
+        let vfr = get_vfr(&file);
+        if vfr {
+            let info = probe_file(&file);
+            info!(
+                "{}: variable frame rate ({:.3} fps average, {:.3} fps base), VMAF is measured at the average rate",
+                file,
+                info.avg_fps(),
+                info.fps()
+            );
+        }
+        if let Err(e) = db().update_vfr(&file, vfr) {
+            warn!("Failed to store the frame rate mode of {}: {}", file, e);
+        }
+        // files scanned before the tags were stored get them here, for the summary of the run
+        let tags = MediaTags::from_format(&probe_file(&file).format);
+        if let Err(e) = db().update_media_tags(&file, &tags) {
+            warn!("Failed to store the tags of {}: {}", file, e);
+        }
+        let mut params_x265 = args.params_x265.clone();
+        if is_dovi(&dynamic_hdr) && args.strip_dovi {
+            info!(
+                "{}: Dolby Vision ({}), --strip-dovi: the encode keeps only the HDR10 base layer",
+                file, dynamic_hdr
+            );
+            params_x265 = format!("{} {}", params_x265, STRIP_DOVI_ARGS.join(" "));
+        }
+
+        Ok(FileSettings {
+            args: args.clone(),
+            ab_av1_tune_args,
+            tonemap,
+            downscale,
+            dynamic_hdr,
+            params_x265,
+        })
+    }
+}
@@ -0,0 +1,289 @@
+// --watch: after the first pass a Pipeline keeps watching the input folder and encodes the files that
+// appear once they settled, and the files put back by --settle-seconds or a changed source are
+// retried at the end of the batch
+
+use crate::*;
+
+use colored::Colorize;
+use indicatif::ProgressBar;
+use log::{debug, error, info, warn};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+impl Pipeline {
+    // After run(): watch the input folder and process video files created or moved into it once
+    // their size stopped changing for --watch-settle seconds. The first ctrl-C finishes the
+    // current file and returns, a second one exits right away.
+    pub fn watch(&mut self, sink: &mut dyn ProgressSink) -> Result<(), String> {
+        let folder = match &self.input_folder {
+            Some(folder) => folder.clone(),
+            None => return Err("--watch needs a single input folder".to_string()),
+        };
+
+        // from now on the first ctrl-C of install_ctrlc_handler only stops the watch
+        SHIELD_CHILDREN.store(true, Ordering::SeqCst);
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|e| format!("Failed to watch {}: {}", folder, e))?;
+        watcher
+            .watch(Path::new(&folder), RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch {}: {}", folder, e))?;
+        info!("Watching {} for new video files, ctrl-C to stop", folder);
+
+        let settle = Duration::from_secs(self.args.watch_settle);
+        let excludes = ScanExcludes::load(&folder, &self.args.exclude);
+        let bars = sink.bars(0);
+        // new files with their last seen size and when it last changed
+        let mut settling: HashMap<PathBuf, (u64, Instant)> = HashMap::new();
+        while !shutdown_requested() {
+            match receiver.recv_timeout(Duration::from_secs(1)) {
+                Ok(Ok(event)) => {
+                    let created = matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+                    );
+                    for path in event.paths {
+                        if created
+                            && path.is_file()
+                            && is_video_file(&path)
+                            && !excludes.is_excluded(&path, false)
+                        {
+                            if !settling.contains_key(&path) {
+                                info!("New file {}, waiting for it to be complete", path.display());
+                            }
+                            settling.insert(path, (0, Instant::now()));
+                        }
+                    }
+                }
+                Ok(Err(e)) => warn!("Watch error: {}", e),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            // files still being copied keep growing
+            let mut ready: Vec<PathBuf> = Vec::new();
+            settling.retain(|path, (size, changed)| {
+                let current = match fs::metadata(path) {
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => {
+                        info!("{} is gone, ignoring it", path.display());
+                        return false;
+                    }
+                };
+                if current != *size {
+                    *size = current;
+                    *changed = Instant::now();
+                } else if changed.elapsed() >= settle {
+                    ready.push(path.clone());
+                    return false;
+                }
+                true
+            });
+
+            for path in ready {
+                if shutdown_requested() {
+                    break;
+                }
+                self.process_new_file(&path.display().to_string(), &bars, sink)?;
+            }
+        }
+        info!("Stopped watching {}", folder);
+        Ok(())
+    }
+
+    // Add a file found by watch() to the database and encode it when it is pending
+    fn process_new_file(
+        &mut self,
+        file: &str,
+        bars: &PipelineBars,
+        sink: &mut dyn ProgressSink,
+    ) -> Result<(), String> {
+        wait_while_paused();
+        info!("Processing new file {}", file);
+        add_to_db(vec![file.to_string()], ProgressBar::hidden())
+            .map_err(|e| format!("Failed to add {} to the database: {}", file, e))?;
+        let status = db()
+            .status(file)
+            .map_err(|e| format!("Failed to read the status of {}: {}", file, e))?
+            .unwrap_or_default();
+        if !status.contains("pending") {
+            info!("{} is {}, nothing to encode", file, status);
+            return Ok(());
+        }
+
+        // the frames bar follows this file only, like a single input file
+        let frames = trial_frame_count(file, frame_count(file) as u64, &self.args);
+        self.frame_counts = Vec::new();
+        bars.total_bar.set_length(frames);
+        bars.total_bar.set_position(0);
+        self.args.inputpath = vec![absolute_path(file)];
+        self.current_file_count = self.current_file_count + 1;
+        let job = FileJob {
+            file: file.to_string(),
+            current_file_count: self.current_file_count,
+            total_files: 1,
+        };
+        // the watch waited for the file to settle already, a change during the encode is retried
+        if let Some(deferral) = self.process_or_defer(&job, bars, sink, true) {
+            self.retry_deferred(&job, deferral, bars, sink);
+        }
+        Ok(())
+    }
+
+    // Encode the file of job, unless it was modified less than --settle-seconds ago or changed
+    // during its encode: it is then returned to be retried at the end of the batch. settled skips
+    // the first check
+    pub(crate) fn process_or_defer(
+        &mut self,
+        job: &FileJob,
+        bars: &PipelineBars,
+        sink: &mut dyn ProgressSink,
+        settled: bool,
+    ) -> Option<Deferral> {
+        let settle = Duration::from_secs(self.args.settle_seconds);
+        if !settled {
+            if let Some(remaining) = settle_remaining(&job.file, settle) {
+                warn!(
+                    "{}",
+                    format!(
+                        "{} was modified less than {}s ago and may still be written, encoding it at the end of the batch",
+                        job.file, self.args.settle_seconds
+                    )
+                    .yellow()
+                );
+                debug!("{} settles in {:.0}s", job.file, remaining.as_secs_f64());
+                return Some(Deferral::Unsettled);
+            }
+        }
+        let total_position = bars.total_bar.position();
+        sink.file_started(job);
+        let failed = match self.process_file_with_fallback(job, bars) {
+            Ok(()) => {
+                self.record_estimate_check(&job.file);
+                return None;
+            }
+            Err(failed) => failed,
+        };
+        match take_source_change(&job.file) {
+            Some(change) => {
+                if let Err(e) = set_db_status(&job.file, "source_changed") {
+                    error!("Failed to update status of {}: {}", job.file, e);
+                }
+                warn!(
+                    "{}",
+                    format!(
+                        "{} changed during its encode ({}), encoding it again at the end of the batch",
+                        job.file, change
+                    )
+                    .yellow()
+                );
+                // the retry counts its frames again
+                bars.total_bar.set_position(total_position);
+                self.source_checks.push(SourceCheck::new(
+                    &job.file,
+                    Deferral::SourceChanged,
+                    &change,
+                    "not retried",
+                ));
+                Some(Deferral::SourceChanged)
+            }
+            None => {
+                self.record_failure(failed);
+                None
+            }
+        }
+    }
+
+    // The one retry of a file process_or_defer put back, at the end of the batch. A file modified
+    // less than --settle-seconds ago is waited for first
+    pub(crate) fn retry_deferred(
+        &mut self,
+        job: &FileJob,
+        deferral: Deferral,
+        bars: &PipelineBars,
+        sink: &mut dyn ProgressSink,
+    ) {
+        let settle = Duration::from_secs(self.args.settle_seconds);
+        let mut check = match deferral {
+            // the entry of the change is completed below
+            Deferral::SourceChanged => match self
+                .source_checks
+                .iter()
+                .rposition(|check| check.path == job.file)
+            {
+                Some(index) => self.source_checks.remove(index),
+                None => SourceCheck::new(&job.file, deferral, "", "not retried"),
+            },
+            Deferral::Unsettled => SourceCheck::new(
+                &job.file,
+                deferral,
+                &format!("modified less than {}s ago", self.args.settle_seconds),
+                "not retried",
+            ),
+        };
+        // wait out the settle time since the last modification, until it stops changing
+        let mut waited = Duration::ZERO;
+        while let Some(remaining) = settle_remaining(&job.file, settle) {
+            if shutdown_requested() || waited >= settle * 2 {
+                break;
+            }
+            if waited.is_zero() {
+                info!(
+                    "Waiting up to {:.0}s for {} to settle",
+                    remaining.as_secs_f64(),
+                    job.file
+                );
+            }
+            let step = remaining.min(Duration::from_secs(1));
+            thread::sleep(step);
+            waited += step;
+        }
+        if shutdown_requested() {
+            self.source_checks.push(check);
+            return;
+        }
+        if settle_remaining(&job.file, settle).is_some() {
+            warn!(
+                "{}",
+                format!("{} is still changing, not encoding it", job.file).yellow()
+            );
+            check.outcome = "still changing".to_string();
+            self.source_checks.push(check);
+            self.record_failure(FailedFile::new(
+                &job.file,
+                "source_unstable",
+                format!(
+                    "modified again within {}s while waiting for it to settle",
+                    self.args.settle_seconds
+                ),
+            ));
+            return;
+        }
+
+        info!("Retrying {}", job.file);
+        self.args.inputpath = vec![absolute_path(&job.file)];
+        sink.file_started(job);
+        check.outcome = match self.process_file_with_fallback(job, bars) {
+            Ok(()) => {
+                self.record_estimate_check(&job.file);
+                "encoded".to_string()
+            }
+            Err(failed) => {
+                let outcome = match take_source_change(&job.file) {
+                    Some(_) => "changed again",
+                    None => "failed",
+                };
+                self.record_failure(failed);
+                outcome.to_string()
+            }
+        };
+        self.source_checks.push(check);
+    }
+}
@@ -0,0 +1,344 @@
+// ffprobe wrappers on top of the probe cache of media.rs: frame counts, durations, chapters, frame
+// rates and the HDR format, and the parsers every ffprobe value goes through
+
+use crate::*;
+
+use std::num::ParseIntError;
+
+lazy_static! {
+    static ref COUNT_VALUE: Regex = Regex::new(r"^\d+$").unwrap();
+    static ref DECIMAL_VALUE: Regex = Regex::new(r"^\d+(\.\d+)?$").unwrap();
+    static ref RATE_VALUE: Regex = Regex::new(r"^(\d+)/(\d+)$").unwrap();
+}
+
+// A size or bitrate like "2461000", anything that isn't only digits is an Err
+pub(crate) fn parse_int(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    if !COUNT_VALUE.is_match(value) {
+        return Err(format!("not an integer: {:?}", value));
+    }
+    value
+        .parse::<i64>()
+        .map_err(|e| format!("not an integer: {:?}: {}", value, e))
+}
+
+// A frame count like "64936", 0 is an Err as well, so a garbage tag doesn't win over the fallbacks
+pub(crate) fn parse_count(value: &str) -> Result<f32, String> {
+    match parse_int(value)? {
+        0 => Err("a count of 0".to_string()),
+        count => Ok(count as f32),
+    }
+}
+
+// A duration or timestamp like "2708.240000", as f32 or f64
+pub(crate) fn parse_decimal<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    let value = value.trim();
+    if !DECIMAL_VALUE.is_match(value) {
+        return Err(format!("not a number: {:?}", value));
+    }
+    value
+        .parse::<T>()
+        .map_err(|_| format!("not a number: {:?}", value))
+}
+
+// A frame rate like "24000/1001", "0/0" of an unknown rate is an Err
+pub(crate) fn parse_rate(value: &str) -> Result<f32, String> {
+    let value = value.trim();
+    let caps = RATE_VALUE
+        .captures(value)
+        .ok_or_else(|| format!("not a frame rate: {:?}", value))?;
+    let frames: f32 = caps[1].parse().unwrap_or(0.0);
+    let seconds: f32 = caps[2].parse().unwrap_or(0.0);
+    if frames > 0.0 && seconds > 0.0 {
+        Ok(frames / seconds)
+    } else {
+        Err(format!("unknown frame rate: {:?}", value))
+    }
+}
+
+// The first line of "-of default=nokey=1" output parse accepts, warnings mixed into stdout are skipped
+pub(crate) fn first_probe_value(
+    output: &str,
+    parse: fn(&str) -> Result<f32, String>,
+) -> Result<f32, String> {
+    output
+        .lines()
+        .find_map(|line| parse(line).ok())
+        .ok_or_else(|| format!("no value in the ffprobe output {:?}", output.trim()))
+}
+
+// The json of an "-of json" ffprobe call, from its first { in case anything else got into stdout,
+// Null when there is none. Its warnings are only logged at debug level
+pub(crate) fn probe_json(output: &Output, file: &str) -> Value {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        debug!("ffprobe {}: {}", file, stderr.trim());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.find('{') {
+        Some(start) => serde_json::from_str(&stdout[start..]).unwrap_or_else(|e| {
+            debug!("ffprobe {}: invalid json: {}", file, e);
+            Value::Null
+        }),
+        None => Value::Null,
+    }
+}
+
+// Output options that remove the Dolby Vision RPUs and configuration record, leaving the HDR10 base layer
+pub const STRIP_DOVI_ARGS: [&str; 2] = ["-bsf:v:0", "dovi_rpu=strip=1"];
+
+// "dovi_p7" for a video stream with a Dolby Vision configuration record, "dovi" when only the
+// codec tag (dvhe, dvh1, dav1) signals it, empty otherwise
+// "side_data_list": [{ "side_data_type": "DOVI configuration record", "dv_profile": 7, ... }]
+pub(crate) fn dynamic_hdr_from_stream(stream: &Value) -> String {
+    let record = stream["side_data_list"].as_array().and_then(|side_data| {
+        side_data
+            .iter()
+            .find(|entry| entry["side_data_type"] == "DOVI configuration record")
+    });
+    if let Some(record) = record {
+        return match record["dv_profile"].as_i64() {
+            Some(profile) => format!("dovi_p{}", profile),
+            None => "dovi".to_string(),
+        };
+    }
+    match stream["codec_tag_string"].as_str() {
+        Some("dvhe") | Some("dvh1") | Some("dav1") | Some("dva1") => "dovi".to_string(),
+        _ => String::new(),
+    }
+}
+
+// Dynamic HDR format of the video stream, see dynamic_hdr_from_stream
+pub fn get_dynamic_hdr(file: &str) -> String {
+    match probe_file(file).video_stream() {
+        Some(stream) => dynamic_hdr_from_stream(stream),
+        None => String::new(),
+    }
+}
+
+pub fn is_dovi(dynamic_hdr: &str) -> bool {
+    dynamic_hdr.starts_with("dovi")
+}
+
+// Size in bytes of the format ffprobe reports
+pub(crate) fn get_file_size(file_path: &str) -> Result<u64, ParseIntError> {
+    let info = probe_file(file_path);
+    info.format["size"].as_str().unwrap_or("").parse::<u64>()
+}
+
+pub fn get_framecount(file_path: &str) -> Result<f32, String> {
+    warn!(
+        "No frame count in the metadata of {}, counting frames by decoding the file",
+        file_path
+    );
+    let output = ffprobe_command()
+        .arg("-i")
+        .arg(file_path)
+        .arg("-v")
+        .arg("error")
+        .arg("-count_frames")
+        .arg("-select_streams")
+        .arg(probe_file(file_path).video_select())
+        .arg("-show_entries")
+        .arg("stream=nb_read_frames")
+        .arg("-of")
+        .arg("default=noprint_wrappers=1:nokey=1")
+        .output()
+        .map_err(|e| format!("failed to execute ffprobe: {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        debug!("ffprobe {}: {}", file_path, stderr.trim());
+    }
+    first_probe_value(&String::from_utf8_lossy(&output.stdout), parse_count)
+}
+
+pub fn get_framecount_tag(file_path: &str) -> Result<f32, String> {
+    let info = probe_file(file_path);
+    let stream = info.video_stream().unwrap_or(&Value::Null);
+    parse_count(stream["tags"]["NUMBER_OF_FRAMES-eng"].as_str().unwrap_or(""))
+}
+
+pub fn get_framecount_metadata(file_path: &str) -> Result<f32, String> {
+    let info = probe_file(file_path);
+    let stream = info.video_stream().unwrap_or(&Value::Null);
+    parse_count(stream["tags"]["NUMBER_OF_FRAMES"].as_str().unwrap_or(""))
+}
+
+// The stderr of ".\ffmpeg.exe -i file -map 0:v:0 -c copy -f null -" on the video stream
+fn copy_video_stream(file_path: &str) -> String {
+    let mut ffmpeg_command = ffmpeg_command()
+        .arg("-i")
+        .arg(file_path)
+        .arg("-map")
+        .arg(probe_file(file_path).video_map())
+        .arg("-c")
+        .arg("copy")
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to execute ffmpeg");
+    let _tracked = track_child(&ffmpeg_command, &[]);
+
+    // Read the output asynchronously
+    let stderr = ffmpeg_command.stderr.take().unwrap();
+    let mut stderr_reader = io::BufReader::new(stderr);
+    let mut stderr_output = String::new();
+
+    stderr_reader.read_to_string(&mut stderr_output).unwrap();
+
+    // Wait for the ffmpeg command to finish
+    ffmpeg_command.wait().expect("failed to wait for ffmpeg");
+    stderr_output
+}
+
+pub fn get_framecount_ffmpeg(file_path: &str) -> Result<f32, String> {
+    let stderr_output = copy_video_stream(file_path);
+
+    // Process the output and return the frame count "frame=64936 fps=2256 q=-1.0 Lsize=N/A time=00:45:08.24 bitrate=N/A speed=94.1x"
+    // Get the frame count from the last progress line, ffmpeg pads it like "frame=  250"
+    let framecount_str = stderr_output
+        .rsplit_once("frame=")
+        .and_then(|(_, frames)| frames.split_whitespace().next())
+        .unwrap_or("");
+    parse_count(framecount_str)
+}
+
+pub fn get_audio_details(file_path: &str) -> Result<Vec<(String, String)>, ParseFloatError> {
+    let info = probe_file(file_path);
+    let audio_details = info
+        .streams_of("audio")
+        .into_iter()
+        .map(|stream| {
+            (
+                stream["codec_name"].as_str().unwrap_or("NaN").to_string(),
+                stream["channels"].as_i64().unwrap_or(0).to_string(),
+            )
+        })
+        .collect();
+    Ok(audio_details)
+}
+
+// Function to get the video codec and resolution for each video stream, the cover art is not one
+pub fn get_video_details(
+    file_path: &str,
+) -> Result<Vec<(String, String, String)>, ParseFloatError> {
+    let info = probe_file(file_path);
+    let video_details = info
+        .streams_of("video")
+        .into_iter()
+        .filter(|stream| stream["disposition"]["attached_pic"] != 1)
+        .map(|stream| {
+            (
+                stream["codec_name"].as_str().unwrap_or("NaN").to_string(),
+                stream["width"].as_i64().unwrap_or(0).to_string(),
+                stream["height"].as_i64().unwrap_or(0).to_string(),
+            )
+        })
+        .collect();
+    Ok(video_details)
+}
+
+// Seconds of the video stream by reading it to the end, the time= of the last progress line
+// "frame=64936 fps=2256 q=-1.0 Lsize=N/A time=00:45:08.24 bitrate=N/A speed=94.1x"
+pub fn get_duration_ffmpeg(file_path: &str) -> Option<f32> {
+    warn!(
+        "No duration in the metadata of {}, reading the video stream to the end",
+        file_path
+    );
+    let stderr_output = copy_video_stream(file_path);
+    let (_, time) = stderr_output.rsplit_once("time=")?;
+    let time = time.split_whitespace().next()?;
+    time.split(':')
+        .try_fold(0.0, |seconds, part| {
+            Some(seconds * 60.0 + part.parse::<f32>().ok()?)
+        })
+        .filter(|duration| *duration > 0.0)
+}
+
+pub(crate) fn get_duration(file: &str) -> Option<f32> {
+    media_duration(file)
+}
+
+// Whether the encode deinterlaces file with yadif: --deinterlace on and off force it, auto
+// deinterlaces streams whose field order is interlaced
+pub fn should_deinterlace(file: &str, deinterlace: &str) -> bool {
+    match deinterlace {
+        "on" => true,
+        "off" => false,
+        _ => probe_file(file).is_interlaced(),
+    }
+}
+
+// Split points of file by its chapters for --chunk-by chapters, in the format of
+// run_ffmpeg_scene_change: 0, the chapter starts and the duration
+// Chapters shorter than min_length are merged into their neighbor, None when less than 2 chapters remain
+pub fn get_chapter_changes(file: &str, min_length: f32) -> Option<Vec<f32>> {
+    let info = probe_file(file);
+    let mut starts: Vec<f32> = info
+        .chapters
+        .iter()
+        .filter_map(|chapter| parse_decimal(chapter["start_time"].as_str()?).ok())
+        .collect();
+    if starts.len() < 2 {
+        return None;
+    }
+    starts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let duration = info.duration()?;
+
+    let mut chapter_changes: Vec<f32> = vec![0.0];
+    for start in starts.into_iter().skip(1) {
+        // a short chapter is merged into the one after it
+        if start - chapter_changes[chapter_changes.len() - 1] >= min_length && start < duration {
+            chapter_changes.push(start);
+        }
+    }
+    // a short last chapter is merged into the one before it
+    if chapter_changes.len() > 1
+        && duration - chapter_changes[chapter_changes.len() - 1] < min_length
+    {
+        chapter_changes.pop();
+    }
+    chapter_changes.push(duration);
+
+    if chapter_changes.len() < 3 {
+        return None;
+    }
+    Some(chapter_changes)
+}
+
+pub(crate) fn get_fps(file: &str) -> String {
+    // Get the fps of the input file
+    probe_file(file).fps().to_string()
+}
+
+pub fn get_fps_f32(file: &str) -> f32 {
+    // Get the fps of the input file
+    probe_file(file).fps()
+}
+
+pub fn get_vfr(file: &str) -> bool {
+    probe_file(file).is_vfr()
+}
+
+// The rate the VMAF filters resample both inputs to, the average rate of a variable frame rate
+// source, its r_frame_rate is only the rate its timestamps are based on
+pub(crate) fn vmaf_fps(file: &str) -> String {
+    let info = probe_file(file);
+    if info.is_vfr() {
+        info.avg_fps().to_string()
+    } else {
+        info.fps().to_string()
+    }
+}
+
+pub fn get_bitrate(file: &str) -> String {
+    // Get the bitrate of the input file, the container bitrate when the video stream has none
+    probe_file(file).bitrate_kbps().to_string()
+}
+
+#[cfg(test)]
+mod tests;
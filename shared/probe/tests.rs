@@ -0,0 +1,134 @@
+use super::*;
+
+// What ffprobe printed to stdout with default=nokey=1 on the mkv files that broke get_fps and the
+// frame counts: the warnings of the demuxer around the value
+const NOISY_COUNT: &str = "\
+[matroska,webm @ 0x55d0c3a1c2c0] Could not find codec parameters for stream 2 (Subtitle: hdmv_pgs_subtitle): unspecified size
+N/A
+64936
+";
+const NOISY_RATE: &str = "\
+25/1
+[warning] Could not find codec parameters for stream 3
+";
+
+// The json of the same files, after the warning
+const NOISY_JSON: &str = r#"[matroska,webm @ 0x55d0c3a1c2c0] Could not find codec parameters for stream 2
+{
+    "streams": [{
+        "index": 0, "codec_type": "video", "codec_name": "h264", "width": 1920, "height": 1080,
+        "r_frame_rate": "0/0", "avg_frame_rate": "25/1", "nb_frames": "64936",
+        "bit_rate": "N/A", "tags": {"NUMBER_OF_FRAMES": "1e9", "NUMBER_OF_FRAMES-eng": "0"}
+    }],
+    "format": {"duration": "2597.440000", "bit_rate": "8000000", "size": "2597440000"}
+}
+"#;
+
+#[test]
+fn integers_and_counts_are_only_digits() {
+    assert_eq!(parse_int(" 2461000\n"), Ok(2461000));
+    assert_eq!(parse_count("64936"), Ok(64936.0));
+    for value in ["", "N/A", "1e9", "-5", "25/1", "64936 [warning]", "12.5"] {
+        assert!(parse_int(value).is_err(), "{:?}", value);
+        assert!(parse_count(value).is_err(), "{:?}", value);
+    }
+    // a garbage count of 0 doesn't win over the next source
+    assert_eq!(parse_int("0"), Ok(0));
+    assert!(parse_count("0").is_err());
+}
+
+#[test]
+fn decimals_are_plain_numbers() {
+    assert_eq!(parse_decimal::<f64>("2708.240000"), Ok(2708.24));
+    assert_eq!(parse_decimal::<f32>("12"), Ok(12.0));
+    for value in ["", "N/A", "-1.5", "1e3", "inf", "NaN", "2708.24\n[warning]"] {
+        assert!(parse_decimal::<f64>(value).is_err(), "{:?}", value);
+    }
+}
+
+#[test]
+fn rates_are_fractions_of_known_rates() {
+    assert_eq!(parse_rate("25/1"), Ok(25.0));
+    assert!((parse_rate("24000/1001").unwrap() - 23.976).abs() < 0.001);
+    for value in [
+        "0/0",
+        "25/0",
+        "0/1",
+        "25",
+        "N/A",
+        "",
+        "25/1\n[warning] Could not",
+    ] {
+        assert!(parse_rate(value).is_err(), "{:?}", value);
+    }
+}
+
+#[test]
+fn the_first_valid_line_of_a_noisy_output_is_taken() {
+    assert_eq!(first_probe_value(NOISY_COUNT, parse_count), Ok(64936.0));
+    assert_eq!(first_probe_value(NOISY_RATE, parse_rate), Ok(25.0));
+    assert!(first_probe_value(
+        "N/A\n[warning] Could not find codec parameters\n",
+        parse_count
+    )
+    .is_err());
+    assert!(first_probe_value("", parse_count).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn the_json_is_read_from_its_first_brace() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let output = |stdout: &str| Output {
+        status: std::process::ExitStatus::from_raw(0),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: b"[warning] Could not find codec parameters".to_vec(),
+    };
+    let json = probe_json(&output(NOISY_JSON), "movie.mkv");
+    assert_eq!(json["streams"][0]["codec_name"], "h264");
+    assert_eq!(json["format"]["duration"], "2597.440000");
+
+    assert_eq!(
+        probe_json(&output("[warning] no json"), "movie.mkv"),
+        Value::Null
+    );
+    assert_eq!(
+        probe_json(&output("{\"streams\": ["), "movie.mkv"),
+        Value::Null
+    );
+}
+
+// The stub ffprobe: the noisy count of -count_frames, otherwise the noisy json
+#[cfg(unix)]
+fn stub_ffprobe() -> String {
+    format!(
+        "case \"$*\" in\n*-count_frames*) cat <<'EOF'\n{}EOF\n;;\n*) cat <<'EOF'\n{}EOF\n;;\nesac\n",
+        NOISY_COUNT, NOISY_JSON
+    )
+}
+
+#[cfg(unix)]
+#[test]
+fn garbage_values_of_a_noisy_probe_fall_back() {
+    let file = temp_path("noisy.mkv");
+    fs::write(&file, b"video").unwrap();
+    let file = file.to_string_lossy().to_string();
+    with_stub("FFPROBE_BIN", &stub_ffprobe(), || {
+        let info = probe_file(&file);
+        assert_eq!(info.video_map(), "0:0");
+        // the garbage tags give way to nb_frames
+        assert!(get_framecount_tag(&file).is_err());
+        assert!(get_framecount_metadata(&file).is_err());
+        assert_eq!(info.frame_count(), Some(64936.0));
+        // an unknown rate is 0, the bitrate of the stream gives way to the one of the format
+        assert_eq!(get_fps_f32(&file), 0.0);
+        assert_eq!(info.avg_fps(), 25.0);
+        assert_eq!(get_bitrate(&file), "8000");
+        assert_eq!(get_file_size(&file), Ok(2597440000));
+        assert_eq!(get_duration(&file), Some(2597.44));
+
+        // the frames counted by decoding skip the warning and the N/A
+        assert_eq!(get_framecount(&file), Ok(64936.0));
+    });
+}
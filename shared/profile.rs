@@ -0,0 +1,187 @@
+// Saved argument presets: --save-profile writes the current settings as toml, --profile loads
+// them again with the options given on the command line taking precedence
+
+use crate::*;
+
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use log::warn;
+use std::ffi::OsString;
+
+// Not stored in a profile: the paths and task of a run, the one-off actions and the profile options
+const PROFILE_EXCLUDED: [&str; 11] = [
+    "inputpath",
+    "input_list",
+    "outputpath",
+    "output_folder",
+    "task_id",
+    "db_export",
+    "db_import",
+    "profile",
+    "save_profile",
+    "list_profiles",
+    "command",
+];
+
+// A saved profile, settings are the stored options by their Args field name
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    pub name: String,
+    pub settings: Value,
+}
+
+// ~/.config/transrustica/profiles on linux, %APPDATA%\transrustica\profiles on windows
+pub fn profiles_dir() -> Result<PathBuf, String> {
+    dirs::config_dir()
+        .map(|dir| dir.join("transrustica").join("profiles"))
+        .ok_or_else(|| "No config directory found for the profiles".to_string())
+}
+
+fn profile_path(name: &str) -> Result<PathBuf, String> {
+    Ok(profiles_dir()?.join(format!("{}.toml", name)))
+}
+
+// Write the settings of args to the profile name, returns the path of the profile
+pub fn save_profile(name: &str, args: &Args) -> Result<PathBuf, String> {
+    let mut settings = match serde_json::to_value(args) {
+        Ok(Value::Object(settings)) => settings,
+        Ok(_) => return Err("Failed to serialize the arguments".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    // unset options keep their default, toml has no null
+    settings.retain(|key, value| !PROFILE_EXCLUDED.contains(&key.as_str()) && !value.is_null());
+    // --target-size conflicts with --vmaf, the vmaf in args is only its default then
+    if settings.contains_key("target_size") {
+        settings.remove("vmaf");
+    }
+
+    let toml = toml::to_string(&settings).map_err(|e| e.to_string())?;
+    let path = profile_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, toml).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn load_profile(name: &str) -> Result<Profile, String> {
+    let path = profile_path(name)?;
+    let toml = fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "Failed to read profile {} ({}): {}",
+            name,
+            path.display(),
+            e
+        )
+    })?;
+    let table: toml::Table =
+        toml::from_str(&toml).map_err(|e| format!("Profile {} is not valid toml: {}", name, e))?;
+    let settings = serde_json::to_value(table).map_err(|e| e.to_string())?;
+    Ok(Profile {
+        name: name.to_string(),
+        settings,
+    })
+}
+
+// All saved profiles that can be read, sorted by name
+pub fn list_profiles() -> Result<Vec<Profile>, String> {
+    let dir = profiles_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "toml")
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    Ok(names
+        .iter()
+        .filter_map(|name| match load_profile(name) {
+            Ok(profile) => Some(profile),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+// Parse the command line and apply --profile
+// The profile options are appended to the command line as --option=value unless the option was
+// given there, so clap validates them and the command line wins
+pub fn parse_args() -> Result<Args, String> {
+    let command_line: Vec<OsString> = env::args_os().collect();
+    let matches = Args::command().get_matches_from(command_line.clone());
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let name = match &args.profile {
+        Some(name) => name.clone(),
+        None => return Ok(args),
+    };
+
+    let profile = load_profile(&name)?;
+    let command = Args::command();
+    let known: Vec<String> = command
+        .get_arguments()
+        .map(|arg| arg.get_id().to_string())
+        .collect();
+    let mut command_line = command_line;
+    if let Value::Object(settings) = profile.settings {
+        for (key, value) in settings {
+            if PROFILE_EXCLUDED.contains(&key.as_str()) {
+                continue;
+            }
+            if !known.contains(&key) {
+                return Err(format!("Profile {} has an unknown option {}", name, key));
+            }
+            if matches.value_source(&key) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            // the command line also wins over options that conflict with it, like --vmaf and --target-size
+            let conflicts = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == key.as_str())
+                .map_or(false, |arg| {
+                    command.get_arg_conflicts_with(arg).iter().any(|other| {
+                        matches.value_source(other.get_id().as_str())
+                            == Some(ValueSource::CommandLine)
+                    })
+                });
+            if conflicts {
+                continue;
+            }
+            let flag = format!("--{}", key.replace('_', "-"));
+            let values = match value {
+                Value::Array(values) => values,
+                value => vec![value],
+            };
+            for value in values {
+                match value {
+                    Value::Bool(true) => command_line.push(flag.clone().into()),
+                    Value::Bool(false) | Value::Null => {}
+                    Value::String(value) => command_line.push(format!("{}={}", flag, value).into()),
+                    value => command_line.push(format!("{}={}", flag, value).into()),
+                }
+            }
+        }
+    }
+
+    Args::try_parse_from(command_line)
+        .map_err(|e| format!("Profile {} has an invalid value: {}", name, e))
+}
+
+// --profile and --save-profile: letters, digits, - and _
+pub(crate) fn profile_name_validation(s: &str) -> Result<String, String> {
+    let re = Regex::new(r"^[A-Za-z0-9_-]+$").unwrap();
+    if re.is_match(s) {
+        Ok(s.to_string())
+    } else {
+        Err(String::from_str("profile names may only contain letters, digits, - and _").unwrap())
+    }
+}
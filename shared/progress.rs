@@ -0,0 +1,564 @@
+// Progress of the running task for the terminal bars and GET /progress: the WEB_*_STATIC values
+// and the logging setup. The scan progress is in scan_progress.rs, the task logs in task_log.rs
+
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Progress {
+    pub id: String,
+    fps: u64,
+    frame: u64,
+    frames: f32,
+    percentage: f32,
+    eta: String,
+    // running or paused
+    #[serde(default)]
+    pub state: String,
+    // the expected output size in bytes
+    size: u64,
+    current_file_count: u64,
+    total_files: u64,
+    current_file_name: String,
+    #[serde(default)]
+    ffmpeg_version: String,
+    // older clients don't send the codec fields, they are left at their defaults
+    #[serde(flatten)]
+    pub codecs: ProgressCodecs,
+    // the stage and last tool output line of the last failed file of the task
+    #[serde(default)]
+    pub error: Option<String>,
+    // crf_search while the CRF of the file is searched, encode once ffmpeg runs, empty before the
+    // first file. phase_percentage is the progress of the phase
+    #[serde(default)]
+    pub phase: String,
+    #[serde(default)]
+    pub phase_percentage: f32,
+    #[serde(default)]
+    pub crf_search: Option<CrfSearchProgress>,
+    // the scenes of a chunked encode, phase and phase_percentage follow its phase
+    #[serde(default)]
+    pub chunked: Option<ChunkedProgress>,
+}
+
+// The CRF search of the file being encoded, the vmaf bar shows the same
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct CrfSearchProgress {
+    // lower than requested_vmaf when no CRF reached the requested target and the search is retried
+    pub vmaf_target: f32,
+    pub requested_vmaf: f32,
+    // the CRF being tried, None until ab-av1 shows it
+    pub crf: Option<f32>,
+    pub sample: u32,
+    pub samples: u32,
+    pub percentage: f32,
+}
+
+impl CrfSearchProgress {
+    pub fn new(vmaf_target: f32, requested_vmaf: f32) -> CrfSearchProgress {
+        CrfSearchProgress {
+            vmaf_target,
+            requested_vmaf,
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for CrfSearchProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "VMAF {}", self.vmaf_target)?;
+        if self.vmaf_target != self.requested_vmaf {
+            write!(f, " (lowered from {})", self.requested_vmaf)?;
+        }
+        if let Some(crf) = self.crf {
+            write!(f, ", crf {}", crf)?;
+        }
+        if self.samples > 0 {
+            write!(f, ", sample {}/{}", self.sample, self.samples)?;
+        }
+        write!(f, ", {:.0}%", self.percentage)
+    }
+}
+
+// The steps of concatenating the scenes of a chunked encode, a --stream-concat run appended them
+// as they finished and starts at verify. sync is left out by --sync-check off
+pub const CONCAT_STEPS: [&str; 4] = ["concat", "verify", "mux", "sync"];
+
+// The chunked encode of the file being encoded, from its scene detection until its scenes are
+// concatenated. The scenes are searched and encoded side by side, the phase is crf_search until the
+// first scene is encoded
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ChunkedProgress {
+    // scene_detect, crf_search, encode or concat
+    pub phase: String,
+    // the part of the file the scene detection went through
+    pub scene_detect_percentage: f32,
+    pub total_scenes: usize,
+    pub searched_scenes: usize,
+    pub encoded_scenes: usize,
+    // the scenes being encoded right now
+    pub encoding: Vec<EncodingScene>,
+    // the frames of the encoded scenes and of the ones being encoded, a resumed run starts with the
+    // frames of the scenes it found
+    pub frames_done: u64,
+    pub total_frames: u64,
+    // bytes, the output at the reduction of the encoded scenes
+    pub estimated_size: u64,
+    // percent of the source size the encoded scenes saved, negative when they came out larger
+    pub reduction: f64,
+    // one of CONCAT_STEPS while the scenes are concatenated
+    pub concat_step: String,
+    // when the scenes started encoding and the frames done then, for the fps
+    #[serde(skip)]
+    started: Option<(Instant, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct EncodingScene {
+    pub index: i32,
+    pub crf: f32,
+    // encoded at the CRF of --fast-scene-threshold
+    pub fast: bool,
+}
+
+impl ChunkedProgress {
+    // The progress of the phase in percent
+    pub fn percentage(&self) -> f32 {
+        let share = |done: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                (done as f32 / total as f32 * 100.0).min(100.0)
+            }
+        };
+        match self.phase.as_str() {
+            "scene_detect" => self.scene_detect_percentage,
+            "crf_search" => share(self.searched_scenes, self.total_scenes),
+            "encode" => share(self.frames_done as usize, self.total_frames as usize),
+            "concat" => share(
+                CONCAT_STEPS
+                    .iter()
+                    .position(|step| *step == self.concat_step)
+                    .unwrap_or(0),
+                CONCAT_STEPS.len(),
+            ),
+            _ => 0.0,
+        }
+    }
+
+    // The frames per second of the scene encodes of this run, all of them together
+    fn fps(&self) -> u64 {
+        match self.started {
+            Some((started, frames)) => {
+                let seconds = started.elapsed().as_secs_f64();
+                if seconds < 1.0 {
+                    0
+                } else {
+                    (self.frames_done.saturating_sub(frames) as f64 / seconds) as u64
+                }
+            }
+            None => 0,
+        }
+    }
+}
+
+// What happens to the streams of the file being encoded, the cli shows the same in the codec bar
+// A copied video stream has the same codec in video_codec_from and video_codec_to, and no crf
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ProgressCodecs {
+    pub video_codec_from: String,
+    pub video_codec_to: String,
+    pub audio_codecs_from: Vec<String>,
+    pub audio_codecs_to: Vec<String>,
+    // bytes
+    pub input_size: u64,
+    pub crf: Option<f32>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ProgressScan {
+    pub count: u64,
+    pub total: u64,
+    // walking, probing, removing or done
+    pub phase: String,
+    pub found: u64,
+    pub present: u64,
+    pub added: u64,
+    pub removed: u64,
+    pub errors: u64,
+    #[serde(skip)]
+    pub paths: Vec<String>,
+}
+
+// Global variable to store the latest FFmpeg output
+lazy_static! {
+    pub static ref WEB_TASK_ID_STATIC: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
+    pub static ref WEB_PAGE_STATIC: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
+    pub static ref WEB_FPS_STATIC: Arc<Mutex<u64>> = Arc::new(Mutex::new(u64::MAX));
+    pub static ref WEB_CURRENT_FRAME_STATIC: Arc<Mutex<u64>> = Arc::new(Mutex::new(u64::MAX));
+    pub static ref WEB_TOTAL_FRAME_STATIC: Arc<Mutex<f32>> = Arc::new(Mutex::new(f32::MAX));
+    pub static ref WEB_EXPECTED_SIZE_STATIC: Arc<Mutex<u64>> = Arc::new(Mutex::new(u64::MAX));
+    pub static ref WEB_CURRENT_FILE_STATIC: Arc<Mutex<u64>> = Arc::new(Mutex::new(u64::MAX));
+    pub static ref WEB_TOTAL_FILES_STATIC: Arc<Mutex<u64>> = Arc::new(Mutex::new(u64::MAX));
+    pub static ref WEB_CURRENT_FILE_NAME_STATIC: Arc<Mutex<String>> =
+        Arc::new(Mutex::new(String::new()));
+    pub static ref WEB_CODECS_STATIC: Arc<Mutex<ProgressCodecs>> =
+        Arc::new(Mutex::new(ProgressCodecs::default()));
+    // Set while the CRF of the current file is searched
+    pub static ref WEB_CRF_SEARCH_STATIC: Arc<Mutex<Option<CrfSearchProgress>>> =
+        Arc::new(Mutex::new(None));
+    // Set while the current file is encoded in chunked mode
+    pub static ref WEB_CHUNKED_STATIC: Arc<Mutex<Option<ChunkedProgress>>> =
+        Arc::new(Mutex::new(None));
+    pub static ref WEB_SCAN_COUNT_STATIC: Arc<Mutex<u64>> = Arc::new(Mutex::new(u64::MAX));
+    pub static ref WEB_SCAN_TOTAL_STATIC: Arc<Mutex<u64>> = Arc::new(Mutex::new(u64::MAX));
+    // End of the ffmpeg/ab-av1 output of each transcode task, by task id
+    pub static ref WEB_TASK_LOGS_STATIC: Arc<Mutex<HashMap<String, TaskLog>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+    // Progress of each scan task started from the web, by scan id
+    pub static ref WEB_SCANS_STATIC: Arc<Mutex<HashMap<String, ProgressScan>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+    // Progress bars added here are suspended while a log line is written
+    pub static ref LOG_PROGRESS: MultiProgress = MultiProgress::new();
+}
+
+// Define a struct to hold the progress of each transcode task
+pub struct TranscodeProgress {
+    pub task_id: String,
+    pub current_frame: u64,
+    pub total_frame: f32,
+    pub fps: u64,
+}
+
+// Set up the log facade, log lines go through LOG_PROGRESS so they don't mangle the progress bars
+// Colors are disabled when stdout is not a terminal, or when logging to a file
+pub fn init_logging(log_level: &str, log_file: &Option<String>) {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(log::LevelFilter::from_str(log_level).unwrap_or(log::LevelFilter::Info));
+    builder.format_timestamp_secs();
+
+    if !io::stdout().is_terminal() {
+        colored::control::set_override(false);
+        builder.write_style(env_logger::WriteStyle::Never);
+    }
+
+    if let Some(log_file) = log_file {
+        match fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)
+        {
+            Ok(file) => {
+                colored::control::set_override(false);
+                builder.write_style(env_logger::WriteStyle::Never);
+                builder.target(env_logger::Target::Pipe(Box::new(file)));
+            }
+            Err(e) => eprintln!("Failed to open log file {}: {}", log_file, e),
+        }
+    }
+
+    let logger = builder.build();
+    let level = logger.filter();
+    if LogWrapper::new(LOG_PROGRESS.clone(), logger)
+        .try_init()
+        .is_ok()
+    {
+        log::set_max_level(level);
+    }
+}
+
+// Publish the codecs of the file being encoded for /progress, encoder is None when the video is copied
+pub(crate) fn set_progress_codecs(
+    vec_video_args: &[(usize, String, String, String)],
+    encoder: Option<&str>,
+    audio_plans: &[AudioPlan],
+    input_size: u64,
+    crf: Option<f32>,
+) {
+    let video_codec_from = vec_video_args
+        .first()
+        .map(|(_, codec, _, _)| codec.clone())
+        .unwrap_or_default();
+    let video_codec_to = match encoder {
+        Some(encoder) => encoder_codec(encoder).to_string(),
+        None => video_codec_from.clone(),
+    };
+    *WEB_CODECS_STATIC.lock().unwrap() = ProgressCodecs {
+        video_codec_from,
+        video_codec_to,
+        audio_codecs_from: audio_plans
+            .iter()
+            .map(|plan| plan.original_codec.clone())
+            .collect(),
+        audio_codecs_to: audio_plans
+            .iter()
+            .map(|plan| plan.target_codec.clone())
+            .collect(),
+        input_size,
+        crf,
+    };
+}
+
+// Show the chunked encode of file on /progress, from its scene detection on
+pub(crate) fn start_chunked_progress(
+    task_id: &str,
+    current_file_count: u64,
+    total_files: i32,
+    file: &str,
+) {
+    *WEB_TASK_ID_STATIC.lock().unwrap() = task_id.to_string();
+    *WEB_FPS_STATIC.lock().unwrap() = 0;
+    *WEB_CURRENT_FRAME_STATIC.lock().unwrap() = 0;
+    *WEB_TOTAL_FRAME_STATIC.lock().unwrap() = 0.0;
+    *WEB_EXPECTED_SIZE_STATIC.lock().unwrap() = 0;
+    *WEB_CURRENT_FILE_STATIC.lock().unwrap() = current_file_count;
+    *WEB_TOTAL_FILES_STATIC.lock().unwrap() = total_files as u64;
+    *WEB_CURRENT_FILE_NAME_STATIC.lock().unwrap() = file.to_string();
+    *WEB_CHUNKED_STATIC.lock().unwrap() = Some(ChunkedProgress {
+        phase: "scene_detect".to_string(),
+        ..Default::default()
+    });
+}
+
+// Update the chunked encode on /progress, its frame, frames, fps and size follow it
+// Nothing happens outside of a chunked encode, like for the scene detection of --estimate
+pub(crate) fn update_chunked_progress(update: impl FnOnce(&mut ChunkedProgress)) {
+    let (frames_done, total_frames, estimated_size, fps) = {
+        let mut chunked = WEB_CHUNKED_STATIC.lock().unwrap();
+        let chunked = match chunked.as_mut() {
+            Some(chunked) => chunked,
+            None => return,
+        };
+        update(chunked);
+        if chunked.phase == "encode" && chunked.started.is_none() {
+            chunked.started = Some((Instant::now(), chunked.frames_done));
+        }
+        (
+            chunked.frames_done,
+            chunked.total_frames,
+            chunked.estimated_size,
+            chunked.fps(),
+        )
+    };
+    // get_progress_web holds these while it reads WEB_CHUNKED_STATIC, so they are set after
+    *WEB_FPS_STATIC.lock().unwrap() = fps;
+    *WEB_CURRENT_FRAME_STATIC.lock().unwrap() = frames_done;
+    *WEB_TOTAL_FRAME_STATIC.lock().unwrap() = total_frames as f32;
+    *WEB_EXPECTED_SIZE_STATIC.lock().unwrap() = estimated_size;
+}
+
+// Move the chunked encode on /progress to step of CONCAT_STEPS
+pub(crate) fn set_concat_step(step: &str) {
+    update_chunked_progress(|chunked| {
+        chunked.phase = "concat".to_string();
+        chunked.concat_step = step.to_string();
+    });
+}
+
+// The chunked encode ended
+pub(crate) fn clear_chunked_progress() {
+    *WEB_CHUNKED_STATIC.lock().unwrap() = None;
+}
+
+// Remaining time of the current file as H:MM:SS, empty until ffmpeg reports an fps
+// ffmpeg reports no frames while the encode is paused, so the eta holds until it is resumed
+fn calculate_eta(fps: u64, frame: u64, frames: f32) -> String {
+    if fps == 0 || fps == u64::MAX || frames <= frame as f32 {
+        return "".to_string();
+    }
+    let seconds = ((frames - frame as f32) / fps as f32) as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+fn progress_state(task_id: &str) -> String {
+    if is_task_paused(task_id) {
+        "paused"
+    } else {
+        "running"
+    }
+    .to_string()
+}
+
+// The phase of the current file and its percentage, percentage is the encode's, a chunked encode
+// has its own phases
+fn progress_phase(started: bool, percentage: f32) -> (String, f32, Option<CrfSearchProgress>) {
+    if let Some(chunked) = WEB_CHUNKED_STATIC.lock().unwrap().as_ref() {
+        return (chunked.phase.clone(), chunked.percentage(), None);
+    }
+    match WEB_CRF_SEARCH_STATIC.lock().unwrap().clone() {
+        Some(crf_search) => (
+            "crf_search".to_string(),
+            crf_search.percentage,
+            Some(crf_search),
+        ),
+        None if started => (
+            "encode".to_string(),
+            if percentage.is_finite() {
+                percentage
+            } else {
+                0.0
+            },
+            None,
+        ),
+        None => (String::new(), 0.0, None),
+    }
+}
+
+pub fn get_progress_web() -> Progress {
+    // Mock data for demonstration purposes
+    // In a real-world scenario, replace this with actual data from your transcoding process
+
+    // Simulate FPS based on current second
+    let task_id = WEB_TASK_ID_STATIC.lock().unwrap();
+    let fps = WEB_FPS_STATIC.lock().unwrap();
+    let frame = WEB_CURRENT_FRAME_STATIC.lock().unwrap();
+    let frames = WEB_TOTAL_FRAME_STATIC.lock().unwrap();
+    let percentage = (*frame as f32 / *frames as f32) * 100.0;
+    let size = WEB_EXPECTED_SIZE_STATIC.lock().unwrap();
+    // Calculate eta based on fps and frame/frames
+    let eta = calculate_eta(*fps, *frame, *frames);
+    let state = progress_state(&task_id);
+    let (phase, phase_percentage, crf_search) = progress_phase(*fps != u64::MAX, percentage);
+
+    let current_file_count = WEB_CURRENT_FILE_STATIC.lock().unwrap();
+    let total_files = WEB_TOTAL_FILES_STATIC.lock().unwrap();
+    let current_file_name = WEB_CURRENT_FILE_NAME_STATIC.lock().unwrap();
+    let stem_filename_ = current_file_name.clone();
+
+    // Get the stem of the file
+    let stem_filename = Path::new(&stem_filename_)
+        .file_stem()
+        .unwrap_or(std::ffi::OsStr::new("Unknown"))
+        .to_str()
+        .unwrap_or("Invalid UTF-8");
+
+    // If values are MAX of each type, set values in Progress to 0
+    if *fps == u64::MAX {
+        return Progress {
+            id: "".to_string(),
+            fps: 0,
+            frame: 0,
+            frames: 0.0,
+            percentage: 0.0,
+            eta: eta,
+            state,
+            size: 0,
+            current_file_count: 0,
+            total_files: 0,
+            current_file_name: "Unknown".to_string(),
+            ffmpeg_version: ffmpeg_capabilities().version.clone(),
+            codecs: ProgressCodecs::default(),
+            error: task_error(&task_id),
+            phase,
+            phase_percentage,
+            crf_search,
+            chunked: None,
+        };
+    } else {
+        Progress {
+            id: task_id.to_string(),
+            fps: *fps,
+            frame: *frame,
+            frames: *frames,
+            percentage: percentage,
+            eta: eta,
+            state,
+            size: *size,
+            current_file_count: *current_file_count,
+            total_files: *total_files,
+            current_file_name: stem_filename.to_string(),
+            ffmpeg_version: ffmpeg_capabilities().version.clone(),
+            codecs: WEB_CODECS_STATIC.lock().unwrap().clone(),
+            error: task_error(&task_id),
+            phase,
+            phase_percentage,
+            crf_search,
+            chunked: WEB_CHUNKED_STATIC.lock().unwrap().clone(),
+        }
+    }
+}
+
+pub fn get_progress_web_id(id: String) -> Progress {
+    // Mock data for demonstration purposes
+    // In a real-world scenario, replace this with actual data from your transcoding process
+
+    // Simulate FPS based on current second
+    let task_id = id;
+    let fps = WEB_FPS_STATIC.lock().unwrap();
+    let frame = WEB_CURRENT_FRAME_STATIC.lock().unwrap();
+    let frames = WEB_TOTAL_FRAME_STATIC.lock().unwrap();
+    let percentage = (*frame as f32 / *frames as f32) * 100.0;
+    let size = WEB_EXPECTED_SIZE_STATIC.lock().unwrap();
+    // Calculate eta based on fps and frame/frames
+    let eta = calculate_eta(*fps, *frame, *frames);
+    let state = progress_state(&task_id);
+    let (phase, phase_percentage, crf_search) = progress_phase(*fps != u64::MAX, percentage);
+
+    let current_file_count = WEB_CURRENT_FILE_STATIC.lock().unwrap();
+    let total_files = WEB_TOTAL_FILES_STATIC.lock().unwrap();
+    let current_file_name = WEB_CURRENT_FILE_NAME_STATIC.lock().unwrap();
+    let stem_filename_ = current_file_name.clone();
+
+    // Get the stem of the file
+    let stem_filename = Path::new(&stem_filename_)
+        .file_stem()
+        .unwrap_or(std::ffi::OsStr::new("Unknown"))
+        .to_str()
+        .unwrap_or("Invalid UTF-8");
+
+    // If values are MAX of each type, set values in Progress to 0
+    if *fps == u64::MAX {
+        return Progress {
+            id: "".to_string(),
+            fps: 0,
+            frame: 0,
+            frames: 0.0,
+            percentage: 0.0,
+            eta: eta,
+            state,
+            size: 0,
+            current_file_count: 0,
+            total_files: 0,
+            current_file_name: "Unknown".to_string(),
+            ffmpeg_version: ffmpeg_capabilities().version.clone(),
+            codecs: ProgressCodecs::default(),
+            error: task_error(&task_id),
+            phase,
+            phase_percentage,
+            crf_search,
+            chunked: None,
+        };
+    } else {
+        Progress {
+            id: task_id.to_string(),
+            fps: *fps,
+            frame: *frame,
+            frames: *frames,
+            percentage: percentage,
+            eta: eta,
+            state,
+            size: *size,
+            current_file_count: *current_file_count,
+            total_files: *total_files,
+            current_file_name: stem_filename.to_string(),
+            ffmpeg_version: ffmpeg_capabilities().version.clone(),
+            codecs: WEB_CODECS_STATIC.lock().unwrap().clone(),
+            error: task_error(&task_id),
+            phase,
+            phase_percentage,
+            crf_search,
+            chunked: WEB_CHUNKED_STATIC.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
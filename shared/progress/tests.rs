@@ -0,0 +1,113 @@
+use super::*;
+
+// What GET /progress returned before the codecs, the error, the phases and the chunked encodes
+const OLD_PROGRESS: &str = r#"{
+    "id": "task-1",
+    "fps": 48,
+    "frame": 1200,
+    "frames": 48000.0,
+    "percentage": 2.5,
+    "eta": "16m 15s",
+    "size": 1073741824,
+    "current_file_count": 3,
+    "total_files": 12,
+    "current_file_name": "/media/Movie (2020)/Movie.mkv"
+}"#;
+
+#[test]
+fn an_old_progress_still_parses() {
+    let progress: Progress = serde_json::from_str(OLD_PROGRESS).unwrap();
+    assert_eq!(progress.id, "task-1");
+    assert_eq!(progress.frame, 1200);
+    assert_eq!(progress.current_file_name, "/media/Movie (2020)/Movie.mkv");
+    // the fields added since are left at their defaults
+    assert_eq!(progress.state, "");
+    assert_eq!(progress.ffmpeg_version, "");
+    assert_eq!(progress.codecs.video_codec_from, "");
+    assert_eq!(progress.codecs.video_codec_to, "");
+    assert!(progress.codecs.audio_codecs_from.is_empty());
+    assert!(progress.codecs.audio_codecs_to.is_empty());
+    assert_eq!(progress.codecs.input_size, 0);
+    assert_eq!(progress.codecs.crf, None);
+    assert_eq!(progress.error, None);
+    assert_eq!(progress.phase, "");
+    assert_eq!(progress.phase_percentage, 0.0);
+    assert!(progress.crf_search.is_none());
+    assert!(progress.chunked.is_none());
+}
+
+#[test]
+fn the_codecs_are_fields_of_the_progress() {
+    let mut progress: Progress = serde_json::from_str(OLD_PROGRESS).unwrap();
+    progress.codecs = ProgressCodecs {
+        video_codec_from: "h264".to_string(),
+        video_codec_to: "hevc".to_string(),
+        audio_codecs_from: vec!["dts".to_string(), "aac".to_string()],
+        audio_codecs_to: vec!["opus".to_string(), "copy".to_string()],
+        input_size: 4_000_000_000,
+        crf: Some(24.5),
+    };
+    let json = serde_json::to_value(&progress).unwrap();
+    assert!(json.get("codecs").is_none());
+    assert_eq!(json["video_codec_from"], "h264");
+    assert_eq!(json["video_codec_to"], "hevc");
+    assert_eq!(json["audio_codecs_from"], serde_json::json!(["dts", "aac"]));
+    assert_eq!(json["audio_codecs_to"], serde_json::json!(["opus", "copy"]));
+    assert_eq!(json["input_size"], 4_000_000_000u64);
+    assert_eq!(json["crf"], 24.5);
+
+    let parsed: Progress = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed.codecs.audio_codecs_from, ["dts", "aac"]);
+    assert_eq!(parsed.codecs.crf, Some(24.5));
+}
+
+#[test]
+fn a_progress_with_some_of_the_codecs_parses() {
+    let mut json: serde_json::Value = serde_json::from_str(OLD_PROGRESS).unwrap();
+    json["video_codec_from"] = "h264".into();
+    json["crf"] = serde_json::Value::Null;
+    let progress: Progress = serde_json::from_value(json).unwrap();
+    assert_eq!(progress.codecs.video_codec_from, "h264");
+    assert_eq!(progress.codecs.video_codec_to, "");
+    assert!(progress.codecs.audio_codecs_to.is_empty());
+    assert_eq!(progress.codecs.crf, None);
+}
+
+#[test]
+fn set_progress_codecs_lists_each_audio_stream() {
+    let plan = |index: usize, from: &str, to: &str| AudioPlan {
+        index,
+        output_index: index,
+        args: String::new(),
+        original_codec: from.to_string(),
+        target_codec: to.to_string(),
+    };
+    let video = [(0, "h264".to_string(), String::new(), String::new())];
+    let plans = [plan(0, "dts", "opus"), plan(1, "aac", "copy")];
+
+    set_progress_codecs(&video, Some("libx265"), &plans, 1000, Some(22.0));
+    let codecs = WEB_CODECS_STATIC.lock().unwrap().clone();
+    assert_eq!(
+        (
+            codecs.video_codec_from.as_str(),
+            codecs.video_codec_to.as_str()
+        ),
+        ("h264", "hevc")
+    );
+    assert_eq!(codecs.audio_codecs_from, ["dts", "aac"]);
+    assert_eq!(codecs.audio_codecs_to, ["opus", "copy"]);
+    assert_eq!((codecs.input_size, codecs.crf), (1000, Some(22.0)));
+
+    // a copied video keeps its codec and has no crf
+    set_progress_codecs(&video, None, &[], 1000, None);
+    let codecs = WEB_CODECS_STATIC.lock().unwrap().clone();
+    assert_eq!(
+        (
+            codecs.video_codec_from.as_str(),
+            codecs.video_codec_to.as_str()
+        ),
+        ("h264", "h264")
+    );
+    assert!(codecs.audio_codecs_to.is_empty());
+    assert_eq!(codecs.crf, None);
+}
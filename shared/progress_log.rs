@@ -0,0 +1,111 @@
+// The resume logs of the chunked mode: done.txt with the encoded scenes and searched.txt with the
+// CRF each scene got
+
+use crate::*;
+
+// Serializes line writes from the chunked threadpool workers into a single file
+// (done.txt, chunks.txt, debug log), so concurrent appends never interleave
+pub struct ProgressLog {
+    file: Mutex<File>,
+}
+
+impl ProgressLog {
+    pub fn open(path: &str) -> Result<ProgressLog, io::Error> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(ProgressLog {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn write_line(&self, line: &str) -> Result<(), io::Error> {
+        // Build the full line first so it reaches the file in a single write
+        let line = format!("{}\n", line);
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+}
+
+// Read the scene indices from a done.txt resume file
+// Malformed lines are skipped with a warning and repeated indices are only returned once
+pub fn read_done_indices(path: &str) -> Result<Vec<i32>, io::Error> {
+    let content = fs::read_to_string(path)?;
+    let mut seen = std::collections::HashSet::new();
+    let mut indices = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse::<i32>() {
+            Ok(index) => {
+                if seen.insert(index) {
+                    indices.push(index);
+                }
+            }
+            Err(_) => {
+                warn!(
+                    "Skipping malformed line {} in {}: {:?}",
+                    line_number + 1,
+                    path,
+                    line
+                );
+            }
+        }
+    }
+    Ok(indices)
+}
+
+// Read the "index crf vmaf [path]" lines from a searched.txt resume file, with whether the scene took
+// the fast path, lines of older runs have no path
+// Malformed lines are skipped with a warning and only the first result per scene is kept
+pub fn read_searched_scenes(path: &str) -> Result<Vec<(i32, f32, f32, bool)>, io::Error> {
+    let content = fs::read_to_string(path)?;
+    let mut seen = std::collections::HashSet::new();
+    let mut scenes = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let parsed = match parts.as_slice() {
+            [index, crf, vmaf, scene_path @ ..] if scene_path.len() <= 1 => match (
+                index.parse::<i32>(),
+                crf.parse::<f32>(),
+                vmaf.parse::<f32>(),
+            ) {
+                (Ok(index), Ok(crf), Ok(vmaf)) => Some((
+                    index,
+                    crf,
+                    vmaf,
+                    scene_path.first() == Some(&FAST_SCENE_PATH),
+                )),
+                _ => None,
+            },
+            _ => None,
+        };
+        match parsed {
+            Some((index, crf, vmaf, fast)) => {
+                if seen.insert(index) {
+                    scenes.push((index, crf, vmaf, fast));
+                }
+            }
+            None => {
+                warn!(
+                    "Skipping malformed line {} in {}: {:?}",
+                    line_number + 1,
+                    path,
+                    line
+                );
+            }
+        }
+    }
+    Ok(scenes)
+}
+
+#[cfg(test)]
+mod tests;
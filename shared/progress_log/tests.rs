@@ -0,0 +1,72 @@
+use super::*;
+
+const THREADS: i32 = 8;
+const SCENES_PER_THREAD: i32 = 200;
+
+// Append the scenes of 8 threads to path through one log, thread t writes the indices from
+// t * SCENES_PER_THREAD + first on
+fn append_from_threads(path: &str, first: i32, count: i32) {
+    let log = Arc::new(ProgressLog::open(path).unwrap());
+    let threads: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let log = Arc::clone(&log);
+            thread::spawn(move || {
+                for index in first..first + count {
+                    log.write_line(&(thread * SCENES_PER_THREAD + index).to_string())
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+fn sorted(mut indices: Vec<i32>) -> Vec<i32> {
+    indices.sort();
+    indices
+}
+
+#[test]
+fn concurrent_appends_read_back_as_every_index() {
+    let path = temp_path("done.txt");
+    let path = path.to_str().unwrap();
+    append_from_threads(path, 0, SCENES_PER_THREAD);
+
+    assert_eq!(
+        sorted(read_done_indices(path).unwrap()),
+        (0..THREADS * SCENES_PER_THREAD).collect::<Vec<_>>()
+    );
+    // one index per line, none split or joined
+    assert_eq!(
+        fs::read_to_string(path).unwrap().lines().count(),
+        (THREADS * SCENES_PER_THREAD) as usize
+    );
+}
+
+#[test]
+fn a_resumed_run_appends_after_the_first_one() {
+    let path = temp_path("done.txt");
+    let path = path.to_str().unwrap();
+    // the first run stops halfway, the resumed one opens the log again and does the rest
+    append_from_threads(path, 0, SCENES_PER_THREAD / 2);
+    let done = read_done_indices(path).unwrap();
+    assert_eq!(done.len(), (THREADS * SCENES_PER_THREAD / 2) as usize);
+    append_from_threads(path, SCENES_PER_THREAD / 2, SCENES_PER_THREAD / 2);
+
+    assert_eq!(
+        sorted(read_done_indices(path).unwrap()),
+        (0..THREADS * SCENES_PER_THREAD).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn read_done_indices_skips_malformed_and_repeated_lines() {
+    let path = temp_path("done.txt");
+    fs::write(&path, "3\n\n1\nnot a scene\n3\n 2 \n4 5\n").unwrap();
+    assert_eq!(
+        read_done_indices(path.to_str().unwrap()).unwrap(),
+        vec![3, 1, 2]
+    );
+}
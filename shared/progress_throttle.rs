@@ -0,0 +1,51 @@
+// Throttling of the progress of a fast ffmpeg: its output is parsed as it comes, the bars and the
+// WEB_*_STATIC values are only updated every --progress-interval
+
+use crate::*;
+
+// --progress-interval: the minimum time between two updates of the bars and the web progress
+static PROGRESS_INTERVAL_MS: AtomicU64 = AtomicU64::new(250);
+
+pub fn set_progress_interval(ms: u64) {
+    PROGRESS_INTERVAL_MS.store(ms, Ordering::SeqCst);
+}
+
+// Coalesces the progress of a fast ffmpeg, the output is parsed as it comes but the bars and the
+// WEB_*_STATIC values are only pushed when ready, the caller pushes once more when ffmpeg is done
+pub(crate) struct ProgressThrottle {
+    interval: Duration,
+    last_update: Option<Instant>,
+}
+
+impl ProgressThrottle {
+    pub(crate) fn new() -> ProgressThrottle {
+        ProgressThrottle::with_interval(Duration::from_millis(
+            PROGRESS_INTERVAL_MS.load(Ordering::SeqCst),
+        ))
+    }
+
+    pub(crate) fn with_interval(interval: Duration) -> ProgressThrottle {
+        ProgressThrottle {
+            interval,
+            last_update: None,
+        }
+    }
+
+    // Whether an update is due, the first one always is
+    pub(crate) fn ready(&mut self) -> bool {
+        self.ready_at(Instant::now())
+    }
+
+    fn ready_at(&mut self, now: Instant) -> bool {
+        match self.last_update {
+            Some(last_update) if now.duration_since(last_update) < self.interval => false,
+            _ => {
+                self.last_update = Some(now);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,47 @@
+use super::*;
+
+// The positions a bar is set to for frames progress lines, one every line_interval, and the last
+// one ffmpeg ends on
+fn throttled_positions(
+    throttle: &mut ProgressThrottle,
+    frames: u64,
+    line_interval: Duration,
+) -> Vec<u64> {
+    let start = Instant::now();
+    let mut positions = Vec::new();
+    for frame in 1..=frames {
+        if throttle.ready_at(start + line_interval * frame as u32) {
+            positions.push(frame);
+        }
+    }
+    if positions.last() != Some(&frames) {
+        positions.push(frames);
+    }
+    positions
+}
+
+#[test]
+fn ten_thousand_progress_lines_are_coalesced() {
+    // 2000 fps for 5 seconds
+    let mut throttle = ProgressThrottle::with_interval(Duration::from_millis(250));
+    let positions = throttled_positions(&mut throttle, 10_000, Duration::from_micros(500));
+    assert_eq!(positions.len(), 21);
+    assert_eq!(positions[0], 1);
+    assert_eq!(positions[1], 501);
+    // the bars end on the last frame
+    assert_eq!(positions.last(), Some(&10_000));
+}
+
+#[test]
+fn an_interval_of_0_updates_on_every_line() {
+    let mut throttle = ProgressThrottle::with_interval(Duration::ZERO);
+    let positions = throttled_positions(&mut throttle, 10_000, Duration::from_micros(500));
+    assert_eq!(positions, (1..=10_000).collect::<Vec<u64>>());
+}
+
+#[test]
+fn the_first_update_is_always_ready() {
+    let mut throttle = ProgressThrottle::with_interval(Duration::from_secs(3600));
+    assert!(throttle.ready());
+    assert!(!throttle.ready());
+}
@@ -0,0 +1,225 @@
+// The schedule of db_queue: which entry the queue worker of the web server starts next and when the
+// entries are expected to start. not_before is stored as RFC 3339 UTC, only_between is a daily
+// window in local time like 23:00-07:00
+
+use crate::*;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeZone, Utc};
+
+// 23:00
+fn parse_time_of_day(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()
+}
+
+// The local time of date at time, the hour after when a DST change skips it
+fn local_at(date: NaiveDate, time: NaiveTime) -> DateTime<Local> {
+    let naive = date.and_time(time);
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| {
+            Local
+                .from_local_datetime(&(naive + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| Local.from_utc_datetime(&naive))
+}
+
+fn next_day(date: NaiveDate) -> NaiveDate {
+    date.succ_opt().unwrap_or(date)
+}
+
+// The next time of day at or after now, tomorrow when it has already passed today
+fn next_time_of_day(time: NaiveTime, now: DateTime<Local>) -> DateTime<Local> {
+    let today = local_at(now.date_naive(), time);
+    if today >= now {
+        today
+    } else {
+        local_at(next_day(now.date_naive()), time)
+    }
+}
+
+// Resolve a not_before value to RFC 3339 UTC: HH:MM is the next time it is that time of day,
+// "YYYY-MM-DD HH:MM" a local date and time, or an RFC 3339 time. Empty stays empty
+pub fn parse_not_before(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(String::new());
+    }
+    let at = if let Some(time) = parse_time_of_day(s) {
+        next_time_of_day(time, Local::now())
+    } else if let Ok(naive) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        local_at(naive.date(), naive.time())
+    } else if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+        at.with_timezone(&Local)
+    } else {
+        return Err(format!(
+            "{}: expected HH:MM, YYYY-MM-DD HH:MM or an RFC 3339 time",
+            s
+        ));
+    };
+    Ok(at
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+// The time until it is next HH:MM in local time, for the nightly maintenance of the web server
+pub fn until_time_of_day(s: &str) -> Result<Duration, String> {
+    let time = parse_time_of_day(s).ok_or_else(|| format!("{}: expected HH:MM", s))?;
+    let now = Local::now();
+    Ok((next_time_of_day(time, now) - now)
+        .to_std()
+        .unwrap_or(Duration::ZERO))
+}
+
+// 23:00-07:00, the window ends the next day when the end is before the start
+fn parse_window(s: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = s.split_once('-')?;
+    let (start, end) = (parse_time_of_day(start)?, parse_time_of_day(end)?);
+    (start != end).then_some((start, end))
+}
+
+// Check an only_between window and write it as HH:MM-HH:MM. Empty stays empty
+pub fn parse_only_between(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(String::new());
+    }
+    match parse_window(s) {
+        Some((start, end)) => Ok(format!("{}-{}", start.format("%H:%M"), end.format("%H:%M"))),
+        None => Err(format!(
+            "{}: expected a window of two different times like 23:00-07:00",
+            s
+        )),
+    }
+}
+
+fn in_window(time: NaiveTime, (start, end): (NaiveTime, NaiveTime)) -> bool {
+    if start < end {
+        start <= time && time < end
+    } else {
+        time >= start || time < end
+    }
+}
+
+// The first time at or after at that the entry may start
+fn queue_entry_start_at(entry: &QueueEntry, at: DateTime<Local>) -> DateTime<Local> {
+    let mut start = at;
+    if let Ok(not_before) = DateTime::parse_from_rfc3339(&entry.not_before) {
+        start = start.max(not_before.with_timezone(&Local));
+    }
+    if let Some(window) = parse_window(&entry.only_between) {
+        if !in_window(start.time(), window) {
+            // the window opens later today, or tomorrow when today's has passed
+            start = next_time_of_day(window.0, start);
+        }
+    }
+    start
+}
+
+// The entry to start at at: the first queued one by priority and sort order that may start, or
+// else the time the earliest one may
+fn next_queue_entry_at(
+    entries: &[QueueEntry],
+    at: DateTime<Local>,
+) -> Option<Result<usize, DateTime<Local>>> {
+    let mut earliest: Option<DateTime<Local>> = None;
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.state != "queued" {
+            continue;
+        }
+        let start = queue_entry_start_at(entry, at);
+        if start <= at {
+            return Some(Ok(index));
+        }
+        earliest = Some(earliest.map_or(start, |earliest| earliest.min(start)));
+    }
+    earliest.map(Err)
+}
+
+pub enum QueueNext {
+    // the index of the entry to start
+    Start(usize),
+    // nothing may start before this, the schedules of the entries keep them waiting
+    Wait(Duration),
+    Empty,
+}
+
+// What the queue worker does now with the entries of queue_all
+pub fn next_queue_entry(entries: &[QueueEntry]) -> QueueNext {
+    let now = Local::now();
+    match next_queue_entry_at(entries, now) {
+        Some(Ok(index)) => QueueNext::Start(index),
+        Some(Err(until)) => QueueNext::Wait((until - now).to_std().unwrap_or_default()),
+        None => QueueNext::Empty,
+    }
+}
+
+// A queue entry for /queue, with where it stands in the queue
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueListing {
+    #[serde(flatten)]
+    pub entry: QueueEntry,
+    // 0 for the running entry, the queued ones from 1 in the order they will start
+    pub position: usize,
+    // RFC 3339 local time, None until a task has been done to estimate from
+    pub estimated_start: Option<String>,
+}
+
+fn format_local(at: DateTime<Local>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+fn add_duration(at: DateTime<Local>, duration: Duration) -> DateTime<Local> {
+    at + chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::zero())
+}
+
+// The queue in the order the worker will run it. The estimated starts follow the schedules and
+// take the average run time of the done tasks for every entry ahead
+pub fn queue_listing() -> Result<Vec<QueueListing>> {
+    let entries = db().queue_all()?;
+    let average = db().average_task_duration()?;
+    let now = Local::now();
+
+    let mut listing = Vec::new();
+    // when the worker is free to start the next entry, None when that is not known
+    let mut free_at = if any_task_running() { None } else { Some(now) };
+    let (running, mut pending): (Vec<QueueEntry>, Vec<QueueEntry>) = entries
+        .into_iter()
+        .partition(|entry| entry.state == "running");
+    for entry in running {
+        let started = db()
+            .task(&entry.task_id)?
+            .and_then(|task| DateTime::parse_from_rfc3339(&task.started_at).ok())
+            .map(|started| started.with_timezone(&Local));
+        free_at = match (started, average) {
+            (Some(started), Some(average)) => Some(add_duration(started, average).max(now)),
+            _ => None,
+        };
+        listing.push(QueueListing {
+            entry,
+            position: 0,
+            estimated_start: started.map(format_local),
+        });
+    }
+
+    // run the worker's choices forward in time, a free worker waits for the schedules
+    let mut at = free_at.unwrap_or(now);
+    while let Some(next) = next_queue_entry_at(&pending, at) {
+        match next {
+            Ok(index) => {
+                listing.push(QueueListing {
+                    entry: pending.remove(index),
+                    position: listing.iter().filter(|listed| listed.position > 0).count() + 1,
+                    estimated_start: free_at.map(|_| format_local(at)),
+                });
+                match average {
+                    Some(average) => at = add_duration(at, average),
+                    None => free_at = None,
+                }
+            }
+            Err(until) => at = until,
+        }
+    }
+    Ok(listing)
+}
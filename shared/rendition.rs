@@ -0,0 +1,173 @@
+// --renditions: several encodes of every source in the default mode, like the bitrate ladder of a
+// streaming setup. "1080p:vmaf=95,720p:bitrate=2500k" encodes a 1080p rendition at the CRF found
+// for VMAF 95 and a 720p one at 2500 kbps
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenditionQuality {
+    // searched at the rendition's resolution, the scaling changes the score
+    Vmaf(f32),
+    // kbps, no search
+    Bitrate(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendition {
+    pub height: u32,
+    pub quality: RenditionQuality,
+}
+
+impl Rendition {
+    // 720p
+    pub fn label(&self) -> String {
+        format!("{}p", self.height)
+    }
+
+    // the width follows the aspect ratio and stays even for the encoders
+    pub fn scale_filter(&self) -> String {
+        format!("scale=-2:{}", self.height)
+    }
+}
+
+impl std::fmt::Display for Rendition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.quality {
+            RenditionQuality::Vmaf(vmaf) => write!(f, "{} VMAF {}", self.label(), vmaf),
+            RenditionQuality::Bitrate(kbps) => write!(f, "{} {} kbps", self.label(), kbps),
+        }
+    }
+}
+
+// 2500k, 2.5M or 2500 (kbps)
+fn parse_rendition_bitrate(s: &str) -> Option<u32> {
+    let s = s.trim().to_lowercase();
+    let (number, factor) = if let Some(number) = s.strip_suffix('m') {
+        (number, 1000.0)
+    } else if let Some(number) = s.strip_suffix('k') {
+        (number, 1.0)
+    } else {
+        (s.as_str(), 1.0)
+    };
+    let kbps = (number.parse::<f64>().ok()? * factor).round();
+    (kbps >= 1.0 && kbps <= u32::MAX as f64).then_some(kbps as u32)
+}
+
+// Parse a --renditions spec, comma separated <height>p:vmaf=<target> or <height>p:bitrate=<rate>
+pub fn parse_renditions(spec: &str) -> Result<Vec<Rendition>, String> {
+    let mut renditions: Vec<Rendition> = Vec::new();
+    for part in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let (resolution, quality) = part.split_once(':').ok_or_else(|| {
+            format!(
+                "{}: expected <height>p:vmaf=<target> or <height>p:bitrate=<rate>",
+                part
+            )
+        })?;
+        let height = resolution
+            .trim()
+            .trim_end_matches(['p', 'P'])
+            .parse::<u32>()
+            .ok()
+            .filter(|height| *height > 0 && height % 2 == 0)
+            .ok_or_else(|| format!("{}: {} is not an even height like 720p", part, resolution))?;
+        let quality = match quality.trim().split_once('=') {
+            Some(("vmaf", vmaf)) => match vmaf.trim().parse::<f32>() {
+                Ok(vmaf) if (1.0..=100.0).contains(&vmaf) => RenditionQuality::Vmaf(vmaf),
+                _ => return Err(format!("{}: the VMAF target must be 1 to 100", part)),
+            },
+            Some(("bitrate", bitrate)) => match parse_rendition_bitrate(bitrate) {
+                Some(kbps) => RenditionQuality::Bitrate(kbps),
+                None => return Err(format!("{}: {} is not a bitrate like 2500k", part, bitrate)),
+            },
+            _ => {
+                return Err(format!(
+                    "{}: expected vmaf=<target> or bitrate=<rate>",
+                    part
+                ))
+            }
+        };
+        let rendition = Rendition { height, quality };
+        if renditions.contains(&rendition) {
+            return Err(format!("{} is given twice", part));
+        }
+        renditions.push(rendition);
+    }
+    if renditions.is_empty() {
+        return Err("no renditions given".to_string());
+    }
+    Ok(renditions)
+}
+
+pub(crate) fn renditions_validation(s: &str) -> Result<String, String> {
+    parse_renditions(s).map(|_| s.to_string())
+}
+
+// Output filename of a rendition, like "Movie.720p.libx265.vmaf95.crf24.mkv" or
+// "Movie.720p.libx265.2500kbps.mkv"
+pub fn set_output_folder_filename_rendition(
+    file: &str,
+    encoder: &str,
+    rendition: &Rendition,
+    final_vmaf: &f32,
+    target_crf: &str,
+    output_folder: &str,
+) -> String {
+    let (stem, extension) = output_name_parts(file);
+
+    let quality = match rendition.quality {
+        RenditionQuality::Vmaf(_) => format!("vmaf{}.crf{}", final_vmaf, target_crf.trim()),
+        RenditionQuality::Bitrate(kbps) => format!("{}kbps", kbps),
+    };
+    let output_filename = format!(
+        "{}.{}.{}.{}.{}",
+        stem,
+        rendition.label(),
+        encoder,
+        quality,
+        extension
+    );
+
+    output_folder_path(output_folder, &output_filename)
+}
+
+// An encoded rendition, for the summary at the end of the run
+#[derive(Debug, Clone)]
+pub struct RenditionOutput {
+    pub source: String,
+    pub rendition: String,
+    pub output: String,
+    pub size: u64,
+}
+
+// The renditions of the run grouped under their source, in the order they were encoded
+pub fn report_renditions(outputs: &[RenditionOutput]) {
+    if outputs.is_empty() {
+        return;
+    }
+    info!("Renditions:");
+    let mut sources: Vec<&str> = Vec::new();
+    for output in outputs {
+        if !sources.contains(&output.source.as_str()) {
+            sources.push(&output.source);
+        }
+    }
+    for source in sources {
+        info!(
+            "  {} ({})",
+            source,
+            format_size(get_file_size(source).unwrap_or(0))
+        );
+        for output in outputs.iter().filter(|output| output.source == source) {
+            info!(
+                "    {}: {} ({})",
+                output.rendition,
+                output.output,
+                format_size(output.size)
+            );
+        }
+    }
+}
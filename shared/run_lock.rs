@@ -0,0 +1,298 @@
+// Run locks: a run locks its inputs in the locks table of the database while it encodes them, so a
+// cli batch and a web task or queue entry on the same folder don't encode the same files to the same
+// outputs. A run refreshes its locks while it is alive, the locks a crashed run left are not
+// refreshed anymore and --force-lock-takeover clears them once they are older than --lock-stale-after
+
+use crate::*;
+
+use rusqlite::TransactionBehavior;
+
+// How often a run refreshes its locks, --lock-stale-after must be longer
+const LOCK_HEARTBEAT: Duration = Duration::from_secs(30);
+// --lock-stale-after of the web server, which has no command line for it
+pub const DEFAULT_LOCK_STALE_AFTER: &str = "10m";
+
+lazy_static! {
+    // The locks of this process, released by the ctrl-C handler
+    static ref RUN_LOCKS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+// A locked input of a run
+#[derive(Debug, Clone, Serialize)]
+pub struct RunLockRow {
+    pub path: String,
+    pub run_id: String,
+    pub task_id: String,
+    pub pid: u32,
+    // unix seconds
+    pub locked_at: i64,
+    pub heartbeat_at: i64,
+}
+
+impl RunLockRow {
+    fn from_row(row: &rusqlite::Row) -> Result<RunLockRow> {
+        Ok(RunLockRow {
+            path: row.get(0)?,
+            run_id: row.get(1)?,
+            task_id: row.get(2)?,
+            pid: row.get(3)?,
+            locked_at: row.get(4)?,
+            heartbeat_at: row.get(5)?,
+        })
+    }
+
+    // Whether the run of the lock stopped refreshing it stale_after ago
+    fn is_stale(&self, now: i64, stale_after: Duration) -> bool {
+        now - self.heartbeat_at > stale_after.as_secs() as i64
+    }
+
+    // Why path can't be encoded or queued while the lock holds
+    fn conflict(&self, path: &str, now: i64, stale_after: Duration) -> String {
+        let owner = format!(
+            "task {} (pid {}) since {}",
+            self.task_id,
+            self.pid,
+            format_unix_time(self.locked_at)
+        );
+        if self.is_stale(now, stale_after) {
+            format!(
+                "{} is locked by {}, which has not refreshed the lock for {}s and probably crashed, --force-lock-takeover clears it",
+                path,
+                owner,
+                now - self.heartbeat_at
+            )
+        } else {
+            format!("{} is locked by the running {}", path, owner)
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn format_unix_time(seconds: i64) -> String {
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64);
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+// The path a lock is keyed by: the canonical path, the absolute one for a path that doesn't exist
+pub fn lock_key(path: &str) -> String {
+    match fs::canonicalize(path) {
+        Ok(canonical) => canonical.to_string_lossy().to_string(),
+        Err(_) => absolute_path(path),
+    }
+}
+
+// A folder overlaps the files and folders in it
+fn keys_overlap(a: &str, b: &str) -> bool {
+    let (a, b) = (Path::new(a), Path::new(b));
+    a.starts_with(b) || b.starts_with(a)
+}
+
+// --lock-stale-after, validated by clap
+pub(crate) fn parse_lock_stale_after(value: &str) -> Result<Duration, String> {
+    let duration = humantime::parse_duration(value)
+        .map_err(|_| "expected a duration (10m, 1h)".to_string())?;
+    if duration <= LOCK_HEARTBEAT * 2 {
+        return Err(format!(
+            "must be longer than {}s, the locks are refreshed every {}s",
+            LOCK_HEARTBEAT.as_secs() * 2,
+            LOCK_HEARTBEAT.as_secs()
+        ));
+    }
+    Ok(duration)
+}
+
+// What try_lock found: the locks of other runs on the paths, and the stale ones it cleared
+pub(crate) struct LockAttempt {
+    pub(crate) conflicts: Vec<RunLockRow>,
+    pub(crate) taken_over: Vec<RunLockRow>,
+}
+
+impl Db {
+    pub fn run_locks(&self) -> Result<Vec<RunLockRow>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, run_id, task_id, pid, locked_at, heartbeat_at FROM locks ORDER BY locked_at",
+        )?;
+        let rows = stmt.query_map(params![], RunLockRow::from_row)?;
+        rows.collect()
+    }
+
+    // Lock keys for run_id unless a lock of another run overlaps one of them. With takeover the
+    // overlapping locks older than stale_after are cleared first. The immediate transaction keeps
+    // two processes from locking the same paths at once
+    pub(crate) fn try_lock(
+        &self,
+        keys: &[String],
+        run_id: &str,
+        task_id: &str,
+        stale_after: Duration,
+        takeover: bool,
+    ) -> Result<LockAttempt> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let now = unix_now();
+        let locks: Vec<RunLockRow> = {
+            let mut stmt = transaction
+                .prepare("SELECT path, run_id, task_id, pid, locked_at, heartbeat_at FROM locks")?;
+            let rows = stmt.query_map(params![], RunLockRow::from_row)?;
+            rows.collect::<Result<Vec<RunLockRow>>>()?
+        };
+        let mut attempt = LockAttempt {
+            conflicts: Vec::new(),
+            taken_over: Vec::new(),
+        };
+        for lock in locks {
+            if !keys.iter().any(|key| keys_overlap(key, &lock.path)) {
+                continue;
+            }
+            if takeover && lock.is_stale(now, stale_after) {
+                transaction.execute("DELETE FROM locks WHERE run_id=?1", params![lock.run_id])?;
+                attempt.taken_over.push(lock);
+            } else {
+                attempt.conflicts.push(lock);
+            }
+        }
+        if !attempt.conflicts.is_empty() {
+            return Ok(attempt);
+        }
+        for key in keys {
+            transaction.execute(
+                "INSERT INTO locks (path, run_id, task_id, pid, locked_at, heartbeat_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                params![key, run_id, task_id, std::process::id(), now],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(attempt)
+    }
+
+    pub(crate) fn refresh_locks(&self, run_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE locks SET heartbeat_at=?1 WHERE run_id=?2",
+            params![unix_now(), run_id],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn unlock(&self, run_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM locks WHERE run_id=?1", params![run_id])?;
+        Ok(())
+    }
+}
+
+// Why a run can't start on input now: the lock of another run on it, None when it is free
+pub fn lock_conflict(input: &str) -> Result<Option<String>> {
+    let key = lock_key(input);
+    let stale_after = parse_lock_stale_after(DEFAULT_LOCK_STALE_AFTER).unwrap();
+    let now = unix_now();
+    Ok(db()
+        .run_locks()?
+        .iter()
+        .find(|lock| keys_overlap(&key, &lock.path))
+        .map(|lock| lock.conflict(input, now, stale_after)))
+}
+
+// Why input can't be queued: a queue entry on it or the lock of a run, None when it is free
+pub fn input_conflict(input: &str) -> Result<Option<String>> {
+    let key = lock_key(input);
+    for entry in db().queue_all()? {
+        if keys_overlap(&key, &lock_key(&entry.input_path)) {
+            return Ok(Some(format!(
+                "{} is already in the queue as entry {} ({}, {})",
+                input, entry.id, entry.input_path, entry.state
+            )));
+        }
+    }
+    lock_conflict(input)
+}
+
+// Release the locks of this process, for ctrl-C which exits without dropping the RunLock
+pub fn release_run_locks() {
+    for run_id in RUN_LOCKS.lock().unwrap().drain(..) {
+        if let Err(e) = db().unlock(&run_id) {
+            warn!("Failed to release the run lock {}: {}", run_id, e);
+        }
+    }
+}
+
+// The locks of a run on its inputs, refreshed until it is dropped
+pub(crate) struct RunLock {
+    run_id: String,
+    stop: Option<mpsc::Sender<()>>,
+    heartbeat: Option<thread::JoinHandle<()>>,
+}
+
+impl RunLock {
+    // Lock the inputs of the run, an error naming the run that holds one of them
+    pub(crate) fn acquire(inputs: &[String], args: &Args) -> Result<RunLock, String> {
+        // validated by clap
+        let stale_after = parse_lock_stale_after(&args.lock_stale_after).unwrap();
+        let keys: Vec<String> = inputs.iter().map(|input| lock_key(input)).collect();
+        let run_id = uuid::Uuid::now_v7().to_string();
+        let attempt = db()
+            .try_lock(
+                &keys,
+                &run_id,
+                &args.task_id,
+                stale_after,
+                args.force_lock_takeover,
+            )
+            .map_err(|e| format!("Failed to lock the inputs: {}", e))?;
+        let now = unix_now();
+        for lock in &attempt.taken_over {
+            warn!(
+                "Took over the stale lock of task {} (pid {}) on {}, last refreshed at {}",
+                lock.task_id,
+                lock.pid,
+                lock.path,
+                format_unix_time(lock.heartbeat_at)
+            );
+        }
+        if let Some(lock) = attempt.conflicts.first() {
+            return Err(lock.conflict(&lock.path, now, stale_after));
+        }
+        RUN_LOCKS.lock().unwrap().push(run_id.clone());
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let heartbeat_run = run_id.clone();
+        let heartbeat = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(LOCK_HEARTBEAT) {
+                if let Err(e) = db().refresh_locks(&heartbeat_run) {
+                    warn!("Failed to refresh the run lock: {}", e);
+                }
+            }
+        });
+        Ok(RunLock {
+            run_id,
+            stop: Some(stop),
+            heartbeat: Some(heartbeat),
+        })
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(heartbeat) = self.heartbeat.take() {
+            let _ = heartbeat.join();
+        }
+        RUN_LOCKS
+            .lock()
+            .unwrap()
+            .retain(|run_id| *run_id != self.run_id);
+        if let Err(e) = db().unlock(&self.run_id) {
+            warn!("Failed to release the run lock {}: {}", self.run_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,156 @@
+use super::*;
+
+// A lock of another run on path, refreshed age seconds ago
+fn insert_lock(path: &str, run_id: &str, age: i64) {
+    let now = unix_now();
+    db().conn
+        .lock()
+        .unwrap()
+        .execute(
+            "INSERT INTO locks (path, run_id, task_id, pid, locked_at, heartbeat_at)
+             VALUES (?1, ?2, 'other-task', 4242, ?3, ?3)",
+            params![lock_key(path), run_id, now - age],
+        )
+        .unwrap();
+}
+
+// The run ids of the locks on dir and the paths in it
+fn locks_in(dir: &str) -> Vec<String> {
+    let key = lock_key(dir);
+    db().run_locks()
+        .unwrap()
+        .into_iter()
+        .filter(|lock| keys_overlap(&key, &lock.path))
+        .map(|lock| lock.run_id)
+        .collect()
+}
+
+// A folder with a video in it, returns both
+fn input_folder(name: &str) -> (String, String) {
+    let dir = temp_path(name);
+    fs::create_dir_all(&dir).unwrap();
+    let video = dir.join("movie.mkv");
+    fs::write(&video, b"video").unwrap();
+    (
+        dir.to_string_lossy().to_string(),
+        video.to_string_lossy().to_string(),
+    )
+}
+
+// Lock folder with the options of a run
+fn acquire(folder: &str, options: &[&str]) -> Result<RunLock, String> {
+    let mut argv = vec!["-i", folder, "-d", "lock-task"];
+    argv.extend(options);
+    RunLock::acquire(&[folder.to_string()], &test_args(&argv))
+}
+
+fn queue(input: &str) -> Result<i64, String> {
+    add_to_db_queue(input, "", "libx265", "", "95", "", 0, "", "")
+}
+
+#[test]
+fn a_folder_overlaps_the_paths_in_it() {
+    assert!(keys_overlap("/media/shows", "/media/shows/a.mkv"));
+    assert!(keys_overlap("/media/shows/a.mkv", "/media/shows"));
+    assert!(keys_overlap("/media", "/media"));
+    assert!(!keys_overlap("/media/shows", "/media/shows2"));
+    assert!(!keys_overlap("/media/shows/a.mkv", "/media/shows/b.mkv"));
+}
+
+#[test]
+fn the_stale_age_is_longer_than_two_heartbeats() {
+    assert_eq!(parse_lock_stale_after("10m"), Ok(Duration::from_secs(600)));
+    assert_eq!(parse_lock_stale_after("61s"), Ok(Duration::from_secs(61)));
+    assert!(parse_lock_stale_after("60s").is_err());
+    assert!(parse_lock_stale_after("soon").is_err());
+}
+
+#[test]
+fn a_live_lock_refuses_the_run_and_the_queue() {
+    shared_db();
+    let (folder, video) = input_folder("lock-live");
+    insert_lock(&video, "live-run", 5);
+
+    // the takeover only clears stale locks
+    let running = format!(
+        "{} is locked by the running task other-task (pid 4242)",
+        lock_key(&video)
+    );
+    for options in [&[][..], &["--force-lock-takeover"]] {
+        let Err(e) = acquire(&folder, options) else {
+            panic!("{:?}", options);
+        };
+        assert!(e.starts_with(&running), "{}", e);
+    }
+    assert_eq!(locks_in(&folder), ["live-run"]);
+
+    let conflict = input_conflict(&folder).unwrap().unwrap();
+    assert!(
+        conflict.starts_with(&format!(
+            "{} is locked by the running task other-task",
+            folder
+        )),
+        "{}",
+        conflict
+    );
+    assert_eq!(queue(&folder), Err(conflict));
+
+    // once the other run is done
+    db().unlock("live-run").unwrap();
+    let lock = acquire(&folder, &[]).unwrap();
+    assert_eq!(locks_in(&folder).len(), 1);
+    let conflict = lock_conflict(&video).unwrap().unwrap();
+    assert!(conflict.contains("task lock-task"), "{}", conflict);
+    drop(lock);
+    assert!(locks_in(&folder).is_empty());
+    assert_eq!(input_conflict(&folder).unwrap(), None);
+}
+
+#[test]
+fn a_stale_lock_is_taken_over_with_the_flag() {
+    shared_db();
+    let (folder, video) = input_folder("lock-stale");
+    insert_lock(&video, "crashed-run", 3600);
+
+    let Err(e) = acquire(&folder, &[]) else {
+        panic!("a stale lock without the flag");
+    };
+    assert!(
+        e.contains("probably crashed, --force-lock-takeover clears it"),
+        "{}",
+        e
+    );
+    // an hour is not stale for a run that may stop refreshing for two
+    let Err(e) = acquire(
+        &folder,
+        &["--force-lock-takeover", "--lock-stale-after", "2h"],
+    ) else {
+        panic!("a live lock with the flag");
+    };
+    assert!(e.contains("is locked by the running task"), "{}", e);
+    assert_eq!(locks_in(&folder), ["crashed-run"]);
+
+    let lock = acquire(&folder, &["--force-lock-takeover"]).unwrap();
+    let locks = locks_in(&folder);
+    assert_eq!(locks.len(), 1);
+    assert_ne!(locks[0], "crashed-run");
+    drop(lock);
+    assert!(locks_in(&folder).is_empty());
+}
+
+#[test]
+fn an_input_in_the_queue_is_not_queued_again() {
+    shared_db();
+    let (folder, video) = input_folder("lock-queued");
+    let id = queue(&folder).unwrap();
+    assert_eq!(
+        queue(&video),
+        Err(format!(
+            "{} is already in the queue as entry {} ({}, queued)",
+            video, id, folder
+        ))
+    );
+    db().queue_remove(&id.to_string()).unwrap();
+    let id = queue(&video).unwrap();
+    db().queue_remove(&id.to_string()).unwrap();
+}
@@ -0,0 +1,64 @@
+// Progress of the folder scans started from the web, by scan id
+
+use crate::*;
+
+pub fn get_progress_scan_web() -> ProgressScan {
+    // Mock data for demonstration purposes
+    // In a real-world scenario, replace this with actual data from your transcoding process
+
+    // Simulate FPS based on current second
+    let count = WEB_SCAN_COUNT_STATIC.lock().unwrap();
+    let total = WEB_SCAN_TOTAL_STATIC.lock().unwrap();
+
+    // If values are MAX of each type, set values in Progress to 0
+    if *count == u64::MAX {
+        return ProgressScan::default();
+    } else {
+        ProgressScan {
+            count: *count,
+            total: *total,
+            ..Default::default()
+        }
+    }
+}
+
+// Register a new scan task, fails if a running scan covers one of the paths, or the other way around
+pub fn start_scan_progress(scan_id: &str, paths: &[String]) -> Result<(), String> {
+    let mut scans = WEB_SCANS_STATIC.lock().unwrap();
+    let paths: Vec<String> = paths.iter().map(|path| absolute_path(path)).collect();
+    for (id, scan) in scans.iter() {
+        if scan.phase == "done" {
+            continue;
+        }
+        for running in &scan.paths {
+            for path in &paths {
+                if Path::new(path).starts_with(running) || Path::new(running).starts_with(path) {
+                    return Err(format!("{} overlaps with running scan {}", path, id));
+                }
+            }
+        }
+    }
+    scans.insert(
+        scan_id.to_string(),
+        ProgressScan {
+            phase: "walking".to_string(),
+            paths,
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+// Update the progress of a scan task, the global scan progress follows the last updated scan
+pub fn update_scan_progress(scan_id: &str, update: impl FnOnce(&mut ProgressScan)) {
+    let mut scans = WEB_SCANS_STATIC.lock().unwrap();
+    if let Some(scan) = scans.get_mut(scan_id) {
+        update(scan);
+        *WEB_SCAN_COUNT_STATIC.lock().unwrap() = scan.count;
+        *WEB_SCAN_TOTAL_STATIC.lock().unwrap() = scan.total;
+    }
+}
+
+pub fn get_scan_progress(scan_id: &str) -> Option<ProgressScan> {
+    WEB_SCANS_STATIC.lock().unwrap().get(scan_id).cloned()
+}
@@ -0,0 +1,530 @@
+// Chunked mode scenes: scene detection, timecodes and the concatenation of the encoded scenes,
+// the resume logs are in progress_log.rs
+
+use crate::*;
+
+pub fn format_timecode(timecode: &f32) -> String {
+    // format the scene change from seconds to a timecode like 00:00:00.000, rounded to the millisecond
+    // the end of a scene and the start of the next are the same value, so they format the same
+    let total_milliseconds = (timecode.max(0.0) as f64 * 1000.0).round() as u64;
+    let hours = total_milliseconds / 3_600_000;
+    let minutes = (total_milliseconds % 3_600_000) / 60_000;
+    let seconds = (total_milliseconds % 60_000) / 1000;
+    let milliseconds = total_milliseconds % 1000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        hours, minutes, seconds, milliseconds
+    )
+}
+
+pub fn run_ffmpeg_scene_change(file: &str, args: &Args) -> Result<Vec<f32>, io::Error> {
+    // Get the file's duration from ffprobe
+    let duration_f32 = get_duration(file).unwrap();
+
+    // Get the total duration
+    let total_duration = duration_f32;
+
+    // Create a progress bar
+    let progress_bar = progress_bar(total_duration as u64);
+    let progress_bar_style =
+        "[scd][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]";
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template(progress_bar_style)
+            .unwrap(),
+    );
+
+    // Run ffmpeg to detect scene changes
+    let mut command = ffmpeg_command()
+        .arg("-i")
+        .arg(staged_input(file))
+        .arg("-map")
+        .arg(probe_file(file).video_map())
+        .arg("-vf")
+        .arg("select='gt(scene,0.4)',showinfo")
+        .arg("-f")
+        .arg("NULL")
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to execute process");
+    let _tracked = track_child(&command, &[]);
+
+    // list of scene changes
+    let mut scene_changes_list: Vec<f32> = Vec::new();
+    let mut scene_count = 0;
+
+    // Add 00:00:00.000 to the beginning of the scene_changes_list
+    scene_changes_list.push(0.0);
+
+    loop {
+        let mut buffer = [0; 1024];
+        match command.stderr.as_mut().unwrap().read(&mut buffer) {
+            Ok(n) => {
+                // if line contains 'pts_time:' then print it
+                let line = String::from_utf8_lossy(&buffer[..n]);
+                if line.contains("pts_time:") {
+                    scene_count += 1;
+                    // Update the progress bar based on the current pts_time
+                    let current_pts_time = line.split("pts_time:").collect::<Vec<&str>>()[1]
+                        .split(" ")
+                        .collect::<Vec<&str>>()[0]
+                        .parse::<f32>()
+                        .unwrap();
+                    // if difference between current pts_time and last pts_time is less than args.scene_split_min second, skip
+                    if current_pts_time - scene_changes_list[scene_changes_list.len() - 1]
+                        < args.scene_split_min
+                    {
+                        // add the current pts_time to the scene_changes_list
+                        //scene_changes_list.push(current_pts_time);
+                        continue;
+                    } else {
+                        // add the current pts_time to the scene_changes_list
+                        scene_changes_list.push(current_pts_time);
+                    }
+                    // add the current pts_time to the scene_changes_list
+                    //scene_changes_list.push(current_pts_time);
+                    progress_bar.set_position(current_pts_time as u64);
+                    update_chunked_progress(|chunked| {
+                        chunked.scene_detect_percentage =
+                            (current_pts_time / total_duration * 100.0).min(100.0);
+                    });
+                }
+                // if line contains 'out#0' then break
+                if line.contains("out#0") {
+                    // set the progress bar to the total duration
+                    progress_bar.set_position(total_duration as u64);
+                    progress_bar.finish();
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("Error: {}", e);
+                break;
+            }
+        }
+    }
+
+    /*     for scene_change in scene_changes_list.clone() {
+        println!("scene_change: {}", scene_change);
+    } */
+
+    // Finish the progress bar
+    progress_bar.finish();
+
+    info!("Number of detected scenes: {}", scene_count);
+
+    // print the scene changes
+    //println!("Scene changes: {:?}", scene_changes_list);
+
+    // add the total duration to the end of the scene_changes_list
+    scene_changes_list.push(total_duration);
+
+    Ok(scene_changes_list)
+}
+
+// The kept audio and subtitle streams and the cover art of streams are copied without re-encoding,
+// those of its range for a trial
+pub(crate) fn extract_non_video_content(
+    input_file: &str,
+    output_file: &str,
+    streams: &StreamSelection,
+) -> Result<Output, std::io::Error> {
+    let seek: Vec<String> = match trial_range(input_file) {
+        Some(range) => {
+            let (ss, to) = range.seek_args();
+            vec!["-ss".to_string(), ss, "-to".to_string(), to]
+        }
+        None => Vec::new(),
+    };
+    // -vn would drop the cover art as well, its maps leave the video out
+    let no_video: &[&str] = if streams.cover_art.is_empty() {
+        &["-vn"]
+    } else {
+        &[]
+    };
+    let output = tracked_output(
+        ffmpeg_command()
+            .args(seek)
+            .arg("-i")
+            .arg(staged_input(input_file))
+            .args(no_video)
+            .args(streams.map_args())
+            .args(streams.cover_art_args(0))
+            .arg(output_file),
+        &[output_file],
+    )?;
+
+    Ok(output)
+}
+
+// FNV-1a, stable across Rust versions so a resumed run finds the chunks of the one it resumes
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Run id of the chunked encode of file: a hash of its absolute path, its size and its first MiB,
+// and of the range of a trial, whose scenes are not the ones of the whole file
+pub(crate) fn chunk_run_id(file: &str) -> String {
+    let mut hash = fnv1a(0xcbf29ce484222325, absolute_path(file).as_bytes());
+    hash = fnv1a(hash, &get_file_size(file).unwrap_or(0).to_le_bytes());
+    let mut head = Vec::new();
+    if let Ok(source) = File::open(file) {
+        let _ = source.take(1024 * 1024).read_to_end(&mut head);
+    }
+    hash = fnv1a(hash, &head);
+    if let Some(range) = trial_range(file) {
+        hash = fnv1a(hash, range.output_name("").as_bytes());
+    }
+    format!("{:012x}", hash & 0xffff_ffff_ffff)
+}
+
+// The files a chunked encode writes to the work dir, named with the run id of the input so two
+// encodes in the same folder, or chunks left by a crashed run of another file, never mix
+// The scene index is padded to the digits of the scene count, at least 4, so the names sort
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkFiles {
+    run_id: String,
+    width: usize,
+}
+
+impl ChunkFiles {
+    pub(crate) fn new(file: &str, scene_count: usize) -> ChunkFiles {
+        ChunkFiles {
+            run_id: chunk_run_id(file),
+            width: scene_count.to_string().len().max(4),
+        }
+    }
+
+    // scene_<run id>_0042_encoded.mkv
+    pub(crate) fn scene(&self, index: i32) -> String {
+        format!(
+            "scene_{}_{:0width$}_encoded.mkv",
+            self.run_id,
+            index,
+            width = self.width
+        )
+    }
+
+    // search_<run id>_0042_crf23.5.nut, the encode of a CRF search iteration of the scene
+    pub(crate) fn search_stream(&self, index: i32, crf: f32) -> String {
+        format!(
+            "search_{}_{:0width$}_crf{}.nut",
+            self.run_id,
+            index,
+            crf,
+            width = self.width
+        )
+    }
+
+    // the audio and subtitles, muxed with the scenes at the end
+    pub(crate) fn non_video(&self) -> String {
+        format!("temp_{}.mkv", self.run_id)
+    }
+
+    pub(crate) fn merged(&self) -> String {
+        format!("merged_scenes_{}.mkv", self.run_id)
+    }
+
+    // the growing video of --stream-concat
+    pub(crate) fn stream(&self) -> String {
+        format!("stream_{}.ts", self.run_id)
+    }
+
+    // The scene index of a chunk of this run, None for other files
+    pub(crate) fn scene_index(&self, file_name: &str) -> Option<i32> {
+        file_name
+            .strip_prefix(&format!("scene_{}_", self.run_id))?
+            .strip_suffix("_encoded.mkv")?
+            .parse::<i32>()
+            .ok()
+    }
+
+    // The chunks of this run in the work dir, by scene index
+    pub(crate) fn existing_scenes(&self) -> Vec<(i32, String)> {
+        let mut scene_files: Vec<(i32, String)> = WalkDir::new(".")
+            .max_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let file_name = e.file_name().to_str()?.to_string();
+                Some((self.scene_index(&file_name)?, file_name))
+            })
+            .collect();
+        scene_files.sort_by_key(|(index, _)| *index);
+        scene_files
+    }
+
+    // Remove the search streams of this run that no scene encode took, like the ones of a resumed
+    // run that searched the scene again
+    pub(crate) fn remove_search_streams(&self) {
+        let prefix = format!("search_{}_", self.run_id);
+        let search_streams = WalkDir::new(".")
+            .max_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str().map(String::from))
+            .filter(|name| name.starts_with(&prefix) && name.ends_with(".nut"));
+        for path in search_streams {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    // Remove the chunks and the audio and subtitles of this run, once its encode was given up
+    pub(crate) fn remove_all(&self) {
+        for (_, path) in self.existing_scenes() {
+            let _ = fs::remove_file(path);
+        }
+        self.remove_search_streams();
+        let _ = fs::remove_file(self.non_video());
+        let _ = fs::remove_file(self.stream());
+    }
+
+    // The chunk paths of scenes 0 to scene_count - 1 in order, an error lists the missing or empty
+    // chunks and the chunks of this run that are not one of the scenes
+    pub(crate) fn verified_scenes(&self, scene_count: usize) -> Result<Vec<String>, io::Error> {
+        let expected: Vec<String> = (0..scene_count as i32)
+            .map(|index| self.scene(index))
+            .collect();
+        let missing: Vec<&str> = expected
+            .iter()
+            .filter(|path| fs::metadata(path).map(|m| m.len() == 0).unwrap_or(true))
+            .map(String::as_str)
+            .collect();
+        let extra: Vec<String> = self
+            .existing_scenes()
+            .into_iter()
+            .filter(|(_, path)| !expected.contains(path))
+            .map(|(_, path)| path)
+            .collect();
+        if missing.is_empty() && extra.is_empty() {
+            return Ok(expected);
+        }
+        let mut problems = Vec::new();
+        if !missing.is_empty() {
+            problems.push(format!("missing {}", missing.join(", ")));
+        }
+        if !extra.is_empty() {
+            problems.push(format!("unexpected {}", extra.join(", ")));
+        }
+        Err(io::Error::new(
+            ErrorKind::Other,
+            format!("{} scenes expected, {}", scene_count, problems.join("; ")),
+        ))
+    }
+}
+
+// A file line of an ffmpeg concat list, a ' in the path closes the quotes, is escaped and reopens them
+fn concat_list_line(path: &str) -> String {
+    format!("file '{}'\n", path.replace('\'', "'\\''"))
+}
+
+// Concatenate scene_paths in their order and mux them with the audio and subtitles of chunks
+pub(crate) fn concatenate_videos(
+    output_filename: &str,
+    source_file: &str,
+    list_file_name: &Path,
+    chunks: &ChunkFiles,
+    scene_paths: &[String],
+    args: &Args,
+) -> Result<(), std::io::Error> {
+    let merged = chunks.merged();
+    let non_video = chunks.non_video();
+
+    info!("Concatenating {} scenes", scene_paths.len());
+    set_concat_step("concat");
+
+    // Step 1: List the scene files, by scene index
+    let mut list_file = File::create(list_file_name)?;
+    for path in scene_paths {
+        list_file.write_all(concat_list_line(path).as_bytes())?;
+    }
+
+    // Step 2: Run FFmpeg to concatenate videos
+    let ffmpeg_output = tracked_output(
+        ffmpeg_command()
+            .arg("-y")
+            .arg("-f")
+            .arg("concat")
+            .arg("-safe")
+            .arg("0")
+            .arg("-i")
+            .arg(list_file_name)
+            .arg("-c")
+            .arg("copy")
+            .arg(&merged),
+        &[&merged],
+    )?;
+
+    // Optional: Check FFmpeg command output for success or error
+    if !ffmpeg_output.status.success() {
+        let error_message = String::from_utf8_lossy(&ffmpeg_output.stderr);
+        error!("FFmpeg error: {}", error_message);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "FFmpeg failed to concatenate videos.",
+        ));
+    }
+
+    // Step 3: Remove list file
+    std::fs::remove_file(list_file_name)?;
+
+    // The scene files are kept, so the failing run can be inspected
+    set_concat_step("verify");
+    verify_scene_frames(source_file, &merged)?;
+
+    // Step 4: Merge merged_scenes.mkv with temp.mkv containing audio and subtitles
+    set_concat_step("mux");
+    mux_non_video(output_filename, source_file, &merged, &non_video, args)?;
+
+    info!("Videos concatenated successfully.");
+
+    // Step 5: Delete the merged scenes
+    std::fs::remove_file(&merged)?;
+
+    // Step 6: Delete the audio and subtitles
+    std::fs::remove_file(&non_video)?;
+
+    // Step 7: Delete the scene files
+    for path in scene_paths {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+// The scenes have to add up to the source exactly, otherwise a frame was duplicated or dropped
+pub(crate) fn verify_scene_frames(source_file: &str, video: &str) -> Result<(), std::io::Error> {
+    // a trial has the frames of its range
+    let source_frames = match trial_range(source_file) {
+        Some(range) => Ok(range.frames(get_fps_f32(source_file)) as f32),
+        None => get_framecount_ffmpeg(source_file),
+    };
+    let video_frames = get_framecount_ffmpeg(video);
+    match (source_frames, video_frames) {
+        (Ok(source_frames), Ok(video_frames)) if source_frames == video_frames => Ok(()),
+        (Ok(source_frames), Ok(video_frames)) => Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "merged scenes have {} frames, the source has {}",
+                video_frames, source_frames
+            ),
+        )),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Failed to count the frames of the merged scenes or the source",
+        )),
+    }
+}
+
+// Mux the concatenated scenes of source_file in video with the audio, subtitles and cover art of
+// non_video, the video first, and check their A/V sync before the output gets its name
+pub(crate) fn mux_non_video(
+    output_filename: &str,
+    source_file: &str,
+    video: &str,
+    non_video: &str,
+    args: &Args,
+) -> Result<(), std::io::Error> {
+    let part = PartFile::new(output_filename, source_file);
+    let output = tracked_output(
+        ffmpeg_command()
+            .arg("-y")
+            .arg("-i")
+            .arg(non_video)
+            .arg("-i")
+            .arg(video)
+            .args(["-map", "1:v", "-map", "0", "-c", "copy"])
+            .args(part.format_args())
+            .arg(part.path()),
+        &[part.path()],
+    )?;
+
+    // Optional: Check FFmpeg command output for success or error
+    if !output.status.success() {
+        let error_message = String::from_utf8_lossy(&output.stderr);
+        error!("FFmpeg error: {}", error_message);
+        part.discard();
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "FFmpeg failed to merge videos.",
+        ));
+    }
+    verify_av_sync(source_file, non_video, part.path(), output_filename, args)?;
+    part.commit()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+}
+
+// Seconds of a timecode like the ones of format_timecode or --trial-range, 00:01:02.500 is 62.5,
+// 20:00.5 and plain seconds like 1200 are read too
+pub(crate) fn parse_timecode(timecode: &str) -> Option<f64> {
+    let parts: Vec<&str> = timecode.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    parts.into_iter().try_fold(0.0, |seconds, part| {
+        let part = part.parse::<f64>().ok()?;
+        if part < 0.0 || !part.is_finite() {
+            return None;
+        }
+        Some(seconds * 60.0 + part)
+    })
+}
+
+// Size in bytes of the video packets of file between the timecodes ss and to
+// -read_intervals starts at the keyframe before ss, the packets before it are left out
+pub fn get_scene_size(file_path: &str, ss: &str, to: &str) -> Result<u64, Error> {
+    let output = ffprobe_command()
+        .args(["-v", "error", "-select_streams"])
+        .arg(probe_file(file_path).video_select())
+        .arg("-read_intervals")
+        .arg(format!("{}%{}", ss, to))
+        .args(["-show_entries", "packet=pts_time,size", "-of", "json"])
+        .arg(file_path)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "ffprobe failed on {}: {}",
+                file_path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    let json = probe_json(&output, file_path);
+    if json.is_null() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("ffprobe returned no packets json for {}", file_path),
+        ));
+    }
+    Ok(scene_packet_bytes(&json, ss, to))
+}
+
+// The bytes of the packets of an ffprobe -show_entries packet=pts_time,size json between the
+// timecodes ss and to, a packet without a time is counted
+pub(crate) fn scene_packet_bytes(json: &Value, ss: &str, to: &str) -> u64 {
+    let start = parse_timecode(ss).unwrap_or(0.0);
+    let end = parse_timecode(to).unwrap_or(f64::MAX);
+    let packets = json["packets"].as_array().cloned().unwrap_or_default();
+    packets
+        .iter()
+        .filter(|packet| {
+            match packet["pts_time"]
+                .as_str()
+                .and_then(|pts| pts.parse::<f64>().ok())
+            {
+                Some(pts) => pts >= start && pts < end,
+                None => true,
+            }
+        })
+        .filter_map(|packet| packet["size"].as_str()?.parse::<u64>().ok())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests;
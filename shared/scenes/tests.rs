@@ -0,0 +1,105 @@
+use super::*;
+
+#[test]
+fn format_timecode_pads_every_field() {
+    assert_eq!(format_timecode(&0.0), "00:00:00.000");
+    assert_eq!(format_timecode(&61.5), "00:01:01.500");
+    assert_eq!(format_timecode(&3661.25), "01:01:01.250");
+}
+
+#[test]
+fn format_timecode_rounds_to_the_millisecond() {
+    let cases = [
+        (0.0, "00:00:00.000"),
+        (0.04, "00:00:00.040"),
+        (59.999, "00:00:59.999"),
+        (3600.5, "01:00:00.500"),
+        (7262.376, "02:01:02.376"),
+    ];
+    for (seconds, expected) in cases {
+        assert_eq!(format_timecode(&seconds), expected, "{}", seconds);
+    }
+}
+
+#[test]
+fn parse_timecode_reads_the_timecodes_of_trial_range() {
+    assert_eq!(parse_timecode("00:20:00"), Some(1200.0));
+    assert_eq!(parse_timecode(" 20:00.5"), Some(1200.5));
+    assert_eq!(parse_timecode("1200"), Some(1200.0));
+    for timecode in ["1:00:00:00", "-5", "20:-1", "inf", "", "20:xx"] {
+        assert_eq!(parse_timecode(timecode), None, "{:?}", timecode);
+    }
+}
+
+#[test]
+fn parse_timecode_reads_format_timecode_back_within_a_millisecond() {
+    let times = [
+        0.0, 0.04, 0.0005, 1.001, 59.999, 60.0, 599.9996, 3599.9999, 3600.5, 7262.376, 86399.5,
+    ];
+    for seconds in times {
+        let parsed = parse_timecode(&format_timecode(&seconds)).unwrap();
+        assert!(
+            (parsed - seconds as f64).abs() <= 0.001,
+            "{} came back as {}",
+            seconds,
+            parsed
+        );
+    }
+}
+
+#[test]
+fn format_timecode_of_a_negative_time_is_zero() {
+    assert_eq!(format_timecode(&-1.0), "00:00:00.000");
+}
+
+#[test]
+fn concat_list_line_escapes_single_quotes() {
+    let cases = [
+        ("/tmp/scenes/00001.mkv", "file '/tmp/scenes/00001.mkv'\n"),
+        (
+            "/media/It's a Movie/00001.mkv",
+            "file '/media/It'\\''s a Movie/00001.mkv'\n",
+        ),
+        ("''", "file ''\\'''\\'''\n"),
+        // double quotes, spaces and backslashes need no escaping in single quotes
+        (
+            r#"D:\Movies\"A" b\00001.mkv"#,
+            "file 'D:\\Movies\\\"A\" b\\00001.mkv'\n",
+        ),
+    ];
+    for (path, expected) in cases {
+        assert_eq!(concat_list_line(path), expected, "{}", path);
+    }
+}
+
+#[test]
+fn scene_packet_bytes_of_the_packets_in_the_scene() {
+    let json = serde_json::json!({
+        "packets": [
+            // the keyframe before ss that -read_intervals starts at
+            {"pts_time": "9.960000", "size": "90000"},
+            {"pts_time": "10.000000", "size": "120000"},
+            {"pts_time": "12.500000", "size": "4000"},
+            {"size": "500"},
+            {"pts_time": "19.958000", "size": "3000"},
+            // to is left out
+            {"pts_time": "20.000000", "size": "80000"},
+            {"pts_time": "15.000000", "size": "N/A"},
+        ]
+    });
+    assert_eq!(
+        scene_packet_bytes(&json, "00:00:10.000", "00:00:20.000"),
+        127_500
+    );
+    assert_eq!(scene_packet_bytes(&json, "00:00:00.000", "bad"), 297_500);
+    assert_eq!(scene_packet_bytes(&serde_json::json!({}), "0", "10"), 0);
+}
+
+#[test]
+fn scene_packet_bytes_past_4_gb() {
+    let packets: Vec<Value> = (0..3)
+        .map(|index| serde_json::json!({"pts_time": index.to_string(), "size": "2000000000"}))
+        .collect();
+    let json = serde_json::json!({ "packets": packets });
+    assert_eq!(scene_packet_bytes(&json, "0", "10"), 6_000_000_000);
+}
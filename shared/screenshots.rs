@@ -0,0 +1,140 @@
+// --screenshots: frames of the source and the encode at the same timestamps, saved as png under
+// <outputpath>/screenshots to compare them side by side after the encode
+
+use crate::*;
+
+// Seconds left out at the start and the end, intros and credits say little about the encode
+const SCREENSHOT_MARGIN: f32 = 30.0;
+
+// count timestamps in whole seconds between the margins, sorted
+// worst_scenes are (start, end) from the lowest VMAF up, their middles take up to half of the
+// timestamps and the rest is spread evenly over the file
+pub fn screenshot_timestamps(duration: f32, count: u32, worst_scenes: &[(f32, f32)]) -> Vec<u32> {
+    if count == 0 || duration <= 0.0 {
+        return Vec::new();
+    }
+    // short files keep a quarter at each end instead
+    let margin = SCREENSHOT_MARGIN.min(duration / 4.0);
+    let (start, end) = (margin, duration - margin);
+    // closer timestamps would mostly show the same shot
+    let min_gap = ((end - start) / (count as f32 * 2.0)).max(1.0);
+
+    let mut timestamps: Vec<f32> = Vec::new();
+    let far_enough =
+        |timestamps: &Vec<f32>, t: f32| timestamps.iter().all(|other| (other - t).abs() >= min_gap);
+    let worst_count = (count as usize + 1) / 2;
+    for (scene_start, scene_end) in worst_scenes {
+        if timestamps.len() >= worst_count {
+            break;
+        }
+        let middle = (scene_start + scene_end) / 2.0;
+        if middle >= start && middle <= end && far_enough(&timestamps, middle) {
+            timestamps.push(middle);
+        }
+    }
+    let even: Vec<f32> = (0..count)
+        .map(|i| start + (end - start) * (i as f32 + 0.5) / count as f32)
+        .collect();
+    for t in &even {
+        if timestamps.len() >= count as usize {
+            break;
+        }
+        if far_enough(&timestamps, *t) {
+            timestamps.push(*t);
+        }
+    }
+
+    let mut seconds: Vec<u32> = timestamps.iter().map(|t| t.round() as u32).collect();
+    seconds.sort();
+    seconds.dedup();
+    seconds
+}
+
+// rgb24 for png, 8 and 10 bit alike, HDR is tonemapped to bt709 first so it isn't washed out
+fn screenshot_filter(file: &str) -> String {
+    if probe_file(file).is_hdr() && has_zscale_tonemap() {
+        format!("{},format=rgb24", ZSCALE_TONEMAP)
+    } else {
+        "format=rgb24".to_string()
+    }
+}
+
+// Save the frame of file at second as png, false when ffmpeg failed
+fn extract_frame(file: &str, second: u32, png: &str) -> bool {
+    let video = probe_file(file).video_map();
+    let mut cmd = ffmpeg_command();
+    cmd.args(["-v", "error", "-y", "-ss", &second.to_string(), "-i", file])
+        .args([
+            "-map",
+            &video,
+            "-frames:v",
+            "1",
+            "-vf",
+            &screenshot_filter(file),
+        ])
+        .arg(png);
+    debug!("{:?}", cmd);
+    match tracked_output(&mut cmd, &[png]) {
+        Ok(output) if output.status.success() => true,
+        Ok(output) => {
+            warn!(
+                "Failed to save {}: {}",
+                png,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            false
+        }
+        Err(e) => {
+            warn!("Failed to execute ffmpeg: {}", e);
+            false
+        }
+    }
+}
+
+// After a successful encode of file to output: --screenshots frames of both as
+// stem_t123_src.png and stem_t123_enc.png, the list is stored with the file in the database
+pub fn save_screenshots(file: &str, output: &str, args: &Args, worst_scenes: &[(f32, f32)]) {
+    let count = match args.screenshots {
+        Some(count) if count > 0 => count,
+        _ => return,
+    };
+    let duration = match get_duration(file) {
+        Some(duration) => duration,
+        None => {
+            warn!("No screenshots of {}, failed to get its duration", file);
+            return;
+        }
+    };
+    let folder = Path::new(&args.outputpath).join("screenshots");
+    if let Err(e) = fs::create_dir_all(&folder) {
+        warn!("Failed to create {}: {}", folder.display(), e);
+        return;
+    }
+    let stem = Path::new(file)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("file");
+
+    let mut screenshots: Vec<String> = Vec::new();
+    for second in screenshot_timestamps(duration, count, worst_scenes) {
+        for (input, suffix) in [(file, "src"), (output, "enc")] {
+            let png = folder
+                .join(format!("{}_t{}_{}.png", stem, second, suffix))
+                .display()
+                .to_string();
+            if extract_frame(input, second, &png) {
+                screenshots.push(png);
+            }
+        }
+    }
+
+    info!(
+        "Saved {} screenshots of {} to {}",
+        screenshots.len(),
+        file,
+        folder.display()
+    );
+    if let Err(e) = db().update_screenshots(file, &screenshots) {
+        warn!("Failed to store the screenshots of {}: {}", file, e);
+    }
+}
@@ -0,0 +1,403 @@
+// Session journal of a batch: when a run on an input folder or list starts encoding, its files in
+// order with their frame counts, its Args and the state of every file are stored in the sessions
+// and session_files tables, the states are updated after each file. --resume-last and --resume <id>
+// continue an interrupted batch with the same files in the same order, skipping the done ones, and
+// the bars, the elapsed time and the summary count the whole batch. `sessions list` and
+// `sessions clean` show and remove the journals
+
+use crate::*;
+
+use rusqlite::OptionalExtension;
+
+// The options a resumed batch has to keep, the done files were encoded with them
+const RESUME_KEPT: [&str; 5] = ["encoder", "mode", "vmaf", "target_size", "renditions"];
+
+// A batch as `sessions list` shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: String,
+    // the task of the run that started it
+    pub task_id: String,
+    // RFC 3339 UTC
+    pub created_at: String,
+    pub updated_at: String,
+    // running, done (every file done) or incomplete
+    pub state: String,
+    // the -i paths joined with ;
+    pub input_path: String,
+    // the run time of the batch over all its runs
+    pub elapsed_s: i64,
+    pub files: i64,
+    pub done_files: i64,
+    // the Args of the run that started it, as json
+    #[serde(skip)]
+    pub args: String,
+}
+
+// A file of a batch, state is pending, done, failed or deferred (changed during its encode or
+// still written, retried at the end of the batch)
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionFile {
+    pub path: String,
+    pub frames: u64,
+    pub state: String,
+}
+
+// The journal of the batch of a running pipeline
+pub(crate) struct BatchSession {
+    pub(crate) id: String,
+    pub(crate) created_at: String,
+    // the run time of the earlier runs of a resumed batch
+    pub(crate) elapsed_before: Duration,
+    pub(crate) files: Vec<SessionFile>,
+}
+
+impl BatchSession {
+    // The journal of a new batch of files with their frame counts
+    pub(crate) fn start(args: &Args, files: &[(String, u64)]) -> Result<BatchSession, String> {
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let session = Session {
+            id: new_task_id(),
+            task_id: args.task_id.clone(),
+            created_at: now.clone(),
+            updated_at: now,
+            state: "running".to_string(),
+            input_path: args.inputpath.join(";"),
+            elapsed_s: 0,
+            files: files.len() as i64,
+            done_files: 0,
+            args: serde_json::to_string(args).map_err(|e| e.to_string())?,
+        };
+        let files: Vec<SessionFile> = files
+            .iter()
+            .map(|(path, frames)| SessionFile {
+                path: path.clone(),
+                frames: *frames,
+                state: "pending".to_string(),
+            })
+            .collect();
+        db().insert_session(&session, &files)
+            .map_err(|e| e.to_string())?;
+        info!("Session {} started, --resume-last continues it", session.id);
+        Ok(BatchSession {
+            id: session.id,
+            created_at: session.created_at,
+            elapsed_before: Duration::ZERO,
+            files,
+        })
+    }
+
+    // The frames of the done files, where the total bar of a resumed batch starts
+    pub(crate) fn done_frames(&self) -> u64 {
+        self.files
+            .iter()
+            .filter(|file| file.state == "done")
+            .map(|file| file.frames)
+            .sum()
+    }
+
+    pub(crate) fn total_frames(&self) -> u64 {
+        self.files.iter().map(|file| file.frames).sum()
+    }
+
+    // The frame count the journal has for path, 0 for a file that is not in it
+    pub(crate) fn frames(&self, path: &str) -> u64 {
+        self.files
+            .iter()
+            .find(|file| file.path == path)
+            .map_or(0, |file| file.frames)
+    }
+
+    // Store the state of path and the run time of the batch so far
+    pub(crate) fn update(&mut self, path: &str, state: &str, elapsed: Duration) {
+        if let Some(file) = self.files.iter_mut().find(|file| file.path == path) {
+            file.state = state.to_string();
+        }
+        let result = db()
+            .set_session_file_state(&self.id, path, state)
+            .and_then(|_| db().update_session(&self.id, "running", elapsed.as_secs() as i64));
+        if let Err(e) = result {
+            warn!("Failed to update session {}: {}", self.id, e);
+        }
+    }
+
+    // Close the journal at the end of the run: done when every file is done, incomplete otherwise
+    pub(crate) fn finish(&self, elapsed: Duration) {
+        let done = self
+            .files
+            .iter()
+            .filter(|file| file.state == "done")
+            .count();
+        let state = if done == self.files.len() {
+            "done"
+        } else {
+            "incomplete"
+        };
+        if let Err(e) = db().update_session(&self.id, state, elapsed.as_secs() as i64) {
+            warn!("Failed to update session {}: {}", self.id, e);
+        }
+        let savings = db().stats_since(&self.created_at).unwrap_or_default();
+        info!(
+            "Session {}: {} of {} files done, {} -> {} ({:.0}% saved)",
+            self.id,
+            done,
+            self.files.len(),
+            format_size(savings.input_bytes.max(0) as u64),
+            format_size(savings.output_bytes.max(0) as u64),
+            savings.saved_percent
+        );
+        if state == "incomplete" {
+            info!("--resume {} encodes the remaining files", self.id);
+        }
+    }
+}
+
+// The session --resume-last or --resume asks for with its files, None without them. The session
+// has to be unfinished and args has to keep its RESUME_KEPT options, args then gets its inputs
+pub(crate) fn resume_session(args: &mut Args) -> Result<Option<BatchSession>, String> {
+    let session = match (&args.resume, args.resume_last) {
+        (Some(id), _) => db()
+            .session(id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("No session {}, `sessions list` shows them", id))?,
+        (None, true) => db()
+            .last_unfinished_session()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "No unfinished session to resume".to_string())?,
+        (None, false) => return Ok(None),
+    };
+    if session.state == "done" {
+        return Err(format!("Session {} is already done", session.id));
+    }
+    let stored: Value = serde_json::from_str(&session.args)
+        .map_err(|e| format!("Session {} has invalid args: {}", session.id, e))?;
+    let current = serde_json::to_value(&*args).map_err(|e| e.to_string())?;
+    let changed: Vec<String> = RESUME_KEPT
+        .iter()
+        .filter(|key| stored[**key] != current[**key])
+        .map(|key| {
+            format!(
+                "--{} {} (the session has {})",
+                key.replace('_', "-"),
+                current[*key],
+                stored[*key]
+            )
+        })
+        .collect();
+    if !changed.is_empty() {
+        return Err(format!(
+            "Session {} was started with other options, resume it with the same: {}",
+            session.id,
+            changed.join(", ")
+        ));
+    }
+    // the inputs of the session, -i of the command line is not scanned again
+    if let Ok(inputpath) = serde_json::from_value(stored["inputpath"].clone()) {
+        args.inputpath = inputpath;
+    }
+    args.input_list = stored["input_list"].as_str().map(str::to_string);
+
+    let files = db().session_files(&session.id).map_err(|e| e.to_string())?;
+    let done = files.iter().filter(|file| file.state == "done").count();
+    info!(
+        "Resuming session {} of {}: {} of {} files done",
+        session.id,
+        session.created_at,
+        done,
+        files.len()
+    );
+    Ok(Some(BatchSession {
+        id: session.id,
+        created_at: session.created_at,
+        elapsed_before: Duration::from_secs(session.elapsed_s.max(0) as u64),
+        files,
+    }))
+}
+
+const SESSION_COLUMNS: &str =
+    "id, task_id, created_at, updated_at, state, input_path, elapsed_s, args,
+    (SELECT COUNT(*) FROM session_files WHERE session_id=sessions.id),
+    (SELECT COUNT(*) FROM session_files WHERE session_id=sessions.id AND state='done')";
+
+impl Session {
+    fn from_row(row: &rusqlite::Row) -> Result<Session> {
+        Ok(Session {
+            id: row.get(0)?,
+            task_id: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+            state: row.get(4)?,
+            input_path: row.get(5)?,
+            elapsed_s: row.get(6)?,
+            args: row.get(7)?,
+            files: row.get(8)?,
+            done_files: row.get(9)?,
+        })
+    }
+}
+
+impl Db {
+    pub fn insert_session(&self, session: &Session, files: &[SessionFile]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            "INSERT INTO sessions (id, task_id, created_at, updated_at, state, input_path, elapsed_s, args) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                session.id,
+                session.task_id,
+                session.created_at,
+                session.updated_at,
+                session.state,
+                session.input_path,
+                session.elapsed_s,
+                session.args
+            ],
+        )?;
+        for (position, file) in files.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO session_files (session_id, position, path, frames, state) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    session.id,
+                    position as i64,
+                    file.path,
+                    file.frames as i64,
+                    file.state
+                ],
+            )?;
+        }
+        transaction.commit()
+    }
+
+    pub fn session(&self, id: &str) -> Result<Option<Session>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT {} FROM sessions WHERE id=?1", SESSION_COLUMNS),
+            params![id],
+            Session::from_row,
+        )
+        .optional()
+    }
+
+    // The newest session that is not done, ids are uuid v7 and sort by creation time
+    pub fn last_unfinished_session(&self) -> Result<Option<Session>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!(
+                "SELECT {} FROM sessions WHERE state != 'done' ORDER BY id DESC LIMIT 1",
+                SESSION_COLUMNS
+            ),
+            params![],
+            Session::from_row,
+        )
+        .optional()
+    }
+
+    // All sessions, the newest first
+    pub fn sessions(&self) -> Result<Vec<Session>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM sessions ORDER BY id DESC",
+            SESSION_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![], Session::from_row)?;
+        rows.collect()
+    }
+
+    // The files of session id in their order
+    pub fn session_files(&self, id: &str) -> Result<Vec<SessionFile>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT path, frames, state FROM session_files WHERE session_id=?1 ORDER BY position",
+        )?;
+        let rows = stmt.query_map(params![id], |row| {
+            Ok(SessionFile {
+                path: row.get(0)?,
+                frames: row.get::<_, i64>(1)?.max(0) as u64,
+                state: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn set_session_file_state(&self, id: &str, path: &str, state: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE session_files SET state=?1 WHERE session_id=?2 AND path=?3",
+            params![state, id, path],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_session(&self, id: &str, state: &str, elapsed_s: i64) -> Result<()> {
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sessions SET state=?1, elapsed_s=?2, updated_at=?3 WHERE id=?4",
+            params![state, elapsed_s, now, id],
+        )?;
+        Ok(())
+    }
+
+    // Remove the done sessions and the ones updated before before, an RFC 3339 UTC time, returns
+    // how many were removed
+    pub fn delete_sessions(&self, before: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let transaction = conn.transaction()?;
+        let condition = "state = 'done' OR updated_at < ?1";
+        transaction.execute(
+            &format!(
+                "DELETE FROM session_files WHERE session_id IN (SELECT id FROM sessions WHERE {})",
+                condition
+            ),
+            params![before],
+        )?;
+        let removed = transaction.execute(
+            &format!("DELETE FROM sessions WHERE {}", condition),
+            params![before],
+        )?;
+        transaction.commit()?;
+        Ok(removed)
+    }
+}
+
+// Remove the done sessions and, with older_than ("30d"), the unfinished ones not updated for that
+// long, returns how many were removed
+pub fn clean_sessions(older_than: Option<&str>) -> Result<usize, String> {
+    let before = match older_than {
+        Some(older_than) => {
+            let age = humantime::parse_duration(older_than)
+                .map_err(|e| format!("invalid age {}: {}", older_than, e))?;
+            SystemTime::now()
+                .checked_sub(age)
+                .map(|before| humantime::format_rfc3339_seconds(before).to_string())
+                .unwrap_or_default()
+        }
+        // no updated_at is before the empty string
+        None => String::new(),
+    };
+    db().delete_sessions(&before).map_err(|e| e.to_string())
+}
+
+pub fn sessions_json(sessions: &[Session]) -> String {
+    serde_json::to_string_pretty(sessions).unwrap_or_default()
+}
+
+// The report of `sessions list`, the newest first
+pub fn print_sessions(sessions: &[Session]) {
+    if sessions.is_empty() {
+        println!("No sessions");
+        return;
+    }
+    for session in sessions {
+        println!("{} {}", session.id.bold(), session.state);
+        println!(
+            "  {} of {} files done, started {}, updated {}, ran {}",
+            session.done_files,
+            session.files,
+            session.created_at,
+            session.updated_at,
+            humantime::format_duration(Duration::from_secs(session.elapsed_s.max(0) as u64))
+        );
+        println!("  {}", session.input_path);
+    }
+}
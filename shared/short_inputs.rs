@@ -0,0 +1,34 @@
+// --min-duration: trailers, samples and clips shorter than the samples of a CRF search are encoded at
+// --short-input-crf without the search, or skipped with --skip-short. Chunked mode encodes them, and
+// files that split into fewer than 2 scenes, like the default mode
+
+use crate::*;
+
+// The duration of file when it is shorter than --min-duration, None for longer files and files
+// without a duration
+pub(crate) fn short_input_duration(file: &str, args: &Args) -> Option<f32> {
+    get_duration(file).filter(|duration| (*duration as f64) < args.min_duration)
+}
+
+// --skip-short, a run without it encodes the file again
+pub(crate) fn skip_short_input(file: &str, duration: f32, min_duration: f64) {
+    info!(
+        "Skipping {}: {:.1}s, shorter than --min-duration {}s",
+        file, duration, min_duration
+    );
+    if let Err(e) = set_db_status(file, "skipped_short") {
+        error!("Failed to update status of {}: {}", file, e);
+    }
+}
+
+// The scenes of scene_changes that have a frame at fps, the others are rounded away by the chunked
+// encode
+pub(crate) fn usable_scenes(scene_changes: &[f32], fps: f32) -> usize {
+    scene_changes
+        .windows(2)
+        .filter(|scene| ((scene[1] - scene[0]) * fps).round() >= 1.0)
+        .count()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,45 @@
+use super::*;
+
+// A 24 fps file of duration seconds
+fn clip(name: &str, duration: f32) -> String {
+    probed_file(
+        name,
+        serde_json::json!({
+            "streams": [{
+                "index": 0, "codec_type": "video", "codec_name": "h264",
+                "width": 1920, "height": 1080, "r_frame_rate": "24/1",
+            }],
+            "format": {"duration": duration.to_string()},
+        }),
+    )
+}
+
+#[test]
+fn a_file_below_min_duration_is_a_short_input() {
+    let args = test_args(&[]);
+    assert_eq!(args.min_duration, 60.0);
+    assert_eq!(args.short_input_crf, 20.0);
+    assert!(!args.skip_short);
+
+    let five_seconds = clip("short-5s.mkv", 5.0);
+    assert_eq!(short_input_duration(&five_seconds, &args), Some(5.0));
+    let minute = clip("short-60s.mkv", 60.0);
+    assert_eq!(short_input_duration(&minute, &args), None);
+
+    let args = test_args(&["--min-duration", "1"]);
+    assert_eq!(short_input_duration(&five_seconds, &args), None);
+}
+
+#[test]
+fn scenes_without_a_frame_are_not_usable() {
+    // a 5 second clip with no scene change
+    assert_eq!(usable_scenes(&[0.0, 5.0], 24.0), 1);
+    // a change in its first frame leaves a scene of less than half a frame
+    assert_eq!(usable_scenes(&[0.0, 0.01, 5.0], 24.0), 1);
+    assert_eq!(usable_scenes(&[0.0, 0.03, 5.0], 24.0), 2);
+    assert_eq!(usable_scenes(&[0.0, 2.5, 5.0], 24.0), 2);
+    assert_eq!(usable_scenes(&[0.0], 24.0), 0);
+    assert_eq!(usable_scenes(&[], 24.0), 0);
+    // without a frame rate nothing can be encoded
+    assert_eq!(usable_scenes(&[0.0, 2.5, 5.0], 0.0), 0);
+}
@@ -0,0 +1,128 @@
+// After a successful encode: --preserve-mtime gives the output the modified time of the source and
+// --copy-sidecars copies the subtitles, nfo and artwork named like the source next to the output,
+// so media servers sort and match the encodes like their sources
+
+use crate::*;
+
+// The files next to file named <stem>.<anything> that are no videos, as (path, name after the stem)
+// movie.en.srt of movie.mkv is (.../movie.en.srt, ".en.srt")
+pub fn sidecar_files(file: &str) -> Vec<(PathBuf, String)> {
+    let path = Path::new(file);
+    let stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) => stem,
+        None => return Vec::new(),
+    };
+    let folder = match path.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read {}: {}", folder.display(), e);
+            return Vec::new();
+        }
+    };
+    let prefix = format!("{}.", stem);
+    let mut sidecars: Vec<(PathBuf, String)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|sidecar| sidecar.is_file() && !is_video_file(sidecar))
+        .filter_map(|sidecar| {
+            let name = sidecar.file_name()?.to_str()?.to_string();
+            let suffix = name.strip_prefix(&prefix)?.to_string();
+            Some((sidecar, format!(".{}", suffix)))
+        })
+        .collect();
+    sidecars.sort();
+    sidecars
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Set the modified time of output to the one of source
+fn copy_mtime(source: &Path, output: &Path) -> Result<(), io::Error> {
+    let mtime = fs::metadata(source)?.modified()?;
+    File::options()
+        .write(true)
+        .open(output)?
+        .set_modified(mtime)
+}
+
+// --copy-sidecars: copy the sidecar files of file to the folder of output, renamed to its stem
+// A destination modified after its sidecar is kept, returns the copied destinations
+pub fn copy_sidecars(file: &str, output: &str, preserve_mtime: bool) -> Vec<String> {
+    let output = Path::new(output);
+    let (folder, stem) = match (
+        output.parent(),
+        output.file_stem().and_then(|stem| stem.to_str()),
+    ) {
+        (Some(folder), Some(stem)) => (folder, stem),
+        _ => return Vec::new(),
+    };
+    let mut copied = Vec::new();
+    for (sidecar, suffix) in sidecar_files(file) {
+        let destination = folder.join(format!("{}{}", stem, suffix));
+        if destination == sidecar {
+            continue;
+        }
+        if let (Some(existing), Some(source)) = (modified(&destination), modified(&sidecar)) {
+            if existing > source {
+                info!("Kept the newer {}", destination.display());
+                continue;
+            }
+        }
+        if let Err(e) = fs::copy(&sidecar, &destination) {
+            warn!(
+                "Failed to copy {} to {}: {}",
+                sidecar.display(),
+                destination.display(),
+                e
+            );
+            continue;
+        }
+        if preserve_mtime {
+            if let Err(e) = copy_mtime(&sidecar, &destination) {
+                warn!(
+                    "Failed to set the modified time of {}: {}",
+                    destination.display(),
+                    e
+                );
+            }
+        }
+        copied.push(destination.display().to_string());
+    }
+    copied
+}
+
+// The post-encode step of --preserve-mtime and --copy-sidecars for file encoded to output, what
+// was done goes to the task log
+pub fn finish_output(file: &str, output: &str, args: &Args) {
+    if args.preserve_mtime {
+        match copy_mtime(Path::new(file), Path::new(output)) {
+            Ok(()) => {
+                debug!("Set the modified time of {} to the one of {}", output, file);
+                record_task_log(
+                    &args.task_id,
+                    &format!("preserve-mtime {}", output),
+                    "modified time of the source",
+                );
+            }
+            Err(e) => warn!("Failed to set the modified time of {}: {}", output, e),
+        }
+    }
+    if args.copy_sidecars {
+        let copied = copy_sidecars(file, output, args.preserve_mtime);
+        if !copied.is_empty() {
+            info!("Copied {} sidecar files of {}", copied.len(), file);
+        }
+        record_task_log(
+            &args.task_id,
+            &format!("copy-sidecars {}", output),
+            &copied.join("\n"),
+        );
+    }
+    record_task_output(&args.task_id, output);
+}
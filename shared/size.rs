@@ -0,0 +1,40 @@
+// File sizes are u64 bytes, converted to MB (1024 * 1024 bytes, like ffmpeg's kB) only to be shown
+// or compared with the MB and GB options
+
+pub const KB: f64 = 1024.0;
+pub const MB: f64 = 1024.0 * 1024.0;
+pub const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+pub const TB: f64 = 1024.0 * 1024.0 * 1024.0 * 1024.0;
+
+pub fn bytes_to_mb(bytes: u64) -> f64 {
+    bytes as f64 / MB
+}
+
+pub fn mb_to_bytes(mb: f64) -> u64 {
+    (mb.max(0.0) * MB) as u64
+}
+
+// 812.5 kB, 96.30 MB, 80.00 GB, 1.80 TB
+pub fn format_size(bytes: u64) -> String {
+    let size = bytes as f64;
+    if size >= TB {
+        format!("{:.2} TB", size / TB)
+    } else if size >= GB {
+        format!("{:.2} GB", size / GB)
+    } else if size >= MB {
+        format!("{:.2} MB", size / MB)
+    } else {
+        format!("{:.1} kB", size / KB)
+    }
+}
+
+// How much smaller output is than input in percent, negative when it grew, 0 for an empty input
+pub fn reduction_percent(input: u64, output: u64) -> f64 {
+    if input == 0 {
+        return 0.0;
+    }
+    (1.0 - output as f64 / input as f64) * 100.0
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,44 @@
+use super::*;
+
+#[test]
+fn format_size_from_100_kb_to_80_gb() {
+    let cases = [
+        (100 * 1024, "100.0 kB"),
+        (832_000, "812.5 kB"),
+        // just below a MB is still shown in kB
+        (1024 * 1024 - 1, "1024.0 kB"),
+        (1024 * 1024, "1.00 MB"),
+        (100_978_278, "96.30 MB"),
+        (1024 * 1024 * 1024 - 1, "1024.00 MB"),
+        (1024 * 1024 * 1024, "1.00 GB"),
+        (4_000_000_000, "3.73 GB"),
+        (80 * 1024 * 1024 * 1024, "80.00 GB"),
+        (1_979_120_929_996, "1.80 TB"),
+    ];
+    for (bytes, expected) in cases {
+        assert_eq!(format_size(bytes), expected, "{}", bytes);
+    }
+    assert_eq!(format_size(0), "0.0 kB");
+}
+
+#[test]
+fn mb_and_bytes_round_trip_for_large_files() {
+    // an 80 GB remux, f32 would be off by kilobytes
+    let bytes: u64 = 85_899_345_921;
+    assert_eq!(mb_to_bytes(bytes_to_mb(bytes)), bytes);
+    assert_eq!(mb_to_bytes(4000.0), 4000 * 1024 * 1024);
+    assert_eq!(bytes_to_mb(1024 * 1024 * 1024), 1024.0);
+    // a size below zero is no size
+    assert_eq!(mb_to_bytes(-1.0), 0);
+}
+
+#[test]
+fn reduction_percent_of_smaller_larger_and_empty_outputs() {
+    assert_eq!(reduction_percent(1000, 400), 60.0);
+    assert_eq!(reduction_percent(1000, 1000), 0.0);
+    assert_eq!(reduction_percent(1000, 1500), -50.0);
+    assert_eq!(reduction_percent(0, 400), 0.0);
+    assert_eq!(reduction_percent(80 * 1024 * 1024 * 1024, 0), 100.0);
+    // a byte saved of 80 GB still shows
+    assert!(reduction_percent(85_899_345_920, 85_899_345_919) > 0.0);
+}
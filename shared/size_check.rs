@@ -0,0 +1,145 @@
+// --discard-if-larger and --warn-if-larger: an encode that came out larger than --max-output-ratio of
+// its source is deleted and the original kept (status kept_original), or kept and listed in red at
+// the end of the run. A chunked encode is checked after a fifth of its scenes as well
+
+use crate::*;
+
+// The share of the scenes of a chunked encode that is encoded before it can give up
+const CHUNKED_CHECK_SCENES: f64 = 0.2;
+
+// An encode larger than --max-output-ratio of its source, for the summary and --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeCheck {
+    pub path: String,
+    // empty when a chunked encode gave up before its scenes were concatenated
+    pub output: String,
+    pub input_size: u64,
+    // estimated from the encoded scenes when a chunked encode gave up
+    pub output_size: u64,
+    // output_size / input_size
+    pub ratio: f64,
+    // kept_original or larger
+    pub decision: String,
+}
+
+fn output_ratio(input_size: u64, output_size: u64) -> f64 {
+    if input_size == 0 {
+        0.0
+    } else {
+        output_size as f64 / input_size as f64
+    }
+}
+
+// Check a finished encode of file, None when it is small enough or neither flag is set
+pub(crate) fn check_output_size(file: &str, output: &str, args: &Args) -> Option<SizeCheck> {
+    if !args.discard_if_larger && !args.warn_if_larger {
+        return None;
+    }
+    let input_size = get_file_size(file).unwrap_or(0);
+    let output_size = get_file_size(output).unwrap_or(0);
+    if input_size == 0 || output_ratio(input_size, output_size) <= args.max_output_ratio {
+        return None;
+    }
+    if args.discard_if_larger {
+        if let Err(e) = fs::remove_file(output) {
+            warn!("Failed to remove {}: {}", output, e);
+        }
+        // the lifetime savings only count the encodes that were kept
+        if let Err(e) = db().delete_encode_stats(output) {
+            warn!("Failed to remove the stats of {}: {}", output, e);
+        }
+        return Some(keep_original(file, output, input_size, output_size));
+    }
+    let check = size_check(file, output, input_size, output_size, "larger");
+    warn!(
+        "{}",
+        format!(
+            "{} is larger than its source: {} -> {} ({:.0}%)",
+            output,
+            format_size(input_size),
+            format_size(output_size),
+            check.ratio * 100.0
+        )
+        .red()
+    );
+    Some(check)
+}
+
+// Set file to kept_original once its encode, finished or estimated, was discarded
+pub(crate) fn keep_original(
+    file: &str,
+    output: &str,
+    input_size: u64,
+    output_size: u64,
+) -> SizeCheck {
+    let check = size_check(file, output, input_size, output_size, "kept_original");
+    warn!(
+        "{}",
+        format!(
+            "{}: the encode is larger than the source, {} -> {} ({:.0}%), keeping the original",
+            file,
+            format_size(input_size),
+            format_size(output_size),
+            check.ratio * 100.0
+        )
+        .yellow()
+    );
+    if let Err(e) = set_db_status(file, "kept_original") {
+        error!("Failed to update status of {}: {}", file, e);
+    }
+    check
+}
+
+fn size_check(
+    file: &str,
+    output: &str,
+    input_size: u64,
+    output_size: u64,
+    decision: &str,
+) -> SizeCheck {
+    SizeCheck {
+        path: file.to_string(),
+        output: output.to_string(),
+        input_size,
+        output_size,
+        ratio: output_ratio(input_size, output_size),
+        decision: decision.to_string(),
+    }
+}
+
+// A chunked encode with --discard-if-larger gives up once a fifth of its scenes are encoded and
+// they are larger than --max-output-ratio of the same scenes of the source
+pub(crate) fn chunked_encode_losing(
+    args: &Args,
+    encoded_scenes: usize,
+    total_scenes: usize,
+    source_size: u64,
+    encoded_size: u64,
+) -> bool {
+    args.discard_if_larger
+        && total_scenes > 0
+        && encoded_scenes as f64 >= total_scenes as f64 * CHUNKED_CHECK_SCENES
+        && source_size > 0
+        && output_ratio(source_size, encoded_size) > args.max_output_ratio
+}
+
+// The summary lines of the encodes larger than their source
+pub(crate) fn log_size_checks(checks: &[SizeCheck]) {
+    if checks.is_empty() {
+        return;
+    }
+    warn!("{} encodes were larger than their source:", checks.len());
+    for check in checks {
+        let line = format!(
+            "  {}: {} -> {} ({:.0}%)",
+            check.path,
+            format_size(check.input_size),
+            format_size(check.output_size),
+            check.ratio * 100.0
+        );
+        match check.decision.as_str() {
+            "kept_original" => warn!("{}, kept the original", line),
+            _ => warn!("{}", format!("{}, kept {}", line, check.output).red()),
+        }
+    }
+}
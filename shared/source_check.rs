@@ -0,0 +1,148 @@
+// Sources that are still being written, by a torrent client or a NAS sync: a file modified less than
+// --settle-seconds ago is encoded at the end of the batch, and the size, mtime and content hash of
+// a source taken when its processing starts are compared again right before its output is renamed
+// in place. A source that changed in between loses its output, gets the status source_changed and
+// is encoded once more at the end of the batch
+
+use crate::*;
+
+// What a source looked like when its processing started
+#[derive(Debug, Clone, PartialEq)]
+struct SourceSnapshot {
+    size: u64,
+    modified: Option<SystemTime>,
+    hash: String,
+}
+
+impl SourceSnapshot {
+    fn take(file: &str) -> io::Result<SourceSnapshot> {
+        let metadata = fs::metadata(file)?;
+        Ok(SourceSnapshot {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            hash: content_hash(file)?,
+        })
+    }
+}
+
+lazy_static! {
+    // The snapshots of the files being processed, by source
+    static ref SOURCE_SNAPSHOTS: Mutex<HashMap<String, SourceSnapshot>> = Mutex::new(HashMap::new());
+    // The sources check_source_unchanged found changed, with how, until the pipeline takes them
+    static ref SOURCE_CHANGES: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+// The snapshot of a source, until it is dropped the outputs of the source are checked against it
+pub(crate) struct SourceWatch {
+    file: String,
+}
+
+impl Drop for SourceWatch {
+    fn drop(&mut self) {
+        SOURCE_SNAPSHOTS.lock().unwrap().remove(&self.file);
+    }
+}
+
+// Snapshot file at the start of its processing, None when it can't be read, the encode then fails
+// on its own
+pub(crate) fn watch_source(file: &str) -> Option<SourceWatch> {
+    match SourceSnapshot::take(file) {
+        Ok(snapshot) => {
+            SOURCE_SNAPSHOTS
+                .lock()
+                .unwrap()
+                .insert(file.to_string(), snapshot);
+            Some(SourceWatch {
+                file: file.to_string(),
+            })
+        }
+        Err(e) => {
+            warn!("Failed to snapshot {}: {}", file, e);
+            None
+        }
+    }
+}
+
+// Right before an output of file is renamed in place: an error when the source differs from its
+// snapshot, the pipeline then requeues it
+pub(crate) fn check_source_unchanged(file: &str) -> Result<(), String> {
+    let before = match SOURCE_SNAPSHOTS.lock().unwrap().get(file).cloned() {
+        Some(before) => before,
+        None => return Ok(()),
+    };
+    let change = match SourceSnapshot::take(file) {
+        Ok(now) if now == before => return Ok(()),
+        Ok(now) if now.size != before.size => {
+            format!("its size changed from {} to {}", before.size, now.size)
+        }
+        Ok(now) if now.modified != before.modified => "its modification time changed".to_string(),
+        Ok(_) => "its content changed".to_string(),
+        Err(e) => format!("it can't be read anymore: {}", e),
+    };
+    warn!(
+        "{}",
+        format!(
+            "{} changed during its encode, {}, discarding the output",
+            file, change
+        )
+        .yellow()
+    );
+    SOURCE_CHANGES
+        .lock()
+        .unwrap()
+        .insert(file.to_string(), change.clone());
+    Err(format!("{} changed during its encode, {}", file, change))
+}
+
+// How file changed during its encode, once, None when it did not
+pub(crate) fn take_source_change(file: &str) -> Option<String> {
+    SOURCE_CHANGES.lock().unwrap().remove(file)
+}
+
+// How long file has to stay as it is before it is settled, None when it was last modified at least
+// settle ago or its time can't be read
+pub(crate) fn settle_remaining(file: &str, settle: Duration) -> Option<Duration> {
+    let modified = fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    // a modification time in the future counts as now
+    let age = modified.elapsed().unwrap_or_default();
+    settle
+        .checked_sub(age)
+        .filter(|remaining| !remaining.is_zero())
+}
+
+// Why a file of the batch was put back to its end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Deferral {
+    // modified less than --settle-seconds ago
+    Unsettled,
+    // changed during its encode
+    SourceChanged,
+}
+
+// A file one of the checks put back to the end of the batch, for the summary and --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceCheck {
+    pub path: String,
+    // "unsettled" or "source_changed"
+    pub check: String,
+    pub detail: String,
+    // "encoded", "failed", "changed again", "still changing" or "not retried"
+    pub outcome: String,
+}
+
+impl SourceCheck {
+    pub(crate) fn new(path: &str, deferral: Deferral, detail: &str, outcome: &str) -> SourceCheck {
+        SourceCheck {
+            path: path.to_string(),
+            check: match deferral {
+                Deferral::Unsettled => "unsettled",
+                Deferral::SourceChanged => "source_changed",
+            }
+            .to_string(),
+            detail: detail.to_string(),
+            outcome: outcome.to_string(),
+        }
+    }
+}
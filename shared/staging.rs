@@ -0,0 +1,310 @@
+// --stage-dir: the sources of a run on a slow share are copied to a local folder first, the next
+// file while the current one encodes, and the encodes read the copy. The database, the output names
+// and the sidecars keep the source. Every run stages into a folder of its own with a locked marker
+// file, the folders of a crashed run are unlocked and removed by the next run
+
+use crate::*;
+
+use fs2::FileExt;
+use sha2::{Digest, Sha256};
+
+// The marker of a staging folder, locked while its run is alive
+const STAGING_MARKER: &str = ".staging";
+const COPY_BLOCK: usize = 1024 * 1024;
+
+lazy_static! {
+    // The staged copy of the files being encoded, by source
+    static ref STAGED_INPUTS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    // The staging folders of this process, removed by the ctrl-C handler
+    static ref STAGING_FOLDERS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+}
+
+// The path the encodes of file read, its staged copy when it has one
+pub(crate) fn staged_input(file: &str) -> String {
+    STAGED_INPUTS
+        .lock()
+        .unwrap()
+        .get(file)
+        .cloned()
+        .unwrap_or_else(|| file.to_string())
+}
+
+// Remove the staging folders of this process, for ctrl-C which exits without dropping the Stager
+pub fn remove_staging_folders() {
+    for folder in STAGING_FOLDERS.lock().unwrap().drain(..) {
+        match fs::remove_dir_all(&folder) {
+            Ok(_) => info!("Removed {}", folder.display()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove {}: {}", folder.display(), e),
+        }
+    }
+}
+
+// A file of the run that was encoded from its staged copy, for the summary and --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct StagedFile {
+    pub path: String,
+    pub size: u64,
+    pub seconds: f64,
+    pub mb_per_second: f64,
+}
+
+struct StagedCopy {
+    source: String,
+    copy: PathBuf,
+    staged: StagedFile,
+}
+
+// Remove the staging folders in stage_dir whose marker is not locked, their run has ended without
+// removing them
+fn remove_stale_staging(stage_dir: &Path) {
+    let entries = match fs::read_dir(stage_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let folder = entry.path();
+        let marker = match File::open(folder.join(STAGING_MARKER)) {
+            Ok(marker) => marker,
+            Err(_) => continue,
+        };
+        if marker.try_lock_exclusive().is_err() {
+            continue;
+        }
+        let _ = marker.unlock();
+        drop(marker);
+        match fs::remove_dir_all(&folder) {
+            Ok(_) => warn!("Removed the stale staging folder {}", folder.display()),
+            Err(e) => warn!(
+                "Failed to remove the stale staging folder {}: {}",
+                folder.display(),
+                e
+            ),
+        }
+    }
+}
+
+// sha256 of the file at path
+fn file_sha256(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut block = vec![0; COPY_BLOCK];
+    loop {
+        let n = file.read(&mut block)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&block[..n]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+// Copy source to copy with a progress bar, hashing what was read. Stops when cancel is set
+fn copy_with_sha256(source: &str, copy: &Path, cancel: &AtomicBool) -> io::Result<Vec<u8>> {
+    let mut input = File::open(source)?;
+    let mut output = File::create(copy)?;
+    let size = input.metadata()?.len();
+    let bar = progress_bar(size);
+    let style = "[stg][{elapsed_precise}][{wide_bar:.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} [ETA: {eta:<3}]";
+    bar.set_style(ProgressStyle::default_bar().template(style).unwrap());
+
+    let mut hasher = Sha256::new();
+    let mut block = vec![0; COPY_BLOCK];
+    loop {
+        if cancel.load(Ordering::SeqCst) || shutdown_requested() {
+            bar.finish_and_clear();
+            return Err(Error::new(ErrorKind::Interrupted, "the run was stopped"));
+        }
+        let n = input.read(&mut block)?;
+        if n == 0 {
+            break;
+        }
+        output.write_all(&block[..n])?;
+        hasher.update(&block[..n]);
+        bar.inc(n as u64);
+    }
+    output.sync_all()?;
+    bar.finish_and_clear();
+    Ok(hasher.finalize().to_vec())
+}
+
+// Copy source into folder as the index-th staged file, None when it does not fit or the copy
+// failed, the file is then encoded from where it is
+fn stage_copy(
+    source: &str,
+    folder: &Path,
+    index: usize,
+    min_free_space: f64,
+    cancel: &AtomicBool,
+) -> Option<StagedCopy> {
+    let size = get_file_size(source).unwrap_or(0);
+    if let Err(e) = check_free_space(&folder.to_string_lossy(), bytes_to_mb(size), min_free_space) {
+        warn!(
+            "Not staging {}, encoding it from where it is: {}",
+            source, e
+        );
+        return None;
+    }
+    let name = Path::new(source)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // two files of the run can have the same name
+    let copy = folder.join(format!("{}_{}", index, name));
+    info!("Staging {} to {}", source, copy.display());
+
+    let started = Instant::now();
+    let result = copy_with_sha256(source, &copy, cancel).and_then(|source_hash| {
+        if file_sha256(&copy)? == source_hash {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                "the staged copy differs from the source",
+            ))
+        }
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&copy);
+        if e.kind() != ErrorKind::Interrupted {
+            warn!(
+                "Failed to stage {}, encoding it from where it is: {}",
+                source, e
+            );
+        }
+        return None;
+    }
+
+    let seconds = started.elapsed().as_secs_f64();
+    let staged = StagedFile {
+        path: source.to_string(),
+        size,
+        seconds,
+        mb_per_second: if seconds > 0.0 {
+            bytes_to_mb(size) / seconds
+        } else {
+            0.0
+        },
+    };
+    info!(
+        "Staged {} ({}) in {:.1}s, {:.1} MB/s",
+        source,
+        format_size(size),
+        seconds,
+        staged.mb_per_second
+    );
+    Some(StagedCopy {
+        source: source.to_string(),
+        copy,
+        staged,
+    })
+}
+
+// The staging folder of a run, one file is encoded from it while the next one is copied
+pub(crate) struct Stager {
+    folder: PathBuf,
+    min_free_space: f64,
+    // locked until the Stager is dropped
+    marker: Option<File>,
+    staged_count: usize,
+    cancel: Arc<AtomicBool>,
+    // the copy of the next file, running in its thread
+    next: Option<(String, thread::JoinHandle<Option<StagedCopy>>)>,
+    current: Option<StagedCopy>,
+}
+
+impl Stager {
+    pub(crate) fn new(stage_dir: &str, min_free_space: f64) -> Result<Stager, String> {
+        let stage_dir = Path::new(stage_dir);
+        fs::create_dir_all(stage_dir)
+            .map_err(|e| format!("Failed to create {}: {}", stage_dir.display(), e))?;
+        remove_stale_staging(stage_dir);
+
+        let folder = stage_dir.join(format!("stage_{}", uuid::Uuid::now_v7()));
+        fs::create_dir_all(&folder)
+            .map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+        let marker = File::create(folder.join(STAGING_MARKER))
+            .and_then(|marker| marker.lock_exclusive().map(|_| marker))
+            .map_err(|e| format!("Failed to lock {}: {}", folder.display(), e))?;
+        STAGING_FOLDERS.lock().unwrap().push(folder.clone());
+
+        Ok(Stager {
+            folder,
+            min_free_space,
+            marker: Some(marker),
+            staged_count: 0,
+            cancel: Arc::new(AtomicBool::new(false)),
+            next: None,
+            current: None,
+        })
+    }
+
+    fn start_copy(&mut self, file: &str) -> thread::JoinHandle<Option<StagedCopy>> {
+        self.staged_count += 1;
+        let file = file.to_string();
+        let folder = self.folder.clone();
+        let index = self.staged_count;
+        let min_free_space = self.min_free_space;
+        let cancel = Arc::clone(&self.cancel);
+        thread::spawn(move || stage_copy(&file, &folder, index, min_free_space, &cancel))
+    }
+
+    // Point the encodes of file at its staged copy, the prefetched one or a copy made now, and
+    // start copying next. Returns how file was staged, None when it is encoded from where it is
+    pub(crate) fn stage(&mut self, file: &str, next: Option<&str>) -> Option<StagedFile> {
+        self.release();
+        let current = match self.next.take() {
+            Some((prefetched, handle)) if prefetched == file => handle.join().ok().flatten(),
+            // the file of the prefetch was left out, its copy is not needed
+            Some((_, handle)) => {
+                if let Ok(Some(unused)) = handle.join() {
+                    let _ = fs::remove_file(&unused.copy);
+                }
+                self.start_copy(file).join().ok().flatten()
+            }
+            None => self.start_copy(file).join().ok().flatten(),
+        };
+        if let Some(next) = next {
+            self.next = Some((next.to_string(), self.start_copy(next)));
+        }
+        let current = current?;
+        STAGED_INPUTS.lock().unwrap().insert(
+            current.source.clone(),
+            current.copy.to_string_lossy().to_string(),
+        );
+        let staged = current.staged.clone();
+        self.current = Some(current);
+        Some(staged)
+    }
+
+    // The encode of the current file is done, its copy is removed
+    pub(crate) fn release(&mut self) {
+        if let Some(current) = self.current.take() {
+            STAGED_INPUTS.lock().unwrap().remove(&current.source);
+            if let Err(e) = fs::remove_file(&current.copy) {
+                warn!("Failed to remove {}: {}", current.copy.display(), e);
+            }
+        }
+    }
+}
+
+impl Drop for Stager {
+    // The end of the run, also a stopped one: the copy of the next file is stopped and the folder
+    // removed
+    fn drop(&mut self) {
+        self.release();
+        self.cancel.store(true, Ordering::SeqCst);
+        if let Some((_, handle)) = self.next.take() {
+            let _ = handle.join();
+        }
+        STAGING_FOLDERS
+            .lock()
+            .unwrap()
+            .retain(|folder| *folder != self.folder);
+        // windows can't remove an open file
+        drop(self.marker.take());
+        if let Err(e) = fs::remove_dir_all(&self.folder) {
+            warn!("Failed to remove {}: {}", self.folder.display(), e);
+        }
+    }
+}
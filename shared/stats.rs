@@ -0,0 +1,236 @@
+// Lifetime savings: every finished encode is a row in the stats table, the totals, the savings by
+// encoder and by month are summed in sql for the cli startup line and GET /stats
+
+use crate::*;
+
+// The sizes, VMAF and duration of one finished encode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncodeStats {
+    pub filepath: String,
+    pub output_path: String,
+    // "copy" for audio-only remuxes
+    pub encoder: String,
+    pub input_size: i64,
+    pub output_size: i64,
+    // seconds of video
+    pub duration: f64,
+    // the searched or verified VMAF, 0 when none was measured (bitrate encodes, remuxes)
+    pub vmaf: f64,
+    // the preset of the encoder, empty for remuxes
+    pub preset: String,
+    // the cost of the encode processes, 0 and None when it was not measured
+    pub wall_seconds: f64,
+    pub cpu_seconds: Option<f64>,
+    // source frames per wall second
+    pub average_fps: f64,
+    pub gpu_utilization: Option<f64>,
+    // RFC 3339 UTC
+    pub finished_at: String,
+}
+
+// Sums over a set of stats rows. Rows without an output size (0) count as files but are left out
+// of the byte totals, rows without a VMAF out of the average
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatsTotals {
+    pub files: i64,
+    pub input_bytes: i64,
+    pub output_bytes: i64,
+    pub saved_bytes: i64,
+    pub saved_percent: f64,
+    pub average_vmaf: Option<f64>,
+    // seconds of video encoded
+    pub duration: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderStats {
+    pub encoder: String,
+    #[serde(flatten)]
+    pub totals: StatsTotals,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonthStats {
+    // 2024-06, UTC
+    pub month: String,
+    #[serde(flatten)]
+    pub totals: StatsTotals,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LifetimeStats {
+    #[serde(flatten)]
+    pub totals: StatsTotals,
+    pub by_encoder: Vec<EncoderStats>,
+    // oldest month first
+    pub monthly: Vec<MonthStats>,
+}
+
+// The totals of the stats rows grouped by key, a sql expression, or of all rows without one, the
+// rows finished at or after since when given
+fn stats_totals(
+    conn: &Connection,
+    key: Option<&str>,
+    since: Option<&str>,
+) -> Result<Vec<(String, StatsTotals)>> {
+    let (select_key, group_by) = match key {
+        Some(key) => (key.to_string(), " GROUP BY 1 ORDER BY 1".to_string()),
+        None => ("''".to_string(), String::new()),
+    };
+    let filter = if since.is_some() {
+        " WHERE finished_at >= ?1"
+    } else {
+        ""
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, COUNT(*),
+            COALESCE(SUM(CASE WHEN output_size > 0 THEN input_size END), 0),
+            COALESCE(SUM(CASE WHEN output_size > 0 THEN output_size END), 0),
+            AVG(CASE WHEN vmaf > 0 THEN vmaf END),
+            COALESCE(SUM(duration), 0)
+         FROM stats{}{}",
+        select_key, filter, group_by
+    ))?;
+    let since: Vec<&str> = since.into_iter().collect();
+    let rows = stmt.query_map(rusqlite::params_from_iter(since), |row| {
+        let input_bytes: i64 = row.get(2)?;
+        let output_bytes: i64 = row.get(3)?;
+        Ok((
+            row.get(0)?,
+            StatsTotals {
+                files: row.get(1)?,
+                input_bytes,
+                output_bytes,
+                saved_bytes: input_bytes - output_bytes,
+                saved_percent: reduction_percent(input_bytes as u64, output_bytes as u64),
+                average_vmaf: row.get(4)?,
+                duration: row.get(5)?,
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+impl Db {
+    pub fn insert_encode_stats(&self, stats: &EncodeStats) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO stats (filepath, output_path, encoder, input_size, output_size, duration, vmaf, finished_at, preset, wall_seconds, cpu_seconds, average_fps, gpu_utilization) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                stats.filepath,
+                stats.output_path,
+                stats.encoder,
+                stats.input_size,
+                stats.output_size,
+                stats.duration,
+                stats.vmaf,
+                stats.finished_at,
+                stats.preset,
+                stats.wall_seconds,
+                stats.cpu_seconds,
+                stats.average_fps,
+                stats.gpu_utilization
+            ],
+        )?;
+        Ok(())
+    }
+
+    // --verify-vmaf replaces the searched VMAF of the latest encode of output with the measured one
+    pub fn update_encode_stats_vmaf(&self, output_path: &str, vmaf: f64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE stats SET vmaf=?1 WHERE id = (SELECT MAX(id) FROM stats WHERE output_path=?2)",
+            params![vmaf, output_path],
+        )?;
+        Ok(())
+    }
+
+    // --discard-if-larger removes the latest encode of output once it was deleted
+    pub fn delete_encode_stats(&self, output_path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM stats WHERE id = (SELECT MAX(id) FROM stats WHERE output_path=?1)",
+            params![output_path],
+        )?;
+        Ok(())
+    }
+
+    // The totals of the encodes finished at or after since, an RFC 3339 UTC time
+    pub fn stats_since(&self, since: &str) -> Result<StatsTotals> {
+        let conn = self.conn.lock().unwrap();
+        Ok(stats_totals(&conn, None, Some(since))?
+            .into_iter()
+            .next()
+            .map(|(_, totals)| totals)
+            .unwrap_or_default())
+    }
+
+    pub fn lifetime_stats(&self) -> Result<LifetimeStats> {
+        let conn = self.conn.lock().unwrap();
+        let totals = stats_totals(&conn, None, None)?
+            .into_iter()
+            .next()
+            .map(|(_, totals)| totals)
+            .unwrap_or_default();
+        let by_encoder = stats_totals(&conn, Some("encoder"), None)?
+            .into_iter()
+            .map(|(encoder, totals)| EncoderStats { encoder, totals })
+            .collect();
+        let monthly = stats_totals(&conn, Some("substr(finished_at, 1, 7)"), None)?
+            .into_iter()
+            .map(|(month, totals)| MonthStats { month, totals })
+            .collect();
+        Ok(LifetimeStats {
+            totals,
+            by_encoder,
+            monthly,
+        })
+    }
+}
+
+// Add a finished encode of file to the stats with its cost, a failure to write it is only logged
+pub fn record_encode_stats(
+    file: &str,
+    output: &str,
+    encoder: &str,
+    preset: &str,
+    vmaf: f64,
+    cost: Option<&EncodeCost>,
+) {
+    let size = |path: &str| fs::metadata(path).map_or(0, |metadata| metadata.len() as i64);
+    let cost = cost.cloned().unwrap_or_default();
+    let average_fps = if cost.wall_seconds > 0.0 {
+        frame_count(file) as f64 / cost.wall_seconds
+    } else {
+        0.0
+    };
+    let stats = EncodeStats {
+        filepath: file.to_string(),
+        output_path: output.to_string(),
+        encoder: encoder.to_string(),
+        input_size: size(file),
+        output_size: size(output),
+        duration: get_duration(file).unwrap_or(0.0) as f64,
+        vmaf,
+        finished_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        preset: preset.to_string(),
+        wall_seconds: cost.wall_seconds,
+        cpu_seconds: cost.cpu_seconds,
+        average_fps,
+        gpu_utilization: cost.gpu_utilization,
+    };
+    if let Err(e) = db().insert_encode_stats(&stats) {
+        warn!("Failed to record the stats of {}: {}", output, e);
+    }
+}
+
+// Lifetime: 412 files, 1.80 TB -> 640.00 GB (64% saved)
+pub fn lifetime_summary(totals: &StatsTotals) -> String {
+    format!(
+        "Lifetime: {} files, {} -> {} ({:.0}% saved)",
+        totals.files,
+        format_size(totals.input_bytes.max(0) as u64),
+        format_size(totals.output_bytes.max(0) as u64),
+        totals.saved_percent
+    )
+}
@@ -0,0 +1,195 @@
+// --stream-concat: each encoded scene is appended to one growing MPEG-TS file in scene order and
+// deleted right away, so the work dir holds the video encoded so far and the scenes that finished
+// ahead of their turn, instead of every scene plus the merged copy. A resumed run counts the frames
+// of the growing file to know which scenes it already has
+
+use crate::*;
+
+use std::collections::BTreeSet;
+
+pub(crate) struct StreamConcat {
+    path: String,
+    // the start frame of each scene by index, the growing file ends on one of them
+    scene_starts: Vec<u64>,
+    fps: f32,
+    // the next scene to append, every scene before it is in the growing file
+    next: i32,
+    // the scenes encoded ahead of next, their chunks wait in the work dir
+    waiting: BTreeSet<i32>,
+}
+
+impl StreamConcat {
+    // Open the growing file of chunks, a file that does not end on a scene boundary, left by a
+    // run killed while appending, is started over
+    pub(crate) fn open(
+        chunks: &ChunkFiles,
+        scene_changes: &[f32],
+        fps: f32,
+        total_frames: u64,
+    ) -> Result<StreamConcat, io::Error> {
+        let last_scene_change = scene_changes.last().cloned().unwrap_or(0.0);
+        let scene_starts: Vec<u64> = scene_changes
+            .windows(2)
+            .map(|window| {
+                scene_frame_range(window[0], window[1], fps, window[1] == last_scene_change).0
+            })
+            .collect();
+        // a trial starts at its range, the growing file at frame 0
+        let first_start = scene_starts.first().copied().unwrap_or(0);
+        let scene_starts: Vec<u64> = scene_starts
+            .into_iter()
+            .map(|start| start - first_start)
+            .collect();
+        let path = chunks.stream();
+        let mut next = 0;
+        if fs::metadata(&path).map_or(false, |m| m.len() > 0) {
+            let frames = get_framecount_ffmpeg(&path).unwrap_or(0.0) as u64;
+            next = match scene_starts.iter().position(|start| *start == frames) {
+                // a file without frames is started over as well
+                Some(index) if index > 0 => index as i32,
+                // every scene is in it, only the mux was left
+                None if frames > 0 && frames == total_frames => scene_starts.len() as i32,
+                _ => {
+                    warn!(
+                        "{} has {} frames, which is not the end of a scene, starting it over",
+                        path, frames
+                    );
+                    fs::remove_file(&path)?;
+                    0
+                }
+            };
+            info!("{} scenes already in {}", next, path);
+        }
+        Ok(StreamConcat {
+            path,
+            scene_starts,
+            fps,
+            next,
+            waiting: BTreeSet::new(),
+        })
+    }
+
+    // The scenes the growing file has, and the scenes of done that still have their chunk, those
+    // are appended once the scenes before them are
+    pub(crate) fn done_indices(
+        &mut self,
+        done: &[i32],
+        chunks: &ChunkFiles,
+    ) -> Result<Vec<i32>, io::Error> {
+        let scene_count = self.scene_starts.len() as i32;
+        for index in done {
+            // a run killed between appending a chunk and deleting it
+            if *index < self.next {
+                let _ = fs::remove_file(chunks.scene(*index));
+                continue;
+            }
+            let has_chunk = fs::metadata(chunks.scene(*index)).map_or(false, |m| m.len() > 0);
+            if *index < scene_count && has_chunk {
+                self.waiting.insert(*index);
+            }
+        }
+        self.append_waiting(chunks)?;
+        Ok((0..self.next).chain(self.waiting.iter().cloned()).collect())
+    }
+
+    // The scenes in the growing file and its size in bytes
+    pub(crate) fn appended(&self) -> (Vec<i32>, u64) {
+        let size = fs::metadata(&self.path).map_or(0, |m| m.len());
+        ((0..self.next).collect(), size)
+    }
+
+    // Scene index is encoded, append it and the waiting scenes that follow it
+    pub(crate) fn scene_done(&mut self, index: i32, chunks: &ChunkFiles) -> Result<(), io::Error> {
+        self.waiting.insert(index);
+        self.append_waiting(chunks)
+    }
+
+    fn append_waiting(&mut self, chunks: &ChunkFiles) -> Result<(), io::Error> {
+        while self.waiting.contains(&self.next) {
+            self.append(chunks, self.next)?;
+            self.waiting.remove(&self.next);
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    // Remux the chunk of scene index to MPEG-TS with its timestamps moved to where the scene
+    // starts, so the appended pieces play as one stream, then delete it
+    fn append(&self, chunks: &ChunkFiles, index: i32) -> Result<(), io::Error> {
+        let chunk = chunks.scene(index);
+        let part = format!("{}.part", self.path);
+        let start = self.scene_starts[index as usize] as f64 / self.fps as f64;
+        let output = tracked_output(
+            ffmpeg_command()
+                .args(["-y", "-i", &chunk, "-map", "0:v", "-c", "copy"])
+                .args(["-output_ts_offset", &format!("{:.6}", start)])
+                .args(["-f", "mpegts", &part]),
+            &[&part],
+        )?;
+        if !output.status.success() {
+            let _ = fs::remove_file(&part);
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "Failed to append {} to {}: {}",
+                    chunk,
+                    self.path,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        let mut stream = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        io::copy(&mut File::open(&part)?, &mut stream)?;
+        stream.sync_all()?;
+        fs::remove_file(&part)?;
+        fs::remove_file(&chunk)?;
+        Ok(())
+    }
+
+    // Mux the growing file, which has to hold every scene, with the audio and subtitles of chunks
+    pub(crate) fn finish(
+        &self,
+        output_filename: &str,
+        source_file: &str,
+        chunks: &ChunkFiles,
+        args: &Args,
+    ) -> Result<(), io::Error> {
+        let scene_count = self.scene_starts.len() as i32;
+        if self.next < scene_count {
+            let waiting: Vec<String> = self.waiting.iter().map(i32::to_string).collect();
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                format!(
+                    "{} scenes expected, {} has {}, waiting for scene {}: {}",
+                    scene_count,
+                    self.path,
+                    self.next,
+                    self.next,
+                    waiting.join(", ")
+                ),
+            ));
+        }
+        // the growing file is kept, so the failing run can be inspected
+        set_concat_step("verify");
+        verify_scene_frames(source_file, &self.path)?;
+        set_concat_step("mux");
+        mux_non_video(
+            output_filename,
+            source_file,
+            &self.path,
+            &chunks.non_video(),
+            args,
+        )?;
+        info!("Videos concatenated successfully.");
+        fs::remove_file(&self.path)?;
+        fs::remove_file(chunks.non_video())?;
+        Ok(())
+    }
+}
+
+// the tests run a shell script as ffmpeg
+#[cfg(all(test, unix))]
+mod tests;
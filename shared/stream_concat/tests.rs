@@ -0,0 +1,135 @@
+use super::*;
+
+// The stub ffmpeg of the tests: a remux copies its input, the -i after -y, to its output, the last
+// argument, and reading a file to the end prints its bytes / 1000 as the frame count
+const STUB_FFMPEG: &str = r#"case "$*" in
+*" -f null "*) echo "frame=  $(( $(wc -c < "$2") / 1000 )) fps=0.0" >&2 ;;
+*) for last; do :; done; cp "$3" "$last" ;;
+esac
+"#;
+
+// 5 scenes of 1 second at 10 fps, 10 frames each
+const SCENE_CHANGES: [f32; 6] = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+const FPS: f32 = 10.0;
+const FRAMES: u64 = 50;
+const SCENE_BYTES: usize = 10_000;
+
+// The chunk of scene index, 10 frames of the stub of the bytes of its index
+fn encode_scene(chunks: &ChunkFiles, index: i32) {
+    fs::write(chunks.scene(index), vec![index as u8; SCENE_BYTES]).unwrap();
+}
+
+// The bytes of the files in the working directory
+fn scratch_bytes() -> u64 {
+    fs::read_dir(".")
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+// What the growing file holds after the scenes of indices
+fn appended_bytes(indices: std::ops::Range<i32>) -> Vec<u8> {
+    indices
+        .flat_map(|index| vec![index as u8; SCENE_BYTES])
+        .collect()
+}
+
+// Run test in a new work dir with the stub ffmpeg
+fn in_work_dir(name: &str, test: impl FnOnce(ChunkFiles)) {
+    let dir = temp_path(name);
+    fs::create_dir_all(&dir).unwrap();
+    with_stub("FFMPEG_BIN", STUB_FFMPEG, || {
+        in_dir(&dir, || {
+            test(ChunkFiles::new("source.mkv", SCENE_CHANGES.len() - 1))
+        })
+    });
+}
+
+#[test]
+fn scenes_finishing_out_of_order_are_appended_in_order() {
+    in_work_dir("stream-order", |chunks| {
+        let mut stream = StreamConcat::open(&chunks, &SCENE_CHANGES, FPS, FRAMES).unwrap();
+        let mut peak_scratch = 0;
+        // two threads: a scene finishes ahead of the one before it
+        for index in [1, 0, 3, 2, 4] {
+            encode_scene(&chunks, index);
+            let (_, appended) = stream.appended();
+            peak_scratch = peak_scratch.max(scratch_bytes() - appended);
+            stream.scene_done(index, &chunks).unwrap();
+        }
+
+        assert_eq!(stream.appended(), ((0..5).collect(), FRAMES * 1000));
+        assert_eq!(fs::read(chunks.stream()).unwrap(), appended_bytes(0..5));
+        for index in 0..5 {
+            assert!(!Path::new(&chunks.scene(index)).exists(), "{}", index);
+        }
+        // besides the growing file at most the scenes of the two threads, not every scene
+        assert_eq!(peak_scratch, 2 * SCENE_BYTES as u64);
+    });
+}
+
+#[test]
+fn a_resumed_run_keeps_the_scenes_of_the_growing_file() {
+    in_work_dir("stream-resume", |chunks| {
+        let mut stream = StreamConcat::open(&chunks, &SCENE_CHANGES, FPS, FRAMES).unwrap();
+        for index in 0..3 {
+            encode_scene(&chunks, index);
+            stream.scene_done(index, &chunks).unwrap();
+        }
+        // killed with scene 4 waiting for scene 3, and between appending scene 2 and deleting it
+        encode_scene(&chunks, 4);
+        encode_scene(&chunks, 2);
+
+        let mut stream = StreamConcat::open(&chunks, &SCENE_CHANGES, FPS, FRAMES).unwrap();
+        assert_eq!(stream.appended().0, vec![0, 1, 2]);
+        assert_eq!(
+            stream.done_indices(&[0, 1, 2, 4], &chunks).unwrap(),
+            vec![0, 1, 2, 4]
+        );
+        assert!(!Path::new(&chunks.scene(2)).exists());
+
+        encode_scene(&chunks, 3);
+        stream.scene_done(3, &chunks).unwrap();
+        assert_eq!(stream.appended().0, vec![0, 1, 2, 3, 4]);
+        assert_eq!(fs::read(chunks.stream()).unwrap(), appended_bytes(0..5));
+    });
+}
+
+#[test]
+fn a_growing_file_that_ends_mid_scene_is_started_over() {
+    in_work_dir("stream-mid-scene", |chunks| {
+        fs::write(chunks.stream(), vec![0; 25 * 1000]).unwrap();
+        let stream = StreamConcat::open(&chunks, &SCENE_CHANGES, FPS, FRAMES).unwrap();
+        assert_eq!(stream.appended(), (Vec::new(), 0));
+        assert!(!Path::new(&chunks.stream()).exists());
+
+        // every scene, only the mux was left
+        fs::write(chunks.stream(), appended_bytes(0..5)).unwrap();
+        let stream = StreamConcat::open(&chunks, &SCENE_CHANGES, FPS, FRAMES).unwrap();
+        assert_eq!(stream.appended().0, vec![0, 1, 2, 3, 4]);
+    });
+}
+
+#[test]
+fn finish_refuses_a_growing_file_without_every_scene() {
+    in_work_dir("stream-missing", |chunks| {
+        let mut stream = StreamConcat::open(&chunks, &SCENE_CHANGES, FPS, FRAMES).unwrap();
+        for index in [0, 2] {
+            encode_scene(&chunks, index);
+            stream.scene_done(index, &chunks).unwrap();
+        }
+        let e = stream
+            .finish("output.mkv", "source.mkv", &chunks, &test_args(&[]))
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "5 scenes expected, {} has 1, waiting for scene 1: 2",
+                chunks.stream()
+            )
+        );
+        // the waiting scene stays for the next run
+        assert!(Path::new(&chunks.scene(2)).exists());
+    });
+}
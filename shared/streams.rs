@@ -0,0 +1,550 @@
+// Audio and subtitle streams: which are kept, which audio is re-encoded at what bitrate, and the
+// -map and metadata arguments for them
+
+use crate::*;
+
+// Function to get the audio codec and channels for each audio stream
+// An audio stream of the input file, bitrate is in bits/s (0 if unknown)
+#[derive(Debug, Clone)]
+pub struct AudioStream {
+    pub index: usize,
+    pub codec: String,
+    pub channels: String,
+    pub bitrate: i64,
+    pub language: String,
+    pub default: bool,
+}
+
+// How audio streams get re-encoded
+#[derive(Debug, Clone)]
+pub struct AudioOptions {
+    pub codec: String,
+    pub max_bitrate_kbps: Option<i64>,
+    // --audio-bitrate-stereo, --audio-bitrate-51 and --audio-bitrate-71
+    pub stereo_kbps: Option<i64>,
+    pub surround_51_kbps: Option<i64>,
+    pub surround_71_kbps: Option<i64>,
+}
+
+impl AudioOptions {
+    pub fn from_args(args: &Args) -> AudioOptions {
+        AudioOptions {
+            codec: args.audio_codec.clone(),
+            max_bitrate_kbps: args.max_audio_bitrate,
+            stereo_kbps: args.audio_bitrate_stereo,
+            surround_51_kbps: args.audio_bitrate_51,
+            surround_71_kbps: args.audio_bitrate_71,
+        }
+    }
+}
+
+// The ffmpeg arguments for one audio stream, empty args means the stream is copied
+// index is the input stream (0:a:index), output_index the stream in the output (-c:a:output_index)
+#[derive(Debug, Clone)]
+pub struct AudioPlan {
+    pub index: usize,
+    pub output_index: usize,
+    pub args: String,
+    pub original_codec: String,
+    pub target_codec: String,
+}
+
+// Get codec, channel count and bitrate of every audio stream
+// Matroska files usually have no stream bit_rate, then the BPS tag is used
+pub fn get_audio_streams(file_path: &str) -> Vec<AudioStream> {
+    let info = probe_file(file_path);
+    let mut audio_streams = Vec::new();
+    for (index, stream) in info.streams_of("audio").into_iter().enumerate() {
+        let bitrate = stream["bit_rate"]
+            .as_str()
+            .or(stream["tags"]["BPS"].as_str())
+            .or(stream["tags"]["BPS-eng"].as_str())
+            .and_then(|bitrate| bitrate.parse::<i64>().ok())
+            .unwrap_or(0);
+        audio_streams.push(AudioStream {
+            index,
+            codec: stream["codec_name"].as_str().unwrap_or("NaN").to_string(),
+            channels: stream["channels"].as_i64().unwrap_or(0).to_string(),
+            bitrate,
+            language: stream["tags"]["language"]
+                .as_str()
+                .unwrap_or("")
+                .to_string(),
+            default: stream["disposition"]["default"].as_i64().unwrap_or(0) == 1,
+        });
+    }
+    audio_streams
+}
+
+// The layout a re-encoded stream gets for its channel count, one libopus takes with its surround
+// mapping and aac too. aformat converts layouts like 5.1(side) and 4.0 to it, which libopus refuses,
+// downmixes more than 8 channels to 7.1 and an unknown count to stereo
+fn target_layout(channels: &str) -> &'static str {
+    match channels.parse::<i64>().unwrap_or(0) {
+        1 => "mono",
+        3 => "3.0",
+        4 => "quad",
+        5 => "5.0",
+        6 => "5.1",
+        7 => "6.1",
+        8.. => "7.1",
+        _ => "stereo",
+    }
+}
+
+// Target bitrate in kbps for a layout of target_layout, the options override stereo, 5.1 and 7.1
+fn audio_target_bitrate(options: &AudioOptions, layout: &str) -> i64 {
+    let overridden = match layout {
+        "stereo" => options.stereo_kbps,
+        "5.1" => options.surround_51_kbps,
+        "7.1" => options.surround_71_kbps,
+        _ => None,
+    };
+    if let Some(bitrate) = overridden {
+        return bitrate;
+    }
+    match (options.codec.as_str(), layout) {
+        // 128 kbps is recommended for quality stereo music, 256 kbps for 5.1 and 450 kbps for 7.1
+        ("aac", "mono") => 96,
+        ("aac", "stereo") => 192,
+        ("aac", "3.0") => 256,
+        ("aac", "quad") => 320,
+        ("aac", "5.0") => 352,
+        ("aac", "5.1") => 384,
+        ("aac", "6.1") => 448,
+        ("aac", _) => 512,
+        (_, "mono") => 64,
+        (_, "stereo") => 128,
+        (_, "3.0") => 160,
+        (_, "quad") => 192,
+        (_, "5.0") => 224,
+        (_, "5.1") => 256,
+        (_, "6.1") => 352,
+        (_, _) => 450,
+    }
+}
+
+// Decide per audio stream whether to copy it or re-encode it
+// Streams that are not aac/opus/mp3, or that exceed the max bitrate, are re-encoded to the chosen codec
+// in the layout of target_layout
+// streams are the audio streams of the output in order, see select_streams
+pub fn plan_audio_streams(streams: &[AudioStream], options: &AudioOptions) -> Vec<AudioPlan> {
+    let mut plans = Vec::new();
+    for (output_index, stream) in streams.iter().enumerate() {
+        let allowed_codec =
+            stream.codec == "aac" || stream.codec == "opus" || stream.codec == "mp3";
+        let too_large = match options.max_bitrate_kbps {
+            Some(max_bitrate) => stream.bitrate > max_bitrate * 1000,
+            None => false,
+        };
+
+        if allowed_codec && !too_large {
+            plans.push(AudioPlan {
+                index: stream.index,
+                output_index,
+                args: "".to_string(),
+                original_codec: stream.codec.clone(),
+                target_codec: stream.codec.clone(),
+            });
+            continue;
+        }
+
+        let encoder = match options.codec.as_str() {
+            "aac" => "aac",
+            _ => "libopus",
+        };
+        let i = output_index;
+        let layout = target_layout(&stream.channels);
+        let args = format!(
+            "-c:a:{} {} -filter:a:{} aformat=channel_layouts={} -b:a:{} {}k",
+            i,
+            encoder,
+            i,
+            layout,
+            i,
+            audio_target_bitrate(options, layout)
+        );
+        plans.push(AudioPlan {
+            index: stream.index,
+            output_index,
+            args,
+            original_codec: stream.codec.clone(),
+            target_codec: options.codec.clone(),
+        });
+    }
+    plans
+}
+
+// Bitrate in kbps of the audio of the output: copied streams keep their bitrate, re-encoded
+// streams get the -b:a of their plan, copied streams of unknown bitrate count as 192 kbps
+pub fn estimated_audio_kbps(streams: &[AudioStream], plans: &[AudioPlan]) -> f64 {
+    let re = Regex::new(r"-b:a:\d+ (\d+)k").unwrap();
+    plans
+        .iter()
+        .map(|plan| {
+            if let Some(captures) = re.captures(&plan.args) {
+                return captures[1].parse::<f64>().unwrap_or(0.0);
+            }
+            match streams.iter().find(|stream| stream.index == plan.index) {
+                Some(stream) if stream.bitrate > 0 => stream.bitrate as f64 / 1000.0,
+                _ => 192.0,
+            }
+        })
+        .sum()
+}
+
+// Share of --target-size kept free for the container and the subtitles
+const TARGET_SIZE_OVERHEAD: f64 = 0.02;
+
+// Video bitrate in kbps that makes the output of file --target-size MB, after the audio
+pub fn target_size_bitrate(
+    file: &str,
+    target_size_mb: f64,
+    audio_streams: &[AudioStream],
+    audio_plans: &[AudioPlan],
+) -> Result<u32, String> {
+    if target_size_mb <= 0.0 {
+        return Err(format!("Invalid --target-size {}", target_size_mb));
+    }
+    let duration = get_duration(file)
+        .filter(|duration| *duration > 0.0)
+        .ok_or_else(|| format!("Failed to get the duration of {}", file))?;
+    let audio_kbps = estimated_audio_kbps(audio_streams, audio_plans);
+    video_kbps_for_size(target_size_mb, duration, audio_kbps).ok_or_else(|| {
+        format!(
+            "{} MB is too small for {} ({:.0} s with {:.0} kbps audio)",
+            target_size_mb, file, duration, audio_kbps
+        )
+    })
+}
+
+// Video bitrate in kbps of an output of target_size_mb MB lasting duration seconds with audio_kbps
+// of audio, None when the audio leaves no room for the video
+pub(crate) fn video_kbps_for_size(
+    target_size_mb: f64,
+    duration: f32,
+    audio_kbps: f64,
+) -> Option<u32> {
+    let total_kbits =
+        mb_to_bytes(target_size_mb) as f64 * 8.0 / 1000.0 * (1.0 - TARGET_SIZE_OVERHEAD);
+    let video_kbps = total_kbits / duration as f64 - audio_kbps;
+    if video_kbps < 1.0 {
+        return None;
+    }
+    Some(video_kbps as u32)
+}
+
+// A subtitle stream of the input file, index counts subtitle streams only (0:s:index)
+#[derive(Debug, Clone)]
+pub struct SubtitleStream {
+    pub index: usize,
+    pub codec: String,
+    pub language: String,
+    pub forced: bool,
+}
+
+impl SubtitleStream {
+    // PGS/DVD/DVB subtitles are images and go through overlay, the rest through the subtitles filter
+    pub fn is_bitmap(&self) -> bool {
+        matches!(
+            self.codec.as_str(),
+            "hdmv_pgs_subtitle" | "dvd_subtitle" | "dvb_subtitle" | "xsub"
+        )
+    }
+}
+
+// Get codec, language and forced flag of every subtitle stream
+pub fn get_subtitle_streams(file_path: &str) -> Vec<SubtitleStream> {
+    let info = probe_file(file_path);
+    let mut subtitle_streams = Vec::new();
+    for (index, stream) in info.streams_of("subtitle").into_iter().enumerate() {
+        subtitle_streams.push(SubtitleStream {
+            index,
+            codec: stream["codec_name"].as_str().unwrap_or("NaN").to_string(),
+            language: stream["tags"]["language"]
+                .as_str()
+                .unwrap_or("")
+                .to_string(),
+            forced: stream["disposition"]["forced"].as_i64().unwrap_or(0) == 1,
+        });
+    }
+    subtitle_streams
+}
+
+// The subtitle stream --burn-subs asks for: auto takes the first forced track, a number the
+// subtitle stream with that index, and a language its forced track, or else its first track
+pub fn select_burn_subtitle(streams: &[SubtitleStream], burn_subs: &str) -> Option<SubtitleStream> {
+    if burn_subs == "auto" {
+        return streams.iter().find(|stream| stream.forced).cloned();
+    }
+    if let Ok(index) = burn_subs.parse::<usize>() {
+        return streams.iter().find(|stream| stream.index == index).cloned();
+    }
+    let language: Vec<&SubtitleStream> = streams
+        .iter()
+        .filter(|stream| stream.language.eq_ignore_ascii_case(burn_subs))
+        .collect();
+    language
+        .iter()
+        .find(|stream| stream.forced)
+        .or(language.first())
+        .map(|stream| (*stream).clone())
+}
+
+// The audio and subtitle streams of the input that go to the output, in output order
+#[derive(Debug, Clone, Default)]
+pub struct StreamSelection {
+    pub audio: Vec<AudioStream>,
+    pub subtitles: Vec<SubtitleStream>,
+    // the streams left out by --keep-audio-langs and --keep-subs-langs, like "a:1(ger)"
+    pub dropped: Vec<String>,
+    // the subtitle files of --ingest-external-subs, after the subtitles of the input
+    pub external_subtitles: Vec<ExternalSubtitle>,
+    // the absolute indices of the cover art (attached_pic) video streams, copied after the video
+    pub cover_art: Vec<u64>,
+}
+
+impl StreamSelection {
+    // -map arguments of the kept audio and subtitle streams, each copied unless an audio plan
+    // re-encodes it, and of the external subtitles, inputs 1 and on
+    pub fn map_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (output_index, stream) in self.audio.iter().enumerate() {
+            args.push("-map".to_string());
+            args.push(format!("0:a:{}", stream.index));
+            args.push(format!("-c:a:{}", output_index));
+            args.push("copy".to_string());
+        }
+        for (output_index, stream) in self.subtitles.iter().enumerate() {
+            args.push("-map".to_string());
+            args.push(format!("0:s:{}", stream.index));
+            args.push(format!("-c:s:{}", output_index));
+            args.push("copy".to_string());
+        }
+        for (i, external) in self.external_subtitles.iter().enumerate() {
+            let output_index = self.subtitles.len() + i;
+            args.push("-map".to_string());
+            args.push(format!("{}:s:0", i + 1));
+            args.push(format!("-c:s:{}", output_index));
+            args.push(external.codec.clone());
+            if !external.language.is_empty() {
+                args.push(format!("-metadata:s:s:{}", output_index));
+                args.push(format!("language={}", external.language));
+            }
+        }
+        args
+    }
+
+    // -map arguments of the cover art, copied as the video streams from first_video on with the
+    // attached_pic disposition
+    pub fn cover_art_args(&self, first_video: usize) -> Vec<String> {
+        let mut args = Vec::new();
+        for (i, index) in self.cover_art.iter().enumerate() {
+            let output_index = first_video + i;
+            args.extend([
+                "-map".to_string(),
+                format!("0:{}", index),
+                format!("-c:v:{}", output_index),
+                "copy".to_string(),
+                format!("-disposition:v:{}", output_index),
+                "attached_pic".to_string(),
+            ]);
+        }
+        args
+    }
+
+    // The -i of the external subtitles, after the input
+    pub fn external_inputs(&self) -> Vec<String> {
+        self.external_subtitles
+            .iter()
+            .flat_map(|external| ["-i".to_string(), external.path.clone()])
+            .collect()
+    }
+}
+
+pub(crate) fn stream_language(language: &str) -> &str {
+    if language.is_empty() {
+        "und"
+    } else {
+        language
+    }
+}
+
+// An empty list keeps every language, streams without a language tag count as "und"
+pub(crate) fn language_kept(language: &str, languages: &[String]) -> bool {
+    languages.is_empty()
+        || languages
+            .iter()
+            .any(|kept| kept.trim().eq_ignore_ascii_case(stream_language(language)))
+}
+
+// The streams of file for the output: the audio streams in --keep-audio-langs, at least one, the
+// subtitle streams in --keep-subs-langs, the burned in subtitle is part of the video instead, and
+// the cover art
+pub fn select_streams(
+    file: &str,
+    args: &Args,
+    burn_subtitle: Option<&SubtitleStream>,
+) -> StreamSelection {
+    let audio_streams = get_audio_streams(file);
+    let mut audio: Vec<AudioStream> = audio_streams
+        .iter()
+        .filter(|stream| language_kept(&stream.language, &args.keep_audio_langs))
+        .cloned()
+        .collect();
+    if audio.is_empty() {
+        let fallback = audio_streams
+            .iter()
+            .find(|stream| stream.default)
+            .or(audio_streams.first());
+        if let Some(fallback) = fallback {
+            warn!(
+                "{}: no audio stream in {}, keeping audio stream {} ({})",
+                file,
+                args.keep_audio_langs.join(","),
+                fallback.index,
+                stream_language(&fallback.language)
+            );
+            audio.push(fallback.clone());
+        }
+    }
+
+    let burned = |index: usize| burn_subtitle.map_or(false, |subtitle| subtitle.index == index);
+    let subtitle_streams = get_subtitle_streams(file);
+    let subtitles: Vec<SubtitleStream> = subtitle_streams
+        .iter()
+        .filter(|stream| !burned(stream.index))
+        .filter(|stream| language_kept(&stream.language, &args.keep_subs_langs))
+        .cloned()
+        .collect();
+
+    let mut dropped = Vec::new();
+    for stream in &audio_streams {
+        if !audio.iter().any(|kept| kept.index == stream.index) {
+            dropped.push(format!(
+                "a:{}({})",
+                stream.index,
+                stream_language(&stream.language)
+            ));
+        }
+    }
+    for stream in &subtitle_streams {
+        if !burned(stream.index) && !subtitles.iter().any(|kept| kept.index == stream.index) {
+            dropped.push(format!(
+                "s:{}({})",
+                stream.index,
+                stream_language(&stream.language)
+            ));
+        }
+    }
+    // the cover art of a file without another video stream is the video, see select_video_stream
+    let info = probe_file(file);
+    let video = info
+        .video_stream()
+        .and_then(|stream| stream["index"].as_u64());
+    let cover_art = info
+        .streams_of("video")
+        .into_iter()
+        .filter(|stream| stream["disposition"]["attached_pic"] == 1)
+        .filter_map(|stream| stream["index"].as_u64())
+        .filter(|index| Some(*index) != video)
+        .collect();
+
+    StreamSelection {
+        audio,
+        subtitles,
+        dropped,
+        external_subtitles: Vec::new(),
+        cover_art,
+    }
+}
+
+// Escape a path for the filename option of the subtitles filter
+// The value is escaped once for the option (: and ') and once more for the filtergraph,
+// backslashes become slashes first, ffmpeg accepts them on Windows too
+pub fn escape_filter_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut option = String::new();
+    for c in path.chars() {
+        if matches!(c, '\\' | '\'' | ':') {
+            option.push('\\');
+        }
+        option.push(c);
+    }
+    let mut graph = String::new();
+    for c in option.chars() {
+        if matches!(c, '\\' | '\'' | '[' | ']' | ',' | ';') {
+            graph.push('\\');
+        }
+        graph.push(c);
+    }
+    graph
+}
+
+// Copy the metadata of each kept stream to the stream it became, with dropped streams output
+// stream n is not input stream n
+pub(crate) fn run_ffmpeg_map_metadata(streams: &StreamSelection) -> String {
+    let audio_indices: Vec<usize> = streams.audio.iter().map(|stream| stream.index).collect();
+    let subtitle_indices: Vec<usize> = streams
+        .subtitles
+        .iter()
+        .map(|stream| stream.index)
+        .collect();
+
+    let mut map_metadata_builder = StringBuilder::new();
+
+    // Build map_metadata arguments for audio streams
+    build_map_metadata_arguments(&mut map_metadata_builder, "a", &audio_indices);
+
+    // Build map_metadata arguments for subtitle streams
+    build_map_metadata_arguments(&mut map_metadata_builder, "s", &subtitle_indices);
+
+    map_metadata_builder.to_string()
+}
+
+// input_indices are the input streams in output order
+fn build_map_metadata_arguments(
+    builder: &mut StringBuilder,
+    stream_type: &str,
+    input_indices: &[usize],
+) {
+    for (i, input_index) in input_indices.iter().enumerate() {
+        builder.push_str("-map_metadata:s:");
+        builder.push_str(stream_type);
+        builder.push(':');
+        builder.push_str(&i.to_string());
+        builder.push(' ');
+        builder.push_str("0:s:");
+        builder.push_str(stream_type);
+        builder.push(':');
+        builder.push_str(&input_index.to_string());
+        builder.push(' ');
+    }
+}
+
+struct StringBuilder {
+    buffer: String,
+}
+
+impl StringBuilder {
+    fn new() -> StringBuilder {
+        StringBuilder {
+            buffer: String::new(),
+        }
+    }
+
+    fn push_str(&mut self, s: &str) {
+        self.buffer.push_str(s);
+    }
+
+    fn push(&mut self, c: char) {
+        self.buffer.push(c);
+    }
+
+    fn to_string(&self) -> String {
+        self.buffer.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,307 @@
+use super::*;
+
+fn stream(index: usize, codec: &str, channels: &str, bitrate: i64) -> AudioStream {
+    AudioStream {
+        index,
+        codec: codec.to_string(),
+        channels: channels.to_string(),
+        bitrate,
+        language: "eng".to_string(),
+        default: index == 0,
+    }
+}
+
+fn options(codec: &str, max_bitrate_kbps: Option<i64>) -> AudioOptions {
+    AudioOptions {
+        codec: codec.to_string(),
+        max_bitrate_kbps,
+        stereo_kbps: None,
+        surround_51_kbps: None,
+        surround_71_kbps: None,
+    }
+}
+
+// The args of the one stream of channels in codec, re-encoded to opus or aac
+fn reencoded(channels: &str, codec: &str) -> String {
+    let plans = plan_audio_streams(&[stream(0, "dts", channels, 0)], &options(codec, None));
+    assert_eq!(plans.len(), 1);
+    assert_eq!(plans[0].target_codec, codec);
+    plans[0].args.clone()
+}
+
+#[test]
+fn opus_of_each_layout() {
+    let cases = [
+        ("1", "mono", 64),
+        ("2", "stereo", 128),
+        ("3", "3.0", 160),
+        ("4", "quad", 192),
+        ("5", "5.0", 224),
+        ("6", "5.1", 256),
+        ("7", "6.1", 352),
+        ("8", "7.1", 450),
+        // more than 8 channels are downmixed to 7.1
+        ("10", "7.1", 450),
+        // an unknown layout is downmixed to stereo
+        ("0", "stereo", 128),
+        ("NaN", "stereo", 128),
+    ];
+    for (channels, layout, kbps) in cases {
+        assert_eq!(
+            reencoded(channels, "opus"),
+            format!(
+                "-c:a:0 libopus -filter:a:0 aformat=channel_layouts={} -b:a:0 {}k",
+                layout, kbps
+            ),
+            "{} channels",
+            channels
+        );
+    }
+}
+
+#[test]
+fn aac_of_each_layout() {
+    let cases = [
+        ("1", "mono", 96),
+        ("2", "stereo", 192),
+        ("3", "3.0", 256),
+        ("4", "quad", 320),
+        ("5", "5.0", 352),
+        ("6", "5.1", 384),
+        ("7", "6.1", 448),
+        ("8", "7.1", 512),
+        ("", "stereo", 192),
+    ];
+    for (channels, layout, kbps) in cases {
+        assert_eq!(
+            reencoded(channels, "aac"),
+            format!(
+                "-c:a:0 aac -filter:a:0 aformat=channel_layouts={} -b:a:0 {}k",
+                layout, kbps
+            ),
+            "{} channels",
+            channels
+        );
+    }
+}
+
+#[test]
+fn allowed_codecs_are_copied_up_to_the_max_bitrate() {
+    let streams = [
+        stream(0, "aac", "2", 1_500_000),
+        stream(1, "opus", "6", 256_000),
+        stream(2, "mp3", "2", 320_000),
+        stream(3, "truehd", "8", 4_000_000),
+    ];
+    let copied = |plans: &[AudioPlan]| -> Vec<bool> {
+        plans.iter().map(|plan| plan.args.is_empty()).collect()
+    };
+
+    // without --max-audio-bitrate only the codec counts
+    let plans = plan_audio_streams(&streams, &options("opus", None));
+    assert_eq!(copied(&plans), [true, true, true, false]);
+
+    let plans = plan_audio_streams(&streams, &options("opus", Some(640)));
+    assert_eq!(copied(&plans), [false, true, true, false]);
+    assert_eq!(
+        plans[0].args,
+        "-c:a:0 libopus -filter:a:0 aformat=channel_layouts=stereo -b:a:0 128k"
+    );
+    assert_eq!(
+        (
+            plans[0].original_codec.as_str(),
+            plans[0].target_codec.as_str()
+        ),
+        ("aac", "opus")
+    );
+    // a copied stream keeps its codec
+    assert_eq!(plans[1].target_codec, "opus");
+    assert_eq!(
+        plans[3].args,
+        "-c:a:3 libopus -filter:a:3 aformat=channel_layouts=7.1 -b:a:3 450k"
+    );
+}
+
+#[test]
+fn the_layouts_between_are_converted_not_copied() {
+    // the dts of a 4.0 or a 6.1 stream was copied
+    let streams = [
+        stream(0, "dts", "4", 0),
+        stream(1, "dts", "7", 0),
+        stream(2, "opus", "4", 0),
+    ];
+    let plans = plan_audio_streams(&streams, &options("opus", None));
+    assert_eq!(
+        plans[0].args,
+        "-c:a:0 libopus -filter:a:0 aformat=channel_layouts=quad -b:a:0 192k"
+    );
+    assert_eq!(
+        plans[1].args,
+        "-c:a:1 libopus -filter:a:1 aformat=channel_layouts=6.1 -b:a:1 352k"
+    );
+    assert_eq!(plans[2].args, "");
+}
+
+#[test]
+fn the_output_index_follows_the_kept_streams() {
+    // the streams the selection kept, input streams 1 and 3
+    let streams = [stream(1, "dts", "6", 0), stream(3, "ac3", "2", 0)];
+    let plans = plan_audio_streams(&streams, &options("opus", None));
+    assert_eq!(
+        plans
+            .iter()
+            .map(|plan| (plan.index, plan.output_index))
+            .collect::<Vec<_>>(),
+        [(1, 0), (3, 1)]
+    );
+    assert_eq!(
+        plans[1].args,
+        "-c:a:1 libopus -filter:a:1 aformat=channel_layouts=stereo -b:a:1 128k"
+    );
+}
+
+#[test]
+fn the_bitrate_options_override_the_ladder() {
+    let audio_options = AudioOptions {
+        stereo_kbps: Some(96),
+        surround_51_kbps: Some(320),
+        surround_71_kbps: Some(512),
+        ..options("opus", None)
+    };
+    let streams = [
+        stream(0, "dts", "2", 0),
+        stream(1, "dts", "6", 0),
+        stream(2, "dts", "8", 0),
+        stream(3, "dts", "1", 0),
+    ];
+    let kbps: Vec<String> = plan_audio_streams(&streams, &audio_options)
+        .into_iter()
+        .map(|plan| plan.args.rsplit(' ').next().unwrap().to_string())
+        .collect();
+    // mono has no option
+    assert_eq!(kbps, ["96k", "320k", "512k", "64k"]);
+}
+
+#[test]
+fn estimated_audio_kbps_of_copied_and_reencoded_streams() {
+    let streams = [
+        stream(0, "aac", "2", 160_000),
+        stream(1, "aac", "2", 0),
+        stream(2, "dts", "6", 1_500_000),
+    ];
+    let plans = plan_audio_streams(&streams, &options("opus", None));
+    // 160 copied, 192 for a copy of unknown bitrate, 256 re-encoded
+    assert_eq!(
+        estimated_audio_kbps(&streams, &plans),
+        160.0 + 192.0 + 256.0
+    );
+}
+
+#[test]
+fn video_kbps_for_size_fits_the_target_size() {
+    // MB of 1024 * 1024 bytes, 2% kept for the container, the rest minus the audio
+    let cases = [
+        // a 4 GB budget for a two hour movie with 7.1 opus and stereo aac
+        (4000.0, 7200.0, 610.0, 3957),
+        (700.0, 5400.0, 128.0, 937),
+        (50.0, 60.0, 0.0, 6850),
+        (20000.0, 10800.0, 1536.0, 13687),
+    ];
+    for (target_size_mb, duration, audio_kbps, video_kbps) in cases {
+        assert_eq!(
+            video_kbps_for_size(target_size_mb, duration, audio_kbps),
+            Some(video_kbps),
+            "{} MB",
+            target_size_mb
+        );
+    }
+}
+
+#[test]
+fn video_kbps_for_size_without_room_for_the_video() {
+    // the audio alone takes more than the target
+    assert_eq!(video_kbps_for_size(10.0, 7200.0, 640.0), None);
+    assert_eq!(video_kbps_for_size(0.001, 60.0, 0.0), None);
+}
+
+#[test]
+fn target_size_bitrate_refuses_sizes_below_zero() {
+    for target_size_mb in [0.0, -4000.0] {
+        assert!(
+            target_size_bitrate("/media/Movie.mkv", target_size_mb, &[], &[])
+                .unwrap_err()
+                .starts_with("Invalid --target-size")
+        );
+    }
+}
+
+// What ffprobe shows of a file with a cover art picture before the video, like cover_art.mkv
+fn with_cover_art() -> Value {
+    serde_json::json!({
+        "streams": [
+            {
+                "index": 0, "codec_type": "video", "codec_name": "mjpeg",
+                "width": 320, "height": 320, "nb_frames": "1",
+                "disposition": {"attached_pic": 1}
+            },
+            {
+                "index": 1, "codec_type": "video", "codec_name": "h264",
+                "width": 1280, "height": 720, "nb_frames": "48",
+                "disposition": {"default": 1}
+            },
+            {"index": 2, "codec_type": "audio", "codec_name": "aac", "channels": 2},
+        ],
+        "format": {"duration": "2.0"},
+    })
+}
+
+#[test]
+fn the_cover_art_is_selected_besides_the_video() {
+    let file = probed_file("cover-art.mkv", with_cover_art());
+    let streams = select_streams(&file, &test_args(&[]), None);
+    assert_eq!(streams.cover_art, [0]);
+    assert_eq!(streams.audio.len(), 1);
+    // the frames and the details are the ones of the video, not of the picture
+    let info = probe_file(&file);
+    assert_eq!(info.video_map(), "0:1");
+    assert_eq!(info.frame_count(), Some(48.0));
+    assert_eq!(
+        get_video_details(&file).unwrap(),
+        [("h264".to_string(), "1280".to_string(), "720".to_string())]
+    );
+
+    // a picture that is the only video stream is the video, not cover art
+    let mut json = with_cover_art();
+    json["streams"].as_array_mut().unwrap().remove(1);
+    let file = probed_file("cover-art-only.mka", json);
+    assert!(select_streams(&file, &test_args(&[]), None)
+        .cover_art
+        .is_empty());
+    assert_eq!(probe_file(&file).video_map(), "0:0");
+}
+
+#[test]
+fn cover_art_args_copy_each_picture_as_attached_pic() {
+    let streams = StreamSelection {
+        cover_art: vec![0, 4],
+        ..Default::default()
+    };
+    assert_eq!(
+        streams.cover_art_args(1),
+        [
+            "-map",
+            "0:0",
+            "-c:v:1",
+            "copy",
+            "-disposition:v:1",
+            "attached_pic",
+            "-map",
+            "0:4",
+            "-c:v:2",
+            "copy",
+            "-disposition:v:2",
+            "attached_pic",
+        ]
+    );
+    assert!(StreamSelection::default().cover_art_args(1).is_empty());
+}
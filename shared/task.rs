@@ -0,0 +1,214 @@
+// Task history: every transcode run, started from the web or the cli, is a row in the tasks table
+// Ids are uuid v7, so they sort by creation time
+
+use crate::*;
+
+use rusqlite::OptionalExtension;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub id: String,
+    // RFC 3339 UTC
+    pub created_at: String,
+    // queued, running, done, failed or cancelled
+    pub state: String,
+    pub input_path: String,
+    pub output_path: String,
+    pub encoder: String,
+    pub vmaf_target: f32,
+    // RFC 3339 UTC, empty until the task runs and until it ends
+    #[serde(default)]
+    pub started_at: String,
+    #[serde(default)]
+    pub finished_at: String,
+    // the last output the task finished, empty before the first one
+    #[serde(default)]
+    pub output_file: String,
+}
+
+impl Task {
+    // A queued task created now, several input paths are joined with ;
+    pub fn new(
+        id: &str,
+        input_paths: &[String],
+        output_path: &str,
+        encoder: &str,
+        vmaf_target: f32,
+    ) -> Task {
+        Task {
+            id: id.to_string(),
+            created_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            state: "queued".to_string(),
+            input_path: input_paths.join(";"),
+            output_path: output_path.to_string(),
+            encoder: encoder.to_string(),
+            vmaf_target,
+            started_at: String::new(),
+            finished_at: String::new(),
+            output_file: String::new(),
+        }
+    }
+
+    // The task of a run with args, args.task_id is its id
+    pub fn from_args(args: &Args) -> Task {
+        Task::new(
+            &args.task_id,
+            &args.inputpath,
+            &args.outputpath,
+            &args.encoder,
+            args.vmaf,
+        )
+    }
+
+    fn from_row(row: &rusqlite::Row) -> Result<Task> {
+        Ok(Task {
+            id: row.get(0)?,
+            created_at: row.get(1)?,
+            state: row.get(2)?,
+            input_path: row.get(3)?,
+            output_path: row.get(4)?,
+            encoder: row.get(5)?,
+            vmaf_target: row.get(6)?,
+            started_at: row.get(7)?,
+            finished_at: row.get(8)?,
+            output_file: row.get(9)?,
+        })
+    }
+}
+
+pub fn new_task_id() -> String {
+    uuid::Uuid::now_v7().to_string()
+}
+
+impl Db {
+    pub fn insert_task(&self, task: &Task) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO tasks (id, created_at, state, input_path, output_path, encoder, vmaf_target) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                task.id,
+                task.created_at,
+                task.state,
+                task.input_path,
+                task.output_path,
+                task.encoder,
+                task.vmaf_target
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Entering running sets started_at, done, failed and cancelled set finished_at
+    pub fn update_task_state(&self, id: &str, state: &str) -> Result<()> {
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE tasks SET state=?1,
+                started_at = CASE WHEN ?1 = 'running' THEN ?3 ELSE started_at END,
+                finished_at = CASE WHEN ?1 IN ('done', 'failed', 'cancelled') THEN ?3 ELSE finished_at END
+             WHERE id=?2",
+            params![state, id, now],
+        )?;
+        Ok(())
+    }
+
+    // Average run time of the last 20 done tasks, None before the first one
+    pub fn average_task_duration(&self) -> Result<Option<Duration>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT started_at, finished_at FROM tasks WHERE state='done' AND started_at != '' AND finished_at != '' ORDER BY finished_at DESC LIMIT 20",
+        )?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut durations = Vec::new();
+        for row in rows {
+            let (started_at, finished_at) = row?;
+            if let (Ok(started), Ok(finished)) = (
+                humantime::parse_rfc3339(&started_at),
+                humantime::parse_rfc3339(&finished_at),
+            ) {
+                durations.push(finished.duration_since(started).unwrap_or_default());
+            }
+        }
+        if durations.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            durations.iter().sum::<Duration>() / durations.len() as u32,
+        ))
+    }
+
+    pub fn task(&self, id: &str) -> Result<Option<Task>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT id, created_at, state, input_path, output_path, encoder, vmaf_target, started_at, finished_at, output_file FROM tasks WHERE id=?1",
+            params![id],
+            Task::from_row,
+        )
+        .optional()
+    }
+
+    // Most recent first
+    pub fn tasks(&self, limit: u32, offset: u32) -> Result<Vec<Task>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, created_at, state, input_path, output_path, encoder, vmaf_target, started_at, finished_at, output_file FROM tasks ORDER BY created_at DESC, id DESC LIMIT ?1 OFFSET ?2",
+        )?;
+        let rows = stmt.query_map(params![limit, offset], Task::from_row)?;
+        rows.collect()
+    }
+}
+
+// Add the task of args to the history, a task created by the web is already there
+pub fn create_task(args: &Args) {
+    let result = db().task(&args.task_id).and_then(|task| match task {
+        Some(_) => Ok(()),
+        None => db().insert_task(&Task::from_args(args)),
+    });
+    if let Err(e) = result {
+        warn!("Failed to store task {}: {}", args.task_id, e);
+    }
+}
+
+lazy_static! {
+    // Tasks of this process in the running state, cancelled when it is interrupted
+    static ref RUNNING_TASKS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+pub fn set_task_state(id: &str, state: &str) {
+    {
+        let mut running = RUNNING_TASKS.lock().unwrap();
+        running.retain(|running_id| running_id != id);
+        if state == "running" {
+            running.push(id.to_string());
+            // the children this thread starts belong to the task
+            set_current_task(id);
+        } else {
+            clear_pause(id);
+        }
+    }
+    if let Err(e) = db().update_task_state(id, state) {
+        warn!("Failed to update the state of task {}: {}", id, e);
+    }
+}
+
+pub fn is_task_running(id: &str) -> bool {
+    RUNNING_TASKS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|running| running == id)
+}
+
+pub fn any_task_running() -> bool {
+    !RUNNING_TASKS.lock().unwrap().is_empty()
+}
+
+// Set the running tasks of this process to cancelled, on ctrl-C or when the web server stops
+pub fn cancel_running_tasks() {
+    let running: Vec<String> = RUNNING_TASKS.lock().unwrap().clone();
+    for id in running {
+        set_task_state(&id, "cancelled");
+    }
+}
@@ -0,0 +1,42 @@
+// The end of the ffmpeg/ab-av1 output and the error of every task, by task id, for GET /progress
+// and the task pages
+
+use crate::*;
+
+// Lines of ffmpeg/ab-av1 stderr kept for the failure summary and the task log
+pub(crate) const STDERR_TAIL_LINES: usize = 50;
+
+// The end of the tool output of a transcode task for /tasks/<id>/log
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct TaskLog {
+    pub lines: VecDeque<String>,
+    pub error: Option<String>,
+}
+
+// Add the stderr tail of a finished ffmpeg/ab-av1 run to the task log, headed by the command
+// Only the last STDERR_TAIL_LINES lines of the task are kept
+pub fn record_task_log(task_id: &str, command: &str, stderr_tail: &str) {
+    let mut logs = WEB_TASK_LOGS_STATIC.lock().unwrap();
+    let log = logs.entry(task_id.to_string()).or_default();
+    log.lines.push_back(format!("$ {}", command));
+    for line in stderr_tail.lines().filter(|line| !line.trim().is_empty()) {
+        log.lines.push_back(line.to_string());
+    }
+    while log.lines.len() > STDERR_TAIL_LINES {
+        log.lines.pop_front();
+    }
+}
+
+// Set the error /progress reports for the task
+pub fn set_task_error(task_id: &str, error: &str) {
+    let mut logs = WEB_TASK_LOGS_STATIC.lock().unwrap();
+    logs.entry(task_id.to_string()).or_default().error = Some(error.to_string());
+}
+
+pub fn task_log(task_id: &str) -> Option<TaskLog> {
+    WEB_TASK_LOGS_STATIC.lock().unwrap().get(task_id).cloned()
+}
+
+pub(crate) fn task_error(task_id: &str) -> Option<String> {
+    task_log(task_id).and_then(|log| log.error)
+}
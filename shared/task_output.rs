@@ -0,0 +1,221 @@
+// Previews of the web server: the last output a task finished is stored in the tasks table with its
+// size and modification time, GET /files/<task_id>/output serves it in byte ranges for the video
+// player of the browser and GET /files/<task_id>/thumb.jpg a frame of it at a quarter of its length.
+// Only outputs under the --serve-root folders of the web server are served, and an output that was
+// moved or replaced since is not found anymore
+
+use crate::*;
+
+// The folder of the cached thumbnails in the artifacts folder, one <task id>.jpg per task
+pub const THUMBNAIL_FOLDER: &str = "thumbs";
+
+// Why the output of a task can't be served
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServeRefusal {
+    // no such task, no output yet, or the output was moved or replaced
+    NotFound(String),
+    // the output is outside of the --serve-root folders
+    Forbidden(String),
+}
+
+impl std::fmt::Display for ServeRefusal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ServeRefusal::NotFound(message) | ServeRefusal::Forbidden(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+// The size and the modification time in unix seconds of path
+fn output_identity(path: &str) -> io::Result<(i64, i64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|modified| modified.as_secs() as i64)
+        .unwrap_or(0);
+    Ok((metadata.len() as i64, modified))
+}
+
+impl Db {
+    pub fn set_task_output(&self, id: &str, output: &str, size: i64, modified: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE tasks SET output_file=?1, output_size=?2, output_mtime=?3 WHERE id=?4",
+            params![output, size, modified, id],
+        )?;
+        Ok(())
+    }
+
+    // The output of task id with its size and modification time, None for an unknown task
+    fn task_output(&self, id: &str) -> Result<Option<(String, i64, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT output_file, output_size, output_mtime FROM tasks WHERE id=?1")?;
+        let mut rows = stmt.query_map(params![id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.next().transpose()
+    }
+}
+
+// Store output as the last output task_id finished, after its modification time was set
+pub fn record_task_output(task_id: &str, output: &str) {
+    let path = fs::canonicalize(output)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| absolute_path(output));
+    let result = output_identity(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|(size, modified)| {
+            db().set_task_output(task_id, &path, size, modified)
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        warn!("Failed to store the output of task {}: {}", task_id, e);
+    }
+}
+
+// The output of task_id when it is still the file the task wrote and it is in one of roots
+pub fn servable_output(task_id: &str, roots: &[String]) -> Result<PathBuf, ServeRefusal> {
+    servable_task_output(db(), task_id, roots)
+}
+
+// servable_output with the tasks of db
+pub(crate) fn servable_task_output(
+    db: &Db,
+    task_id: &str,
+    roots: &[String],
+) -> Result<PathBuf, ServeRefusal> {
+    let (output, size, modified) = match db.task_output(task_id) {
+        Ok(Some((output, size, modified))) if !output.is_empty() => (output, size, modified),
+        Ok(_) => {
+            return Err(ServeRefusal::NotFound(format!(
+                "task {} has no output",
+                task_id
+            )))
+        }
+        Err(e) => return Err(ServeRefusal::NotFound(e.to_string())),
+    };
+    let gone = || {
+        ServeRefusal::NotFound(format!(
+            "the output of task {} was moved or replaced",
+            task_id
+        ))
+    };
+    // a symlink or a .. can't lead out of the roots, both sides are compared resolved
+    let path = fs::canonicalize(&output).map_err(|_| gone())?;
+    if path.to_string_lossy() != output || output_identity(&output).ok() != Some((size, modified)) {
+        return Err(gone());
+    }
+    let allowed = roots
+        .iter()
+        .filter_map(|root| fs::canonicalize(root).ok())
+        .any(|root| path.starts_with(root));
+    if !allowed {
+        return Err(ServeRefusal::Forbidden(format!(
+            "the output of task {} is not in a --serve-root folder",
+            task_id
+        )));
+    }
+    Ok(path)
+}
+
+// The first and last byte of the Range header of a request for a file of total bytes. The whole
+// file is served without a header and for a header that is not one well-formed range of bytes:
+// another unit, several ranges, bad syntax or an end before the start. Only a well-formed range
+// that starts past the end of the file is an error, a 416
+pub fn byte_range(header: Option<&str>, total: u64) -> Result<Option<(u64, u64)>, String> {
+    let spec = match header.and_then(|header| header.trim().strip_prefix("bytes=")) {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return Ok(None),
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => return Ok(None),
+    };
+    // digits only, a number too large for u64 is past the end of any file
+    let number = |value: &str| {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            None
+        } else {
+            Some(value.parse::<u64>().unwrap_or(u64::MAX))
+        }
+    };
+    let unsatisfiable = || format!("range {} is outside of the {} bytes", spec, total);
+    let (start, end) = match (number(start), number(end)) {
+        // the last end bytes
+        (None, Some(suffix)) if start.is_empty() => {
+            if suffix == 0 || total == 0 {
+                return Err(unsatisfiable());
+            }
+            (total.saturating_sub(suffix), total - 1)
+        }
+        (Some(start), None) if end.is_empty() => (start, total.saturating_sub(1)),
+        (Some(start), Some(end)) if end >= start => (start, end.min(total.saturating_sub(1))),
+        _ => return Ok(None),
+    };
+    if start >= total {
+        return Err(unsatisfiable());
+    }
+    Ok(Some((start, end)))
+}
+
+// The thumbnail of the output of task_id in dir, extracted at a quarter of its length the first
+// time and again once the output is newer than it
+pub fn output_thumbnail(task_id: &str, output: &Path, dir: &Path) -> Result<PathBuf, String> {
+    // the id names the file
+    if task_id.is_empty()
+        || !task_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("invalid task id {}", task_id));
+    }
+    let thumbnail = dir.join(format!("{}.jpg", task_id));
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    if let (Ok(cached), Ok(output_modified)) = (modified(&thumbnail), modified(output)) {
+        if cached >= output_modified {
+            return Ok(thumbnail);
+        }
+    }
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let output = output.to_string_lossy().to_string();
+    let at = get_duration(&output).unwrap_or(0.0) / 4.0;
+    let thumbnail_path = thumbnail.to_string_lossy().to_string();
+    let mut cmd = ffmpeg_command();
+    cmd.args([
+        "-v",
+        "error",
+        "-y",
+        "-ss",
+        &format!("{:.3}", at),
+        "-i",
+        &output,
+    ])
+    .args([
+        "-map",
+        &probe_file(&output).video_map(),
+        "-frames:v",
+        "1",
+        "-vf",
+        "scale=-2:'min(480,ih)'",
+    ])
+    .arg(&thumbnail_path);
+    debug!("{:?}", cmd);
+    let result = tracked_output(&mut cmd, &[&thumbnail_path])
+        .map_err(|e| format!("Failed to execute ffmpeg: {}", e))?;
+    if !result.status.success() || !thumbnail.exists() {
+        let _ = fs::remove_file(&thumbnail);
+        return Err(format!(
+            "Failed to extract the thumbnail of {}: {}",
+            output,
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    Ok(thumbnail)
+}
+
+#[cfg(test)]
+mod tests;
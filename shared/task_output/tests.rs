@@ -0,0 +1,207 @@
+use super::*;
+
+#[test]
+fn byte_range_serves_the_whole_file_without_a_range() {
+    assert_eq!(byte_range(None, 1000), Ok(None));
+}
+
+#[test]
+fn byte_range_of_well_formed_ranges() {
+    let cases = [
+        ("bytes=0-499", Some((0, 499))),
+        ("bytes=500-999", Some((500, 999))),
+        // open-ended
+        ("bytes=500-", Some((500, 999))),
+        // the last 200 bytes
+        ("bytes=-200", Some((800, 999))),
+        // a suffix longer than the file is the whole file
+        ("bytes=-5000", Some((0, 999))),
+        // an end past the file is clamped
+        ("bytes=900-5000", Some((900, 999))),
+        ("bytes=999-999", Some((999, 999))),
+        (" bytes= 10 - 20 ", Some((10, 20))),
+    ];
+    for (header, expected) in cases {
+        assert_eq!(byte_range(Some(header), 1000), Ok(expected), "{}", header);
+    }
+}
+
+#[test]
+fn byte_range_ignores_malformed_and_unsupported_ranges() {
+    let cases = [
+        "",
+        "bytes",
+        "bytes=",
+        "bytes=-",
+        "bytes=abc-def",
+        "bytes=1-abc",
+        "bytes=+1-2",
+        "bytes=-1-2",
+        "bytes=0.5-10",
+        "bytes=100",
+        // an end before the start
+        "bytes=500-100",
+        // several ranges
+        "bytes=0-10,20-30",
+        "bytes=0-10, 2000-3000",
+        "bytes=0-10,",
+        // another unit
+        "items=0-10",
+    ];
+    for header in cases {
+        assert_eq!(byte_range(Some(header), 1000), Ok(None), "{}", header);
+    }
+}
+
+#[test]
+fn byte_range_refuses_well_formed_ranges_outside_of_the_file() {
+    let cases = [
+        ("bytes=1000-", 1000),
+        ("bytes=1000-2000", 1000),
+        ("bytes=5000-6000", 1000),
+        ("bytes=99999999999999999999999-", 1000),
+        // nothing of the file is asked for
+        ("bytes=-0", 1000),
+        // an empty file has no byte to start from
+        ("bytes=0-", 0),
+        ("bytes=-10", 0),
+    ];
+    for (header, total) in cases {
+        assert!(byte_range(Some(header), total).is_err(), "{}", header);
+    }
+}
+
+// A --serve-root folder and a folder next to it that is not one
+fn serve_folders() -> (PathBuf, PathBuf) {
+    let parent = temp_path("serve");
+    let root = parent.join("root");
+    let outside = parent.join("outside");
+    fs::create_dir_all(&root).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    (root, outside)
+}
+
+// Store output, as given, as the output of a new task id of db
+fn stored_output(db: &Db, id: &str, output: &Path) {
+    fs::write(output, b"video").unwrap();
+    let task = Task::new(id, &[], "", "hevc_qsv", 95.0);
+    db.insert_task(&task).unwrap();
+    let output = output.to_string_lossy().to_string();
+    let (size, modified) = output_identity(&output).unwrap();
+    db.set_task_output(id, &output, size, modified).unwrap();
+}
+
+fn root_list(root: &Path) -> Vec<String> {
+    vec![root.to_string_lossy().to_string()]
+}
+
+#[test]
+fn an_output_in_a_serve_root_is_served() {
+    let db = temp_db("serve-in-root");
+    let (root, _) = serve_folders();
+    let output = fs::canonicalize(&root).unwrap().join("out.mkv");
+    stored_output(&db, "task", &output);
+
+    assert_eq!(
+        servable_task_output(&db, "task", &root_list(&root)),
+        Ok(output)
+    );
+}
+
+#[test]
+fn a_dot_dot_does_not_lead_out_of_the_serve_roots() {
+    let db = temp_db("serve-dot-dot");
+    let (root, outside) = serve_folders();
+    let target = fs::canonicalize(&outside).unwrap().join("out.mkv");
+    fs::write(&target, b"video").unwrap();
+    let roots = root_list(&root);
+
+    // through the root to the folder next to it
+    let dotted = fs::canonicalize(&root)
+        .unwrap()
+        .join("..")
+        .join("outside")
+        .join("out.mkv");
+    stored_output(&db, "dotted", &dotted);
+    assert!(matches!(
+        servable_task_output(&db, "dotted", &roots),
+        Err(ServeRefusal::NotFound(_))
+    ));
+
+    // resolved, the same file is outside of the root
+    stored_output(&db, "resolved", &target);
+    assert!(matches!(
+        servable_task_output(&db, "resolved", &roots),
+        Err(ServeRefusal::Forbidden(_))
+    ));
+
+    // a root given with a .. is compared resolved too
+    let dotted_roots = vec![root.join("..").join("root").to_string_lossy().to_string()];
+    assert!(matches!(
+        servable_task_output(&db, "resolved", &dotted_roots),
+        Err(ServeRefusal::Forbidden(_))
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn a_symlink_does_not_lead_out_of_the_serve_roots() {
+    let db = temp_db("serve-symlink");
+    let (root, outside) = serve_folders();
+    let target = fs::canonicalize(&outside).unwrap().join("out.mkv");
+    fs::write(&target, b"video").unwrap();
+    let root_canonical = fs::canonicalize(&root).unwrap();
+    let roots = root_list(&root);
+
+    // a link to the file and a link to its folder, both in the root
+    let file_link = root_canonical.join("link.mkv");
+    std::os::unix::fs::symlink(&target, &file_link).unwrap();
+    let folder_link = root_canonical.join("escape");
+    std::os::unix::fs::symlink(fs::canonicalize(&outside).unwrap(), &folder_link).unwrap();
+
+    stored_output(&db, "file-link", &file_link);
+    stored_output(&db, "folder-link", &folder_link.join("out.mkv"));
+    for id in ["file-link", "folder-link"] {
+        assert!(
+            matches!(
+                servable_task_output(&db, id, &roots),
+                Err(ServeRefusal::NotFound(_))
+            ),
+            "{}",
+            id
+        );
+    }
+
+    // a root that is a link to the folder outside serves it
+    let root_link = root_canonical.join("linked-root");
+    std::os::unix::fs::symlink(fs::canonicalize(&outside).unwrap(), &root_link).unwrap();
+    stored_output(&db, "linked-root", &target);
+    assert_eq!(
+        servable_task_output(&db, "linked-root", &root_list(&root_link)),
+        Ok(target)
+    );
+}
+
+#[test]
+fn a_replaced_or_missing_output_is_not_served() {
+    let db = temp_db("serve-replaced");
+    let (root, _) = serve_folders();
+    let roots = root_list(&root);
+    let output = fs::canonicalize(&root).unwrap().join("out.mkv");
+    stored_output(&db, "task", &output);
+
+    fs::write(&output, b"another video").unwrap();
+    assert!(matches!(
+        servable_task_output(&db, "task", &roots),
+        Err(ServeRefusal::NotFound(_))
+    ));
+    fs::remove_file(&output).unwrap();
+    assert!(matches!(
+        servable_task_output(&db, "task", &roots),
+        Err(ServeRefusal::NotFound(_))
+    ));
+    assert!(matches!(
+        servable_task_output(&db, "unknown", &roots),
+        Err(ServeRefusal::NotFound(_))
+    ));
+}
@@ -0,0 +1,146 @@
+// --generate-test-media: small synthetic files from ffmpeg's lavfi testsrc and sine sources, the
+// same names and contents on every run, to try the modes without real media
+
+use crate::*;
+
+const TEST_VIDEO: &str = "testsrc=size=320x240:rate=24:duration=2";
+const TEST_AUDIO: &str = "sine=frequency=440:sample_rate=48000:duration=2";
+const TEST_COVER: &str = "color=color=red:size=300x300";
+
+// (file name, with a sine audio track, the codec and filter arguments)
+fn test_media_files() -> Vec<(&'static str, bool, Vec<&'static str>)> {
+    let h264 = [
+        "-c:v",
+        "libx264",
+        "-preset",
+        "ultrafast",
+        "-pix_fmt",
+        "yuv420p",
+    ];
+    vec![
+        (
+            "h264_aac.mkv",
+            true,
+            [&h264[..], &["-c:a", "aac", "-ac", "2"]].concat(),
+        ),
+        // a multichannel lossless track, like the dts and truehd tracks of remuxes
+        (
+            "h264_flac_5.1.mkv",
+            true,
+            [&h264[..], &["-c:a", "flac", "-ac", "6"]].concat(),
+        ),
+        // the layout of most dts and ac3 tracks, libopus only takes 5.1
+        (
+            "h264_flac_5.1_side.mkv",
+            true,
+            [
+                &h264[..],
+                &["-c:a", "flac", "-af", "aformat=channel_layouts=5.1(side)"],
+            ]
+            .concat(),
+        ),
+        // the layouts around 5.1 the re-encode converts: mono, 4.0 to quad, 6.1 and 7.1
+        (
+            "h264_flac_mono.mkv",
+            true,
+            [&h264[..], &["-c:a", "flac", "-ac", "1"]].concat(),
+        ),
+        (
+            "h264_flac_4.0.mkv",
+            true,
+            [
+                &h264[..],
+                &["-c:a", "flac", "-af", "aformat=channel_layouts=4.0"],
+            ]
+            .concat(),
+        ),
+        (
+            "h264_flac_6.1.mkv",
+            true,
+            [&h264[..], &["-c:a", "flac", "-ac", "7"]].concat(),
+        ),
+        (
+            "h264_flac_7.1.mkv",
+            true,
+            [&h264[..], &["-c:a", "flac", "-ac", "8"]].concat(),
+        ),
+        ("video_only.mkv", false, h264.to_vec()),
+        // every fourth frame dropped, the frame durations alternate
+        (
+            "vfr.mkv",
+            false,
+            [
+                &h264[..],
+                &["-vf", "select='not(eq(mod(n,4),1))'", "-fps_mode", "vfr"],
+            ]
+            .concat(),
+        ),
+        // 720x480 shown at 16:9, like the NTSC widescreen DVDs
+        (
+            "anamorphic.mkv",
+            false,
+            [&h264[..], &["-vf", "scale=720:480,setsar=32/27"]].concat(),
+        ),
+        // a cover art picture, mapped before the video, the encode must pick the video and keep it
+        (
+            "cover_art.mkv",
+            true,
+            [
+                &["-f", "lavfi", "-i", TEST_COVER],
+                &["-map", "2:v", "-map", "0:v", "-map", "1:a"][..],
+                &h264[..],
+                &[
+                    "-c:v:0",
+                    "mjpeg",
+                    "-pix_fmt:v:0",
+                    "yuvj420p",
+                    "-frames:v:0",
+                    "1",
+                    "-disposition:v:0",
+                    "attached_pic",
+                    "-c:a",
+                    "aac",
+                ],
+            ]
+            .concat(),
+        ),
+    ]
+}
+
+// Write the test files to dir, returns their paths
+pub fn generate_test_media(dir: &str) -> Result<Vec<String>, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir, e))?;
+    let mut files = Vec::new();
+    for (name, with_audio, codec_args) in test_media_files() {
+        let output = Path::new(dir).join(name).display().to_string();
+        let mut command = ffmpeg_command();
+        command
+            .arg("-hide_banner")
+            .arg("-y")
+            .arg("-f")
+            .arg("lavfi")
+            .arg("-i")
+            .arg(TEST_VIDEO);
+        if with_audio {
+            command.arg("-f").arg("lavfi").arg("-i").arg(TEST_AUDIO);
+        }
+        let result = command
+            .args(&codec_args)
+            .arg(&output)
+            .output()
+            .map_err(|e| format!("Failed to run {}: {}", ffmpeg_bin(), e))?;
+        if !result.status.success() {
+            return Err(format!(
+                "Failed to generate {}: {}",
+                output,
+                String::from_utf8_lossy(&result.stderr).trim()
+            ));
+        }
+        info!("Generated {}", output);
+        files.push(output);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,424 @@
+use super::*;
+
+#[test]
+fn the_test_media_covers_the_streams_the_modes_handle() {
+    let files = test_media_files();
+    let names: Vec<&str> = files.iter().map(|(name, _, _)| *name).collect();
+    for name in [
+        "h264_aac.mkv",
+        "h264_flac_5.1.mkv",
+        "h264_flac_7.1.mkv",
+        "video_only.mkv",
+        "vfr.mkv",
+        "cover_art.mkv",
+    ] {
+        assert!(names.contains(&name), "{}", name);
+    }
+    for (name, with_audio, codec_args) in &files {
+        assert!(codec_args.contains(&"libx264"), "{}", name);
+        // the audio codec comes with the audio input
+        assert_eq!(codec_args.contains(&"-c:a"), *with_audio, "{}", name);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn a_stub_ffprobe_replays_its_probe() {
+    let probe = r#"cat <<'JSON'
+{
+  "streams": [
+    {"index": 0, "codec_type": "video", "codec_name": "h264", "width": 1920, "height": 1080,
+     "r_frame_rate": "24000/1001", "avg_frame_rate": "24000/1001",
+     "tags": {"NUMBER_OF_FRAMES": "1438"}},
+    {"index": 1, "codec_type": "audio", "codec_name": "dts", "channels": 6,
+     "tags": {"BPS": "1509000", "language": "eng"}}
+  ],
+  "format": {"duration": "60.0", "size": "1000000000", "bit_rate": "133333333"},
+  "chapters": []
+}
+JSON
+"#;
+    let file = temp_path("stub.mkv").to_string_lossy().to_string();
+    let info = with_stub("FFPROBE_BIN", probe, || probe_file(&file));
+    assert_eq!(info.streams.len(), 2);
+    assert_eq!(info.video_map(), "0:0");
+    assert!((info.fps() - 23.976).abs() < 0.001);
+    assert!(!info.is_vfr());
+    assert_eq!(info.frame_count(), Some(1438.0));
+    assert_eq!(info.duration(), Some(60.0));
+    assert_eq!(info.streams_of("audio")[0]["tags"]["BPS"], "1509000");
+}
+
+#[cfg(unix)]
+#[test]
+fn generate_test_media_reports_a_failing_ffmpeg() {
+    let dir = temp_path("media-failing").to_string_lossy().to_string();
+    let result = with_stub(
+        "FFMPEG_BIN",
+        "echo \"Unknown encoder 'libx264'\" >&2\nexit 1\n",
+        || generate_test_media(&dir),
+    );
+    let error = result.unwrap_err();
+    assert!(error.contains("h264_aac.mkv"), "{}", error);
+    assert!(error.contains("Unknown encoder 'libx264'"), "{}", error);
+}
+
+#[cfg(unix)]
+#[test]
+fn generate_test_media_runs_the_ffmpeg_of_ffmpeg_bin() {
+    let dir = temp_path("media-stub");
+    let dir_name = dir.to_string_lossy().to_string();
+    // the stub writes each output it is given, the last argument
+    let files = with_stub(
+        "FFMPEG_BIN",
+        "for last; do :; done\ntouch \"$last\"\n",
+        || generate_test_media(&dir_name),
+    )
+    .unwrap();
+    assert_eq!(files.len(), test_media_files().len());
+    for file in &files {
+        assert!(Path::new(file).is_file(), "{}", file);
+        assert_eq!(Path::new(file).parent(), Some(dir.as_path()));
+    }
+}
+
+// The end-to-end tests run the real ffmpeg, with libx264, libx265, libopus and libvmaf, and are
+// skipped when there is none
+
+// The test media, generated once for all the tests
+fn generated_media() -> &'static Path {
+    static MEDIA: OnceLock<PathBuf> = OnceLock::new();
+    MEDIA.get_or_init(|| {
+        let dir = temp_path("media");
+        generate_test_media(&dir.to_string_lossy()).unwrap();
+        dir
+    })
+}
+
+// A clip of 5 seconds in the folder of the test media, a trailer or a sample to the pipeline
+fn five_second_clip() -> &'static str {
+    static CLIP: OnceLock<()> = OnceLock::new();
+    CLIP.get_or_init(|| {
+        let status = ffmpeg_command()
+            .args(["-v", "error", "-y", "-f", "lavfi", "-i"])
+            .arg(TEST_VIDEO.replace("duration=2", "duration=5"))
+            .args([
+                "-c:v",
+                "libx264",
+                "-preset",
+                "ultrafast",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .arg(generated_media().join("clip_5s.mkv"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    });
+    "clip_5s.mkv"
+}
+
+// The bars of the pipeline are not drawn in the tests
+struct HiddenProgress;
+
+impl ProgressSink for HiddenProgress {
+    fn bars(&mut self, _total_frames: u64) -> PipelineBars {
+        PipelineBars::hidden()
+    }
+}
+
+// Run the pipeline with options on a folder of the test files named files, returns the input and
+// the output folder. The pipeline runs on the shared database
+fn run_pipeline(name: &str, files: &[&str], options: &[&str]) -> (PathBuf, PathBuf) {
+    shared_db();
+
+    let input = temp_path(&format!("{}-input", name));
+    let output = temp_path(&format!("{}-output", name));
+    fs::create_dir_all(&input).unwrap();
+    fs::create_dir_all(&output).unwrap();
+    for file in files {
+        fs::copy(generated_media().join(file), input.join(file)).unwrap();
+    }
+    let input_arg = input.to_string_lossy().to_string();
+    let output_arg = output.to_string_lossy().to_string();
+    let mut argv = vec![
+        "-i",
+        &input_arg,
+        "-o",
+        &output_arg,
+        "--crf-search",
+        "native",
+    ];
+    argv.extend(options);
+    Pipeline::new(test_args(&argv))
+        .and_then(|mut pipeline| {
+            pipeline.prepare()?;
+            pipeline.run(&mut HiddenProgress)
+        })
+        .unwrap();
+    (input, output)
+}
+
+// The outputs in folder, sorted
+fn outputs(folder: &Path) -> Vec<String> {
+    let mut outputs: Vec<String> = fs::read_dir(folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().to_string_lossy().to_string())
+        .collect();
+    outputs.sort();
+    outputs
+}
+
+// The codec names of the streams of codec_type in file
+fn codecs(file: &str, codec_type: &str) -> Vec<String> {
+    probe_file(file)
+        .streams_of(codec_type)
+        .iter()
+        .map(|stream| stream["codec_name"].as_str().unwrap_or("").to_string())
+        .collect()
+}
+
+// The status of the video_info row of file
+fn status_of(file: &Path) -> String {
+    let file = file.to_string_lossy();
+    let rows = db().get_all().unwrap();
+    let row = rows.iter().find(|row| row.2 == file).unwrap();
+    row.14.clone()
+}
+
+fn encoded_files() -> i64 {
+    db().stats_since("").unwrap().files
+}
+
+#[test]
+fn default_mode_encodes_the_test_media() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let encoded = encoded_files();
+    let (input, output) = run_pipeline(
+        "e2e-default",
+        &["h264_aac.mkv", "video_only.mkv"],
+        &["--encoder", "libx265", "--vmaf", "90"],
+    );
+
+    let outputs = outputs(&output);
+    assert_eq!(outputs.len(), 2, "{:?}", outputs);
+    for (output, (stem, audio)) in outputs.iter().zip([("h264_aac", 1), ("video_only", 0)]) {
+        let name = Path::new(output).file_name().unwrap().to_string_lossy();
+        assert!(
+            name.starts_with(&format!("{}.libx265.vmaf90.crf", stem)),
+            "{}",
+            name
+        );
+        assert!(name.ends_with(".mkv"), "{}", name);
+        assert_eq!(codecs(output, "video"), ["hevc"], "{}", name);
+        assert_eq!(codecs(output, "audio").len(), audio, "{}", name);
+    }
+    for file in ["h264_aac.mkv", "video_only.mkv"] {
+        let file = input.join(file).to_string_lossy().to_string();
+        assert!(db().video_info_exists(&file).unwrap(), "{}", file);
+    }
+    assert_eq!(encoded_files(), encoded + 2);
+}
+
+#[test]
+fn audio_only_mode_converts_the_audio_of_the_test_media() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let encoded = encoded_files();
+    let (input, output) = run_pipeline(
+        "e2e-audio-only",
+        &["h264_flac_5.1.mkv", "h264_flac_7.1.mkv"],
+        &["--mode", "audio-only"],
+    );
+
+    let outputs = outputs(&output);
+    assert_eq!(outputs.len(), 2, "{:?}", outputs);
+    for (output, channels) in outputs.iter().zip([6, 8]) {
+        // the video is copied
+        assert_eq!(codecs(output, "video"), ["h264"], "{}", output);
+        assert_eq!(codecs(output, "audio"), ["opus"], "{}", output);
+        let info = probe_file(output);
+        assert_eq!(
+            info.streams_of("audio")[0]["channels"],
+            channels,
+            "{}",
+            output
+        );
+    }
+    for file in ["h264_flac_5.1.mkv", "h264_flac_7.1.mkv"] {
+        let file = input.join(file).to_string_lossy().to_string();
+        assert!(db().video_info_exists(&file).unwrap(), "{}", file);
+    }
+    assert_eq!(encoded_files(), encoded + 2);
+}
+
+#[test]
+fn audio_only_mode_converts_each_layout_to_a_layout_opus_takes() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let layouts = [
+        ("h264_flac_mono.mkv", "mono"),
+        ("h264_flac_4.0.mkv", "quad"),
+        ("h264_flac_5.1_side.mkv", "5.1"),
+        ("h264_flac_6.1.mkv", "6.1"),
+        ("h264_flac_7.1.mkv", "7.1"),
+    ];
+    let files: Vec<&str> = layouts.iter().map(|(file, _)| *file).collect();
+    let (_, output) = run_pipeline("e2e-layouts", &files, &["--mode", "audio-only"]);
+
+    // 5.1(side) is converted to the 5.1 libopus takes
+    let outputs = outputs(&output);
+    assert_eq!(outputs.len(), layouts.len(), "{:?}", outputs);
+    for (file, layout) in layouts {
+        let output = output.join(file).to_string_lossy().to_string();
+        assert_eq!(codecs(&output, "audio"), ["opus"], "{}", file);
+        let info = probe_file(&output);
+        assert_eq!(
+            info.streams_of("audio")[0]["channel_layout"],
+            layout,
+            "{}",
+            file
+        );
+    }
+}
+
+#[test]
+fn a_short_clip_is_encoded_without_the_search_in_both_modes() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let clip = five_second_clip();
+    for mode in ["default", "chunked"] {
+        let encoded = encoded_files();
+        let (_, output) = run_pipeline(
+            &format!("e2e-short-{}", mode),
+            &[clip],
+            &["--mode", mode, "--encoder", "libx265", "--vmaf", "90"],
+        );
+
+        // chunked mode encodes it like the default mode, at --short-input-crf
+        let outputs = outputs(&output);
+        assert_eq!(outputs.len(), 1, "{}: {:?}", mode, outputs);
+        let name = Path::new(&outputs[0])
+            .file_name()
+            .unwrap()
+            .to_string_lossy();
+        assert_eq!(name, "clip_5s.libx265.vmaf90.crf20.mkv", "{}", mode);
+        assert_eq!(codecs(&outputs[0], "video"), ["hevc"], "{}", mode);
+        let duration = get_duration(&outputs[0]).unwrap();
+        assert!((duration - 5.0).abs() < 0.1, "{}: {}", mode, duration);
+        assert_eq!(encoded_files(), encoded + 1, "{}", mode);
+    }
+}
+
+#[test]
+fn target_size_encodes_two_passes_to_the_size() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let clip = five_second_clip();
+    let (_, output) = run_pipeline(
+        "e2e-target-size",
+        &[clip],
+        &["--encoder", "libx265", "--target-size", "0.2"],
+    );
+
+    let outputs = outputs(&output);
+    assert_eq!(outputs.len(), 1, "{:?}", outputs);
+    let name = Path::new(&outputs[0])
+        .file_name()
+        .unwrap()
+        .to_string_lossy();
+    assert_eq!(name, "clip_5s.libx265.size0.2MB.mkv");
+    // the two passes land within 10% of the target, under it by the container share
+    let target = mb_to_bytes(0.2) as f64;
+    let size = fs::metadata(&outputs[0]).unwrap().len() as f64;
+    assert!(
+        size <= target * 1.1 && size >= target * 0.9,
+        "{} bytes for {} bytes",
+        size,
+        target
+    );
+}
+
+#[test]
+fn skip_short_leaves_a_short_clip() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let clip = five_second_clip();
+    let encoded = encoded_files();
+    let (input, output) = run_pipeline("e2e-skip-short", &[clip], &["--skip-short"]);
+
+    assert!(outputs(&output).is_empty());
+    assert_eq!(status_of(&input.join(clip)), "skipped_short");
+    assert_eq!(encoded_files(), encoded);
+}
+
+#[test]
+fn an_anamorphic_clip_keeps_its_display_aspect_ratio() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let (input, output) = run_pipeline(
+        "e2e-anamorphic",
+        &["anamorphic.mkv"],
+        &["--encoder", "libx265", "--vmaf", "90"],
+    );
+
+    let source = input.join("anamorphic.mkv").to_string_lossy().to_string();
+    assert_eq!(anamorphic(&source).map(|source| source.dar), Some((16, 9)));
+    let outputs = outputs(&output);
+    assert_eq!(outputs.len(), 1, "{:?}", outputs);
+    let encoded = anamorphic(&outputs[0]).unwrap();
+    assert_eq!((encoded.width, encoded.height), (720, 480));
+    assert_eq!(encoded.sar, (32, 27));
+    assert_eq!(encoded.dar, (16, 9));
+}
+
+#[test]
+fn the_cover_art_is_kept_after_the_encoded_video() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let (input, output) = run_pipeline(
+        "e2e-cover-art",
+        &["cover_art.mkv"],
+        &["--encoder", "libx265", "--vmaf", "90"],
+    );
+
+    // the video at stream 1 is encoded, the picture at stream 0 is not
+    let source = input.join("cover_art.mkv").to_string_lossy().to_string();
+    let streams = select_streams(&source, &test_args(&[]), None);
+    assert_eq!(streams.cover_art, [0]);
+    assert_eq!(probe_file(&source).video_map(), "0:1");
+
+    let outputs = outputs(&output);
+    assert_eq!(outputs.len(), 1, "{:?}", outputs);
+    assert_eq!(codecs(&outputs[0], "video"), ["hevc", "mjpeg"]);
+    assert_eq!(codecs(&outputs[0], "audio"), ["aac"]);
+    let info = probe_file(&outputs[0]);
+    let video = info.streams_of("video");
+    assert_eq!(video[1]["disposition"]["attached_pic"], 1);
+    assert_eq!(info.video_map(), format!("0:{}", video[0]["index"]));
+}
@@ -0,0 +1,130 @@
+// Helpers of the unit tests: temporary paths and databases, rows and parsed options
+
+use crate::*;
+
+// A path in the temp dir that no other test or process uses, nothing is created
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    env::temp_dir().join(format!(
+        "transrustica-test-{}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst),
+        name
+    ))
+}
+
+// A new database in a temp file
+pub(crate) fn temp_db(name: &str) -> Db {
+    let path = temp_path(&format!("{}.db", name));
+    Db::open(path.to_str().unwrap()).unwrap()
+}
+
+// The database of db() for the tests that run the pipeline or the encodes, opened in the temp dir
+// before anything opens data.db in the working directory
+pub(crate) fn shared_db() -> &'static Db {
+    static DB_PATH: OnceLock<PathBuf> = OnceLock::new();
+    let db_path = DB_PATH.get_or_init(|| {
+        let path = temp_path("shared.db");
+        init_db(&path.to_string_lossy()).unwrap();
+        path
+    });
+    assert_eq!(db().path(), db_path.to_string_lossy());
+    db()
+}
+
+// A pending video_info row of filepath, the columns added later left at their defaults
+pub(crate) fn video_info(filepath: &str) -> VideoInfo {
+    let filename = Path::new(filepath)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    serde_json::from_value(serde_json::json!({
+        "filename": filename,
+        "filepath": filepath,
+        "width": 1920,
+        "height": 1080,
+        "duration": 60.0,
+        "pixel_format": "yuv420p",
+        "display_aspect_ratio": "16:9",
+        "sample_aspect_ratio": "1:1",
+        "format": "matroska,webm",
+        "size": 1_000_000,
+        "folder_size": 0,
+        "bitrate": 8_000_000,
+        "codec": "h264",
+        "status": "pending_video",
+        "audio_codec": "aac",
+        "audio_bitrate": 128_000,
+        "hash": "",
+    }))
+    .unwrap()
+}
+
+// The options of the cli given options, -i is the temp dir unless options have their own
+pub(crate) fn test_args(options: &[&str]) -> Args {
+    let temp_dir = env::temp_dir().to_string_lossy().to_string();
+    let mut argv = vec!["TransRustica".to_string()];
+    if !options.contains(&"-i") {
+        argv.extend(["-i".to_string(), temp_dir]);
+    }
+    argv.extend(options.iter().map(|option| option.to_string()));
+    Args::try_parse_from(argv).unwrap()
+}
+
+// Held by the tests that change the environment of the tools (set_tool_env, FFMPEG_BIN,
+// FFPROBE_BIN) or run them, both are set for the whole process
+pub(crate) fn lock_tools() -> std::sync::MutexGuard<'static, ()> {
+    static TOOLS: Mutex<()> = Mutex::new(());
+    TOOLS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Whether ffmpeg runs, the tests that need the real one are skipped without it
+pub(crate) fn ffmpeg_available() -> bool {
+    ffmpeg_command()
+        .arg("-version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+// A file in the temp dir that probe_file describes with json, the output of ffprobe for it
+pub(crate) fn probed_file(name: &str, json: Value) -> String {
+    let path = temp_path(name);
+    fs::write(&path, b"video").unwrap();
+    cache_media_info(media_info(&path, &json, file_stamp(&path)));
+    path.to_string_lossy().to_string()
+}
+
+// Run command with var pointing at a stub script of body, the stub of a tool that replays its
+// canned output
+#[cfg(unix)]
+pub(crate) fn with_stub<T>(var: &str, body: &str, command: impl FnOnce() -> T) -> T {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _tools = lock_tools();
+    let stub = temp_path(&format!("{}.sh", var));
+    fs::write(&stub, format!("#!/bin/sh\n{}", body)).unwrap();
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).unwrap();
+    let previous = env::var(var).ok();
+    env::set_var(var, &stub);
+    let result = command();
+    match previous {
+        Some(previous) => env::set_var(var, previous),
+        None => env::remove_var(var),
+    }
+    result
+}
+
+// Run command in dir as the working directory, where the chunked mode writes its files. The working
+// directory is the one of the process, it is only changed within with_stub, which holds lock_tools
+pub(crate) fn in_dir<T>(dir: &Path, command: impl FnOnce() -> T) -> T {
+    // back to the previous one when command panics too
+    struct Restore(PathBuf);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = env::set_current_dir(&self.0);
+        }
+    }
+    let _restore = Restore(env::current_dir().unwrap());
+    env::set_current_dir(dir).unwrap();
+    command()
+}
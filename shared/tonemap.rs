@@ -0,0 +1,56 @@
+// --tonemap sdr: HDR10 and HLG sources are converted to 8 bit bt709 by the encode. The samples of the
+// CRF search and the reference of every VMAF measurement go through the same filters, an SDR
+// encode scored against HDR frames says nothing about its quality
+
+use crate::*;
+
+// Linear light from the source, hable in float, then bt709 limited range
+pub(crate) const ZSCALE_TONEMAP: &str = "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv";
+
+// libplacebo does the same on the GPU, with a better peak detection
+const LIBPLACEBO_TONEMAP: &str =
+    "libplacebo=tonemapping=hable:colorspace=bt709:color_primaries=bt709:color_trc=bt709:range=tv";
+
+// The mastering display and light level of the source would still mark the output as HDR
+const STRIP_HDR_SIDE_DATA: &str = "sidedata=mode=delete:type=MASTERING_DISPLAY_METADATA,sidedata=mode=delete:type=CONTENT_LIGHT_LEVEL";
+
+// The pixel format of a tonemapped encode, whatever --pix-fmt says
+pub const TONEMAP_PIX_FMT: &str = "yuv420p";
+
+// A file of the run that was tonemapped, for --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct TonemappedFile {
+    pub path: String,
+    pub filter: String,
+}
+
+pub(crate) fn has_libplacebo() -> bool {
+    ffmpeg_capabilities()
+        .filters
+        .iter()
+        .any(|f| f == "libplacebo")
+}
+
+pub(crate) fn has_zscale_tonemap() -> bool {
+    let filters = &ffmpeg_capabilities().filters;
+    ["zscale", "tonemap"]
+        .iter()
+        .all(|filter| filters.iter().any(|f| f == filter))
+}
+
+// The filter chain that converts the video of file to SDR, None when tonemap is off or file is not
+// HDR. The same chain goes into the encode and the reference of the VMAF
+pub fn tonemap_filter(file: &str, tonemap: &str) -> Option<String> {
+    if tonemap != "sdr" || !probe_file(file).is_hdr() {
+        return None;
+    }
+    let tonemap = if has_libplacebo() {
+        LIBPLACEBO_TONEMAP
+    } else {
+        ZSCALE_TONEMAP
+    };
+    Some(format!(
+        "{},format={},setparams=color_primaries=bt709:color_trc=bt709:colorspace=bt709,{}",
+        tonemap, TONEMAP_PIX_FMT, STRIP_HDR_SIDE_DATA
+    ))
+}
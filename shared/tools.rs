@@ -0,0 +1,186 @@
+// The ffmpeg, ffprobe and ab-av1 binaries every command runs. FFMPEG_BIN, FFPROBE_BIN and AB_AV1_BIN
+// override them, for builds outside the PATH or a stub script that replays canned output. Every
+// command is made by ffmpeg_command, ffprobe_command or ab_av1_command, so the environment of the run
+// (--gpu-index, --env, --clean-env) reaches the searches, encodes, scenes and VMAF alike
+
+use crate::*;
+
+fn tool_bin(var: &str, default: &str) -> String {
+    match env::var(var) {
+        Ok(bin) if !bin.trim().is_empty() => bin,
+        _ => default.to_string(),
+    }
+}
+
+pub fn ffmpeg_bin() -> String {
+    tool_bin("FFMPEG_BIN", "ffmpeg")
+}
+
+pub fn ffprobe_bin() -> String {
+    tool_bin("FFPROBE_BIN", "ffprobe")
+}
+
+pub fn ab_av1_bin() -> String {
+    tool_bin("AB_AV1_BIN", "ab-av1.exe")
+}
+
+// The variables --clean-env passes on, what the tools need to find their libraries, the drivers and
+// a temp folder
+const CLEAN_ENV_KEPT: [&str; 15] = [
+    "PATH",
+    "PATHEXT",
+    "HOME",
+    "USERPROFILE",
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "WINDIR",
+    "TEMP",
+    "TMP",
+    "TMPDIR",
+    "LANG",
+    "LD_LIBRARY_PATH",
+    "XDG_RUNTIME_DIR",
+    "LIBVA_DRIVER_NAME",
+    "LIBVA_DRIVERS_PATH",
+];
+
+// The first render node of linux, the GPU of --gpu-index n is renderD128 + n
+const RENDER_NODE_BASE: u32 = 128;
+
+// The environment of the tool processes of a run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolEnv {
+    // --clean-env, only CLEAN_ENV_KEPT and vars
+    pub clean: bool,
+    // set in this order, the CUDA_VISIBLE_DEVICES of --gpu-index first so --env can override it
+    pub vars: Vec<(String, String)>,
+}
+
+impl ToolEnv {
+    pub fn from_args(args: &Args) -> ToolEnv {
+        let mut vars = Vec::new();
+        // nvenc encodes on the first GPU CUDA sees
+        if let Some(gpu_index) = args.gpu_index {
+            vars.push(("CUDA_VISIBLE_DEVICES".to_string(), gpu_index.to_string()));
+        }
+        // validated by clap
+        vars.extend(args.env.iter().filter_map(|var| {
+            var.split_once('=')
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+        }));
+        ToolEnv {
+            clean: args.clean_env,
+            vars,
+        }
+    }
+
+    pub fn apply(&self, command: &mut Command) {
+        if self.clean {
+            command.env_clear();
+            for key in CLEAN_ENV_KEPT {
+                if let Some(value) = env::var_os(key) {
+                    command.env(key, value);
+                }
+            }
+        }
+        for (key, value) in &self.vars {
+            command.env(key, value);
+        }
+    }
+}
+
+lazy_static! {
+    static ref TOOL_ENV: Mutex<ToolEnv> = Mutex::new(ToolEnv::default());
+}
+
+// Use the environment of args for the tools from now on, a web task sets its own
+pub fn set_tool_env(args: &Args) {
+    let tool_env = ToolEnv::from_args(args);
+    if tool_env.clean || !tool_env.vars.is_empty() {
+        let keys: Vec<&str> = tool_env.vars.iter().map(|(key, _)| key.as_str()).collect();
+        debug!(
+            "Tool environment: {}{}",
+            if tool_env.clean { "clean, " } else { "" },
+            keys.join(", ")
+        );
+    }
+    *TOOL_ENV.lock().unwrap() = tool_env;
+}
+
+// A command of program with the environment of the run
+pub fn tool_command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    TOOL_ENV.lock().unwrap().apply(&mut command);
+    command
+}
+
+pub fn ffmpeg_command() -> Command {
+    tool_command(&ffmpeg_bin())
+}
+
+pub fn ffprobe_command() -> Command {
+    tool_command(&ffprobe_bin())
+}
+
+pub fn ab_av1_command() -> Command {
+    tool_command(&ab_av1_bin())
+}
+
+// The device of --gpu-index gpu_index: a number stays a number (the adapter of windows), a render
+// node /dev/dri/renderD128 becomes the one of the GPU
+fn gpu_device(device: &str, gpu_index: u32) -> String {
+    match device.rfind("renderD") {
+        Some(at) => format!("{}renderD{}", &device[..at], RENDER_NODE_BASE + gpu_index),
+        None => gpu_index.to_string(),
+    }
+}
+
+// params with the child_device= of -init_hw_device and the path of -vaapi_device pointing at the GPU
+// of --gpu-index
+// -init_hw_device qsv=intel,child_device=0 -> -init_hw_device qsv=intel,child_device=1
+// params that don't split into arguments are left as they are, params_input_validation refuses them
+pub fn gpu_params(params: &str, gpu_index: u32) -> String {
+    let mut words = match split_params(params) {
+        Ok(words) => words,
+        Err(_) => return params.to_string(),
+    };
+    for i in 0..words.len() {
+        if words[i].contains("child_device=") {
+            words[i] = words[i]
+                .split(',')
+                .map(|option| match option.split_once("child_device=") {
+                    Some((prefix, device)) => {
+                        format!("{}child_device={}", prefix, gpu_device(device, gpu_index))
+                    }
+                    None => option.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join(",");
+        }
+        if i > 0 && words[i - 1] == "-vaapi_device" {
+            words[i] = gpu_device(&words[i], gpu_index);
+        }
+    }
+    join_params(&words)
+}
+
+// Point the params of every encoder at the GPU of --gpu-index, before the encoder is resolved
+pub fn apply_gpu_index(args: &mut Args) {
+    let gpu_index = match args.gpu_index {
+        Some(gpu_index) => gpu_index,
+        None => return,
+    };
+    for params in [
+        &mut args.params_x265,
+        &mut args.params_hevc_nvenc,
+        &mut args.params_hevc_qsv,
+        &mut args.params_av1_qsv,
+        &mut args.params_libaom_av1,
+        &mut args.params_libsvtav1,
+    ] {
+        *params = gpu_params(params, gpu_index);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,202 @@
+use super::*;
+
+// The variables command sets, None for the ones it removes
+fn command_env(command: &Command) -> Vec<(String, Option<String>)> {
+    command
+        .get_envs()
+        .map(|(key, value)| {
+            (
+                key.to_string_lossy().to_string(),
+                value.map(|value| value.to_string_lossy().to_string()),
+            )
+        })
+        .collect()
+}
+
+fn var(key: &str, value: &str) -> (String, String) {
+    (key.to_string(), value.to_string())
+}
+
+#[test]
+fn tool_env_of_the_options() {
+    let args = test_args(&[
+        "--gpu-index",
+        "1",
+        "--env",
+        "HTTP_PROXY=",
+        "--env",
+        "LIBVA_DRIVER_NAME=iHD",
+        "--clean-env",
+    ]);
+    assert_eq!(
+        ToolEnv::from_args(&args),
+        ToolEnv {
+            clean: true,
+            vars: vec![
+                var("CUDA_VISIBLE_DEVICES", "1"),
+                var("HTTP_PROXY", ""),
+                var("LIBVA_DRIVER_NAME", "iHD"),
+            ],
+        }
+    );
+    assert_eq!(ToolEnv::from_args(&test_args(&[])), ToolEnv::default());
+}
+
+#[test]
+fn env_overrides_the_gpu_index() {
+    let args = test_args(&["--gpu-index", "1", "--env", "CUDA_VISIBLE_DEVICES=0,2"]);
+    let mut command = Command::new("ffmpeg");
+    ToolEnv::from_args(&args).apply(&mut command);
+    assert_eq!(
+        command_env(&command),
+        vec![("CUDA_VISIBLE_DEVICES".to_string(), Some("0,2".to_string()))]
+    );
+}
+
+#[test]
+fn the_command_gets_only_the_vars_without_clean_env() {
+    let mut command = Command::new("ffmpeg");
+    ToolEnv {
+        clean: false,
+        vars: vec![var("CUDA_VISIBLE_DEVICES", "3"), var("NO_PROXY", "*")],
+    }
+    .apply(&mut command);
+    assert_eq!(
+        command_env(&command),
+        vec![
+            ("CUDA_VISIBLE_DEVICES".to_string(), Some("3".to_string())),
+            ("NO_PROXY".to_string(), Some("*".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn clean_env_keeps_only_the_listed_vars() {
+    let mut command = Command::new("ffmpeg");
+    ToolEnv {
+        clean: true,
+        vars: vec![var("CUDA_VISIBLE_DEVICES", "0")],
+    }
+    .apply(&mut command);
+    for (key, _) in command_env(&command) {
+        assert!(
+            CLEAN_ENV_KEPT.contains(&key.as_str()) || key == "CUDA_VISIBLE_DEVICES",
+            "{}",
+            key
+        );
+    }
+    if let Some(path) = env::var_os("PATH") {
+        assert!(command_env(&command)
+            .contains(&("PATH".to_string(), Some(path.to_string_lossy().to_string()))));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn a_clean_env_child_sees_only_the_kept_vars() {
+    let mut command = Command::new("env");
+    ToolEnv {
+        clean: true,
+        vars: vec![var("TRANSRUSTICA_TEST", "1")],
+    }
+    .apply(&mut command);
+    let output = command.output().unwrap();
+    let keys: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('=').map(|(key, _)| key.to_string()))
+        .collect();
+    assert!(keys.contains(&"TRANSRUSTICA_TEST".to_string()));
+    for key in keys {
+        assert!(
+            CLEAN_ENV_KEPT.contains(&key.as_str()) || key == "TRANSRUSTICA_TEST",
+            "{}",
+            key
+        );
+    }
+}
+
+#[test]
+fn gpu_params_rewrites_the_devices() {
+    let cases = [
+        // the qsv adapter of windows
+        (
+            "-init_hw_device qsv=intel,child_device=0 -look_ahead 1",
+            2,
+            "-init_hw_device qsv=intel,child_device=2 -look_ahead 1",
+        ),
+        // the render node of linux
+        (
+            "-init_hw_device qsv=hw,child_device=/dev/dri/renderD128",
+            1,
+            "-init_hw_device qsv=hw,child_device=/dev/dri/renderD129",
+        ),
+        (
+            "-vaapi_device /dev/dri/renderD128 -tune grain",
+            2,
+            "-vaapi_device /dev/dri/renderD130 -tune grain",
+        ),
+        // nothing to point at a GPU
+        ("-preset slow -tune grain", 1, "-preset slow -tune grain"),
+        ("", 1, ""),
+        // quoted arguments stay together
+        (
+            r#"-init_hw_device qsv=intel,child_device=0 -metadata "title=A Movie""#,
+            1,
+            r#"-init_hw_device qsv=intel,child_device=1 -metadata "title=A Movie""#,
+        ),
+    ];
+    for (params, gpu_index, expected) in cases {
+        assert_eq!(gpu_params(params, gpu_index), expected, "{}", params);
+    }
+    // params that don't split are not touched
+    assert_eq!(
+        gpu_params(r#"-x "child_device=0"#, 1),
+        r#"-x "child_device=0"#
+    );
+}
+
+#[test]
+fn apply_gpu_index_points_every_encoder_at_the_gpu() {
+    let mut args = test_args(&[
+        "--gpu-index",
+        "1",
+        "--params-hevc-qsv=-init_hw_device qsv=intel,child_device=0",
+        "--params-av1-qsv=-init_hw_device qsv=hw,child_device=/dev/dri/renderD128",
+    ]);
+    let params_x265 = args.params_x265.clone();
+    apply_gpu_index(&mut args);
+    assert_eq!(
+        args.params_hevc_qsv,
+        "-init_hw_device qsv=intel,child_device=1"
+    );
+    assert_eq!(
+        args.params_av1_qsv,
+        "-init_hw_device qsv=hw,child_device=/dev/dri/renderD129"
+    );
+    assert_eq!(args.params_x265, params_x265);
+
+    // without --gpu-index the params stay
+    let mut args = test_args(&["--params-hevc-qsv=-init_hw_device qsv=intel,child_device=0"]);
+    apply_gpu_index(&mut args);
+    assert_eq!(
+        args.params_hevc_qsv,
+        "-init_hw_device qsv=intel,child_device=0"
+    );
+}
+
+#[test]
+fn every_tool_command_gets_the_env_of_the_run() {
+    let _tools = lock_tools();
+    set_tool_env(&test_args(&["--gpu-index", "2"]));
+    let commands = [ffmpeg_command(), ffprobe_command(), ab_av1_command()];
+    set_tool_env(&test_args(&[]));
+    for command in &commands {
+        assert_eq!(
+            command_env(command),
+            vec![("CUDA_VISIBLE_DEVICES".to_string(), Some("2".to_string()))],
+            "{:?}",
+            command.get_program()
+        );
+    }
+    assert_eq!(command_env(&ffmpeg_command()), vec![]);
+}
@@ -0,0 +1,549 @@
+// The final encode of the default mode, in two passes for --target-size
+
+use crate::*;
+
+use std::ffi::OsString;
+
+// Codec an ffmpeg encoder produces
+pub fn encoder_codec(encoder: &str) -> &str {
+    match encoder {
+        "libx265" | "hevc_nvenc" | "hevc_qsv" => "hevc",
+        "av1" | "libaom-av1" | "libsvtav1" | "av1_qsv" | "av1_nvenc" => "av1",
+        other => other,
+    }
+}
+
+// Append key=value pairs to the value of an option like -x265-params or -svtav1-params, or add
+// the option when params has none
+pub(crate) fn add_codec_params(
+    mut params: Vec<String>,
+    option: &str,
+    codec_params: &str,
+) -> Vec<String> {
+    match params.iter().position(|param| param == option) {
+        Some(i) if i + 1 < params.len() => {
+            params[i + 1] = format!("{}:{}", params[i + 1], codec_params);
+        }
+        _ => {
+            params.push(option.to_string());
+            params.push(codec_params.to_string());
+        }
+    }
+    params
+}
+
+// -map arguments of the video stream picked by select_video_stream, so the data and null video
+// streams of TS captures stay out, deinterlaced with deinterlace, the SAR of an anamorphic source
+// kept with setsar, tonemapped with the filter of --tonemap, with the --burn-subs subtitle burned
+// in as [burned] and scaled to the --renditions height or into --max-resolution with scale
+fn video_map_args(
+    file: &str,
+    burn_subtitle: Option<&SubtitleStream>,
+    scale: Option<&str>,
+    deinterlace: bool,
+    setsar: Option<&str>,
+    tonemap: Option<&str>,
+) -> Vec<String> {
+    let video = probe_file(file).video_map();
+    let scaled = scale.map(|scale| format!(",{}", scale)).unwrap_or_default();
+    let prepare: Vec<&str> = deinterlace
+        .then_some("yadif")
+        .into_iter()
+        .chain(setsar)
+        .chain(tonemap)
+        .collect();
+    // the subtitle is burned into the deinterlaced and tonemapped frames
+    let input = if prepare.is_empty() {
+        format!("[{}]", video)
+    } else {
+        format!("[{}]{}[video];[video]", video, prepare.join(","))
+    };
+    match burn_subtitle {
+        Some(subtitle) if subtitle.is_bitmap() => vec![
+            "-filter_complex".to_string(),
+            format!("{}[0:s:{}]overlay{}[burned]", input, subtitle.index, scaled),
+            "-map".to_string(),
+            "[burned]".to_string(),
+        ],
+        Some(subtitle) => vec![
+            "-filter_complex".to_string(),
+            format!(
+                "{}subtitles=filename={}:si={}{}[burned]",
+                input,
+                escape_filter_path(file),
+                subtitle.index,
+                scaled
+            ),
+            "-map".to_string(),
+            "[burned]".to_string(),
+        ],
+        None => {
+            let filters: Vec<&str> = prepare.into_iter().chain(scale).collect();
+            let mut args = vec!["-map".to_string(), video];
+            // only the video, the cover art after it is copied
+            if !filters.is_empty() {
+                args.extend(["-filter:v:0".to_string(), filters.join(",")]);
+            }
+            args
+        }
+    }
+}
+
+// Log how much smaller output came out than file and count it in the metrics
+pub(crate) fn log_size_reduction(file: &str, output: &str, labels: &[(&str, &str)]) {
+    let input_size = get_file_size(file).unwrap_or(0);
+    let output_size = get_file_size(output).unwrap_or(0);
+    info!(
+        "Size reduction: {} -> {} ({:.2}%)",
+        format_size(input_size),
+        format_size(output_size),
+        reduction_percent(input_size, output_size)
+    );
+    inc_metric(&FILES_TRANSCODED, labels, 1.0);
+    inc_metric(
+        &BYTES_SAVED,
+        labels,
+        input_size.saturating_sub(output_size) as f64,
+    );
+    emit_file_done(file, output);
+}
+
+// The arguments of the final encode of file with spec into part, the kept streams with the audio
+// plans and the aspect of an anamorphic source after the video. Returns them with the audio codecs
+// for the progress line
+pub(crate) fn final_encode_args(
+    file: &str,
+    spec: &EncodeSpec,
+    streams: &StreamSelection,
+    audio_plans: &[AudioPlan],
+    original_audio_codec: &str,
+    aspect_args: Vec<String>,
+    part: &PartFile,
+) -> (Vec<OsString>, String) {
+    let (stream_args, final_audio_codec) =
+        stream_output_args(streams, audio_plans, original_audio_codec);
+    // total_size= of the progress blocks is the output size in bytes so far
+    let args = FfmpegArgs::new(file, spec.clone(), part.path())
+        .input_args(["-y"])
+        .extra_inputs(streams.external_inputs())
+        .output_args(stream_args)
+        .output_args(aspect_args)
+        .output_args(["-progress", "pipe:2"])
+        .output_args(part.format_args())
+        .build();
+    (args, final_audio_codec)
+}
+
+// The expected size in bytes of the output, the output_size bytes of the first frame frames scaled to
+// all frames, 0 before the first frame
+pub(crate) fn expected_output_size(output_size: u64, frame: u64, frames: f32) -> u64 {
+    if frame == 0 {
+        return 0;
+    }
+    (output_size as f64 / frame as f64 * frames as f64) as u64
+}
+
+// Ok(false) when the encode was stopped because the output volume ran full,
+// the error carries the end of ffmpeg's stderr when ffmpeg failed
+pub fn run_ffmpeg_transcode(
+    file: &str,
+    encoder: &str,
+    params_x265: &str,
+    preset_x265: &str,
+    pix_fmt: &str,
+    output_folder: &str,
+    target_crf: &str,
+    file_bar: &ProgressBar,
+    transcode_bar: &ProgressBar,
+    total_bar: &ProgressBar,
+    info_bar: &ProgressBar,
+    codec_bar: &ProgressBar,
+    total_files: &i32,
+    current_file_count: &u64,
+    vector_files_to_process_frame_count: &Vec<(String, u64)>,
+    final_vmaf: &f32,
+    original_audio_codec: &str,
+    transcode_info: &str,
+    audio_plans: &[AudioPlan],
+    vec_video_args: &Vec<(usize, String, String, String)>,
+    task_id: &str,
+    min_free_space: f64,
+    burn_subtitle: Option<&SubtitleStream>,
+    streams: &StreamSelection,
+    target_bitrate: Option<u32>,
+    rendition: Option<&Rendition>,
+    deinterlace: bool,
+    tonemap: Option<&str>,
+    downscale: Option<&str>,
+    keyint: Option<Keyint>,
+) -> Result<bool, String> {
+    let target_crf = target_crf.trim();
+    // the scale of a rendition or of --max-resolution, never both
+    let scale = rendition
+        .map(|rendition| rendition.scale_filter())
+        .or(downscale.map(String::from));
+    let _final_audio_codec: String;
+    let _final_video_codec: String;
+    let mut _web_fps: u64 = 0;
+    let mut _web_current_frame: u64 = 0;
+    let mut _web_total_frame: f32 = 0.0;
+    let mut _web_expected_size: u64 = 0;
+    let mut _web_current_file: u64 = current_file_count.clone() as u64;
+    let mut _web_total_files: u64 = total_files.clone() as u64;
+    // a rendition shows up by its output name, the source has several
+    let mut _web_current_file_name = match rendition {
+        Some(_) => output_folder.to_string(),
+        None => file.to_string(),
+    };
+    let mut _web_progess: Progress;
+
+    // --target-size with libx265 and libaom-av1 runs a first pass that writes its stats to the
+    // working directory, the encode below is the second pass
+    let two_pass = target_bitrate.is_some() && is_two_pass_encoder(encoder);
+    let pass_log = two_pass_log_prefix(file);
+
+    let quality = match target_bitrate {
+        Some(kbps) => EncodeQuality::Bitrate {
+            kbps,
+            pass: two_pass.then(|| (2, pass_log.clone())),
+        },
+        None => EncodeQuality::Crf(
+            target_crf
+                .parse()
+                .map_err(|_| format!("Invalid CRF {:?} for {}", target_crf, file))?,
+        ),
+    };
+    let mut spec = EncodeSpec::new(encoder, quality, preset_x265, params_x265, pix_fmt);
+    spec.keyint = keyint;
+    // a trial encodes its range
+    let trial = trial_range(file);
+    spec.seek = trial.map(|range| range.seek_args());
+    // an anamorphic source keeps its SAR and DAR, or the output plays stretched
+    let anamorphic = anamorphic(file);
+    let setsar = anamorphic.map(|anamorphic| anamorphic.setsar_filter());
+    let aspect_args = anamorphic
+        .map(|anamorphic| anamorphic.aspect_args())
+        .unwrap_or_default();
+    // map the video stream, or the video with the subtitle burned in
+    spec.maps = video_map_args(
+        file,
+        burn_subtitle,
+        scale.as_deref(),
+        deinterlace,
+        setsar.as_deref(),
+        tonemap,
+    );
+
+    // Add video codec to final_video_codec string
+    let mut final_video_codec = String::new(); // Initialize with an empty string
+    if !vec_video_args.is_empty() {
+        for (_i, codec, _width, _height) in vec_video_args {
+            if !codec.is_empty() {
+                final_video_codec.push_str(&format!("{}->{},", codec, encoder_codec(encoder)));
+            } else if codec == "" {
+                final_video_codec.push_str(&format!("{},", encoder_codec(encoder)));
+            }
+        }
+        final_video_codec.pop(); // Remove the trailing comma
+    } else {
+        final_video_codec = "copy".to_string();
+    }
+
+    let part = PartFile::new(output_folder, file);
+    let (final_args, final_audio_codec) = final_encode_args(
+        file,
+        &spec,
+        streams,
+        audio_plans,
+        original_audio_codec,
+        aspect_args,
+        &part,
+    );
+    let mut cmd = ffmpeg_command();
+    cmd.args(final_args);
+
+    let frames = match trial {
+        Some(range) => range.frames(get_fps_f32(file)) as f32,
+        None => frame_count(&file),
+    };
+
+    // set transcode_progress length to the file's number of frames', each pass counts once
+    let passes = if two_pass { 2 } else { 1 };
+    transcode_bar.set_length(frames as u64 * passes);
+
+    // the first pass fills the first half of the bar, the second pass continues from there
+    let mut pass_offset = 0;
+    let mut cost = EncodeCost::default();
+    if let Some(target_bitrate) = target_bitrate.filter(|_| two_pass) {
+        spec.quality = EncodeQuality::Bitrate {
+            kbps: target_bitrate,
+            pass: Some((1, pass_log.clone())),
+        };
+        let mut first_pass = ffmpeg_command();
+        first_pass.args(first_pass_args(file, spec));
+        info!(
+            "{}",
+            format!("First pass of {} at {} kbps", file, target_bitrate).yellow()
+        );
+        match run_ffmpeg_first_pass(
+            first_pass,
+            file,
+            encoder,
+            transcode_bar,
+            frames,
+            task_id,
+            &pass_log,
+        ) {
+            Ok(first_pass_cost) => cost = first_pass_cost,
+            Err(tail) => {
+                remove_two_pass_logs(&pass_log);
+                return Err(tail);
+            }
+        }
+        pass_offset = frames as u64;
+    }
+
+    debug!("{:?}", cmd);
+    shield_from_ctrlc(&mut cmd);
+
+    // Execute ffmpeg command
+    let mut output = cmd
+        .stdout(Stdio::piped()) // Redirect standard output
+        .stderr(Stdio::piped()) // Redirect standard error
+        .spawn()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    let mut outputs = vec![part.path().to_string()];
+    if two_pass {
+        outputs.extend(two_pass_log_files(&pass_log));
+    }
+    let outputs: Vec<&str> = outputs.iter().map(String::as_str).collect();
+    let _tracked = track_child(&output, &outputs);
+    let sampler = CostSampler::start(&output, encoder);
+    let mut stderr_tail = String::new();
+
+    let input_file_size = get_file_size(file).unwrap_or(0);
+    set_progress_codecs(
+        vec_video_args,
+        Some(encoder),
+        audio_plans,
+        input_file_size,
+        target_crf.parse::<f32>().ok(),
+    );
+
+    // Set file_bar message to the current file count / total file count + file name
+    let file_name = Path::new(&file)
+        .file_name()
+        .unwrap_or(std::ffi::OsStr::new("Unknown"))
+        .to_str()
+        .unwrap_or("Invalid UTF-8");
+    match rendition {
+        Some(rendition) => file_bar.set_message(format!("[{}][{}]", file_name, rendition)),
+        None => file_bar.set_message(format!("[{}]", file_name)),
+    }
+
+    let mut last_space_check = std::time::Instant::now();
+    let labels = task_labels(task_id, encoder);
+    let mut last_frame: u64 = 0;
+    let mut output_size: u64 = 0;
+
+    // ffmpeg is read as fast as it writes, the bars and the web progress take the latest values at
+    // most every --progress-interval, and once more when it is done so they end on its last frame
+    let mut throttle = ProgressThrottle::new();
+    let mut pending_frame: Option<u64> = None;
+    let mut last_fps: Option<u64> = None;
+    let mut speed = String::new();
+    let mut bitrate_kbps: Option<f64> = None;
+    let mut out_time_us: u64 = 0;
+    let mut samples = EncodeSampler::new(task_id);
+    loop {
+        let mut buffer = [0; 1024]; // Adjust buffer size as needed
+        let finished = match output.stderr.as_mut().unwrap().read(&mut buffer) {
+            Ok(0) => true,
+            Ok(n) => {
+                push_stderr_tail(&mut stderr_tail, &String::from_utf8_lossy(&buffer[..n]));
+                let output_str = String::from_utf8_lossy(&buffer[..n]).trim().to_string();
+                if let Some(total_size) = parse_total_size(&output_str) {
+                    output_size = total_size;
+                }
+                pending_frame = parse_frame_from_output(&output_str).or(pending_frame);
+                last_fps = parse_fps_from_output(&output_str).or(last_fps);
+                if let Some(last_speed) = parse_speed_from_output(&output_str) {
+                    speed = last_speed;
+                }
+                bitrate_kbps = parse_bitrate_from_output(&output_str).or(bitrate_kbps);
+                out_time_us = parse_out_time_us(&output_str).unwrap_or(out_time_us);
+                false
+            }
+            Err(e) => {
+                error!("Error reading stdout: {}", e);
+                true
+            }
+        };
+        if pending_frame.is_some() && (finished || throttle.ready()) {
+            let frame = pending_frame.take().unwrap();
+            transcode_bar.set_position(pass_offset + frame);
+            inc_metric(
+                &FRAMES_PROCESSED,
+                &labels,
+                frame.saturating_sub(last_frame) as f64,
+            );
+            last_frame = last_frame.max(frame);
+            // TODO: If total files = 1 the iter, otherwise for loop
+            let mut current_frame_count = 0;
+            if *total_files == 1 {
+                current_frame_count = vector_files_to_process_frame_count
+                    .iter()
+                    .filter(|(file, _)| file == &file.to_string())
+                    .map(|(_, frame_count)| frame_count)
+                    .sum();
+            } else {
+                for i in 0..*current_file_count {
+                    current_frame_count += vector_files_to_process_frame_count[i as usize].1;
+                }
+            }
+            // Set the total_bar position to the current frame count
+            total_bar.set_position(current_frame_count + frame);
+            total_bar.set_message(format!("{}/{}", current_file_count, total_files));
+            // set info_bar message to the current file count / total file count, current FPS from the output, and the file name
+            if let Some(fps) = last_fps {
+                let expected_size = expected_output_size(output_size, frame, frames);
+                let expected_size_percent =
+                    100.0 - reduction_percent(input_file_size, expected_size);
+
+                info_bar.set_message(format!(
+                    "{}][{}/{}][CRF: {}][VMAF: {}][{} FPS][{}][{:.2}%][{}x",
+                    transcode_info,
+                    current_file_count,
+                    total_files,
+                    target_crf,
+                    final_vmaf,
+                    fps,
+                    format_size(expected_size),
+                    expected_size_percent,
+                    speed
+                ));
+
+                codec_bar.set_message(format!("{}][{}", final_video_codec, final_audio_codec));
+
+                emit_transcode_progress(file, pass_offset + frame, frames as u64 * passes, fps);
+                // bitrate=N/A until the first packet, the size so far over the time so far then
+                let out_bitrate_kbps = bitrate_kbps.unwrap_or(if out_time_us > 0 {
+                    output_size as f64 * 8000.0 / out_time_us as f64
+                } else {
+                    0.0
+                });
+                samples.sample(frame, fps, out_bitrate_kbps, speed.parse().unwrap_or(0.0));
+
+                // stop before the output volume runs full, the rest of the encode has to fit
+                if expected_size > 0 && last_space_check.elapsed().as_secs() >= 10 {
+                    last_space_check = std::time::Instant::now();
+                    let remaining_mb = bytes_to_mb(expected_size.saturating_sub(output_size));
+                    if let Err(e) = check_free_space(output_folder, remaining_mb, min_free_space) {
+                        error!("{}", format!("Stopping {}: {}", file, e).red());
+                        let _ = output.kill();
+                        let _ = output.wait();
+                        part.discard();
+                        if let Err(e) = set_db_status(file, "no_space") {
+                            error!("Failed to update status of {}: {}", file, e);
+                        }
+                        if two_pass {
+                            remove_two_pass_logs(&pass_log);
+                        }
+                        return Ok(false);
+                    }
+                }
+
+                let _web_task_id = task_id.to_string();
+
+                *WEB_TASK_ID_STATIC.lock().unwrap() = _web_task_id.clone();
+                *WEB_FPS_STATIC.lock().unwrap() = fps.clone();
+                set_metric(&TASK_FPS, &labels, fps as f64);
+                _web_fps = *WEB_FPS_STATIC.lock().unwrap();
+                *WEB_CURRENT_FRAME_STATIC.lock().unwrap() = pass_offset + frame;
+                _web_current_frame = *WEB_CURRENT_FRAME_STATIC.lock().unwrap();
+                *WEB_TOTAL_FRAME_STATIC.lock().unwrap() = frames * passes as f32;
+                _web_total_frame = *WEB_TOTAL_FRAME_STATIC.lock().unwrap();
+                // ffmpeg reports size=0kB until the muxer flushes, keep the predicted size until then
+                if expected_size > 0 {
+                    *WEB_EXPECTED_SIZE_STATIC.lock().unwrap() = expected_size;
+                }
+                _web_expected_size = *WEB_EXPECTED_SIZE_STATIC.lock().unwrap();
+                *WEB_CURRENT_FILE_STATIC.lock().unwrap() = _web_current_file.clone();
+                *WEB_TOTAL_FILES_STATIC.lock().unwrap() = _web_total_files.clone();
+                *WEB_CURRENT_FILE_NAME_STATIC.lock().unwrap() = _web_current_file_name.clone();
+
+                /*                         // Post progress to web server every 100ms in JSON format, add it to an existing array
+                let progress = Progress {
+                    id: _web_task_id.clone(),
+                    fps: _web_fps,
+                    frame: _web_current_frame,
+                    frames: _web_total_frame,
+                    percentage: 0.0,
+                    eta: "".to_string(),
+                    size: _web_expected_size,
+                    current_file_count: _web_current_file,
+                    total_files: _web_total_files,
+                    current_file_name: _web_current_file_name.to_string(),
+                };
+                let progress_json = serde_json::to_string(&progress).unwrap();
+
+                // Add the progress_json_array to the progress_json_array_string
+                let progress_json_array = format!("[{}]", progress_json);
+
+                // Post the progress_json_array_string to the web server
+                let _ = reqwest::Client::new()
+                    .post(format!("http://localhost:8000/progress/{}", _web_task_id))
+                    .body(progress_json_array.clone())
+                    .send();
+
+                // TEMP Write the progress to a file
+                let _ = fs::write("progress.json", progress_json_array); */
+            }
+        }
+        if finished {
+            break;
+        }
+    }
+
+    cost.add(&sampler.finish());
+    samples.finish(file);
+    // a failed encode leaves a truncated output behind
+    let status = output
+        .wait()
+        .map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+    wait_if_interrupted();
+    set_metric(&TASK_FPS, &labels, 0.0);
+    if two_pass {
+        remove_two_pass_logs(&pass_log);
+    }
+    if !status.success() {
+        error!(
+            "{}",
+            format!("ffmpeg failed on {} ({})", file, status).red()
+        );
+        part.discard();
+        push_stderr_tail(&mut stderr_tail, &format!("\nffmpeg {}", status));
+        record_task_log(task_id, &format!("ffmpeg {}", file), &stderr_tail);
+        return Err(stderr_tail.trim().to_string());
+    }
+    record_task_log(task_id, &format!("ffmpeg {}", file), &stderr_tail);
+    part.commit()?;
+
+    // a trial stays out of the statistics
+    if trial.is_some() {
+        record_trial_output(file, output_folder, target_crf, *final_vmaf);
+        return Ok(true);
+    }
+    log_size_reduction(file, output_folder, &labels);
+    // a bitrate encode was not searched for a VMAF
+    let vmaf = if target_bitrate.is_some() {
+        0.0
+    } else {
+        *final_vmaf as f64
+    };
+    record_encode_stats(file, output_folder, encoder, preset_x265, vmaf, Some(&cost));
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests;
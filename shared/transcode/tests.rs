@@ -0,0 +1,62 @@
+use super::*;
+
+#[test]
+fn expected_output_size_scales_the_size_so_far_to_all_frames() {
+    assert_eq!(expected_output_size(1_000_000, 100, 1000.0), 10_000_000);
+    // a 2 hour movie at 24 fps, 20 GB expected, past what a u32 or an f32 in MB keeps exact
+    assert_eq!(
+        expected_output_size(2_000_000_000, 17_280, 172_800.0),
+        20_000_000_000
+    );
+    assert_eq!(expected_output_size(0, 100, 1000.0), 0);
+    // before the first frame nothing is expected yet
+    assert_eq!(expected_output_size(4096, 0, 1000.0), 0);
+}
+
+#[test]
+fn the_video_of_a_capture_is_mapped_by_its_index() {
+    let capture = probed_file(
+        "map-capture.ts",
+        serde_json::json!({
+            "streams": [
+                {"index": 0, "codec_type": "video", "width": 0, "height": 0},
+                {"index": 1, "codec_type": "data"},
+                {"index": 2, "codec_type": "video", "width": 1920, "height": 1080},
+            ],
+        }),
+    );
+    assert_eq!(
+        video_map_args(&capture, None, None, false, None, None),
+        ["-map", "0:2"]
+    );
+    assert_eq!(
+        video_map_args(&capture, None, Some("scale=-2:720"), true, None, None),
+        ["-map", "0:2", "-filter:v:0", "yadif,scale=-2:720"]
+    );
+}
+
+#[test]
+fn the_sar_is_set_after_the_deinterlace_and_before_the_scale() {
+    let dvd = probed_file(
+        "map-dvd.mkv",
+        serde_json::json!({
+            "streams": [{"index": 0, "codec_type": "video", "width": 720, "height": 480}],
+        }),
+    );
+    assert_eq!(
+        video_map_args(
+            &dvd,
+            None,
+            Some("scale=-2:360"),
+            true,
+            Some("setsar=sar=32/27"),
+            None
+        ),
+        [
+            "-map",
+            "0:0",
+            "-filter:v:0",
+            "yadif,setsar=sar=32/27,scale=-2:360"
+        ]
+    );
+}
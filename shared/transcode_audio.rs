@@ -0,0 +1,239 @@
+// The audio remux of the default and audio-only modes, the video is copied
+
+use crate::*;
+
+// The error carries the end of ffmpeg's stderr when ffmpeg failed
+pub fn run_ffmpeg_transcode_audio(
+    file: &str,
+    output_folder: &str,
+    file_bar: &ProgressBar,
+    transcode_bar: &ProgressBar,
+    total_bar: &ProgressBar,
+    info_bar: &ProgressBar,
+    codec_bar: &ProgressBar,
+    total_files: &i32,
+    current_file_count: &u64,
+    vector_files_to_process_frame_count: &Vec<(String, u64)>,
+    original_audio_codec: &str,
+    transcode_info: &str,
+    audio_plans: &[AudioPlan],
+    streams: &StreamSelection,
+    vec_video_args: &Vec<(usize, String, String, String)>,
+    task_id: &str,
+) -> Result<(), String> {
+    let _final_audio_codec: String;
+    let _final_video_codec: String;
+    let mut _web_task_id = task_id.to_string();
+    let mut _web_fps: u64 = 0;
+    let mut _web_current_frame: u64 = 0;
+    let mut _web_total_frame: f32 = 0.0;
+    let mut _web_expected_size: u64 = 0;
+    let mut _web_current_file: u64 = current_file_count.clone() as u64;
+    let mut _web_total_files: u64 = total_files.clone() as u64;
+    let mut _web_current_file_name = file;
+
+    // map the video stream, see select_video_stream, and the kept audio and subtitle streams
+    let mut spec = EncodeSpec::copy();
+    spec.maps = vec!["-map".to_string(), probe_file(file).video_map()];
+    let (stream_args, final_audio_codec) =
+        stream_output_args(streams, audio_plans, original_audio_codec);
+
+    // Add video codec to final_video_codec string
+    let mut final_video_codec = String::new(); // Initialize with an empty string
+    if !vec_video_args.is_empty() {
+        for (_i, codec, _width, _height) in vec_video_args {
+            if !codec.is_empty() {
+                final_video_codec.push_str(&format!("{},", codec));
+            }
+        }
+        final_video_codec.pop(); // Remove the trailing comma
+    } else {
+        final_video_codec = "copy".to_string();
+    }
+
+    // total_size= of the progress blocks is the output size in bytes so far
+    let part = PartFile::new(output_folder, file);
+    let mut cmd = ffmpeg_command();
+    cmd.args(
+        FfmpegArgs::new(file, spec, part.path())
+            .input_args(["-y"])
+            .extra_inputs(streams.external_inputs())
+            .output_args(stream_args)
+            .output_args(["-progress", "pipe:2"])
+            .output_args(part.format_args())
+            .build(),
+    );
+
+    debug!("{:?}", cmd);
+    shield_from_ctrlc(&mut cmd);
+
+    // Execute ffmpeg command
+    let mut output = cmd
+        .stdout(Stdio::piped()) // Redirect standard output
+        .stderr(Stdio::piped()) // Redirect standard error
+        .spawn()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    let _tracked = track_child(&output, &[part.path()]);
+    let mut stderr_tail = String::new();
+
+    let frames = frame_count(&file);
+
+    // set transcode_progress length to the file's number of frames'
+    transcode_bar.set_length(frames as u64);
+
+    let input_file_size = get_file_size(file).unwrap_or(0);
+    set_progress_codecs(vec_video_args, None, audio_plans, input_file_size, None);
+
+    // Set file_bar message to the current file count / total file count + file name
+    let file_name = Path::new(&file)
+        .file_name()
+        .unwrap_or(std::ffi::OsStr::new("Unknown"))
+        .to_str()
+        .unwrap_or("Invalid UTF-8");
+    file_bar.set_message(format!("[{}]", file_name));
+
+    // the video is copied, the metrics count the file under the encoder copy
+    let labels = task_labels(task_id, "copy");
+    let mut last_frame: u64 = 0;
+    let mut output_size: u64 = 0;
+
+    loop {
+        let mut buffer = [0; 1024]; // Adjust buffer size as needed
+        match output.stderr.as_mut().unwrap().read(&mut buffer) {
+            Ok(0) => {
+                break;
+            }
+            Ok(n) => {
+                push_stderr_tail(&mut stderr_tail, &String::from_utf8_lossy(&buffer[..n]));
+                let output_str = String::from_utf8_lossy(&buffer[..n]).trim().to_string();
+                if let Some(total_size) = parse_total_size(&output_str) {
+                    output_size = total_size;
+                }
+                if let Some(frame) = parse_frame_from_output(&output_str) {
+                    let frame = frame as u64; // convert frame to u64
+                    transcode_bar.set_position(frame);
+                    inc_metric(
+                        &FRAMES_PROCESSED,
+                        &labels,
+                        frame.saturating_sub(last_frame) as f64,
+                    );
+                    last_frame = last_frame.max(frame);
+                    let mut current_frame_count = 0;
+                    for i in 0..*current_file_count {
+                        current_frame_count += vector_files_to_process_frame_count[i as usize].1;
+                    }
+                    // Set the total_bar position to the current frame count
+                    total_bar.set_position(current_frame_count + frame);
+                    total_bar.set_message(format!("{}/{}", current_file_count, total_files));
+                    // set info_bar message to the current file count / total file count, current FPS from the output, and the file name
+                    if let Some(fps) = parse_fps_from_output(&output_str) {
+                        // The expected size of the output is the size so far scaled to all frames
+                        let expected_size = if frame > 0 {
+                            (output_size as f64 / frame as f64 * frames as f64) as u64
+                        } else {
+                            0
+                        };
+                        let expected_size_percent =
+                            100.0 - reduction_percent(input_file_size, expected_size);
+
+                        // Show the speed in the info_bar
+                        let speed = output_str
+                            .find("speed=")
+                            .map(|index| &output_str[index + 6..])
+                            .and_then(|speed_str| {
+                                speed_str.find('x').map(|index| &speed_str[..index])
+                            })
+                            .unwrap_or("");
+
+                        info_bar.set_message(format!(
+                            "{}][{}/{}][{} FPS][{}][{:.2}%][{}x",
+                            transcode_info,
+                            current_file_count,
+                            total_files,
+                            fps,
+                            format_size(expected_size),
+                            expected_size_percent,
+                            speed
+                        ));
+
+                        codec_bar
+                            .set_message(format!("{}][{}", final_video_codec, final_audio_codec));
+
+                        *WEB_TASK_ID_STATIC.lock().unwrap() = _web_task_id.clone();
+                        *WEB_FPS_STATIC.lock().unwrap() = fps.clone();
+                        set_metric(&TASK_FPS, &labels, fps as f64);
+                        _web_fps = *WEB_FPS_STATIC.lock().unwrap();
+                        *WEB_CURRENT_FRAME_STATIC.lock().unwrap() = frame.clone();
+                        _web_current_frame = *WEB_CURRENT_FRAME_STATIC.lock().unwrap();
+                        *WEB_TOTAL_FRAME_STATIC.lock().unwrap() = frames.clone();
+                        _web_total_frame = *WEB_TOTAL_FRAME_STATIC.lock().unwrap();
+                        // ffmpeg reports size=0kB until the muxer flushes, keep the predicted size until then
+                        if expected_size > 0 {
+                            *WEB_EXPECTED_SIZE_STATIC.lock().unwrap() = expected_size;
+                        }
+                        _web_expected_size = *WEB_EXPECTED_SIZE_STATIC.lock().unwrap();
+                        *WEB_CURRENT_FILE_STATIC.lock().unwrap() = _web_current_file.clone();
+                        *WEB_TOTAL_FILES_STATIC.lock().unwrap() = _web_total_files.clone();
+                        *WEB_CURRENT_FILE_NAME_STATIC.lock().unwrap() =
+                            _web_current_file_name.to_string();
+
+                        /*                         // Post progress to web server every 100ms in JSON format, add it to an existing array
+                        let progress = Progress {
+                            id: _web_task_id.clone(),
+                            fps: _web_fps,
+                            frame: _web_current_frame,
+                            frames: _web_total_frame,
+                            percentage: 0.0,
+                            eta: "".to_string(),
+                            size: _web_expected_size,
+                            current_file_count: _web_current_file,
+                            total_files: _web_total_files,
+                            current_file_name: _web_current_file_name.to_string(),
+                        };
+                        let progress_json = serde_json::to_string(&progress).unwrap();
+
+                        // Add the progress_json_array to the progress_json_array_string
+                        let progress_json_array = format!("[{}]", progress_json);
+
+                        // Post the progress_json_array_string to the web server
+                        let _ = reqwest::Client::new()
+                            .post(format!("http://localhost:8000/progress/{}", _web_task_id))
+                            .body(progress_json_array.clone())
+                            .send();
+
+                        // TEMP Write the progress to a file
+                        let _ = fs::write("progress.json", progress_json_array); */
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Error reading stdout: {}", e);
+                break;
+            }
+        }
+        thread::sleep(std::time::Duration::from_millis(100));
+    }
+
+    // a failed encode leaves a truncated output behind
+    let status = output
+        .wait()
+        .map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+    wait_if_interrupted();
+    set_metric(&TASK_FPS, &labels, 0.0);
+    if !status.success() {
+        error!(
+            "{}",
+            format!("ffmpeg failed on {} ({})", file, status).red()
+        );
+        part.discard();
+        push_stderr_tail(&mut stderr_tail, &format!("\nffmpeg {}", status));
+        record_task_log(task_id, &format!("ffmpeg {}", file), &stderr_tail);
+        return Err(stderr_tail.trim().to_string());
+    }
+    record_task_log(task_id, &format!("ffmpeg {}", file), &stderr_tail);
+    part.commit()?;
+
+    log_size_reduction(file, output_folder, &labels);
+    record_encode_stats(file, output_folder, "copy", "", 0.0, None);
+    Ok(())
+}
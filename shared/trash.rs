@@ -0,0 +1,205 @@
+// The trash of the library: a row a folder scan removes, because its file is gone or now matches an
+// exclude pattern, is moved to video_info_trash with the time and the reason instead of being
+// deleted, so a share that was unmounted for a moment doesn't take the encode history with it.
+// `db restore-trash` puts the rows back, the ones older than TRASH_DAYS are purged when the database
+// is opened. A scan that would trash more than --max-trash-fraction of the rows of its folder lists
+// them and trashes nothing without --force
+
+use crate::*;
+
+// Days a trashed row is kept
+pub const TRASH_DAYS: u64 = 30;
+
+// How long before the files that seem gone are looked at again, for a mount that was reconnecting
+pub(crate) const RECHECK_DELAY: Duration = Duration::from_secs(5);
+
+// The columns of video_info the trash keeps, all but the id
+fn trash_columns(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info('video_info')")?;
+    let rows = stmt.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let columns: Vec<(String, String)> = rows.collect::<Result<_>>()?;
+    Ok(columns
+        .into_iter()
+        .filter(|(name, _)| name != "id")
+        .collect())
+}
+
+// Give video_info_trash the columns video_info got since, and purge the rows trashed more than
+// TRASH_DAYS ago
+pub(crate) fn maintain_trash(conn: &Connection) -> Result<()> {
+    for (name, kind) in trash_columns(conn)? {
+        let exists = conn
+            .prepare("SELECT 1 FROM pragma_table_info('video_info_trash') WHERE name=?1")?
+            .exists(params![name])?;
+        if !exists {
+            // without the NOT NULL of video_info, the rows trashed before have no value
+            conn.execute(
+                &format!("ALTER TABLE video_info_trash ADD COLUMN {} {}", name, kind),
+                params![],
+            )?;
+        }
+    }
+    let before = SystemTime::now()
+        .checked_sub(Duration::from_secs(TRASH_DAYS * 24 * 3600))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let purged = conn.execute(
+        "DELETE FROM video_info_trash WHERE deleted_at < ?1",
+        params![humantime::format_rfc3339_seconds(before).to_string()],
+    )?;
+    if purged > 0 {
+        info!(
+            "Purged {} rows trashed more than {} days ago",
+            purged, TRASH_DAYS
+        );
+    }
+    Ok(())
+}
+
+impl Db {
+    // Move the rows of filepath to the trash, reason is not_found or excluded
+    pub fn trash_by_filepath(&self, filepath: &str, reason: &str) -> Result<()> {
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let mut conn = self.conn.lock().unwrap();
+        let columns = trash_columns(&conn)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let transaction = conn.transaction()?;
+        transaction.execute(
+            &format!(
+                "INSERT INTO video_info_trash (deleted_at, reason, {0}) SELECT ?1, ?2, {0} FROM video_info WHERE filepath=?3",
+                columns
+            ),
+            params![now, reason, filepath],
+        )?;
+        transaction.execute(
+            "DELETE FROM video_info WHERE filepath=?1",
+            params![filepath],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    // Put the rows trashed since since, an RFC 3339 UTC time, back into video_info. A restored row
+    // replaces the row a later scan added for its path, and of a path trashed more than once the last
+    // row is restored. Returns how many paths were restored
+    pub fn restore_from_trash(&self, since: &str) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let columns = trash_columns(&conn)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let transaction = conn.transaction()?;
+        let trashed: Vec<(i64, String)> = {
+            let mut stmt = transaction.prepare(
+                "SELECT trash_id, filepath FROM video_info_trash WHERE deleted_at >= ?1 ORDER BY trash_id",
+            )?;
+            let rows = stmt.query_map(params![since], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+        let mut restored = std::collections::HashSet::new();
+        for (trash_id, filepath) in trashed {
+            transaction.execute(
+                "DELETE FROM video_info WHERE filepath=?1",
+                params![filepath],
+            )?;
+            transaction.execute(
+                &format!(
+                    "INSERT INTO video_info ({0}) SELECT {0} FROM video_info_trash WHERE trash_id=?1",
+                    columns
+                ),
+                params![trash_id],
+            )?;
+            transaction.execute(
+                "DELETE FROM video_info_trash WHERE trash_id=?1",
+                params![trash_id],
+            )?;
+            restored.insert(filepath);
+        }
+        transaction.commit()?;
+        Ok(restored.len())
+    }
+}
+
+// Put the rows trashed since since ("2h" or a date, see parse_since), or all of them, back into the
+// library, returns how many files were restored
+pub fn restore_trash(since: Option<&str>) -> Result<usize, String> {
+    let since = match since {
+        Some(since) => humantime::format_rfc3339_seconds(parse_since(since)?).to_string(),
+        // every deleted_at is after the empty string
+        None => String::new(),
+    };
+    db().restore_from_trash(&since).map_err(|e| e.to_string())
+}
+
+// The files of paths that are still gone after delay, RECHECK_DELAY for the scans, a share that was
+// reconnecting has them back by then
+pub(crate) fn missing_files(paths: &[String], delay: Duration) -> Vec<String> {
+    let missing: Vec<String> = paths
+        .iter()
+        .filter(|path| metadata(path).is_err())
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return missing;
+    }
+    info!(
+        "{} files of the database are not found, looking again in {:?}",
+        missing.len(),
+        delay
+    );
+    thread::sleep(delay);
+    missing
+        .into_iter()
+        .filter(|path| metadata(path).is_err())
+        .collect()
+}
+
+// Move the rows of removals, (path, reason) pairs of a scan of a folder with rows rows, to the
+// trash of db, returns how many were moved. More than --max-trash-fraction of the rows are only
+// listed without --force, a share that is gone looks like every file of it was deleted
+pub(crate) fn trash_removals(
+    db: &Db,
+    folder: &str,
+    removals: &[(String, &str)],
+    rows: usize,
+    args: &Args,
+) -> Result<usize> {
+    if removals.is_empty() {
+        return Ok(0);
+    }
+    if removals.len() as f64 > rows as f64 * args.max_trash_fraction && !args.force {
+        warn!(
+            "{}",
+            format!(
+                "Not removing {} of the {} files of {} from the database, more than --max-trash-fraction {}, --force removes them:",
+                removals.len(),
+                rows,
+                folder,
+                args.max_trash_fraction
+            )
+            .red()
+        );
+        for (filepath, reason) in removals {
+            warn!("  {} ({})", filepath, reason);
+        }
+        return Ok(0);
+    }
+    for (filepath, reason) in removals {
+        db.trash_by_filepath(filepath, reason)?;
+        warn!(
+            "{}",
+            format!("REMOVING: {} ({})", filepath, reason).yellow()
+        );
+    }
+    info!(
+        "Moved {} files to the trash of the database, `db restore-trash` puts them back",
+        removals.len()
+    );
+    Ok(removals.len())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,154 @@
+use super::*;
+
+// A folder with files, each with its row in db
+fn scanned_folder(db: &Db, files: usize) -> (PathBuf, Vec<String>) {
+    let folder = temp_path("folder");
+    fs::create_dir_all(&folder).unwrap();
+    let paths: Vec<String> = (0..files)
+        .map(|index| {
+            let path = folder.join(format!("episode {}.mkv", index));
+            fs::write(&path, b"video").unwrap();
+            path.to_string_lossy().to_string()
+        })
+        .collect();
+    for path in &paths {
+        db.insert_video_info(&video_info(path)).unwrap();
+    }
+    (folder, paths)
+}
+
+fn trashed(db: &Db) -> Vec<(String, String)> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT filepath, reason FROM video_info_trash ORDER BY filepath")
+        .unwrap();
+    let rows = stmt
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    rows.collect::<Result<_>>().unwrap()
+}
+
+#[test]
+fn a_missing_folder_is_not_trashed_above_the_fraction() {
+    let db = temp_db("trash-guard");
+    let (folder, paths) = scanned_folder(&db, 5);
+    // the share is not mounted at the first check nor at the second
+    fs::remove_dir_all(&folder).unwrap();
+    let args = test_args(&["--max-trash-fraction", "0.2"]);
+
+    assert_eq!(
+        trash_folder_removals(&db, folder.to_str().unwrap(), &args, Duration::ZERO).unwrap(),
+        0
+    );
+
+    assert!(trashed(&db).is_empty());
+    for path in &paths {
+        assert!(db.video_info_exists(path).unwrap());
+    }
+}
+
+#[test]
+fn force_trashes_and_restore_brings_the_rows_back() {
+    let db = temp_db("trash-restore");
+    let (folder, paths) = scanned_folder(&db, 5);
+    fs::remove_dir_all(&folder).unwrap();
+    let args = test_args(&["--max-trash-fraction", "0.2", "--force"]);
+
+    assert_eq!(
+        trash_folder_removals(&db, folder.to_str().unwrap(), &args, Duration::ZERO).unwrap(),
+        paths.len()
+    );
+
+    let mut expected: Vec<(String, String)> = paths
+        .iter()
+        .map(|path| (path.clone(), "not_found".to_string()))
+        .collect();
+    expected.sort();
+    assert_eq!(trashed(&db), expected);
+    for path in &paths {
+        assert!(!db.video_info_exists(path).unwrap());
+    }
+
+    assert_eq!(db.restore_from_trash("").unwrap(), 5);
+    assert!(trashed(&db).is_empty());
+    for path in &paths {
+        assert!(db.video_info_exists(path).unwrap());
+    }
+}
+
+#[test]
+fn rows_below_the_fraction_are_trashed() {
+    let db = temp_db("trash-below");
+    let (folder, paths) = scanned_folder(&db, 5);
+    fs::remove_file(&paths[0]).unwrap();
+    let args = test_args(&["--max-trash-fraction", "0.2"]);
+
+    assert_eq!(
+        trash_folder_removals(&db, folder.to_str().unwrap(), &args, Duration::ZERO).unwrap(),
+        1
+    );
+
+    assert_eq!(
+        trashed(&db),
+        vec![(paths[0].clone(), "not_found".to_string())]
+    );
+    assert!(db.video_info_exists(&paths[1]).unwrap());
+}
+
+#[test]
+fn a_folder_back_before_the_recheck_is_kept() {
+    let db = temp_db("trash-recheck");
+    let (folder, paths) = scanned_folder(&db, 2);
+    let moved = temp_path("unmounted");
+    fs::rename(&folder, &moved).unwrap();
+    // the share reconnects while the scan waits for the recheck
+    let remount = {
+        let (folder, moved) = (folder.clone(), moved.clone());
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::rename(moved, folder).unwrap();
+        })
+    };
+    let args = test_args(&["--force"]);
+
+    assert_eq!(
+        trash_folder_removals(&db, folder.to_str().unwrap(), &args, Duration::from_secs(2))
+            .unwrap(),
+        0
+    );
+    remount.join().unwrap();
+
+    assert!(trashed(&db).is_empty());
+    for path in &paths {
+        assert!(db.video_info_exists(path).unwrap());
+    }
+}
+
+#[test]
+fn rows_that_match_an_exclude_pattern_now_are_trashed() {
+    let db = temp_db("trash-excluded");
+    // 2 of the 10 rows, within --max-trash-fraction
+    let (folder, paths) = scanned_folder(&db, 9);
+    let sample = folder.join("sample").join("episode sample.mkv");
+    fs::create_dir_all(sample.parent().unwrap()).unwrap();
+    fs::write(&sample, b"video").unwrap();
+    let sample = sample.to_string_lossy().to_string();
+    db.insert_video_info(&video_info(&sample)).unwrap();
+    let args = test_args(&["--exclude", "**/sample/**", "--exclude", "episode 0.mkv"]);
+
+    assert_eq!(
+        trash_folder_removals(&db, folder.to_str().unwrap(), &args, Duration::ZERO).unwrap(),
+        2
+    );
+
+    let mut expected = vec![
+        (paths[0].clone(), "excluded".to_string()),
+        (sample.clone(), "excluded".to_string()),
+    ];
+    expected.sort();
+    assert_eq!(trashed(&db), expected);
+    assert!(!db.video_info_exists(&sample).unwrap());
+    for path in &paths[1..] {
+        assert!(db.video_info_exists(path).unwrap());
+    }
+}
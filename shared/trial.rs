@@ -0,0 +1,228 @@
+// --trial-range: encode only a part of each file to try settings before the whole encode. The CRF
+// search samples the range, the encode seeks to it and chunked mode keeps the scenes inside it. The
+// output is named after the range, its file gets the status trial, which a later run still encodes,
+// and it is left out of the encode statistics
+
+use crate::*;
+
+// Ranges shorter than this don't leave the CRF search anything to measure
+const MIN_TRIAL_SECONDS: f32 = 5.0;
+
+// The part of a file a trial encodes, in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrialRange {
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TrialRange {
+    pub(crate) fn duration(&self) -> f32 {
+        self.end - self.start
+    }
+
+    // -ss and -to of the range, before the -i of the source
+    pub(crate) fn seek_args(&self) -> (String, String) {
+        (format_timecode(&self.start), format_timecode(&self.end))
+    }
+
+    // The frames of the range at fps, rounded like the scenes of chunked mode
+    pub(crate) fn frames(&self, fps: f32) -> u64 {
+        let start = (self.start * fps).round() as u64;
+        let end = (self.end * fps).round() as u64;
+        end.saturating_sub(start)
+    }
+
+    // movie.libx265.vmaf95.crf23.mkv becomes movie.libx265.vmaf95.crf23.trial_1200_1500.mkv
+    pub(crate) fn output_name(&self, output: &str) -> String {
+        let suffix = format!(".trial_{}_{}", self.start, self.end);
+        let path = Path::new(output);
+        match path.extension() {
+            Some(extension) => path
+                .with_extension(format!(
+                    "{}.{}",
+                    suffix.trim_start_matches('.'),
+                    extension.to_string_lossy()
+                ))
+                .to_string_lossy()
+                .to_string(),
+            None => format!("{}{}", output, suffix),
+        }
+    }
+
+    // The scene changes of chunked mode inside the range, starting and ending on it
+    pub(crate) fn clip_scene_changes(&self, scene_changes: &[f32]) -> Vec<f32> {
+        let mut clipped = vec![self.start];
+        clipped.extend(
+            scene_changes
+                .iter()
+                .filter(|change| **change > self.start && **change < self.end),
+        );
+        clipped.push(self.end);
+        clipped
+    }
+}
+
+impl std::fmt::Display for TrialRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (start, end) = self.seek_args();
+        write!(f, "{}-{} ({:.1}s)", start, end, self.duration())
+    }
+}
+
+// <start>-<end> of --trial-range
+pub(crate) fn parse_trial_range(value: &str) -> Result<TrialRange, String> {
+    let (start, end) = value
+        .split_once('-')
+        .ok_or_else(|| "the range must look like <start>-<end>, like 20:00-25:00".to_string())?;
+    let start =
+        parse_timecode(start).ok_or_else(|| format!("{:?} is not a timecode or seconds", start))?;
+    let end =
+        parse_timecode(end).ok_or_else(|| format!("{:?} is not a timecode or seconds", end))?;
+    let range = TrialRange {
+        start: start as f32,
+        end: end as f32,
+    };
+    if range.duration() < MIN_TRIAL_SECONDS {
+        return Err(format!(
+            "the range must be at least {} seconds long",
+            MIN_TRIAL_SECONDS
+        ));
+    }
+    Ok(range)
+}
+
+lazy_static! {
+    // The trial range of the files being encoded, by source
+    static ref TRIAL_RANGES: Mutex<HashMap<String, TrialRange>> = Mutex::new(HashMap::new());
+    // The trial encodes since the pipeline last took them
+    static ref TRIAL_OUTPUTS: Mutex<Vec<TrialOutput>> = Mutex::new(Vec::new());
+}
+
+// The trial range of file while it is encoded, None when it is encoded as a whole
+pub(crate) fn trial_range(file: &str) -> Option<TrialRange> {
+    TRIAL_RANGES.lock().unwrap().get(file).copied()
+}
+
+// The trial range of a file, until it is dropped the encodes of the file use it
+pub(crate) struct ActiveTrial {
+    file: String,
+    pub(crate) range: TrialRange,
+}
+
+impl Drop for ActiveTrial {
+    fn drop(&mut self) {
+        TRIAL_RANGES.lock().unwrap().remove(&self.file);
+    }
+}
+
+// --trial-range for file, an error when the range does not fit in it
+pub(crate) fn start_trial(file: &str, args: &Args) -> Result<Option<ActiveTrial>, String> {
+    let range = match &args.trial_range {
+        Some(value) => parse_trial_range(value)?,
+        None => return Ok(None),
+    };
+    let duration =
+        get_duration(file).ok_or_else(|| format!("Failed to get the duration of {}", file))?;
+    if range.end > duration {
+        return Err(format!(
+            "--trial-range {} ends after the end of {} at {}",
+            range,
+            file,
+            format_timecode(&duration)
+        ));
+    }
+    TRIAL_RANGES.lock().unwrap().insert(file.to_string(), range);
+    Ok(Some(ActiveTrial {
+        file: file.to_string(),
+        range,
+    }))
+}
+
+// The frames of file a run encodes, those of the range with --trial-range
+pub(crate) fn trial_frame_count(file: &str, frames: u64, args: &Args) -> u64 {
+    let range = match &args.trial_range {
+        Some(value) if args.mode != "audio-only" => parse_trial_range(value).ok(),
+        _ => None,
+    };
+    match range {
+        Some(range) => range.frames(get_fps_f32(file)).min(frames),
+        None => frames,
+    }
+}
+
+// Copy the video of the range to a clip in the working directory for the ab-av1 CRF search, which
+// can only search a whole file. The copy starts on the keyframe before the range
+pub(crate) fn cut_trial_clip(file: &str, range: &TrialRange) -> Result<String, String> {
+    let (_, extension) = output_name_parts(file);
+    let clip = format!("trial_clip_{}.{}", chunk_run_id(file), extension);
+    let (ss, to) = range.seek_args();
+    let output = tracked_output(
+        ffmpeg_command()
+            .args(["-hide_banner", "-y", "-ss", &ss, "-to", &to, "-i"])
+            .arg(staged_input(file))
+            .arg("-map")
+            .arg(probe_file(file).video_map())
+            .args(["-c", "copy", &clip]),
+        &[&clip],
+    )
+    .map_err(|e| format!("Failed to cut the trial clip of {}: {}", file, e))?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&clip);
+        return Err(format!(
+            "Failed to cut the trial clip of {}: {}",
+            file,
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .last()
+                .unwrap_or_default()
+        ));
+    }
+    Ok(clip)
+}
+
+// A trial encode of the run, for the summary and --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct TrialOutput {
+    pub path: String,
+    pub output: String,
+    pub start: f32,
+    pub end: f32,
+    // empty for chunked mode, its scenes have CRFs of their own
+    pub crf: String,
+    pub vmaf: f32,
+    pub output_size: u64,
+}
+
+// A trial encode is done: its file gets the status trial and the encode waits for the summary
+// instead of going to the statistics
+pub(crate) fn record_trial_output(file: &str, output: &str, crf: &str, vmaf: f32) {
+    let range = match trial_range(file) {
+        Some(range) => range,
+        None => return,
+    };
+    let output_size = get_file_size(output).unwrap_or(0);
+    info!(
+        "Trial of {} from {}: {} ({})",
+        file,
+        range,
+        output,
+        format_size(output_size)
+    );
+    if let Err(e) = set_db_status(file, "trial") {
+        error!("Failed to update status of {}: {}", file, e);
+    }
+    TRIAL_OUTPUTS.lock().unwrap().push(TrialOutput {
+        path: file.to_string(),
+        output: output.to_string(),
+        start: range.start,
+        end: range.end,
+        crf: crf.to_string(),
+        vmaf,
+        output_size,
+    });
+}
+
+// The trial encodes since the last call
+pub(crate) fn take_trial_outputs() -> Vec<TrialOutput> {
+    std::mem::take(&mut *TRIAL_OUTPUTS.lock().unwrap())
+}
@@ -0,0 +1,270 @@
+// --content-tune: encoder tuning by content, -tune grain for grainy film, -tune animation for flat
+// animation. auto classifies the source by its probed data, the tune's arguments are added to the
+// params of the encoder, so the native and chunked searches encode with them too, and passed to
+// ab-av1 with --enc and --svt
+
+use crate::*;
+
+// Bits per pixel and frame of h264 above which a source is taken as grainy, grain costs bits
+const GRAIN_BPP: f32 = 0.35;
+// and below which as flat animation, large flat areas compress very well
+const ANIMATION_BPP: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentTune {
+    Film,
+    Animation,
+    Grain,
+}
+
+impl ContentTune {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentTune::Film => "film",
+            ContentTune::Animation => "animation",
+            ContentTune::Grain => "grain",
+        }
+    }
+}
+
+// The arguments of a tune for one encoder, both empty when the encoder has no such tune
+#[derive(Debug, Default, PartialEq)]
+pub struct TuneArgs {
+    // (option, value) added to the params, a value of an option like -x265-params is appended to it
+    pub ffmpeg: Vec<(&'static str, &'static str)>,
+    // the same for ab-av1 crf-search
+    pub ab_av1: Vec<(&'static str, &'static str)>,
+}
+
+impl TuneArgs {
+    pub fn is_empty(&self) -> bool {
+        self.ffmpeg.is_empty()
+    }
+}
+
+// film is what the encoders are tuned for by default
+pub fn tune_args(encoder: &str, tune: ContentTune) -> TuneArgs {
+    let (ffmpeg, ab_av1) = match (encoder, tune) {
+        ("libx265", ContentTune::Grain) => {
+            (vec![("-tune", "grain")], vec![("--enc", "tune=grain")])
+        }
+        ("libx265", ContentTune::Animation) => (
+            vec![("-tune", "animation")],
+            vec![("--enc", "tune=animation")],
+        ),
+        // film grain synthesis instead of spending bits on the grain
+        ("libsvtav1", ContentTune::Grain) => (
+            vec![("-svtav1-params", "film-grain=8:film-grain-denoise=0")],
+            vec![("--svt", "film-grain=8"), ("--svt", "film-grain-denoise=0")],
+        ),
+        ("av1" | "libaom-av1", ContentTune::Grain) => (
+            vec![("-denoise-noise-level", "8")],
+            vec![("--enc", "denoise-noise-level=8")],
+        ),
+        _ => (vec![], vec![]),
+    };
+    TuneArgs { ffmpeg, ab_av1 }
+}
+
+// The video bitrate of file per pixel and frame, scaled to h264, codecs that compress better need
+// fewer bits for the same content. None when the probe lacks the size, rate or bitrate
+fn source_bpp(file: &str) -> Option<f32> {
+    let info = probe_file(file);
+    let stream = info.video_stream()?;
+    let width = stream["width"].as_f64().unwrap_or(0.0) as f32;
+    let height = stream["height"].as_f64().unwrap_or(0.0) as f32;
+    let fps = info.avg_fps().max(info.fps());
+    let bitrate = info.bitrate_kbps() * 1000.0;
+    if width <= 0.0 || height <= 0.0 || fps <= 0.0 || bitrate <= 0.0 {
+        return None;
+    }
+    let efficiency = match stream["codec_name"].as_str().unwrap_or("") {
+        "hevc" | "vp9" => 0.6,
+        "av1" => 0.5,
+        "mpeg2video" | "mpeg4" => 1.5,
+        _ => 1.0,
+    };
+    let bpp = bitrate / (width * height * fps) / efficiency;
+    debug!("{}: {:.3} bits per pixel (h264)", file, bpp);
+    Some(bpp)
+}
+
+// Classify file by its bits per pixel
+pub fn detect_content_tune(file: &str) -> ContentTune {
+    match source_bpp(file) {
+        Some(bpp) if bpp >= GRAIN_BPP => ContentTune::Grain,
+        Some(bpp) if bpp <= ANIMATION_BPP => ContentTune::Animation,
+        _ => ContentTune::Film,
+    }
+}
+
+// The tune of --content-tune for file, None for none
+pub fn resolve_content_tune(file: &str, content_tune: &str) -> Option<ContentTune> {
+    match content_tune {
+        "auto" => Some(detect_content_tune(file)),
+        "film" => Some(ContentTune::Film),
+        "animation" => Some(ContentTune::Animation),
+        "grain" => Some(ContentTune::Grain),
+        _ => None,
+    }
+}
+
+// Add the arguments to a params string, an option that is already there keeps its value, except
+// the -x265-params like ones which get the value appended
+fn tune_params(params: &str, args: &[(&str, &str)]) -> String {
+    let mut words = params_args(params);
+    for (option, value) in args {
+        if option.ends_with("-params") {
+            words = add_codec_params(words, option, value);
+        } else if !words.iter().any(|word| word == option) {
+            words.push(option.to_string());
+            words.push(value.to_string());
+        }
+    }
+    join_params(&words)
+}
+
+// Apply the tune to the params the encodes of args.encoder use, returns its ab-av1 arguments
+// Pipeline::new moved the params of the encoder to params_x265, the chunked and native encodes
+// read the ones of the encoder
+pub fn apply_content_tune(args: &mut Args, tune: ContentTune) -> Vec<String> {
+    // the grain synthesis of --film-grain replaces the one of the grain tune
+    if tune == ContentTune::Grain && args.film_grain.is_some() && supports_film_grain(&args.encoder)
+    {
+        return Vec::new();
+    }
+    let tune_args = tune_args(&args.encoder, tune);
+    args.params_x265 = tune_params(&args.params_x265, &tune_args.ffmpeg);
+    match args.encoder.as_str() {
+        "libsvtav1" => {
+            args.params_libsvtav1 = tune_params(&args.params_libsvtav1, &tune_args.ffmpeg)
+        }
+        "av1" | "libaom-av1" => {
+            args.params_libaom_av1 = tune_params(&args.params_libaom_av1, &tune_args.ffmpeg)
+        }
+        _ => {}
+    }
+    tune_args
+        .ab_av1
+        .iter()
+        .flat_map(|(option, value)| [option.to_string(), value.to_string()])
+        .collect()
+}
+
+// --film-grain auto: no grain below the grain tune's bits per pixel, 4 at it up to 20 at twice it
+const AUTO_FILM_GRAIN_MIN: f32 = 4.0;
+const AUTO_FILM_GRAIN_MAX: f32 = 20.0;
+
+// The encoders with film grain synthesis, Pipeline::new rejects --film-grain for the others
+pub fn supports_film_grain(encoder: &str) -> bool {
+    matches!(encoder, "libsvtav1" | "av1" | "libaom-av1")
+}
+
+// The level of --film-grain for file
+pub fn resolve_film_grain(file: &str, film_grain: &str) -> u8 {
+    if film_grain != "auto" {
+        // validated by clap
+        return film_grain.parse().unwrap_or(0);
+    }
+    match source_bpp(file) {
+        Some(bpp) if bpp >= GRAIN_BPP => {
+            let level = AUTO_FILM_GRAIN_MIN
+                + (AUTO_FILM_GRAIN_MAX - AUTO_FILM_GRAIN_MIN) * (bpp - GRAIN_BPP) / GRAIN_BPP;
+            level.min(AUTO_FILM_GRAIN_MAX).round() as u8
+        }
+        _ => 0,
+    }
+}
+
+// The grain synthesis arguments of the encoder, the ffmpeg ones as (option, value) and the ab-av1
+// ones, --svt for libsvtav1 and --enc for libaom-av1, so the CRF search encodes its samples alike
+pub fn film_grain_args(
+    encoder: &str,
+    level: u8,
+    denoise: Option<&str>,
+) -> (Vec<(&'static str, String)>, Vec<String>) {
+    match encoder {
+        "libsvtav1" => {
+            let mut svt_params = vec![format!("film-grain={}", level)];
+            if let Some(denoise) = denoise {
+                svt_params.push(format!("film-grain-denoise={}", denoise));
+            }
+            let ab_av1 = svt_params
+                .iter()
+                .flat_map(|param| ["--svt".to_string(), param.clone()])
+                .collect();
+            (vec![("-svtav1-params", svt_params.join(":"))], ab_av1)
+        }
+        "av1" | "libaom-av1" => {
+            let mut ffmpeg = vec![("-denoise-noise-level", level.to_string())];
+            let mut ab_av1 = vec![
+                "--enc".to_string(),
+                format!("denoise-noise-level={}", level),
+            ];
+            if let Some(denoise) = denoise {
+                ffmpeg.push(("-aom-params", format!("enable-dnl-denoising={}", denoise)));
+                ab_av1.extend([
+                    "--enc".to_string(),
+                    format!("aom-params=enable-dnl-denoising={}", denoise),
+                ]);
+            }
+            (ffmpeg, ab_av1)
+        }
+        _ => (Vec::new(), Vec::new()),
+    }
+}
+
+// params without the keys of codec_params in the value of option, like film-grain of
+// "-svtav1-params tune=0:film-grain=4"
+fn without_codec_params(mut params: Vec<String>, option: &str, codec_params: &str) -> Vec<String> {
+    let key = |param: &str| param.split('=').next().unwrap_or("").to_string();
+    let keys: Vec<String> = codec_params.split(':').map(key).collect();
+    if let Some(i) = params.iter().position(|param| param == option) {
+        if let Some(value) = params.get(i + 1) {
+            let kept: Vec<&str> = value
+                .split(':')
+                .filter(|param| !keys.contains(&key(param)))
+                .collect();
+            if kept.is_empty() {
+                params.drain(i..i + 2);
+            } else {
+                params[i + 1] = kept.join(":");
+            }
+        }
+    }
+    params
+}
+
+// Set the grain synthesis of level in the params of args.encoder like apply_content_tune, returns
+// the ab-av1 arguments. A level already in the params is replaced, the search and the encode must
+// not differ
+pub fn apply_film_grain(args: &mut Args, level: u8) -> Vec<String> {
+    let (ffmpeg, ab_av1) =
+        film_grain_args(&args.encoder, level, args.film_grain_denoise.as_deref());
+    let set = |params: &str| {
+        let mut words = params_args(params);
+        for (option, value) in &ffmpeg {
+            if option.ends_with("-params") {
+                words = add_codec_params(without_codec_params(words, option, value), option, value);
+            } else {
+                match words.iter().position(|word| word == option) {
+                    Some(i) if i + 1 < words.len() => words[i + 1] = value.clone(),
+                    _ => words.extend([option.to_string(), value.clone()]),
+                }
+            }
+        }
+        join_params(&words)
+    };
+    args.params_x265 = set(&args.params_x265);
+    match args.encoder.as_str() {
+        "libsvtav1" => args.params_libsvtav1 = set(&args.params_libsvtav1),
+        "av1" | "libaom-av1" => args.params_libaom_av1 = set(&args.params_libaom_av1),
+        _ => {}
+    }
+    // the resolved level of auto, for the chunked debug log
+    args.film_grain = Some(level.to_string());
+    ab_av1
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,246 @@
+use super::*;
+
+// The final encode of args with its resolved encoder settings, as strings
+fn encode_args(args: &Args) -> Vec<String> {
+    let settings = resolve_encoder(args, &args.encoder).unwrap();
+    let spec = EncodeSpec::new(
+        &settings.encoder,
+        EncodeQuality::Crf(30.0),
+        &settings.preset,
+        &settings.params,
+        &args.pix_fmt,
+    );
+    FfmpegArgs::new("/media/movie.mkv", spec, "/out/movie.mkv")
+        .build()
+        .into_iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect()
+}
+
+// The value of option in args
+fn value_of<'a>(args: &'a [String], option: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == option)?;
+    args.get(i + 1).map(String::as_str)
+}
+
+// The values of the ab-av1 arguments of flag, like the film-grain=8 of --svt film-grain=8
+fn ab_av1_values<'a>(ab_av1: &'a [String], flag: &str) -> Vec<&'a str> {
+    ab_av1
+        .chunks(2)
+        .filter(|pair| pair[0] == flag)
+        .map(|pair| pair[1].as_str())
+        .collect()
+}
+
+#[test]
+fn film_grain_args_of_each_encoder() {
+    assert_eq!(
+        film_grain_args("libsvtav1", 8, Some("0")),
+        (
+            vec![(
+                "-svtav1-params",
+                "film-grain=8:film-grain-denoise=0".to_string()
+            )],
+            vec![
+                "--svt".to_string(),
+                "film-grain=8".to_string(),
+                "--svt".to_string(),
+                "film-grain-denoise=0".to_string(),
+            ]
+        )
+    );
+    assert_eq!(
+        film_grain_args("libaom-av1", 12, Some("1")),
+        (
+            vec![
+                ("-denoise-noise-level", "12".to_string()),
+                ("-aom-params", "enable-dnl-denoising=1".to_string()),
+            ],
+            vec![
+                "--enc".to_string(),
+                "denoise-noise-level=12".to_string(),
+                "--enc".to_string(),
+                "aom-params=enable-dnl-denoising=1".to_string(),
+            ]
+        )
+    );
+    assert_eq!(
+        film_grain_args("av1", 4, None),
+        film_grain_args("libaom-av1", 4, None)
+    );
+    for encoder in ["libx265", "hevc_nvenc", "hevc_qsv", "av1_qsv"] {
+        assert!(!supports_film_grain(encoder), "{}", encoder);
+        assert_eq!(film_grain_args(encoder, 8, Some("1")), (vec![], vec![]));
+    }
+}
+
+#[test]
+fn the_search_and_the_encode_of_libsvtav1_get_the_same_grain() {
+    let mut args = test_args(&[
+        "-e",
+        "libsvtav1",
+        "--params-libsvtav1=-svtav1-params tune=0",
+        "--film-grain",
+        "8",
+        "--film-grain-denoise",
+        "0",
+    ]);
+    let ab_av1 = apply_film_grain(&mut args, 8);
+    let encode = encode_args(&args);
+
+    let svt_params = value_of(&encode, "-svtav1-params").unwrap();
+    assert_eq!(svt_params, "tune=0:film-grain=8:film-grain-denoise=0");
+    let encode_grain: Vec<&str> = svt_params
+        .split(':')
+        .filter(|param| param.starts_with("film-grain"))
+        .collect();
+    assert_eq!(ab_av1_values(&ab_av1, "--svt"), encode_grain);
+    assert_eq!(args.film_grain.as_deref(), Some("8"));
+}
+
+#[test]
+fn the_search_and_the_encode_of_libaom_get_the_same_grain() {
+    let mut args = test_args(&["-e", "av1", "--film-grain", "12"]);
+    let ab_av1 = apply_film_grain(&mut args, 12);
+    let encode = encode_args(&args);
+
+    assert_eq!(value_of(&encode, "-denoise-noise-level"), Some("12"));
+    assert_eq!(ab_av1_values(&ab_av1, "--enc"), ["denoise-noise-level=12"]);
+    assert_eq!(value_of(&encode, "-aom-params"), None);
+}
+
+#[test]
+fn a_level_already_in_the_params_is_replaced() {
+    let mut args = test_args(&[
+        "-e",
+        "libsvtav1",
+        "--params-libsvtav1=-svtav1-params film-grain=4:tune=0:film-grain-denoise=1",
+        "--film-grain",
+        "10",
+    ]);
+    apply_film_grain(&mut args, 10);
+    assert_eq!(
+        value_of(&encode_args(&args), "-svtav1-params"),
+        Some("tune=0:film-grain-denoise=1:film-grain=10")
+    );
+
+    let mut args = test_args(&[
+        "-e",
+        "av1",
+        "--params-x265=-denoise-noise-level 4",
+        "--film-grain",
+        "10",
+    ]);
+    apply_film_grain(&mut args, 10);
+    let encode = encode_args(&args);
+    assert_eq!(value_of(&encode, "-denoise-noise-level"), Some("10"));
+    assert_eq!(
+        encode
+            .iter()
+            .filter(|arg| *arg == "-denoise-noise-level")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn the_film_grain_replaces_the_grain_tune() {
+    let mut args = test_args(&["-e", "libsvtav1", "--film-grain", "16"]);
+    let mut ab_av1 = apply_content_tune(&mut args, ContentTune::Grain);
+    assert!(ab_av1.is_empty());
+    ab_av1.extend(apply_film_grain(&mut args, 16));
+    assert_eq!(
+        value_of(&encode_args(&args), "-svtav1-params"),
+        Some("film-grain=16")
+    );
+    assert_eq!(ab_av1_values(&ab_av1, "--svt"), ["film-grain=16"]);
+
+    // without --film-grain the tune brings its own grain
+    let mut args = test_args(&["-e", "libsvtav1"]);
+    let ab_av1 = apply_content_tune(&mut args, ContentTune::Grain);
+    assert_eq!(
+        value_of(&encode_args(&args), "-svtav1-params"),
+        Some("film-grain=8:film-grain-denoise=0")
+    );
+    assert_eq!(
+        ab_av1_values(&ab_av1, "--svt"),
+        ["film-grain=8", "film-grain-denoise=0"]
+    );
+}
+
+// A 1080p h264 source of 24 fps at bpp bits per pixel and frame
+fn source_at_bpp(name: &str, bpp: f32) -> String {
+    let bitrate = (bpp * 1920.0 * 1080.0 * 24.0) as u64;
+    probed_file(
+        name,
+        serde_json::json!({
+            "streams": [{
+                "index": 0, "codec_type": "video", "codec_name": "h264",
+                "width": 1920, "height": 1080,
+                "r_frame_rate": "24/1", "avg_frame_rate": "24/1",
+                "bit_rate": bitrate.to_string(),
+            }],
+            "format": {"duration": "60.0"},
+        }),
+    )
+}
+
+#[test]
+fn resolve_film_grain_of_a_level_and_of_auto() {
+    assert_eq!(resolve_film_grain("/media/movie.mkv", "0"), 0);
+    assert_eq!(resolve_film_grain("/media/movie.mkv", "50"), 50);
+
+    let cases = [
+        (0.1, 0),
+        (GRAIN_BPP, 4),
+        (GRAIN_BPP * 1.5, 12),
+        (GRAIN_BPP * 2.0, 20),
+        (GRAIN_BPP * 4.0, 20),
+    ];
+    for (bpp, level) in cases {
+        let source = source_at_bpp(&format!("grain-{}.mkv", bpp), bpp + 0.0001);
+        assert_eq!(resolve_film_grain(&source, "auto"), level, "{}", bpp);
+    }
+}
+
+#[test]
+fn film_grain_options_are_validated() {
+    for level in ["0", "8", "50", "auto"] {
+        assert!(film_grain_input_validation(level).is_ok(), "{}", level);
+    }
+    for level in ["51", "-1", "8.5", "high", ""] {
+        assert!(film_grain_input_validation(level).is_err(), "{}", level);
+    }
+    assert!(film_grain_denoise_input_validation("1").is_ok());
+    assert!(film_grain_denoise_input_validation("2").is_err());
+}
+
+#[test]
+fn film_grain_is_rejected_for_other_encoders() {
+    for argv in [
+        vec!["-e", "libx265", "--film-grain", "8"],
+        vec!["-e", "hevc_nvenc", "--film-grain", "auto"],
+        vec!["-e", "libsvtav1", "--film-grain-denoise", "1"],
+    ] {
+        let e = check_encode_options(&test_args(&argv)).unwrap_err();
+        assert!(e.starts_with("--film-grain"), "{}", e);
+    }
+    for argv in [
+        vec![
+            "-e",
+            "libsvtav1",
+            "--film-grain",
+            "8",
+            "--film-grain-denoise",
+            "1",
+        ],
+        vec!["-e", "av1", "--film-grain", "auto"],
+    ] {
+        assert_eq!(
+            check_encode_options(&test_args(&argv)),
+            Ok(()),
+            "{:?}",
+            argv
+        );
+    }
+}
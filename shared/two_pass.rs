@@ -0,0 +1,152 @@
+// The first pass of the --target-size encodes and its log files
+
+use crate::*;
+
+use std::ffi::OsString;
+
+// Encoders --target-size runs in two passes with ffmpeg's stats files, the hardware encoders and
+// libsvtav1 use their own bitrate modes in one run
+pub(crate) fn is_two_pass_encoder(encoder: &str) -> bool {
+    matches!(encoder, "libx265" | "av1" | "libaom-av1")
+}
+
+// Prefix of the two-pass stats files of file in the working directory, without characters that
+// would need escaping in -x265-params
+pub(crate) fn two_pass_log_prefix(file: &str) -> String {
+    let stem = Path::new(file)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("file");
+    let stem: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("transrustica_2pass_{}", stem)
+}
+
+// The stats files of pass_log: x265 writes a .log and a .cutree, as .temp files while the pass
+// runs, libaom-av1 a -0.log
+pub(crate) fn two_pass_log_files(pass_log: &str) -> Vec<String> {
+    [
+        ".log",
+        ".log.temp",
+        ".log.cutree",
+        ".log.cutree.temp",
+        "-0.log",
+    ]
+    .iter()
+    .map(|suffix| format!("{}{}", pass_log, suffix))
+    .collect()
+}
+
+// The arguments of the first pass of file with spec, only the video is encoded and nothing is written
+// but the stats files
+pub(crate) fn first_pass_args(file: &str, spec: EncodeSpec) -> Vec<OsString> {
+    FfmpegArgs::new(file, spec, "-")
+        .input_args(["-y"])
+        .output_args(["-an", "-sn", "-dn", "-f", "null"])
+        .build()
+}
+
+// Remove the stats files of both passes, x265 also writes a .cutree file
+pub(crate) fn remove_two_pass_logs(pass_log: &str) {
+    for entry in WalkDir::new(".")
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let is_log = entry
+            .file_name()
+            .to_str()
+            .map_or(false, |name| name.starts_with(pass_log));
+        if is_log {
+            if let Err(e) = fs::remove_file(entry.path()) {
+                warn!("Failed to remove {}: {}", entry.path().display(), e);
+            }
+        }
+    }
+}
+
+// Run the first pass of a two-pass encode, its frames fill the first half of transcode_bar
+pub(crate) fn run_ffmpeg_first_pass(
+    mut cmd: Command,
+    file: &str,
+    encoder: &str,
+    transcode_bar: &ProgressBar,
+    frames: f32,
+    task_id: &str,
+    pass_log: &str,
+) -> Result<EncodeCost, String> {
+    debug!("{:?}", cmd);
+    shield_from_ctrlc(&mut cmd);
+    let mut output = cmd
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    let log_files = two_pass_log_files(pass_log);
+    let log_files: Vec<&str> = log_files.iter().map(String::as_str).collect();
+    let _tracked = track_child(&output, &log_files);
+    let sampler = CostSampler::start(&output, encoder);
+
+    let mut stderr_tail = String::new();
+    let mut stderr = output.stderr.take().unwrap();
+    let mut buffer = [0; 1024];
+    let mut throttle = ProgressThrottle::new();
+    let mut pending_frame = None;
+    let mut last_fps = None;
+    loop {
+        let finished = match stderr.read(&mut buffer) {
+            Ok(0) => true,
+            Ok(n) => {
+                let chunk = String::from_utf8_lossy(&buffer[..n]);
+                push_stderr_tail(&mut stderr_tail, &chunk);
+                pending_frame = parse_frame_from_output(&chunk).or(pending_frame);
+                last_fps = parse_fps_from_output(&chunk).or(last_fps);
+                false
+            }
+            Err(e) => {
+                error!("Error reading stdout: {}", e);
+                true
+            }
+        };
+        if pending_frame.is_some() && (finished || throttle.ready()) {
+            let frame = pending_frame.take().unwrap();
+            transcode_bar.set_position(frame);
+            *WEB_CURRENT_FRAME_STATIC.lock().unwrap() = frame;
+            *WEB_TOTAL_FRAME_STATIC.lock().unwrap() = frames * 2.0;
+            if let Some(fps) = last_fps {
+                emit_transcode_progress(file, frame, frames as u64 * 2, fps);
+            }
+        }
+        if finished {
+            break;
+        }
+    }
+
+    let cost = sampler.finish();
+    let status = output
+        .wait()
+        .map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+    wait_if_interrupted();
+    if !status.success() {
+        error!(
+            "{}",
+            format!("First pass of {} failed ({})", file, status).red()
+        );
+        push_stderr_tail(&mut stderr_tail, &format!("\nffmpeg first pass {}", status));
+    }
+    record_task_log(
+        task_id,
+        &format!("ffmpeg first pass {}", file),
+        &stderr_tail,
+    );
+    if status.success() {
+        Ok(cost)
+    } else {
+        Err(stderr_tail.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests;
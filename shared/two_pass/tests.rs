@@ -0,0 +1,76 @@
+use super::*;
+
+#[test]
+fn only_libx265_and_libaom_av1_run_two_passes() {
+    for encoder in ["libx265", "av1", "libaom-av1"] {
+        assert!(is_two_pass_encoder(encoder), "{}", encoder);
+    }
+    for encoder in ["libsvtav1", "hevc_nvenc", "hevc_qsv", "av1_qsv", "copy"] {
+        assert!(!is_two_pass_encoder(encoder), "{}", encoder);
+    }
+}
+
+#[test]
+fn two_pass_log_prefix_has_nothing_to_escape() {
+    assert_eq!(
+        two_pass_log_prefix("/media/Movie (2020)/Movie: Part 1, Director's Cut.mkv"),
+        "transrustica_2pass_Movie__Part_1__Director_s_Cut"
+    );
+    assert_eq!(two_pass_log_prefix(""), "transrustica_2pass_file");
+}
+
+#[test]
+fn two_pass_log_files_of_x265_and_libaom_av1() {
+    assert_eq!(
+        two_pass_log_files("transrustica_2pass_Movie"),
+        [
+            "transrustica_2pass_Movie.log",
+            "transrustica_2pass_Movie.log.temp",
+            "transrustica_2pass_Movie.log.cutree",
+            "transrustica_2pass_Movie.log.cutree.temp",
+            "transrustica_2pass_Movie-0.log",
+        ]
+    );
+}
+
+fn first_pass(encoder: &str) -> Vec<String> {
+    let spec = EncodeSpec::new(
+        encoder,
+        EncodeQuality::Bitrate {
+            kbps: 4000,
+            pass: Some((1, "transrustica_2pass_Movie".to_string())),
+        },
+        "",
+        "",
+        "",
+    );
+    first_pass_args("/media/Movie.mkv", spec)
+        .into_iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect()
+}
+
+#[test]
+fn the_first_pass_only_writes_the_stats() {
+    for encoder in ["libx265", "libaom-av1"] {
+        let args = first_pass(encoder);
+        assert_eq!(args[..3], ["-y", "-i", "/media/Movie.mkv"], "{}", encoder);
+        assert_eq!(
+            args[args.len() - 6..],
+            ["-an", "-sn", "-dn", "-f", "null", "-"],
+            "{}",
+            encoder
+        );
+        assert!(
+            args.windows(2).any(|pair| pair == ["-b:v", "4000k"]),
+            "{}",
+            encoder
+        );
+    }
+    assert!(
+        first_pass("libx265").contains(&"pass=1:stats=transrustica_2pass_Movie.log".to_string())
+    );
+    assert!(first_pass("libaom-av1")
+        .windows(4)
+        .any(|args| args == ["-pass", "1", "-passlogfile", "transrustica_2pass_Movie"]));
+}
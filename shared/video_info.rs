@@ -0,0 +1,390 @@
+// The video_info table of the library: its rows, reading them and adding or replacing them
+
+use crate::*;
+
+// The first 18 columns of a video_info row, in column order
+pub type VideoInfoRow = (
+    i32,
+    String,
+    String,
+    i32,
+    i32,
+    f64,
+    String,
+    String,
+    String,
+    String,
+    i64,
+    i64,
+    i64,
+    String,
+    String,
+    String,
+    i64,
+    String,
+);
+
+// One video_info row without its id, used to insert probed files and to export/import the table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoInfo {
+    pub filename: String,
+    pub filepath: String,
+    pub width: i64,
+    pub height: i64,
+    pub duration: f64,
+    pub pixel_format: String,
+    pub display_aspect_ratio: String,
+    pub sample_aspect_ratio: String,
+    pub format: String,
+    pub size: i64,
+    pub folder_size: i64,
+    pub bitrate: i64,
+    pub codec: String,
+    pub status: String,
+    pub audio_codec: String,
+    pub audio_bitrate: i64,
+    pub hash: String,
+    #[serde(default)]
+    pub predicted_size: f64,
+    #[serde(default)]
+    pub predicted_percent: f64,
+    #[serde(default)]
+    pub verified_vmaf: f64,
+    #[serde(default)]
+    pub dynamic_hdr: String,
+    #[serde(default)]
+    pub chunked_vmaf: f64,
+    // ChunkedVmafStats as json
+    #[serde(default)]
+    pub chunked_stats: String,
+    // the png paths of --screenshots as a json list
+    #[serde(default)]
+    pub screenshots: String,
+    // variable frame rate source, see stream_is_vfr
+    #[serde(default)]
+    pub vfr: bool,
+    // --content-tune picked for the encode, empty without one
+    #[serde(default)]
+    pub content_tune: String,
+    // the software encoder of --fallback-encoder when the hardware encoder failed to start, empty
+    // otherwise
+    #[serde(default)]
+    pub fallback_encoder: String,
+    // the title, show, season, episode and date tags of the container, see MediaTags
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub show: String,
+    #[serde(default)]
+    pub season: String,
+    #[serde(default)]
+    pub episode: String,
+    #[serde(default)]
+    pub date: String,
+    // the counted frames and the size and modification time (ms) of the file then, see
+    // cached_frame_count
+    #[serde(default)]
+    pub frame_count: f64,
+    #[serde(default)]
+    pub frame_count_size: i64,
+    #[serde(default)]
+    pub frame_count_mtime: i64,
+    // the --film-grain level of the encode, 0 without grain synthesis
+    #[serde(default)]
+    pub film_grain: i64,
+    // the --verify-library check: ok, corrupt or suspect, when, the first error line of ffmpeg and
+    // the size and modification time (ms) of the file then
+    #[serde(default)]
+    pub health: String,
+    #[serde(default)]
+    pub health_checked_at: String,
+    #[serde(default)]
+    pub health_error: String,
+    #[serde(default)]
+    pub health_size: i64,
+    #[serde(default)]
+    pub health_mtime: i64,
+    // the --vmaf of the last encode and the target it used, see adapt_vmaf_target
+    #[serde(default)]
+    pub requested_vmaf: f64,
+    #[serde(default)]
+    pub effective_vmaf: f64,
+    // the size of the video of the last --max-resolution encode
+    #[serde(default)]
+    pub output_width: i64,
+    #[serde(default)]
+    pub output_height: i64,
+}
+
+impl VideoInfo {
+    // Reason the row can't be imported, if any
+    pub(crate) fn invalid_reason(&self) -> Option<&'static str> {
+        if self.filepath.is_empty() {
+            Some("empty filepath")
+        } else if self.filename.is_empty() {
+            Some("empty filename")
+        } else if self.status.is_empty() {
+            Some("empty status")
+        } else if self.width < 0 || self.height < 0 || self.duration < 0.0 || self.size < 0 {
+            Some("negative dimensions, duration or size")
+        } else {
+            None
+        }
+    }
+}
+
+impl Db {
+    pub(crate) fn video_info_from_row(row: &rusqlite::Row) -> Result<VideoInfoRow> {
+        Ok((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+            row.get(7)?,
+            row.get(8)?,
+            row.get(9)?,
+            row.get(10)?,
+            row.get(11)?,
+            row.get(12)?,
+            row.get(13)?,
+            row.get(14)?,
+            row.get(15)?,
+            row.get(16)?,
+            row.get(17)?,
+        ))
+    }
+
+    // A video_info row read by column name, with the columns added later
+    pub(crate) fn video_info_from_named_row(row: &rusqlite::Row) -> Result<VideoInfo> {
+        Ok(VideoInfo {
+            filename: row.get("filename")?,
+            filepath: row.get("filepath")?,
+            width: row.get("width")?,
+            height: row.get("height")?,
+            duration: row.get("duration")?,
+            pixel_format: row.get("pixel_format")?,
+            display_aspect_ratio: row.get("display_aspect_ratio")?,
+            sample_aspect_ratio: row.get("sample_aspect_ratio")?,
+            format: row.get("format")?,
+            size: row.get("size")?,
+            folder_size: row.get("folder_size")?,
+            bitrate: row.get("bitrate")?,
+            codec: row.get("codec")?,
+            status: row.get("status")?,
+            audio_codec: row.get("audio_codec")?,
+            audio_bitrate: row.get("audio_bitrate")?,
+            hash: row.get("hash")?,
+            predicted_size: row.get("predicted_size")?,
+            predicted_percent: row.get("predicted_percent")?,
+            verified_vmaf: row.get("verified_vmaf")?,
+            dynamic_hdr: row.get("dynamic_hdr")?,
+            chunked_vmaf: row.get("chunked_vmaf")?,
+            chunked_stats: row.get("chunked_stats")?,
+            screenshots: row.get("screenshots")?,
+            vfr: row.get("vfr")?,
+            content_tune: row.get("content_tune")?,
+            fallback_encoder: row.get("fallback_encoder")?,
+            title: row.get("title")?,
+            show: row.get("show")?,
+            season: row.get("season")?,
+            episode: row.get("episode")?,
+            date: row.get("date")?,
+            frame_count: row.get("frame_count")?,
+            frame_count_size: row.get("frame_count_size")?,
+            frame_count_mtime: row.get("frame_count_mtime")?,
+            film_grain: row.get("film_grain")?,
+            health: row.get("health")?,
+            health_checked_at: row.get("health_checked_at")?,
+            health_error: row.get("health_error")?,
+            health_size: row.get("health_size")?,
+            health_mtime: row.get("health_mtime")?,
+            requested_vmaf: row.get("requested_vmaf")?,
+            effective_vmaf: row.get("effective_vmaf")?,
+            output_width: row.get("output_width")?,
+            output_height: row.get("output_height")?,
+        })
+    }
+
+    pub fn get_all(&self) -> Result<Vec<VideoInfoRow>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT * FROM video_info")?;
+        let rows = stmt.query_map(params![], Db::video_info_from_row)?;
+        rows.collect()
+    }
+
+    // Files are told apart by their path, the episodes of two seasons often share their names
+    pub fn video_info_exists(&self, filepath: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT * FROM video_info WHERE filepath=?1")?;
+        stmt.exists(params![filepath])
+    }
+
+    pub fn insert_video_info(&self, info: &VideoInfo) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO video_info (filename, filepath, width, height, duration, pixel_format, display_aspect_ratio, sample_aspect_ratio, format, size, folder_size, bitrate, codec, status, audio_codec, audio_bitrate, hash, predicted_size, predicted_percent, verified_vmaf, dynamic_hdr, chunked_vmaf, chunked_stats, screenshots, vfr, content_tune, fallback_encoder, title, show, season, episode, date, frame_count, frame_count_size, frame_count_mtime, film_grain, health, health_checked_at, health_error, health_size, health_mtime, requested_vmaf, effective_vmaf, output_width, output_height) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33, ?34, ?35, ?36, ?37, ?38, ?39, ?40, ?41, ?42, ?43, ?44, ?45)",
+            params![
+                info.filename,
+                info.filepath,
+                info.width,
+                info.height,
+                info.duration,
+                info.pixel_format,
+                info.display_aspect_ratio,
+                info.sample_aspect_ratio,
+                info.format,
+                info.size,
+                info.folder_size,
+                info.bitrate,
+                info.codec,
+                info.status,
+                info.audio_codec,
+                info.audio_bitrate,
+                info.hash,
+                info.predicted_size,
+                info.predicted_percent,
+                info.verified_vmaf,
+                info.dynamic_hdr,
+                info.chunked_vmaf,
+                info.chunked_stats,
+                info.screenshots,
+                info.vfr,
+                info.content_tune,
+                info.fallback_encoder,
+                info.title,
+                info.show,
+                info.season,
+                info.episode,
+                info.date,
+                info.frame_count,
+                info.frame_count_size,
+                info.frame_count_mtime,
+                info.film_grain,
+                info.health,
+                info.health_checked_at,
+                info.health_error,
+                info.health_size,
+                info.health_mtime,
+                info.requested_vmaf,
+                info.effective_vmaf,
+                info.output_width,
+                info.output_height
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Update the row with the same filepath, or insert it, returns true when inserted
+    pub fn upsert_video_info(&self, info: &VideoInfo) -> Result<bool> {
+        let updated = {
+            let conn = self.conn.lock().unwrap();
+            conn.execute(
+                "UPDATE video_info SET filename=?1, width=?2, height=?3, duration=?4, pixel_format=?5, display_aspect_ratio=?6, sample_aspect_ratio=?7, format=?8, size=?9, folder_size=?10, bitrate=?11, codec=?12, status=?13, audio_codec=?14, audio_bitrate=?15, hash=?16, predicted_size=?17, predicted_percent=?18, verified_vmaf=?19, dynamic_hdr=?20, chunked_vmaf=?21, chunked_stats=?22, screenshots=?23, vfr=?24, content_tune=?25, fallback_encoder=?26, title=?27, show=?28, season=?29, episode=?30, date=?31, frame_count=?32, frame_count_size=?33, frame_count_mtime=?34, film_grain=?35, health=?36, health_checked_at=?37, health_error=?38, health_size=?39, health_mtime=?40, requested_vmaf=?41, effective_vmaf=?42, output_width=?43, output_height=?44 WHERE filepath=?45",
+                params![
+                    info.filename,
+                    info.width,
+                    info.height,
+                    info.duration,
+                    info.pixel_format,
+                    info.display_aspect_ratio,
+                    info.sample_aspect_ratio,
+                    info.format,
+                    info.size,
+                    info.folder_size,
+                    info.bitrate,
+                    info.codec,
+                    info.status,
+                    info.audio_codec,
+                    info.audio_bitrate,
+                    info.hash,
+                    info.predicted_size,
+                    info.predicted_percent,
+                    info.verified_vmaf,
+                    info.dynamic_hdr,
+                    info.chunked_vmaf,
+                    info.chunked_stats,
+                    info.screenshots,
+                    info.vfr,
+                    info.content_tune,
+                    info.fallback_encoder,
+                    info.title,
+                    info.show,
+                    info.season,
+                    info.episode,
+                    info.date,
+                    info.frame_count,
+                    info.frame_count_size,
+                    info.frame_count_mtime,
+                    info.film_grain,
+                    info.health,
+                    info.health_checked_at,
+                    info.health_error,
+                    info.health_size,
+                    info.health_mtime,
+                    info.requested_vmaf,
+                    info.effective_vmaf,
+                    info.output_width,
+                    info.output_height,
+                    info.filepath
+                ],
+            )?
+        };
+        if updated > 0 {
+            return Ok(false);
+        }
+        self.insert_video_info(info)?;
+        Ok(true)
+    }
+
+    // Call f with every video_info row, one at a time, returns the number of rows
+    // The connection stays locked until all rows are read
+    pub fn for_each_video_info(
+        &self,
+        mut f: impl FnMut(VideoInfo) -> Result<(), io::Error>,
+    ) -> Result<u64, io::Error> {
+        let to_io = |e: rusqlite::Error| io::Error::new(ErrorKind::Other, e);
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT * FROM video_info ORDER BY id")
+            .map_err(to_io)?;
+        let mut rows = stmt.query(params![]).map_err(to_io)?;
+        let mut count = 0;
+        while let Some(row) = rows.next().map_err(to_io)? {
+            let info = Db::video_info_from_named_row(row).map_err(to_io)?;
+            f(info)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Filepaths still to encode (processing, pending or only encoded by --trial-range) matching any
+    // of the LIKE patterns
+    // Skipped files (skipped_manual from interactive mode, skipped_dovi, skipped_short) are only returned again
+    // when their status is in retry_statuses
+    pub fn pending_filepaths(
+        &self,
+        patterns: &[String],
+        retry_statuses: &[&str],
+    ) -> Result<Vec<String>> {
+        let retry: String = retry_statuses
+            .iter()
+            .map(|status| format!(" OR status = '{}'", status))
+            .collect();
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT filepath FROM video_info WHERE (status = 'processing' OR status = 'no_space' OR status = 'trial' OR status = 'source_changed' OR status LIKE '%pending%'{}) AND filepath LIKE ?1",
+            retry
+        ))?;
+        let mut filepaths = Vec::new();
+        for pattern in patterns {
+            let rows = stmt.query_map(params![pattern], |row| row.get(0))?;
+            for filepath in rows {
+                filepaths.push(filepath?);
+            }
+        }
+        Ok(filepaths)
+    }
+}
@@ -0,0 +1,548 @@
+// VMAF measurements: the piped encode and libvmaf of a scene, pooling, the chunked statistics and
+// --verify-vmaf
+
+use crate::*;
+
+pub(crate) fn parse_vmaf_score(output: &Output) -> Option<f32> {
+    let output_str_stderr = String::from_utf8_lossy(&output.stderr);
+    for line in output_str_stderr.lines() {
+        if line.contains("VMAF score:") {
+            return line.split("VMAF score:").nth(1)?.trim().parse().ok();
+        }
+    }
+    None
+}
+
+// The libvmaf comparison of the reference stream of input 0, like 0:3, with input 1 (distorted)
+pub(crate) struct VmafSpec {
+    pub(crate) reference: String,
+    // an interlaced reference is deinterlaced like the encode
+    pub(crate) deinterlace: bool,
+    // the tonemap filter of an HDR reference
+    pub(crate) tonemap: Option<String>,
+    // the downscale filter of --max-resolution
+    pub(crate) downscale: Option<String>,
+    // both inputs are resampled to this rate first, so VFR sources line up frame by frame
+    pub(crate) fps: Option<String>,
+    pub(crate) pool: String,
+    pub(crate) threads: String,
+    pub(crate) subsample: String,
+    // a --renditions encode is scaled back to the reference, libvmaf compares frames of one size
+    pub(crate) reference_size: Option<(u64, u64)>,
+    // the frames of an anamorphic source, both are scaled to that size
+    pub(crate) square_pixels: Option<(u64, u64)>,
+}
+
+impl VmafSpec {
+    pub(crate) fn new(reference: &str, pool: &str, threads: &str, subsample: &str) -> VmafSpec {
+        VmafSpec {
+            reference: reference.to_string(),
+            deinterlace: false,
+            tonemap: None,
+            downscale: None,
+            fps: None,
+            pool: pool.to_string(),
+            threads: threads.to_string(),
+            subsample: subsample.to_string(),
+            reference_size: None,
+            square_pixels: None,
+        }
+    }
+
+    // The -lavfi filter graph
+    pub(crate) fn lavfi(&self) -> String {
+        let deinterlace_filter = if self.deinterlace { "yadif," } else { "" };
+        let tonemap_filter = match &self.tonemap {
+            Some(tonemap) => format!("{},", tonemap),
+            None => String::new(),
+        };
+        let downscale_filter = match &self.downscale {
+            Some(downscale) => format!("{},", downscale),
+            None => String::new(),
+        };
+        let fps_filter = match &self.fps {
+            Some(fps) => format!(",fps={}", fps),
+            None => String::new(),
+        };
+        let scale_filter = match self.reference_size {
+            Some((width, height)) => format!(",scale={}:{}:flags=bicubic", width, height),
+            None => String::new(),
+        };
+        let square_filter = match self.square_pixels {
+            Some((width, height)) => {
+                format!(",scale={}:{}:flags=bicubic,setsar=1", width, height)
+            }
+            None => String::new(),
+        };
+        format!(
+            "[{}]{}{}{}setpts=PTS-STARTPTS{}{}[reference];[1:v]setpts=PTS-STARTPTS{}{}{}[distorted];[reference][distorted]libvmaf='pool={}:n_threads={}:n_subsample={}'",
+            self.reference, deinterlace_filter, tonemap_filter, downscale_filter, fps_filter, square_filter, fps_filter, scale_filter, square_filter, self.pool, self.threads, self.subsample
+        )
+    }
+}
+
+// Width and height of the video stream of file
+pub(crate) fn video_size(file: &str) -> Option<(u64, u64)> {
+    let info = probe_file(file);
+    let stream = info.video_stream()?;
+    Some((stream["width"].as_u64()?, stream["height"].as_u64()?))
+}
+
+// The -r of both inputs of the VMAF pipe of a source with video stream at fps, and the rate of the
+// fps filter of both instead for a variable frame rate source. -r on the input overrides the
+// timestamps, which shifts the frames of such a source against the reference, those are encoded at
+// their average rate with the fps filter and the reference goes through the same filter in libvmaf
+pub(crate) fn vmaf_pipe_rate(
+    video_stream: Option<&Value>,
+    fps: &str,
+) -> (Vec<String>, Option<String>) {
+    match video_stream.filter(|stream| stream_is_vfr(stream)) {
+        Some(stream) => (
+            Vec::new(),
+            Some(frame_rate(stream, "avg_frame_rate").to_string()),
+        ),
+        None => (vec!["-r".to_string(), fps.to_string()], None),
+    }
+}
+
+// With keep_stream the encoded sample is also written to that file, chunked mode remuxes the one of
+// the CRF it chose instead of encoding the scene again
+pub(crate) fn process_video_pipe_and_vmaf(
+    file: &String,
+    args: &Args,
+    crf: &f32,
+    fps: &str,
+    ss_arg: &String,
+    to_arg: &String,
+    vmaf_pool: &String,
+    vmaf_threads: &str,
+    vmaf_subsample: &str,
+    scale: Option<&str>,
+    keep_stream: Option<&str>,
+) -> Result<Output, io::Error> {
+    let (preset, params) = encoder_preset_params(args);
+
+    let (input_rate, vfr_fps) = vmaf_pipe_rate(probe_file(file).video_stream(), fps);
+    let video = probe_file(file).video_map();
+    let deinterlace = should_deinterlace(file, &args.deinterlace);
+    let tonemap = tonemap_filter(file, &args.tonemap);
+    let downscale = downscale(file, args).map(|downscale| downscale.filter);
+
+    let mut spec = EncodeSpec::new(
+        &args.encoder,
+        EncodeQuality::Crf(*crf),
+        preset,
+        params,
+        &args.pix_fmt,
+    );
+    spec.keyint = encode_keyint(fps.parse().unwrap_or(0.0), args);
+    spec.seek = Some((ss_arg.clone(), to_arg.clone()));
+    spec.maps = vec!["-map".to_string(), video.clone()];
+    spec.filters = deinterlace
+        .then(|| "yadif".to_string())
+        .into_iter()
+        .chain(tonemap.clone())
+        .chain(downscale.clone())
+        .chain(vfr_fps.iter().map(|vfr_fps| format!("fps={}", vfr_fps)))
+        .chain(scale.map(String::from))
+        .collect();
+
+    let mut encode_command = ffmpeg_command();
+    encode_command.args(
+        FfmpegArgs::new(file, spec, "pipe:1")
+            .input_args(["-y"])
+            .input_args(&input_rate)
+            .input_args(["-an", "-sn", "-dn"])
+            .output_args(["-f", "nut"])
+            .build(),
+    );
+    encode_command.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    let mut vmaf = VmafSpec::new(&video, vmaf_pool, vmaf_threads, vmaf_subsample);
+    vmaf.deinterlace = deinterlace;
+    vmaf.tonemap = tonemap;
+    vmaf.downscale = downscale;
+    vmaf.fps = vfr_fps;
+    vmaf.reference_size = scale.and_then(|_| video_size(file));
+    vmaf.square_pixels = anamorphic(file).map(|anamorphic| anamorphic.square_pixel_size());
+
+    let reference_input = staged_input(file);
+    let thread_queue_size = vmaf_thread_queue_size(file, args);
+    let mut vmaf_command = ffmpeg_command();
+    vmaf_command.args(&input_rate).args([
+        "-ss",
+        &ss_arg,
+        "-to",
+        &to_arg,
+        "-an",
+        "-sn",
+        "-dn",
+        "-i",
+        &reference_input, // Reference file
+        "-thread_queue_size",
+        &thread_queue_size,
+        "-f",
+        "nut",
+        "-i",
+        "pipe:0", // Reading from pipe
+        "-lavfi",
+        &vmaf.lavfi(),
+        "-f",
+        "null",
+        "-",
+    ]);
+    vmaf_command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+
+    debug!("{:?}", encode_command);
+    debug!("{:?}", vmaf_command);
+    run_vmaf_pipe(
+        encode_command,
+        vmaf_command,
+        args.scene_timeout,
+        &args.task_id,
+        &format!(
+            "ffmpeg VMAF sample {} {}-{} crf {}",
+            file, ss_arg, to_arg, crf
+        ),
+        keep_stream,
+    )
+}
+
+// -thread_queue_size of the piped encode, --thread-queue-size or more packets for larger frames
+fn vmaf_thread_queue_size(file: &str, args: &Args) -> String {
+    if let Some(size) = args.thread_queue_size {
+        return size.to_string();
+    }
+    let pixels = video_size(file).map_or(0, |(width, height)| width * height);
+    let size = if pixels > 3840 * 2160 {
+        16384
+    } else if pixels > 1920 * 1080 {
+        8192
+    } else {
+        4096
+    };
+    size.to_string()
+}
+
+// Read all of reader on a thread of its own, into the stderr tail or as it is. The output is shared
+// so it can be taken without the join, a killed process can leave its pipe to a child of its own
+fn read_on_thread<R: Read + Send + 'static>(
+    mut reader: R,
+    tail: bool,
+) -> (Arc<Mutex<String>>, thread::JoinHandle<()>) {
+    let output = Arc::new(Mutex::new(String::new()));
+    let thread_output = Arc::clone(&output);
+    let handle = thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let chunk = String::from_utf8_lossy(&buffer[..n]);
+                    let mut output = thread_output.lock().unwrap();
+                    if tail {
+                        push_stderr_tail(&mut output, &chunk);
+                    } else {
+                        output.push_str(&chunk);
+                    }
+                }
+            }
+        }
+    });
+    (output, handle)
+}
+
+// Run the encode of a sample into the VMAF ffmpeg. A thread copies the nut stream of the encode to
+// the stdin of the VMAF and the stderr of both is read by threads too, so no pipe can fill up while
+// the other side is still opening the reference. Both are killed once they ran longer than
+// timeout seconds (0 waits forever), the time of a pause doesn't count
+fn run_vmaf_pipe(
+    encode_command: Command,
+    vmaf_command: Command,
+    timeout: u64,
+    task_id: &str,
+    label: &str,
+    keep_stream: Option<&str>,
+) -> Result<Output, io::Error> {
+    let keep = keep_stream.map(File::create).transpose()?;
+    let result = pipe_encode_to_vmaf(encode_command, vmaf_command, timeout, task_id, label, keep);
+    // a stream the VMAF didn't get all of, or the VMAF failed on, can't be remuxed
+    if let Some(keep_stream) = keep_stream {
+        if !matches!(&result, Ok((output, true)) if output.status.success()) {
+            let _ = fs::remove_file(keep_stream);
+        }
+    }
+    result.map(|(output, _)| output)
+}
+
+// Copy the stream of the encode to the VMAF, and to keep when there is one. Whether keep got all of it
+fn pump_stream<R: Read, W: Write>(mut reader: R, mut writer: W, mut keep: Option<File>) -> bool {
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return false,
+        };
+        if let Some(file) = &mut keep {
+            if file.write_all(&buffer[..n]).is_err() {
+                keep = None;
+            }
+        }
+        if writer.write_all(&buffer[..n]).is_err() {
+            return false;
+        }
+    }
+    // dropping the writer closes the stdin of the VMAF
+    drop(writer);
+    keep.is_some_and(|mut file| file.flush().is_ok())
+}
+
+fn pipe_encode_to_vmaf(
+    mut encode_command: Command,
+    mut vmaf_command: Command,
+    timeout: u64,
+    task_id: &str,
+    label: &str,
+    keep: Option<File>,
+) -> Result<(Output, bool), io::Error> {
+    let mut encode_process = encode_command.spawn()?;
+    let _encode_tracked = track_child(&encode_process, &[]);
+    let mut vmaf_process = match vmaf_command.spawn() {
+        Ok(vmaf_process) => vmaf_process,
+        Err(e) => {
+            let _ = encode_process.kill();
+            let _ = encode_process.wait();
+            return Err(e);
+        }
+    };
+    let _vmaf_tracked = track_child(&vmaf_process, &[]);
+
+    let encode_stdout = encode_process.stdout.take().unwrap();
+    let vmaf_stdin = vmaf_process.stdin.take().unwrap();
+    // the VMAF closing its input early ends the copy, the encode then fails on its closed stdout
+    let pump = thread::spawn(move || pump_stream(encode_stdout, vmaf_stdin, keep));
+    let encode_stderr = read_on_thread(encode_process.stderr.take().unwrap(), true);
+    let vmaf_stderr = read_on_thread(vmaf_process.stderr.take().unwrap(), false);
+
+    let timeout = Duration::from_secs(timeout);
+    let mut running = Duration::ZERO;
+    let mut last_check = Instant::now();
+    let mut encode_status = None;
+    let mut vmaf_status = None;
+    let timed_out = loop {
+        if encode_status.is_none() {
+            encode_status = encode_process.try_wait()?;
+        }
+        if vmaf_status.is_none() {
+            vmaf_status = vmaf_process.try_wait()?;
+        }
+        if encode_status.is_some() && vmaf_status.is_some() {
+            break false;
+        }
+        let now = Instant::now();
+        if !is_paused() {
+            running += now.duration_since(last_check);
+        }
+        last_check = now;
+        if !timeout.is_zero() && running > timeout {
+            let _ = encode_process.kill();
+            let _ = vmaf_process.kill();
+            encode_status = Some(encode_process.wait()?);
+            vmaf_status = Some(vmaf_process.wait()?);
+            break true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    wait_if_interrupted();
+
+    if timed_out {
+        // the threads are left to end with the pipes
+        let encode_tail = encode_stderr.0.lock().unwrap().clone();
+        record_task_log(task_id, label, &encode_tail);
+        return Err(io::Error::new(
+            ErrorKind::TimedOut,
+            format!(
+                "{} ran longer than --scene-timeout {}s and was killed",
+                label,
+                timeout.as_secs()
+            ),
+        ));
+    }
+    let kept = pump.join().unwrap_or(false);
+    let _ = encode_stderr.1.join();
+    let _ = vmaf_stderr.1.join();
+    let encode_tail = encode_stderr.0.lock().unwrap().clone();
+    let vmaf_output = vmaf_stderr.0.lock().unwrap().clone();
+
+    let encode_status = encode_status.unwrap();
+    if !encode_status.success() {
+        record_task_log(task_id, label, &encode_tail);
+        let last_line = encode_tail.lines().last().unwrap_or_default().trim();
+        return Err(io::Error::new(
+            ErrorKind::Other,
+            format!(
+                "the sample encode failed ({}): {}",
+                encode_status, last_line
+            ),
+        ));
+    }
+
+    Ok((
+        Output {
+            status: vmaf_status.unwrap(),
+            stdout: Vec::new(),
+            stderr: vmaf_output.into_bytes(),
+        },
+        kept,
+    ))
+}
+
+// After the full encode, measure the VMAF of output against file on --verify-samples 20 second samples
+// The pooled score is stored in the db, a score more than --verify-max-delta below the target is reported
+// and, with --fail-verification, sets the status to failed_verification
+pub fn verify_encode_vmaf(file: &str, output: &str, args: &Args, target_vmaf: f32) -> Option<f32> {
+    let sample_length = 20.0;
+    let samples = args.verify_samples.max(1);
+    let duration = match get_duration(file) {
+        Some(duration) => duration,
+        None => {
+            warn!("Can't verify {}, failed to get its duration", output);
+            return None;
+        }
+    };
+    let fps = vmaf_fps(file);
+    // a --max-resolution encode is scored against the source scaled down like it was searched, a
+    // rendition is scaled back to the size of its source
+    let downscale = downscale(file, args)
+        .filter(|downscale| video_size(output) == Some((downscale.width, downscale.height)));
+    let reference_size =
+        video_size(file).filter(|size| downscale.is_none() && video_size(output) != Some(*size));
+    let reference = probe_file(file).video_map();
+    // the encode of an interlaced source is progressive
+    let deinterlace =
+        should_deinterlace(file, &args.deinterlace) && !probe_file(output).is_interlaced();
+    // and the one of an HDR source is SDR with --tonemap sdr
+    let tonemap = tonemap_filter(file, &args.tonemap).filter(|_| !probe_file(output).is_hdr());
+    let mut vmaf = VmafSpec::new(
+        &reference,
+        &args.vmaf_pool,
+        &args.vmaf_threads,
+        &args.vmaf_subsample,
+    );
+    vmaf.deinterlace = deinterlace;
+    vmaf.tonemap = tonemap;
+    vmaf.downscale = downscale.map(|downscale| downscale.filter);
+    vmaf.fps = Some(fps);
+    vmaf.reference_size = reference_size;
+    vmaf.square_pixels = anamorphic(file).map(|anamorphic| anamorphic.square_pixel_size());
+
+    let reference_input = staged_input(file);
+
+    let verify_bar = LOG_PROGRESS.add(ProgressBar::new(samples as u64));
+    let verify_style =
+        "[verify][{elapsed_precise}][{wide_bar:.cyan/blue}] {pos:>3}/{len:3} [{msg}]";
+    verify_bar.set_style(ProgressStyle::default_bar().template(verify_style).unwrap());
+
+    let mut scores: Vec<f32> = Vec::new();
+    for sample in 0..samples {
+        // center each sample in its share of the file, short files are measured as a whole
+        let center = duration * (sample as f32 + 0.5) / samples as f32;
+        let ss = (center - sample_length / 2.0).max(0.0);
+        let to = (ss + sample_length).min(duration);
+        let ss = format!("{:.3}", ss);
+        let to = format!("{:.3}", to);
+
+        let mut vmaf_command = ffmpeg_command();
+        vmaf_command.args([
+            "-ss",
+            &ss,
+            "-to",
+            &to,
+            "-an",
+            "-sn",
+            "-dn",
+            "-i",
+            &reference_input,
+            "-ss",
+            &ss,
+            "-to",
+            &to,
+            "-an",
+            "-sn",
+            "-dn",
+            "-i",
+            output,
+            "-lavfi",
+            &vmaf.lavfi(),
+            "-f",
+            "null",
+            "-",
+        ]);
+        debug!("{:?}", vmaf_command);
+
+        match vmaf_command.output() {
+            Ok(vmaf_output) => match parse_vmaf_score(&vmaf_output) {
+                Some(score) => {
+                    verify_bar.set_message(format!("{} {}: {:.2}", ss, to, score));
+                    scores.push(score);
+                }
+                None => warn!("No VMAF score for {} from {} to {}", output, ss, to),
+            },
+            Err(e) => error!("Failed to execute ffmpeg: {}", e),
+        }
+        verify_bar.inc(1);
+    }
+    verify_bar.finish_and_clear();
+
+    let score = match pool_vmaf_scores(&scores, &args.vmaf_pool) {
+        Some(score) => score,
+        None => {
+            warn!("Can't verify {}, no sample could be measured", output);
+            return None;
+        }
+    };
+
+    if let Err(e) = db().update_verified_vmaf(file, score) {
+        warn!("Failed to store verified VMAF of {}: {}", file, e);
+    }
+    if let Err(e) = db().update_encode_stats_vmaf(output, score as f64) {
+        warn!(
+            "Failed to store verified VMAF of {} in the stats: {}",
+            output, e
+        );
+    }
+
+    if score < target_vmaf - args.verify_max_delta {
+        warn!(
+            "{}",
+            format!(
+                "Verified VMAF {:.2} of {} is more than {} below the target {}",
+                score, output, args.verify_max_delta, target_vmaf
+            )
+            .red()
+        );
+        if args.fail_verification {
+            if let Err(e) = set_db_status(file, "failed_verification") {
+                error!("Failed to update status of {}: {}", file, e);
+            }
+        }
+    } else {
+        info!(
+            "{}",
+            format!(
+                "Verified VMAF {:.2} of {} (target {})",
+                score, output, target_vmaf
+            )
+            .green()
+        );
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests;
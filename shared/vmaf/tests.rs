@@ -0,0 +1,330 @@
+use super::*;
+
+fn output(stderr: &str) -> Output {
+    Output {
+        status: std::process::ExitStatus::default(),
+        stdout: Vec::new(),
+        stderr: stderr.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn parse_vmaf_score_of_libvmaf() {
+    let stderr = "[Parsed_libvmaf_4 @ 0x55d0c8f0] VMAF score: 95.125000\n";
+    assert_eq!(parse_vmaf_score(&output(stderr)), Some(95.125));
+}
+
+#[test]
+fn parse_vmaf_score_after_the_stats() {
+    let stderr = "frame= 1440 fps= 96 q=-0.0 Lsize=N/A time=00:01:00.00 bitrate=N/A speed=4.01x\n[Parsed_libvmaf_0 @ 0x7f] VMAF score: 100.000000\n";
+    assert_eq!(parse_vmaf_score(&output(stderr)), Some(100.0));
+}
+
+#[test]
+fn parse_vmaf_score_without_a_score() {
+    assert_eq!(parse_vmaf_score(&output("")), None);
+    assert_eq!(
+        parse_vmaf_score(&output("Error initializing filter 'libvmaf'\n")),
+        None
+    );
+    assert_eq!(
+        parse_vmaf_score(&output("[Parsed_libvmaf_0 @ 0x7f] VMAF score:\n")),
+        None
+    );
+}
+
+fn video_stream(r_frame_rate: &str, avg_frame_rate: &str) -> Value {
+    serde_json::json!({
+        "codec_type": "video",
+        "r_frame_rate": r_frame_rate,
+        "avg_frame_rate": avg_frame_rate,
+    })
+}
+
+#[test]
+fn stream_is_vfr_when_the_average_rate_differs() {
+    // every fourth frame of 24 fps dropped, like the vfr.mkv of --generate-test-media
+    assert!(stream_is_vfr(&video_stream("24/1", "18/1")));
+    // a phone clip that slowed down in the dark
+    assert!(stream_is_vfr(&video_stream("30/1", "57/2")));
+    assert!(stream_is_vfr(&video_stream("90000/1", "30/1")));
+}
+
+#[test]
+fn stream_is_not_vfr_for_rounding_or_unknown_rates() {
+    for (r_frame_rate, avg_frame_rate) in [
+        ("24000/1001", "24000/1001"),
+        // the average of a film rounded by its duration
+        ("24000/1001", "2997/125"),
+        ("25/1", "25/1"),
+        ("24/1", "0/0"),
+        ("0/0", "24/1"),
+        ("", ""),
+    ] {
+        assert!(
+            !stream_is_vfr(&video_stream(r_frame_rate, avg_frame_rate)),
+            "{} {}",
+            r_frame_rate,
+            avg_frame_rate
+        );
+    }
+}
+
+#[test]
+fn a_constant_rate_source_is_piped_at_its_rate() {
+    let stream = video_stream("24000/1001", "24000/1001");
+    assert_eq!(
+        vmaf_pipe_rate(Some(&stream), "23.976"),
+        (vec!["-r".to_string(), "23.976".to_string()], None)
+    );
+    // a file without a video stream keeps the -r
+    assert_eq!(
+        vmaf_pipe_rate(None, "25"),
+        (vec!["-r".to_string(), "25".to_string()], None)
+    );
+}
+
+#[test]
+fn a_variable_rate_source_is_resampled_on_both_sides() {
+    let stream = video_stream("24/1", "18/1");
+    let (input_rate, vfr_fps) = vmaf_pipe_rate(Some(&stream), "24");
+    // no -r that would override the timestamps of the source
+    assert!(input_rate.is_empty());
+    assert_eq!(vfr_fps.as_deref(), Some("18"));
+
+    let mut vmaf = VmafSpec::new("0:0", "mean", "4", "1");
+    vmaf.fps = vfr_fps;
+    let lavfi = vmaf.lavfi();
+    assert_eq!(
+        lavfi,
+        "[0:0]setpts=PTS-STARTPTS,fps=18[reference];[1:v]setpts=PTS-STARTPTS,fps=18[distorted];[reference][distorted]libvmaf='pool=mean:n_threads=4:n_subsample=1'"
+    );
+}
+
+#[test]
+fn a_constant_rate_source_has_no_fps_filter() {
+    let lavfi = VmafSpec::new("0:0", "mean", "4", "1").lavfi();
+    assert!(!lavfi.contains("fps="));
+}
+
+#[test]
+fn both_inputs_of_an_anamorphic_source_are_scaled_to_square_pixels() {
+    let mut vmaf = VmafSpec::new("0:0", "mean", "4", "1");
+    vmaf.square_pixels = Some((854, 480));
+    let lavfi = vmaf.lavfi();
+    assert_eq!(
+        lavfi,
+        "[0:0]setpts=PTS-STARTPTS,scale=854:480:flags=bicubic,setsar=1[reference];[1:v]setpts=PTS-STARTPTS,scale=854:480:flags=bicubic,setsar=1[distorted];[reference][distorted]libvmaf='pool=mean:n_threads=4:n_subsample=1'"
+    );
+}
+
+// A shell script as a process of the VMAF pipe, its stdio set like the encode or the VMAF ffmpeg
+#[cfg(unix)]
+fn script(body: &str, encode: bool) -> Command {
+    let mut command = Command::new("sh");
+    command.args(["-c", body]);
+    if encode {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    } else {
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+    }
+    command
+}
+
+#[cfg(unix)]
+#[test]
+fn a_stream_larger_than_the_pipes_goes_through() {
+    // 50 MB of stream and 1 MB of stderr from the encode, while the VMAF still opens the reference
+    let encode = script(
+        "head -c 1000000 /dev/zero | tr '\\0' 'e' >&2; head -c 50000000 /dev/zero",
+        true,
+    );
+    let vmaf = script("sleep 1; echo \"read $(wc -c) bytes\" >&2", false);
+    let keep = temp_path("pipe-stress.nut");
+    let output = run_vmaf_pipe(
+        encode,
+        vmaf,
+        60,
+        "",
+        "stress",
+        Some(&keep.to_string_lossy()),
+    )
+    .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        "read 50000000 bytes"
+    );
+    assert_eq!(fs::metadata(&keep).unwrap().len(), 50_000_000);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_hung_vmaf_is_killed_after_the_scene_timeout() {
+    let encode = script("head -c 50000000 /dev/zero", true);
+    let vmaf = script("sleep 30", false);
+    let keep = temp_path("pipe-hung.nut");
+    let started = Instant::now();
+    let e = run_vmaf_pipe(encode, vmaf, 1, "", "hung", Some(&keep.to_string_lossy())).unwrap_err();
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+    assert_eq!(
+        e.to_string(),
+        "hung ran longer than --scene-timeout 1s and was killed"
+    );
+    assert!(!keep.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn a_failed_encode_is_reported_by_its_last_line() {
+    let encode = script(
+        "echo 'frame=    0' >&2; echo \"Unknown encoder 'libsvtav2'\" >&2; exit 1",
+        true,
+    );
+    let vmaf = script("cat > /dev/null", false);
+    let keep = temp_path("pipe-failed.nut");
+    let e = run_vmaf_pipe(
+        encode,
+        vmaf,
+        60,
+        "",
+        "failed",
+        Some(&keep.to_string_lossy()),
+    )
+    .unwrap_err();
+    assert!(
+        e.to_string()
+            .starts_with("the sample encode failed (exit status: 1)"),
+        "{}",
+        e
+    );
+    assert!(
+        e.to_string().ends_with("Unknown encoder 'libsvtav2'"),
+        "{}",
+        e
+    );
+    assert!(!keep.exists());
+}
+
+#[test]
+fn the_thread_queue_grows_with_the_frames() {
+    let source = |name: &str, width: u64, height: u64| {
+        probed_file(
+            name,
+            serde_json::json!({
+                "streams": [{"index": 0, "codec_type": "video", "width": width, "height": height}],
+            }),
+        )
+    };
+    let args = test_args(&[]);
+    assert_eq!(args.scene_timeout, 3600);
+    for (name, width, height, size) in [
+        ("queue-720p.mkv", 1280, 720, "4096"),
+        ("queue-1080p.mkv", 1920, 1080, "4096"),
+        ("queue-1440p.mkv", 2560, 1440, "8192"),
+        ("queue-2160p.mkv", 3840, 2160, "8192"),
+        ("queue-4320p.mkv", 7680, 4320, "16384"),
+    ] {
+        let file = source(name, width, height);
+        assert_eq!(vmaf_thread_queue_size(&file, &args), size, "{}", name);
+    }
+    let file = source("queue-option.mkv", 7680, 4320);
+    let args = test_args(&["--thread-queue-size", "1024"]);
+    assert_eq!(vmaf_thread_queue_size(&file, &args), "1024");
+}
+
+#[test]
+fn a_high_bitrate_4k_sample_is_measured() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    // lossless 4K noise, hundreds of Mbit/s
+    let clip = temp_path("pipe-4k.mkv").to_string_lossy().to_string();
+    let status = ffmpeg_command()
+        .args(["-v", "error", "-y", "-f", "lavfi", "-i"])
+        .arg("testsrc2=size=3840x2160:rate=24:duration=2,noise=alls=60:allf=t")
+        .args(["-c:v", "libx264", "-preset", "ultrafast", "-qp", "0"])
+        .arg(&clip)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let args = test_args(&["--preset-x265", "ultrafast", "--scene-timeout", "600"]);
+    let output = process_video_pipe_and_vmaf(
+        &clip,
+        &args,
+        &30.0,
+        "24",
+        &"0".to_string(),
+        &"2".to_string(),
+        &"mean".to_string(),
+        "4",
+        "1",
+        None,
+        None,
+    )
+    .unwrap();
+    assert!(output.status.success());
+    assert!(parse_vmaf_score(&output).is_some());
+}
+
+// A 2 second testsrc2 clip, with every fourth frame dropped when vfr
+fn rate_clip(name: &str, vfr: bool) -> String {
+    let clip = temp_path(name).to_string_lossy().to_string();
+    let mut command = ffmpeg_command();
+    command
+        .args(["-v", "error", "-y", "-f", "lavfi", "-i"])
+        .arg("testsrc2=size=320x240:rate=24:duration=2")
+        .args(["-c:v", "libx264", "-preset", "ultrafast", "-qp", "0"]);
+    if vfr {
+        command.args(["-vf", "select='not(eq(mod(n,4),1))'", "-fps_mode", "vfr"]);
+    }
+    assert!(command.arg(&clip).status().unwrap().success());
+    clip
+}
+
+#[test]
+fn a_variable_rate_sample_scores_like_a_constant_rate_one() {
+    let _tools = lock_tools();
+    if !ffmpeg_available() {
+        eprintln!("skipped, {} is not installed", ffmpeg_bin());
+        return;
+    }
+    let args = test_args(&["--preset-x265", "ultrafast"]);
+    let score = |clip: &str| {
+        let output = process_video_pipe_and_vmaf(
+            &clip.to_string(),
+            &args,
+            &20.0,
+            "24",
+            &"0".to_string(),
+            &"2".to_string(),
+            &"mean".to_string(),
+            "4",
+            "1",
+            None,
+            None,
+        )
+        .unwrap();
+        assert!(output.status.success(), "{}", clip);
+        parse_vmaf_score(&output).unwrap()
+    };
+    let vfr = rate_clip("vmaf-vfr.mkv", true);
+    assert!(probe_file(&vfr).is_vfr());
+    let vfr_score = score(&vfr);
+    let cfr_score = score(&rate_clip("vmaf-cfr.mkv", false));
+    // frames shifted against the reference score far lower
+    assert!(vfr_score > 90.0, "{}", vfr_score);
+    assert!(
+        (vfr_score - cfr_score).abs() < 3.0,
+        "{} against {}",
+        vfr_score,
+        cfr_score
+    );
+}
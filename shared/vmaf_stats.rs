@@ -0,0 +1,124 @@
+// Pooling the VMAF scores of the frames, and the statistics of the scores of the chunked scenes
+
+use crate::*;
+
+// Combine per-sample scores the same way libvmaf pools per-frame scores
+pub(crate) fn pool_vmaf_scores(scores: &[f32], vmaf_pool: &str) -> Option<f32> {
+    if scores.is_empty() {
+        return None;
+    }
+    match vmaf_pool {
+        "min" => scores.iter().cloned().reduce(f32::min),
+        "harmonic_mean" => {
+            // libvmaf's harmonic mean adds 1 to every score so 0 doesn't divide by zero
+            let sum: f32 = scores.iter().map(|score| 1.0 / (score + 1.0)).sum();
+            Some(scores.len() as f32 / sum - 1.0)
+        }
+        _ => Some(scores.iter().sum::<f32>() / scores.len() as f32),
+    }
+}
+
+// VMAF statistics of a chunked encode over the final CRF and VMAF of every scene
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ChunkedVmafStats {
+    pub scenes: usize,
+    pub mean: f32,
+    pub harmonic_mean: f32,
+    pub min: f32,
+    pub p1: f32,
+    pub p5: f32,
+    // the statistic --vmaf-pool picks as the score of the file
+    pub pooled: f32,
+    pub vmaf_pool: String,
+    // chosen CRF and the number of scenes encoded with it, by CRF
+    pub crf_distribution: Vec<(f32, usize)>,
+    pub input_size_mb: f64,
+    pub output_size_mb: f64,
+    // what the chunks were split by: scenes or chapters
+    #[serde(default)]
+    pub split_source: String,
+    // scenes kept at the fast CRF of --fast-scene-threshold, in the statistics like the others
+    #[serde(default)]
+    pub fast_scenes: usize,
+}
+
+// Nearest-rank percentile of sorted scores
+fn percentile(sorted: &[f32], percent: f32) -> f32 {
+    let rank = (percent / 100.0 * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+// Statistics of the (scene index, crf, vmaf) results of a chunked encode, None without scenes
+pub fn chunked_vmaf_stats(
+    scenes: &[(i32, f32, f32)],
+    vmaf_pool: &str,
+    input_size_mb: f64,
+    output_size_mb: f64,
+    split_source: &str,
+    fast_scenes: usize,
+) -> Option<ChunkedVmafStats> {
+    let scores: Vec<f32> = scenes.iter().map(|(_, _, vmaf)| *vmaf).collect();
+    let mut sorted = scores.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut crf_distribution: Vec<(f32, usize)> = Vec::new();
+    let mut crfs: Vec<f32> = scenes.iter().map(|(_, crf, _)| *crf).collect();
+    crfs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    for crf in crfs {
+        match crf_distribution.last_mut() {
+            Some((last, count)) if *last == crf => *count += 1,
+            _ => crf_distribution.push((crf, 1)),
+        }
+    }
+
+    Some(ChunkedVmafStats {
+        scenes: scenes.len(),
+        mean: pool_vmaf_scores(&scores, "mean")?,
+        harmonic_mean: pool_vmaf_scores(&scores, "harmonic_mean")?,
+        min: pool_vmaf_scores(&scores, "min")?,
+        p1: percentile(&sorted, 1.0),
+        p5: percentile(&sorted, 5.0),
+        pooled: pool_vmaf_scores(&scores, vmaf_pool)?,
+        vmaf_pool: vmaf_pool.to_string(),
+        crf_distribution,
+        input_size_mb,
+        output_size_mb,
+        split_source: split_source.to_string(),
+        fast_scenes,
+    })
+}
+
+pub fn log_chunked_vmaf_stats(file: &str, stats: &ChunkedVmafStats) {
+    info!(
+        "{}",
+        format!(
+            "{}: VMAF {:.2} ({}) over {} scenes split by {}",
+            file, stats.pooled, stats.vmaf_pool, stats.scenes, stats.split_source
+        )
+        .green()
+    );
+    info!(
+        "  mean {:.2}, harmonic mean {:.2}, min {:.2}, 1% {:.2}, 5% {:.2}",
+        stats.mean, stats.harmonic_mean, stats.min, stats.p1, stats.p5
+    );
+    let crfs: Vec<String> = stats
+        .crf_distribution
+        .iter()
+        .map(|(crf, count)| format!("{} x{}", crf, count))
+        .collect();
+    info!("  CRF: {}", crfs.join(", "));
+    if stats.fast_scenes > 0 {
+        info!(
+            "  fast path: {} of {} scenes",
+            stats.fast_scenes, stats.scenes
+        );
+    }
+    if stats.input_size_mb > 0.0 {
+        info!(
+            "  size: {:.2} MB -> {:.2} MB ({:.2}%)",
+            stats.input_size_mb,
+            stats.output_size_mb,
+            (stats.output_size_mb / stats.input_size_mb - 1.0) * 100.0
+        );
+    }
+}
@@ -0,0 +1,189 @@
+// The defaults of the tasks the web server starts: its compiled Args, then the settings stored with
+// PUT /settings, then the values of the request. Settings are checked by the value parsers of the
+// command line and stored in the settings table, so the next task uses them without a restart
+
+use crate::*;
+
+use clap::CommandFactory;
+
+// The Args fields PUT /settings can change
+pub const WEB_SETTINGS: [&str; 20] = [
+    "encoder",
+    "vmaf",
+    "vmaf_threads",
+    "pix_fmt",
+    "max_crf",
+    "outputpath",
+    "artifacts_dir",
+    "preset_x265",
+    "preset_av1",
+    "preset_hevc_nvenc",
+    "preset_hevc_qsv",
+    "preset_av1_qsv",
+    "preset_libsvtav1",
+    "preset_libaom_av1",
+    "params_x265",
+    "params_hevc_nvenc",
+    "params_hevc_qsv",
+    "params_av1_qsv",
+    "params_libsvtav1",
+    "params_libaom_av1",
+];
+
+// The key of a setting in the settings table, next to the webhook ones
+fn setting_key(field: &str) -> String {
+    format!("default.{}", field)
+}
+
+// A setting that was refused, by its Args field
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingError {
+    pub field: String,
+    pub message: String,
+}
+
+impl SettingError {
+    fn new(field: &str, message: &str) -> SettingError {
+        SettingError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+// Run value through the value parser of the option of field, the message is the first line of clap's
+fn parse_setting(field: &str, value: &str) -> Result<(), String> {
+    if !WEB_SETTINGS.contains(&field) {
+        return Err("not a setting".to_string());
+    }
+    let flag = format!("--{}={}", field.replace('_', "-"), value);
+    // -i only satisfies the required input
+    Args::command()
+        .try_get_matches_from(["transrustica", "-i", ".", flag.as_str()])
+        .map(|_| ())
+        .map_err(|e| {
+            let message = e.to_string();
+            let first_line = message.lines().next().unwrap_or_default();
+            first_line.trim_start_matches("error: ").to_string()
+        })
+}
+
+// Set field of args to value, through serde so vmaf gets a number and artifacts_dir an option
+fn set_field(args: &Args, field: &str, value: &str) -> Result<Args, String> {
+    let mut fields = match serde_json::to_value(args) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(_) => return Err("Failed to serialize the arguments".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let value = match fields.get(field) {
+        Some(Value::Number(_)) => value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("{} is not a number", value))?,
+        Some(Value::Null) if value.is_empty() => Value::Null,
+        _ => Value::String(value.to_string()),
+    };
+    fields.insert(field.to_string(), value);
+    serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())
+}
+
+// args with the settings applied in order, a later value of a field wins. Every refused setting is
+// returned, each value by itself and then the ones that don't go together
+pub fn apply_web_settings(
+    args: Args,
+    settings: &[(String, String)],
+) -> Result<Args, Vec<SettingError>> {
+    let mut args = args;
+    let mut errors = Vec::new();
+    for (field, value) in settings {
+        match parse_setting(field, value).and_then(|_| set_field(&args, field, value)) {
+            Ok(applied) => args = applied,
+            Err(e) => errors.push(SettingError::new(field, &e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    match args.max_crf.trim().parse::<f32>() {
+        Ok(crf) if (0.0..=51.0).contains(&crf) => {}
+        _ => errors.push(SettingError::new(
+            "max_crf",
+            "must be a number from 0 to 51",
+        )),
+    }
+    // auto is resolved when the task starts
+    if args.encoder != "auto" {
+        if let Err(e) = resolve_encoder(&args, &args.encoder) {
+            errors.push(SettingError::new("encoder", &e));
+        }
+    }
+    if let Err(e) = resolve_thread_counts(&mut args.clone()) {
+        errors.push(SettingError::new("vmaf_threads", &e));
+    }
+    if errors.is_empty() {
+        Ok(args)
+    } else {
+        Err(errors)
+    }
+}
+
+// The settings stored with PUT /settings, by field
+pub fn stored_web_settings() -> rusqlite::Result<Vec<(String, String)>> {
+    let mut settings = Vec::new();
+    for field in WEB_SETTINGS {
+        if let Some(value) = db().get_setting(&setting_key(field))? {
+            settings.push((field.to_string(), value));
+        }
+    }
+    Ok(settings)
+}
+
+// Store the settings if defaults with the stored settings and them pass apply_web_settings, an
+// empty value removes a setting so the compiled default is used again. Returns the args the next
+// task starts from
+pub fn store_web_settings(
+    defaults: Args,
+    settings: &[(String, Option<String>)],
+) -> Result<Args, Vec<SettingError>> {
+    let stored =
+        stored_web_settings().map_err(|e| vec![SettingError::new("settings", &e.to_string())])?;
+    let mut merged: Vec<(String, String)> = stored
+        .into_iter()
+        .filter(|(field, _)| !settings.iter().any(|(changed, _)| changed == field))
+        .collect();
+    for (field, value) in settings {
+        match value.as_deref().map(str::trim) {
+            Some(value) if !value.is_empty() => merged.push((field.clone(), value.to_string())),
+            // removing an unknown field is refused like setting it
+            _ if !WEB_SETTINGS.contains(&field.as_str()) => {
+                return Err(vec![SettingError::new(field, "not a setting")])
+            }
+            _ => {}
+        }
+    }
+    let args = apply_web_settings(defaults, &merged)?;
+
+    for (field, value) in settings {
+        let value = value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        db().set_setting(&setting_key(field), value)
+            .map_err(|e| vec![SettingError::new(field, &e.to_string())])?;
+    }
+    Ok(args)
+}
+
+// The value of every setting for the tasks started now, by field
+pub fn web_settings_values(args: &Args) -> Value {
+    let fields = serde_json::to_value(args).unwrap_or(Value::Null);
+    let values: serde_json::Map<String, Value> = WEB_SETTINGS
+        .iter()
+        .map(|field| (field.to_string(), fields[*field].clone()))
+        .collect();
+    Value::Object(values)
+}
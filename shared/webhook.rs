@@ -0,0 +1,158 @@
+// --notify-webhook: a JSON POST for every failed file as it happens and one with the run summary at
+// the end, filtered by --notify-on. The posts are sent from their own thread with a 10 second
+// timeout and 3 attempts, so a dead webhook never stalls the encodes
+
+use crate::*;
+
+use std::thread::JoinHandle;
+
+const WEBHOOK_ATTEMPTS: u32 = 3;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The web server's webhook, stored in the settings table
+pub const WEBHOOK_URL_SETTING: &str = "webhook_url";
+pub const WEBHOOK_NOTIFY_ON_SETTING: &str = "webhook_notify_on";
+
+// The run summary posted at the end, the failures and larger outputs are those of --report-json
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub task_id: String,
+    pub files: u64,
+    pub failed: u64,
+    // the encodes finished during the run
+    pub savings: StatsTotals,
+    pub elapsed_s: u64,
+    pub failures: Vec<FailedFile>,
+    pub larger_outputs: Vec<SizeCheck>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum WebhookEvent {
+    FileFailed {
+        task_id: String,
+        #[serde(flatten)]
+        failure: FailedFile,
+    },
+    RunFinished(RunSummary),
+}
+
+pub(crate) struct Webhook {
+    notify_on: String,
+    task_id: String,
+    sender: Option<mpsc::Sender<WebhookEvent>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Webhook {
+    pub(crate) fn start(url: &str, notify_on: &str, task_id: &str) -> Webhook {
+        let url = url.to_string();
+        let (sender, receiver) = mpsc::channel::<WebhookEvent>();
+        // the blocking client runs its own runtime, which cannot live on a thread of the web
+        // server's runtime
+        let worker = thread::spawn(move || {
+            let client = match reqwest::blocking::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+            {
+                Ok(client) => client,
+                Err(e) => {
+                    error!("Failed to set up the webhook {}: {}", redact_url(&url), e);
+                    return;
+                }
+            };
+            for event in receiver {
+                post_event(&client, &url, &event);
+            }
+        });
+        Webhook {
+            notify_on: notify_on.to_string(),
+            task_id: task_id.to_string(),
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    pub(crate) fn file_failed(&self, failed: &FailedFile) {
+        if self.notify_on == "success" {
+            return;
+        }
+        self.send(WebhookEvent::FileFailed {
+            task_id: self.task_id.clone(),
+            failure: failed.clone(),
+        });
+    }
+
+    // Post the summary and wait until every post went out or gave up
+    pub(crate) fn run_finished(&mut self, summary: RunSummary) {
+        let wanted = match self.notify_on.as_str() {
+            "failure" => summary.failed > 0,
+            "success" => summary.failed == 0,
+            _ => true,
+        };
+        if wanted {
+            self.send(WebhookEvent::RunFinished(summary));
+        }
+        drop(self.sender.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+
+    fn send(&self, event: WebhookEvent) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(event);
+        }
+    }
+}
+
+fn post_event(client: &reqwest::blocking::Client, url: &str, event: &WebhookEvent) {
+    for attempt in 1..=WEBHOOK_ATTEMPTS {
+        let result = client
+            .post(url)
+            .json(event)
+            .send()
+            .map_err(|e| e.without_url().to_string())
+            .and_then(|response| match response.status() {
+                status if status.is_success() => Ok(()),
+                status => Err(format!("HTTP {}", status)),
+            });
+        match result {
+            Ok(()) => return,
+            Err(e) if attempt < WEBHOOK_ATTEMPTS => {
+                warn!(
+                    "Webhook {} failed ({}), retrying ({}/{})",
+                    redact_url(url),
+                    e,
+                    attempt,
+                    WEBHOOK_ATTEMPTS
+                );
+                // 1, then 2 seconds
+                thread::sleep(Duration::from_secs(1 << (attempt - 1)));
+            }
+            Err(e) => error!(
+                "Webhook {} failed after {} attempts: {}",
+                redact_url(url),
+                WEBHOOK_ATTEMPTS,
+                e
+            ),
+        }
+    }
+}
+
+// The scheme and host of url for the logs, the user info, path and query of webhook urls often
+// hold the token: https://hooks.example.com/services/T0/B0/xyz is https://hooks.example.com/***
+pub fn redact_url(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (format!("{}://", scheme), rest),
+        None => (String::new(), url),
+    };
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..end];
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    if end < rest.len() || host.len() < authority.len() {
+        format!("{}{}/***", scheme, host)
+    } else {
+        format!("{}{}", scheme, host)
+    }
+}
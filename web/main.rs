@@ -17,16 +17,11 @@ extern crate serde_derive;
 // The ffmpeg conversion process should be able to be stopped and resumed at any time, and should be able to be run in parallel
 // The ffmpeg conversion process should be able to output the important information of the running process, such as ETA, bitrate, etc
 
-use indicatif::MultiProgress;
-use indicatif::{ProgressBar, ProgressStyle};
+use clap::Parser;
+use log::{error, info};
 use rocket::futures::io;
-use rocket::tokio::time::Instant;
-use rusqlite::{Connection, Result};
-use std::collections::HashMap;
-use std::fs::metadata;
-use std::path::Path;
-use std::str;
-use std::vec;
+use rusqlite::Result;
+use std::collections::HashSet;
 use std::{string::String, vec::Vec};
 
 use rocket::http::ContentType;
@@ -38,6 +33,9 @@ use std::path::PathBuf;
 
 mod routes; // routes.rs
 
+#[cfg(test)]
+mod tests;
+
 // TODO
 // show search progress, spinner, eta, etc
 // highlight the (audio?) channel count output with colors
@@ -72,10 +70,184 @@ fn dist(file: PathBuf) -> Option<(ContentType, Cow<'static, [u8]>)> {
   Some((content_type, asset.data))
 }
 
+// Command line options of the web server
+#[derive(Parser, Debug)]
+#[clap(name = "TransRustica web")]
+struct WebArgs {
+    /// Path of the sqlite database
+    #[clap(long, default_value = "data.db")]
+    db_path: String,
+
+    /// Local time (HH:MM) of the nightly database maintenance, the one of cli --db-maintain, off disables it
+    #[clap(long, default_value = "04:00")]
+    maintain_at: String,
+
+    /// Percent of free pages from which the nightly maintenance runs VACUUM
+    #[clap(long, default_value = "10.0")]
+    vacuum_threshold: f64,
+
+    /// Folder whose encoded outputs /files/<task_id>/output and thumb.jpg serve for a preview, can be repeated, without it nothing is served
+    #[clap(long)]
+    serve_root: Vec<String>,
+}
+
 #[tokio::main]
 async fn main() {
-    // Launch the server
+    let web_args = WebArgs::parse();
+    init_logging("info", &None);
+    if let Err(e) = init_db(&web_args.db_path) {
+        error!("Failed to open database {}: {}", web_args.db_path, e);
+        std::process::exit(1);
+    }
+    // detect the ffmpeg capabilities once, transcode tasks and /progress reuse them
+    ffmpeg_capabilities();
+    // the hardware encoder tests take a few seconds, run them before the first /system request
+    std::thread::spawn(|| {
+        probe_hardware();
+    });
+    std::thread::spawn(queue_worker);
+    routes::set_serve_roots(&web_args.serve_root);
+    if web_args.maintain_at != "off" {
+        if let Err(e) = until_time_of_day(&web_args.maintain_at) {
+            error!("Invalid --maintain-at {}", e);
+            std::process::exit(1);
+        }
+        let (at, vacuum_threshold) = (web_args.maintain_at.clone(), web_args.vacuum_threshold);
+        std::thread::spawn(move || maintenance_worker(&at, vacuum_threshold));
+    }
+
+    // Launch the server, it returns on ctrl-C
     rocket().launch().await.unwrap();
+
+    // the transcode tasks run on their own threads, stop their ffmpeg/ab-av1 before exiting
+    stop_children();
+    cancel_running_tasks();
+    release_run_locks();
+}
+
+// How often the queue worker looks at the queue while it has nothing to start
+const QUEUE_POLL: std::time::Duration = std::time::Duration::from_secs(5);
+
+// Start the queued entries one at a time, highest priority first and within their schedule, while
+// no other transcode task runs
+fn queue_worker() {
+    match db().queue_requeue_running() {
+        Ok(0) => {}
+        Ok(count) => info!(
+            "{} queue entries were running when the server stopped, queued again",
+            count
+        ),
+        Err(e) => error!("Failed to requeue the running queue entries: {}", e),
+    }
+    // the entries waiting for a run that locked their input, logged once
+    let mut waiting: HashSet<i64> = HashSet::new();
+    loop {
+        if any_task_running() {
+            std::thread::sleep(QUEUE_POLL);
+            continue;
+        }
+        let mut entries = match get_all_from_db_queue() {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read the queue: {}", e);
+                std::thread::sleep(QUEUE_POLL);
+                continue;
+            }
+        };
+        // a cli run on the same input would encode the same files, the entry waits for it
+        entries.retain(|entry| {
+            if entry.state != "queued" {
+                return true;
+            }
+            match lock_conflict(&entry.input_path) {
+                Ok(Some(conflict)) => {
+                    if waiting.insert(entry.id) {
+                        info!("Queue entry {} waits: {}", entry.id, conflict);
+                    }
+                    false
+                }
+                Ok(None) => {
+                    waiting.remove(&entry.id);
+                    true
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to check the locks of queue entry {}: {}",
+                        entry.id, e
+                    );
+                    false
+                }
+            }
+        });
+        match next_queue_entry(&entries) {
+            QueueNext::Start(index) => run_queue_entry(&entries[index]),
+            // wake up when the window opens, or sooner for entries added or changed meanwhile
+            QueueNext::Wait(until_open) => std::thread::sleep(until_open.min(QUEUE_POLL)),
+            QueueNext::Empty => std::thread::sleep(QUEUE_POLL),
+        }
+    }
+}
+
+// Maintain the database every night at --maintain-at, once the running task finished
+fn maintenance_worker(at: &str, vacuum_threshold: f64) {
+    loop {
+        match until_time_of_day(at) {
+            Ok(wait) => std::thread::sleep(wait),
+            Err(e) => {
+                error!("Invalid --maintain-at {}", e);
+                return;
+            }
+        }
+        while any_task_running() {
+            std::thread::sleep(QUEUE_POLL);
+        }
+        info!("Nightly database maintenance");
+        match db().maintain(vacuum_threshold) {
+            Ok(report) => log_maintenance(&report, vacuum_threshold),
+            Err(e) => error!("Failed to maintain the database: {}", e),
+        }
+    }
+}
+
+// Run a queue entry as a task, it stays in the queue as running until the task ends
+fn run_queue_entry(entry: &QueueEntry) {
+    let task_id = new_task_id();
+    let task = Task::new(
+        &task_id,
+        &[entry.input_path.clone()],
+        &entry.output_path,
+        &entry.encoder,
+        entry.vmaf_target.parse().unwrap_or(0.0),
+    );
+    if let Err(e) = db().insert_task(&task) {
+        error!("Failed to store task {}: {}", task_id, e);
+    }
+    if let Err(e) = db().queue_set_running(entry.id, &task_id) {
+        error!("Failed to mark queue entry {} as running: {}", entry.id, e);
+    }
+    routes::push_task_id(&task_id);
+    info!(
+        "Starting queue entry {} ({}) as task {}",
+        entry.id, entry.input_path, task_id
+    );
+    if let Err(e) = run_from_web(
+        &task_id,
+        &entry.input_path,
+        &[],
+        &entry.output_path,
+        &entry.encoder,
+        &entry.vmaf_target,
+        &entry.vmaf_threads,
+        "",
+    ) {
+        error!("{}", e);
+        set_task_error(&task_id, &e.to_string());
+        set_task_state(&task_id, "failed");
+    }
+    // the task history keeps the result
+    if let Err(e) = db().queue_remove(&entry.id.to_string()) {
+        error!("Failed to remove queue entry {}: {}", entry.id, e);
+    }
 }
 
 #[get("/echo?stream", rank = 1)]
@@ -105,11 +277,37 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
                 routes::start_transcode,
                 routes::get_all_from_db_web,
                 routes::get_all_from_db_search_web,
+                routes::search_library_web,
+                routes::export_db_csv_web,
+                routes::duplicates_web,
+                routes::stats_web,
+                routes::encoder_stats_web,
+                routes::mark_duplicates_web,
+                routes::library_health_web,
                 routes::scan_path_web,
+                routes::scan_paths_web,
+                routes::scan_progress_web,
                 routes::add_to_queue,
                 routes::remove_from_queue,
                 routes::queue,
+                routes::queue_priority,
+                routes::queue_reorder,
                 routes::task_ids,
+                routes::tasks,
+                routes::task,
+                routes::task_log_web,
+                routes::task_samples_web,
+                routes::task_output_file,
+                routes::task_output_thumbnail,
+                routes::pause_task,
+                routes::resume_task,
+                routes::system,
+                routes::profiles,
+                routes::metrics,
+                routes::webhook_settings,
+                routes::set_webhook_settings,
+                routes::settings,
+                routes::set_settings,
                 echo_stream,
                 //routes::progress_id_post,
                 routes::progress_all,
@@ -119,949 +317,152 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
         //.mount("/static", FileServer::from("..\\..\\frontend"))
 }
 
-#[tokio::main]
-pub async fn main_loop(args_: shared::Args) {
-    let main_now = Instant::now();
-
-    // get the arguments from clap and store them in args
-    let mut args = args_;
-
-    let encoder = args.encoder.clone();
-    let preset_libaom_av1 = args.preset_libaom_av1.clone();
-    let preset_hevc_nvenc = args.preset_hevc_nvenc.clone();
-    let preset_hevc_qsv = args.preset_hevc_qsv.clone();
-    let preset_av1_qsv = args.preset_av1_qsv.clone();
-    let preset_libsvtav1 = args.preset_libsvtav1.clone();
-    let mut preset_x265 = args.preset_x265.clone();
-    let params_x265 = args.params_x265.clone();
-    let params_hevc_nvenc = args.params_hevc_nvenc.clone();
-    let params_hevc_qsv = args.params_hevc_qsv.clone();
-    let params_av1_qsv = args.params_av1_qsv.clone();
-    let params_libsvtav1 = args.params_libsvtav1.clone();
-    let verbose = args.verbose.clone();
-    let task_id = args.task_id.clone();
-
-    if verbose {
-        args.verbose = true;
-    } else {
-        args.verbose = false;
-    }
-
-    match encoder.as_str() {
-        "libx265" => {
-            args.encoder = "libx265".to_string();
-        }
-        "av1" => {
-            args.encoder = "libaom-av1".to_string();
-        }
-        "libsvtav1" => {
-            args.encoder = "libsvtav1".to_string();
-        }
-        "hevc_nvenc" => {
-            args.encoder = "hevc_nvenc".to_string();
-        }
-        "hevc_qsv" => {
-            args.encoder = "hevc_qsv".to_string();
-        }
-        "av1_qsv" => {
-            args.encoder = "av1_qsv".to_string();
-        }
-        _ => {
-            println!("{} is not a valid encoder!", encoder);
-            std::process::exit(1);
-        }
-    }
-
-    // set preset
-    if encoder == "av1" {
-        preset_x265 = preset_libaom_av1;
-    } else if encoder == "hevc_nvenc" {
-        preset_x265 = preset_hevc_nvenc;
-    } else if encoder == "hevc_qsv" {
-        preset_x265 = preset_hevc_qsv;
-    } else if encoder == "av1_qsv" {
-        preset_x265 = preset_av1_qsv;
-    } else if encoder == "libsvtav1" {
-        preset_x265 = preset_libsvtav1;
-    }
-
-    // set params
-    if encoder == "libx265" {
-        args.params_x265 = params_x265;
-    } else if encoder == "hevc_nvenc" {
-        args.params_x265 = params_hevc_nvenc;
-    } else if encoder == "hevc_qsv" {
-        args.params_x265 = params_hevc_qsv;
-    } else if encoder == "av1_qsv" {
-        args.params_x265 = params_av1_qsv;
-    } else if encoder == "libsvtav1" {
-        args.params_x265 = params_libsvtav1;
-    }
+// Besides the terminal bars, publish the file being encoded so /progress switches to it
+// before ffmpeg reports its first frame
+struct TaskProgress {
+    task_id: String,
+}
 
-    // if binary 'ab-av1' is not in the path, exit
-    if !std::path::Path::new("ab-av1.exe").exists() {
-        println!("Binary 'ab-av1.exe' not found in current path!");
-        println!("Searching for ab-av1.exe in system path...");
-        // search for binary in system path
-        let output = std::process::Command::new("where")
-            .arg("ab-av1.exe")
-            .output();
-        match output {
-            Ok(output) => {
-                println!(
-                    "ab-av1.exe found in: {}",
-                    String::from_utf8_lossy(&output.stdout)
-                );
-            }
-            Err(e) => {
-                println!("Failed to execute process: {}", e);
-            }
-        }
+impl ProgressSink for TaskProgress {
+    fn file_started(&mut self, job: &FileJob) {
+        *WEB_TASK_ID_STATIC.lock().unwrap() = self.task_id.clone();
+        *WEB_CURRENT_FILE_STATIC.lock().unwrap() = job.current_file_count;
+        *WEB_TOTAL_FILES_STATIC.lock().unwrap() = job.total_files as u64;
+        *WEB_CURRENT_FILE_NAME_STATIC.lock().unwrap() = job.file.clone();
+        *WEB_CURRENT_FRAME_STATIC.lock().unwrap() = 0;
     }
+}
 
-    let mut current_file_count = 0;
-    let mut total_files: i32;
-
-    let md = metadata(Path::new(&args.inputpath)).unwrap();
-    // Check if input is a directory, if yes, check how many video files are in it, and process the ones that are smaller than the given resolution
-    if md.is_dir() {
-        let mut count;
-        let db_count;
-        let db_count_added;
-        let walk_count: u64 = walk_count(&args.inputpath) as u64;
-        let files_bar = ProgressBar::new(walk_count);
-        let files_style =
-            "[file][{elapsed_precise}] [{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} [analyzed files] eta: {eta:<7}";
-        files_bar.set_style(ProgressStyle::default_bar().template(files_style).unwrap());
-
-        let vector_files = walk_files(&args.inputpath);
-
-        let result = add_to_db(vector_files.clone(), files_bar.clone()).unwrap();
-
-        // remove items from db that don't exists anymore, for the given folder and it's subfolders
-        remove_from_db_folder(&args.inputpath).unwrap();
-
-        // get the counters from the add_to_db function
-        let counters = result.0;
-
-        let to_process = result.1;
-        // get the vector of files to process
-        let mut vector_files_to_process = to_process.lock().unwrap().clone();
-
-        // count, db_count, db_count_added, db_count_skipped
-        count = format!("{:?}", counters[0]).parse::<i32>().unwrap();
-        db_count = format!("{:?}", counters[1]).parse::<u64>().unwrap();
-        db_count_added = format!("{:?}", counters[2]).parse::<u64>().unwrap();
-
-        if vector_files_to_process.is_empty() {
-            let conn = Connection::open("data.db").unwrap();
-            let input = args.inputpath.clone();
-            let mut stmt = conn
-                .prepare(
-                    "SELECT * FROM video_info WHERE (status = 'processing' OR status LIKE '%pending%') AND filepath LIKE ?"
-                )
-                .unwrap();
-            let mut rows = stmt.query(&[&format!("%{}%", input)]).unwrap();
-            while let Some(row) = rows.next().unwrap() {
-                vector_files_to_process.push(row.get(2).unwrap());
-            }
-        }
-
-        if count == 0 && !vector_files_to_process.is_empty() {
-            count = vector_files_to_process.len() as i32;
-            current_file_count = db_count - (vector_files_to_process.len() as u64);
-        }
-
-        files_bar.finish_and_clear();
-        println!(
-            "Added {} files to the database ({} already present)",
-            db_count_added, db_count
-        );
-
-        // print how many files are to be processed
-        println!("Processing {} files...", count);
-
-        // count the total number of frames to be processed
-        let mut total_frames: u64 = 0;
-        let frame_count_progress_bar = ProgressBar::new(vector_files_to_process.len() as u64);
-        let frame_count_progress_bar_style = ProgressStyle::default_bar().template(
-            "[frmcnt][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
-        );
-        frame_count_progress_bar.set_style(frame_count_progress_bar_style.unwrap());
-
-        for file in vector_files_to_process.clone() {
-            let frame_count = get_framecount_tag(&file).unwrap_or_else(|_| {
-                get_framecount_metadata(&file).unwrap_or_else(|_| {
-                    get_framecount_ffmpeg(&file)
-                        .unwrap_or_else(|_| get_framecount(&file).unwrap_or_else(|_| 0.0))
-                })
-            });
-            total_frames = total_frames + (frame_count as u64);
-            frame_count_progress_bar.inc(1);
-        }
-
-        frame_count_progress_bar.finish();
-
-        // Print the total number of frames to be processed, within a total number of files
-        println!(
-            "Total number of frames to be processed: {} in {} files",
-            total_frames, count
-        );
-
-        // create a vector of all the files to process, with their frame count
-        let mut vector_files_to_process_frame_count: Vec<(String, u64)> = Vec::new();
-        // add entry of 0
-        vector_files_to_process_frame_count.push(("".to_string(), 0));
-        for file in vector_files_to_process.clone() {
-            let frame_count = get_framecount_tag(&file).unwrap_or_else(|_| {
-                get_framecount_metadata(&file).unwrap_or_else(|_| {
-                    get_framecount_ffmpeg(&file)
-                        .unwrap_or_else(|_| get_framecount(&file).unwrap_or_else(|_| 0.0))
-                })
-            });
-            vector_files_to_process_frame_count.push((file, frame_count as u64));
-        }
-
-        // setup progress bar and show count for each file being processed
-        let total_style = ProgressStyle::default_bar().template(
-            "[frames][{elapsed_precise}] [{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
-        );
-        let transcode_style = ProgressStyle::default_bar().template(
-            "[ffmpeg][{elapsed_precise}] [{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
-        );
-        let info_style = ProgressStyle::default_bar().template("[info][{msg}]");
-        let codec_style = ProgressStyle::default_bar().template("[codec][{msg}]");
-        let file_bar_style = ProgressStyle::default_bar().template("[file]{msg}");
-        let vmaf_bar_style =
-            ProgressStyle::default_bar().template("[vmaf][{elapsed_precise}][{msg}]");
-        let m = MultiProgress::new();
-        let file_bar = m.add(ProgressBar::new(0));
-        file_bar.set_style(file_bar_style.unwrap());
-        let total_bar = m.add(ProgressBar::new(total_frames));
-        total_bar.set_style(total_style.unwrap());
-        let transcode_bar = m.add(ProgressBar::new(0));
-        transcode_bar.set_style(transcode_style.unwrap());
-        let info_bar = m.add(ProgressBar::new(0));
-        info_bar.set_style(info_style.unwrap());
-        let codec_bar = m.add(ProgressBar::new(0));
-        codec_bar.set_style(codec_style.unwrap());
-        let vmaf_bar = m.add(ProgressBar::new(0));
-        vmaf_bar.set_style(vmaf_bar_style.unwrap());
-
-        for file in vector_files_to_process.clone() {
-            current_file_count = current_file_count + 1;
-            total_files = vector_files_to_process.len() as i32;
-
-            // print the current file being processed
-            println!("Processing file {} of {}...", current_file_count, count);
-
-            /*             // Check status of file in database
-            let conn = Connection::open("data.db").unwrap();
-            let mut stmt = conn
-                .prepare("SELECT * FROM video_info WHERE filepath = ?")
-                .unwrap();
-            let mut rows = stmt.query(&[&file]).unwrap();
-            let mut status = String::new();
-            while let Some(row) = rows.next().unwrap() {
-                status = row.get(14).unwrap();
-            } */
-
-            args.inputpath = file.clone();
-            args.inputpath = absolute_path(file.clone());
-
-            /*             // update status in sqlite database 'data.db' to processing for this file where filepaths match the current file
-            let conn = Connection::open("data.db").unwrap();
-            conn.execute(
-                "UPDATE video_info SET status = 'processing' WHERE filepath = ?",
-                &[&args.inputpath],
-            )
-            .unwrap(); */
-
-            let _vmaf = &args.vmaf;
-            let _encoder = &args.encoder;
-            let _params_x265 = &args.params_x265;
-            let _pix_fmt = &args.pix_fmt;
-            let _preset_x265 = &preset_x265;
-            let _max_crf = &args.max_crf;
-            let _sample_every = &args.sample_every;
-            let _vmaf_threads = &args.vmaf_threads;
-            let _verbose = &args.verbose;
-            let _mode = &args.mode;
-
-            if _mode == "default" {
-                println!("Running default ab-av1...");
-
-                // TEMP TODO place this in a function
-                // For each audio track in audio_details, check if it's codec is aac or opus, if not, convert it to opus wit the same channel count and channel layout
-                // Only process if codex is: flac, ac3, eac3, dts or truehd
-                // Use -c:a libopus -b:a 128k
-                // 128 kbps is recommended for quality stereo music, for channel_count == 2.
-                // 256 kbps for 5.1 surround, for channel_count > 2 but less than 6.
-                // 450 kbps for 7.1 surround sound, for channel_count > 6.
-
-                let audio_details = get_audio_details(&file).unwrap();
-                let video_details = get_video_details(&file).unwrap();
-                let audio_details_clone = audio_details.clone();
-                let mut _audio_arg: String = "".to_string();
-                let mut vec_audio_args: Vec<(usize, String, String)> = Vec::new();
-                let mut vec_video_args: Vec<(usize, String, String, String)> = Vec::new();
-                let mut original_audio_codec: String = "".to_string();
-
-                let mut i = 0;
-                for (audio_codec, mut audio_channel_count) in audio_details {
-                    if &audio_codec != "aac" && audio_codec != "opus" && audio_codec != "mp3" {
-                        if audio_channel_count == "2" {
-                            _audio_arg =
-                                format!("-c:a:{} libopus -b:a 128k -ac {}", i, audio_channel_count);
-                            original_audio_codec = audio_details_clone[i].0.clone();
-                            vec_audio_args.push((
-                                i,
-                                _audio_arg.clone(),
-                                original_audio_codec.clone(),
-                            ));
-                        } else if audio_channel_count == "6" {
-                            audio_channel_count = "5.1".to_string();
-                            _audio_arg = format!(
-                                //"-c:a:{} libopus -af channelmap=channel_layout={} -b:a 256k",
-                                "-c:a:{} libopus -channel_layout:a:{} {} -b:a 256k",
-                                //i, audio_channel_count
-                                i,
-                                i,
-                                audio_channel_count
-                            );
-                            original_audio_codec = audio_details_clone[i].0.clone();
-                            vec_audio_args.push((
-                                i,
-                                _audio_arg.clone(),
-                                original_audio_codec.clone(),
-                            ));
-                        } else if audio_channel_count == "8" {
-                            audio_channel_count = "7.1".to_string();
-                            _audio_arg = format!(
-                                //"-c:a:{} libopus -af channelmap=channel_layout={} -b:a 450k",
-                                //i, audio_channel_count
-                                "-c:a:{} libopus -channel_layout:a:{} {} -b:a 450k",
-                                i, i, audio_channel_count
-                            );
-                            original_audio_codec = audio_details_clone[i].0.clone();
-                            vec_audio_args.push((
-                                i,
-                                _audio_arg.clone(),
-                                original_audio_codec.clone(),
-                            ));
-                        }
-                    } else {
-                        _audio_arg = "".to_string();
-                        original_audio_codec = audio_details_clone[i].0.clone();
-                        vec_audio_args.push((i, _audio_arg.clone(), original_audio_codec.clone()));
-                    }
-                    i = i + 1;
-                }
-
-                let mut j = 0;
-                for (video_codec, video_width, video_height) in video_details {
-                    let video_arg = format!("{}", video_codec.clone());
-                    vec_video_args.push((j, video_arg.clone(), video_width, video_height));
-                    j = j + 1;
-                }
-
-                // // if (size is over 600MB or) bitrate is over 3MB/s then add to db with status pending_video, otherwise add to db with status skipped
-                // // also if audio_codec is not aac or opus then add to db with status pending_audio, otherwise add to db with status skipped
-                // // if both video and audio are pending then add to db with status pending_all
-                let mut status = "pending_video";
-                let bitrate = get_bitrate(&file);
-                // Convert bitrate to f64
-                let bitrate = bitrate.parse::<f64>().unwrap();
-                let audio_codec = get_audio_details(&file).unwrap();
-                // get the first audio codec
-                let audio_codec = audio_codec[0].0.clone();
-                let _transcode_info = "".to_string();
-
-                if bitrate < 3000.0 {
-                    status = "skipped";
-                }
-                if bitrate > 3000.0 {
-                    status = "pending_video";
-                }
-                if audio_codec != "aac" && audio_codec != "opus" && audio_codec != "mp3" {
-                    status = "pending_audio";
-                }
-                if bitrate > 3000.0
-                    && audio_codec != "aac"
-                    && audio_codec != "opus"
-                    && audio_codec != "mp3"
-                {
-                    status = "pending_all";
-                }
-
-                /*                 // Get the status of the file from the database
-                let conn = Connection::open("data.db").unwrap();
-                let mut stmt = conn
-                    .prepare("SELECT * FROM video_info WHERE filepath = ?")
-                    .unwrap();
-                let mut rows = stmt.query(&[&file]).unwrap();
-                let mut status_db = String::new();
-                while let Some(row) = rows.next().unwrap() {
-                    status_db = row.get(14).unwrap();
-                }
-
-                let status: &str = &status_db; */
-
-                // Match status, if status is pending_video, transcode_info = "video", if status is pending_audio, transcode_info = "audio", if status is pending_all, transcode_info = "all"
-                let transcode_info = match status {
-                    "pending_video" => "video",
-                    "pending_audio" => "audio",
-                    "pending_all" => "all",
-                    _ => "",
-                };
-
-                if status == "pending_audio".to_string() {
-                    // set_output_folder function
-                    let final_output = set_output_folder_filename_audio(&file, &args.outputpath);
-
-                    run_ffmpeg_transcode_audio(
-                        &file,
-                        &final_output,
-                        &file_bar,
-                        &transcode_bar,
-                        &total_bar,
-                        &info_bar,
-                        &codec_bar,
-                        &total_files,
-                        &current_file_count,
-                        &vector_files_to_process_frame_count,
-                        &original_audio_codec,
-                        &transcode_info,
-                        &vec_audio_args,
-                        &vec_video_args,
-                        &task_id,
-                    );
-                } else {
-                    // run ab-av1.exe to find the best crf for the file
-                    let crf_search_result = run_ab_av1_crf_search(
-                        &file,
-                        &encoder,
-                        &preset_x265,
-                        _pix_fmt,
-                        *_vmaf,
-                        _max_crf,
-                        _sample_every,
-                        _vmaf_threads,
-                        *_verbose,
-                        &task_id,
-                        &current_file_count,
-                        &total_files,
-                    );
-
-                    let crf_search_result_unwrap = crf_search_result.unwrap();
-                    let output_final = crf_search_result_unwrap.clone();
-
-                    // set_output_folder function
-                    let final_output = set_output_folder_filename(
-                        &file,
-                        &encoder,
-                        &output_final.1,
-                        &output_final.0,
-                        &args.output_folder,
-                    );
-
-                    // run ffmpeg.exe to encode the file
-                    run_ffmpeg_transcode(
-                        &file,
-                        &encoder,
-                        &_params_x265,
-                        &preset_x265,
-                        _pix_fmt,
-                        &final_output,
-                        // use the result from run_ab_av1_crf_search function
-                        &output_final.0.to_string(),
-                        &file_bar,
-                        &transcode_bar,
-                        &total_bar,
-                        &info_bar,
-                        &codec_bar,
-                        &total_files,
-                        &current_file_count,
-                        &vector_files_to_process_frame_count,
-                        &output_final.1,
-                        &original_audio_codec,
-                        &transcode_info,
-                        &vec_audio_args,
-                        &vec_video_args,
-                        &task_id,
-                    );
-                }
-            } else if _mode == "chunked" {
-                println!("Running chunked...");
-                let scene_changes = run_ffmpeg_scene_change(&file, &args);
-                let scene_changes_clone = scene_changes.unwrap().clone();
-                let scene_changes_clone2 = scene_changes_clone.clone();
-
-                // for each scene in scene_changes run get_scene_size
-                // Create a vector of (scene_index, scene_size)
-                // Possibly add this to the vector of scene_changes
-                let scene_changes_clone = scene_changes_clone;
-                let mut scene_sizes: Vec<(i32, i32)> = Vec::new();
-                let mut scenes: Vec<(f32, f32)> = Vec::new();
-                {
-                    let scene_changes_locked = scene_changes_clone;
-                    for window in scene_changes_locked.windows(2) {
-                        scenes.push((window[0], window[1]));
-                    }
-                }
-
-                // Create a progress bar
-                let progress_bar = ProgressBar::new(scenes.len() as u64);
-                let progress_bar_style =
-                    "[scs][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]";
-                progress_bar.set_style(
-                    ProgressStyle::default_bar()
-                        .template(progress_bar_style)
-                        .unwrap(),
-                );
-
-                let mut scene_index = 0;
-                for (scene_start, scene_end) in &scenes {
-                    let ss = format_timecode(&scene_start);
-                    let to = format_timecode(&scene_end);
-                    let scene_size = get_scene_size(&file, &ss, &to);
-
-                    scene_sizes.push((scene_index, scene_size.unwrap()));
-                    scene_index += 1;
-                    progress_bar.inc(1);
-                }
-
-                let scene_changes_vec = scene_changes_clone2;
-
-                let _scene_changes = run_ffmpeg_extract_scene_changes_pipe_vmaf_target_threaded(
-                    &file,
-                    &scene_changes_vec,
-                    &scene_sizes,
-                    &args,
-                    &get_fps_f32(&file),
-                );
-
-                // TODO make conversion inside function, directly after calculation
-                // It should probably have it's own function and progress bar
-                let scene_changes = _scene_changes.unwrap();
-
-                let vmaf_f32 = args.vmaf as f32;
-                let mut closest_scores: HashMap<i32, (i32, f32, f32)> = HashMap::new();
-                for (scene_index, crf, vmaf_score) in &scene_changes {
-                    if let Some((_, _, stored_vmaf)) = closest_scores.get(scene_index) {
-                        let current_diff = (vmaf_score - &vmaf_f32).abs();
-                        let stored_diff = (stored_vmaf - &vmaf_f32).abs();
-
-                        if current_diff < stored_diff {
-                            closest_scores.insert(*scene_index, (*scene_index, *crf, *vmaf_score));
-                        }
-                    } else {
-                        closest_scores.insert(*scene_index, (*scene_index, *crf, *vmaf_score));
-                    }
-                }
-
-                // Convert hashmap back to a vector if needed
-                let mut closest_scores_vec: Vec<(i32, f32, f32)> =
-                    closest_scores.values().cloned().collect();
-                // Sort the vector by scene_index
-                closest_scores_vec.sort_by(|a, b| a.0.cmp(&b.0));
-                // Print all _scene_changes
-                for (scene_index, crf, vmaf_score) in &closest_scores_vec {
-                    println!("{} {} {}", scene_index, crf, vmaf_score);
-                }
-            } else {
-                println!("{} is not a valid mode!", _mode);
-                std::process::exit(1);
-            }
-        }
-
-        let elapsed = main_now.elapsed();
-        let seconds = elapsed.as_secs() % 60;
-        let minutes = (elapsed.as_secs() / 60) % 60;
-        let hours = elapsed.as_secs() / 60 / 60;
-        println!(
-            "done {} files in {}h:{}m:{}s",
-            count, hours, minutes, seconds
-        );
-    } else if md.is_file() {
-        // do find map_metadata_audio and map_metadata_subtitle using run_ffmpeg_map_metadata function
-        // run ab-av1.exe to find the best crf for the file
-        // set_output_folder function
-        // run ffmpeg.exe to encode the file
-
-        let file = args.inputpath.clone();
-        let _vmaf = &args.vmaf;
-        let _encoder = &args.encoder;
-        let _params_x265 = &args.params_x265;
-        let _pix_fmt = &args.pix_fmt;
-        let _preset_x265 = &preset_x265;
-        let _max_crf = &args.max_crf;
-        let _sample_every = &args.sample_every;
-        let _vmaf_threads = &args.vmaf_threads;
-        let _verbose = &args.verbose;
-        let _total_files = 1;
-        let _mode = &args.mode;
-
-        /*         // Check status of file in database
-        let conn = Connection::open("data.db").unwrap();
-        let mut stmt = conn
-            .prepare("SELECT * FROM video_info WHERE filepath = ?")
-            .unwrap();
-        let mut rows = stmt.query(&[&file]).unwrap();
-        let mut status = String::new();
-        while let Some(row) = rows.next().unwrap() {
-            status = row.get(15).unwrap();
-        } */
-
-        // TEMP TODO place this in a function
-        // For each audio track in audio_details, check if it's codec is aac or opus, if not, convert it to opus wit the same channel count and channel layout
-        // Only process if codex is: flac, ac3, eac3, dts or truehd
-        // Use -c:a libopus -b:a 128k
-        // 128 kbps is recommended for quality stereo music, for channel_count == 2.
-        // 256 kbps for 5.1 surround, for channel_count > 2 but less than 6.
-        // 450 kbps for 7.1 surround sound, for channel_count > 6.
-
-        let audio_details = get_audio_details(&file).unwrap();
-        let video_details = get_video_details(&file).unwrap();
-        let audio_details_clone = audio_details.clone();
-        let mut _audio_arg: String = "".to_string();
-        let mut vec_audio_args: Vec<(usize, String, String)> = Vec::new();
-        let mut vec_video_args: Vec<(usize, String, String, String)> = Vec::new();
-        let mut original_audio_codec: String = "".to_string();
-
-        let mut i = 0;
-        for (audio_codec, mut audio_channel_count) in audio_details {
-            if &audio_codec != "aac" && audio_codec != "opus" && audio_codec != "mp3" {
-                if audio_channel_count == "2" {
-                    _audio_arg =
-                        format!("-c:a:{} libopus -b:a 128k -ac {}", i, audio_channel_count);
-                    original_audio_codec = audio_details_clone[i].0.clone();
-                    vec_audio_args.push((i, _audio_arg.clone(), original_audio_codec.clone()));
-                } else if audio_channel_count == "6" {
-                    audio_channel_count = "5.1".to_string();
-                    _audio_arg = format!(
-                        //"-c:a:{} libopus -af channelmap=channel_layout={} -b:a 256k",
-                        //i, audio_channel_count
-                        "-c:a:{} libopus -channel_layout:a:{} {} -b:a 256k",
-                        i, i, audio_channel_count
-                    );
-                    original_audio_codec = audio_details_clone[i].0.clone();
-                    vec_audio_args.push((i, _audio_arg.clone(), original_audio_codec.clone()));
-                } else if audio_channel_count == "8" {
-                    audio_channel_count = "7.1".to_string();
-                    _audio_arg = format!(
-                        //"-c:a:{} libopus -af channelmap=channel_layout={} -b:a 450k",
-                        //i, audio_channel_count
-                        "-c:a:{} libopus -channel_layout:a:{} {} -b:a 450k",
-                        i, i, audio_channel_count
-                    );
-                    original_audio_codec = audio_details_clone[i].0.clone();
-                    vec_audio_args.push((i, _audio_arg.clone(), original_audio_codec.clone()));
-                }
-            } else {
-                _audio_arg = "".to_string();
-                original_audio_codec = audio_details_clone[i].0.clone();
-                vec_audio_args.push((i, _audio_arg.clone(), original_audio_codec.clone()));
-            }
-            i = i + 1;
-        }
-
-        let mut j = 0;
-        for (video_codec, video_width, video_height) in video_details {
-            let video_arg = format!("{}", video_codec.clone());
-            vec_video_args.push((j, video_arg.clone(), video_width, video_height));
-            j = j + 1;
-        }
-
-        if _mode == "default" {
-            println!("Running default ab-av1...");
-
-            // Get the number of frames in the file
-            let total_frames = get_framecount_tag(&file).unwrap_or_else(|_| {
-                get_framecount_metadata(&file).unwrap_or_else(|_| {
-                    get_framecount_ffmpeg(&file)
-                        .unwrap_or_else(|_| get_framecount(&file).unwrap_or_else(|_| 0.0))
-                })
-            }) as u64;
-
-            // setup progress bar and show count for each file being processed
-            let total_style = ProgressStyle::default_bar().template(
-                "[frames][{elapsed_precise}] [{wide_bar:.green/white}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
-            );
-            let transcode_style = ProgressStyle::default_bar().template(
-                "[ffmpeg][{elapsed_precise}] [{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]"
-            );
-            let info_style = ProgressStyle::default_bar().template("[info][{msg}]");
-            let codec_style = ProgressStyle::default_bar().template("[codec][{msg}]");
-            let file_bar_style = ProgressStyle::default_bar().template("[file]{msg}");
-            let vmaf_bar_style =
-                ProgressStyle::default_bar().template("[vmaf][{elapsed_precise}][{msg}]");
-            let m = MultiProgress::new();
-            let file_bar = m.add(ProgressBar::new(0));
-            file_bar.set_style(file_bar_style.unwrap());
-            let total_bar = m.add(ProgressBar::new(total_frames));
-            total_bar.set_style(total_style.unwrap());
-            let transcode_bar = m.add(ProgressBar::new(0));
-            transcode_bar.set_style(transcode_style.unwrap());
-            let info_bar = m.add(ProgressBar::new(0));
-            info_bar.set_style(info_style.unwrap());
-            let codec_bar = m.add(ProgressBar::new(0));
-            codec_bar.set_style(codec_style.unwrap());
-            let vmaf_bar = m.add(ProgressBar::new(0));
-            vmaf_bar.set_style(vmaf_bar_style.unwrap());
-
-            // // if (size is over 600MB or) bitrate is over 3MB/s then add to db with status pending_video, otherwise add to db with status skipped
-            // // also if audio_codec is not aac or opus then add to db with status pending_audio, otherwise add to db with status skipped
-            // // if both video and audio are pending then add to db with status pending_all
-            let mut status = "pending_video";
-            let bitrate = get_bitrate(&file);
-            // Convert bitrate to f64
-            let bitrate = bitrate.parse::<f64>().unwrap();
-            let audio_codec = get_audio_details(&file).unwrap();
-            // get the first audio codec
-            let audio_codec = audio_codec[0].0.clone();
-            let _transcode_info = "".to_string();
-
-            if bitrate < 3000.0 {
-                status = "skipped";
-            }
-            if bitrate > 3000.0 {
-                status = "pending_video";
-            }
-            if audio_codec != "aac" && audio_codec != "opus" && audio_codec != "mp3" {
-                status = "pending_audio";
-            }
-            if bitrate > 3000.0
-                && audio_codec != "aac"
-                && audio_codec != "opus"
-                && audio_codec != "mp3"
-            {
-                status = "pending_all";
-            }
-
-            /*             // Get the status of the file from the database
-            let conn = Connection::open("data.db").unwrap();
-            let mut stmt = conn
-                .prepare("SELECT * FROM video_info WHERE filepath = ?")
-                .unwrap();
-            let mut rows = stmt.query(&[&file]).unwrap();
-            let mut status_db = String::new();
-            while let Some(row) = rows.next().unwrap() {
-                status_db = row.get(14).unwrap();
-            }
-
-            let status: &str = &status_db; */
-
-            // Match status, if status is pending_video, transcode_info = "video", if status is pending_audio, transcode_info = "audio", if status is pending_all, transcode_info = "all"
-            let transcode_info = match status {
-                "pending_video" => "video",
-                "pending_audio" => "audio",
-                "pending_all" => "all",
-                _ => "",
-            };
-
-            if status == "pending_audio".to_string() {
-                // set_output_folder function
-                let final_output = set_output_folder_filename_audio(&file, &args.outputpath);
-
-                run_ffmpeg_transcode_audio(
-                    &file,
-                    &final_output,
-                    &file_bar,
-                    &transcode_bar,
-                    &total_bar,
-                    &info_bar,
-                    &codec_bar,
-                    &_total_files,
-                    &current_file_count,
-                    &vec![], // empty vector
-                    &original_audio_codec,
-                    &transcode_info,
-                    &vec_audio_args,
-                    &vec_video_args,
-                    &task_id,
-                );
-            } else {
-                let current_file_count = 1;
-                let total_files = 1;
-
-                // run ab-av1.exe to find the best crf for the file
-                let crf_search_result = run_ab_av1_crf_search(
-                    &file,
-                    &encoder,
-                    &preset_x265,
-                    _pix_fmt,
-                    *_vmaf,
-                    _max_crf,
-                    _sample_every,
-                    _vmaf_threads,
-                    *_verbose,
-                    &args.task_id,
-                    &current_file_count,
-                    &total_files,
-                );
-
-                let crf_search_result_unwrap = crf_search_result.unwrap();
-                let output_final = crf_search_result_unwrap.clone();
-
-                // set_output_folder function
-                let final_output = set_output_folder_filename(
-                    &file,
-                    &encoder,
-                    &output_final.1,
-                    &output_final.0,
-                    &args.output_folder,
-                );
-
-                // TEMP Print final_output
-                println!("final_output: {}", final_output);
-
-                // run ffmpeg.exe to encode the file
-                run_ffmpeg_transcode(
-                    &file,
-                    &encoder,
-                    &_params_x265,
-                    &preset_x265,
-                    _pix_fmt,
-                    &final_output,
-                    // use the result from run_ab_av1_crf_search function
-                    &output_final.0.to_string(),
-                    &file_bar,
-                    &transcode_bar,
-                    &total_bar,
-                    &info_bar,
-                    &codec_bar,
-                    &_total_files,
-                    &current_file_count,
-                    &vec![], // empty vector
-                    &output_final.1,
-                    &original_audio_codec,
-                    &transcode_info,
-                    &vec_audio_args,
-                    &vec_video_args,
-                    &task_id,
-                );
-            }
-        } else if _mode == "chunked" {
-            println!("Running chunked...");
-            let scene_changes = shared::run_ffmpeg_scene_change(&file, &args);
-            let scene_changes_clone = scene_changes.unwrap().clone();
-            let scene_changes_clone2 = scene_changes_clone.clone();
-
-            // for each scene in scene_changes run get_scene_size
-            // Create a vector of (scene_index, scene_size)
-            // Possibly add this to the vector of scene_changes
-            let scene_changes_clone = scene_changes_clone;
-            let mut scene_sizes: Vec<(i32, i32)> = Vec::new();
-            let mut scenes: Vec<(f32, f32)> = Vec::new();
-            {
-                let scene_changes_locked = scene_changes_clone;
-                for window in scene_changes_locked.windows(2) {
-                    scenes.push((window[0], window[1]));
-                }
-            }
-
-            // Create a progress bar
-            let progress_bar = ProgressBar::new(scenes.len() as u64);
-            let progress_bar_style =
-                "[scs][{elapsed_precise}][{wide_bar:.cyan/blue}] {percent:3} {pos:>7}/{len:7} [ETA: {eta:<3}]";
-            progress_bar.set_style(
-                ProgressStyle::default_bar()
-                    .template(progress_bar_style)
-                    .unwrap(),
-            );
-
-            let mut scene_index = 0;
-            for (scene_start, scene_end) in &scenes {
-                let ss = format_timecode(&scene_start);
-                let to = format_timecode(&scene_end);
-                let scene_size = shared::get_scene_size(&file, &ss, &to);
-
-                scene_sizes.push((scene_index, scene_size.unwrap()));
-                scene_index += 1;
-                progress_bar.inc(1);
-            }
-
-            let scene_changes_vec = scene_changes_clone2;
-
-            let _scene_changes = run_ffmpeg_extract_scene_changes_pipe_vmaf_target_threaded(
-                &file,
-                &scene_changes_vec,
-                &scene_sizes,
-                &args,
-                &get_fps_f32(&file),
-            );
-
-            // TODO make conversion inside function, directly after calculation
-            // It should probably have it's own function and progress bar
-            let scene_changes = _scene_changes.unwrap();
-
-            let vmaf_f32 = args.vmaf as f32;
-            let mut closest_scores: HashMap<i32, (i32, f32, f32)> = HashMap::new();
-            for (scene_index, crf, vmaf_score) in &scene_changes {
-                if let Some((_, _, stored_vmaf)) = closest_scores.get(scene_index) {
-                    let current_diff = (vmaf_score - &vmaf_f32).abs();
-                    let stored_diff = (stored_vmaf - &vmaf_f32).abs();
-
-                    if current_diff < stored_diff {
-                        closest_scores.insert(*scene_index, (*scene_index, *crf, *vmaf_score));
-                    }
-                } else {
-                    closest_scores.insert(*scene_index, (*scene_index, *crf, *vmaf_score));
-                }
-            }
-
-            // Convert hashmap back to a vector if needed
-            let mut closest_scores_vec: Vec<(i32, f32, f32)> =
-                closest_scores.values().cloned().collect();
-            // Sort the vector by scene_index
-            closest_scores_vec.sort_by(|a, b| a.0.cmp(&b.0));
-            // Print all _scene_changes
-            for (scene_index, crf, vmaf_score) in &closest_scores_vec {
-                println!("{} {} {}", scene_index, crf, vmaf_score);
-            }
-        } else {
-            println!("{} is not a valid mode!", _mode);
-            std::process::exit(1);
-        }
+#[tokio::main]
+pub async fn main_loop(args: shared::Args) {
+    let task_id = args.task_id.clone();
+    let mut progress = TaskProgress {
+        task_id: args.task_id.clone(),
+    };
+    let result = Pipeline::new(args).and_then(|mut pipeline| {
+        pipeline.prepare()?;
+        pipeline.run(&mut progress)?;
+        pipeline.report_failures();
+        Ok(())
+    });
+    if let Err(e) = result {
+        error!("{}", e);
+        set_task_error(&task_id, &e);
+        set_task_state(&task_id, "failed");
     }
-
-    let elapsed = main_now.elapsed();
-    let seconds = elapsed.as_secs() % 60;
-    let minutes = (elapsed.as_secs() / 60) % 60;
-    let hours = elapsed.as_secs() / 60 / 60;
-    println!(
-        "done {} files in {}h:{}m:{}s",
-        current_file_count, hours, minutes, seconds
-    );
 }
 
 pub fn run_from_web(
     web_uuid: &str,
     web_input_path: &str,
+    web_input_paths: &[String],
     web_output_path: &str,
     web_encoder: &str,
     web_vmaf: &str,
     web_vmaf_threads: &str,
+    web_mode: &str,
 ) -> Result<(), io::Error> {
-    // convert web_vmaf to i32
-    let web_vmaf = web_vmaf.parse::<i32>().unwrap();
+    let args = web_task_args(
+        web_uuid,
+        web_input_path,
+        web_input_paths,
+        web_output_path,
+        web_encoder,
+        web_vmaf,
+        web_vmaf_threads,
+        web_mode,
+    )?;
+    main_loop(args);
+
+    Ok(())
+}
+
+// The Args of a task started from the web, the same -o of the video and the audio-only encodes
+#[allow(clippy::too_many_arguments)]
+fn web_task_args(
+    web_uuid: &str,
+    web_input_path: &str,
+    web_input_paths: &[String],
+    web_output_path: &str,
+    web_encoder: &str,
+    web_vmaf: &str,
+    web_vmaf_threads: &str,
+    web_mode: &str,
+) -> Result<Args, io::Error> {
+    let web_mode = if web_mode.is_empty() {
+        "default".to_string()
+    } else {
+        possible_mode_values(web_mode)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", e, web_mode)))?
+    };
+
+    // the compiled defaults, then the settings of PUT /settings, then the values of the request,
+    // an empty value keeps the setting
+    let mut defaults = web_default_args(web_uuid);
+    defaults.mode = web_mode;
+    let mut settings = stored_web_settings()
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    for (field, value) in [
+        ("outputpath", web_output_path),
+        ("encoder", web_encoder),
+        // 95 or 95.5
+        ("vmaf", web_vmaf),
+        ("vmaf_threads", web_vmaf_threads),
+    ] {
+        if !value.trim().is_empty() {
+            settings.push((field.to_string(), value.trim().to_string()));
+        }
+    }
+    let mut args = apply_web_settings(defaults, &settings).map_err(|errors| {
+        let errors: Vec<String> = errors
+            .iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect();
+        io::Error::new(io::ErrorKind::InvalidInput, errors.join(", "))
+    })?;
+    args.inputpath = if web_input_paths.is_empty() {
+        vec![web_input_path.to_string()]
+    } else {
+        web_input_paths.to_vec()
+    };
+    Ok(args)
+}
 
-    // set up args
-    let args = Args {
-        inputpath: web_input_path.to_string(),
-        outputpath: web_output_path.to_string(),
-        output_folder: web_output_path.to_string(),
-        encoder: web_encoder.to_string(),
-        vmaf: web_vmaf,
-        vmaf_threads: web_vmaf_threads.to_string(),
+// The Args of the tasks of the web server before the settings and the request, its presets and
+// params differ from the ones of the cli
+pub fn web_default_args(task_id: &str) -> Args {
+    Args {
+        inputpath: Vec::new(),
+        input_list: None,
+        outputpath: ".".to_string(),
+        output_folder: None,
+        encoder: "libx265".to_string(),
+        vmaf: 97.0,
+        vmaf_retry_step: 0.5,
+        no_adaptive_target: false,
+        low_quality_bpp: 0.05,
+        target_size: None,
+        renditions: None,
+        max_resolution: None,
+        vmaf_threads: String::new(),
+        scene_jobs: None,
+        probe_jobs: 4,
+        thread_queue_size: None,
+        scene_timeout: 3600,
         vmaf_pool: "mean".to_string(),
         vmaf_subsample: "1".to_string(),
         mode: "default".to_string(),
         verbose: false,
         pix_fmt: "yuv420p10le".to_string(),
+        keyint_seconds: 10.0,
+        min_keyint: None,
         max_crf: "28".to_string(),
         sample_every: "3m".to_string(),
+        crf_search: "ab-av1".to_string(),
+        min_duration: 60.0,
+        short_input_crf: 20.0,
+        skip_short: false,
+        trial_range: None,
         params_ab_av1: "x265-params=limit-sao,bframes=8,psy-rd=1,aq-mode=3".to_string(),
         params_x265: "-x265-params limit-sao:bframes=8:psy-rd=1:aq-mode=3".to_string(),
         preset_x265: "veryslow".to_string(),
@@ -1078,10 +479,97 @@ pub fn run_from_web(
         params_libaom_av1:
             "--cpu-used=3 --end-usage=q --cq-level=30 --tile-columns=2 --tile-rows=2".to_string(),
         scene_split_min: 2.0,
-        task_id: web_uuid.to_string(),
-    };
-
-    main_loop(args);
-
-    Ok(())
+        chunk_by: "scenes".to_string(),
+        fast_scene_threshold: 5.0,
+        uniform_scenes: false,
+        content_tune: "none".to_string(),
+        film_grain: None,
+        film_grain_denoise: None,
+        task_id: task_id.to_string(),
+        debug_log: None,
+        artifacts_dir: None,
+        legacy_artifacts: false,
+        clean_artifacts: None,
+        audio_codec: "opus".to_string(),
+        max_audio_bitrate: None,
+        audio_bitrate_stereo: None,
+        audio_bitrate_51: None,
+        audio_bitrate_71: None,
+        keep_audio_langs: Vec::new(),
+        keep_subs_langs: Vec::new(),
+        force_reencode: false,
+        force_lock_takeover: false,
+        lock_stale_after: DEFAULT_LOCK_STALE_AFTER.to_string(),
+        log_level: "info".to_string(),
+        log_file: None,
+        db_path: db().path().to_string(),
+        db_export: None,
+        db_export_format: "json".to_string(),
+        db_import: None,
+        db_duplicates: false,
+        mark_duplicates: false,
+        db_maintain: false,
+        vacuum_threshold: 10.0,
+        interactive: false,
+        include_manual_skips: false,
+        skip_if_no_savings: false,
+        estimate: false,
+        min_estimated_savings: None,
+        encode_jobs: None,
+        stream_concat: false,
+        sync_check: "fail".to_string(),
+        sync_max_drift: 50.0,
+        verify_vmaf: false,
+        verify_samples: 3,
+        verify_max_delta: 1.0,
+        fail_verification: false,
+        screenshots: None,
+        preserve_mtime: false,
+        copy_sidecars: false,
+        ingest_external_subs: false,
+        prefer_external_subs: false,
+        min_free_space: 5.0,
+        stage_dir: None,
+        strip_dovi: false,
+        discard_if_larger: false,
+        warn_if_larger: false,
+        max_output_ratio: 1.0,
+        tonemap: "off".to_string(),
+        burn_subs: None,
+        deinterlace: "auto".to_string(),
+        fallback_encoder: None,
+        gpu_index: None,
+        env: Vec::new(),
+        clean_env: false,
+        progress_format: "text".to_string(),
+        progress_interval: 250,
+        exclude: Vec::new(),
+        max_trash_fraction: 0.2,
+        force: false,
+        since: None,
+        resume_last: false,
+        resume: None,
+        watch: false,
+        watch_settle: 30,
+        settle_seconds: 30,
+        failures_json: None,
+        report_json: None,
+        mirror_structure: false,
+        verify_library: false,
+        quick: false,
+        verify_jobs: 2,
+        // the webhook of the settings, so queued tasks notify as well
+        notify_webhook: db().get_setting(WEBHOOK_URL_SETTING).ok().flatten(),
+        notify_on: db()
+            .get_setting(WEBHOOK_NOTIFY_ON_SETTING)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| "all".to_string()),
+        metrics_port: None,
+        profile: None,
+        save_profile: None,
+        list_profiles: false,
+        generate_test_media: None,
+        command: None,
+    }
 }
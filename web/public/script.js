@@ -78,6 +78,11 @@ function sendMessageToClient() {
     })
 }
 
+// /progress sends sizes in bytes
+function formatSize(bytes) {
+    return (bytes / 1024 / 1024).toFixed(2) + ' MB';
+}
+
 function fetchProgress() {
     fetch('/progress')
         .then(response => response.json())
@@ -91,10 +96,13 @@ function fetchProgress() {
                     <thead>
                         <tr>
                             <th>Task ID</th>
+                            <th>State</th>
+                            <th>Phase</th>
                             <th>FPS</th>
                             <th>Frame</th>
                             <th>Frames</th>
                             <th>Percentage</th>
+                            <th>ETA</th>
                             <th>Size</th>
                             <th>Current File Count</th>
                             <th>Total Files</th>
@@ -116,11 +124,14 @@ function fetchProgress() {
                         taskRow.id = `task-row-${data.id}`;
                         taskRow.innerHTML = `
                             <td><span id="id-${data.id}">${data.id}</span></td>
+                            <td><span id="state-${data.id}">${data.state}</span></td>
+                            <td><span id="phase-${data.id}">${formatPhase(data)}</span></td>
                             <td><span id="fps-${data.id}">${data.fps}</span></td>
                             <td><span id="frame-${data.id}">${data.frame}</span></td>
                             <td><span id="frames-${data.id}">${data.frames}</span></td>
                             <td><span id="percentage-${data.id}">${parseFloat(data.percentage).toFixed(2)}%</span></td>
-                            <td><span id="size-${data.id}">${parseFloat(data.size).toFixed(2)} MB</span></td>
+                            <td><span id="eta-${data.id}">${data.eta}</span></td>
+                            <td><span id="size-${data.id}">${formatSize(data.size)}</span></td>
                             <td><span id="current_file_count-${data.id}">${data.current_file_count}</span></td>
                             <td><span id="total_files-${data.id}">${data.total_files}</span></td>
                             <td><span id="current_file_name-${data.id}">${data.current_file_name}</span></td>
@@ -129,11 +140,14 @@ function fetchProgress() {
                     } else {
                         // Update the row values
                         document.getElementById(`id-${data.id}`).textContent = data.id;
+                        document.getElementById(`state-${data.id}`).textContent = data.state;
+                        document.getElementById(`phase-${data.id}`).textContent = formatPhase(data);
                         document.getElementById(`fps-${data.id}`).textContent = data.fps;
                         document.getElementById(`frame-${data.id}`).textContent = data.frame;
                         document.getElementById(`frames-${data.id}`).textContent = data.frames;
                         document.getElementById(`percentage-${data.id}`).textContent = parseFloat(data.percentage).toFixed(2) + '%';
-                        document.getElementById(`size-${data.id}`).textContent = parseFloat(data.size).toFixed(2) + ' MB';
+                        document.getElementById(`eta-${data.id}`).textContent = data.eta;
+                        document.getElementById(`size-${data.id}`).textContent = formatSize(data.size);
                         document.getElementById(`current_file_count-${data.id}`).textContent = data.current_file_count;
                         document.getElementById(`total_files-${data.id}`).textContent = data.total_files;
                         document.getElementById(`current_file_name-${data.id}`).textContent = data.current_file_name;
@@ -162,6 +176,7 @@ function startTranscodingWithInput() {
     const preset = document.getElementById('preset').value;
     const vmaf_target = document.getElementById('vmaf-target').value;
     const vmaf_threads = document.getElementById('vmaf-threads').value;
+    const mode = document.getElementById('mode').value;
 
     let job = {
         input_path: input_path,
@@ -169,7 +184,8 @@ function startTranscodingWithInput() {
         encoder: encoder,
         preset: preset,
         vmaf_target: vmaf_target,
-        vmaf_threads: vmaf_threads
+        vmaf_threads: vmaf_threads,
+        mode: mode
     };
 
     fetch('/start_transcode', {
@@ -256,6 +272,9 @@ function addToQueue() {
     const preset = document.getElementById('preset').value;
     const vmaf_target = document.getElementById('vmaf-target').value;
     const vmaf_threads = document.getElementById('vmaf-threads').value;
+    const priority = parseInt(document.getElementById('queue-priority').value) || 0;
+    const not_before = document.getElementById('queue-not-before').value;
+    const only_between = document.getElementById('queue-only-between').value;
 
     // Post the form data to the /add_to_queue endpoint
     fetch('/add_to_queue', {
@@ -269,7 +288,10 @@ function addToQueue() {
             encoder: encoder,
             preset: preset,
             vmaf_target: vmaf_target,
-            vmaf_threads: vmaf_threads
+            vmaf_threads: vmaf_threads,
+            priority: priority,
+            not_before: not_before,
+            only_between: only_between
         }),
     })
     .then(response => {
@@ -277,7 +299,7 @@ function addToQueue() {
             console.log('Job added to queue');
             console.log(response);
         } else {
-            console.error('Failed to add job to queue');
+            response.json().then(error => alert('Failed to add job to queue: ' + error.message));
         }
     })
     .catch(error => {
@@ -285,6 +307,66 @@ function addToQueue() {
     })
 }
 
+// Set the priority of a queue item, a running item keeps running
+function setQueuePriority(id, priority) {
+    fetch('/queue/' + id + '/priority', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ priority: priority }),
+    })
+    .then(response => {
+        if (!response.ok) {
+            console.error('Failed to set the priority of queue item ' + id);
+        }
+    })
+    .catch(error => console.error('Error setting queue priority:', error));
+}
+
+// Swap a queue item with its neighbour in the listing and send the new order
+function moveInQueue(items, id, offset) {
+    const ids = items.map(item => item.id);
+    const index = ids.indexOf(id);
+    const target = index + offset;
+    if (index < 0 || target < 0 || target >= ids.length) {
+        return;
+    }
+    [ids[index], ids[target]] = [ids[target], ids[index]];
+    fetch('/queue/reorder', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify(ids),
+    })
+    .then(response => {
+        if (!response.ok) {
+            console.error('Failed to reorder the queue');
+        }
+    })
+    .catch(error => console.error('Error reordering the queue:', error));
+}
+
+// The phase of a task: the CRF search with its target, crf and sample, or the encode
+function formatPhase(data) {
+    if (data.phase === 'crf_search' && data.crf_search) {
+        const search = data.crf_search;
+        let text = 'CRF search VMAF ' + search.vmaf_target;
+        if (search.vmaf_target !== search.requested_vmaf) {
+            text += ' (lowered from ' + search.requested_vmaf + ')';
+        }
+        if (search.crf !== null) {
+            text += ', crf ' + search.crf;
+        }
+        if (search.samples > 0) {
+            text += ', sample ' + search.sample + '/' + search.samples;
+        }
+        return text + ', ' + parseFloat(data.phase_percentage).toFixed(0) + '%';
+    }
+    return data.phase || '-';
+}
+
 function fetchQueue() {
     fetch('/queue')
         .then(response => response.json())
@@ -366,6 +448,28 @@ function addToQueueTable() {
                     })
                 }
 
+                // Buttons to raise and lower the priority, and to move the item up and down within its priority
+                const raise = document.createElement('button');
+                raise.textContent = '+';
+                raise.title = 'Raise priority';
+                raise.onclick = () => setQueuePriority(item.id, item.priority + 1);
+                const lower = document.createElement('button');
+                lower.textContent = '-';
+                lower.title = 'Lower priority';
+                lower.onclick = () => setQueuePriority(item.id, item.priority - 1);
+                const up = document.createElement('button');
+                up.textContent = '\u25B2';
+                up.title = 'Move up';
+                up.onclick = () => moveInQueue(data, item.id, -1);
+                const down = document.createElement('button');
+                down.textContent = '\u25BC';
+                down.title = 'Move down';
+                down.onclick = () => moveInQueue(data, item.id, 1);
+                td.appendChild(raise);
+                td.appendChild(lower);
+                td.appendChild(up);
+                td.appendChild(down);
+
                 // Add a button to start transcoding
                 const start = document.createElement('button');
                 start.textContent = 'Start';
@@ -1,11 +1,29 @@
-use crate::run_from_web;
+use crate::{run_from_web, web_default_args};
 use base64::{engine::general_purpose, Engine};
 use indicatif::ProgressBar;
 use lazy_static::lazy_static;
+use log::error;
+use rocket::fs::NamedFile;
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, status, Responder, Response};
 use rocket::serde::json::Json;
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{AsyncReadExt, AsyncSeekExt};
 use serde_json::{json, Value};
 use shared::*;
-use std::{path::PathBuf, sync::Mutex, thread};
+use std::io::SeekFrom;
+use std::sync::atomic::Ordering;
+use std::{path::Path, sync::Mutex, thread};
+
+mod files; // routes/files.rs
+pub use files::*;
+mod library; // routes/library.rs
+pub use library::*;
+mod task_queue; // routes/task_queue.rs
+pub use task_queue::*;
+mod web_settings; // routes/web_settings.rs
+pub use web_settings::*;
 
 lazy_static! {
     static ref TASK_IDS: Mutex<Vec<String>> = Mutex::new(vec![]);
@@ -13,32 +31,37 @@ lazy_static! {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TaskDetails {
+    #[serde(default)]
     input_path: String,
+    // optional list of files, folders or glob patterns, used instead of input_path when not empty
+    #[serde(default)]
+    input_paths: Vec<String>,
+    // output_path, encoder, vmaf_target and vmaf_threads left out or empty use GET /settings
+    #[serde(default)]
     output_path: String,
+    #[serde(default)]
     encoder: String,
+    #[serde(default)]
     preset: String,
+    #[serde(default, deserialize_with = "vmaf_target_string")]
     vmaf_target: String,
+    #[serde(default)]
     vmaf_threads: String,
+    // default, chunked or audio-only, default when left out
+    #[serde(default)]
+    mode: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct QueueItem {
-    input_path: String,
-    output_path: String,
-    encoder: String,
-    preset: String,
-    vmaf_target: String,
-    vmaf_threads: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct QueueInput {
-    input_path: String,
-    output_path: String,
-    encoder: String,
-    preset: String,
-    vmaf_target: String,
-    vmaf_threads: String,
+// vmaf_target as the UI sends it, a string like "95.5", or as a number
+fn vmaf_target_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match <Value as serde::Deserialize>::deserialize(deserializer)? {
+        Value::String(vmaf) => Ok(vmaf),
+        Value::Number(vmaf) => Ok(vmaf.to_string()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected a VMAF target, got {}",
+            other
+        ))),
+    }
 }
 
 /* #[get("/")]
@@ -117,138 +140,56 @@ pub fn progress_scan() -> Json<ProgressScan> {
     Json(progress_scan)
 }
 
-#[get("/all")]
-pub fn get_all_from_db_web() -> Json<Vec<Value>> {
-    // Get all the tasks from the database
-    let tasks = get_all_from_db().unwrap();
-
-    // Create a vector to hold all the tasks as JSON objects
-    let mut json_objects = Vec::new();
-
-    // Iterate over the tasks
-    for task in tasks {
-        let id = task.0.clone();
-        let filename = task.1.clone();
-        let filepath = task.2.clone();
-        let width = task.3.clone();
-        let height = task.4.clone();
-        let duration = task.5.clone();
-        let pixel_format = task.6.clone();
-        let display_aspect_ratio = task.7.clone();
-        let sample_aspect_ratio = task.8.clone();
-        let format = task.9.clone();
-        let size = task.10.clone();
-        let folder_size = task.11.clone();
-        let bitrate = task.12.clone();
-        let codec = task.13.clone();
-        let status = task.14.clone();
-        let audio_codec = task.15.clone();
-        let audio_bitrate = task.16.clone();
-        let hash = task.17.clone();
-
-        // Serialize the values into a JSON object
-        let json_object = json!({
-            "id": id,
-            "filename": filename,
-            "filepath": filepath,
-            "width": width,
-            "height": height,
-            "duration": duration,
-            "pixel_format": pixel_format,
-            "display_aspect_ratio": display_aspect_ratio,
-            "sample_aspect_ratio": sample_aspect_ratio,
-            "format": format,
-            "size": size,
-            "folder_size": folder_size,
-            "bitrate": bitrate,
-            "codec": codec,
-            "status": status,
-            "audio_codec": audio_codec,
-            "audio_bitrate": audio_bitrate,
-            "hash": hash
-        });
-
-        // Add the JSON object to the vector
-        json_objects.push(json_object);
-    }
-
-    // Return the vector as JSON
-    Json(json_objects)
+// Hardware and tool versions of the host, so the UI can disable encoders that won't work
+#[get("/system")]
+pub fn system() -> Json<SystemInfo> {
+    Json(system_info())
 }
 
-#[get("/search/<search>")]
-pub fn get_all_from_db_search_web(search: String) -> Json<Vec<Value>> {
-    // Get all the tasks from the database
-    let tasks = get_all_from_db_search(&search).unwrap();
-
-    // Create a vector to hold all the tasks as JSON objects
-    let mut json_objects = Vec::new();
-
-    // Iterate over the tasks
-    for task in tasks {
-        let id = task.0.clone();
-        let filename = task.1.clone();
-        let filepath = task.2.clone();
-        let width = task.3.clone();
-        let height = task.4.clone();
-        let duration = task.5.clone();
-        let pixel_format = task.6.clone();
-        let display_aspect_ratio = task.7.clone();
-        let sample_aspect_ratio = task.8.clone();
-        let format = task.9.clone();
-        let size = task.10.clone();
-        let folder_size = task.11.clone();
-        let bitrate = task.12.clone();
-        let codec = task.13.clone();
-        let status = task.14.clone();
-        let audio_codec = task.15.clone();
-        let audio_bitrate = task.16.clone();
-        let hash = task.17.clone();
-
-        // Serialize the values into a JSON object
-        let json_object = json!({
-            "id": id,
-            "filename": filename,
-            "filepath": filepath,
-            "width": width,
-            "height": height,
-            "duration": duration,
-            "pixel_format": pixel_format,
-            "display_aspect_ratio": display_aspect_ratio,
-            "sample_aspect_ratio": sample_aspect_ratio,
-            "format": format,
-            "size": size,
-            "folder_size": folder_size,
-            "bitrate": bitrate,
-            "codec": codec,
-            "status": status,
-            "audio_codec": audio_codec,
-            "audio_bitrate": audio_bitrate,
-            "hash": hash
-        });
+// Counters and gauges for Prometheus in its text format, see shared/metrics.rs
+#[get("/metrics")]
+pub fn metrics() -> (ContentType, String) {
+    (ContentType::Plain, render_metrics())
+}
 
-        // Add the JSON object to the vector
-        json_objects.push(json_object);
+// The profiles saved with the cli --save-profile
+#[get("/profiles")]
+pub fn profiles() -> Result<Json<Vec<Profile>>, status::Custom<Json<Value>>> {
+    match list_profiles() {
+        Ok(profiles) => Ok(Json(profiles)),
+        Err(e) => {
+            error!("Failed to list profiles: {}", e);
+            Err(status::Custom(
+                Status::InternalServerError,
+                Json(json!({"status": "error", "message": e})),
+            ))
+        }
     }
-
-    // Return the vector as JSON
-    Json(json_objects)
 }
 
 #[post("/scan/<base64>")]
 pub fn scan_path_web(base64: String) -> Json<Value> {
     // Decode the base64 string
-    let path = general_purpose::STANDARD.decode(base64.as_bytes()).unwrap();
-    let path = String::from_utf8(path).unwrap();
+    let path = match general_purpose::STANDARD
+        .decode(base64.as_bytes())
+        .ok()
+        .and_then(|path| String::from_utf8(path).ok())
+    {
+        Some(path) => path,
+        None => {
+            return Json(
+                json!({"status": "error", "message": "Path is not valid base64 encoded UTF-8"}),
+            )
+        }
+    };
 
     // Validate the path is a directory
-    let path = PathBuf::from(path);
-    if !path.is_dir() {
+    if !Path::new(&path).is_dir() {
         return Json(json!({"status": "error", "message": "Path is not a directory"}));
     }
 
-    let number_of_files = walk_count(&path.to_str().unwrap().to_string());
-    let files_vec = walk_files(&path.to_str().unwrap().to_string());
+    let number_of_files = walk_count(&path, &[]);
+    let files_vec = walk_files(&path, &[]);
     let bar = ProgressBar::new(number_of_files as u64);
 
     // Add the task to the database
@@ -258,22 +199,143 @@ pub fn scan_path_web(base64: String) -> Json<Value> {
     Json(json!({"status": "success"}))
 }
 
+// Start a scan task in the background, the body is {"path": "..."} or a JSON array of paths/globs
+#[post("/scan", format = "json", data = "<input>")]
+pub fn scan_paths_web(input: Json<Value>) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    let paths: Vec<String> = match input.into_inner() {
+        Value::String(path) => vec![path],
+        Value::Array(paths) => paths
+            .iter()
+            .filter_map(|path| path.as_str().map(|path| path.to_string()))
+            .collect(),
+        value => match value["path"].as_str() {
+            Some(path) => vec![path.to_string()],
+            None => vec![],
+        },
+    };
+    if paths.is_empty() {
+        return Err(status::Custom(
+            Status::BadRequest,
+            Json(json!({"status": "error", "message": "No path given"})),
+        ));
+    }
+
+    // Generate a UUID for the scan
+    let scan_id = uuid::Uuid::new_v4().to_string();
+    if let Err(e) = start_scan_progress(&scan_id, &paths) {
+        return Err(status::Custom(
+            Status::Conflict,
+            Json(json!({"status": "error", "message": e})),
+        ));
+    }
+
+    let scan_id_clone = scan_id.clone();
+    thread::spawn(move || run_scan(&scan_id_clone, paths));
+
+    Ok(Json(json!({"status": "success", "scan_id": scan_id})))
+}
+
+// Walk the given paths and add the found files to the database, updating the scan progress
+pub(crate) fn run_scan(scan_id: &str, paths: Vec<String>) {
+    // the scanned folders, their rows of files that are gone go to the trash after the scan
+    let folders: Vec<String> = paths
+        .iter()
+        .filter(|path| Path::new(path).is_dir())
+        .cloned()
+        .collect();
+    // Expand the folders and globs, paths that don't exist are skipped
+    let files_vec = resolve_input_paths(paths, &[]);
+    let found = files_vec.len() as u64;
+    update_scan_progress(scan_id, |scan| {
+        scan.phase = "probing".to_string();
+        scan.found = found;
+        scan.total = found;
+    });
+
+    let bar = ProgressBar::hidden();
+    let result = add_to_db_with_progress(files_vec, bar, &|count, total| {
+        update_scan_progress(scan_id, |scan| {
+            scan.count = count;
+            scan.total = total;
+        });
+    });
+
+    // like the scan of a cli run, --max-trash-fraction and --force are the defaults of the web
+    update_scan_progress(scan_id, |scan| scan.phase = "removing".to_string());
+    let args = web_default_args("");
+    let mut removed = 0;
+    for folder in &folders {
+        match remove_from_db_folder(folder, &args) {
+            Ok(trashed) => removed += trashed as u64,
+            Err(e) => error!(
+                "Scan {} failed to trash the removals of {}: {}",
+                scan_id, folder, e
+            ),
+        }
+    }
+
+    update_scan_progress(scan_id, |scan| {
+        scan.removed = removed;
+        match &result {
+            Ok((counters, _)) => {
+                scan.present = counters[1].load(Ordering::SeqCst) as u64;
+                scan.added = counters[2].load(Ordering::SeqCst) as u64;
+                scan.errors = counters[4].load(Ordering::SeqCst) as u64;
+            }
+            Err(e) => {
+                error!("Scan {} failed: {}", scan_id, e);
+                scan.errors += 1;
+            }
+        }
+        scan.count = scan.total;
+        scan.phase = "done".to_string();
+    });
+}
+
+#[get("/scan/<id>")]
+pub fn scan_progress_web(id: String) -> Option<Json<ProgressScan>> {
+    get_scan_progress(&id).map(Json)
+}
+
 #[post("/start_transcode", data = "<task_details>")]
 pub fn start_transcode(task_details: Json<TaskDetails>) -> Json<Vec<String>> {
-    // Generate a UUID for the task
-    let id = uuid::Uuid::new_v4().to_string();
+    // Generate a UUID for the task and add it to the task history
+    let id = new_task_id();
     let id_clone = id.clone();
+    let input_paths = if task_details.input_paths.is_empty() {
+        vec![task_details.input_path.clone()]
+    } else {
+        task_details.input_paths.clone()
+    };
+    let task = Task::new(
+        &id,
+        &input_paths,
+        &task_details.output_path,
+        &task_details.encoder,
+        task_details.vmaf_target.parse().unwrap_or(0.0),
+    );
+    if let Err(e) = db().insert_task(&task) {
+        error!("Failed to store task {}: {}", id, e);
+    }
 
     thread::spawn(move || {
         // Start the task in a new thread
-        let _task = run_from_web(
+        let task = run_from_web(
             &id,
             &task_details.input_path,
+            &task_details.input_paths,
             &task_details.output_path,
             &task_details.encoder,
             &task_details.vmaf_target,
             &task_details.vmaf_threads,
+            &task_details.mode,
         );
+        // the request and the settings are checked before the task runs
+        if let Err(e) = task {
+            error!("{}", e);
+            set_task_error(&id, &e.to_string());
+            set_task_state(&id, "failed");
+        }
     });
 
     let mut task_ids = TASK_IDS.lock().unwrap();
@@ -283,6 +345,11 @@ pub fn start_transcode(task_details: Json<TaskDetails>) -> Json<Vec<String>> {
     Json(task_ids.clone())
 }
 
+// Add a task started by the queue worker to the task ids, for the progress of the UI
+pub fn push_task_id(id: &str) {
+    TASK_IDS.lock().unwrap().push(id.to_string());
+}
+
 // Function to get the task ids
 #[get("/task_ids")]
 pub fn task_ids() -> Json<Vec<String>> {
@@ -291,64 +358,80 @@ pub fn task_ids() -> Json<Vec<String>> {
     Json(task_ids.clone())
 }
 
-#[get("/queue")]
-pub fn queue() -> Json<Vec<Value>> {
-    // Fetch all the items in the queue, in the database in table db_queue
-    let queue_items = get_all_from_db_queue().unwrap();
-
-    // Create a vector to hold all the tasks as JSON objects
-    let mut json_objects = Vec::new();
-
-    // Iterate over the tasks
-    for queue_item in queue_items {
-        let id = queue_item.0.clone();
-        let input_path = queue_item.1.clone();
-        let output_path = queue_item.2.clone();
-        let encoder = queue_item.3.clone();
-        let preset = queue_item.4.clone();
-        let vmaf_target = queue_item.5.clone();
-        let vmaf_threads = queue_item.6.clone();
-
-        // Serialize the values into a JSON object
-        let json_object = json!({
-            "id": id,
-            "input_path": input_path,
-            "output_path": output_path,
-            "encoder": encoder,
-            "preset": preset,
-            "vmaf_target": vmaf_target,
-            "vmaf_threads": vmaf_threads
-        });
-
-        // Add the JSON object to the vector
-        json_objects.push(json_object);
+// Task history, most recent first, per_page tasks (default 50) of page (from 0)
+#[get("/tasks?<page>&<per_page>")]
+pub fn tasks(
+    page: Option<u32>,
+    per_page: Option<u32>,
+) -> Result<Json<Vec<Task>>, status::Custom<Json<Value>>> {
+    let per_page = per_page.unwrap_or(50).clamp(1, 500);
+    match db().tasks(per_page, page.unwrap_or(0) * per_page) {
+        Ok(tasks) => Ok(Json(tasks)),
+        Err(e) => {
+            error!("Failed to read tasks: {}", e);
+            Err(status::Custom(
+                Status::InternalServerError,
+                Json(json!({"status": "error", "message": e.to_string()})),
+            ))
+        }
     }
+}
 
-    Json(json_objects)
+#[get("/tasks/<id>")]
+pub fn task(id: String) -> Option<Json<Task>> {
+    db().task(&id).ok().flatten().map(Json)
 }
 
-#[post("/add_to_queue", data = "<queue_input>")]
-pub fn add_to_queue(queue_input: Json<QueueInput>) -> &'static str {
-    // Add the task to the database
-    let _task = add_to_db_queue(
-        &queue_input.input_path,
-        &queue_input.output_path,
-        &queue_input.encoder,
-        &queue_input.preset,
-        &queue_input.vmaf_target,
-        &queue_input.vmaf_threads,
-    );
+// End of the ffmpeg/ab-av1 output of a transcode task, and the error of its last failed file
+#[get("/tasks/<id>/log")]
+pub fn task_log_web(id: String) -> Option<Json<TaskLog>> {
+    match task_log(&id) {
+        Some(log) => Some(Json(log)),
+        // a task that has not finished a tool run yet
+        None if matches!(db().task(&id), Ok(Some(_))) => Some(Json(TaskLog::default())),
+        None => None,
+    }
+}
 
-    "success"
+// The fps and output bitrate samples of a task after since (t_ms), the web UI polls with the
+// t_ms of the last sample it has
+#[get("/tasks/<id>/samples?<since>")]
+pub fn task_samples_web(id: String, since: Option<u64>) -> Option<Json<Vec<EncodeSample>>> {
+    match task_samples(&id, since) {
+        Some(samples) => Some(Json(samples)),
+        // a task that has not encoded yet, or whose samples were evicted
+        None if matches!(db().task(&id), Ok(Some(_))) => Some(Json(Vec::new())),
+        None => None,
+    }
 }
 
-#[post("/remove_from_queue", data = "<id>")]
-pub fn remove_from_queue(id: Json<Value>) -> &'static str {
-    // Remove the task from the database
-    let _task = remove_from_db_queue(
-        // Extract the id from the JSON
-        id["id"].to_string().replace("\"", ""),
-    );
+// Pause and resume the running encode of task id, granularity says what a pause stops right away:
+// process (unix, the ffmpeg of the task is stopped) or file (the current file is finished first)
+#[post("/pause/<id>")]
+pub fn pause_task(id: String) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    if !is_task_running(&id) {
+        return Err(task_not_running(&id));
+    }
+    pause_encode(&id);
+    Ok(Json(
+        json!({"status": "paused", "task_id": id, "granularity": pause_granularity()}),
+    ))
+}
+
+#[post("/resume/<id>")]
+pub fn resume_task(id: String) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    if !is_task_running(&id) {
+        return Err(task_not_running(&id));
+    }
+    resume_encode(&id);
+    Ok(Json(
+        json!({"status": "running", "task_id": id, "granularity": pause_granularity()}),
+    ))
+}
 
-    "success"
+fn task_not_running(id: &str) -> status::Custom<Json<Value>> {
+    status::Custom(
+        Status::NotFound,
+        Json(json!({"status": "error", "message": format!("Task {} is not running", id)})),
+    )
 }
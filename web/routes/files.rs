@@ -0,0 +1,159 @@
+// GET /files: the outputs of the tasks in the --serve-root folders, with Range support, and their
+// thumbnails
+
+use super::*;
+
+lazy_static! {
+    // --serve-root, the folders /files serves the outputs of
+    static ref SERVE_ROOTS: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+pub fn set_serve_roots(roots: &[String]) {
+    *SERVE_ROOTS.lock().unwrap() = roots.to_vec();
+}
+
+fn file_error(status: Status, message: String) -> status::Custom<Json<Value>> {
+    status::Custom(status, Json(json!({"status": "error", "message": message})))
+}
+
+// The output of task id in a --serve-root folder, 404 when it was moved or replaced
+fn served_output(id: &str) -> Result<std::path::PathBuf, status::Custom<Json<Value>>> {
+    let roots = SERVE_ROOTS.lock().unwrap().clone();
+    servable_output(id, &roots).map_err(|refusal| match refusal {
+        ServeRefusal::NotFound(message) => file_error(Status::NotFound, message),
+        ServeRefusal::Forbidden(message) => file_error(Status::Forbidden, message),
+    })
+}
+
+// The Range header of a request, None without one
+pub struct RangeHeader(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RangeHeader {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        request::Outcome::Success(RangeHeader(
+            request.headers().get_one("Range").map(String::from),
+        ))
+    }
+}
+
+// Bytes read from the file and sent per chunk
+const FILE_CHUNK: usize = 64 * 1024;
+
+// A file, or the range of it from its first to its last byte, read in chunks as it is sent
+pub struct FileRange {
+    file: File,
+    content_type: ContentType,
+    total: u64,
+    range: Option<(u64, u64)>,
+}
+
+impl<'r> Responder<'r, 'static> for FileRange {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response
+            .header(self.content_type)
+            .raw_header("Accept-Ranges", "bytes");
+        let length = match self.range {
+            Some((start, end)) => {
+                response.status(Status::PartialContent).raw_header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end, self.total),
+                );
+                end - start + 1
+            }
+            None => self.total,
+        };
+        response
+            .raw_header("Content-Length", length.to_string())
+            .streamed_body(self.file.take(length))
+            .max_chunk_size(FILE_CHUNK)
+            .ok()
+    }
+}
+
+// Why the output of a task is not sent: a refusal, or a 416 with the size of the file for a range
+// past its end
+pub enum OutputFileError {
+    Refused(status::Custom<Json<Value>>),
+    Unsatisfiable { total: u64, message: String },
+}
+
+impl From<status::Custom<Json<Value>>> for OutputFileError {
+    fn from(refusal: status::Custom<Json<Value>>) -> Self {
+        OutputFileError::Refused(refusal)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for OutputFileError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            OutputFileError::Refused(refusal) => refusal.respond_to(request),
+            OutputFileError::Unsatisfiable { total, message } => Response::build_from(
+                file_error(Status::RangeNotSatisfiable, message).respond_to(request)?,
+            )
+            .raw_header("Content-Range", format!("bytes */{}", total))
+            .ok(),
+        }
+    }
+}
+
+// mkv is not one of the types rocket knows
+fn output_content_type(path: &Path) -> ContentType {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mkv" => ContentType::new("video", "x-matroska"),
+        extension => ContentType::from_extension(extension).unwrap_or(ContentType::Binary),
+    }
+}
+
+// The last output task id finished, with Range support for the video player of the browser
+#[get("/files/<id>/output")]
+pub async fn task_output_file(
+    id: String,
+    range: RangeHeader,
+) -> Result<FileRange, OutputFileError> {
+    let path = served_output(&id)?;
+    let mut file = File::open(&path)
+        .await
+        .map_err(|e| file_error(Status::NotFound, format!("{}: {}", path.display(), e)))?;
+    let total = file
+        .metadata()
+        .await
+        .map_err(|e| file_error(Status::InternalServerError, e.to_string()))?
+        .len();
+    // a malformed range gets the whole file, only a range past its end is refused
+    let range = byte_range(range.0.as_deref(), total)
+        .map_err(|message| OutputFileError::Unsatisfiable { total, message })?;
+    if let Some((start, _)) = range {
+        file.seek(SeekFrom::Start(start))
+            .await
+            .map_err(|e| file_error(Status::InternalServerError, e.to_string()))?;
+    }
+    Ok(FileRange {
+        file,
+        content_type: output_content_type(&path),
+        total,
+        range,
+    })
+}
+
+// A frame of the last output of task id at a quarter of its length, extracted on the first
+// request and cached in the thumbs folder of the artifacts folder
+#[get("/files/<id>/thumb.jpg")]
+pub async fn task_output_thumbnail(id: String) -> Result<NamedFile, status::Custom<Json<Value>>> {
+    let path = served_output(&id)?;
+    let dir = artifacts_root(&web_default_args(&id)).join(THUMBNAIL_FOLDER);
+    let thumbnail = rocket::tokio::task::spawn_blocking(move || output_thumbnail(&id, &path, &dir))
+        .await
+        .map_err(|e| file_error(Status::InternalServerError, e.to_string()))?
+        .map_err(|e| file_error(Status::InternalServerError, e))?;
+    NamedFile::open(&thumbnail)
+        .await
+        .map_err(|e| file_error(Status::InternalServerError, e.to_string()))
+}
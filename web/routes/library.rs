@@ -0,0 +1,215 @@
+// The library of the database: listing and searching it, the export, the statistics, the
+// duplicates and the health report
+
+use super::*;
+use log::error;
+
+#[get("/all")]
+pub fn get_all_from_db_web() -> Json<Vec<Value>> {
+    // Get all the tasks from the database
+    let tasks = get_all_from_db().unwrap();
+
+    // Create a vector to hold all the tasks as JSON objects
+    let mut json_objects = Vec::new();
+
+    // Iterate over the tasks
+    for task in tasks {
+        let id = task.0.clone();
+        let filename = task.1.clone();
+        let filepath = task.2.clone();
+        let width = task.3.clone();
+        let height = task.4.clone();
+        let duration = task.5.clone();
+        let pixel_format = task.6.clone();
+        let display_aspect_ratio = task.7.clone();
+        let sample_aspect_ratio = task.8.clone();
+        let format = task.9.clone();
+        let size = task.10.clone();
+        let folder_size = task.11.clone();
+        let bitrate = task.12.clone();
+        let codec = task.13.clone();
+        let status = task.14.clone();
+        let audio_codec = task.15.clone();
+        let audio_bitrate = task.16.clone();
+        let hash = task.17.clone();
+
+        // Serialize the values into a JSON object
+        let json_object = json!({
+            "id": id,
+            "filename": filename,
+            "filepath": filepath,
+            "width": width,
+            "height": height,
+            "duration": duration,
+            "pixel_format": pixel_format,
+            "display_aspect_ratio": display_aspect_ratio,
+            "sample_aspect_ratio": sample_aspect_ratio,
+            "format": format,
+            "size": size,
+            "folder_size": folder_size,
+            "bitrate": bitrate,
+            "codec": codec,
+            "status": status,
+            "audio_codec": audio_codec,
+            "audio_bitrate": audio_bitrate,
+            "hash": hash
+        });
+
+        // Add the JSON object to the vector
+        json_objects.push(json_object);
+    }
+
+    // Return the vector as JSON
+    Json(json_objects)
+}
+
+#[get("/search/<search>")]
+pub fn get_all_from_db_search_web(search: String) -> Json<Vec<Value>> {
+    // Get all the tasks from the database
+    let tasks = get_all_from_db_search(&search).unwrap();
+
+    // Create a vector to hold all the tasks as JSON objects
+    let mut json_objects = Vec::new();
+
+    // Iterate over the tasks
+    for task in tasks {
+        let id = task.0.clone();
+        let filename = task.1.clone();
+        let filepath = task.2.clone();
+        let width = task.3.clone();
+        let height = task.4.clone();
+        let duration = task.5.clone();
+        let pixel_format = task.6.clone();
+        let display_aspect_ratio = task.7.clone();
+        let sample_aspect_ratio = task.8.clone();
+        let format = task.9.clone();
+        let size = task.10.clone();
+        let folder_size = task.11.clone();
+        let bitrate = task.12.clone();
+        let codec = task.13.clone();
+        let status = task.14.clone();
+        let audio_codec = task.15.clone();
+        let audio_bitrate = task.16.clone();
+        let hash = task.17.clone();
+
+        // Serialize the values into a JSON object
+        let json_object = json!({
+            "id": id,
+            "filename": filename,
+            "filepath": filepath,
+            "width": width,
+            "height": height,
+            "duration": duration,
+            "pixel_format": pixel_format,
+            "display_aspect_ratio": display_aspect_ratio,
+            "sample_aspect_ratio": sample_aspect_ratio,
+            "format": format,
+            "size": size,
+            "folder_size": folder_size,
+            "bitrate": bitrate,
+            "codec": codec,
+            "status": status,
+            "audio_codec": audio_codec,
+            "audio_bitrate": audio_bitrate,
+            "hash": hash
+        });
+
+        // Add the JSON object to the vector
+        json_objects.push(json_object);
+    }
+
+    // Return the vector as JSON
+    Json(json_objects)
+}
+
+// The library filtered by q, every word in the path, title or show, and by title, a title
+// starting with it, the exact file name first and the rows only the tags matched last
+#[get("/db?<q>&<title>")]
+pub fn search_library_web(
+    q: Option<String>,
+    title: Option<String>,
+) -> Result<Json<Vec<LibraryMatch>>, status::Custom<Json<Value>>> {
+    db().search_library(q.as_deref().unwrap_or(""), title.as_deref().unwrap_or(""))
+        .map(Json)
+        .map_err(|e| {
+            error!("Failed to search the library: {}", e);
+            status::Custom(
+                Status::InternalServerError,
+                Json(json!({"status": "error", "message": e.to_string()})),
+            )
+        })
+}
+
+// Download the whole video_info table as csv
+#[get("/db/export.csv")]
+pub fn export_db_csv_web() -> Result<(ContentType, Vec<u8>), status::Custom<Json<Value>>> {
+    let mut csv = Vec::new();
+    match export_db("csv", &mut csv) {
+        Ok(_) => Ok((ContentType::CSV, csv)),
+        Err(e) => {
+            error!("Failed to export database: {}", e);
+            Err(status::Custom(
+                Status::InternalServerError,
+                Json(json!({"status": "error", "message": e.to_string()})),
+            ))
+        }
+    }
+}
+
+// Lifetime totals of the finished encodes, by encoder and by month
+#[get("/stats")]
+pub fn stats_web() -> Result<Json<LifetimeStats>, status::Custom<Json<Value>>> {
+    db().lifetime_stats().map(Json).map_err(|e| {
+        error!("Failed to read the stats: {}", e);
+        status::Custom(
+            Status::InternalServerError,
+            Json(json!({"status": "error", "message": e.to_string()})),
+        )
+    })
+}
+
+// What the encodes saved per CPU hour and per wall hour, by encoder and preset
+#[get("/stats/encoders")]
+pub fn encoder_stats_web() -> Result<Json<Vec<EncoderCost>>, status::Custom<Json<Value>>> {
+    db().encoder_costs().map(Json).map_err(|e| {
+        error!("Failed to read the encode costs: {}", e);
+        status::Custom(
+            Status::InternalServerError,
+            Json(json!({"status": "error", "message": e.to_string()})),
+        )
+    })
+}
+
+fn duplicates_error(e: rusqlite::Error) -> status::Custom<Json<Value>> {
+    error!("Failed to look for duplicates: {}", e);
+    status::Custom(
+        Status::InternalServerError,
+        Json(json!({"status": "error", "message": e.to_string()})),
+    )
+}
+
+// The duplicate sets of the library, the most wasted bytes first
+#[get("/db/duplicates")]
+pub fn duplicates_web() -> Result<Json<Vec<DuplicateSet>>, status::Custom<Json<Value>>> {
+    find_duplicates().map(Json).map_err(duplicates_error)
+}
+
+// Mark all but the highest bitrate copy of every set as duplicate
+#[post("/db/duplicates/mark")]
+pub fn mark_duplicates_web() -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    let sets = find_duplicates().map_err(duplicates_error)?;
+    let marked = mark_duplicates(&sets).map_err(duplicates_error)?;
+    Ok(Json(json!({"status": "ok", "marked": marked})))
+}
+
+// The health of the library found by --verify-library, with the corrupt and suspect files
+#[get("/db/health")]
+pub fn library_health_web() -> Result<Json<LibraryHealth>, status::Custom<Json<Value>>> {
+    db().library_health().map(Json).map_err(|e| {
+        error!("Failed to read the library health: {}", e);
+        status::Custom(
+            Status::InternalServerError,
+            Json(json!({"status": "error", "message": e.to_string()})),
+        )
+    })
+}
@@ -0,0 +1,138 @@
+// The queue of the web: the files waiting to be encoded in order, their priority and their order
+
+use super::*;
+use log::error;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueItem {
+    input_path: String,
+    output_path: String,
+    encoder: String,
+    preset: String,
+    #[serde(deserialize_with = "vmaf_target_string")]
+    vmaf_target: String,
+    vmaf_threads: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueInput {
+    input_path: String,
+    output_path: String,
+    encoder: String,
+    preset: String,
+    #[serde(deserialize_with = "vmaf_target_string")]
+    vmaf_target: String,
+    vmaf_threads: String,
+    // higher runs first, 0 when left out
+    #[serde(default)]
+    priority: i32,
+    // HH:MM, YYYY-MM-DD HH:MM or RFC 3339, empty to start right away
+    #[serde(default)]
+    not_before: String,
+    // daily window like 23:00-07:00, empty for any time
+    #[serde(default)]
+    only_between: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuePriority {
+    priority: i32,
+}
+
+// The queue in the order the worker runs it, with the position and estimated start of each entry
+#[get("/queue")]
+pub fn queue() -> Result<Json<Vec<QueueListing>>, status::Custom<Json<Value>>> {
+    match queue_listing() {
+        Ok(listing) => Ok(Json(listing)),
+        Err(e) => {
+            error!("Failed to read the queue: {}", e);
+            Err(queue_error(Status::InternalServerError, e.to_string()))
+        }
+    }
+}
+
+#[post("/add_to_queue", data = "<queue_input>")]
+pub fn add_to_queue(
+    queue_input: Json<QueueInput>,
+) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    // an input that is queued already or locked by a running cli or web run
+    match input_conflict(&queue_input.input_path) {
+        Ok(Some(conflict)) => return Err(queue_error(Status::Conflict, conflict)),
+        Ok(None) => {}
+        Err(e) => return Err(queue_error(Status::InternalServerError, e.to_string())),
+    }
+    // Add the task to the database
+    match add_to_db_queue(
+        &queue_input.input_path,
+        &queue_input.output_path,
+        &queue_input.encoder,
+        &queue_input.preset,
+        &queue_input.vmaf_target,
+        &queue_input.vmaf_threads,
+        queue_input.priority,
+        &queue_input.not_before,
+        &queue_input.only_between,
+    ) {
+        Ok(id) => Ok(Json(json!({"status": "success", "id": id}))),
+        Err(e) => Err(queue_error(Status::BadRequest, e)),
+    }
+}
+
+// Change the priority of an entry. A running entry is not interrupted, its priority only matters
+// if it is queued again
+#[post("/queue/<id>/priority", format = "json", data = "<input>")]
+pub fn queue_priority(
+    id: i64,
+    input: Json<QueuePriority>,
+) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    match db().queue_set_priority(id, input.priority) {
+        Ok(true) => Ok(Json(
+            json!({"status": "success", "id": id, "priority": input.priority}),
+        )),
+        Ok(false) => Err(queue_error(
+            Status::NotFound,
+            format!("Queue entry {} not found", id),
+        )),
+        Err(e) => Err(queue_error(Status::InternalServerError, e.to_string())),
+    }
+}
+
+// Reorder the queue with a list of entry ids, they come first in this order and the entries left
+// out keep their order after them. The priority still comes first, the order is within a priority
+#[post("/queue/reorder", format = "json", data = "<ids>")]
+pub fn queue_reorder(ids: Json<Vec<i64>>) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    let entries = get_all_from_db_queue()
+        .map_err(|e| queue_error(Status::InternalServerError, e.to_string()))?;
+    for (index, id) in ids.iter().enumerate() {
+        if !entries.iter().any(|entry| entry.id == *id) {
+            return Err(queue_error(
+                Status::NotFound,
+                format!("Queue entry {} not found", id),
+            ));
+        }
+        if ids[..index].contains(id) {
+            return Err(queue_error(
+                Status::BadRequest,
+                format!("Queue entry {} is given twice", id),
+            ));
+        }
+    }
+    db().queue_reorder(&ids)
+        .map_err(|e| queue_error(Status::InternalServerError, e.to_string()))?;
+    Ok(Json(json!({"status": "success"})))
+}
+
+fn queue_error(status: Status, message: String) -> status::Custom<Json<Value>> {
+    status::Custom(status, Json(json!({"status": "error", "message": message})))
+}
+
+#[post("/remove_from_queue", data = "<id>")]
+pub fn remove_from_queue(id: Json<Value>) -> &'static str {
+    // Remove the task from the database
+    let _task = remove_from_db_queue(
+        // Extract the id from the JSON
+        id["id"].to_string().replace("\"", ""),
+    );
+
+    "success"
+}
@@ -0,0 +1,136 @@
+// The settings of the web stored in the database, the webhook and the defaults of the tasks
+
+use super::*;
+use log::error;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    // an empty or null url removes the webhook
+    #[serde(default)]
+    url: Option<String>,
+    // failure, success or all, all when left out
+    #[serde(default)]
+    notify_on: Option<String>,
+}
+
+fn settings_error(status: Status, message: String) -> status::Custom<Json<Value>> {
+    error!("Failed to update the webhook: {}", message);
+    status::Custom(status, Json(json!({"status": "error", "message": message})))
+}
+
+// The webhook the tasks of the web server post to, the url only shows its host
+#[get("/settings/webhook")]
+pub fn webhook_settings() -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    let read = || -> rusqlite::Result<(Option<String>, Option<String>)> {
+        Ok((
+            db().get_setting(WEBHOOK_URL_SETTING)?,
+            db().get_setting(WEBHOOK_NOTIFY_ON_SETTING)?,
+        ))
+    };
+    let (url, notify_on) =
+        read().map_err(|e| settings_error(Status::InternalServerError, e.to_string()))?;
+    Ok(Json(json!({
+        "url": url.as_deref().map(redact_url),
+        "notify_on": notify_on.unwrap_or_else(|| "all".to_string()),
+    })))
+}
+
+#[post("/settings/webhook", format = "json", data = "<input>")]
+pub fn set_webhook_settings(
+    input: Json<WebhookSettings>,
+) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    let url = input
+        .url
+        .as_deref()
+        .map(str::trim)
+        .filter(|url| !url.is_empty());
+    let notify_on = input.notify_on.as_deref().unwrap_or("all");
+    if !matches!(notify_on, "failure" | "success" | "all") {
+        return Err(settings_error(
+            Status::BadRequest,
+            format!(
+                "invalid notify_on value {}, expected failure, success or all",
+                notify_on
+            ),
+        ));
+    }
+    if let Some(url) = url {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(settings_error(
+                Status::BadRequest,
+                format!("{} is not an http or https url", redact_url(url)),
+            ));
+        }
+    }
+    db().set_setting(WEBHOOK_URL_SETTING, url)
+        .and_then(|_| db().set_setting(WEBHOOK_NOTIFY_ON_SETTING, Some(notify_on)))
+        .map_err(|e| settings_error(Status::InternalServerError, e.to_string()))?;
+    Ok(Json(json!({
+        "status": "success",
+        "url": url.map(redact_url),
+        "notify_on": notify_on,
+    })))
+}
+
+// The defaults of the next task started from the web and the ones stored with PUT /settings
+#[get("/settings")]
+pub fn settings() -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    let stored = stored_web_settings().map_err(|e| {
+        status::Custom(
+            Status::InternalServerError,
+            Json(json!({"status": "error", "message": e.to_string()})),
+        )
+    })?;
+    // stored settings were checked when they were stored
+    let args =
+        apply_web_settings(web_default_args(""), &stored).unwrap_or_else(|_| web_default_args(""));
+    let stored: serde_json::Map<String, Value> = stored
+        .into_iter()
+        .map(|(field, value)| (field, Value::String(value)))
+        .collect();
+    Ok(Json(json!({
+        "settings": web_settings_values(&args),
+        "stored": stored,
+    })))
+}
+
+// Change the defaults of the next tasks, {"encoder": "hevc_nvenc", "vmaf": 95.5}, a null or empty
+// value goes back to the compiled default. Nothing is stored when a value is refused, the errors
+// are listed by field
+#[put("/settings", format = "json", data = "<input>")]
+pub fn set_settings(
+    input: Json<serde_json::Map<String, Value>>,
+) -> Result<Json<Value>, status::Custom<Json<Value>>> {
+    let refused = |errors: Vec<SettingError>| {
+        let errors: serde_json::Map<String, Value> = errors
+            .into_iter()
+            .map(|error| (error.field, Value::String(error.message)))
+            .collect();
+        error!("Refused settings: {}", Value::Object(errors.clone()));
+        status::Custom(
+            Status::BadRequest,
+            Json(json!({"status": "error", "errors": errors})),
+        )
+    };
+    let mut settings = Vec::new();
+    let mut errors = Vec::new();
+    for (field, value) in input.into_inner() {
+        match value {
+            Value::String(value) => settings.push((field, Some(value))),
+            Value::Number(value) => settings.push((field, Some(value.to_string()))),
+            Value::Null => settings.push((field, None)),
+            _ => errors.push(SettingError {
+                field,
+                message: "expected a string, a number or null".to_string(),
+            }),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(refused(errors));
+    }
+    let args = store_web_settings(web_default_args(""), &settings).map_err(refused)?;
+    Ok(Json(json!({
+        "status": "success",
+        "settings": web_settings_values(&args),
+    })))
+}
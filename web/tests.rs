@@ -0,0 +1,154 @@
+use super::*;
+
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use std::path::Path;
+
+// The routes and the tasks use a database in the temp dir
+fn web_db() {
+    static DB: std::sync::Once = std::sync::Once::new();
+    DB.call_once(|| {
+        let path = std::env::temp_dir().join(format!("transrustica-web-{}.db", std::process::id()));
+        init_db(&path.to_string_lossy()).unwrap();
+    });
+}
+
+// The tasks read the settings of PUT /settings
+fn web_task(output: &str, mode: &str) -> Args {
+    web_db();
+    web_task_args("task-1", "/media", &[], output, "", "", "", mode).unwrap()
+}
+
+#[test]
+fn the_output_of_a_task_is_the_folder_of_the_video_and_the_audio_outputs() {
+    let output = Path::new("/out");
+    let video_output = output.join("movie.libx265.vmaf97.crf24.mkv");
+    let audio_output = output.join("movie.mkv");
+    for mode in ["", "default", "chunked", "audio-only"] {
+        let args = web_task("/out", mode);
+        assert_eq!(args.outputpath, "/out", "{}", mode);
+        assert_eq!(args.output_folder, None, "{}", mode);
+        assert_eq!(
+            set_output_folder_filename(
+                "/media/movie.mkv",
+                &args.encoder,
+                &97.0,
+                "24",
+                &args.outputpath
+            ),
+            video_output.display().to_string(),
+            "{}",
+            mode
+        );
+        assert_eq!(
+            set_output_folder_filename_audio("/media/movie.mkv", &args.outputpath),
+            audio_output.display().to_string(),
+            "{}",
+            mode
+        );
+    }
+
+    // without an output in the request, the setting, then the compiled default
+    db().set_setting("default.outputpath", Some("/stored"))
+        .unwrap();
+    assert_eq!(web_task("", "audio-only").outputpath, "/stored");
+    db().set_setting("default.outputpath", None).unwrap();
+    assert_eq!(web_task("", "").outputpath, ".");
+}
+
+// The body of POST /add_to_queue for input
+fn queue_input(input: &Path) -> Json<routes::QueueInput> {
+    Json(
+        serde_json::from_value(serde_json::json!({
+            "input_path": input.to_string_lossy(),
+            "output_path": "",
+            "encoder": "libx265",
+            "preset": "",
+            "vmaf_target": "95",
+            "vmaf_threads": "",
+        }))
+        .unwrap(),
+    )
+}
+
+#[test]
+fn an_input_already_in_the_queue_is_a_conflict() {
+    web_db();
+    let folder =
+        std::env::temp_dir().join(format!("transrustica-web-queue-{}", std::process::id()));
+    std::fs::create_dir_all(&folder).unwrap();
+    let Ok(Json(added)) = routes::add_to_queue(queue_input(&folder)) else {
+        panic!("the folder was not queued");
+    };
+    let id = added["id"].as_i64().unwrap();
+
+    // a file in the queued folder
+    let video = folder.join("movie.mkv");
+    let Err(status::Custom(status, Json(error))) = routes::add_to_queue(queue_input(&video)) else {
+        panic!("a file of a queued folder was queued");
+    };
+    assert_eq!(status, Status::Conflict);
+    assert_eq!(
+        error["message"],
+        format!(
+            "{} is already in the queue as entry {} ({}, queued)",
+            video.display(),
+            id,
+            folder.display()
+        )
+    );
+
+    db().queue_remove(&id.to_string()).unwrap();
+    let Ok(Json(added)) = routes::add_to_queue(queue_input(&video)) else {
+        panic!("the file was not queued once the folder left the queue");
+    };
+    db().queue_remove(&added["id"].to_string()).unwrap();
+}
+
+// A row of the scan folder with the file gone
+fn scanned_row(path: &Path) -> VideoInfo {
+    serde_json::from_value(serde_json::json!({
+        "filename": path.file_name().unwrap().to_string_lossy(),
+        "filepath": path.to_string_lossy(),
+        "width": 1920,
+        "height": 1080,
+        "duration": 60.0,
+        "pixel_format": "yuv420p",
+        "display_aspect_ratio": "16:9",
+        "sample_aspect_ratio": "1:1",
+        "format": "matroska,webm",
+        "size": 1_000_000,
+        "folder_size": 0,
+        "bitrate": 8_000_000,
+        "codec": "h264",
+        "status": "pending_video",
+        "audio_codec": "aac",
+        "audio_bitrate": 128_000,
+        "hash": "",
+    }))
+    .unwrap()
+}
+
+#[test]
+fn a_scan_trashes_the_rows_of_the_files_that_are_gone() {
+    web_db();
+    let folder = std::env::temp_dir().join(format!("transrustica-web-scan-{}", std::process::id()));
+    std::fs::create_dir_all(&folder).unwrap();
+    for i in 0..9 {
+        let video = folder.join(format!("movie{}.mkv", i));
+        std::fs::write(&video, b"video").unwrap();
+        db().insert_video_info(&scanned_row(&video)).unwrap();
+    }
+    let gone = folder.join("gone.mkv");
+    db().insert_video_info(&scanned_row(&gone)).unwrap();
+
+    let paths = vec![folder.to_string_lossy().to_string()];
+    start_scan_progress("scan-removals", &paths).unwrap();
+    routes::run_scan("scan-removals", paths);
+    let scan = get_scan_progress("scan-removals").unwrap();
+    assert_eq!(scan.phase, "done");
+    assert_eq!(scan.present, 9);
+    assert_eq!(scan.removed, 1);
+    assert!(!db().video_info_exists(&gone.to_string_lossy()).unwrap());
+}